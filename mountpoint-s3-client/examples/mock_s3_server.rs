@@ -0,0 +1,76 @@
+//! Serve an in-memory S3 bucket over HTTP on localhost, for testing S3 clients without network
+//! access. Point a client at the printed endpoint URL, for example with `mount-s3 --endpoint-url`.
+//! Pass `--access-key-id` and `--secret-access-key` to have the server verify request signatures.
+
+use std::net::SocketAddr;
+
+use mountpoint_s3_client::config::Credentials;
+use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig};
+use mountpoint_s3_client::mock_server::{MockS3Server, MockS3ServerConfig};
+
+use clap::{Arg, Command};
+use tracing_subscriber::fmt::Subscriber;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Like `tracing_subscriber::fmt::init` but sends logs to stderr
+fn init_tracing_subscriber() {
+    let subscriber = Subscriber::builder()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .finish();
+
+    subscriber.try_init().expect("unable to install global subscriber");
+}
+
+fn main() {
+    init_tracing_subscriber();
+
+    let matches = Command::new("mock_s3_server")
+        .about("Serve an in-memory S3 bucket on localhost")
+        .arg(Arg::new("bucket").required(true))
+        .arg(Arg::new("bind").long("bind").default_value("127.0.0.1:0"))
+        .arg(
+            Arg::new("part-size")
+                .long("part-size")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("8388608"),
+        )
+        .arg(
+            Arg::new("access-key-id")
+                .long("access-key-id")
+                .requires("secret-access-key"),
+        )
+        .arg(
+            Arg::new("secret-access-key")
+                .long("secret-access-key")
+                .requires("access-key-id"),
+        )
+        .get_matches();
+
+    let bucket = matches.get_one::<String>("bucket").unwrap();
+    let bind = matches.get_one::<String>("bind").unwrap();
+    let part_size = *matches.get_one::<usize>("part-size").unwrap();
+    let credentials = matches
+        .get_one::<String>("access-key-id")
+        .zip(matches.get_one::<String>("secret-access-key"))
+        .map(|(access_key_id, secret_access_key)| Credentials::new(access_key_id, secret_access_key, None));
+
+    let addr: SocketAddr = bind.parse().expect("invalid bind address");
+    let client = MockClient::new(MockClientConfig {
+        bucket: bucket.to_owned(),
+        part_size,
+        ..Default::default()
+    });
+    let config = MockS3ServerConfig {
+        bucket: bucket.to_owned(),
+        credentials,
+    };
+    let server = MockS3Server::start_with_config(addr, config, client).expect("couldn't start server");
+    println!("{}", server.endpoint_url());
+
+    // Serve until killed
+    loop {
+        std::thread::park();
+    }
+}
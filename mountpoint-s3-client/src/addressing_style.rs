@@ -0,0 +1,11 @@
+//! How S3 request URIs address the bucket, shared by every client configuration.
+
+/// Whether requests address the bucket in the host name (virtual-hosted style) or the path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressingStyle {
+    /// Use virtual addressing if possible, but fall back to path addressing if necessary
+    #[default]
+    Automatic,
+    /// Always use path addressing
+    Path,
+}
@@ -1,6 +1,12 @@
 //! Provides base64 encoding/decoding for CRC32C checksums.
+#[cfg(feature = "crt")]
 pub use mountpoint_s3_crt::checksums::crc32c::{self, Crc32c};
 
+#[cfg(not(feature = "crt"))]
+pub mod crc32c;
+#[cfg(not(feature = "crt"))]
+pub use crc32c::Crc32c;
+
 use base64ct::Base64;
 use base64ct::Encoding;
 use thiserror::Error;
@@ -0,0 +1,85 @@
+//! A pure-Rust CRC32C implementation with the same interface as `mountpoint_s3_crt::checksums::crc32c`, used
+//! when the client is built without the `crt` feature.
+
+/// CRC32C checksum
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+pub struct Crc32c(u32);
+
+impl Crc32c {
+    /// Create a new CRC32C checksum with the given value.
+    pub fn new(value: u32) -> Crc32c {
+        Crc32c(value)
+    }
+
+    /// The CRC32C checksum value.
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Computes the CRC32C checksum of a byte slice.
+///
+/// Use [`Hasher`] for more advanced use-cases.
+pub fn checksum(buf: &[u8]) -> Crc32c {
+    Crc32c(::crc32c::crc32c(buf))
+}
+
+/// CRC32C Hasher
+#[derive(Debug, Clone)]
+pub struct Hasher {
+    state: Crc32c,
+}
+
+impl Hasher {
+    /// Create a new CRC32C [`Hasher`].
+    pub fn new() -> Self {
+        Self { state: Crc32c(0) }
+    }
+
+    /// Update the hash state with the given bytes slice.
+    pub fn update(&mut self, buf: &[u8]) {
+        self.state = Crc32c(::crc32c::crc32c_append(self.state.0, buf));
+    }
+
+    /// Finalize the hash state and return the computed CRC32C checksum value.
+    pub fn finalize(self) -> Crc32c {
+        self.state
+    }
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::hash::Hasher for Hasher {
+    fn finish(&self) -> u64 {
+        self.clone().finalize().0.into()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32c_simple() {
+        let buf: &[u8] = b"123456789";
+        let crc = checksum(buf);
+        assert_eq!(crc, Crc32c(0xe3069283));
+    }
+
+    #[test]
+    fn crc32c_append() {
+        let mut hasher = Hasher::new();
+        hasher.update(b"1234");
+        hasher.update(b"56789");
+        let crc = hasher.finalize();
+        assert_eq!(crc, Crc32c(0xe3069283));
+    }
+}
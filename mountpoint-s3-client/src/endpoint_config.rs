@@ -8,20 +8,13 @@ use mountpoint_s3_crt::{
 };
 use thiserror::Error;
 
+use crate::addressing_style::AddressingStyle;
+
 lazy_static! {
     // A static s3 endpoint rule engine that can be shared across s3 client
     static ref S3_ENDPOINT_RULE_ENGINE: RuleEngine = RuleEngine::new(&Default::default()).unwrap();
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum AddressingStyle {
-    /// Use virtual addressing if possible, but fall back to path addressing if necessary
-    #[default]
-    Automatic,
-    /// Always use path addressing
-    Path,
-}
-
 #[derive(Debug, Clone)]
 pub struct AuthScheme {
     disable_double_encoding: bool,
@@ -7,71 +7,145 @@ use std::fmt::Debug;
 use std::ops::Range;
 use std::pin::Pin;
 use std::sync::Mutex;
-use std::task::{Context, Poll};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
 
+use async_io::Timer;
 use async_trait::async_trait;
 use futures::Stream;
-use mountpoint_s3_crt::s3::client::BufferPoolUsageStats;
 use pin_project::pin_project;
 
+use crate::error_metadata::ProvideErrorMetadata;
 use crate::object_client::{
-    DeleteObjectError, DeleteObjectResult, ETag, GetBodyPart, GetObjectAttributesError, GetObjectAttributesResult,
-    GetObjectError, GetObjectRequest, HeadObjectError, HeadObjectResult, ListObjectsError, ListObjectsResult,
-    ObjectAttribute, ObjectClient, ObjectClientError, ObjectClientResult, PutObjectError, PutObjectParams,
-    PutObjectRequest, PutObjectResult, PutObjectSingleParams, UploadReview,
+    BufferPoolUsageStats, DeleteObjectError, DeleteObjectResult, ETag, GetBodyPart, GetObjectAttributesError,
+    GetObjectAttributesResult, GetObjectError, GetObjectRequest, HeadObjectError, HeadObjectResult, ListObjectsError,
+    ListObjectsResult, ObjectAttribute, ObjectClient, ObjectClientError, ObjectClientResult, PutObjectError,
+    PutObjectParams, PutObjectRequest, PutObjectResult, PutObjectSingleParams, UploadReview,
 };
 
+pub mod fault_injection;
+
+/// An operation of a [FailureClient], passed to the hooks that apply to every operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    DeleteObject,
+    GetObject,
+    GetObjectAttributes,
+    HeadObject,
+    ListObjects,
+    PutObject,
+    PutObjectSingle,
+}
+
+/// A point in a GetObject or PutObject request at which a [FailureRequestWrapper] can fail it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestEvent {
+    /// Polling for the next part of a GetObject body
+    Read,
+    /// Writing this many bytes to a PutObject request
+    Write(usize),
+    /// Completing a PutObject request
+    Complete,
+}
+
 // Wrapper for injecting failures into a get stream or a put request
-pub struct FailureRequestWrapper<Client: ObjectClient, RequestWrapperState> {
+pub struct FailureRequestWrapper<RequestWrapperState, Error> {
     state: RequestWrapperState,
-    result_fn: fn(&mut RequestWrapperState) -> Result<(), Client::ClientError>,
+    result_fn: fn(&mut RequestWrapperState, RequestEvent) -> Result<(), Error>,
+    part_fn: fn(&mut RequestWrapperState, GetBodyPart) -> GetBodyPart,
 }
 
+impl<RequestWrapperState, Error> FailureRequestWrapper<RequestWrapperState, Error> {
+    /// Create a wrapper that fails a request when `result_fn` returns an error, and otherwise
+    /// passes GetObject body parts through unchanged
+    pub fn new(
+        state: RequestWrapperState,
+        result_fn: fn(&mut RequestWrapperState, RequestEvent) -> Result<(), Error>,
+    ) -> Self {
+        Self {
+            state,
+            result_fn,
+            part_fn: |_state, part| part,
+        }
+    }
+
+    /// Replace each part of a GetObject body with the result of `part_fn`, for example to truncate it
+    pub fn with_part_fn(mut self, part_fn: fn(&mut RequestWrapperState, GetBodyPart) -> GetBodyPart) -> Self {
+        self.part_fn = part_fn;
+        self
+    }
+}
+
+/// An [ObjectClient] that forwards requests to another client, calling a hook before each request
+/// that can fail it. By default, the wrapper has the same error type as the client it wraps, but
+/// hooks can return a different `Error` that the client's errors convert into.
 #[allow(clippy::type_complexity)]
-pub struct FailureClient<Client: ObjectClient, State, RequestWrapperState> {
+pub struct FailureClient<
+    Client: ObjectClient,
+    State,
+    RequestWrapperState,
+    Error = <Client as ObjectClient>::ClientError,
+> {
     pub client: Client,
     pub state: Mutex<State>,
-    pub get_object_cb: fn(
-        &mut State,
-        &str,
-        &str,
-        Option<Range<u64>>,
-        Option<ETag>,
-    ) -> Result<
-        FailureRequestWrapper<Client, RequestWrapperState>,
-        ObjectClientError<GetObjectError, Client::ClientError>,
-    >,
-    pub head_object_cb:
-        fn(&mut State, &str, &str) -> Result<(), ObjectClientError<HeadObjectError, Client::ClientError>>,
-    pub list_objects_cb: fn(
-        &mut State,
-        &str,
-        Option<&str>,
-        &str,
-        usize,
-        &str,
-    ) -> Result<(), ObjectClientError<ListObjectsError, Client::ClientError>>,
-    pub put_object_cb: fn(
-        &mut State,
-        &str,
-        &str,
-        &PutObjectParams,
-    ) -> Result<
-        FailureRequestWrapper<Client, RequestWrapperState>,
-        ObjectClientError<PutObjectError, Client::ClientError>,
-    >,
+    /// Called before every request's other hook, returning how long to delay the request by
+    pub delay_cb: fn(&mut State, Operation) -> Option<Duration>,
+    pub delete_object_cb: fn(&mut State, &str, &str) -> Result<(), ObjectClientError<DeleteObjectError, Error>>,
+    pub get_object_cb:
+        fn(
+            &mut State,
+            &str,
+            &str,
+            Option<Range<u64>>,
+            Option<ETag>,
+        )
+            -> Result<FailureRequestWrapper<RequestWrapperState, Error>, ObjectClientError<GetObjectError, Error>>,
+    pub get_object_attributes_cb:
+        fn(&mut State, &str, &str) -> Result<(), ObjectClientError<GetObjectAttributesError, Error>>,
+    pub head_object_cb: fn(&mut State, &str, &str) -> Result<(), ObjectClientError<HeadObjectError, Error>>,
+    pub list_objects_cb:
+        fn(&mut State, &str, Option<&str>, &str, usize, &str) -> Result<(), ObjectClientError<ListObjectsError, Error>>,
+    pub put_object_cb:
+        fn(
+            &mut State,
+            &str,
+            &str,
+            &PutObjectParams,
+        )
+            -> Result<FailureRequestWrapper<RequestWrapperState, Error>, ObjectClientError<PutObjectError, Error>>,
+    pub put_object_single_cb:
+        fn(&mut State, &str, &str, &PutObjectSingleParams) -> Result<(), ObjectClientError<PutObjectError, Error>>,
+}
+
+impl<Client: ObjectClient, State, RequestWrapperState, Error> FailureClient<Client, State, RequestWrapperState, Error> {
+    /// Wait for the delay, if any, that the hooks add to the given operation
+    async fn delay(&self, operation: Operation) {
+        let delay = (self.delay_cb)(&mut *self.state.lock().unwrap(), operation);
+        if let Some(delay) = delay {
+            Timer::after(delay).await;
+        }
+    }
+}
+
+/// Convert the client error of a request forwarded to the wrapped client
+fn forward<T, S, E, Error: From<E>>(result: ObjectClientResult<T, S, E>) -> ObjectClientResult<T, S, Error> {
+    result.map_err(|e| match e {
+        ObjectClientError::ServiceError(e) => ObjectClientError::ServiceError(e),
+        ObjectClientError::ClientError(e) => ObjectClientError::ClientError(e.into()),
+    })
 }
 
 #[cfg_attr(not(docsrs), async_trait)]
-impl<Client, State, GetWrapperState> ObjectClient for FailureClient<Client, State, GetWrapperState>
+impl<Client, State, GetWrapperState, Error> ObjectClient for FailureClient<Client, State, GetWrapperState, Error>
 where
     Client: ObjectClient + Send + Sync + 'static,
     State: Send + Sync + 'static,
     GetWrapperState: Send + Sync + 'static,
+    Error: From<Client::ClientError> + std::error::Error + ProvideErrorMetadata + Send + Sync + 'static,
 {
-    type GetObjectRequest = FailureGetRequest<Client, GetWrapperState>;
-    type PutObjectRequest = FailurePutObjectRequest<Client, GetWrapperState>;
-    type ClientError = Client::ClientError;
+    type GetObjectRequest = FailureGetRequest<Client, GetWrapperState, Error>;
+    type PutObjectRequest = FailurePutObjectRequest<Client, GetWrapperState, Error>;
+    type ClientError = Error;
 
     fn read_part_size(&self) -> Option<usize> {
         self.client.read_part_size()
@@ -94,8 +168,9 @@ where
         bucket: &str,
         key: &str,
     ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
-        // TODO failure hook for delete_object
-        self.client.delete_object(bucket, key).await
+        self.delay(Operation::DeleteObject).await;
+        (self.delete_object_cb)(&mut *self.state.lock().unwrap(), bucket, key)?;
+        forward(self.client.delete_object(bucket, key).await)
     }
 
     async fn get_object(
@@ -105,6 +180,7 @@ where
         range: Option<Range<u64>>,
         if_match: Option<ETag>,
     ) -> ObjectClientResult<Self::GetObjectRequest, GetObjectError, Self::ClientError> {
+        self.delay(Operation::GetObject).await;
         let wrapper = (self.get_object_cb)(
             &mut *self.state.lock().unwrap(),
             bucket,
@@ -112,10 +188,12 @@ where
             range.clone(),
             if_match.clone(),
         )?;
-        let request = self.client.get_object(bucket, key, range, if_match).await?;
+        let request = forward(self.client.get_object(bucket, key, range, if_match).await)?;
         Ok(FailureGetRequest {
             state: wrapper.state,
             result_fn: wrapper.result_fn,
+            part_fn: wrapper.part_fn,
+            failed: false,
             request,
         })
     }
@@ -128,6 +206,7 @@ where
         max_keys: usize,
         prefix: &str,
     ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.delay(Operation::ListObjects).await;
         (self.list_objects_cb)(
             &mut *self.state.lock().unwrap(),
             bucket,
@@ -137,9 +216,37 @@ where
             prefix,
         )?;
 
-        self.client
-            .list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
-            .await
+        forward(
+            self.client
+                .list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
+                .await,
+        )
+    }
+
+    async fn list_objects_after(
+        &self,
+        bucket: &str,
+        start_after: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.delay(Operation::ListObjects).await;
+        (self.list_objects_cb)(
+            &mut *self.state.lock().unwrap(),
+            bucket,
+            continuation_token,
+            delimiter,
+            max_keys,
+            prefix,
+        )?;
+
+        forward(
+            self.client
+                .list_objects_after(bucket, start_after, continuation_token, delimiter, max_keys, prefix)
+                .await,
+        )
     }
 
     async fn head_object(
@@ -147,8 +254,9 @@ where
         bucket: &str,
         key: &str,
     ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        self.delay(Operation::HeadObject).await;
         (self.head_object_cb)(&mut *self.state.lock().unwrap(), bucket, key)?;
-        self.client.head_object(bucket, key).await
+        forward(self.client.head_object(bucket, key).await)
     }
 
     async fn put_object(
@@ -157,8 +265,9 @@ where
         key: &str,
         params: &PutObjectParams,
     ) -> ObjectClientResult<Self::PutObjectRequest, PutObjectError, Self::ClientError> {
+        self.delay(Operation::PutObject).await;
         let wrapper = (self.put_object_cb)(&mut *self.state.lock().unwrap(), bucket, key, &params.clone())?;
-        let request = self.client.put_object(bucket, key, params).await?;
+        let request = forward(self.client.put_object(bucket, key, params).await)?;
         Ok(FailurePutObjectRequest {
             request,
             state: wrapper.state,
@@ -173,8 +282,9 @@ where
         params: &PutObjectSingleParams,
         contents: impl AsRef<[u8]> + Send + 'a,
     ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
-        // TODO failure hook for put_object_single
-        self.client.put_object_single(bucket, key, params, contents).await
+        self.delay(Operation::PutObjectSingle).await;
+        (self.put_object_single_cb)(&mut *self.state.lock().unwrap(), bucket, key, params)?;
+        forward(self.client.put_object_single(bucket, key, params, contents).await)
     }
 
     async fn get_object_attributes(
@@ -185,23 +295,34 @@ where
         part_number_marker: Option<usize>,
         object_attributes: &[ObjectAttribute],
     ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
-        // TODO failure hook for get_object_attributes
-        self.client
-            .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
-            .await
+        self.delay(Operation::GetObjectAttributes).await;
+        (self.get_object_attributes_cb)(&mut *self.state.lock().unwrap(), bucket, key)?;
+        forward(
+            self.client
+                .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
+                .await,
+        )
     }
 }
 
 #[pin_project]
-pub struct FailureGetRequest<Client: ObjectClient, GetWrapperState> {
+pub struct FailureGetRequest<Client: ObjectClient, GetWrapperState, Error = <Client as ObjectClient>::ClientError> {
     state: GetWrapperState,
-    result_fn: fn(&mut GetWrapperState) -> Result<(), Client::ClientError>,
+    result_fn: fn(&mut GetWrapperState, RequestEvent) -> Result<(), Error>,
+    part_fn: fn(&mut GetWrapperState, GetBodyPart) -> GetBodyPart,
+    /// Set once a failure has been injected, after which the stream ends
+    failed: bool,
     #[pin]
     request: Client::GetObjectRequest,
 }
 
-impl<Client: ObjectClient, FailState: Send> GetObjectRequest for FailureGetRequest<Client, FailState> {
-    type ClientError = Client::ClientError;
+impl<Client, FailState, Error> GetObjectRequest for FailureGetRequest<Client, FailState, Error>
+where
+    Client: ObjectClient,
+    FailState: Send,
+    Error: From<Client::ClientError> + std::error::Error + Send + Sync + 'static,
+{
+    type ClientError = Error;
 
     fn increment_read_window(self: Pin<&mut Self>, len: usize) {
         let this = self.project();
@@ -214,46 +335,60 @@ impl<Client: ObjectClient, FailState: Send> GetObjectRequest for FailureGetReque
     }
 }
 
-impl<Client: ObjectClient, FailState> Stream for FailureGetRequest<Client, FailState> {
-    type Item = ObjectClientResult<GetBodyPart, GetObjectError, Client::ClientError>;
+impl<Client, FailState, Error> Stream for FailureGetRequest<Client, FailState, Error>
+where
+    Client: ObjectClient,
+    Error: From<Client::ClientError>,
+{
+    type Item = ObjectClientResult<GetBodyPart, GetObjectError, Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
         let this = self.project();
-        (this.result_fn)(this.state)?;
-        this.request.poll_next(cx)
+        if *this.failed {
+            return Poll::Ready(None);
+        }
+        if let Err(e) = (this.result_fn)(this.state, RequestEvent::Read) {
+            *this.failed = true;
+            return Poll::Ready(Some(Err(ObjectClientError::ClientError(e))));
+        }
+        let next = ready!(this.request.poll_next(cx));
+        Poll::Ready(next.map(|result| forward(result).map(|part| (this.part_fn)(this.state, part))))
     }
 }
 
-pub struct FailurePutObjectRequest<Client: ObjectClient, PutWrapperState> {
+pub struct FailurePutObjectRequest<Client: ObjectClient, PutWrapperState, Error = <Client as ObjectClient>::ClientError>
+{
     request: Client::PutObjectRequest,
     state: PutWrapperState,
-    result_fn: fn(&mut PutWrapperState) -> Result<(), Client::ClientError>,
+    result_fn: fn(&mut PutWrapperState, RequestEvent) -> Result<(), Error>,
 }
 
 #[cfg_attr(not(docsrs), async_trait)]
-impl<Client: ObjectClient, PutWrapperState> PutObjectRequest for FailurePutObjectRequest<Client, PutWrapperState>
+impl<Client: ObjectClient, PutWrapperState, Error> PutObjectRequest
+    for FailurePutObjectRequest<Client, PutWrapperState, Error>
 where
     Client::PutObjectRequest: Send,
     PutWrapperState: Send,
+    Error: From<Client::ClientError> + std::error::Error + Send + Sync + 'static,
 {
-    type ClientError = Client::ClientError;
+    type ClientError = Error;
 
     async fn write(&mut self, slice: &[u8]) -> ObjectClientResult<(), PutObjectError, Self::ClientError> {
-        (self.result_fn)(&mut self.state)?;
-        self.request.write(slice).await
+        (self.result_fn)(&mut self.state, RequestEvent::Write(slice.len()))?;
+        forward(self.request.write(slice).await)
     }
 
     async fn complete(mut self) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
-        (self.result_fn)(&mut self.state)?;
-        self.request.complete().await
+        (self.result_fn)(&mut self.state, RequestEvent::Complete)?;
+        forward(self.request.complete().await)
     }
 
     async fn review_and_complete(
         mut self,
         review_callback: impl FnOnce(UploadReview) -> bool + Send + 'static,
     ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
-        (self.result_fn)(&mut self.state)?;
-        self.request.review_and_complete(review_callback).await
+        (self.result_fn)(&mut self.state, RequestEvent::Complete)?;
+        forward(self.request.review_and_complete(review_callback).await)
     }
 }
 
@@ -322,6 +457,8 @@ pub fn countdown_failure_client<Client: ObjectClient>(
     FailureClient {
         client,
         state,
+        delay_cb: |_state, _operation| None,
+        delete_object_cb: |_state, _bucket, _key| Ok(()),
         get_object_cb: |state, _bucket, _key, _range, _if_match| {
             state.get_count += 1;
             let (fail_count, error) = if let Some(result) = state.get_results.remove(&state.get_count) {
@@ -330,13 +467,13 @@ pub fn countdown_failure_client<Client: ObjectClient>(
             } else {
                 (usize::MAX, None)
             };
-            Ok(FailureRequestWrapper {
-                state: CountdownFailureRequestState {
+            Ok(FailureRequestWrapper::new(
+                CountdownFailureRequestState {
                     count: 0,
                     fail_count,
                     error,
                 },
-                result_fn: |state| {
+                |state, _event| {
                     state.count += 1;
                     if state.count >= state.fail_count {
                         Err(state.error.take().unwrap())
@@ -344,8 +481,9 @@ pub fn countdown_failure_client<Client: ObjectClient>(
                         Ok(())
                     }
                 },
-            })
+            ))
         },
+        get_object_attributes_cb: |_state, _bucket, _key| Ok(()),
         head_object_cb: |state, _bucket, _key| {
             state.head_count += 1;
             if let Some(error) = state.head_failures.remove(&state.head_count) {
@@ -370,13 +508,13 @@ pub fn countdown_failure_client<Client: ObjectClient>(
             } else {
                 (usize::MAX, None)
             };
-            Ok(FailureRequestWrapper {
-                state: CountdownFailureRequestState {
+            Ok(FailureRequestWrapper::new(
+                CountdownFailureRequestState {
                     count: 0,
                     fail_count,
                     error,
                 },
-                result_fn: |state| {
+                |state, _event| {
                     state.count += 1;
                     if state.count >= state.fail_count {
                         Err(state.error.take().unwrap())
@@ -384,8 +522,9 @@ pub fn countdown_failure_client<Client: ObjectClient>(
                         Ok(())
                     }
                 },
-            })
+            ))
         },
+        put_object_single_cb: |_state, _bucket, _key, _params| Ok(()),
     }
 }
 
@@ -0,0 +1,618 @@
+//! An [`ObjectClient`] that injects randomized faults into requests, driven by a [FaultSpec].
+//!
+//! Where [countdown_failure_client](super::countdown_failure_client) fails specific calls chosen
+//! by a unit test, a [FaultInjectionClient] is a [FailureClient] whose hooks fail requests at
+//! random according to per-operation rates, which makes it suitable for chaos-testing a whole
+//! application against a mock bucket. It can inject:
+//!
+//! * errors (500 InternalError) at a given rate;
+//! * throttling (503 SlowDown), either at a given rate or once a request rate limit is exceeded;
+//! * added latency, drawn from a fixed, uniform, or exponential distribution;
+//! * interrupted requests: GetObject bodies that are truncated and then fail, and uploads that fail
+//!   partway through.
+//!
+//! A [FaultSpec] is usually loaded from JSON, for example:
+//!
+//! ```json
+//! {
+//!     "seed": 42,
+//!     "get_object": {
+//!         "error_rate": 0.01,
+//!         "interrupt_rate": 0.05,
+//!         "latency": { "distribution": "exponential", "mean_millis": 20 }
+//!     },
+//!     "list_objects": { "max_requests_per_second": 100 },
+//!     "put_object": { "slow_down_rate": 0.1, "interrupt_rate": 0.01 }
+//! }
+//! ```
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::error_metadata::{ClientErrorMetadata, ProvideErrorMetadata};
+use crate::mock_client::leaky_bucket::LeakyBucket;
+use crate::object_client::{GetBodyPart, ObjectClient, ObjectClientError};
+
+use super::{FailureClient, FailureRequestWrapper, Operation, RequestEvent};
+
+/// Part size used to place interruptions, if the wrapped client doesn't have one
+const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Interrupted uploads fail after writing a random amount of data up to this many parts
+const MAX_PARTS_BEFORE_UPLOAD_INTERRUPTION: u64 = 4;
+
+/// The faults to inject into each operation
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FaultSpec {
+    /// Seed for the random number generator that decides which requests fail. If not set, a random
+    /// seed is chosen (and logged).
+    pub seed: Option<u64>,
+    pub delete_object: OperationFaults,
+    pub get_object: OperationFaults,
+    pub get_object_attributes: OperationFaults,
+    pub head_object: OperationFaults,
+    pub list_objects: OperationFaults,
+    pub put_object: OperationFaults,
+    pub put_object_single: OperationFaults,
+}
+
+/// The faults to inject into a single operation
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OperationFaults {
+    /// Probability that a request fails with a 500 InternalError
+    pub error_rate: f64,
+    /// Probability that a request fails with a 503 SlowDown
+    pub slow_down_rate: f64,
+    /// Sustained request rate above which requests fail with a 503 SlowDown. Bursts of up to one
+    /// second's worth of requests are allowed.
+    pub max_requests_per_second: Option<u32>,
+    /// Latency to add to each request, before any error is injected
+    pub latency: Option<Latency>,
+    /// Probability that a request is interrupted partway through. Only supported for GetObject,
+    /// whose body is truncated at a random offset and then fails, and PutObject, which fails after
+    /// a random amount of data has been written (or on completion, if the upload is smaller).
+    ///
+    /// GetObject requests without a range are truncated within their first part, so may complete
+    /// normally if the object is small.
+    pub interrupt_rate: f64,
+}
+
+/// A distribution of latencies
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum Latency {
+    Fixed { millis: u64 },
+    Uniform { min_millis: u64, max_millis: u64 },
+    Exponential { mean_millis: u64 },
+}
+
+impl Latency {
+    fn sample(&self, rng: &mut impl Rng) -> Duration {
+        match *self {
+            Latency::Fixed { millis } => Duration::from_millis(millis),
+            Latency::Uniform { min_millis, max_millis } => {
+                Duration::from_millis(rng.gen_range(min_millis..=max_millis))
+            }
+            Latency::Exponential { mean_millis } => {
+                // Inverse transform sampling. `gen` is in [0, 1), so the logarithm is finite.
+                let u: f64 = rng.gen();
+                Duration::from_secs_f64(-(1.0 - u).ln() * mean_millis as f64 / 1000.0)
+            }
+        }
+    }
+}
+
+impl FaultSpec {
+    /// Parse a [FaultSpec] from JSON
+    pub fn from_json(json: &str) -> Result<Self, FaultSpecError> {
+        let spec: Self = serde_json::from_str(json)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Read a [FaultSpec] from a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, FaultSpecError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    fn validate(&self) -> Result<(), FaultSpecError> {
+        let operations = [
+            ("delete_object", &self.delete_object, false),
+            ("get_object", &self.get_object, true),
+            ("get_object_attributes", &self.get_object_attributes, false),
+            ("head_object", &self.head_object, false),
+            ("list_objects", &self.list_objects, false),
+            ("put_object", &self.put_object, true),
+            ("put_object_single", &self.put_object_single, false),
+        ];
+        for (name, faults, streaming) in operations {
+            let rates = [
+                ("error_rate", faults.error_rate),
+                ("slow_down_rate", faults.slow_down_rate),
+                ("interrupt_rate", faults.interrupt_rate),
+            ];
+            for (field, rate) in rates {
+                if !(0.0..=1.0).contains(&rate) {
+                    return Err(FaultSpecError::Invalid(format!(
+                        "{name}.{field} must be between 0 and 1, got {rate}"
+                    )));
+                }
+            }
+            if faults.interrupt_rate > 0.0 && !streaming {
+                return Err(FaultSpecError::Invalid(format!(
+                    "{name}.interrupt_rate is only supported for get_object and put_object"
+                )));
+            }
+            if faults.max_requests_per_second == Some(0) {
+                return Err(FaultSpecError::Invalid(format!(
+                    "{name}.max_requests_per_second must be positive"
+                )));
+            }
+            if let Some(Latency::Uniform { min_millis, max_millis }) = faults.latency {
+                if min_millis > max_millis {
+                    return Err(FaultSpecError::Invalid(format!(
+                        "{name}.latency.min_millis must not be greater than max_millis"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FaultSpecError {
+    #[error("failed to read fault spec")]
+    IoError(#[from] std::io::Error),
+
+    #[error("failed to parse fault spec")]
+    ParseError(#[from] serde_json::Error),
+
+    #[error("invalid fault spec: {0}")]
+    Invalid(String),
+}
+
+/// A fault injected by a [FaultInjectionClient]
+#[derive(Debug, Clone, Error)]
+#[error("injected fault: {message}")]
+pub struct InjectedFault {
+    pub http_code: Option<i32>,
+    pub error_code: Option<String>,
+    pub message: String,
+}
+
+impl InjectedFault {
+    fn internal_error() -> Self {
+        Self {
+            http_code: Some(500),
+            error_code: Some("InternalError".to_string()),
+            message: "We encountered an internal error. Please try again.".to_string(),
+        }
+    }
+
+    fn slow_down() -> Self {
+        Self {
+            http_code: Some(503),
+            error_code: Some("SlowDown".to_string()),
+            message: "Please reduce your request rate.".to_string(),
+        }
+    }
+
+    fn interrupted() -> Self {
+        Self {
+            http_code: None,
+            error_code: None,
+            message: "connection closed before the request completed".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FaultInjectionError<E> {
+    #[error(transparent)]
+    Injected(InjectedFault),
+
+    #[error(transparent)]
+    ClientError(E),
+}
+
+impl<E: ProvideErrorMetadata> ProvideErrorMetadata for FaultInjectionError<E> {
+    fn meta(&self) -> ClientErrorMetadata {
+        match self {
+            FaultInjectionError::Injected(fault) => ClientErrorMetadata {
+                http_code: fault.http_code,
+                error_code: fault.error_code.clone(),
+                error_message: Some(fault.message.clone()),
+            },
+            FaultInjectionError::ClientError(e) => e.meta(),
+        }
+    }
+}
+
+impl<E> From<E> for FaultInjectionError<E> {
+    fn from(err: E) -> Self {
+        FaultInjectionError::ClientError(err)
+    }
+}
+
+fn injected<S, E>(fault: InjectedFault) -> ObjectClientError<S, FaultInjectionError<E>> {
+    ObjectClientError::ClientError(FaultInjectionError::Injected(fault))
+}
+
+/// A [FailureClient] that forwards requests to another client, injecting faults as described by a
+/// [FaultSpec].
+pub type FaultInjectionClient<Client> = FailureClient<
+    Client,
+    FaultInjectionState,
+    FaultInjectionRequestState,
+    FaultInjectionError<<Client as ObjectClient>::ClientError>,
+>;
+
+/// Faults for one operation, together with its rate limiter
+#[derive(Debug)]
+struct OperationState {
+    faults: OperationFaults,
+    rate_limiter: Option<LeakyBucket>,
+}
+
+impl OperationState {
+    fn new(faults: OperationFaults) -> Self {
+        let rate_limiter = faults.max_requests_per_second.map(|requests_per_second| {
+            LeakyBucket::builder()
+                .refill_interval(Duration::from_secs_f64(1.0 / requests_per_second as f64))
+                .refill_amount(1)
+                .max(requests_per_second)
+                .tokens(requests_per_second)
+                .build()
+        });
+        Self { faults, rate_limiter }
+    }
+}
+
+/// The state of a [FaultInjectionClient], shared by all its requests
+#[derive(Debug)]
+pub struct FaultInjectionState {
+    rng: ChaCha20Rng,
+    read_part_size: u64,
+    write_part_size: u64,
+    delete_object: OperationState,
+    get_object: OperationState,
+    get_object_attributes: OperationState,
+    head_object: OperationState,
+    list_objects: OperationState,
+    put_object: OperationState,
+    put_object_single: OperationState,
+}
+
+impl FaultInjectionState {
+    fn operation(&self, operation: Operation) -> &OperationState {
+        match operation {
+            Operation::DeleteObject => &self.delete_object,
+            Operation::GetObject => &self.get_object,
+            Operation::GetObjectAttributes => &self.get_object_attributes,
+            Operation::HeadObject => &self.head_object,
+            Operation::ListObjects => &self.list_objects,
+            Operation::PutObject => &self.put_object,
+            Operation::PutObjectSingle => &self.put_object_single,
+        }
+    }
+
+    /// Return true with the given probability
+    fn roll(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.rng.gen_bool(probability)
+    }
+
+    fn latency(&mut self, operation: Operation) -> Option<Duration> {
+        let latency = self.operation(operation).faults.latency?;
+        Some(latency.sample(&mut self.rng))
+    }
+
+    /// Decide whether a request should fail
+    fn inject<S, E>(&mut self, operation: Operation) -> Result<(), ObjectClientError<S, FaultInjectionError<E>>> {
+        let state = self.operation(operation);
+        let (slow_down_rate, error_rate) = (state.faults.slow_down_rate, state.faults.error_rate);
+        let fault = if let Some(rate_limiter) = &state.rate_limiter {
+            (!rate_limiter.try_acquire(1)).then(InjectedFault::slow_down)
+        } else {
+            None
+        };
+        let fault = fault
+            .or_else(|| self.roll(slow_down_rate).then(InjectedFault::slow_down))
+            .or_else(|| self.roll(error_rate).then(InjectedFault::internal_error));
+        match fault {
+            Some(fault) => {
+                debug!(?operation, ?fault, "injecting fault");
+                Err(injected(fault))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Choose where to interrupt a GetObject body, if it's to be interrupted
+    fn get_object_interruption(&mut self, range: Option<&Range<u64>>) -> Option<u64> {
+        if !self.roll(self.get_object.faults.interrupt_rate) {
+            return None;
+        }
+        let (start, len) = match range {
+            Some(range) => (range.start, range.end - range.start),
+            None => (0, self.read_part_size),
+        };
+        let offset = start + self.rng.gen_range(0..len.max(1));
+        debug!(operation = ?Operation::GetObject, offset, "injecting interruption");
+        Some(offset)
+    }
+
+    /// Choose how many bytes to write before interrupting an upload, if it's to be interrupted
+    fn put_object_interruption(&mut self) -> Option<u64> {
+        if !self.roll(self.put_object.faults.interrupt_rate) {
+            return None;
+        }
+        let bytes = self
+            .rng
+            .gen_range(0..self.write_part_size * MAX_PARTS_BEFORE_UPLOAD_INTERRUPTION);
+        debug!(operation = ?Operation::PutObject, bytes, "injecting interruption");
+        Some(bytes)
+    }
+}
+
+/// The state of a GetObject or PutObject request made through a [FaultInjectionClient]
+#[derive(Debug)]
+pub struct FaultInjectionRequestState {
+    /// Offset of a GetObject body, or number of bytes written to an upload, at which to interrupt
+    /// the request, if it's to be interrupted
+    interrupt_at: Option<u64>,
+    /// Offset of the next part of a GetObject body, or number of bytes written to an upload
+    position: u64,
+    failed: bool,
+}
+
+impl FaultInjectionRequestState {
+    fn new(interrupt_at: Option<u64>, position: u64) -> Self {
+        Self {
+            interrupt_at,
+            position,
+            failed: false,
+        }
+    }
+
+    fn check_interrupted<E>(&mut self, event: RequestEvent) -> Result<(), FaultInjectionError<E>> {
+        let interrupted = match event {
+            RequestEvent::Read => self.interrupt_at.is_some_and(|offset| self.position >= offset),
+            RequestEvent::Write(len) => {
+                self.position += len as u64;
+                self.interrupt_at.is_some_and(|limit| self.position > limit)
+            }
+            // Uploads smaller than the interruption point fail on completion
+            RequestEvent::Complete => self.interrupt_at.is_some(),
+        };
+        if self.failed || interrupted {
+            self.failed = true;
+            return Err(FaultInjectionError::Injected(InjectedFault::interrupted()));
+        }
+        Ok(())
+    }
+
+    /// Truncate the part of a GetObject body that crosses the interruption offset
+    fn truncate_part(&mut self, (offset, data): GetBodyPart) -> GetBodyPart {
+        let data = match self.interrupt_at {
+            Some(interrupt_offset) if offset + data.len() as u64 > interrupt_offset => {
+                data[..interrupt_offset.saturating_sub(offset) as usize].into()
+            }
+            _ => data,
+        };
+        self.position = offset + data.len() as u64;
+        (offset, data)
+    }
+}
+
+/// Create a [FaultInjectionClient] that forwards requests to `client`
+pub fn fault_injection_client<Client: ObjectClient>(client: Client, spec: FaultSpec) -> FaultInjectionClient<Client> {
+    let seed = spec.seed.unwrap_or_else(rand::random);
+    info!(seed, "injecting faults into object client requests");
+    let state = Mutex::new(FaultInjectionState {
+        rng: ChaCha20Rng::seed_from_u64(seed),
+        read_part_size: client.read_part_size().unwrap_or(DEFAULT_PART_SIZE) as u64,
+        write_part_size: client.write_part_size().unwrap_or(DEFAULT_PART_SIZE) as u64,
+        delete_object: OperationState::new(spec.delete_object),
+        get_object: OperationState::new(spec.get_object),
+        get_object_attributes: OperationState::new(spec.get_object_attributes),
+        head_object: OperationState::new(spec.head_object),
+        list_objects: OperationState::new(spec.list_objects),
+        put_object: OperationState::new(spec.put_object),
+        put_object_single: OperationState::new(spec.put_object_single),
+    });
+    FailureClient {
+        client,
+        state,
+        delay_cb: |state, operation| state.latency(operation),
+        delete_object_cb: |state, _bucket, _key| state.inject(Operation::DeleteObject),
+        get_object_cb: |state, _bucket, _key, range, _if_match| {
+            state.inject(Operation::GetObject)?;
+            let interrupt_offset = state.get_object_interruption(range.as_ref());
+            let start = range.map_or(0, |range| range.start);
+            Ok(FailureRequestWrapper::new(
+                FaultInjectionRequestState::new(interrupt_offset, start),
+                FaultInjectionRequestState::check_interrupted,
+            )
+            .with_part_fn(FaultInjectionRequestState::truncate_part))
+        },
+        get_object_attributes_cb: |state, _bucket, _key| state.inject(Operation::GetObjectAttributes),
+        head_object_cb: |state, _bucket, _key| state.inject(Operation::HeadObject),
+        list_objects_cb: |state, _bucket, _ct, _delim, _max_keys, _prefix| state.inject(Operation::ListObjects),
+        put_object_cb: |state, _bucket, _key, _params| {
+            state.inject(Operation::PutObject)?;
+            let interrupt_after = state.put_object_interruption();
+            Ok(FailureRequestWrapper::new(
+                FaultInjectionRequestState::new(interrupt_after, 0),
+                FaultInjectionRequestState::check_interrupted,
+            ))
+        },
+        put_object_single_cb: |state, _bucket, _key, _params| state.inject(Operation::PutObjectSingle),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use futures::StreamExt;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::mock_client::{MockClient, MockClientConfig, MockObject};
+    use crate::object_client::{ETag, HeadObjectError, PutObjectRequest};
+
+    fn mock_client() -> MockClient {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            ..Default::default()
+        });
+        client.add_object("key", MockObject::ramp(0xaa, 4096, ETag::for_tests()));
+        client
+    }
+
+    fn assert_injected<S: std::fmt::Debug, E: ProvideErrorMetadata + std::fmt::Debug>(
+        err: ObjectClientError<S, FaultInjectionError<E>>,
+        http_code: Option<i32>,
+    ) {
+        let ObjectClientError::ClientError(err @ FaultInjectionError::Injected(_)) = err else {
+            panic!("expected an injected fault, got {err:?}");
+        };
+        assert_eq!(err.meta().http_code, http_code);
+    }
+
+    #[tokio::test]
+    async fn no_faults() {
+        let client = fault_injection_client(mock_client(), FaultSpec::default());
+        for _ in 0..100 {
+            client.head_object("test_bucket", "key").await.unwrap();
+        }
+        let err = client.head_object("test_bucket", "missing").await.unwrap_err();
+        assert!(matches!(
+            err,
+            ObjectClientError::ServiceError(HeadObjectError::NotFound)
+        ));
+    }
+
+    #[test_case(r#"{"head_object": {"error_rate": 1.0}}"#, Some(500); "errors")]
+    #[test_case(r#"{"head_object": {"slow_down_rate": 1.0}}"#, Some(503); "slow down")]
+    #[tokio::test]
+    async fn inject_errors(spec: &str, http_code: Option<i32>) {
+        let client = fault_injection_client(mock_client(), FaultSpec::from_json(spec).unwrap());
+        let err = client.head_object("test_bucket", "key").await.unwrap_err();
+        assert_injected(err, http_code);
+        // Other operations are unaffected
+        client.list_objects("test_bucket", None, "/", 10, "").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn error_rate_is_deterministic() {
+        let spec = FaultSpec::from_json(r#"{"seed": 7, "head_object": {"error_rate": 0.5}}"#).unwrap();
+        let mut outcomes = Vec::new();
+        for _ in 0..2 {
+            let client = fault_injection_client(mock_client(), spec.clone());
+            let mut results = Vec::new();
+            for _ in 0..100 {
+                results.push(client.head_object("test_bucket", "key").await.is_ok());
+            }
+            outcomes.push(results);
+        }
+        assert_eq!(outcomes[0], outcomes[1]);
+        let successes = outcomes[0].iter().filter(|ok| **ok).count();
+        assert!((20..80).contains(&successes), "successes: {successes}");
+    }
+
+    #[tokio::test]
+    async fn throttle() {
+        let spec = FaultSpec::from_json(r#"{"list_objects": {"max_requests_per_second": 5}}"#).unwrap();
+        let client = fault_injection_client(mock_client(), spec);
+        for _ in 0..5 {
+            client.list_objects("test_bucket", None, "/", 10, "").await.unwrap();
+        }
+        let err = client.list_objects("test_bucket", None, "/", 10, "").await.unwrap_err();
+        assert_injected(err, Some(503));
+    }
+
+    #[tokio::test]
+    async fn latency() {
+        let spec = r#"{"head_object": {"latency": {"distribution": "fixed", "millis": 50}}}"#;
+        let client = fault_injection_client(mock_client(), FaultSpec::from_json(spec).unwrap());
+        let start = Instant::now();
+        client.head_object("test_bucket", "key").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test_case(None; "no range")]
+    #[test_case(Some(1000..3000); "range")]
+    #[tokio::test]
+    async fn truncate_get_object(range: Option<Range<u64>>) {
+        let spec = FaultSpec::from_json(r#"{"get_object": {"interrupt_rate": 1.0}}"#).unwrap();
+        let client = fault_injection_client(mock_client(), spec);
+        let expected_end = range.as_ref().map(|range| range.end).unwrap_or(4096);
+        let mut request = client.get_object("test_bucket", "key", range, None).await.unwrap();
+
+        let mut next_offset = None;
+        let mut result = None;
+        while let Some(part) = request.next().await {
+            match part {
+                Ok((offset, data)) => {
+                    if let Some(next_offset) = next_offset {
+                        assert_eq!(offset, next_offset);
+                    }
+                    next_offset = Some(offset + data.len() as u64);
+                }
+                Err(e) => {
+                    result = Some(e);
+                    break;
+                }
+            }
+        }
+        assert_injected(result.expect("body should be interrupted"), None);
+        assert!(next_offset.unwrap_or_default() < expected_end);
+        assert!(request.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn interrupt_upload() {
+        let spec = FaultSpec::from_json(r#"{"put_object": {"interrupt_rate": 1.0}}"#).unwrap();
+        let client = fault_injection_client(mock_client(), spec);
+        let mut request = client
+            .put_object("test_bucket", "new", &Default::default())
+            .await
+            .unwrap();
+
+        // Interrupted uploads fail within a few parts, or at completion
+        let mut write_failed = false;
+        for _ in 0..MAX_PARTS_BEFORE_UPLOAD_INTERRUPTION {
+            if let Err(e) = request.write(&[0u8; 1024]).await {
+                assert_injected(e, None);
+                write_failed = true;
+                break;
+            }
+        }
+        assert!(write_failed || request.complete().await.is_err());
+        client.head_object("test_bucket", "new").await.unwrap_err();
+    }
+
+    #[test_case(r#"{"get_object": {"error_rate": 1.5}}"#; "rate out of range")]
+    #[test_case(r#"{"head_object": {"interrupt_rate": 0.5}}"#; "interrupt unsupported")]
+    #[test_case(r#"{"get_object": {"max_requests_per_second": 0}}"#; "zero rate limit")]
+    #[test_case(r#"{"get_object": {"latency": {"distribution": "uniform", "min_millis": 5, "max_millis": 1}}}"#; "inverted latency")]
+    #[test_case(r#"{"get_objects": {}}"#; "unknown operation")]
+    fn invalid_spec(spec: &str) {
+        FaultSpec::from_json(spec).expect_err("spec should be invalid");
+    }
+}
@@ -38,22 +38,47 @@
 //! let client = S3CrtClient::new(config).expect("client construction failed");
 //! ```
 //!
+//! With the `http_client` feature enabled, this crate also provides `S3HttpClient`, an alternative
+//! implementation of [`ObjectClient`] that makes requests with a pure-Rust HTTP stack instead of
+//! the CRT. It only loads credentials from environment variables or from static credentials
+//! provided in its configuration.
+//!
+//! `S3CrtClient` and the other CRT-backed modules are behind the default `crt` feature. Building
+//! with `--no-default-features --features http_client` gives a client that does not link the CRT
+//! at all.
+//!
 //! [awscrt]: https://docs.aws.amazon.com/sdkref/latest/guide/common-runtime.html
 
 // Make async trait docs not-ugly on docs.rs (https://github.com/dtolnay/async-trait/issues/213)
 #![cfg_attr(docsrs, feature(async_fn_in_trait))]
 
+mod addressing_style;
 mod build_info;
 pub mod checksums;
+#[cfg(feature = "crt")]
 mod endpoint_config;
 #[doc(hidden)]
 pub mod failure_client;
+#[cfg(feature = "crt")]
 pub mod imds_crt_client;
+#[cfg(feature = "crt")]
 pub mod instance_info;
 #[doc(hidden)]
+pub mod local_fs_client;
+#[doc(hidden)]
 pub mod mock_client;
+#[doc(hidden)]
+pub mod mock_server;
 mod object_client;
+#[cfg(feature = "crt")]
 mod s3_crt_client;
+#[cfg(feature = "http_client")]
+mod s3_http_client;
+mod s3_response;
+#[cfg(any(feature = "http_client", feature = "mock_server"))]
+mod sigv4;
+#[doc(hidden)]
+pub mod trace_client;
 #[doc(hidden)]
 pub mod user_agent;
 
@@ -61,21 +86,35 @@ pub mod error_metadata;
 
 pub use object_client::{ObjectClient, PutObjectRequest};
 
+#[cfg(feature = "crt")]
 pub use s3_crt_client::{get_object::S3GetObjectRequest, put_object::S3PutObjectRequest, S3CrtClient, S3RequestError};
 
+#[cfg(feature = "http_client")]
+pub use s3_http_client::{
+    ErrorResponse, NewHttpClientError, S3HttpClient, S3HttpGetObjectRequest, S3HttpPutObjectRequest, S3HttpRequestError,
+};
+
 /// Configuration for the S3 client
 pub mod config {
-    pub use super::endpoint_config::{AddressingStyle, EndpointConfig};
+    pub use super::addressing_style::AddressingStyle;
+    #[cfg(feature = "crt")]
+    pub use super::endpoint_config::EndpointConfig;
+    #[cfg(feature = "crt")]
     pub use super::s3_crt_client::{S3ClientAuthConfig, S3ClientConfig};
+    #[cfg(feature = "http_client")]
+    pub use super::s3_http_client::{S3HttpClientAuthConfig, S3HttpClientConfig};
+    #[cfg(any(feature = "http_client", feature = "mock_server"))]
+    pub use super::sigv4::Credentials;
 }
 
 /// Types used by all object clients
 pub mod types {
     pub use super::object_client::{
-        Checksum, ChecksumAlgorithm, DeleteObjectResult, ETag, GetBodyPart, GetObjectAttributesParts,
-        GetObjectAttributesResult, GetObjectRequest, HeadObjectResult, ListObjectsResult, ObjectAttribute,
-        ObjectClientResult, ObjectInfo, ObjectPart, PutObjectParams, PutObjectResult, PutObjectSingleParams,
-        PutObjectTrailingChecksums, RestoreStatus, UploadChecksum, UploadReview, UploadReviewPart,
+        BufferPoolUsageStats, Checksum, ChecksumAlgorithm, DeleteObjectResult, ETag, GetBodyPart,
+        GetObjectAttributesParts, GetObjectAttributesResult, GetObjectRequest, HeadObjectResult, ListObjectsResult,
+        ObjectAttribute, ObjectClientResult, ObjectInfo, ObjectPart, PutObjectParams, PutObjectResult,
+        PutObjectSingleParams, PutObjectTrailingChecksums, RestoreStatus, UploadChecksum, UploadReview,
+        UploadReviewPart,
     };
 }
 
@@ -90,10 +129,11 @@ pub mod error {
         ObjectClientError, PutObjectError,
     };
     #[doc(hidden)]
+    #[cfg(feature = "crt")]
     pub use super::s3_crt_client::HeadBucketError;
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "crt"))]
 mod tests {
     use crate::s3_crt_client::S3CrtClient;
 
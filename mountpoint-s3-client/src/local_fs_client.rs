@@ -0,0 +1,1320 @@
+//! An object client that stores objects in a directory on the local filesystem.
+//!
+//! Each object is stored as a regular file at the path given by its key, relative to the client's
+//! root directory, so an existing directory tree can be served as a bucket as-is. Object metadata
+//! that a filesystem can't hold (ETag, storage class, user-defined metadata, and the part layout of
+//! multi-part uploads) is kept in JSON sidecar files under a reserved `.mountpoint-s3` directory in
+//! the root. Files without a sidecar, or whose sidecar no longer matches their size and
+//! modification time, are still visible, with an ETag derived from their size and modification
+//! time.
+//!
+//! Not every key can be stored this way. Keys that aren't valid relative paths (for example, keys
+//! with empty, `.` or `..` components), keys under the reserved directory, and a key that is also
+//! a prefix of another key (like `a` and `a/b`) are rejected with a client error.
+
+#![cfg(feature = "mock")]
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::{ControlFlow, Range};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::UNIX_EPOCH;
+
+use async_trait::async_trait;
+use futures::Stream;
+use md5::{Digest as _, Md5};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::OffsetDateTime;
+use tracing::trace;
+
+use crate::checksums::{crc32c, crc32c_to_base64};
+use crate::error_metadata::{ClientErrorMetadata, ProvideErrorMetadata};
+use crate::object_client::{
+    BufferPoolUsageStats, Checksum, ChecksumAlgorithm, DeleteObjectError, DeleteObjectResult, ETag, GetBodyPart,
+    GetObjectAttributesError, GetObjectAttributesParts, GetObjectAttributesResult, GetObjectError, GetObjectRequest,
+    HeadObjectError, HeadObjectResult, ListObjectsError, ListObjectsResult, ObjectAttribute, ObjectClient,
+    ObjectClientError, ObjectClientResult, ObjectInfo, ObjectPart, PutObjectError, PutObjectParams, PutObjectRequest,
+    PutObjectResult, PutObjectSingleParams, PutObjectTrailingChecksums, UploadChecksum, UploadReview, UploadReviewPart,
+};
+
+/// Directory under the root that holds sidecar metadata and in-progress uploads
+const RESERVED_DIR: &str = ".mountpoint-s3";
+const METADATA_DIR: &str = "metadata";
+const UPLOADS_DIR: &str = "uploads";
+
+#[derive(Debug, Default, Clone)]
+pub struct LocalFsClientConfig {
+    /// The bucket name this client will connect to
+    pub bucket: String,
+    /// The directory holding the bucket's objects. Created if it doesn't exist.
+    pub root: PathBuf,
+    /// The size of the parts that GetObject will respond with, and that uploads are split into
+    pub part_size: usize,
+    /// A flag to enable backpressure read
+    pub enable_backpressure: bool,
+    /// Initial backpressure read window size, ignored if enable_backpressure is false
+    pub initial_read_window_size: usize,
+}
+
+/// An object client that serves a bucket out of a local directory. See the [module
+/// documentation](self) for how objects are laid out.
+#[derive(Debug, Clone)]
+pub struct LocalFsClient {
+    config: Arc<LocalFsClientConfig>,
+    next_upload_id: Arc<AtomicU64>,
+}
+
+impl LocalFsClient {
+    /// Create a new [LocalFsClient] with the given config
+    pub fn new(config: LocalFsClientConfig) -> Result<Self, LocalFsClientError> {
+        fs::create_dir_all(config.root.join(RESERVED_DIR).join(UPLOADS_DIR))?;
+        Ok(Self {
+            config: Arc::new(config),
+            next_upload_id: Default::default(),
+        })
+    }
+
+    /// Path to the file holding the object with the given key
+    fn object_path(&self, key: &str) -> Result<PathBuf, LocalFsClientError> {
+        let is_valid = !key.is_empty()
+            && !key.contains('\0')
+            && key.split('/').all(|c| !c.is_empty() && c != "." && c != "..")
+            && key.split('/').next() != Some(RESERVED_DIR);
+        if !is_valid {
+            return Err(LocalFsClientError::InvalidKey(key.to_owned()));
+        }
+        Ok(self.config.root.join(key))
+    }
+
+    /// Path to the sidecar file for the given (already validated) key. The metadata directory
+    /// mirrors the layout of the root, so sidecars can't collide with each other's directories.
+    fn sidecar_path(&self, key: &str) -> PathBuf {
+        self.config.root.join(RESERVED_DIR).join(METADATA_DIR).join(key)
+    }
+
+    /// A fresh path for a temporary file in the uploads directory
+    fn temp_path(&self) -> PathBuf {
+        let id = self.next_upload_id.fetch_add(1, Ordering::SeqCst);
+        self.config
+            .root
+            .join(RESERVED_DIR)
+            .join(UPLOADS_DIR)
+            .join(format!("{}-{}", std::process::id(), id))
+    }
+
+    /// Look up the object with the given key, returning `None` if it doesn't exist
+    fn stat_object(&self, key: &str) -> Result<Option<LocalObject>, LocalFsClientError> {
+        let path = self.object_path(key)?;
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(self.read_object(key, &metadata)?)),
+            Ok(_) => Ok(None),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Combine a file's metadata with its sidecar, if the sidecar is present and up to date
+    fn read_object(&self, key: &str, metadata: &fs::Metadata) -> Result<LocalObject, LocalFsClientError> {
+        let size = metadata.len();
+        let mtime_nanos = mtime_nanos(metadata)?;
+        let sidecar = match fs::read(self.sidecar_path(key)) {
+            Ok(bytes) => serde_json::from_slice::<ObjectSidecar>(&bytes)
+                .ok()
+                .filter(|sidecar| sidecar.size == size && sidecar.mtime_nanos == mtime_nanos),
+            Err(e) if is_not_found(&e) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let sidecar = sidecar.unwrap_or_else(|| ObjectSidecar {
+            size,
+            mtime_nanos,
+            etag: format!("{mtime_nanos:x}-{size:x}"),
+            ..Default::default()
+        });
+        Ok(LocalObject {
+            key: key.to_owned(),
+            last_modified: OffsetDateTime::from(metadata.modified()?),
+            sidecar,
+        })
+    }
+
+    /// Move a fully written temporary file into place as the object with the given key, and write
+    /// its sidecar. The `size` and `mtime_nanos` fields of the sidecar are filled in here.
+    fn finish_upload(
+        &self,
+        temp_path: &Path,
+        key: &str,
+        mut sidecar: ObjectSidecar,
+    ) -> Result<ETag, LocalFsClientError> {
+        let path = self.object_path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(temp_path, &path)?;
+
+        let metadata = fs::metadata(&path)?;
+        sidecar.size = metadata.len();
+        sidecar.mtime_nanos = mtime_nanos(&metadata)?;
+
+        // Write the sidecar atomically too, so readers never see a partial one
+        let sidecar_path = self.sidecar_path(key);
+        if let Some(parent) = sidecar_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let sidecar_temp_path = self.temp_path();
+        fs::write(
+            &sidecar_temp_path,
+            serde_json::to_vec(&sidecar).expect("sidecar is serializable"),
+        )?;
+        fs::rename(&sidecar_temp_path, &sidecar_path)?;
+
+        Ok(ETag::from(sidecar.etag))
+    }
+
+    /// Remove empty directories from `path` up to (but not including) `root`, since S3 has no
+    /// directories that outlive their last object.
+    fn remove_empty_parents(root: &Path, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == root || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+
+    /// A page of the entries under `prefix` after `start_after`, for ListObjects requests.
+    fn list_page(
+        &self,
+        bucket: &str,
+        start_after: Option<&str>,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, LocalFsClientError> {
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket));
+        }
+
+        let mut page = ListPage {
+            prefix,
+            delimiter,
+            continuation_token,
+            start_after,
+            max_keys,
+            entries: Vec::new(),
+        };
+        self.list_entries(&mut page).map_err(ObjectClientError::ClientError)?;
+
+        // Continuation tokens are just the name of the first entry of the next page
+        let next_continuation_token = if page.entries.len() > max_keys {
+            page.entries.pop().map(|entry| entry.name().to_owned())
+        } else {
+            None
+        };
+
+        let mut objects = Vec::new();
+        let mut common_prefixes = Vec::new();
+        for entry in page.entries {
+            match entry {
+                ListEntry::Object(object) => objects.push(object),
+                ListEntry::CommonPrefix(prefix) => common_prefixes.push(prefix),
+            }
+        }
+
+        Ok(ListObjectsResult {
+            objects,
+            common_prefixes,
+            next_continuation_token,
+        })
+    }
+
+    /// Collect the objects and common prefixes for the given page, in key order. Only the
+    /// entries on the page (and the first entry of the next one) have their sidecars read.
+    fn list_entries(&self, page: &mut ListPage) -> Result<(), LocalFsClientError> {
+        // Start from the directory containing the prefix. If that directory can't exist, neither
+        // can any matching keys.
+        let prefix = page.prefix;
+        let parent = prefix.rfind('/').map(|i| &prefix[..i + 1]).unwrap_or("");
+        let dir = if parent.is_empty() {
+            self.config.root.clone()
+        } else {
+            match self.object_path(&parent[..parent.len() - 1]) {
+                Ok(dir) => dir,
+                Err(_) => return Ok(()),
+            }
+        };
+
+        // With a `/` delimiter, subdirectories map directly to common prefixes, so we don't need
+        // to walk them. Otherwise, walk every key and roll them up as we go.
+        let recursive = page.delimiter != "/";
+        self.walk(&dir, parent, recursive, page).map(|_| ())
+    }
+
+    /// Add the keys under `dir` to `page` in key order, until the page is full. Subdirectories
+    /// whose keys all sort before the start of the page are skipped without being read.
+    fn walk(
+        &self,
+        dir: &Path,
+        key_prefix: &str,
+        recursive: bool,
+        page: &mut ListPage,
+    ) -> Result<ControlFlow<()>, LocalFsClientError> {
+        let read_dir = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if is_not_found(&e) || e.kind() == io::ErrorKind::NotADirectory => {
+                return Ok(ControlFlow::Continue(()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // A directory's keys all start with `name/`, so sorting directories by that puts the
+        // children in the same order as their keys.
+        let mut children = Vec::new();
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            // Names that aren't valid UTF-8 can't be S3 keys
+            let Ok(name) = dir_entry.file_name().into_string() else {
+                continue;
+            };
+            if key_prefix.is_empty() && name == RESERVED_DIR {
+                continue;
+            }
+            let path = dir_entry.path();
+            // Follow symlinks, so that a symlinked file or directory looks like the real thing
+            let file_type = dir_entry.file_type()?;
+            let is_dir = if file_type.is_symlink() {
+                match fs::metadata(&path) {
+                    Ok(metadata) => metadata.is_dir(),
+                    Err(e) if is_not_found(&e) => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            } else {
+                file_type.is_dir()
+            };
+            let key = if is_dir {
+                format!("{key_prefix}{name}/")
+            } else {
+                format!("{key_prefix}{name}")
+            };
+            if !key.starts_with(page.prefix) || (is_dir && page.is_before(&key)) {
+                continue;
+            }
+            children.push((key, path, is_dir));
+        }
+        children.sort_unstable_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+        for (key, path, is_dir) in children {
+            let entry = if is_dir {
+                if recursive {
+                    if self.walk(&path, &key, recursive, page)?.is_break() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+                    continue;
+                }
+                if !page.includes(&key) || !contains_files(&path)? {
+                    continue;
+                }
+                ListEntry::CommonPrefix(key)
+            } else if let Some(common_prefix) = page.common_prefix(&key) {
+                if !page.includes(&common_prefix) {
+                    continue;
+                }
+                ListEntry::CommonPrefix(common_prefix)
+            } else {
+                if !page.includes(&key) {
+                    continue;
+                }
+                let metadata = match fs::metadata(&path) {
+                    Ok(metadata) => metadata,
+                    Err(e) if is_not_found(&e) => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                if !metadata.is_file() {
+                    continue;
+                }
+                ListEntry::Object(self.read_object(&key, &metadata)?.info())
+            };
+            if page.push(entry).is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Returns `true` if the given directory, or any directory beneath it, contains a file
+fn contains_files(dir: &Path) -> Result<bool, LocalFsClientError> {
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if is_not_found(&e) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if metadata.is_file() || (metadata.is_dir() && contains_files(&path)?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn mtime_nanos(metadata: &fs::Metadata) -> io::Result<u64> {
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(mtime.as_nanos() as u64)
+}
+
+fn is_not_found(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::NotFound
+}
+
+/// An entry in a listing, before pagination
+#[derive(Debug)]
+enum ListEntry {
+    Object(ObjectInfo),
+    CommonPrefix(String),
+}
+
+impl ListEntry {
+    fn name(&self) -> &str {
+        match self {
+            ListEntry::Object(object) => &object.key,
+            ListEntry::CommonPrefix(prefix) => prefix,
+        }
+    }
+}
+
+/// A page of a listing, collected in key order
+struct ListPage<'a> {
+    prefix: &'a str,
+    delimiter: &'a str,
+    continuation_token: Option<&'a str>,
+    start_after: Option<&'a str>,
+    max_keys: usize,
+    /// The entries on the page, followed by the first entry of the next page if there is one
+    entries: Vec<ListEntry>,
+}
+
+impl ListPage<'_> {
+    /// Does an entry with the given name belong on this page or a later one?
+    fn includes(&self, name: &str) -> bool {
+        self.continuation_token.is_none_or(|token| name >= token)
+            && self.start_after.is_none_or(|start_after| name > start_after)
+    }
+
+    /// Returns `true` if every key starting with `key_prefix` sorts before the start of the page
+    fn is_before(&self, key_prefix: &str) -> bool {
+        [self.continuation_token, self.start_after]
+            .into_iter()
+            .flatten()
+            .any(|start| key_prefix < start && !start.starts_with(key_prefix))
+    }
+
+    /// The common prefix that the given key is rolled up into, if any
+    fn common_prefix(&self, key: &str) -> Option<String> {
+        if self.delimiter.is_empty() {
+            return None;
+        }
+        let remaining_key = &key[self.prefix.len()..];
+        let (pre, _) = remaining_key.split_once(self.delimiter)?;
+        Some(format!("{}{pre}{}", self.prefix, self.delimiter))
+    }
+
+    /// Add the next entry in key order, returning [ControlFlow::Break] once the page is full and
+    /// the first entry of the next page is known.
+    fn push(&mut self, entry: ListEntry) -> ControlFlow<()> {
+        // Keys rolled up into the same common prefix are adjacent
+        if self.entries.last().is_some_and(|last| last.name() == entry.name()) {
+            return ControlFlow::Continue(());
+        }
+        self.entries.push(entry);
+        if self.entries.len() > self.max_keys {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+/// An object as found on disk, with its sidecar metadata
+#[derive(Debug)]
+struct LocalObject {
+    key: String,
+    last_modified: OffsetDateTime,
+    sidecar: ObjectSidecar,
+}
+
+impl LocalObject {
+    fn info(&self) -> ObjectInfo {
+        ObjectInfo {
+            key: self.key.clone(),
+            size: self.sidecar.size,
+            last_modified: self.last_modified,
+            storage_class: self.sidecar.storage_class.clone(),
+            restore_status: None,
+            etag: self.sidecar.etag.clone(),
+        }
+    }
+}
+
+/// Metadata stored alongside each object. The size and modification time of the object's file
+/// when the sidecar was written are used to detect files that were changed behind our back.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct ObjectSidecar {
+    size: u64,
+    mtime_nanos: u64,
+    etag: String,
+    #[serde(default)]
+    storage_class: Option<String>,
+    #[serde(default)]
+    object_metadata: HashMap<String, String>,
+    #[serde(default)]
+    checksum_crc32c: Option<String>,
+    #[serde(default)]
+    parts: Option<LocalObjectParts>,
+}
+
+/// As with the mock client, per-part data is only reported from GetObjectAttributes if parts were
+/// uploaded with additional checksums. Otherwise, the only thing we report is the number of parts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LocalObjectParts {
+    Count(usize),
+    Parts(Vec<LocalObjectPart>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LocalObjectPart {
+    size: u64,
+    checksum: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct LocalFsGetObjectRequest {
+    file: File,
+    next_offset: u64,
+    end_offset: u64,
+    part_size: usize,
+    enable_backpressure: bool,
+    read_window_end_offset: u64,
+}
+
+impl GetObjectRequest for LocalFsGetObjectRequest {
+    type ClientError = LocalFsClientError;
+
+    fn increment_read_window(mut self: Pin<&mut Self>, len: usize) {
+        self.read_window_end_offset += len as u64;
+    }
+
+    fn read_window_end_offset(self: Pin<&Self>) -> u64 {
+        self.read_window_end_offset
+    }
+}
+
+impl Stream for LocalFsGetObjectRequest {
+    type Item = ObjectClientResult<GetBodyPart, GetObjectError, LocalFsClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.next_offset >= self.end_offset {
+            return Poll::Ready(None);
+        }
+
+        // Simulate backpressure mechanism
+        if self.enable_backpressure && self.next_offset >= self.read_window_end_offset {
+            return Poll::Ready(Some(Err(ObjectClientError::ClientError(
+                LocalFsClientError::EmptyReadWindow,
+            ))));
+        }
+
+        let next_read_size = (self.part_size as u64).min(self.end_offset - self.next_offset) as usize;
+        let mut buffer = vec![0u8; next_read_size];
+        if let Err(e) = self.file.read_exact_at(&mut buffer, self.next_offset) {
+            // Don't return any more data after a failed read
+            self.end_offset = self.next_offset;
+            return Poll::Ready(Some(Err(ObjectClientError::ClientError(e.into()))));
+        }
+
+        let result = (self.next_offset, buffer.into_boxed_slice());
+        self.next_offset += next_read_size as u64;
+        Poll::Ready(Some(Ok(result)))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LocalFsClientError {
+    #[error("key {0:?} cannot be stored in a local directory")]
+    InvalidKey(String),
+
+    #[error("invalid range, length={0}")]
+    InvalidRange(u64),
+
+    #[error("empty read window")]
+    EmptyReadWindow,
+
+    #[error("upload review failed, aborting")]
+    ReviewFailed,
+
+    #[error("IO error")]
+    IoError(#[from] io::Error),
+}
+
+impl ProvideErrorMetadata for LocalFsClientError {
+    fn meta(&self) -> ClientErrorMetadata {
+        Default::default()
+    }
+}
+
+#[cfg_attr(not(docsrs), async_trait)]
+impl ObjectClient for LocalFsClient {
+    type GetObjectRequest = LocalFsGetObjectRequest;
+    type PutObjectRequest = LocalFsPutObjectRequest;
+    type ClientError = LocalFsClientError;
+
+    fn read_part_size(&self) -> Option<usize> {
+        Some(self.config.part_size)
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
+        Some(self.config.part_size)
+    }
+
+    fn initial_read_window_size(&self) -> Option<usize> {
+        if self.config.enable_backpressure {
+            Some(self.config.initial_read_window_size)
+        } else {
+            None
+        }
+    }
+
+    fn mem_usage_stats(&self) -> Option<BufferPoolUsageStats> {
+        None
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        trace!(bucket, key, "DeleteObject");
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(DeleteObjectError::NoSuchBucket));
+        }
+
+        // Keys that can't be stored can't exist, so deleting them trivially succeeds
+        let Ok(path) = self.object_path(key) else {
+            return Ok(DeleteObjectResult {});
+        };
+        if path.is_file() {
+            fs::remove_file(&path).map_err(|e| ObjectClientError::ClientError(e.into()))?;
+            Self::remove_empty_parents(&self.config.root, &path);
+        }
+
+        let sidecar_path = self.sidecar_path(key);
+        if fs::remove_file(&sidecar_path).is_ok() {
+            Self::remove_empty_parents(&self.config.root.join(RESERVED_DIR).join(METADATA_DIR), &sidecar_path);
+        }
+
+        Ok(DeleteObjectResult {})
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        range: Option<Range<u64>>,
+        if_match: Option<ETag>,
+    ) -> ObjectClientResult<Self::GetObjectRequest, GetObjectError, Self::ClientError> {
+        trace!(bucket, key, ?range, ?if_match, "GetObject");
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchBucket));
+        }
+
+        let Ok(path) = self.object_path(key) else {
+            return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey));
+        };
+        // Open the file before looking at its metadata, so that the data we return is consistent
+        // with the ETag we check even if the object is replaced concurrently.
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if is_not_found(&e) => return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey)),
+            Err(e) => return Err(ObjectClientError::ClientError(e.into())),
+        };
+        let metadata = file.metadata().map_err(|e| ObjectClientError::ClientError(e.into()))?;
+        if !metadata.is_file() {
+            return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey));
+        }
+        let object = self
+            .read_object(key, &metadata)
+            .map_err(ObjectClientError::ClientError)?;
+
+        if let Some(etag_match) = if_match {
+            if etag_match.as_str() != object.sidecar.etag {
+                return Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed));
+            }
+        }
+
+        let size = object.sidecar.size;
+        let (next_offset, end_offset) = if let Some(range) = range {
+            if range.start > range.end || range.end > size {
+                return Err(ObjectClientError::ClientError(LocalFsClientError::InvalidRange(size)));
+            }
+            (range.start, range.end)
+        } else {
+            (0, size)
+        };
+
+        Ok(LocalFsGetObjectRequest {
+            file,
+            next_offset,
+            end_offset,
+            part_size: self.config.part_size,
+            enable_backpressure: self.config.enable_backpressure,
+            read_window_end_offset: next_offset + self.config.initial_read_window_size as u64,
+        })
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        trace!(bucket, key, "HeadObject");
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(HeadObjectError::NotFound));
+        }
+
+        match self.stat_object(key) {
+            Ok(Some(object)) => Ok(HeadObjectResult {
+                bucket: bucket.to_string(),
+                object: object.info(),
+            }),
+            Ok(None) | Err(LocalFsClientError::InvalidKey(_)) => {
+                Err(ObjectClientError::ServiceError(HeadObjectError::NotFound))
+            }
+            Err(e) => Err(ObjectClientError::ClientError(e)),
+        }
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        trace!(bucket, ?continuation_token, delimiter, max_keys, prefix, "ListObjects");
+        self.list_page(bucket, None, continuation_token, delimiter, max_keys, prefix)
+    }
+
+    async fn list_objects_after(
+        &self,
+        bucket: &str,
+        start_after: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        trace!(
+            bucket,
+            start_after,
+            ?continuation_token,
+            delimiter,
+            max_keys,
+            prefix,
+            "ListObjects"
+        );
+        self.list_page(
+            bucket,
+            Some(start_after),
+            continuation_token,
+            delimiter,
+            max_keys,
+            prefix,
+        )
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<Self::PutObjectRequest, PutObjectError, Self::ClientError> {
+        trace!(bucket, key, "PutObject");
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(PutObjectError::NoSuchBucket));
+        }
+
+        self.object_path(key).map_err(ObjectClientError::ClientError)?;
+        let temp_path = self.temp_path();
+        let file = File::create(&temp_path).map_err(|e| ObjectClientError::ClientError(e.into()))?;
+
+        Ok(LocalFsPutObjectRequest {
+            client: self.clone(),
+            key: key.to_owned(),
+            params: params.clone(),
+            temp_path,
+            file,
+            buffer: Vec::new(),
+            parts: Vec::new(),
+            completed: false,
+        })
+    }
+
+    async fn put_object_single<'a>(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectSingleParams,
+        contents: impl AsRef<[u8]> + Send + 'a,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        trace!(bucket, key, "PutObject");
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(PutObjectError::NoSuchBucket));
+        }
+
+        self.object_path(key).map_err(ObjectClientError::ClientError)?;
+        let contents = contents.as_ref();
+        let sidecar = ObjectSidecar {
+            etag: ETag::from_object_bytes(contents).into_inner(),
+            storage_class: params.storage_class.clone(),
+            object_metadata: params.object_metadata.clone(),
+            checksum_crc32c: params.checksum.as_ref().map(|checksum| match checksum {
+                UploadChecksum::Crc32c(crc32c) => crc32c_to_base64(crc32c),
+            }),
+            ..Default::default()
+        };
+
+        let temp_path = self.temp_path();
+        let result = fs::write(&temp_path, contents)
+            .map_err(LocalFsClientError::from)
+            .and_then(|()| self.finish_upload(&temp_path, key, sidecar));
+        match result {
+            Ok(etag) => Ok(PutObjectResult {
+                etag,
+                sse_type: None,
+                sse_kms_key_id: None,
+            }),
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(ObjectClientError::ClientError(e))
+            }
+        }
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        _max_parts: Option<usize>,
+        _part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        trace!(bucket, key, "GetObjectAttributes");
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(GetObjectAttributesError::NoSuchBucket));
+        }
+
+        let object = match self.stat_object(key) {
+            Ok(Some(object)) => object,
+            Ok(None) | Err(LocalFsClientError::InvalidKey(_)) => {
+                return Err(ObjectClientError::ServiceError(GetObjectAttributesError::NoSuchKey))
+            }
+            Err(e) => return Err(ObjectClientError::ClientError(e)),
+        };
+
+        let sidecar = object.sidecar;
+        let mut result = GetObjectAttributesResult::default();
+        for attribute in object_attributes.iter() {
+            match attribute {
+                ObjectAttribute::ETag => result.etag = Some(sidecar.etag.clone()),
+                ObjectAttribute::Checksum => {
+                    result.checksum = sidecar.checksum_crc32c.clone().map(|checksum_crc32c| Checksum {
+                        checksum_crc32: None,
+                        checksum_crc32c: Some(checksum_crc32c),
+                        checksum_sha1: None,
+                        checksum_sha256: None,
+                    })
+                }
+                ObjectAttribute::ObjectParts => {
+                    result.object_parts = match &sidecar.parts {
+                        Some(LocalObjectParts::Count(num_parts)) => Some(GetObjectAttributesParts {
+                            is_truncated: None,
+                            max_parts: None,
+                            next_part_number_marker: None,
+                            part_number_marker: None,
+                            parts: None,
+                            total_parts_count: Some(*num_parts),
+                        }),
+                        Some(LocalObjectParts::Parts(parts)) => Some(GetObjectAttributesParts {
+                            is_truncated: Some(false),
+                            max_parts: Some(10000),
+                            next_part_number_marker: Some(parts.len()),
+                            part_number_marker: Some(0),
+                            parts: Some(
+                                parts
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(i, part)| ObjectPart {
+                                        checksum: Some(Checksum {
+                                            checksum_crc32: None,
+                                            checksum_crc32c: part.checksum.clone(),
+                                            checksum_sha1: None,
+                                            checksum_sha256: None,
+                                        }),
+                                        // Part numbers start at 1
+                                        part_number: i + 1,
+                                        size: part.size as usize,
+                                    })
+                                    .collect(),
+                            ),
+                            total_parts_count: Some(parts.len()),
+                        }),
+                        None => None,
+                    };
+                }
+                ObjectAttribute::StorageClass => sidecar.storage_class.clone_into(&mut result.storage_class),
+                ObjectAttribute::ObjectSize => result.object_size = Some(sidecar.size),
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// An in-progress upload to a [LocalFsClient].
+///
+/// Data is written to a temporary file in the reserved directory, and split into parts of the
+/// client's part size to emulate a multi-part upload. The file is moved into place when the upload
+/// completes, or removed if the request is dropped before then.
+#[derive(Debug)]
+pub struct LocalFsPutObjectRequest {
+    client: LocalFsClient,
+    key: String,
+    params: PutObjectParams,
+    temp_path: PathBuf,
+    file: File,
+    /// Data for the current part, always smaller than a part after a successful write
+    buffer: Vec<u8>,
+    parts: Vec<UploadedPart>,
+    completed: bool,
+}
+
+#[derive(Debug)]
+struct UploadedPart {
+    size: u64,
+    checksum: Option<String>,
+    md5: Vec<u8>,
+}
+
+impl LocalFsPutObjectRequest {
+    fn upload_part(&mut self, data: &[u8]) -> Result<(), LocalFsClientError> {
+        self.file.write_all(data)?;
+        let checksum = (self.params.trailing_checksums != PutObjectTrailingChecksums::Disabled)
+            .then(|| crc32c_to_base64(&crc32c::checksum(data)));
+        self.parts.push(UploadedPart {
+            size: data.len() as u64,
+            checksum,
+            md5: Md5::digest(data).to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Upload whatever is left in the buffer as the final part. Every upload has at least one part,
+    /// even if it's empty.
+    fn upload_final_part(&mut self) -> Result<(), LocalFsClientError> {
+        if !self.buffer.is_empty() || self.parts.is_empty() {
+            let buffer = std::mem::take(&mut self.buffer);
+            self.upload_part(&buffer)?;
+        }
+        Ok(())
+    }
+
+    fn complete_inner(mut self) -> ObjectClientResult<PutObjectResult, PutObjectError, LocalFsClientError> {
+        self.file
+            .sync_all()
+            .map_err(|e| ObjectClientError::ClientError(e.into()))?;
+
+        // Multi-part ETags are the MD5 of the concatenated part MD5s, suffixed by the part count
+        let mut hasher = Md5::new();
+        for part in &self.parts {
+            hasher.update(&part.md5);
+        }
+        let etag = format!("{:x}-{}", hasher.finalize(), self.parts.len());
+
+        // For S3 Standard, part attributes are only available when additional checksums are used
+        let parts = if self.params.trailing_checksums == PutObjectTrailingChecksums::Enabled {
+            LocalObjectParts::Parts(
+                self.parts
+                    .iter()
+                    .map(|part| LocalObjectPart {
+                        size: part.size,
+                        checksum: part.checksum.clone(),
+                    })
+                    .collect(),
+            )
+        } else {
+            LocalObjectParts::Count(self.parts.len())
+        };
+        let sidecar = ObjectSidecar {
+            etag,
+            storage_class: self.params.storage_class.clone(),
+            object_metadata: self.params.object_metadata.clone(),
+            parts: Some(parts),
+            ..Default::default()
+        };
+
+        let etag = self
+            .client
+            .finish_upload(&self.temp_path, &self.key, sidecar)
+            .map_err(ObjectClientError::ClientError)?;
+        self.completed = true;
+        Ok(PutObjectResult {
+            etag,
+            sse_type: None,
+            sse_kms_key_id: None,
+        })
+    }
+}
+
+impl Drop for LocalFsPutObjectRequest {
+    fn drop(&mut self) {
+        if !self.completed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+#[cfg_attr(not(docsrs), async_trait)]
+impl PutObjectRequest for LocalFsPutObjectRequest {
+    type ClientError = LocalFsClientError;
+
+    async fn write(&mut self, slice: &[u8]) -> ObjectClientResult<(), PutObjectError, Self::ClientError> {
+        let part_size = self.client.config.part_size;
+        self.buffer.extend_from_slice(slice);
+        while self.buffer.len() >= part_size {
+            let rest = self.buffer.split_off(part_size);
+            let part = std::mem::replace(&mut self.buffer, rest);
+            self.upload_part(&part).map_err(ObjectClientError::ClientError)?;
+        }
+        Ok(())
+    }
+
+    async fn complete(mut self) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        self.upload_final_part().map_err(ObjectClientError::ClientError)?;
+        self.complete_inner()
+    }
+
+    async fn review_and_complete(
+        mut self,
+        review_callback: impl FnOnce(UploadReview) -> bool + Send + 'static,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        self.upload_final_part().map_err(ObjectClientError::ClientError)?;
+        let checksum_algorithm = if self.params.trailing_checksums != PutObjectTrailingChecksums::Disabled {
+            Some(ChecksumAlgorithm::Crc32c)
+        } else {
+            None
+        };
+        let review = UploadReview {
+            checksum_algorithm,
+            parts: self
+                .parts
+                .iter()
+                .map(|part| UploadReviewPart {
+                    size: part.size,
+                    checksum: part.checksum.clone(),
+                })
+                .collect(),
+        };
+        if !review_callback(review) {
+            return Err(ObjectClientError::ClientError(LocalFsClientError::ReviewFailed));
+        }
+        self.complete_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tempfile::TempDir;
+    use test_case::test_case;
+
+    use super::*;
+
+    fn new_client(part_size: usize) -> (TempDir, LocalFsClient) {
+        let dir = tempfile::tempdir().unwrap();
+        let client = LocalFsClient::new(LocalFsClientConfig {
+            bucket: "test_bucket".to_owned(),
+            root: dir.path().to_owned(),
+            part_size,
+            ..Default::default()
+        })
+        .unwrap();
+        (dir, client)
+    }
+
+    async fn get_bytes(client: &LocalFsClient, key: &str, range: Option<Range<u64>>) -> Vec<u8> {
+        let start = range.as_ref().map(|range| range.start).unwrap_or(0);
+        let mut request = client.get_object("test_bucket", key, range, None).await.unwrap();
+        let mut body = Vec::new();
+        while let Some((offset, part)) = request.next().await.transpose().unwrap() {
+            assert_eq!(offset, start + body.len() as u64);
+            body.extend_from_slice(&part);
+        }
+        body
+    }
+
+    #[tokio::test]
+    async fn put_get_delete() {
+        let (dir, client) = new_client(4);
+
+        let mut request = client
+            .put_object("test_bucket", "a/b/hello.txt", &PutObjectParams::new())
+            .await
+            .unwrap();
+        request.write(b"hello ").await.unwrap();
+        request.write(b"world").await.unwrap();
+        let result = request.complete().await.unwrap();
+        assert!(result.etag.as_str().ends_with("-3"));
+
+        // Objects are plain files in the root directory
+        assert_eq!(fs::read(dir.path().join("a/b/hello.txt")).unwrap(), b"hello world");
+        assert_eq!(get_bytes(&client, "a/b/hello.txt", None).await, b"hello world");
+        assert_eq!(get_bytes(&client, "a/b/hello.txt", Some(3..9)).await, b"lo wor");
+
+        let head = client.head_object("test_bucket", "a/b/hello.txt").await.unwrap();
+        assert_eq!(head.object.size, 11);
+        assert_eq!(head.object.etag, result.etag.as_str());
+
+        let err = client
+            .get_object("test_bucket", "a/b/hello.txt", None, Some("wrong".into()))
+            .await
+            .expect_err("etag doesn't match");
+        assert!(matches!(
+            err,
+            ObjectClientError::ServiceError(GetObjectError::PreconditionFailed)
+        ));
+
+        client.delete_object("test_bucket", "a/b/hello.txt").await.unwrap();
+        let err = client
+            .head_object("test_bucket", "a/b/hello.txt")
+            .await
+            .expect_err("object was deleted");
+        assert!(matches!(
+            err,
+            ObjectClientError::ServiceError(HeadObjectError::NotFound)
+        ));
+        // Empty directories are cleaned up
+        assert!(!dir.path().join("a").exists());
+    }
+
+    #[tokio::test]
+    async fn put_object_single_metadata() {
+        let (_dir, client) = new_client(1024);
+
+        let params = PutObjectSingleParams::new()
+            .storage_class("STANDARD_IA".to_owned())
+            .checksum(Some(UploadChecksum::Crc32c(crc32c::checksum(b"data"))));
+        let result = client
+            .put_object_single("test_bucket", "key", &params, b"data")
+            .await
+            .unwrap();
+        assert_eq!(result.etag, ETag::from_object_bytes(b"data"));
+
+        let attributes = client
+            .get_object_attributes(
+                "test_bucket",
+                "key",
+                None,
+                None,
+                &[ObjectAttribute::StorageClass, ObjectAttribute::Checksum],
+            )
+            .await
+            .unwrap();
+        assert_eq!(attributes.storage_class.as_deref(), Some("STANDARD_IA"));
+        assert_eq!(
+            attributes.checksum.unwrap().checksum_crc32c,
+            Some(crc32c_to_base64(&crc32c::checksum(b"data")))
+        );
+    }
+
+    #[tokio::test]
+    async fn unmanaged_files() {
+        let (dir, client) = new_client(1024);
+
+        fs::create_dir_all(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("dir/file"), b"contents").unwrap();
+
+        let head = client.head_object("test_bucket", "dir/file").await.unwrap();
+        assert_eq!(head.object.size, 8);
+        assert_eq!(get_bytes(&client, "dir/file", None).await, b"contents");
+
+        // Modifying the file changes its ETag, even if it had a sidecar
+        client
+            .put_object_single("test_bucket", "dir/file", &Default::default(), b"contents")
+            .await
+            .unwrap();
+        let etag = client.head_object("test_bucket", "dir/file").await.unwrap().object.etag;
+        fs::write(dir.path().join("dir/file"), b"new contents").unwrap();
+        let head = client.head_object("test_bucket", "dir/file").await.unwrap();
+        assert_ne!(head.object.etag, etag);
+        assert_eq!(head.object.size, 12);
+    }
+
+    #[test_case(""; "empty")]
+    #[test_case("/a"; "leading slash")]
+    #[test_case("a//b"; "empty component")]
+    #[test_case("a/"; "trailing slash")]
+    #[test_case("a/../b"; "parent component")]
+    #[test_case(".mountpoint-s3/metadata/a"; "reserved directory")]
+    #[tokio::test]
+    async fn invalid_keys(key: &str) {
+        let (_dir, client) = new_client(1024);
+
+        let err = client
+            .put_object_single("test_bucket", key, &Default::default(), b"data")
+            .await
+            .expect_err("key is invalid");
+        assert!(matches!(
+            err,
+            ObjectClientError::ClientError(LocalFsClientError::InvalidKey(_))
+        ));
+        let err = client
+            .head_object("test_bucket", key)
+            .await
+            .expect_err("key is invalid");
+        assert!(matches!(
+            err,
+            ObjectClientError::ServiceError(HeadObjectError::NotFound)
+        ));
+    }
+
+    #[test_case("", "/", 2; "root delimited")]
+    #[test_case("", "", 2; "root undelimited")]
+    #[test_case("dir", "/", 1; "partial prefix delimited")]
+    #[test_case("dir/", "/", 3; "directory delimited")]
+    #[test_case("dir/", "", 1; "directory undelimited")]
+    #[test_case("dir/s", "-", 1; "other delimiter")]
+    #[test_case("", "-", 2; "root other delimiter")]
+    #[tokio::test]
+    async fn list_objects(prefix: &str, delimiter: &str, page_size: usize) {
+        let (dir, client) = new_client(1024);
+
+        let keys = [
+            "a",
+            "dir/a",
+            "dir/sub/b",
+            "dir/sub/c",
+            "dir/sub-dir/d",
+            "dir/sub.txt",
+            "dir2/e",
+            "z",
+        ];
+        for key in keys {
+            client
+                .put_object_single("test_bucket", key, &Default::default(), key)
+                .await
+                .unwrap();
+        }
+        // Empty directories aren't visible
+        fs::create_dir_all(dir.path().join("dir/empty/nested")).unwrap();
+
+        // Work out the expected listing the slow way
+        let mut expected_objects = Vec::new();
+        let mut expected_prefixes = Vec::new();
+        for key in keys.iter().filter(|key| key.starts_with(prefix)) {
+            match (!delimiter.is_empty())
+                .then(|| key[prefix.len()..].split_once(delimiter))
+                .flatten()
+            {
+                Some((pre, _)) => {
+                    let common_prefix = format!("{prefix}{pre}{delimiter}");
+                    if !expected_prefixes.contains(&common_prefix) {
+                        expected_prefixes.push(common_prefix);
+                    }
+                }
+                None => expected_objects.push(key.to_string()),
+            }
+        }
+        expected_objects.sort();
+        expected_prefixes.sort();
+
+        let mut objects = Vec::new();
+        let mut prefixes = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let result = client
+                .list_objects(
+                    "test_bucket",
+                    continuation_token.as_deref(),
+                    delimiter,
+                    page_size,
+                    prefix,
+                )
+                .await
+                .unwrap();
+            assert!(result.objects.len() + result.common_prefixes.len() <= page_size);
+            objects.extend(result.objects.into_iter().map(|object| object.key));
+            prefixes.extend(result.common_prefixes);
+            continuation_token = result.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        assert_eq!(objects, expected_objects);
+        assert_eq!(prefixes, expected_prefixes);
+
+        // Starting after a key skips the entries up to it
+        let start_after = format!("{prefix}sub");
+        let result = client
+            .list_objects_after("test_bucket", &start_after, None, delimiter, 1000, prefix)
+            .await
+            .unwrap();
+        let objects_after: Vec<_> = result.objects.into_iter().map(|object| object.key).collect();
+        expected_objects.retain(|key| *key > start_after);
+        expected_prefixes.retain(|common_prefix| *common_prefix > start_after);
+        assert_eq!(objects_after, expected_objects);
+        assert_eq!(result.common_prefixes, expected_prefixes);
+    }
+
+    #[tokio::test]
+    async fn review_parts_and_abort() {
+        let (dir, client) = new_client(4);
+
+        let params = PutObjectParams::new().trailing_checksums(PutObjectTrailingChecksums::Enabled);
+        let mut request = client.put_object("test_bucket", "key", &params).await.unwrap();
+        request.write(b"0123456789").await.unwrap();
+        let err = request
+            .review_and_complete(|review| {
+                let sizes: Vec<_> = review.parts.iter().map(|part| part.size).collect();
+                assert_eq!(sizes, vec![4, 4, 2]);
+                assert!(review.parts.iter().all(|part| part.checksum.is_some()));
+                false
+            })
+            .await
+            .expect_err("review failed");
+        assert!(matches!(
+            err,
+            ObjectClientError::ClientError(LocalFsClientError::ReviewFailed)
+        ));
+
+        // The aborted upload leaves nothing behind
+        assert!(!dir.path().join("key").exists());
+        let uploads = dir.path().join(RESERVED_DIR).join(UPLOADS_DIR);
+        assert_eq!(fs::read_dir(uploads).unwrap().count(), 0);
+    }
+}
@@ -13,8 +13,6 @@ use std::time::{Duration, SystemTime};
 use async_trait::async_trait;
 use futures::{Stream, StreamExt};
 use lazy_static::lazy_static;
-use mountpoint_s3_crt::checksums::crc32c;
-use mountpoint_s3_crt::s3::client::BufferPoolUsageStats;
 use rand::seq::SliceRandom;
 use rand::SeedableRng;
 use rand_chacha::ChaCha20Rng;
@@ -22,17 +20,17 @@ use thiserror::Error;
 use time::OffsetDateTime;
 use tracing::trace;
 
-use crate::checksums::crc32c_to_base64;
+use crate::checksums::{crc32c, crc32c_to_base64};
 use crate::error_metadata::{ClientErrorMetadata, ProvideErrorMetadata};
 use crate::object_client::{
-    Checksum, ChecksumAlgorithm, DeleteObjectError, DeleteObjectResult, ETag, GetBodyPart, GetObjectAttributesError,
-    GetObjectAttributesParts, GetObjectAttributesResult, GetObjectError, GetObjectRequest, HeadObjectError,
-    HeadObjectResult, ListObjectsError, ListObjectsResult, ObjectAttribute, ObjectClient, ObjectClientError,
-    ObjectClientResult, ObjectInfo, ObjectPart, PutObjectError, PutObjectParams, PutObjectRequest, PutObjectResult,
-    PutObjectSingleParams, PutObjectTrailingChecksums, RestoreStatus, UploadReview, UploadReviewPart,
+    BufferPoolUsageStats, Checksum, ChecksumAlgorithm, DeleteObjectError, DeleteObjectResult, ETag, GetBodyPart,
+    GetObjectAttributesError, GetObjectAttributesParts, GetObjectAttributesResult, GetObjectError, GetObjectRequest,
+    HeadObjectError, HeadObjectResult, ListObjectsError, ListObjectsResult, ObjectAttribute, ObjectClient,
+    ObjectClientError, ObjectClientResult, ObjectInfo, ObjectPart, PutObjectError, PutObjectParams, PutObjectRequest,
+    PutObjectResult, PutObjectSingleParams, PutObjectTrailingChecksums, RestoreStatus, UploadReview, UploadReviewPart,
 };
 
-mod leaky_bucket;
+pub(crate) mod leaky_bucket;
 pub mod throughput_client;
 
 pub const RAMP_MODULUS: usize = 251; // Largest prime under 256
@@ -175,6 +173,7 @@ impl MockClient {
     /// Ordered list implementation
     fn list_objects_ordered(
         &self,
+        start_after: Option<&str>,
         continuation_token: Option<&str>,
         delimiter: &str,
         max_keys: usize,
@@ -212,8 +211,8 @@ impl MockClient {
                 break;
             }
 
-            // Skip keys that do not start with the specified prefix
-            if !key.starts_with(prefix) {
+            // Skip keys that do not start with the specified prefix, or not after `start_after`
+            if !key.starts_with(prefix) || start_after.is_some_and(|start_after| key.as_str() <= start_after) {
                 continue;
             }
 
@@ -270,6 +269,7 @@ impl MockClient {
 
     fn list_objects_unordered(
         &self,
+        start_after: Option<&str>,
         continuation_token: Option<&str>,
         delimiter: &str,
         max_keys: usize,
@@ -287,7 +287,11 @@ impl MockClient {
 
         // Shuffle the keys now before we construct an iterator over them. This won't be stable in
         // the presence of mutation, but that's the expected behavior anyway.
-        let mut object_keys: Vec<_> = objects.keys().filter(|key| key.starts_with(prefix)).collect();
+        let mut object_keys: Vec<_> = objects
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .filter(|key| start_after.is_none_or(|start_after| key.as_str() > start_after))
+            .collect();
         object_keys.shuffle(&mut ChaCha20Rng::seed_from_u64(seed));
 
         // Continuation tokens for unordered list will just be the index in the shuffled list. This
@@ -694,9 +698,40 @@ impl ObjectClient for MockClient {
         }
 
         if let Some(seed) = self.config.unordered_list_seed {
-            Ok(self.list_objects_unordered(continuation_token, delimiter, max_keys, prefix, seed))
+            Ok(self.list_objects_unordered(None, continuation_token, delimiter, max_keys, prefix, seed))
+        } else {
+            Ok(self.list_objects_ordered(None, continuation_token, delimiter, max_keys, prefix))
+        }
+    }
+
+    async fn list_objects_after(
+        &self,
+        bucket: &str,
+        start_after: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        trace!(
+            bucket,
+            start_after,
+            ?continuation_token,
+            delimiter,
+            max_keys,
+            prefix,
+            "ListObjects"
+        );
+        self.inc_op_count(Operation::ListObjectsV2);
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket));
+        }
+
+        if let Some(seed) = self.config.unordered_list_seed {
+            Ok(self.list_objects_unordered(Some(start_after), continuation_token, delimiter, max_keys, prefix, seed))
         } else {
-            Ok(self.list_objects_ordered(continuation_token, delimiter, max_keys, prefix))
+            Ok(self.list_objects_ordered(Some(start_after), continuation_token, delimiter, max_keys, prefix))
         }
     }
 
@@ -769,7 +804,7 @@ impl ObjectClient for MockClient {
             let mut result = GetObjectAttributesResult::default();
             for attribute in object_attributes.iter() {
                 match attribute {
-                    ObjectAttribute::ETag => result.etag = Some("TODO".to_owned()),
+                    ObjectAttribute::ETag => result.etag = Some(object.etag.as_str().to_owned()),
                     ObjectAttribute::Checksum => {
                         result.checksum = Some(Checksum {
                             checksum_crc32: Some("TODO".to_owned()),
@@ -1249,6 +1284,50 @@ mod tests {
         check_continuation!("/", 2, "dirs/dir2/", &keys[7..9], &[]);
     }
 
+    #[tokio::test]
+    async fn list_objects_after() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            unordered_list_seed: None,
+            ..Default::default()
+        });
+
+        for key in ["dir/a", "dir/b", "dir/b/c", "dir/c", "dir/d"] {
+            client.add_object(key, MockObject::constant(0u8, 5, ETag::for_tests()));
+        }
+
+        let result = client
+            .list_objects_after("test_bucket", "dir/a", None, "/", 1000, "dir/")
+            .await
+            .expect("should not fail");
+        let keys: Vec<_> = result.objects.into_iter().map(|object| object.key).collect();
+        assert_eq!(keys, ["dir/b", "dir/c", "dir/d"]);
+        assert_eq!(result.common_prefixes, ["dir/b/"]);
+
+        let result = client
+            .list_objects_after("test_bucket", "dir/b", None, "/", 1, "dir/")
+            .await
+            .expect("should not fail");
+        assert!(result.objects.is_empty());
+        assert_eq!(result.common_prefixes, ["dir/b/"]);
+
+        let result = client
+            .list_objects_after(
+                "test_bucket",
+                "dir/b",
+                result.next_continuation_token.as_deref(),
+                "/",
+                1000,
+                "dir/",
+            )
+            .await
+            .expect("should not fail");
+        let keys: Vec<_> = result.objects.into_iter().map(|object| object.key).collect();
+        assert_eq!(keys, ["dir/c", "dir/d"]);
+        assert!(result.next_continuation_token.is_none());
+    }
+
     #[tokio::test]
     async fn list_objects_unicode() {
         let client = MockClient::new(MockClientConfig {
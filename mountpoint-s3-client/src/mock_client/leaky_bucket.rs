@@ -68,6 +68,13 @@ impl LeakyBucketInner {
         }
     }
 
+    fn try_acquire(&self, amount: u32) -> bool {
+        let Some(mut locked) = self.locked.try_lock() else {
+            return false;
+        };
+        self.try_acquire_locked(amount, &mut locked).is_ok()
+    }
+
     fn try_acquire_locked(
         &self,
         amount: u32,
@@ -122,6 +129,14 @@ impl LeakyBucket {
     pub async fn acquire(&self, amount: u32) {
         self.inner.acquire(amount).await;
     }
+
+    /// Acquire the given `amount` of tokens if they're available right now, without waiting for
+    /// the bucket to refill. Returns whether the tokens were acquired, which they aren't if another
+    /// task is waiting to acquire tokens.
+    #[inline]
+    pub fn try_acquire(&self, amount: u32) -> bool {
+        self.inner.try_acquire(amount)
+    }
 }
 
 /// Builder for a leaky bucket.
@@ -232,4 +247,18 @@ mod tests {
         assert_eq!(3, wakeups);
         assert!(duration.expect("expected measured duration") > INTERVAL * 2);
     }
+
+    #[test]
+    fn test_leaky_bucket_try_acquire() {
+        let leaky = Builder::new()
+            .tokens(2)
+            .max(2)
+            .refill_amount(1)
+            .refill_interval(Duration::from_secs(3600))
+            .build();
+
+        assert!(leaky.try_acquire(1));
+        assert!(leaky.try_acquire(1));
+        assert!(!leaky.try_acquire(1));
+    }
 }
@@ -0,0 +1,258 @@
+//! Verification of the SigV4 signatures on requests to the mock server.
+//!
+//! Signatures are checked against the server's static credentials. Any region is accepted, and the
+//! request timestamp isn't checked for clock skew. Presigned requests (with the signature in the
+//! query string) aren't supported.
+
+use hyper::header::{HeaderMap, AUTHORIZATION};
+use hyper::{Method, StatusCode, Uri};
+use percent_encoding::percent_decode_str;
+
+use super::{AwsChunkedBody, S3Error};
+use crate::sigv4::{self, Credentials};
+
+/// How the payload of a request is signed, from its `x-amz-content-sha256` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PayloadSigning {
+    /// The payload isn't signed
+    Unsigned,
+    /// The hex-encoded SHA-256 hash of the payload is signed
+    Hash(String),
+    /// The payload is `aws-chunked` encoded and each chunk is signed, as are the trailing headers
+    /// if `trailer` is set
+    SignedChunks { trailer: bool },
+    /// The payload is `aws-chunked` encoded, with unsigned chunks and trailing headers
+    UnsignedChunks,
+}
+
+impl PayloadSigning {
+    fn from_header(value: &str) -> Result<Self, S3Error> {
+        let signing = match value {
+            sigv4::UNSIGNED_PAYLOAD => Self::Unsigned,
+            "STREAMING-AWS4-HMAC-SHA256-PAYLOAD" => Self::SignedChunks { trailer: false },
+            "STREAMING-AWS4-HMAC-SHA256-PAYLOAD-TRAILER" => Self::SignedChunks { trailer: true },
+            "STREAMING-UNSIGNED-PAYLOAD-TRAILER" => Self::UnsignedChunks,
+            hash if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                Self::Hash(hash.to_ascii_lowercase())
+            }
+            _ => {
+                return Err(S3Error::invalid_argument(
+                    "x-amz-content-sha256 must be UNSIGNED-PAYLOAD, a streaming payload type, or a SHA-256 hash",
+                ))
+            }
+        };
+        Ok(signing)
+    }
+}
+
+/// The signing details of a request whose headers have a valid signature, used to check its payload
+#[derive(Debug, Clone)]
+pub(super) struct VerifiedSignature {
+    amz_date: String,
+    scope: String,
+    signing_key: Vec<u8>,
+    /// The signature of the request headers, which the first chunk signature is chained to
+    seed_signature: String,
+    payload: PayloadSigning,
+}
+
+impl VerifiedSignature {
+    /// Check the payload of a request that isn't `aws-chunked` encoded
+    pub(super) fn verify_payload(&self, body: &[u8]) -> Result<(), S3Error> {
+        match &self.payload {
+            PayloadSigning::Unsigned => Ok(()),
+            PayloadSigning::Hash(hash) if *hash == sigv4::payload_hash(body) => Ok(()),
+            PayloadSigning::Hash(_) => Err(S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "XAmzContentSHA256Mismatch",
+                "The provided 'x-amz-content-sha256' header does not match what was computed.",
+            )),
+            PayloadSigning::SignedChunks { .. } | PayloadSigning::UnsignedChunks => Err(S3Error::invalid_argument(
+                "streaming payloads must use aws-chunked encoding",
+            )),
+        }
+    }
+
+    /// Check the chunk and trailer signatures of an `aws-chunked` body
+    pub(super) fn verify_chunks(&self, body: &AwsChunkedBody) -> Result<(), S3Error> {
+        let trailer = match &self.payload {
+            PayloadSigning::SignedChunks { trailer } => *trailer,
+            PayloadSigning::UnsignedChunks => return Ok(()),
+            PayloadSigning::Unsigned | PayloadSigning::Hash(_) => {
+                return Err(S3Error::invalid_argument(
+                    "aws-chunked bodies must have a streaming x-amz-content-sha256",
+                ))
+            }
+        };
+
+        let mut previous_signature = self.seed_signature.clone();
+        for (chunk, chunk_signature) in body.chunks() {
+            let string_to_sign = sigv4::chunk_string_to_sign(&self.amz_date, &self.scope, &previous_signature, chunk);
+            let expected = sigv4::signature(&self.signing_key, &string_to_sign);
+            if chunk_signature != Some(expected.as_str()) {
+                return Err(signature_does_not_match());
+            }
+            previous_signature = expected;
+        }
+
+        if trailer {
+            let string_to_sign =
+                sigv4::trailer_string_to_sign(&self.amz_date, &self.scope, &previous_signature, &body.trailers);
+            let expected = sigv4::signature(&self.signing_key, &string_to_sign);
+            if body.trailer_signature.as_deref() != Some(expected.as_str()) {
+                return Err(signature_does_not_match());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn signature_does_not_match() -> S3Error {
+    S3Error::new(
+        StatusCode::FORBIDDEN,
+        "SignatureDoesNotMatch",
+        "The request signature we calculated does not match the signature you provided.",
+    )
+}
+
+fn access_denied(message: &str) -> S3Error {
+    S3Error::new(StatusCode::FORBIDDEN, "AccessDenied", message)
+}
+
+/// The parts of an `Authorization` header
+struct Authorization<'a> {
+    access_key_id: &'a str,
+    date: &'a str,
+    region: &'a str,
+    signed_headers: Vec<&'a str>,
+    signature: &'a str,
+}
+
+impl<'a> Authorization<'a> {
+    fn parse(value: &'a str) -> Option<Self> {
+        let fields = value.strip_prefix(sigv4::ALGORITHM)?.strip_prefix(' ')?;
+        let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+        for field in fields.split(',') {
+            match field.trim().split_once('=')? {
+                ("Credential", value) => credential = Some(value),
+                ("SignedHeaders", value) => signed_headers = Some(value),
+                ("Signature", value) => signature = Some(value),
+                _ => return None,
+            }
+        }
+
+        let mut scope = credential?.split('/');
+        let (access_key_id, date, region) = (scope.next()?, scope.next()?, scope.next()?);
+        if (scope.next()?, scope.next()?, scope.next()) != ("s3", "aws4_request", None) {
+            return None;
+        }
+        Some(Self {
+            access_key_id,
+            date,
+            region,
+            signed_headers: signed_headers?.split(';').collect(),
+            signature: signature?,
+        })
+    }
+}
+
+/// Check the signature of a request's headers against the given credentials. Returns what's needed
+/// to check the signature of its payload, which hasn't been read yet.
+pub(super) fn verify_request(
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    credentials: &Credentials,
+) -> Result<VerifiedSignature, S3Error> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let authorization = header(AUTHORIZATION.as_str()).ok_or_else(|| access_denied("Access Denied"))?;
+    let authorization = Authorization::parse(authorization).ok_or_else(|| {
+        S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "AuthorizationHeaderMalformed",
+            "The authorization header is malformed.",
+        )
+    })?;
+    if authorization.access_key_id != credentials.access_key_id() {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "InvalidAccessKeyId",
+            "The AWS Access Key Id you provided does not exist in our records.",
+        ));
+    }
+    if header("x-amz-security-token") != credentials.session_token() {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "InvalidToken",
+            "The provided token is malformed or otherwise invalid.",
+        ));
+    }
+    let amz_date = header("x-amz-date")
+        .filter(|amz_date| amz_date.get(..8) == Some(authorization.date))
+        .ok_or_else(|| access_denied("AWS authentication requires a valid Date or x-amz-date header"))?;
+    let payload_hash = header("x-amz-content-sha256").ok_or_else(|| {
+        S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Missing required header for this request: x-amz-content-sha256",
+        )
+    })?;
+    let payload = PayloadSigning::from_header(payload_hash)?;
+
+    let mut signed_headers = authorization.signed_headers.clone();
+    signed_headers.sort_unstable();
+    if !signed_headers.contains(&"host") || signed_headers != authorization.signed_headers {
+        return Err(signature_does_not_match());
+    }
+
+    let canonical_request = sigv4::canonical_request(
+        method,
+        &canonical_uri(uri),
+        headers,
+        &authorization.signed_headers,
+        payload_hash,
+    );
+    let scope = sigv4::scope(authorization.date, authorization.region);
+    let signing_key = sigv4::signing_key(
+        credentials.secret_access_key(),
+        authorization.date,
+        authorization.region,
+    );
+    let expected = sigv4::signature(
+        &signing_key,
+        &sigv4::string_to_sign(amz_date, &scope, &canonical_request),
+    );
+    if authorization.signature != expected {
+        return Err(signature_does_not_match());
+    }
+
+    Ok(VerifiedSignature {
+        amz_date: amz_date.to_owned(),
+        scope,
+        signing_key,
+        seed_signature: expected,
+        payload,
+    })
+}
+
+/// The request URI with its query string re-encoded the way SigV4 expects, since clients may
+/// encode query parameters differently when sending them. The path is signed as sent.
+fn canonical_uri(uri: &Uri) -> Uri {
+    let Some(query) = uri.query() else {
+        return uri.clone();
+    };
+    let encode = |s: &str| sigv4::encode_query_component(&percent_decode_str(s).decode_utf8_lossy());
+    let query = query
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .map(|param| {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            format!("{}={}", encode(name), encode(value))
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{query}", uri.path())
+        .parse()
+        .unwrap_or_else(|_| uri.clone())
+}
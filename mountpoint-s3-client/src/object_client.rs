@@ -6,7 +6,6 @@ use std::time::SystemTime;
 use async_trait::async_trait;
 use auto_impl::auto_impl;
 use futures::Stream;
-use mountpoint_s3_crt::s3::client::BufferPoolUsageStats;
 use std::collections::HashMap;
 use thiserror::Error;
 use time::OffsetDateTime;
@@ -14,7 +13,9 @@ use time::OffsetDateTime;
 use crate::checksums;
 use crate::error_metadata::{ClientErrorMetadata, ProvideErrorMetadata};
 
+mod crt_types;
 mod etag;
+pub use crt_types::{BufferPoolUsageStats, ChecksumAlgorithm, UploadReview, UploadReviewPart};
 pub use etag::ETag;
 
 /// A generic interface to S3-like object storage services.
@@ -76,6 +77,30 @@ pub trait ObjectClient {
         prefix: &str,
     ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError>;
 
+    /// List the objects in a bucket under a given prefix, starting after the given key. Objects and
+    /// common prefixes that do not sort after `start_after` are not returned.
+    ///
+    /// The default implementation fails with [ListObjectsError::StartAfterNotSupported], for
+    /// object stores that cannot start a listing at a given key. Callers should then list the
+    /// prefix from the start with [list_objects](Self::list_objects) instead.
+    async fn list_objects_after(
+        &self,
+        bucket: &str,
+        start_after: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError>
+    where
+        Self: Sync,
+    {
+        let _ = (bucket, start_after, continuation_token, delimiter, max_keys, prefix);
+        Err(ObjectClientError::ServiceError(
+            ListObjectsError::StartAfterNotSupported,
+        ))
+    }
+
     /// Retrieve object metadata without retrieving the object contents
     async fn head_object(
         &self,
@@ -188,6 +213,9 @@ pub struct ListObjectsResult {
 pub enum ListObjectsError {
     #[error("The bucket does not exist")]
     NoSuchBucket,
+
+    #[error("The object client does not support starting a listing after a given key")]
+    StartAfterNotSupported,
 }
 
 /// Result of a [`head_object`](ObjectClient::head_object) request
@@ -330,15 +358,6 @@ pub enum PutObjectTrailingChecksums {
     Disabled,
 }
 
-/// Info for the caller to review before an upload completes.
-pub type UploadReview = mountpoint_s3_crt::s3::client::UploadReview;
-
-/// Info about a single part, for the caller to review before the upload completes.
-pub type UploadReviewPart = mountpoint_s3_crt::s3::client::UploadReviewPart;
-
-/// A checksum algorithm used by the object client for integrity checks on uploads and downloads.
-pub type ChecksumAlgorithm = mountpoint_s3_crt::s3::client::ChecksumAlgorithm;
-
 /// Parameters to a [`put_object_single`](ObjectClient::put_object_single) request
 #[derive(Debug, Default, Clone)]
 #[non_exhaustive]
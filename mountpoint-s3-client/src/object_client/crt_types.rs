@@ -0,0 +1,76 @@
+//! Types that the [ObjectClient](super::ObjectClient) interface shares with the AWS Common Runtime.
+//!
+//! With the `crt` feature these are the CRT's own types. Without it, they are plain Rust types of the same
+//! shape, so that clients that don't link the CRT can still implement the interface.
+
+#[cfg(feature = "crt")]
+pub use mountpoint_s3_crt::s3::client::{BufferPoolUsageStats, ChecksumAlgorithm, UploadReview, UploadReviewPart};
+
+/// S3 buffer pool usage stats
+#[cfg(not(feature = "crt"))]
+#[derive(Debug)]
+pub struct BufferPoolUsageStats {
+    /// Effective Max memory limit. Memory limit value provided during construction minus
+    /// buffer reserved for overhead of the pool
+    pub mem_limit: u64,
+
+    /// Max size of buffer to be allocated from primary.
+    pub primary_cutoff: u64,
+
+    /// Primary mem used, including memory used by blocks
+    /// that are waiting on all allocs to release before being put back in circulation.
+    pub primary_used: u64,
+
+    /// Overall memory allocated for blocks.
+    pub primary_allocated: u64,
+
+    /// Reserved memory for primary storage.
+    pub primary_reserved: u64,
+
+    /// Number of blocks allocated in primary.
+    pub primary_num_blocks: u64,
+
+    /// Secondary mem used. Accurate, maps directly to base allocator.
+    pub secondary_reserved: u64,
+
+    /// Secondary mem reserved. Accurate, maps directly to base allocator.
+    pub secondary_used: u64,
+
+    /// Bytes used in "forced" buffers (created even if they exceed memory limits).
+    pub forced_used: u64,
+}
+
+/// Checksum algorithm.
+#[cfg(not(feature = "crt"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChecksumAlgorithm {
+    /// Crc32c checksum.
+    Crc32c,
+    /// Crc32 checksum.
+    Crc32,
+    /// Sha1 checksum.
+    Sha1,
+    /// Sha256 checksum.
+    Sha256,
+}
+
+/// Info for the caller to review before an upload completes.
+#[cfg(not(feature = "crt"))]
+#[derive(Debug)]
+pub struct UploadReview {
+    /// Info about each part uploaded.
+    pub parts: Vec<UploadReviewPart>,
+    /// The checksum algorithm used.
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
+}
+
+/// Info about a single part, for the caller to review before the upload completes.
+#[cfg(not(feature = "crt"))]
+#[derive(Debug)]
+pub struct UploadReviewPart {
+    /// Size in bytes of this part.
+    pub size: u64,
+
+    /// Checksum string (usually base64-encoded), if computed.
+    pub checksum: Option<String>,
+}
@@ -41,6 +41,7 @@ use crate::endpoint_config::EndpointError;
 use crate::endpoint_config::{self, EndpointConfig};
 use crate::error_metadata::{ClientErrorMetadata, ProvideErrorMetadata};
 use crate::object_client::*;
+use crate::s3_response::emit_throughput_metric;
 use crate::user_agent::UserAgent;
 
 macro_rules! request_span {
@@ -1205,22 +1206,6 @@ fn try_parse_generic_error(request_result: &MetaRequestResult) -> Option<S3Reque
     }
 }
 
-/// Record a throughput metric for GET/PUT. We can't inline this into S3CrtClient callbacks because
-/// PUT bytes don't transit those callbacks.
-fn emit_throughput_metric(bytes: u64, duration: Duration, op: &'static str) {
-    let throughput_mbps = bytes as f64 / 1024.0 / 1024.0 / duration.as_secs_f64();
-    // Semi-arbitrary choices here to avoid averaging out large and small requests
-    const MEGABYTE: u64 = 1024 * 1024;
-    let bucket = if bytes < MEGABYTE {
-        "<1MiB"
-    } else if bytes <= 16 * MEGABYTE {
-        "1-16MiB"
-    } else {
-        ">16MiB"
-    };
-    metrics::histogram!("s3.meta_requests.throughput_mibs", "op" => op, "size" => bucket).record(throughput_mbps);
-}
-
 #[cfg_attr(not(docsrs), async_trait)]
 impl ObjectClient for S3CrtClient {
     type GetObjectRequest = S3GetObjectRequest;
@@ -1282,10 +1267,30 @@ impl ObjectClient for S3CrtClient {
         max_keys: usize,
         prefix: &str,
     ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
-        self.list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
+        self.list_objects(bucket, None, continuation_token, delimiter, max_keys, prefix)
             .await
     }
 
+    async fn list_objects_after(
+        &self,
+        bucket: &str,
+        start_after: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.list_objects(
+            bucket,
+            Some(start_after),
+            continuation_token,
+            delimiter,
+            max_keys,
+            prefix,
+        )
+        .await
+    }
+
     async fn head_object(
         &self,
         bucket: &str,
@@ -2,19 +2,17 @@ use std::ffi::OsString;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
-use lazy_static::lazy_static;
 use mountpoint_s3_crt::http::request_response::{Headers, HeadersError};
 use mountpoint_s3_crt::s3::client::MetaRequestResult;
-use regex::Regex;
 use thiserror::Error;
 use time::format_description::well_known::Rfc2822;
 use time::OffsetDateTime;
-use tracing::error;
 
 use crate::object_client::{
     HeadObjectError, HeadObjectResult, ObjectClientError, ObjectClientResult, ObjectInfo, RestoreStatus,
 };
 use crate::s3_crt_client::{S3CrtClient, S3Operation, S3RequestError};
+use crate::s3_response::restore::{self, parse_restore_header};
 
 #[derive(Error, Debug)]
 #[non_exhaustive]
@@ -31,8 +29,8 @@ pub enum ParseError {
     #[error("Failed to parse field {1} as an int: {0:?}")]
     Int(#[source] std::num::ParseIntError, String),
 
-    #[error("Header x-amz-restore is invalid: {0:?}")]
-    InvalidRestore(String),
+    #[error("Header x-amz-restore is invalid: {0}")]
+    Restore(#[from] restore::ParseError),
 }
 
 fn get_field(headers: &Headers, name: &str) -> Result<String, ParseError> {
@@ -53,36 +51,12 @@ fn get_optional_field(headers: &Headers, name: &str) -> Result<Option<String>, P
     })
 }
 
-lazy_static! {
-    // Example: ongoing-request="true"
-    static ref RESTORE_IN_PROGRESS_RE: Regex = Regex::new(r#"^ongoing-request="(?<ongoing>[^"]*)"$"#).unwrap();
-
-    // Example: ongoing-request="false", expiry-date="Fri, 21 Dec 2012 00:00:00 GMT"
-    static ref RESTORE_DONE_RE: Regex =
-        Regex::new(r#"^ongoing-request="[^"]*",\s*expiry-date="(?<expiry>[^"]*)"$"#).unwrap();
-}
-
 impl HeadObjectResult {
     fn parse_restore_status(headers: &Headers) -> Result<Option<RestoreStatus>, ParseError> {
         let Some(header) = get_optional_field(headers, "x-amz-restore")? else {
             return Ok(None);
         };
-
-        if let Some(caps) = RESTORE_IN_PROGRESS_RE.captures(&header) {
-            let ongoing = bool::from_str(&caps["ongoing"]).map_err(|_| ParseError::InvalidRestore(header.clone()))?;
-            return if ongoing {
-                Ok(Some(RestoreStatus::InProgress))
-            } else {
-                Err(ParseError::InvalidRestore(header.clone()))
-            };
-        };
-
-        let Some(caps) = RESTORE_DONE_RE.captures(&header) else {
-            return Err(ParseError::InvalidRestore(header));
-        };
-        let expiry = OffsetDateTime::parse(&caps["expiry"], &Rfc2822)
-            .map_err(|e| ParseError::OffsetDateTime(e, "x-amz-restore::expiry".into()))?;
-        Ok(Some(RestoreStatus::Restored { expiry: expiry.into() }))
+        Ok(Some(parse_restore_header(header)?))
     }
 
     fn parse_from_hdr(bucket: String, key: String, headers: &Headers) -> Result<Self, ParseError> {
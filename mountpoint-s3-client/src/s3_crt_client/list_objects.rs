@@ -1,153 +1,18 @@
 use std::ops::Deref;
 use std::os::unix::prelude::OsStrExt;
-use std::str::FromStr;
 
 use mountpoint_s3_crt::http::request_response::Header;
 use mountpoint_s3_crt::s3::client::MetaRequestResult;
-use thiserror::Error;
-use time::format_description::well_known::Rfc3339;
-use time::OffsetDateTime;
-use tracing::error;
 
-use crate::object_client::{
-    ListObjectsError, ListObjectsResult, ObjectClientError, ObjectClientResult, ObjectInfo, RestoreStatus,
-};
+use crate::object_client::{ListObjectsError, ListObjectsResult, ObjectClientError, ObjectClientResult};
 use crate::s3_crt_client::{S3CrtClient, S3Operation, S3RequestError};
-
-#[derive(Error, Debug)]
-#[non_exhaustive]
-pub enum ParseError {
-    #[error("XML response was not valid: problem = {1}, xml node = {0:?}")]
-    InvalidResponse(xmltree::Element, String),
-
-    #[error("XML parsing error: {0:?}")]
-    Xml(#[from] xmltree::ParseError),
-
-    #[error("Missing field {1} from XML element {0:?}")]
-    MissingField(xmltree::Element, String),
-
-    #[error("Failed to parse field {1} as bool: {0:?}")]
-    Bool(#[source] std::str::ParseBoolError, String),
-
-    #[error("Failed to parse field {1} as int: {0:?}")]
-    Int(#[source] std::num::ParseIntError, String),
-
-    #[error("Failed to parse field {1} as OffsetDateTime: {0:?}")]
-    OffsetDateTime(#[source] time::error::Parse, String),
-}
-
-/// Copy text out of an XML element, with the right error type.
-fn get_text(element: &xmltree::Element) -> Result<String, ParseError> {
-    Ok(element
-        .get_text()
-        .ok_or_else(|| ParseError::InvalidResponse(element.clone(), "field has no text".to_string()))?
-        .to_string())
-}
-
-/// Wrapper to get child with some name out of an XML element, with the right error type.
-fn get_child<'a>(element: &'a xmltree::Element, name: &str) -> Result<&'a xmltree::Element, ParseError> {
-    element
-        .get_child(name)
-        .ok_or_else(|| ParseError::MissingField(element.clone(), name.to_string()))
-}
-
-/// Get the text out of a child node, with the right error type.
-fn get_field(element: &xmltree::Element, name: &str) -> Result<String, ParseError> {
-    get_text(get_child(element, name)?)
-}
-
-fn parse_result_from_bytes(bytes: &[u8]) -> Result<ListObjectsResult, ParseError> {
-    parse_result_from_xml(&mut xmltree::Element::parse(bytes)?)
-}
-
-fn parse_result_from_xml(element: &mut xmltree::Element) -> Result<ListObjectsResult, ParseError> {
-    let mut objects = Vec::new();
-
-    while let Some(content) = element.take_child("Contents") {
-        objects.push(parse_object_info_from_xml(&content)?);
-    }
-
-    let mut common_prefixes = Vec::new();
-
-    while let Some(common_prefix) = element.take_child("CommonPrefixes") {
-        let prefix = get_field(&common_prefix, "Prefix")?;
-        common_prefixes.push(prefix);
-    }
-
-    let mut next_continuation_token = None;
-    if let Some(elem) = element.get_child("NextContinuationToken") {
-        next_continuation_token = Some(get_text(elem)?);
-    }
-
-    let is_truncated = get_field(element, "IsTruncated")?;
-    let is_truncated = bool::from_str(&is_truncated).map_err(|e| ParseError::Bool(e, "IsTruncated".to_string()))?;
-
-    if is_truncated != next_continuation_token.is_some() {
-        return Err(ParseError::InvalidResponse(
-            element.clone(),
-            "IsTruncated doesn't match NextContinuationToken".to_string(),
-        ));
-    }
-
-    Ok(ListObjectsResult {
-        objects,
-        common_prefixes,
-        next_continuation_token,
-    })
-}
-
-fn parse_restore_status(element: &xmltree::Element) -> Result<Option<RestoreStatus>, ParseError> {
-    let Some(restore_status) = element.get_child("RestoreStatus") else {
-        return Ok(None);
-    };
-
-    let restore_in_progress = bool::from_str(&get_field(restore_status, "IsRestoreInProgress")?)
-        .map_err(|e| ParseError::Bool(e, "IsRestoreInProgress".to_string()))?;
-    if restore_in_progress {
-        return Ok(Some(RestoreStatus::InProgress));
-    }
-
-    Ok(Some(RestoreStatus::Restored {
-        expiry: OffsetDateTime::parse(&get_field(restore_status, "RestoreExpiryDate")?, &Rfc3339)
-            .map_err(|e| ParseError::OffsetDateTime(e, "RestoreExpiryDate".to_string()))?
-            .into(),
-    }))
-}
-
-fn parse_object_info_from_xml(element: &xmltree::Element) -> Result<ObjectInfo, ParseError> {
-    let key = get_field(element, "Key")?;
-
-    let size = get_field(element, "Size")?;
-
-    let size = u64::from_str(&size).map_err(|e| ParseError::Int(e, "Size".to_string()))?;
-
-    let last_modified = get_field(element, "LastModified")?;
-
-    // S3 appears to use RFC 3339 to encode this field, based on the API example here:
-    // https://docs.aws.amazon.com/AmazonS3/latest/API/API_ListObjectsV2.html
-    let last_modified = OffsetDateTime::parse(&last_modified, &Rfc3339)
-        .map_err(|e| ParseError::OffsetDateTime(e, "LastModified".to_string()))?;
-
-    let storage_class = get_field(element, "StorageClass").ok();
-
-    let restore_status = parse_restore_status(element)?;
-
-    let etag = get_field(element, "ETag")?;
-
-    Ok(ObjectInfo {
-        key,
-        size,
-        last_modified,
-        storage_class,
-        restore_status,
-        etag,
-    })
-}
+use crate::s3_response::list_objects::parse_result_from_bytes;
 
 impl S3CrtClient {
     pub(super) async fn list_objects(
         &self,
         bucket: &str,
+        start_after: Option<&str>,
         continuation_token: Option<&str>,
         delimiter: &str,
         max_keys: usize,
@@ -172,6 +37,9 @@ impl S3CrtClient {
             if let Some(continuation_token) = continuation_token {
                 query.push(("continuation-token", continuation_token));
             }
+            if let Some(start_after) = start_after {
+                query.push(("start-after", start_after));
+            }
 
             message
                 .set_request_path_and_query("/", query)
@@ -182,6 +50,7 @@ impl S3CrtClient {
                 "list_objects",
                 bucket,
                 continued = continuation_token.is_some(),
+                ?start_after,
                 delimiter,
                 max_keys,
                 prefix
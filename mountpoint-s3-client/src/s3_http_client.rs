@@ -0,0 +1,924 @@
+//! An Amazon S3 client built directly on an HTTP stack written in Rust.
+//!
+//! [S3HttpClient] is an alternative to `S3CrtClient` that issues S3 requests
+//! using [hyper] and [rustls](https://github.com/rustls/rustls), signing them with a small SigV4
+//! implementation. It does not use the AWS CRT to make requests, so it is easier to debug and
+//! trace, at the cost of the CRT's request parallelization. Each GetObject is a single streaming
+//! HTTP request and each PutObject uploads its parts sequentially.
+//!
+//! This client is only built with the `http_client` feature. It doesn't depend on the CRT, so
+//! together with `--no-default-features` (which turns off the `crt` feature and `S3CrtClient`)
+//! the CRT is left out of the build entirely.
+
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use hyper::body::Bytes;
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
+use hyper_rustls::HttpsConnector;
+use percent_encoding::utf8_percent_encode;
+use thiserror::Error;
+use time::OffsetDateTime;
+use tracing::{debug, Instrument};
+
+use crate::addressing_style::AddressingStyle;
+use crate::error_metadata::{ClientErrorMetadata, ProvideErrorMetadata};
+use crate::object_client::*;
+use crate::sigv4::{self, Credentials};
+use crate::user_agent::UserAgent;
+
+macro_rules! request_span {
+    ($self:expr, $method:expr, $($field:tt)*) => {{
+        let counter = $self.next_request_counter();
+        let span = tracing::warn_span!(target: "mountpoint_s3_client::s3_http_client::request", $method, id = counter, $($field)*);
+        span.in_scope(|| tracing::debug!("new request"));
+        span
+    }};
+    ($self:expr, $method:expr) => { request_span!($self, $method,) };
+}
+
+pub(crate) mod delete_object;
+pub(crate) mod get_object;
+pub(crate) mod get_object_attributes;
+pub(crate) mod head_object;
+pub(crate) mod list_objects;
+pub(crate) mod put_object;
+
+pub use get_object::S3HttpGetObjectRequest;
+pub use put_object::S3HttpPutObjectRequest;
+
+/// Configurations for the HTTP-based S3 client
+#[derive(Debug, Clone)]
+pub struct S3HttpClientConfig {
+    auth_config: S3HttpClientAuthConfig,
+    region: String,
+    endpoint_url: Option<String>,
+    addressing_style: AddressingStyle,
+    read_part_size: usize,
+    write_part_size: usize,
+    user_agent: Option<UserAgent>,
+    max_attempts: Option<NonZeroUsize>,
+    read_backpressure: bool,
+    initial_read_window: usize,
+}
+
+impl Default for S3HttpClientConfig {
+    fn default() -> Self {
+        const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+        Self {
+            auth_config: Default::default(),
+            region: "us-east-1".to_owned(),
+            endpoint_url: None,
+            addressing_style: AddressingStyle::Automatic,
+            read_part_size: DEFAULT_PART_SIZE,
+            write_part_size: DEFAULT_PART_SIZE,
+            user_agent: None,
+            max_attempts: None,
+            read_backpressure: false,
+            initial_read_window: DEFAULT_PART_SIZE,
+        }
+    }
+}
+
+impl S3HttpClientConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the configuration for authenticating to S3
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn auth_config(mut self, auth_config: S3HttpClientAuthConfig) -> Self {
+        self.auth_config = auth_config;
+        self
+    }
+
+    /// Set the region used to sign requests and to build the default endpoint
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn region(mut self, region: &str) -> Self {
+        self.region = region.to_owned();
+        self
+    }
+
+    /// Set a custom endpoint URL (e.g. `http://localhost:9000`) instead of the default AWS endpoint
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn endpoint_url(mut self, endpoint_url: &str) -> Self {
+        self.endpoint_url = Some(endpoint_url.to_owned());
+        self
+    }
+
+    /// Set the addressing style for requests
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn addressing_style(mut self, addressing_style: AddressingStyle) -> Self {
+        self.addressing_style = addressing_style;
+        self
+    }
+
+    /// Set the part size for multi-part operations to S3 (both PUT and GET)
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn part_size(mut self, part_size: usize) -> Self {
+        self.read_part_size = part_size;
+        self.write_part_size = part_size;
+        self
+    }
+
+    /// Set the part size for multi-part-get operations to S3.
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn read_part_size(mut self, size: usize) -> Self {
+        self.read_part_size = size;
+        self
+    }
+
+    /// Set the part size for multi-part-put operations to S3.
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn write_part_size(mut self, size: usize) -> Self {
+        self.write_part_size = size;
+        self
+    }
+
+    /// Set a constructor for the HTTP User-agent header for S3 requests
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn user_agent(mut self, user_agent: UserAgent) -> Self {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    /// Set a maximum number of attempts for S3 requests. Will be overridden by the
+    /// `AWS_MAX_ATTEMPTS` environment variable if set.
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn max_attempts(mut self, max_attempts: NonZeroUsize) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Set the flag for backpressure read
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn read_backpressure(mut self, read_backpressure: bool) -> Self {
+        self.read_backpressure = read_backpressure;
+        self
+    }
+
+    /// Set a value for initial backpressure read window size
+    #[must_use = "S3HttpClientConfig follows a builder pattern"]
+    pub fn initial_read_window(mut self, initial_read_window: usize) -> Self {
+        self.initial_read_window = initial_read_window;
+        self
+    }
+}
+
+/// Authentication configuration for the HTTP-based S3 client
+#[derive(Debug, Clone, Default)]
+pub enum S3HttpClientAuthConfig {
+    /// Load credentials from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, and
+    /// `AWS_SESSION_TOKEN` environment variables.
+    ///
+    /// Unlike the CRT client's default credentials chain, this does not read shared profiles or
+    /// query the instance metadata service. If the variables are not set, requests fail with
+    /// [S3HttpRequestError::NoSigningCredentials].
+    #[default]
+    Environment,
+    /// Do not sign requests at all
+    NoSigning,
+    /// Sign requests with the given static credentials
+    Credentials(Credentials),
+}
+
+/// An S3 client that makes requests with a pure-Rust HTTP stack.
+///
+/// The client owns a small Tokio runtime that drives its connections, so it can be used from any
+/// async executor.
+#[derive(Debug, Clone)]
+pub struct S3HttpClient {
+    inner: Arc<S3HttpClientInner>,
+}
+
+impl S3HttpClient {
+    /// Construct a new HTTP-based S3 client with the given configuration.
+    pub fn new(config: S3HttpClientConfig) -> Result<Self, NewHttpClientError> {
+        Ok(Self {
+            inner: Arc::new(S3HttpClientInner::new(config)?),
+        })
+    }
+}
+
+/// How requests are signed, resolved from [S3HttpClientAuthConfig] when the client is created.
+#[derive(Debug)]
+enum Signer {
+    NoSigning,
+    Credentials(Credentials),
+    /// Signing was requested, but no credentials could be found. Requests fail without being sent.
+    Unavailable,
+}
+
+/// The endpoint requests are sent to, split into the parts we need to build request URIs.
+#[derive(Debug)]
+struct Endpoint {
+    scheme: String,
+    authority: String,
+    base_path: String,
+    /// Whether the endpoint allows virtual-hosted-style addressing when [AddressingStyle::Automatic]
+    /// is configured. True for AWS endpoints, false for IP addresses.
+    allow_virtual: bool,
+}
+
+#[derive(Debug)]
+struct S3HttpClientInner {
+    http: hyper::Client<HttpsConnector<HttpConnector>>,
+    /// The runtime that drives the client's connections. Kept in an [Option] so it can be shut
+    /// down without blocking when the client is dropped, possibly from within an async context.
+    runtime: Option<tokio::runtime::Runtime>,
+    handle: tokio::runtime::Handle,
+    signer: Signer,
+    region: String,
+    endpoint: Endpoint,
+    addressing_style: AddressingStyle,
+    next_request_counter: AtomicU64,
+    user_agent_header: String,
+    read_part_size: usize,
+    write_part_size: usize,
+    enable_backpressure: bool,
+    initial_read_window_size: usize,
+    max_attempts: usize,
+}
+
+impl S3HttpClientInner {
+    fn new(config: S3HttpClientConfig) -> Result<Self, NewHttpClientError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .thread_name("s3-http-client")
+            .enable_all()
+            .build()
+            .map_err(NewHttpClientError::Runtime)?;
+        let handle = runtime.handle().clone();
+
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        let http = hyper::Client::builder().build(connector);
+
+        let signer = match config.auth_config {
+            S3HttpClientAuthConfig::Environment => match Credentials::from_env() {
+                Some(credentials) => Signer::Credentials(credentials),
+                None => Signer::Unavailable,
+            },
+            S3HttpClientAuthConfig::NoSigning => Signer::NoSigning,
+            S3HttpClientAuthConfig::Credentials(credentials) => Signer::Credentials(credentials),
+        };
+
+        let endpoint = match config.endpoint_url {
+            Some(url) => {
+                let uri = Uri::from_str(&url).map_err(|_| NewHttpClientError::InvalidEndpoint(url.clone()))?;
+                let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+                    return Err(NewHttpClientError::InvalidEndpoint(url));
+                };
+                let allow_virtual =
+                    authority.host().parse::<std::net::IpAddr>().is_err() && !authority.host().starts_with('[');
+                Endpoint {
+                    scheme: scheme.to_owned(),
+                    authority: authority.as_str().to_owned(),
+                    base_path: uri.path().trim_end_matches('/').to_owned(),
+                    allow_virtual,
+                }
+            }
+            None => {
+                let suffix = if config.region.starts_with("cn-") {
+                    "amazonaws.com.cn"
+                } else {
+                    "amazonaws.com"
+                };
+                Endpoint {
+                    scheme: "https".to_owned(),
+                    authority: format!("s3.{}.{suffix}", config.region),
+                    base_path: String::new(),
+                    allow_virtual: true,
+                }
+            }
+        };
+
+        let max_attempts = match std::env::var("AWS_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+        {
+            Some(attempts) if attempts > 0 => attempts,
+            _ => config.max_attempts.map(NonZeroUsize::get).unwrap_or(3),
+        };
+
+        let user_agent = config.user_agent.unwrap_or_else(|| UserAgent::new(None));
+
+        Ok(Self {
+            http,
+            runtime: Some(runtime),
+            handle,
+            signer,
+            region: config.region,
+            endpoint,
+            addressing_style: config.addressing_style,
+            next_request_counter: AtomicU64::new(0),
+            user_agent_header: user_agent.build(),
+            read_part_size: config.read_part_size,
+            write_part_size: config.write_part_size,
+            enable_backpressure: config.read_backpressure,
+            initial_read_window_size: config.initial_read_window,
+            max_attempts,
+        })
+    }
+
+    fn next_request_counter(&self) -> u64 {
+        self.next_request_counter.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Build the URI for a request to the given bucket and (unencoded) key. `query` is a list of
+    /// unencoded parameter names and values.
+    fn request_uri(&self, bucket: &str, key: &str, query: &[(&str, &str)]) -> Result<Uri, S3HttpRequestError> {
+        let use_virtual = self.addressing_style == AddressingStyle::Automatic
+            && self.endpoint.allow_virtual
+            && is_virtual_hostable(bucket, &self.endpoint.scheme);
+        let encoded_key = utf8_percent_encode(key, sigv4::PATH_ENCODE_SET);
+        let (authority, path) = if use_virtual {
+            (
+                format!("{bucket}.{}", self.endpoint.authority),
+                format!("{}/{encoded_key}", self.endpoint.base_path),
+            )
+        } else {
+            (
+                self.endpoint.authority.clone(),
+                format!("{}/{bucket}/{encoded_key}", self.endpoint.base_path),
+            )
+        };
+
+        let mut uri = format!("{}://{authority}{path}", self.endpoint.scheme);
+        for (i, (name, value)) in query.iter().enumerate() {
+            uri.push(if i == 0 { '?' } else { '&' });
+            uri.push_str(&sigv4::encode_query_component(name));
+            uri.push('=');
+            uri.push_str(&sigv4::encode_query_component(value));
+        }
+        Uri::from_str(&uri).map_err(|e| S3HttpRequestError::ConstructionFailure(e.into()))
+    }
+
+    /// Create a new request template for the given operation, with the headers every request needs.
+    fn new_request(
+        &self,
+        method: Method,
+        bucket: &str,
+        key: &str,
+        query: &[(&str, &str)],
+    ) -> Result<S3HttpRequest, S3HttpRequestError> {
+        let uri = self.request_uri(bucket, key, query)?;
+        let mut request = S3HttpRequest {
+            method,
+            uri,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+        };
+        request.set_header("user-agent", &self.user_agent_header)?;
+        Ok(request)
+    }
+
+    /// Sign a request for a single attempt. Signatures include a timestamp, so retries are re-signed.
+    fn sign(&self, request: &S3HttpRequest) -> Result<Request<Body>, S3HttpRequestError> {
+        let mut headers = request.headers.clone();
+        match &self.signer {
+            Signer::NoSigning => {}
+            Signer::Unavailable => return Err(S3HttpRequestError::NoSigningCredentials),
+            Signer::Credentials(credentials) => {
+                // Over HTTPS the transport protects the body, so skip hashing large payloads.
+                let payload_hash = if request.uri.scheme_str() == Some("https") {
+                    sigv4::UNSIGNED_PAYLOAD.to_owned()
+                } else {
+                    sigv4::payload_hash(&request.body)
+                };
+                sigv4::sign_request(
+                    &request.method,
+                    &request.uri,
+                    &mut headers,
+                    credentials,
+                    &self.region,
+                    &payload_hash,
+                    OffsetDateTime::now_utc(),
+                );
+            }
+        }
+
+        let mut builder = Request::builder()
+            .method(request.method.clone())
+            .uri(request.uri.clone());
+        *builder.headers_mut().unwrap() = headers;
+        builder
+            .body(Body::from(request.body.clone()))
+            .map_err(S3HttpRequestError::ConstructionFailure)
+    }
+
+    /// Send a request, retrying transport errors, server errors, and throttling with exponential
+    /// backoff. Error responses are read in full so they can be parsed by the caller.
+    async fn send(
+        &self,
+        request: &S3HttpRequest,
+        operation: &'static str,
+    ) -> Result<Result<Response<Body>, ErrorResponse>, S3HttpRequestError> {
+        let start_time = Instant::now();
+        let mut attempt = 1;
+        let result = loop {
+            let attempt_start = Instant::now();
+            let result = self.http.request(self.sign(request)?).await;
+
+            let status = result.as_ref().map(|response| response.status()).ok();
+            let status_label = status.map(|s| s.as_u16() as i32).unwrap_or(-1);
+            metrics::histogram!("s3.requests.total_latency_us", "op" => operation)
+                .record(attempt_start.elapsed().as_micros() as f64);
+            metrics::counter!("s3.requests", "op" => operation).increment(1);
+            if !status.is_some_and(|s| s.is_success()) {
+                metrics::counter!("s3.requests.failures", "op" => operation, "status" => status_label.to_string())
+                    .increment(1);
+            }
+
+            let retryable = match &status {
+                Some(status) => status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS,
+                None => true,
+            };
+            if !retryable || attempt >= self.max_attempts {
+                break result;
+            }
+
+            let backoff = Duration::from_millis(50 * (1 << attempt.min(8)));
+            debug!(attempt, ?status, ?backoff, "retrying request");
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        };
+
+        metrics::counter!("s3.meta_requests", "op" => operation).increment(1);
+        metrics::histogram!("s3.meta_requests.total_latency_us", "op" => operation)
+            .record(start_time.elapsed().as_micros() as f64);
+
+        let response = result.map_err(S3HttpRequestError::Transport)?;
+        if response.status().is_success() {
+            return Ok(Ok(response));
+        }
+
+        let (parts, body) = response.into_parts();
+        metrics::counter!("s3.meta_requests.failures", "op" => operation, "status" => parts.status.as_u16().to_string())
+            .increment(1);
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(S3HttpRequestError::Transport)?;
+        Ok(Err(ErrorResponse {
+            status: parts.status.as_u16() as i32,
+            headers: parts.headers,
+            body,
+        }))
+    }
+}
+
+impl Drop for S3HttpClientInner {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl S3HttpClient {
+    /// Make a request on the client's runtime and return the response if it was successful, or a
+    /// modeled service error parsed by `parse_error`.
+    async fn make_request<E: std::error::Error + Send + Sync + 'static>(
+        &self,
+        request: S3HttpRequest,
+        operation: &'static str,
+        span: tracing::Span,
+        parse_error: impl FnOnce(&ErrorResponse) -> Option<E>,
+    ) -> ObjectClientResult<Response<Body>, E, S3HttpRequestError> {
+        let inner = self.inner.clone();
+        let result = self
+            .inner
+            .handle
+            .spawn(async move { inner.send(&request, operation).await }.instrument(span.clone()))
+            .await
+            .map_err(|e| S3HttpRequestError::InternalError(Box::new(e)))??;
+
+        match result {
+            Ok(response) => Ok(response),
+            Err(error_response) => {
+                let error = match parse_error(&error_response) {
+                    Some(error) => ObjectClientError::ServiceError(error),
+                    None => ObjectClientError::ClientError(try_parse_generic_error(error_response)),
+                };
+                span.in_scope(|| debug!(?error, "request failed"));
+                Err(error)
+            }
+        }
+    }
+
+    /// Like [make_request](Self::make_request), but also read the whole response body.
+    async fn make_simple_request<E: std::error::Error + Send + Sync + 'static>(
+        &self,
+        request: S3HttpRequest,
+        operation: &'static str,
+        span: tracing::Span,
+        parse_error: impl FnOnce(&ErrorResponse) -> Option<E>,
+    ) -> ObjectClientResult<(HeaderMap, Bytes), E, S3HttpRequestError> {
+        let response = self.make_request(request, operation, span, parse_error).await?;
+        let (parts, body) = response.into_parts();
+        let body = self
+            .inner
+            .handle
+            .spawn(hyper::body::to_bytes(body))
+            .await
+            .map_err(|e| S3HttpRequestError::InternalError(Box::new(e)))?
+            .map_err(S3HttpRequestError::Transport)?;
+        Ok((parts.headers, body))
+    }
+}
+
+/// A request template. Unlike [Request], this can be cloned, so it can be re-signed and resent on
+/// every attempt.
+#[derive(Debug, Clone)]
+struct S3HttpRequest {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl S3HttpRequest {
+    fn set_header(&mut self, name: &str, value: &str) -> Result<(), S3HttpRequestError> {
+        let name = HeaderName::from_str(name).map_err(|e| S3HttpRequestError::ConstructionFailure(e.into()))?;
+        let value = HeaderValue::from_str(value).map_err(|e| S3HttpRequestError::ConstructionFailure(e.into()))?;
+        self.headers.insert(name, value);
+        Ok(())
+    }
+
+    fn add_header(&mut self, name: &str, value: &str) -> Result<(), S3HttpRequestError> {
+        let name = HeaderName::from_str(name).map_err(|e| S3HttpRequestError::ConstructionFailure(e.into()))?;
+        let value = HeaderValue::from_str(value).map_err(|e| S3HttpRequestError::ConstructionFailure(e.into()))?;
+        self.headers.append(name, value);
+        Ok(())
+    }
+}
+
+/// Whether a bucket name can be used as a DNS label for virtual-hosted-style requests.
+fn is_virtual_hostable(bucket: &str, scheme: &str) -> bool {
+    // Dots in bucket names break TLS certificate validation for virtual-hosted-style requests.
+    let allow_dots = scheme != "https";
+    (3..=63).contains(&bucket.len())
+        && bucket
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || (allow_dots && c == '.'))
+        && !bucket.starts_with('-')
+        && !bucket.ends_with('-')
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// A failed response from S3, with its body fully read.
+#[derive(Debug)]
+pub struct ErrorResponse {
+    /// HTTP status code of the response
+    pub status: i32,
+    /// Headers of the response
+    pub headers: HeaderMap,
+    /// Body of the response, usually an XML error document
+    pub body: Bytes,
+}
+
+impl ErrorResponse {
+    /// The `Code` field of the XML error document in the body, if any.
+    fn error_code(&self) -> Option<String> {
+        let root = xmltree::Element::parse(self.body.deref()).ok()?;
+        let error_code = root.get_child("Code")?;
+        Some(error_code.get_text()?.into_owned())
+    }
+}
+
+/// Errors returned on failure to create a new [S3HttpClient]
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum NewHttpClientError {
+    /// Invalid S3 endpoint
+    #[error("invalid S3 endpoint: {0}")]
+    InvalidEndpoint(String),
+    /// Failed to create the runtime that drives the client
+    #[error("failed to create client runtime")]
+    Runtime(#[source] std::io::Error),
+}
+
+/// Errors returned by the HTTP-based S3 client
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum S3HttpRequestError {
+    /// An internal error from within the S3 client. The request may have been sent.
+    #[error("Internal S3 client error")]
+    InternalError(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// An error in the HTTP transport. The request may have been sent.
+    #[error("HTTP transport error")]
+    Transport(#[source] hyper::Error),
+
+    /// An error during construction of a request. The request was not sent.
+    #[error("Failed to construct request")]
+    ConstructionFailure(#[source] hyper::http::Error),
+
+    /// The request was sent but an unknown or unhandled failure occurred while processing it.
+    #[error("Unknown response error: {0:?}")]
+    ResponseError(Box<ErrorResponse>),
+
+    /// The request was made to the wrong region
+    #[error("Wrong region (expecting {0})")]
+    IncorrectRegion(String),
+
+    /// Forbidden
+    #[error("Forbidden: {0}")]
+    Forbidden(String, ClientErrorMetadata),
+
+    /// The request was attempted but could not be signed due to no available credentials
+    #[error("No signing credentials available")]
+    NoSigningCredentials,
+
+    /// The request was canceled
+    #[error("Request canceled")]
+    RequestCanceled,
+
+    /// The request was throttled by S3
+    #[error("Request throttled")]
+    Throttled,
+
+    /// Cannot fetch more data because current read window is exhausted. The read window must
+    /// be advanced using [GetObjectRequest::increment_read_window(u64)] to continue fetching
+    /// new data.
+    #[error("Polled for data with empty read window")]
+    EmptyReadWindow,
+}
+
+impl ProvideErrorMetadata for S3HttpRequestError {
+    fn meta(&self) -> ClientErrorMetadata {
+        match self {
+            Self::ResponseError(response) => ClientErrorMetadata {
+                http_code: Some(response.status),
+                error_code: response.error_code(),
+                ..Default::default()
+            },
+            Self::Forbidden(_, metadata) => metadata.clone(),
+            Self::Throttled => ClientErrorMetadata {
+                http_code: Some(503),
+                ..Default::default()
+            },
+            _ => Default::default(),
+        }
+    }
+}
+
+/// Try to parse a modeled error out of a failing response, falling back to a generic
+/// [S3HttpRequestError::ResponseError].
+fn try_parse_generic_error(response: ErrorResponse) -> S3HttpRequestError {
+    /// Look for a redirect header pointing to a different region for the bucket
+    fn try_parse_redirect(response: &ErrorResponse) -> Option<S3HttpRequestError> {
+        let region = header_str(&response.headers, "x-amz-bucket-region")?;
+        Some(S3HttpRequestError::IncorrectRegion(region.to_owned()))
+    }
+
+    /// Look for access-related errors
+    fn try_parse_forbidden(response: &ErrorResponse) -> Option<S3HttpRequestError> {
+        if response.body.is_empty() {
+            // Header-only requests like HeadObject can't give us a more detailed error, so just
+            // trust the response code
+            return (response.status == 403).then(|| {
+                S3HttpRequestError::Forbidden(
+                    "<no message>".to_owned(),
+                    ClientErrorMetadata {
+                        http_code: Some(response.status),
+                        ..Default::default()
+                    },
+                )
+            });
+        }
+        let error_elem = xmltree::Element::parse(response.body.deref()).ok()?;
+        let error_code = error_elem.get_child("Code")?.get_text()?;
+        if response.status == 403
+            || matches!(
+                error_code.deref(),
+                "AccessDenied" | "InvalidToken" | "ExpiredToken" | "SignatureDoesNotMatch"
+            )
+        {
+            let message = error_elem
+                .get_child("Message")
+                .and_then(|e| e.get_text())
+                .unwrap_or(error_code.clone());
+            Some(S3HttpRequestError::Forbidden(
+                message.to_string(),
+                ClientErrorMetadata {
+                    http_code: Some(response.status),
+                    error_code: Some(error_code.to_string()),
+                    error_message: Some(message.into_owned()),
+                },
+            ))
+        } else {
+            None
+        }
+    }
+
+    let parsed = match response.status {
+        301 => try_parse_redirect(&response),
+        // 400 is overloaded, it can be an access error (invalid token) or (for MRAP) a bucket
+        // redirect
+        400 => try_parse_forbidden(&response).or_else(|| try_parse_redirect(&response)),
+        403 => try_parse_forbidden(&response),
+        429 | 503 => Some(S3HttpRequestError::Throttled),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| S3HttpRequestError::ResponseError(Box::new(response)))
+}
+
+#[cfg_attr(not(docsrs), async_trait)]
+impl ObjectClient for S3HttpClient {
+    type GetObjectRequest = S3HttpGetObjectRequest;
+    type PutObjectRequest = S3HttpPutObjectRequest;
+    type ClientError = S3HttpRequestError;
+
+    fn read_part_size(&self) -> Option<usize> {
+        Some(self.inner.read_part_size)
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
+        Some(self.inner.write_part_size)
+    }
+
+    fn initial_read_window_size(&self) -> Option<usize> {
+        if self.inner.enable_backpressure {
+            Some(self.inner.initial_read_window_size)
+        } else {
+            None
+        }
+    }
+
+    fn mem_usage_stats(&self) -> Option<BufferPoolUsageStats> {
+        None
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        self.delete_object(bucket, key).await
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        range: Option<std::ops::Range<u64>>,
+        if_match: Option<ETag>,
+    ) -> ObjectClientResult<Self::GetObjectRequest, GetObjectError, Self::ClientError> {
+        self.get_object(bucket, key, range, if_match).await
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.list_objects(bucket, None, continuation_token, delimiter, max_keys, prefix)
+            .await
+    }
+
+    async fn list_objects_after(
+        &self,
+        bucket: &str,
+        start_after: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.list_objects(
+            bucket,
+            Some(start_after),
+            continuation_token,
+            delimiter,
+            max_keys,
+            prefix,
+        )
+        .await
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        self.head_object(bucket, key).await
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<Self::PutObjectRequest, PutObjectError, Self::ClientError> {
+        self.put_object(bucket, key, params).await
+    }
+
+    async fn put_object_single<'a>(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectSingleParams,
+        contents: impl AsRef<[u8]> + Send + 'a,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        self.put_object_single(bucket, key, params, contents.as_ref()).await
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        self.get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn make_response(status: i32, body: &str) -> ErrorResponse {
+        ErrorResponse {
+            status,
+            headers: HeaderMap::new(),
+            body: Bytes::copy_from_slice(body.as_bytes()),
+        }
+    }
+
+    #[test_case(None, "my-bucket", "a/b c.txt", "https://my-bucket.s3.us-west-2.amazonaws.com/a/b%20c.txt"; "virtual hosted")]
+    #[test_case(None, "my.bucket", "key", "https://s3.us-west-2.amazonaws.com/my.bucket/key"; "dotted bucket")]
+    #[test_case(Some("http://localhost:9000"), "my-bucket", "key", "http://my-bucket.localhost:9000/key"; "custom endpoint")]
+    #[test_case(Some("http://127.0.0.1:9000/"), "my-bucket", "key", "http://127.0.0.1:9000/my-bucket/key"; "ip endpoint")]
+    fn request_uri(endpoint_url: Option<&str>, bucket: &str, key: &str, expected: &str) {
+        let mut config = S3HttpClientConfig::new().region("us-west-2");
+        if let Some(endpoint_url) = endpoint_url {
+            config = config.endpoint_url(endpoint_url);
+        }
+        let client = S3HttpClient::new(config).unwrap();
+        let uri = client.inner.request_uri(bucket, key, &[]).unwrap();
+        assert_eq!(uri.to_string(), expected);
+    }
+
+    #[test]
+    fn request_uri_path_style_with_query() {
+        let config = S3HttpClientConfig::new()
+            .endpoint_url("http://localhost:9000")
+            .addressing_style(AddressingStyle::Path);
+        let client = S3HttpClient::new(config).unwrap();
+        let uri = client
+            .inner
+            .request_uri("my-bucket", "", &[("list-type", "2"), ("prefix", "a b/")])
+            .unwrap();
+        assert_eq!(
+            uri.to_string(),
+            "http://localhost:9000/my-bucket/?list-type=2&prefix=a%20b%2F"
+        );
+    }
+
+    #[test]
+    fn parse_403_forbidden() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>"#;
+        let error = try_parse_generic_error(make_response(403, body));
+        let S3HttpRequestError::Forbidden(message, metadata) = error else {
+            panic!("wrong error type: {error:?}");
+        };
+        assert_eq!(message, "Access Denied");
+        assert_eq!(metadata.error_code.as_deref(), Some("AccessDenied"));
+    }
+
+    #[test]
+    fn parse_503_throttled() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>SlowDown</Code><Message>Please reduce your request rate.</Message></Error>"#;
+        let error = try_parse_generic_error(make_response(503, body));
+        assert!(matches!(error, S3HttpRequestError::Throttled));
+    }
+
+    #[test]
+    fn parse_500_unknown() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>InternalError</Code></Error>"#;
+        let error = try_parse_generic_error(make_response(500, body));
+        assert_eq!(error.meta().error_code.as_deref(), Some("InternalError"));
+        assert!(matches!(error, S3HttpRequestError::ResponseError(_)));
+    }
+}
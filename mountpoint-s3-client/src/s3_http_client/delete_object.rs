@@ -0,0 +1,29 @@
+use hyper::Method;
+
+use crate::object_client::{DeleteObjectError, DeleteObjectResult, ObjectClientResult};
+use crate::s3_http_client::{ErrorResponse, S3HttpClient, S3HttpRequestError};
+
+impl S3HttpClient {
+    /// Delete an object from S3
+    pub(super) async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, S3HttpRequestError> {
+        let span = request_span!(self.inner, "delete_object", bucket, key);
+        let request = self.inner.new_request(Method::DELETE, bucket, key, &[])?;
+        self.make_simple_request(request, "DeleteObject", span, parse_delete_object_error)
+            .await?;
+        Ok(DeleteObjectResult {})
+    }
+}
+
+fn parse_delete_object_error(response: &ErrorResponse) -> Option<DeleteObjectError> {
+    match response.status {
+        404 => match response.error_code()?.as_str() {
+            "NoSuchBucket" => Some(DeleteObjectError::NoSuchBucket),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,319 @@
+use std::fmt::{self, Debug};
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream};
+use hyper::body::{Bytes, HttpBody};
+use hyper::{Body, Method, Response};
+
+use crate::object_client::{
+    ETag, GetBodyPart, GetObjectError, GetObjectRequest, ObjectClientError, ObjectClientResult,
+};
+use crate::s3_http_client::{header_str, ErrorResponse, S3HttpClient, S3HttpRequestError};
+use crate::s3_response::emit_throughput_metric;
+
+impl S3HttpClient {
+    /// Create and begin a new GetObject request. The returned [S3HttpGetObjectRequest] is a [Stream]
+    /// of body parts of the object, which will be delivered in order. As with the CRT client, errors
+    /// from S3 (e.g. a missing key or a failed precondition) are returned from the stream rather than
+    /// from this call.
+    pub(super) async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        range: Option<Range<u64>>,
+        if_match: Option<ETag>,
+    ) -> ObjectClientResult<S3HttpGetObjectRequest, GetObjectError, S3HttpRequestError> {
+        let span = request_span!(self.inner, "get_object", bucket, key, ?range, ?if_match);
+
+        let mut request = self.inner.new_request(Method::GET, bucket, key, &[])?;
+        if let Some(etag) = &if_match {
+            // Return the object only if its entity tag (ETag) is matched
+            request.set_header("If-Match", etag.as_str())?;
+        }
+
+        let (next_offset, end_offset) = match &range {
+            Some(range) if range.is_empty() => {
+                // HTTP can't express an empty range, so there's nothing to fetch.
+                return Ok(S3HttpGetObjectRequest::empty(range.start));
+            }
+            Some(range) => {
+                // Range HTTP header is bounded below *inclusive*
+                let range_value = format!("bytes={}-{}", range.start, range.end - 1);
+                request.set_header("Range", &range_value)?;
+                (range.start, Some(range.end))
+            }
+            None => (0, None),
+        };
+
+        let client = self.clone();
+        let response = async move {
+            client
+                .make_request(request, "GetObject", span, parse_get_object_error)
+                .await
+        }
+        .boxed();
+
+        Ok(S3HttpGetObjectRequest {
+            response: Some(response),
+            body: None,
+            body_offset: 0,
+            pending: None,
+            enable_backpressure: self.inner.enable_backpressure,
+            next_offset,
+            end_offset,
+            read_window_end_offset: next_offset + self.inner.initial_read_window_size as u64,
+            start_time: Instant::now(),
+            total_bytes: 0,
+        })
+    }
+}
+
+/// A streaming response to a GetObject request.
+///
+/// This struct implements [`futures::Stream`], which you can use to read the body of the object.
+/// Each item of the stream is a part of the object body together with the part's offset within the
+/// object.
+pub struct S3HttpGetObjectRequest {
+    /// The in-flight request, until its response headers arrive.
+    response: Option<BoxFuture<'static, ObjectClientResult<Response<Body>, GetObjectError, S3HttpRequestError>>>,
+    /// The response body, or [None] before the response arrives and once the stream has finished.
+    body: Option<Body>,
+    /// Data received from the body but not yet returned, because it's beyond the read window.
+    pending: Option<(u64, Bytes)>,
+    enable_backpressure: bool,
+    /// Offset within the object of the next byte we'll receive from `body`
+    body_offset: u64,
+    /// Next offset of the data to be polled from [poll_next]
+    next_offset: u64,
+    /// End of the requested range (exclusive), if a range was requested
+    end_offset: Option<u64>,
+    /// Upper bound of the current read window. When backpressure is enabled,
+    /// [S3HttpGetObjectRequest] can return data up to this offset *exclusively*.
+    read_window_end_offset: u64,
+    start_time: Instant,
+    total_bytes: u64,
+}
+
+impl Debug for S3HttpGetObjectRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3HttpGetObjectRequest")
+            .field("next_offset", &self.next_offset)
+            .field("end_offset", &self.end_offset)
+            .field("read_window_end_offset", &self.read_window_end_offset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3HttpGetObjectRequest {
+    fn empty(offset: u64) -> Self {
+        Self {
+            response: None,
+            body: None,
+            pending: None,
+            enable_backpressure: false,
+            body_offset: offset,
+            next_offset: offset,
+            end_offset: Some(offset),
+            read_window_end_offset: offset,
+            start_time: Instant::now(),
+            total_bytes: 0,
+        }
+    }
+
+    /// Return as much of the pending data as the read window allows.
+    fn take_pending(&mut self) -> Option<ObjectClientResult<GetBodyPart, GetObjectError, S3HttpRequestError>> {
+        let (offset, mut data) = self.pending.take()?;
+        if self.enable_backpressure {
+            if offset >= self.read_window_end_offset {
+                self.pending = Some((offset, data));
+                return Some(Err(ObjectClientError::ClientError(S3HttpRequestError::EmptyReadWindow)));
+            }
+            let allowed = (self.read_window_end_offset - offset) as usize;
+            if data.len() > allowed {
+                let rest = data.split_off(allowed);
+                self.pending = Some((offset + allowed as u64, rest));
+            }
+        }
+        self.next_offset = offset + data.len() as u64;
+        self.total_bytes += data.len() as u64;
+        Some(Ok((offset, data.to_vec().into_boxed_slice())))
+    }
+}
+
+impl GetObjectRequest for S3HttpGetObjectRequest {
+    type ClientError = S3HttpRequestError;
+
+    fn increment_read_window(mut self: Pin<&mut Self>, len: usize) {
+        // Unlike the CRT, data beyond the window is simply not read from the connection, so TCP
+        // flow control does the rest.
+        self.read_window_end_offset += len as u64;
+    }
+
+    fn read_window_end_offset(self: Pin<&Self>) -> u64 {
+        self.read_window_end_offset
+    }
+}
+
+impl Stream for S3HttpGetObjectRequest {
+    type Item = ObjectClientResult<GetBodyPart, GetObjectError, S3HttpRequestError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(response) = this.response.as_mut() {
+            let result = ready!(response.poll_unpin(cx));
+            this.response = None;
+            match result {
+                Ok(response) => {
+                    // If the server ignored our Range header, the body starts at the beginning of the
+                    // object and we'll skip up to the requested offset as it arrives.
+                    this.body_offset = header_str(response.headers(), "Content-Range")
+                        .and_then(parse_content_range_start)
+                        .unwrap_or(0);
+                    this.body = Some(response.into_body());
+                }
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+
+        loop {
+            if let Some(result) = this.take_pending() {
+                return Poll::Ready(Some(result));
+            }
+
+            let Some(body) = this.body.as_mut() else {
+                return Poll::Ready(None);
+            };
+
+            match Pin::new(body).poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    // Trim the chunk to the requested range, in case the server ignored it.
+                    let chunk_start = this.body_offset;
+                    let len = chunk.len() as u64;
+                    this.body_offset += len;
+                    let skip = this.next_offset.saturating_sub(chunk_start).min(len);
+                    let end = this
+                        .end_offset
+                        .map(|end| end.saturating_sub(chunk_start).min(len))
+                        .unwrap_or(len);
+                    if skip < end {
+                        this.pending = Some((chunk_start + skip, chunk.slice(skip as usize..end as usize)));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    this.body = None;
+                    return Poll::Ready(Some(Err(ObjectClientError::ClientError(
+                        S3HttpRequestError::Transport(e),
+                    ))));
+                }
+                Poll::Ready(None) => {
+                    this.body = None;
+                    emit_throughput_metric(this.total_bytes, this.start_time.elapsed(), "get_object");
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => {
+                    // As with the CRT client, return an error rather than blocking forever if the
+                    // read window is too small to make progress.
+                    if this.enable_backpressure && this.read_window_end_offset <= this.next_offset {
+                        return Poll::Ready(Some(Err(ObjectClientError::ClientError(
+                            S3HttpRequestError::EmptyReadWindow,
+                        ))));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+/// Parse the start offset out of a `Content-Range: bytes <start>-<end>/<size>` header.
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    let range = value.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.parse().ok()
+}
+
+fn parse_get_object_error(response: &ErrorResponse) -> Option<GetObjectError> {
+    match response.status {
+        404 => match response.error_code()?.as_str() {
+            "NoSuchBucket" => Some(GetObjectError::NoSuchBucket),
+            "NoSuchKey" => Some(GetObjectError::NoSuchKey),
+            _ => None,
+        },
+        412 => Some(GetObjectError::PreconditionFailed),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use hyper::header::HeaderMap;
+
+    use super::*;
+
+    fn make_request(chunks: Vec<&'static [u8]>, next_offset: u64, end_offset: Option<u64>) -> S3HttpGetObjectRequest {
+        let stream = futures::stream::iter(chunks.into_iter().map(Ok::<_, std::io::Error>));
+        S3HttpGetObjectRequest {
+            response: None,
+            body: Some(Body::wrap_stream(stream)),
+            pending: None,
+            enable_backpressure: false,
+            body_offset: 0,
+            next_offset,
+            end_offset,
+            read_window_end_offset: 0,
+            start_time: Instant::now(),
+            total_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn trims_ignored_range() {
+        let request = make_request(vec![b"0123", b"4567", b"89"], 3, Some(7));
+        let parts: Vec<_> = block_on(request.map(|part| part.unwrap()).collect());
+        assert_eq!(parts, vec![(3, b"3"[..].into()), (4, b"456"[..].into())]);
+    }
+
+    #[test]
+    fn splits_at_read_window() {
+        let mut request = make_request(vec![b"0123456789"], 0, None);
+        request.enable_backpressure = true;
+        request.read_window_end_offset = 4;
+        let mut request = Box::pin(request);
+
+        let (offset, data) = block_on(request.next()).unwrap().unwrap();
+        assert_eq!((offset, &data[..]), (0, &b"0123"[..]));
+        let err = block_on(request.next()).unwrap().unwrap_err();
+        assert!(matches!(
+            err,
+            ObjectClientError::ClientError(S3HttpRequestError::EmptyReadWindow)
+        ));
+
+        request.as_mut().increment_read_window(100);
+        let (offset, data) = block_on(request.next()).unwrap().unwrap();
+        assert_eq!((offset, &data[..]), (4, &b"456789"[..]));
+        assert!(block_on(request.next()).is_none());
+    }
+
+    #[test]
+    fn parse_404_no_such_key() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message><Key>not-a-real-key</Key></Error>"#;
+        let response = ErrorResponse {
+            status: 404,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(body),
+        };
+        assert_eq!(parse_get_object_error(&response), Some(GetObjectError::NoSuchKey));
+    }
+
+    #[test]
+    fn content_range() {
+        assert_eq!(parse_content_range_start("bytes 10-19/100"), Some(10));
+        assert_eq!(parse_content_range_start("items 10-19/100"), None);
+    }
+}
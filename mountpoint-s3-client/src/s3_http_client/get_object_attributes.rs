@@ -0,0 +1,57 @@
+use hyper::Method;
+
+use crate::object_client::{
+    GetObjectAttributesError, GetObjectAttributesResult, ObjectAttribute, ObjectClientError, ObjectClientResult,
+};
+use crate::s3_http_client::{ErrorResponse, S3HttpClient, S3HttpRequestError};
+
+impl S3HttpClient {
+    pub(super) async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, S3HttpRequestError> {
+        let object_attributes: Vec<String> = object_attributes.iter().map(|attr| attr.to_string()).collect();
+        let span = request_span!(
+            self.inner,
+            "get_object_attributes",
+            bucket,
+            key,
+            ?max_parts,
+            ?part_number_marker,
+            ?object_attributes
+        );
+
+        let mut request = self
+            .inner
+            .new_request(Method::GET, bucket, key, &[("attributes", "")])?;
+        if let Some(max_parts) = max_parts {
+            request.set_header("x-amz-max-parts", &format!("{max_parts}"))?;
+        }
+        if let Some(part_number_marker) = part_number_marker {
+            request.set_header("x-amz-part-number-marker", &format!("{part_number_marker}"))?;
+        }
+        request.set_header("x-amz-object-attributes", &object_attributes.join(","))?;
+
+        let (_, body) = self
+            .make_simple_request(request, "GetObjectAttributes", span, parse_get_object_attributes_error)
+            .await?;
+
+        GetObjectAttributesResult::parse_from_bytes(&body)
+            .map_err(|e| ObjectClientError::ClientError(S3HttpRequestError::InternalError(e.into())))
+    }
+}
+
+fn parse_get_object_attributes_error(response: &ErrorResponse) -> Option<GetObjectAttributesError> {
+    match response.status {
+        404 => match response.error_code()?.as_str() {
+            "NoSuchBucket" => Some(GetObjectAttributesError::NoSuchBucket),
+            "NoSuchKey" => Some(GetObjectAttributesError::NoSuchKey),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use hyper::header::HeaderMap;
+use hyper::Method;
+use thiserror::Error;
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+
+use crate::object_client::{HeadObjectError, HeadObjectResult, ObjectClientError, ObjectClientResult, ObjectInfo};
+use crate::s3_http_client::{header_str, ErrorResponse, S3HttpClient, S3HttpRequestError};
+use crate::s3_response::restore::{self, parse_restore_header};
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error("Header {0} is missing or not a valid string")]
+    MissingHeader(String),
+
+    #[error("Failed to parse field {1} as OffsetDateTime: {0:?}")]
+    OffsetDateTime(#[source] time::error::Parse, String),
+
+    #[error("Failed to parse field {1} as an int: {0:?}")]
+    Int(#[source] std::num::ParseIntError, String),
+
+    #[error("Header x-amz-restore is invalid")]
+    InvalidRestore(#[from] restore::ParseError),
+}
+
+fn get_field(headers: &HeaderMap, name: &str) -> Result<String, ParseError> {
+    header_str(headers, name)
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| ParseError::MissingHeader(name.to_owned()))
+}
+
+fn parse_head_object_result(bucket: &str, key: &str, headers: &HeaderMap) -> Result<HeadObjectResult, ParseError> {
+    let last_modified = OffsetDateTime::parse(&get_field(headers, "Last-Modified")?, &Rfc2822)
+        .map_err(|e| ParseError::OffsetDateTime(e, "LastModified".into()))?;
+    let size = u64::from_str(&get_field(headers, "Content-Length")?)
+        .map_err(|e| ParseError::Int(e, "ContentLength".into()))?;
+    let etag = get_field(headers, "ETag")?;
+    let storage_class = header_str(headers, "x-amz-storage-class").map(ToOwned::to_owned);
+    let restore_status = header_str(headers, "x-amz-restore")
+        .map(|header| parse_restore_header(header.to_owned()))
+        .transpose()?;
+    let object = ObjectInfo {
+        key: key.to_owned(),
+        size,
+        last_modified,
+        storage_class,
+        restore_status,
+        etag,
+    };
+    Ok(HeadObjectResult {
+        bucket: bucket.to_owned(),
+        object,
+    })
+}
+
+impl S3HttpClient {
+    pub(super) async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, S3HttpRequestError> {
+        let span = request_span!(self.inner, "head_object", bucket, key);
+        let request = self.inner.new_request(Method::HEAD, bucket, key, &[])?;
+        let (headers, _) = self
+            .make_simple_request(request, "HeadObject", span, parse_head_object_error)
+            .await?;
+
+        parse_head_object_result(bucket, key, &headers)
+            .map_err(|e| ObjectClientError::ClientError(S3HttpRequestError::InternalError(e.into())))
+    }
+}
+
+fn parse_head_object_error(response: &ErrorResponse) -> Option<HeadObjectError> {
+    match response.status {
+        // HeadObject responses have no body, so we can't tell NoSuchBucket and NoSuchKey apart
+        404 => Some(HeadObjectError::NotFound),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::HeaderValue;
+
+    use super::*;
+    use crate::object_client::RestoreStatus;
+
+    #[test]
+    fn parse_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "last-modified",
+            HeaderValue::from_static("Fri, 21 Dec 2012 00:00:00 GMT"),
+        );
+        headers.insert("content-length", HeaderValue::from_static("1024"));
+        headers.insert("etag", HeaderValue::from_static("\"abc\""));
+        headers.insert("x-amz-restore", HeaderValue::from_static("ongoing-request=\"true\""));
+        let result = parse_head_object_result("bucket", "key", &headers).unwrap();
+        assert_eq!(result.object.size, 1024);
+        assert_eq!(result.object.etag, "\"abc\"");
+        assert!(result.object.storage_class.is_none());
+        assert!(matches!(result.object.restore_status, Some(RestoreStatus::InProgress)));
+    }
+
+    #[test]
+    fn parse_headers_missing_etag() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "last-modified",
+            HeaderValue::from_static("Fri, 21 Dec 2012 00:00:00 GMT"),
+        );
+        headers.insert("content-length", HeaderValue::from_static("1024"));
+        let err = parse_head_object_result("bucket", "key", &headers).unwrap_err();
+        assert!(matches!(err, ParseError::MissingHeader(_)));
+    }
+}
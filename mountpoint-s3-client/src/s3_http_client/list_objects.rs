@@ -0,0 +1,61 @@
+use hyper::Method;
+
+use crate::object_client::{ListObjectsError, ListObjectsResult, ObjectClientError, ObjectClientResult};
+use crate::s3_http_client::{ErrorResponse, S3HttpClient, S3HttpRequestError};
+use crate::s3_response::list_objects::parse_result_from_bytes;
+
+impl S3HttpClient {
+    pub(super) async fn list_objects(
+        &self,
+        bucket: &str,
+        start_after: Option<&str>,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, S3HttpRequestError> {
+        let span = request_span!(
+            self.inner,
+            "list_objects",
+            bucket,
+            continued = continuation_token.is_some(),
+            ?start_after,
+            delimiter,
+            max_keys,
+            prefix
+        );
+
+        let max_keys = format!("{max_keys}");
+        let mut query = vec![
+            ("list-type", "2"),
+            ("delimiter", delimiter),
+            ("max-keys", &max_keys),
+            ("prefix", prefix),
+        ];
+        if let Some(continuation_token) = continuation_token {
+            query.push(("continuation-token", continuation_token));
+        }
+        if let Some(start_after) = start_after {
+            query.push(("start-after", start_after));
+        }
+        let mut request = self.inner.new_request(Method::GET, bucket, "", &query)?;
+        request.set_header("x-amz-optional-object-attributes", "RestoreStatus")?;
+
+        let (_, body) = self
+            .make_simple_request(request, "ListObjects", span, parse_list_objects_error)
+            .await?;
+
+        parse_result_from_bytes(&body)
+            .map_err(|e| ObjectClientError::ClientError(S3HttpRequestError::InternalError(e.into())))
+    }
+}
+
+fn parse_list_objects_error(response: &ErrorResponse) -> Option<ListObjectsError> {
+    match response.status {
+        404 => match response.error_code()?.as_str() {
+            "NoSuchBucket" => Some(ListObjectsError::NoSuchBucket),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use hyper::body::Bytes;
+use hyper::header::HeaderMap;
+use hyper::Method;
+use tracing::{debug, Instrument};
+use xmltree::{Element, XMLNode};
+
+use crate::checksums::{crc32c, crc32c_to_base64};
+use crate::object_client::{
+    ChecksumAlgorithm, ObjectClientError, ObjectClientResult, PutObjectError, PutObjectParams, PutObjectRequest,
+    PutObjectResult, PutObjectSingleParams, PutObjectTrailingChecksums, UploadChecksum, UploadReview, UploadReviewPart,
+};
+use crate::s3_http_client::{header_str, ErrorResponse, S3HttpClient, S3HttpRequest, S3HttpRequestError};
+use crate::s3_response::emit_throughput_metric;
+
+const ETAG_HEADER_NAME: &str = "ETag";
+const SSE_TYPE_HEADER_NAME: &str = "x-amz-server-side-encryption";
+const SSE_KEY_ID_HEADER_NAME: &str = "x-amz-server-side-encryption-aws-kms-key-id";
+const CHECKSUM_CRC32C_HEADER_NAME: &str = "x-amz-checksum-crc32c";
+
+impl S3HttpClient {
+    /// Start a new multi-part upload. Parts are buffered in memory up to the client's write part
+    /// size and uploaded one at a time as the caller writes to the returned request.
+    pub(super) async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<S3HttpPutObjectRequest, PutObjectError, S3HttpRequestError> {
+        let span = request_span!(self.inner, "put_object", bucket, key);
+
+        let mut request = self.new_put_request(
+            Method::POST,
+            bucket,
+            key,
+            &[("uploads", "")],
+            params.storage_class.as_deref(),
+            params.server_side_encryption.as_deref(),
+            params.ssekms_key_id.as_deref(),
+            &params.object_metadata,
+            &params.custom_headers,
+        )?;
+        if params.trailing_checksums == PutObjectTrailingChecksums::Enabled {
+            request.set_header("x-amz-checksum-algorithm", "CRC32C")?;
+        }
+
+        // Create the upload eagerly so errors like a missing bucket are reported before any writes.
+        let (_, body) = self
+            .make_simple_request(request, "CreateMultipartUpload", span.clone(), parse_put_object_error)
+            .await?;
+        let upload_id = Element::parse(body.as_ref())
+            .ok()
+            .and_then(|root| root.get_child("UploadId")?.get_text().map(|s| s.into_owned()))
+            .ok_or_else(|| {
+                S3HttpRequestError::InternalError("CreateMultipartUpload response has no UploadId".into())
+            })?;
+
+        Ok(S3HttpPutObjectRequest {
+            client: self.clone(),
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            upload_id,
+            trailing_checksums: params.trailing_checksums,
+            span,
+            buffer: Vec::new(),
+            parts: Vec::new(),
+            start_time: Instant::now(),
+            total_bytes: 0,
+            state: S3HttpPutObjectRequestState::Idle,
+        })
+    }
+
+    pub(super) async fn put_object_single(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectSingleParams,
+        contents: &[u8],
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, S3HttpRequestError> {
+        let span = request_span!(self.inner, "put_object_single", bucket, key);
+        let start_time = Instant::now();
+
+        let mut request = self.new_put_request(
+            Method::PUT,
+            bucket,
+            key,
+            &[],
+            params.storage_class.as_deref(),
+            params.server_side_encryption.as_deref(),
+            params.ssekms_key_id.as_deref(),
+            &params.object_metadata,
+            &params.custom_headers,
+        )?;
+        if let Some(UploadChecksum::Crc32c(checksum)) = &params.checksum {
+            request.set_header(CHECKSUM_CRC32C_HEADER_NAME, &crc32c_to_base64(checksum))?;
+        }
+        request.body = Bytes::copy_from_slice(contents);
+
+        let (headers, _) = self
+            .make_simple_request(request, "PutObject", span, parse_put_object_error)
+            .await?;
+
+        emit_throughput_metric(contents.len() as u64, start_time.elapsed(), "put_object_single");
+
+        extract_result(&headers, None)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_put_request(
+        &self,
+        method: Method,
+        bucket: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        storage_class: Option<&str>,
+        server_side_encryption: Option<&str>,
+        ssekms_key_id: Option<&str>,
+        object_metadata: &HashMap<String, String>,
+        custom_headers: &[(String, String)],
+    ) -> Result<S3HttpRequest, S3HttpRequestError> {
+        let mut request = self.inner.new_request(method, bucket, key, query)?;
+        if let Some(storage_class) = storage_class {
+            request.set_header("x-amz-storage-class", storage_class)?;
+        }
+        if let Some(sse) = server_side_encryption {
+            request.set_header(SSE_TYPE_HEADER_NAME, sse)?;
+        }
+        if let Some(key_id) = ssekms_key_id {
+            request.set_header(SSE_KEY_ID_HEADER_NAME, key_id)?;
+        }
+        for (name, value) in object_metadata {
+            request.set_header(&format!("x-amz-meta-{}", name), value)?;
+        }
+        for (name, value) in custom_headers {
+            request.add_header(name, value)?;
+        }
+        Ok(request)
+    }
+}
+
+/// A part that has been uploaded to S3 as part of an [S3HttpPutObjectRequest].
+#[derive(Debug)]
+struct UploadedPart {
+    etag: String,
+    checksum: Option<String>,
+    size: u64,
+}
+
+/// An in-progress streaming PutObject request to S3.
+///
+/// You can write to or complete the upload using the [`PutObjectRequest`] implementation on this
+/// object. If the request is dropped before completing, the multi-part upload is aborted.
+#[derive(Debug)]
+pub struct S3HttpPutObjectRequest {
+    client: S3HttpClient,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    trailing_checksums: PutObjectTrailingChecksums,
+    span: tracing::Span,
+    /// Data written but not yet uploaded, always smaller than a part after a successful write
+    buffer: Vec<u8>,
+    parts: Vec<UploadedPart>,
+    start_time: Instant,
+    total_bytes: u64,
+    state: S3HttpPutObjectRequestState,
+}
+
+/// Internal state for a [S3HttpPutObjectRequest].
+#[derive(Debug, PartialEq, Eq)]
+enum S3HttpPutObjectRequestState {
+    /// A write operation is in progress or was interrupted before completion.
+    PendingWrite,
+    /// Idle state between write calls.
+    Idle,
+    /// The upload was completed or aborted.
+    Finished,
+}
+
+impl S3HttpPutObjectRequest {
+    /// The number of bytes written to this request so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.total_bytes
+    }
+
+    async fn upload_part(
+        &mut self,
+        data: Vec<u8>,
+    ) -> Result<(), ObjectClientError<PutObjectError, S3HttpRequestError>> {
+        let part_number = format!("{}", self.parts.len() + 1);
+        let mut request = self.client.inner.new_request(
+            Method::PUT,
+            &self.bucket,
+            &self.key,
+            &[("partNumber", &part_number), ("uploadId", &self.upload_id)],
+        )?;
+
+        let checksum = match self.trailing_checksums {
+            PutObjectTrailingChecksums::Disabled => None,
+            PutObjectTrailingChecksums::Enabled | PutObjectTrailingChecksums::ReviewOnly => {
+                Some(crc32c_to_base64(&crc32c::checksum(&data)))
+            }
+        };
+        if let (Some(checksum), PutObjectTrailingChecksums::Enabled) = (&checksum, self.trailing_checksums) {
+            request.set_header(CHECKSUM_CRC32C_HEADER_NAME, checksum)?;
+        }
+        let size = data.len() as u64;
+        request.body = data.into();
+
+        let (headers, _) = self
+            .client
+            .make_simple_request(request, "UploadPart", self.span.clone(), parse_put_object_error)
+            .await?;
+        let etag = header_str(&headers, ETAG_HEADER_NAME)
+            .ok_or_else(|| S3HttpRequestError::InternalError("UploadPart response has no ETag".into()))?;
+        self.parts.push(UploadedPart {
+            etag: etag.to_owned(),
+            checksum,
+            size,
+        });
+        Ok(())
+    }
+
+    fn complete_request_body(&self) -> Vec<u8> {
+        fn text_element(name: &str, text: &str) -> XMLNode {
+            let mut element = Element::new(name);
+            element.children.push(XMLNode::Text(text.to_owned()));
+            XMLNode::Element(element)
+        }
+
+        let mut root = Element::new("CompleteMultipartUpload");
+        for (i, part) in self.parts.iter().enumerate() {
+            let mut element = Element::new("Part");
+            element.children.push(text_element("PartNumber", &format!("{}", i + 1)));
+            element.children.push(text_element("ETag", &part.etag));
+            if let (Some(checksum), PutObjectTrailingChecksums::Enabled) = (&part.checksum, self.trailing_checksums) {
+                element.children.push(text_element("ChecksumCRC32C", checksum));
+            }
+            root.children.push(XMLNode::Element(element));
+        }
+        let mut body = Vec::new();
+        root.write(&mut body).expect("writing to a Vec cannot fail");
+        body
+    }
+
+    fn abort_request(&self) -> Result<S3HttpRequest, S3HttpRequestError> {
+        self.client.inner.new_request(
+            Method::DELETE,
+            &self.bucket,
+            &self.key,
+            &[("uploadId", &self.upload_id)],
+        )
+    }
+}
+
+#[cfg_attr(not(docsrs), async_trait)]
+impl PutObjectRequest for S3HttpPutObjectRequest {
+    type ClientError = S3HttpRequestError;
+
+    async fn write(&mut self, slice: &[u8]) -> ObjectClientResult<(), PutObjectError, Self::ClientError> {
+        // Fail if a previous write was not completed or the upload has already failed.
+        if self.state != S3HttpPutObjectRequestState::Idle {
+            return Err(S3HttpRequestError::RequestCanceled.into());
+        }
+        self.state = S3HttpPutObjectRequestState::PendingWrite;
+
+        let part_size = self.client.inner.write_part_size;
+        self.buffer.extend_from_slice(slice);
+        self.total_bytes += slice.len() as u64;
+        while self.buffer.len() >= part_size {
+            let rest = self.buffer.split_off(part_size);
+            let part = std::mem::replace(&mut self.buffer, rest);
+            self.upload_part(part).await?;
+        }
+
+        // Write completed with no errors, we can reset to `Idle`.
+        self.state = S3HttpPutObjectRequestState::Idle;
+        Ok(())
+    }
+
+    async fn complete(self) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        self.review_and_complete(|_| true).await
+    }
+
+    async fn review_and_complete(
+        mut self,
+        review_callback: impl FnOnce(UploadReview) -> bool + Send + 'static,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        if self.state != S3HttpPutObjectRequestState::Idle {
+            return Err(S3HttpRequestError::RequestCanceled.into());
+        }
+        self.state = S3HttpPutObjectRequestState::PendingWrite;
+
+        // S3 requires at least one part, so upload an empty one for empty objects.
+        if !self.buffer.is_empty() || self.parts.is_empty() {
+            let part = std::mem::take(&mut self.buffer);
+            self.upload_part(part).await?;
+        }
+
+        let review = UploadReview {
+            checksum_algorithm: (self.trailing_checksums != PutObjectTrailingChecksums::Disabled)
+                .then_some(ChecksumAlgorithm::Crc32c),
+            parts: self
+                .parts
+                .iter()
+                .map(|part| UploadReviewPart {
+                    size: part.size,
+                    checksum: part.checksum.clone(),
+                })
+                .collect(),
+        };
+        if !review_callback(review) {
+            // Leave the state as `PendingWrite` so that `Drop` aborts the upload.
+            self.span.in_scope(|| debug!("upload review failed, aborting"));
+            return Err(S3HttpRequestError::RequestCanceled.into());
+        }
+
+        let mut request =
+            self.client
+                .inner
+                .new_request(Method::POST, &self.bucket, &self.key, &[("uploadId", &self.upload_id)])?;
+        request.body = self.complete_request_body().into();
+
+        let (headers, body) = self
+            .client
+            .make_simple_request(
+                request,
+                "CompleteMultipartUpload",
+                self.span.clone(),
+                parse_put_object_error,
+            )
+            .await?;
+        self.state = S3HttpPutObjectRequestState::Finished;
+
+        emit_throughput_metric(self.total_bytes, self.start_time.elapsed(), "put_object");
+
+        // CompleteMultipartUpload can fail after returning a 200 status, in which case the body is
+        // an error document rather than the result.
+        let root = Element::parse(body.as_ref()).map_err(|e| S3HttpRequestError::InternalError(e.into()))?;
+        if root.name == "Error" {
+            return Err(S3HttpRequestError::ResponseError(Box::new(ErrorResponse {
+                status: 200,
+                headers,
+                body,
+            }))
+            .into());
+        }
+        let etag = root
+            .get_child("ETag")
+            .and_then(|e| e.get_text())
+            .map(|s| s.into_owned());
+        extract_result(&headers, etag)
+    }
+}
+
+impl Drop for S3HttpPutObjectRequest {
+    fn drop(&mut self) {
+        if self.state == S3HttpPutObjectRequestState::Finished {
+            return;
+        }
+        let Ok(request) = self.abort_request() else {
+            return;
+        };
+        let inner = self.client.inner.clone();
+        let span = self.span.clone();
+        self.client.inner.handle.spawn(
+            async move {
+                if let Err(error) = inner.send(&request, "AbortMultipartUpload").await {
+                    debug!(?error, "failed to abort multi-part upload");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+/// Build a [PutObjectResult] from the response headers. `etag` overrides the ETag header, for
+/// responses that return it in the body instead.
+fn extract_result(
+    headers: &HeaderMap,
+    etag: Option<String>,
+) -> ObjectClientResult<PutObjectResult, PutObjectError, S3HttpRequestError> {
+    let etag = etag
+        .or_else(|| header_str(headers, ETAG_HEADER_NAME).map(ToOwned::to_owned))
+        .ok_or_else(|| S3HttpRequestError::InternalError("PutObject response has no ETag".into()))?;
+    Ok(PutObjectResult {
+        etag: etag.into(),
+        sse_type: header_str(headers, SSE_TYPE_HEADER_NAME).map(ToOwned::to_owned),
+        sse_kms_key_id: header_str(headers, SSE_KEY_ID_HEADER_NAME).map(ToOwned::to_owned),
+    })
+}
+
+fn parse_put_object_error(response: &ErrorResponse) -> Option<PutObjectError> {
+    match response.status {
+        404 => match response.error_code()?.as_str() {
+            "NoSuchBucket" => Some(PutObjectError::NoSuchBucket),
+            _ => None,
+        },
+        _ => None,
+    }
+}
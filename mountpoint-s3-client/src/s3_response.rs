@@ -0,0 +1,24 @@
+//! Parsing and metrics for S3 responses that are shared by every client implementation, independent of
+//! the HTTP stack that made the request.
+
+use std::time::Duration;
+
+pub(crate) mod get_object_attributes;
+pub(crate) mod list_objects;
+pub(crate) mod restore;
+
+/// Record a throughput metric for GET/PUT. We can't inline this into the S3CrtClient callbacks
+/// because PUT bytes don't transit those callbacks.
+pub(crate) fn emit_throughput_metric(bytes: u64, duration: Duration, op: &'static str) {
+    let throughput_mbps = bytes as f64 / 1024.0 / 1024.0 / duration.as_secs_f64();
+    // Semi-arbitrary choices here to avoid averaging out large and small requests
+    const MEGABYTE: u64 = 1024 * 1024;
+    let bucket = if bytes < MEGABYTE {
+        "<1MiB"
+    } else if bytes <= 16 * MEGABYTE {
+        "1-16MiB"
+    } else {
+        ">16MiB"
+    };
+    metrics::histogram!("s3.meta_requests.throughput_mibs", "op" => op, "size" => bucket).record(throughput_mbps);
+}
@@ -0,0 +1,170 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::object_client::{Checksum, GetObjectAttributesParts, GetObjectAttributesResult, ObjectPart};
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error("XML response was not valid: problem = {1}, xml node = {0:?}")]
+    InvalidResponse(xmltree::Element, String),
+
+    #[error("XML parsing error: {0:?}")]
+    Xml(#[from] xmltree::ParseError),
+
+    #[error("Missing field {1} from XML element {0:?}")]
+    MissingField(xmltree::Element, String),
+}
+
+impl GetObjectAttributesResult {
+    pub(crate) fn parse_from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Self::parse_from_xml(&mut xmltree::Element::parse(bytes)?)
+    }
+
+    fn parse_from_xml(element: &mut xmltree::Element) -> Result<Self, ParseError> {
+        let etag = get_field_or_none(element, "ETag")?;
+        let storage_class = get_field_or_none(element, "StorageClass")?;
+        let object_size = get_field_or_none(element, "ObjectSize")?;
+
+        let mut checksum = None;
+        if let Some(checksum_elem) = element.take_child("Checksum") {
+            checksum = Some(Self::parse_checksums(&checksum_elem)?);
+        }
+
+        let mut object_parts = None;
+        if let Some(mut object_parts_elem) = element.take_child("ObjectParts") {
+            let is_truncated = get_field_or_none(&object_parts_elem, "IsTruncated")?;
+            let max_parts = get_field_or_none(&object_parts_elem, "MaxParts")?;
+            let next_part_number_marker = get_field_or_none(&object_parts_elem, "NextPartNumberMarker")?;
+            let part_number_marker = get_field_or_none(&object_parts_elem, "PartNumberMarker")?;
+            let total_parts_count = get_field_or_none(&object_parts_elem, "PartsCount")?;
+
+            let mut parts = Vec::new();
+            while let Some(part_elem) = object_parts_elem.take_child("Part") {
+                let checksum_inner = Self::parse_checksums(&part_elem)?;
+
+                let part_number = get_field(&part_elem, "PartNumber")?;
+                let size = get_field(&part_elem, "Size")?;
+
+                let part = ObjectPart {
+                    checksum: Some(checksum_inner),
+                    part_number: part_number.parse().unwrap(),
+                    size: size.parse().unwrap(),
+                };
+                parts.push(part);
+            }
+
+            object_parts = Some(GetObjectAttributesParts {
+                is_truncated,
+                max_parts,
+                next_part_number_marker,
+                part_number_marker,
+                parts: (!parts.is_empty()).then_some(parts),
+                total_parts_count,
+            });
+        }
+
+        Ok(Self {
+            etag,
+            checksum,
+            object_parts,
+            storage_class,
+            object_size,
+        })
+    }
+
+    fn parse_checksums(element: &xmltree::Element) -> Result<Checksum, ParseError> {
+        let checksum_crc32 = get_field_or_none(element, "ChecksumCRC32")?;
+        let checksum_crc32c = get_field_or_none(element, "ChecksumCRC32C")?;
+        let checksum_sha1 = get_field_or_none(element, "ChecksumSHA1")?;
+        let checksum_sha256 = get_field_or_none(element, "ChecksumSHA256")?;
+
+        Ok(Checksum {
+            checksum_crc32,
+            checksum_crc32c,
+            checksum_sha1,
+            checksum_sha256,
+        })
+    }
+}
+
+/// Copy text out of an XML element, with the right error type.
+fn get_text(element: &xmltree::Element) -> Result<String, ParseError> {
+    Ok(element
+        .get_text()
+        .ok_or_else(|| ParseError::InvalidResponse(element.clone(), "field has no text".to_owned()))?
+        .to_string())
+}
+
+/// Wrapper to get child with some name out of an XML element, with the right error type.
+fn get_child<'a>(element: &'a xmltree::Element, name: &str) -> Result<&'a xmltree::Element, ParseError> {
+    element
+        .get_child(name)
+        .ok_or_else(|| ParseError::MissingField(element.clone(), name.to_string()))
+}
+
+/// Get the text out of a child node, with the right error type.
+fn get_field(element: &xmltree::Element, name: &str) -> Result<String, ParseError> {
+    get_text(get_child(element, name)?)
+}
+
+/// Get the value out of a child node, return [None] if the child node is missing.
+fn get_field_or_none<T: FromStr>(element: &xmltree::Element, name: &str) -> Result<Option<T>, ParseError> {
+    match get_field(element, name) {
+        Ok(str) => str
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| ParseError::InvalidResponse(element.clone(), "failed to parse field from string".to_owned())),
+        Err(ParseError::MissingField(_, _)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_string() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><GetObjectAttributesResponse xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><ETag>fc3ff98e8c6a0d3087d515c0473f8677</ETag><IsTruncated>false</IsTruncated><ObjectSize>1024</ObjectSize></GetObjectAttributesResponse>"#;
+        let result: String = get_field_or_none(&xmltree::Element::parse(&body[..]).unwrap(), "ETag")
+            .unwrap()
+            .unwrap();
+        assert_eq!(&result, "fc3ff98e8c6a0d3087d515c0473f8677");
+    }
+
+    #[test]
+    fn get_boolean() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><GetObjectAttributesResponse xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><ETag>fc3ff98e8c6a0d3087d515c0473f8677</ETag><IsTruncated>false</IsTruncated><ObjectSize>1024</ObjectSize></GetObjectAttributesResponse>"#;
+        let result: bool = get_field_or_none(&xmltree::Element::parse(&body[..]).unwrap(), "IsTruncated")
+            .unwrap()
+            .unwrap();
+        assert!(!result);
+    }
+
+    #[test]
+    fn get_integer() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><GetObjectAttributesResponse xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><ETag>fc3ff98e8c6a0d3087d515c0473f8677</ETag><IsTruncated>false</IsTruncated><ObjectSize>1024</ObjectSize></GetObjectAttributesResponse>"#;
+        let result: usize = get_field_or_none(&xmltree::Element::parse(&body[..]).unwrap(), "ObjectSize")
+            .unwrap()
+            .unwrap();
+        assert_eq!(result, 1024);
+    }
+
+    #[test]
+    fn get_none() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><GetObjectAttributesResponse xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><ETag>fc3ff98e8c6a0d3087d515c0473f8677</ETag><IsTruncated>false</IsTruncated><ObjectSize>1024</ObjectSize></GetObjectAttributesResponse>"#;
+        let result: Option<usize> =
+            get_field_or_none(&xmltree::Element::parse(&body[..]).unwrap(), "PartNumber").unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn get_parse_error() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><GetObjectAttributesResponse xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><ETag>fc3ff98e8c6a0d3087d515c0473f8677</ETag><IsTruncated>false</IsTruncated><ObjectSize>1024</ObjectSize></GetObjectAttributesResponse>"#;
+        let result: ParseError =
+            get_field_or_none::<usize>(&xmltree::Element::parse(&body[..]).unwrap(), "IsTruncated").unwrap_err();
+        assert!(result.to_string().contains("failed to parse field from string"));
+    }
+}
@@ -0,0 +1,47 @@
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use thiserror::Error;
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+
+use crate::object_client::RestoreStatus;
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error("Header x-amz-restore is invalid: {0:?}")]
+    Invalid(String),
+
+    #[error("Failed to parse field {1} as OffsetDateTime: {0:?}")]
+    OffsetDateTime(#[source] time::error::Parse, String),
+}
+
+lazy_static! {
+    // Example: ongoing-request="true"
+    static ref RESTORE_IN_PROGRESS_RE: Regex = Regex::new(r#"^ongoing-request="(?<ongoing>[^"]*)"$"#).unwrap();
+
+    // Example: ongoing-request="false", expiry-date="Fri, 21 Dec 2012 00:00:00 GMT"
+    static ref RESTORE_DONE_RE: Regex =
+        Regex::new(r#"^ongoing-request="[^"]*",\s*expiry-date="(?<expiry>[^"]*)"$"#).unwrap();
+}
+
+/// Parse the value of an `x-amz-restore` header.
+pub(crate) fn parse_restore_header(header: String) -> Result<RestoreStatus, ParseError> {
+    if let Some(caps) = RESTORE_IN_PROGRESS_RE.captures(&header) {
+        let ongoing = bool::from_str(&caps["ongoing"]).map_err(|_| ParseError::Invalid(header.clone()))?;
+        return if ongoing {
+            Ok(RestoreStatus::InProgress)
+        } else {
+            Err(ParseError::Invalid(header.clone()))
+        };
+    };
+
+    let Some(caps) = RESTORE_DONE_RE.captures(&header) else {
+        return Err(ParseError::Invalid(header));
+    };
+    let expiry = OffsetDateTime::parse(&caps["expiry"], &Rfc2822)
+        .map_err(|e| ParseError::OffsetDateTime(e, "x-amz-restore::expiry".into()))?;
+    Ok(RestoreStatus::Restored { expiry: expiry.into() })
+}
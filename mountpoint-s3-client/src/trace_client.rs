@@ -0,0 +1,1589 @@
+//! Object clients that record requests to a trace file and replay them from it.
+//!
+//! [RecordingClient] wraps another [ObjectClient] and writes every request it forwards, together
+//! with the response, to a trace as one line of JSON per request. [ReplayClient] reads a trace and
+//! serves the recorded responses, so that an application's interaction with S3 can be reproduced
+//! offline and deterministically.
+//!
+//! Object bodies can be recorded in full, as a CRC32C checksum per part, or not at all (see
+//! [TraceBodies]). When replaying a trace without full bodies, GetObject returns zeroes with the
+//! same part offsets and lengths as the recorded response.
+//!
+//! Replayed requests are matched to recorded ones by operation and arguments, but not by bucket
+//! name, so a trace can be replayed against a differently named bucket. Repeated identical
+//! requests receive the recorded responses in order, and once those run out, the last response is
+//! repeated. Requests that were never recorded fail with [ReplayClientError::NotRecorded].
+
+#![cfg(feature = "trace")]
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use base64ct::{Base64, Encoding};
+use futures::Stream;
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::warn;
+
+use crate::checksums::{crc32c, crc32c_to_base64};
+use crate::error_metadata::{ClientErrorMetadata, ProvideErrorMetadata};
+use crate::object_client::{
+    BufferPoolUsageStats, Checksum, ChecksumAlgorithm, DeleteObjectError, DeleteObjectResult, ETag, GetBodyPart,
+    GetObjectAttributesError, GetObjectAttributesParts, GetObjectAttributesResult, GetObjectError, GetObjectRequest,
+    HeadObjectError, HeadObjectResult, ListObjectsError, ListObjectsResult, ObjectAttribute, ObjectClient,
+    ObjectClientError, ObjectClientResult, ObjectInfo, ObjectPart, PutObjectError, PutObjectParams, PutObjectRequest,
+    PutObjectResult, PutObjectSingleParams, PutObjectTrailingChecksums, RestoreStatus, UploadReview, UploadReviewPart,
+};
+
+/// Part size for replayed uploads, if the trace doesn't say what the recorded client used
+const DEFAULT_REPLAY_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// How much of each object body to record in a trace
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TraceBodies {
+    /// Record only the offset and length of each part of a body
+    #[default]
+    None,
+    /// Also record a CRC32C checksum of each part
+    Checksums,
+    /// Record the body data itself, so that it can be replayed
+    Full,
+}
+
+/// A single line of a trace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+enum TraceEvent {
+    /// Configuration of the recorded client. Always the first line of a trace.
+    Header {
+        read_part_size: Option<usize>,
+        write_part_size: Option<usize>,
+        initial_read_window_size: Option<usize>,
+    },
+    DeleteObject {
+        bucket: String,
+        key: String,
+        result: Result<(), TracedError>,
+    },
+    GetObject {
+        bucket: String,
+        key: String,
+        range: Option<Range<u64>>,
+        if_match: Option<String>,
+        result: Result<TracedBody, TracedError>,
+    },
+    HeadObject {
+        bucket: String,
+        key: String,
+        result: Result<TracedObjectInfo, TracedError>,
+    },
+    ListObjects {
+        bucket: String,
+        /// Only set for [ObjectClient::list_objects_after] requests
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start_after: Option<String>,
+        continuation_token: Option<String>,
+        delimiter: String,
+        max_keys: usize,
+        prefix: String,
+        result: Result<TracedListObjectsResult, TracedError>,
+    },
+    PutObject {
+        bucket: String,
+        key: String,
+        /// The data written to the upload, or `None` if the upload couldn't be created, in which
+        /// case `result` is the error from creating it.
+        body: Option<TracedPart>,
+        /// The result of completing the upload, or the first error it returned
+        result: Result<TracedPutObjectResult, TracedError>,
+    },
+    PutObjectSingle {
+        bucket: String,
+        key: String,
+        body: TracedPart,
+        result: Result<TracedPutObjectResult, TracedError>,
+    },
+    GetObjectAttributes {
+        bucket: String,
+        key: String,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: Vec<String>,
+        result: Result<TracedObjectAttributes, TracedError>,
+    },
+}
+
+/// An error returned by the recorded client. Service errors are recorded by variant name, and
+/// client errors by their message, since client errors are specific to each client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TracedError {
+    ServiceError(String),
+    ClientError(String),
+}
+
+impl TracedError {
+    fn new<E: Debug, C: std::error::Error>(error: &ObjectClientError<E, C>) -> Self {
+        match error {
+            ObjectClientError::ServiceError(e) => Self::ServiceError(format!("{e:?}")),
+            ObjectClientError::ClientError(e) => Self::ClientError(e.to_string()),
+        }
+    }
+
+    fn into_error<E: ServiceErrorName>(self) -> ObjectClientError<E, ReplayClientError> {
+        match self {
+            Self::ServiceError(name) => match E::from_name(&name) {
+                Some(e) => ObjectClientError::ServiceError(e),
+                None => ObjectClientError::ClientError(ReplayClientError::UnknownServiceError(name)),
+            },
+            Self::ClientError(message) => ObjectClientError::ClientError(ReplayClientError::Recorded(message)),
+        }
+    }
+}
+
+/// Service errors that can be reconstructed from the variant names recorded in a trace
+trait ServiceErrorName: Sized {
+    fn from_name(name: &str) -> Option<Self>;
+}
+
+impl ServiceErrorName for DeleteObjectError {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "NoSuchBucket" => Some(Self::NoSuchBucket),
+            _ => None,
+        }
+    }
+}
+
+impl ServiceErrorName for GetObjectError {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "NoSuchBucket" => Some(Self::NoSuchBucket),
+            "NoSuchKey" => Some(Self::NoSuchKey),
+            "PreconditionFailed" => Some(Self::PreconditionFailed),
+            _ => None,
+        }
+    }
+}
+
+impl ServiceErrorName for HeadObjectError {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "NotFound" => Some(Self::NotFound),
+            _ => None,
+        }
+    }
+}
+
+impl ServiceErrorName for ListObjectsError {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "NoSuchBucket" => Some(Self::NoSuchBucket),
+            "StartAfterNotSupported" => Some(Self::StartAfterNotSupported),
+            _ => None,
+        }
+    }
+}
+
+impl ServiceErrorName for PutObjectError {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "NoSuchBucket" => Some(Self::NoSuchBucket),
+            _ => None,
+        }
+    }
+}
+
+impl ServiceErrorName for GetObjectAttributesError {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "NoSuchBucket" => Some(Self::NoSuchBucket),
+            "NoSuchKey" => Some(Self::NoSuchKey),
+            _ => None,
+        }
+    }
+}
+
+/// Record the result of a request, using `f` to convert a successful response
+fn traced<T, U, E: Debug, C: std::error::Error>(
+    result: &ObjectClientResult<T, E, C>,
+    f: impl FnOnce(&T) -> U,
+) -> Result<U, TracedError> {
+    result.as_ref().map(f).map_err(TracedError::new)
+}
+
+/// The body of a GetObject response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TracedBody {
+    parts: Vec<TracedPart>,
+    /// The error that ended the body stream early, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<TracedError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TracedPart {
+    offset: u64,
+    len: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crc32c: Option<String>,
+    /// Base64-encoded data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+}
+
+impl TracedPart {
+    fn new(offset: u64, data: &[u8], bodies: TraceBodies) -> Self {
+        Self {
+            offset,
+            len: data.len() as u64,
+            crc32c: (bodies == TraceBodies::Checksums).then(|| crc32c_to_base64(&crc32c::checksum(data))),
+            data: (bodies == TraceBodies::Full).then(|| Base64::encode_string(data)),
+        }
+    }
+
+    /// The recorded data, or zeroes if the trace doesn't include it
+    fn data(&self) -> Result<Box<[u8]>, ReplayClientError> {
+        match &self.data {
+            Some(data) => {
+                let data = Base64::decode_vec(data).map_err(|_| ReplayClientError::InvalidBody)?;
+                if data.len() as u64 != self.len {
+                    return Err(ReplayClientError::InvalidBody);
+                }
+                Ok(data.into_boxed_slice())
+            }
+            None => Ok(vec![0u8; self.len as usize].into_boxed_slice()),
+        }
+    }
+}
+
+/// Accumulates the data written to an upload into a single [TracedPart]
+#[derive(Debug)]
+struct BodyRecorder {
+    bodies: TraceBodies,
+    len: u64,
+    hasher: crc32c::Hasher,
+    data: Vec<u8>,
+}
+
+impl BodyRecorder {
+    fn new(bodies: TraceBodies) -> Self {
+        Self {
+            bodies,
+            len: 0,
+            hasher: crc32c::Hasher::new(),
+            data: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.len += data.len() as u64;
+        match self.bodies {
+            TraceBodies::None => {}
+            TraceBodies::Checksums => self.hasher.update(data),
+            TraceBodies::Full => self.data.extend_from_slice(data),
+        }
+    }
+
+    fn finish(self) -> TracedPart {
+        TracedPart {
+            offset: 0,
+            len: self.len,
+            crc32c: (self.bodies == TraceBodies::Checksums).then(|| crc32c_to_base64(&self.hasher.finalize())),
+            data: (self.bodies == TraceBodies::Full).then(|| Base64::encode_string(&self.data)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TracedObjectInfo {
+    key: String,
+    size: u64,
+    /// RFC 3339 timestamp
+    last_modified: String,
+    storage_class: Option<String>,
+    restore_status: Option<TracedRestoreStatus>,
+    etag: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TracedRestoreStatus {
+    InProgress,
+    Restored { expiry: SystemTime },
+}
+
+impl From<&ObjectInfo> for TracedObjectInfo {
+    fn from(info: &ObjectInfo) -> Self {
+        Self {
+            key: info.key.clone(),
+            size: info.size,
+            last_modified: info.last_modified.format(&Rfc3339).unwrap_or_default(),
+            storage_class: info.storage_class.clone(),
+            restore_status: info.restore_status.map(|status| match status {
+                RestoreStatus::InProgress => TracedRestoreStatus::InProgress,
+                RestoreStatus::Restored { expiry } => TracedRestoreStatus::Restored { expiry },
+            }),
+            etag: info.etag.clone(),
+        }
+    }
+}
+
+impl From<TracedObjectInfo> for ObjectInfo {
+    fn from(info: TracedObjectInfo) -> Self {
+        Self {
+            key: info.key,
+            size: info.size,
+            last_modified: OffsetDateTime::parse(&info.last_modified, &Rfc3339).unwrap_or(OffsetDateTime::UNIX_EPOCH),
+            storage_class: info.storage_class,
+            restore_status: info.restore_status.map(|status| match status {
+                TracedRestoreStatus::InProgress => RestoreStatus::InProgress,
+                TracedRestoreStatus::Restored { expiry } => RestoreStatus::Restored { expiry },
+            }),
+            etag: info.etag,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TracedListObjectsResult {
+    objects: Vec<TracedObjectInfo>,
+    common_prefixes: Vec<String>,
+    next_continuation_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TracedPutObjectResult {
+    etag: String,
+    sse_type: Option<String>,
+    sse_kms_key_id: Option<String>,
+}
+
+impl From<&PutObjectResult> for TracedPutObjectResult {
+    fn from(result: &PutObjectResult) -> Self {
+        Self {
+            etag: result.etag.as_str().to_owned(),
+            sse_type: result.sse_type.clone(),
+            sse_kms_key_id: result.sse_kms_key_id.clone(),
+        }
+    }
+}
+
+impl From<TracedPutObjectResult> for PutObjectResult {
+    fn from(result: TracedPutObjectResult) -> Self {
+        Self {
+            etag: ETag::from(result.etag),
+            sse_type: result.sse_type,
+            sse_kms_key_id: result.sse_kms_key_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TracedObjectAttributes {
+    etag: Option<String>,
+    checksum: Option<TracedChecksum>,
+    object_parts: Option<TracedObjectAttributesParts>,
+    storage_class: Option<String>,
+    object_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TracedChecksum {
+    checksum_crc32: Option<String>,
+    checksum_crc32c: Option<String>,
+    checksum_sha1: Option<String>,
+    checksum_sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TracedObjectAttributesParts {
+    is_truncated: Option<bool>,
+    max_parts: Option<usize>,
+    next_part_number_marker: Option<usize>,
+    part_number_marker: Option<usize>,
+    parts: Option<Vec<TracedObjectPart>>,
+    total_parts_count: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TracedObjectPart {
+    checksum: Option<TracedChecksum>,
+    part_number: usize,
+    size: usize,
+}
+
+impl From<&Checksum> for TracedChecksum {
+    fn from(checksum: &Checksum) -> Self {
+        Self {
+            checksum_crc32: checksum.checksum_crc32.clone(),
+            checksum_crc32c: checksum.checksum_crc32c.clone(),
+            checksum_sha1: checksum.checksum_sha1.clone(),
+            checksum_sha256: checksum.checksum_sha256.clone(),
+        }
+    }
+}
+
+impl From<TracedChecksum> for Checksum {
+    fn from(checksum: TracedChecksum) -> Self {
+        Self {
+            checksum_crc32: checksum.checksum_crc32,
+            checksum_crc32c: checksum.checksum_crc32c,
+            checksum_sha1: checksum.checksum_sha1,
+            checksum_sha256: checksum.checksum_sha256,
+        }
+    }
+}
+
+impl From<&GetObjectAttributesResult> for TracedObjectAttributes {
+    fn from(result: &GetObjectAttributesResult) -> Self {
+        Self {
+            etag: result.etag.clone(),
+            checksum: result.checksum.as_ref().map(Into::into),
+            object_parts: result.object_parts.as_ref().map(|parts| TracedObjectAttributesParts {
+                is_truncated: parts.is_truncated,
+                max_parts: parts.max_parts,
+                next_part_number_marker: parts.next_part_number_marker,
+                part_number_marker: parts.part_number_marker,
+                parts: parts.parts.as_ref().map(|parts| {
+                    parts
+                        .iter()
+                        .map(|part| TracedObjectPart {
+                            checksum: part.checksum.as_ref().map(Into::into),
+                            part_number: part.part_number,
+                            size: part.size,
+                        })
+                        .collect()
+                }),
+                total_parts_count: parts.total_parts_count,
+            }),
+            storage_class: result.storage_class.clone(),
+            object_size: result.object_size,
+        }
+    }
+}
+
+impl From<TracedObjectAttributes> for GetObjectAttributesResult {
+    fn from(result: TracedObjectAttributes) -> Self {
+        Self {
+            etag: result.etag,
+            checksum: result.checksum.map(Into::into),
+            object_parts: result.object_parts.map(|parts| GetObjectAttributesParts {
+                is_truncated: parts.is_truncated,
+                max_parts: parts.max_parts,
+                next_part_number_marker: parts.next_part_number_marker,
+                part_number_marker: parts.part_number_marker,
+                parts: parts.parts.map(|parts| {
+                    parts
+                        .into_iter()
+                        .map(|part| ObjectPart {
+                            checksum: part.checksum.map(Into::into),
+                            part_number: part.part_number,
+                            size: part.size,
+                        })
+                        .collect()
+                }),
+                total_parts_count: parts.total_parts_count,
+            }),
+            storage_class: result.storage_class,
+            object_size: result.object_size,
+        }
+    }
+}
+
+/// Serializes trace events to the trace output, one per line
+struct TraceWriter {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl TraceWriter {
+    fn write(&self, event: &TraceEvent) {
+        let mut line = serde_json::to_vec(event).expect("trace events are serializable");
+        line.push(b'\n');
+        let mut out = self.out.lock().unwrap();
+        // Failing to write the trace shouldn't fail the request being traced
+        if let Err(e) = out.write_all(&line).and_then(|()| out.flush()) {
+            warn!(error = ?e, "failed to write trace event");
+        }
+    }
+}
+
+/// An [ObjectClient] that forwards requests to another client and records them, together with
+/// their responses, to a trace.
+pub struct RecordingClient<Client: ObjectClient> {
+    client: Client,
+    trace: Arc<TraceWriter>,
+    bodies: TraceBodies,
+}
+
+impl<Client: ObjectClient + Clone> Clone for RecordingClient<Client> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            trace: self.trace.clone(),
+            bodies: self.bodies,
+        }
+    }
+}
+
+impl<Client: ObjectClient> RecordingClient<Client> {
+    /// Create a new [RecordingClient] that forwards requests to `client` and writes a trace of them
+    /// to `out`
+    pub fn new(client: Client, out: impl Write + Send + 'static, bodies: TraceBodies) -> Self {
+        let trace = Arc::new(TraceWriter {
+            out: Mutex::new(Box::new(out)),
+        });
+        trace.write(&TraceEvent::Header {
+            read_part_size: client.read_part_size(),
+            write_part_size: client.write_part_size(),
+            initial_read_window_size: client.initial_read_window_size(),
+        });
+        Self { client, trace, bodies }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_list_objects(
+        &self,
+        bucket: &str,
+        start_after: Option<&str>,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+        result: &ObjectClientResult<ListObjectsResult, ListObjectsError, Client::ClientError>,
+    ) {
+        self.trace.write(&TraceEvent::ListObjects {
+            bucket: bucket.to_owned(),
+            start_after: start_after.map(ToOwned::to_owned),
+            continuation_token: continuation_token.map(ToOwned::to_owned),
+            delimiter: delimiter.to_owned(),
+            max_keys,
+            prefix: prefix.to_owned(),
+            result: traced(result, |result| TracedListObjectsResult {
+                objects: result.objects.iter().map(Into::into).collect(),
+                common_prefixes: result.common_prefixes.clone(),
+                next_continuation_token: result.next_continuation_token.clone(),
+            }),
+        });
+    }
+}
+
+#[cfg_attr(not(docsrs), async_trait)]
+impl<Client> ObjectClient for RecordingClient<Client>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+{
+    type GetObjectRequest = RecordingGetObjectRequest<Client>;
+    type PutObjectRequest = RecordingPutObjectRequest<Client>;
+    type ClientError = Client::ClientError;
+
+    fn read_part_size(&self) -> Option<usize> {
+        self.client.read_part_size()
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
+        self.client.write_part_size()
+    }
+
+    fn initial_read_window_size(&self) -> Option<usize> {
+        self.client.initial_read_window_size()
+    }
+
+    fn mem_usage_stats(&self) -> Option<BufferPoolUsageStats> {
+        self.client.mem_usage_stats()
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        let result = self.client.delete_object(bucket, key).await;
+        self.trace.write(&TraceEvent::DeleteObject {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            result: traced(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        range: Option<Range<u64>>,
+        if_match: Option<ETag>,
+    ) -> ObjectClientResult<Self::GetObjectRequest, GetObjectError, Self::ClientError> {
+        let result = self
+            .client
+            .get_object(bucket, key, range.clone(), if_match.clone())
+            .await;
+        let mut record = GetObjectRecord {
+            trace: self.trace.clone(),
+            bodies: self.bodies,
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            range,
+            if_match: if_match.map(ETag::into_inner),
+            body: Some(Default::default()),
+        };
+        match result {
+            Ok(request) => Ok(RecordingGetObjectRequest { request, record }),
+            Err(e) => {
+                record.finish(Err(TracedError::new(&e)));
+                Err(e)
+            }
+        }
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        let result = self.client.head_object(bucket, key).await;
+        self.trace.write(&TraceEvent::HeadObject {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            result: traced(&result, |result| (&result.object).into()),
+        });
+        result
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        let result = self
+            .client
+            .list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
+            .await;
+        self.write_list_objects(bucket, None, continuation_token, delimiter, max_keys, prefix, &result);
+        result
+    }
+
+    async fn list_objects_after(
+        &self,
+        bucket: &str,
+        start_after: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        let result = self
+            .client
+            .list_objects_after(bucket, start_after, continuation_token, delimiter, max_keys, prefix)
+            .await;
+        self.write_list_objects(
+            bucket,
+            Some(start_after),
+            continuation_token,
+            delimiter,
+            max_keys,
+            prefix,
+            &result,
+        );
+        result
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<Self::PutObjectRequest, PutObjectError, Self::ClientError> {
+        let result = self.client.put_object(bucket, key, params).await;
+        let mut record = PutObjectRecord {
+            trace: self.trace.clone(),
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            body: Some(BodyRecorder::new(self.bodies)),
+            error: None,
+        };
+        match result {
+            Ok(request) => Ok(RecordingPutObjectRequest { request, record }),
+            Err(e) => {
+                record.body = None;
+                record.finish(Err(TracedError::new(&e)));
+                Err(e)
+            }
+        }
+    }
+
+    async fn put_object_single<'a>(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectSingleParams,
+        contents: impl AsRef<[u8]> + Send + 'a,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        let body = TracedPart::new(0, contents.as_ref(), self.bodies);
+        let result = self.client.put_object_single(bucket, key, params, contents).await;
+        self.trace.write(&TraceEvent::PutObjectSingle {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            body,
+            result: traced(&result, |result| result.into()),
+        });
+        result
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        let result = self
+            .client
+            .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
+            .await;
+        self.trace.write(&TraceEvent::GetObjectAttributes {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            max_parts,
+            part_number_marker,
+            object_attributes: object_attributes.iter().map(ToString::to_string).collect(),
+            result: traced(&result, |result| result.into()),
+        });
+        result
+    }
+}
+
+/// A GetObject request that hasn't been written to the trace yet. It's written when the body
+/// stream ends, or when the request is dropped.
+struct GetObjectRecord {
+    trace: Arc<TraceWriter>,
+    bodies: TraceBodies,
+    bucket: String,
+    key: String,
+    range: Option<Range<u64>>,
+    if_match: Option<String>,
+    /// The body received so far, or `None` once the record has been written
+    body: Option<TracedBody>,
+}
+
+impl GetObjectRecord {
+    fn finish(&mut self, result: Result<TracedBody, TracedError>) {
+        self.body = None;
+        self.trace.write(&TraceEvent::GetObject {
+            bucket: std::mem::take(&mut self.bucket),
+            key: std::mem::take(&mut self.key),
+            range: self.range.take(),
+            if_match: self.if_match.take(),
+            result,
+        });
+    }
+}
+
+impl Drop for GetObjectRecord {
+    fn drop(&mut self) {
+        if let Some(body) = self.body.take() {
+            self.finish(Ok(body));
+        }
+    }
+}
+
+/// A GetObject request for a [RecordingClient]
+#[pin_project]
+pub struct RecordingGetObjectRequest<Client: ObjectClient> {
+    #[pin]
+    request: Client::GetObjectRequest,
+    record: GetObjectRecord,
+}
+
+impl<Client: ObjectClient> GetObjectRequest for RecordingGetObjectRequest<Client> {
+    type ClientError = Client::ClientError;
+
+    fn increment_read_window(self: Pin<&mut Self>, len: usize) {
+        self.project().request.increment_read_window(len);
+    }
+
+    fn read_window_end_offset(self: Pin<&Self>) -> u64 {
+        self.project_ref().request.read_window_end_offset()
+    }
+}
+
+impl<Client: ObjectClient> Stream for RecordingGetObjectRequest<Client> {
+    type Item = ObjectClientResult<GetBodyPart, GetObjectError, Client::ClientError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let result = ready!(this.request.poll_next(cx));
+        let record = this.record;
+        if let Some(mut body) = record.body.take() {
+            match &result {
+                Some(Ok((offset, data))) => {
+                    body.parts.push(TracedPart::new(*offset, data, record.bodies));
+                    record.body = Some(body);
+                }
+                Some(Err(e)) => {
+                    body.error = Some(TracedError::new(e));
+                    record.finish(Ok(body));
+                }
+                None => record.finish(Ok(body)),
+            }
+        }
+        Poll::Ready(result)
+    }
+}
+
+/// An upload that hasn't been written to the trace yet. It's written when the upload completes or
+/// fails, or when the request is dropped.
+struct PutObjectRecord {
+    trace: Arc<TraceWriter>,
+    bucket: String,
+    key: String,
+    /// The data written so far, or `None` once the record has been written
+    body: Option<BodyRecorder>,
+    /// The first error returned by the upload
+    error: Option<TracedError>,
+}
+
+impl PutObjectRecord {
+    fn finish(&mut self, result: Result<TracedPutObjectResult, TracedError>) {
+        self.trace.write(&TraceEvent::PutObject {
+            bucket: std::mem::take(&mut self.bucket),
+            key: std::mem::take(&mut self.key),
+            body: self.body.take().map(BodyRecorder::finish),
+            result: self.error.take().map(Err).unwrap_or(result),
+        });
+    }
+}
+
+impl Drop for PutObjectRecord {
+    fn drop(&mut self) {
+        if self.body.is_some() {
+            self.finish(Err(TracedError::ClientError(
+                "upload was dropped before completing".into(),
+            )));
+        }
+    }
+}
+
+/// A PutObject request for a [RecordingClient]
+pub struct RecordingPutObjectRequest<Client: ObjectClient> {
+    request: Client::PutObjectRequest,
+    record: PutObjectRecord,
+}
+
+#[cfg_attr(not(docsrs), async_trait)]
+impl<Client: ObjectClient> PutObjectRequest for RecordingPutObjectRequest<Client>
+where
+    Client::PutObjectRequest: Send,
+{
+    type ClientError = Client::ClientError;
+
+    async fn write(&mut self, slice: &[u8]) -> ObjectClientResult<(), PutObjectError, Self::ClientError> {
+        let result = self.request.write(slice).await;
+        match &result {
+            Ok(()) => {
+                if let Some(body) = self.record.body.as_mut() {
+                    body.update(slice);
+                }
+            }
+            Err(e) => {
+                self.record.error.get_or_insert_with(|| TracedError::new(e));
+            }
+        }
+        result
+    }
+
+    async fn complete(self) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        let Self { request, mut record } = self;
+        let result = request.complete().await;
+        record.finish(traced(&result, |result| result.into()));
+        result
+    }
+
+    async fn review_and_complete(
+        self,
+        review_callback: impl FnOnce(UploadReview) -> bool + Send + 'static,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        let Self { request, mut record } = self;
+        let result = request.review_and_complete(review_callback).await;
+        record.finish(traced(&result, |result| result.into()));
+        result
+    }
+}
+
+/// Identifies a request in a trace, for matching replayed requests to recorded ones. Bucket names
+/// are deliberately not included.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+enum RequestKey {
+    DeleteObject {
+        key: String,
+    },
+    GetObject {
+        key: String,
+        range: Option<Range<u64>>,
+        if_match: Option<String>,
+    },
+    HeadObject {
+        key: String,
+    },
+    ListObjects {
+        start_after: Option<String>,
+        continuation_token: Option<String>,
+        delimiter: String,
+        max_keys: usize,
+        prefix: String,
+    },
+    PutObject {
+        key: String,
+    },
+    PutObjectSingle {
+        key: String,
+    },
+    GetObjectAttributes {
+        key: String,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: Vec<String>,
+    },
+}
+
+impl TraceEvent {
+    fn request_key(&self) -> Option<RequestKey> {
+        let request_key = match self {
+            TraceEvent::Header { .. } => return None,
+            TraceEvent::DeleteObject { key, .. } => RequestKey::DeleteObject { key: key.clone() },
+            TraceEvent::GetObject {
+                key, range, if_match, ..
+            } => RequestKey::GetObject {
+                key: key.clone(),
+                range: range.clone(),
+                if_match: if_match.clone(),
+            },
+            TraceEvent::HeadObject { key, .. } => RequestKey::HeadObject { key: key.clone() },
+            TraceEvent::ListObjects {
+                start_after,
+                continuation_token,
+                delimiter,
+                max_keys,
+                prefix,
+                ..
+            } => RequestKey::ListObjects {
+                start_after: start_after.clone(),
+                continuation_token: continuation_token.clone(),
+                delimiter: delimiter.clone(),
+                max_keys: *max_keys,
+                prefix: prefix.clone(),
+            },
+            TraceEvent::PutObject { key, .. } => RequestKey::PutObject { key: key.clone() },
+            TraceEvent::PutObjectSingle { key, .. } => RequestKey::PutObjectSingle { key: key.clone() },
+            TraceEvent::GetObjectAttributes {
+                key,
+                max_parts,
+                part_number_marker,
+                object_attributes,
+                ..
+            } => RequestKey::GetObjectAttributes {
+                key: key.clone(),
+                max_parts: *max_parts,
+                part_number_marker: *part_number_marker,
+                object_attributes: object_attributes.clone(),
+            },
+        };
+        Some(request_key)
+    }
+}
+
+/// The recorded responses to one request
+#[derive(Debug)]
+struct RecordedResponses {
+    remaining: VecDeque<TraceEvent>,
+    last: TraceEvent,
+}
+
+/// An [ObjectClient] that serves responses from a trace written by a [RecordingClient].
+#[derive(Debug)]
+pub struct ReplayClient {
+    read_part_size: Option<usize>,
+    write_part_size: Option<usize>,
+    initial_read_window_size: Option<usize>,
+    responses: Mutex<HashMap<RequestKey, RecordedResponses>>,
+}
+
+impl ReplayClient {
+    /// Create a new [ReplayClient] from the trace in the given file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ReplayTraceError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+
+    /// Create a new [ReplayClient] from a trace
+    pub fn new(trace: impl BufRead) -> Result<Self, ReplayTraceError> {
+        let mut client = Self {
+            read_part_size: None,
+            write_part_size: None,
+            initial_read_window_size: None,
+            responses: Default::default(),
+        };
+        let responses = client.responses.get_mut().unwrap();
+        for (i, line) in trace.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: TraceEvent =
+                serde_json::from_str(&line).map_err(|e| ReplayTraceError::InvalidEvent(i + 1, e))?;
+            match event.request_key() {
+                Some(request_key) => match responses.get_mut(&request_key) {
+                    Some(recorded) => {
+                        recorded.remaining.push_back(event.clone());
+                        recorded.last = event;
+                    }
+                    None => {
+                        let recorded = RecordedResponses {
+                            remaining: VecDeque::from([event.clone()]),
+                            last: event,
+                        };
+                        responses.insert(request_key, recorded);
+                    }
+                },
+                None => {
+                    let TraceEvent::Header {
+                        read_part_size,
+                        write_part_size,
+                        initial_read_window_size,
+                    } = event
+                    else {
+                        unreachable!("only the header has no request key");
+                    };
+                    client.read_part_size = read_part_size;
+                    client.write_part_size = write_part_size;
+                    client.initial_read_window_size = initial_read_window_size;
+                }
+            }
+        }
+        Ok(client)
+    }
+
+    /// Take the next recorded response to the given request
+    fn next_response<E>(&self, request_key: RequestKey) -> ObjectClientResult<TraceEvent, E, ReplayClientError> {
+        let mut responses = self.responses.lock().unwrap();
+        let Some(recorded) = responses.get_mut(&request_key) else {
+            return Err(ObjectClientError::ClientError(ReplayClientError::NotRecorded(format!(
+                "{request_key:?}"
+            ))));
+        };
+        Ok(recorded.remaining.pop_front().unwrap_or_else(|| recorded.last.clone()))
+    }
+
+    fn replay_list_objects(
+        &self,
+        start_after: Option<&str>,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, ReplayClientError> {
+        let request_key = RequestKey::ListObjects {
+            start_after: start_after.map(ToOwned::to_owned),
+            continuation_token: continuation_token.map(ToOwned::to_owned),
+            delimiter: delimiter.to_owned(),
+            max_keys,
+            prefix: prefix.to_owned(),
+        };
+        let TraceEvent::ListObjects { result, .. } = self.next_response(request_key)? else {
+            unreachable!("request keys match their events");
+        };
+        result
+            .map(|result| ListObjectsResult {
+                objects: result.objects.into_iter().map(Into::into).collect(),
+                common_prefixes: result.common_prefixes,
+                next_continuation_token: result.next_continuation_token,
+            })
+            .map_err(TracedError::into_error)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayTraceError {
+    #[error("failed to read trace")]
+    IoError(#[from] io::Error),
+
+    #[error("invalid trace event on line {0}")]
+    InvalidEvent(usize, #[source] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayClientError {
+    #[error("no response was recorded for request {0}")]
+    NotRecorded(String),
+
+    #[error("recorded client error: {0}")]
+    Recorded(String),
+
+    #[error("unknown service error {0:?} in trace")]
+    UnknownServiceError(String),
+
+    #[error("invalid body data in trace")]
+    InvalidBody,
+
+    #[error("empty read window")]
+    EmptyReadWindow,
+
+    #[error("upload review failed, aborting")]
+    ReviewFailed,
+}
+
+impl ProvideErrorMetadata for ReplayClientError {
+    fn meta(&self) -> ClientErrorMetadata {
+        Default::default()
+    }
+}
+
+#[cfg_attr(not(docsrs), async_trait)]
+impl ObjectClient for ReplayClient {
+    type GetObjectRequest = ReplayGetObjectRequest;
+    type PutObjectRequest = ReplayPutObjectRequest;
+    type ClientError = ReplayClientError;
+
+    fn read_part_size(&self) -> Option<usize> {
+        self.read_part_size
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
+        self.write_part_size
+    }
+
+    fn initial_read_window_size(&self) -> Option<usize> {
+        self.initial_read_window_size
+    }
+
+    fn mem_usage_stats(&self) -> Option<BufferPoolUsageStats> {
+        None
+    }
+
+    async fn delete_object(
+        &self,
+        _bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        let request_key = RequestKey::DeleteObject { key: key.to_owned() };
+        let TraceEvent::DeleteObject { result, .. } = self.next_response(request_key)? else {
+            unreachable!("request keys match their events");
+        };
+        result.map(|()| DeleteObjectResult {}).map_err(TracedError::into_error)
+    }
+
+    async fn get_object(
+        &self,
+        _bucket: &str,
+        key: &str,
+        range: Option<Range<u64>>,
+        if_match: Option<ETag>,
+    ) -> ObjectClientResult<Self::GetObjectRequest, GetObjectError, Self::ClientError> {
+        let start = range.as_ref().map(|range| range.start).unwrap_or(0);
+        let request_key = RequestKey::GetObject {
+            key: key.to_owned(),
+            range,
+            if_match: if_match.map(ETag::into_inner),
+        };
+        let TraceEvent::GetObject { result, .. } = self.next_response(request_key)? else {
+            unreachable!("request keys match their events");
+        };
+        let body = result.map_err(TracedError::into_error)?;
+        Ok(ReplayGetObjectRequest {
+            parts: body.parts.into(),
+            error: body.error,
+            enable_backpressure: self.initial_read_window_size.is_some(),
+            read_window_end_offset: start + self.initial_read_window_size.unwrap_or_default() as u64,
+        })
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        let request_key = RequestKey::HeadObject { key: key.to_owned() };
+        let TraceEvent::HeadObject { result, .. } = self.next_response(request_key)? else {
+            unreachable!("request keys match their events");
+        };
+        result
+            .map(|object| HeadObjectResult {
+                bucket: bucket.to_owned(),
+                object: object.into(),
+            })
+            .map_err(TracedError::into_error)
+    }
+
+    async fn list_objects(
+        &self,
+        _bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.replay_list_objects(None, continuation_token, delimiter, max_keys, prefix)
+    }
+
+    async fn list_objects_after(
+        &self,
+        _bucket: &str,
+        start_after: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.replay_list_objects(Some(start_after), continuation_token, delimiter, max_keys, prefix)
+    }
+
+    async fn put_object(
+        &self,
+        _bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<Self::PutObjectRequest, PutObjectError, Self::ClientError> {
+        let request_key = RequestKey::PutObject { key: key.to_owned() };
+        let TraceEvent::PutObject { body, result, .. } = self.next_response(request_key)? else {
+            unreachable!("request keys match their events");
+        };
+        if body.is_none() {
+            // The recorded upload couldn't be created, so neither can this one
+            return Err(result.expect_err("uploads without a body failed").into_error());
+        }
+        Ok(ReplayPutObjectRequest {
+            result,
+            part_size: self.write_part_size.unwrap_or(DEFAULT_REPLAY_PART_SIZE),
+            trailing_checksums: params.trailing_checksums,
+            buffer: Vec::new(),
+            parts: Vec::new(),
+        })
+    }
+
+    async fn put_object_single<'a>(
+        &self,
+        _bucket: &str,
+        key: &str,
+        _params: &PutObjectSingleParams,
+        _contents: impl AsRef<[u8]> + Send + 'a,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        let request_key = RequestKey::PutObjectSingle { key: key.to_owned() };
+        let TraceEvent::PutObjectSingle { result, .. } = self.next_response(request_key)? else {
+            unreachable!("request keys match their events");
+        };
+        result.map(Into::into).map_err(TracedError::into_error)
+    }
+
+    async fn get_object_attributes(
+        &self,
+        _bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        let request_key = RequestKey::GetObjectAttributes {
+            key: key.to_owned(),
+            max_parts,
+            part_number_marker,
+            object_attributes: object_attributes.iter().map(ToString::to_string).collect(),
+        };
+        let TraceEvent::GetObjectAttributes { result, .. } = self.next_response(request_key)? else {
+            unreachable!("request keys match their events");
+        };
+        result.map(Into::into).map_err(TracedError::into_error)
+    }
+}
+
+/// A GetObject request for a [ReplayClient]
+#[derive(Debug)]
+pub struct ReplayGetObjectRequest {
+    parts: VecDeque<TracedPart>,
+    error: Option<TracedError>,
+    enable_backpressure: bool,
+    read_window_end_offset: u64,
+}
+
+impl GetObjectRequest for ReplayGetObjectRequest {
+    type ClientError = ReplayClientError;
+
+    fn increment_read_window(mut self: Pin<&mut Self>, len: usize) {
+        self.read_window_end_offset += len as u64;
+    }
+
+    fn read_window_end_offset(self: Pin<&Self>) -> u64 {
+        self.read_window_end_offset
+    }
+}
+
+impl Stream for ReplayGetObjectRequest {
+    type Item = ObjectClientResult<GetBodyPart, GetObjectError, ReplayClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(part) = self.parts.front() else {
+            return Poll::Ready(self.error.take().map(|error| Err(error.into_error())));
+        };
+
+        // Simulate backpressure mechanism
+        if self.enable_backpressure && part.offset >= self.read_window_end_offset {
+            return Poll::Ready(Some(Err(ObjectClientError::ClientError(
+                ReplayClientError::EmptyReadWindow,
+            ))));
+        }
+
+        let part = self.parts.pop_front().unwrap();
+        let result = part
+            .data()
+            .map(|data| (part.offset, data))
+            .map_err(ObjectClientError::ClientError);
+        Poll::Ready(Some(result))
+    }
+}
+
+/// A PutObject request for a [ReplayClient]. The data written is discarded, apart from computing
+/// the parts to review, and completing the upload returns the recorded result.
+#[derive(Debug)]
+pub struct ReplayPutObjectRequest {
+    result: Result<TracedPutObjectResult, TracedError>,
+    part_size: usize,
+    trailing_checksums: PutObjectTrailingChecksums,
+    /// Data for the current part, always smaller than a part after a successful write
+    buffer: Vec<u8>,
+    parts: Vec<UploadReviewPart>,
+}
+
+impl ReplayPutObjectRequest {
+    fn finish_part(&mut self, data: &[u8]) {
+        let checksum = (self.trailing_checksums != PutObjectTrailingChecksums::Disabled)
+            .then(|| crc32c_to_base64(&crc32c::checksum(data)));
+        self.parts.push(UploadReviewPart {
+            size: data.len() as u64,
+            checksum,
+        });
+    }
+}
+
+#[cfg_attr(not(docsrs), async_trait)]
+impl PutObjectRequest for ReplayPutObjectRequest {
+    type ClientError = ReplayClientError;
+
+    async fn write(&mut self, slice: &[u8]) -> ObjectClientResult<(), PutObjectError, Self::ClientError> {
+        self.buffer.extend_from_slice(slice);
+        while self.buffer.len() >= self.part_size {
+            let rest = self.buffer.split_off(self.part_size);
+            let part = std::mem::replace(&mut self.buffer, rest);
+            self.finish_part(&part);
+        }
+        Ok(())
+    }
+
+    async fn complete(self) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        self.result.map(Into::into).map_err(TracedError::into_error)
+    }
+
+    async fn review_and_complete(
+        mut self,
+        review_callback: impl FnOnce(UploadReview) -> bool + Send + 'static,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        if !self.buffer.is_empty() || self.parts.is_empty() {
+            let buffer = std::mem::take(&mut self.buffer);
+            self.finish_part(&buffer);
+        }
+        let checksum_algorithm = if self.trailing_checksums != PutObjectTrailingChecksums::Disabled {
+            Some(ChecksumAlgorithm::Crc32c)
+        } else {
+            None
+        };
+        let review = UploadReview {
+            checksum_algorithm,
+            parts: std::mem::take(&mut self.parts),
+        };
+        if !review_callback(review) {
+            return Err(ObjectClientError::ClientError(ReplayClientError::ReviewFailed));
+        }
+        self.complete().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::mock_client::{MockClient, MockClientConfig, MockObject};
+
+    /// A [Write] that can be read back after being given to a [RecordingClient]
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn mock_client() -> MockClient {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 4,
+            enable_backpressure: true,
+            initial_read_window_size: 8,
+            ..Default::default()
+        });
+        client.add_object("dir/hello", MockObject::from_bytes(b"hello world", ETag::for_tests()));
+        client
+    }
+
+    async fn collect_body<R: GetObjectRequest>(mut request: Pin<Box<R>>) -> Vec<u8> {
+        let mut body = Vec::new();
+        while let Some(part) = request.next().await {
+            let Ok((offset, data)) = part else {
+                panic!("body stream failed");
+            };
+            assert_eq!(offset, body.len() as u64);
+            body.extend_from_slice(&data);
+            request.as_mut().increment_read_window(data.len());
+        }
+        body
+    }
+
+    /// Run the same workload against a client, returning a summary of the results
+    async fn workload(client: &impl ObjectClient) -> Vec<String> {
+        let mut results = Vec::new();
+
+        let list = client.list_objects("test_bucket", None, "/", 10, "").await.unwrap();
+        results.push(format!("{:?}", list.common_prefixes));
+        let head = client.head_object("test_bucket", "dir/hello").await.unwrap();
+        results.push(format!("{} {} {}", head.object.key, head.object.size, head.object.etag));
+        let missing = client.head_object("test_bucket", "missing").await.unwrap_err();
+        results.push(format!("{missing:?}"));
+
+        let request = client.get_object("test_bucket", "dir/hello", None, None).await.unwrap();
+        let body = collect_body(Box::pin(request)).await;
+        results.push(String::from_utf8_lossy(&body).into_owned());
+
+        let params = PutObjectParams::new().trailing_checksums(PutObjectTrailingChecksums::Enabled);
+        let mut request = client.put_object("test_bucket", "new", &params).await.unwrap();
+        request.write(b"new object").await.unwrap();
+        let result = request
+            .review_and_complete(|review| review.parts.iter().map(|part| part.size).sum::<u64>() == 10)
+            .await
+            .unwrap();
+        results.push(result.etag.into_inner());
+
+        results
+    }
+
+    #[test_case(TraceBodies::None; "no bodies")]
+    #[test_case(TraceBodies::Checksums; "checksums")]
+    #[test_case(TraceBodies::Full; "full bodies")]
+    #[tokio::test]
+    async fn record_and_replay(bodies: TraceBodies) {
+        let trace = SharedBuffer::default();
+        let client = RecordingClient::new(mock_client(), trace.clone(), bodies);
+        let recorded = workload(&client).await;
+        drop(client);
+
+        let trace = trace.0.lock().unwrap().clone();
+        let client = ReplayClient::new(&trace[..]).unwrap();
+        assert_eq!(client.read_part_size(), Some(4));
+        assert_eq!(client.initial_read_window_size(), Some(8));
+        let replayed = workload(&client).await;
+
+        if bodies == TraceBodies::Full {
+            assert_eq!(recorded, replayed);
+        } else {
+            // Without bodies, the object body is replayed as zeroes
+            assert_eq!(recorded[..3], replayed[..3]);
+            assert_eq!(replayed[3], "\0".repeat(11));
+            assert_eq!(recorded[4], replayed[4]);
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_repeats_last_response() {
+        let trace = SharedBuffer::default();
+        let client = RecordingClient::new(mock_client(), trace.clone(), TraceBodies::None);
+        client.head_object("test_bucket", "dir/hello").await.unwrap();
+        client.delete_object("test_bucket", "dir/hello").await.unwrap();
+        client.head_object("test_bucket", "dir/hello").await.unwrap_err();
+        drop(client);
+
+        let trace = trace.0.lock().unwrap().clone();
+        let client = ReplayClient::new(&trace[..]).unwrap();
+        // Bucket names aren't matched
+        client.head_object("sthree-bucket", "dir/hello").await.unwrap();
+        for _ in 0..3 {
+            let err = client.head_object("sthree-bucket", "dir/hello").await.unwrap_err();
+            assert!(matches!(
+                err,
+                ObjectClientError::ServiceError(HeadObjectError::NotFound)
+            ));
+        }
+        let err = client.head_object("sthree-bucket", "other").await.unwrap_err();
+        assert!(matches!(
+            err,
+            ObjectClientError::ClientError(ReplayClientError::NotRecorded(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn record_dropped_requests() {
+        let trace = SharedBuffer::default();
+        let client = RecordingClient::new(mock_client(), trace.clone(), TraceBodies::Full);
+        let mut request = Box::pin(client.get_object("test_bucket", "dir/hello", None, None).await.unwrap());
+        request.next().await.unwrap().unwrap();
+        drop(request);
+        let mut request = client
+            .put_object("test_bucket", "abandoned", &Default::default())
+            .await
+            .unwrap();
+        request.write(b"data").await.unwrap();
+        drop(request);
+        drop(client);
+
+        let trace = trace.0.lock().unwrap().clone();
+        let client = ReplayClient::new(&trace[..]).unwrap();
+        let mut request = Box::pin(client.get_object("test_bucket", "dir/hello", None, None).await.unwrap());
+        let (offset, data) = request.next().await.unwrap().unwrap();
+        assert_eq!((offset, &data[..]), (0, &b"hell"[..]));
+        assert!(request.next().await.is_none());
+
+        let request = client
+            .put_object("test_bucket", "abandoned", &Default::default())
+            .await
+            .unwrap();
+        let err = request.complete().await.unwrap_err();
+        assert!(matches!(
+            err,
+            ObjectClientError::ClientError(ReplayClientError::Recorded(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_trace() {
+        let trace = b"{\"operation\":\"header\"}\nnot json\n";
+        let err = ReplayClient::new(&trace[..]).unwrap_err();
+        assert!(matches!(err, ReplayTraceError::InvalidEvent(2, _)));
+    }
+}
@@ -1,8 +1,10 @@
 //! Utilities to construct a HTTP User-agent header in the AWS SDK format
 
+#[cfg(feature = "crt")]
 use platform_info::{PlatformInfo, PlatformInfoAPI, UNameAPI};
 
 use crate::build_info;
+#[cfg(feature = "crt")]
 use crate::instance_info::InstanceInfo;
 
 /// A builder for AWS SDK-style user agent headers
@@ -19,11 +21,13 @@ impl UserAgent {
     }
 
     /// Create a new User-agent builder with the default platform metadata fields
+    #[cfg(feature = "crt")]
     pub fn new_with_instance_info(prefix: Option<String>, instance_info: &InstanceInfo) -> Self {
         let user_agent_info = UserAgentInfo::new(instance_info);
         Self::new_with_user_agent_info(prefix, user_agent_info)
     }
 
+    #[cfg_attr(not(feature = "crt"), allow(dead_code))]
     fn new_with_user_agent_info(prefix: Option<String>, user_agent_info: UserAgentInfo) -> Self {
         let mut fields = vec![];
 
@@ -81,6 +85,7 @@ fn sanitize_string(s: impl AsRef<str>) -> String {
         .replace(|c: char| !c.is_alphanumeric() && !VALID_CHARS.contains(&c), "-")
 }
 
+#[cfg_attr(not(feature = "crt"), allow(dead_code))]
 fn canonicalize_sysname(sysname: impl AsRef<str>) -> &'static str {
     match sysname.as_ref() {
         "Linux" => "linux",
@@ -92,6 +97,7 @@ fn canonicalize_sysname(sysname: impl AsRef<str>) -> &'static str {
 }
 
 /// To make this code testable we factor out the platform queries so we can mock them in tests
+#[cfg_attr(not(feature = "crt"), allow(dead_code))]
 struct UserAgentInfo {
     sysname: Option<String>,
     release: Option<String>,
@@ -100,6 +106,7 @@ struct UserAgentInfo {
 }
 
 impl UserAgentInfo {
+    #[cfg(feature = "crt")]
     fn new(instance_info: &InstanceInfo) -> Self {
         let platform_info = PlatformInfo::new().ok();
 
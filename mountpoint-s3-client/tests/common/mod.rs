@@ -9,9 +9,13 @@ use aws_sdk_s3::primitives::ByteStream;
 use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
 use bytes::Bytes;
 use futures::{pin_mut, Stream, StreamExt};
+#[cfg(feature = "http_client")]
+use mountpoint_s3_client::config::{Credentials, S3HttpClientAuthConfig, S3HttpClientConfig};
 use mountpoint_s3_client::config::{EndpointConfig, S3ClientConfig};
 use mountpoint_s3_client::types::GetObjectRequest;
 use mountpoint_s3_client::S3CrtClient;
+#[cfg(feature = "http_client")]
+use mountpoint_s3_client::S3HttpClient;
 use mountpoint_s3_crt::common::rust_log_adapter::RustLogAdapter;
 use rand::rngs::OsRng;
 use rand::RngCore;
@@ -74,6 +78,22 @@ pub fn get_test_client() -> S3CrtClient {
     S3CrtClient::new(S3ClientConfig::new().endpoint_config(endpoint_config)).expect("could not create test client")
 }
 
+/// Create an [S3HttpClient] for the test region, using credentials from the default provider chain
+/// since the HTTP client can't load them itself.
+#[cfg(feature = "http_client")]
+pub async fn get_test_http_client() -> S3HttpClient {
+    let credentials = creds::get_sdk_default_chain_creds().await;
+    let credentials = Credentials::new(
+        credentials.access_key_id(),
+        credentials.secret_access_key(),
+        credentials.session_token(),
+    );
+    let config = S3HttpClientConfig::new()
+        .region(&get_test_region())
+        .auth_config(S3HttpClientAuthConfig::Credentials(credentials));
+    S3HttpClient::new(config).expect("could not create test client")
+}
+
 pub fn get_test_backpressure_client(initial_read_window: usize, part_size: Option<usize>) -> S3CrtClient {
     let endpoint_config = EndpointConfig::new(&get_test_region());
     let mut config = S3ClientConfig::new()
@@ -223,7 +243,8 @@ pub async fn check_backpressure_get_result(
 }
 
 /// Create a test suite that will execute a test that works over a generic [ObjectClient] against
-/// both the Rust CRT as well and the mock object client implementations.
+/// the Rust CRT, the mock, and (with the `http_client` feature) the pure-Rust HTTP object client
+/// implementations.
 ///
 /// To use, define a function that takes an [ObjectClient], and a bucket and prefix to use for testing:
 /// `async fn my_test_fn(client: &impl ObjectClient, bucket: &str, prefix: &str) { ... }`.
@@ -261,6 +282,17 @@ macro_rules! object_client_test {
                 let key = format!("{prefix}hello");
                 $test_fn_identifier(&client, &bucket, &key, Default::default()).await;
             }
+
+            #[cfg(all(feature = "http_client", not(feature = "s3express_tests")))]
+            #[tokio::test]
+            async fn rust_http() {
+                let (bucket, prefix) = get_test_bucket_and_prefix(stringify!($test_fn_identifier));
+
+                let client = $crate::get_test_http_client().await;
+
+                let key = format!("{prefix}hello");
+                $test_fn_identifier(&client, &bucket, &key, Default::default()).await;
+            }
         }
     };
 }
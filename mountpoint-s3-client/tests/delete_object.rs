@@ -8,8 +8,7 @@ use common::*;
 use mountpoint_s3_client::error::{DeleteObjectError, ObjectClientError};
 use mountpoint_s3_client::{ObjectClient, S3CrtClient, S3RequestError};
 
-#[tokio::test]
-async fn test_delete_object() {
+async fn delete_object_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_delete_object");
 
@@ -24,7 +23,6 @@ async fn test_delete_object() {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
     let _result = client
         .delete_object(&bucket, &key)
         .await
@@ -41,8 +39,7 @@ async fn test_delete_object() {
     assert!(head_obj_err.into_service_error().is_not_found());
 }
 
-#[tokio::test]
-async fn test_delete_object_no_obj() {
+async fn delete_object_no_obj_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_delete_object_no_obj");
 
@@ -58,21 +55,17 @@ async fn test_delete_object_no_obj() {
         .expect_err("object should not exist");
     assert!(head_obj_err.into_service_error().is_not_found());
 
-    let client: S3CrtClient = get_test_client();
     let _result = client
         .delete_object(&bucket, &key)
         .await
         .expect("delete_object should not fail for non-existent object");
 }
 
-#[tokio::test]
-async fn test_delete_object_404_bucket() {
+async fn delete_object_404_bucket_test<Client: ObjectClient>(client: Client) {
     let (_bucket, prefix) = get_test_bucket_and_prefix("test_delete_object_404_bucket");
 
     let key = format!("{prefix}/nonexistent_key");
 
-    let client: S3CrtClient = get_test_client();
-
     let result = client.delete_object("DOC-EXAMPLE-BUCKET", &key).await;
     assert!(matches!(
         result,
@@ -96,3 +89,31 @@ async fn test_delete_object_no_perm() {
         Err(ObjectClientError::ClientError(S3RequestError::Forbidden(_, _)))
     ));
 }
+
+/// Instantiate each client-agnostic test for the client created by `$client`
+macro_rules! client_tests {
+    ($module:ident, $client:expr) => {
+        mod $module {
+            use super::*;
+
+            #[tokio::test]
+            async fn test_delete_object() {
+                delete_object_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_delete_object_no_obj() {
+                delete_object_no_obj_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_delete_object_404_bucket() {
+                delete_object_404_bucket_test($client).await;
+            }
+        }
+    };
+}
+
+client_tests!(crt, get_test_client());
+#[cfg(all(feature = "http_client", not(feature = "s3express_tests")))]
+client_tests!(http, get_test_http_client().await);
@@ -17,15 +17,7 @@ use mountpoint_s3_client::{ObjectClient, S3CrtClient, S3RequestError};
 
 use test_case::test_case;
 
-#[test_case(1, None; "1-byte object")]
-#[test_case(10, None; "small object")]
-#[test_case(30000000, None; "large object")]
-#[test_case(1, Some(0..1); "1-byte object with range")]
-#[test_case(10, Some(0..4); "small object with range")]
-#[test_case(30000000, Some(10000000..10000100); "large object with small range")]
-#[test_case(30000000, Some(10000000..30000000); "large object with large range")]
-#[tokio::test]
-async fn test_get_object(size: usize, range: Option<Range<u64>>) {
+async fn get_object_test<Client: ObjectClient>(client: Client, size: usize, range: Option<Range<u64>>) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_object");
 
@@ -40,8 +32,6 @@ async fn test_get_object(size: usize, range: Option<Range<u64>>) {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
-
     let result = client
         .get_object(&bucket, &key, range.clone(), None)
         .await
@@ -192,19 +182,17 @@ async fn test_mutated_during_get_object_backpressure() {
     ));
 }
 
-#[tokio::test]
-async fn test_get_object_404_key() {
+async fn get_object_404_key_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_object_404_key");
 
     let key = format!("{prefix}/nonexistent_key");
 
-    let client: S3CrtClient = get_test_client();
-
-    let mut result = client
+    let result = client
         .get_object(&bucket, &key, None, None)
         .await
         .expect("get_object should succeed");
-    let next = StreamExt::next(&mut result).await.expect("stream needs to return Err");
+    pin_mut!(result);
+    let next = result.next().await.expect("stream needs to return Err");
     assert!(matches!(
         next,
         Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
@@ -213,27 +201,25 @@ async fn test_get_object_404_key() {
     // TODO: what happens if the object is deleted mid-GET? the CRT does lots of ranged GETs, so they
     // will start failing. need a way to test that.
 }
-#[tokio::test]
-async fn test_get_object_404_bucket() {
+
+async fn get_object_404_bucket_test<Client: ObjectClient>(client: Client) {
     let (_bucket, prefix) = get_test_bucket_and_prefix("test_get_object_404_bucket");
 
     let key = format!("{prefix}/nonexistent_key");
 
-    let client: S3CrtClient = get_test_client();
-
-    let mut result = client
+    let result = client
         .get_object("DOC-EXAMPLE-BUCKET", &key, None, None)
         .await
         .expect("get_object failed");
-    let next = StreamExt::next(&mut result).await.expect("stream needs to return Err");
+    pin_mut!(result);
+    let next = result.next().await.expect("stream needs to return Err");
     assert!(matches!(
         next,
         Err(ObjectClientError::ServiceError(GetObjectError::NoSuchBucket))
     ));
 }
 
-#[tokio::test]
-async fn test_get_object_success_if_match() {
+async fn get_object_success_if_match_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_object_if_match");
 
@@ -250,7 +236,6 @@ async fn test_get_object_success_if_match() {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
     let etag = Some(ETag::from_str(response.e_tag().expect("E-Tag should be set")).unwrap());
 
     let result = client
@@ -260,8 +245,7 @@ async fn test_get_object_success_if_match() {
     check_get_result(result, None, &body[..]).await;
 }
 
-#[tokio::test]
-async fn test_get_object_412_if_match() {
+async fn get_object_412_if_match_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_object_412_if_match");
 
@@ -277,15 +261,15 @@ async fn test_get_object_412_if_match() {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
     let etag = Some(ETag::from_str("incorrect_etag").unwrap());
 
-    let mut result = client
+    let result = client
         .get_object(&bucket, &key, None, etag)
         .await
         .expect("get_object should succeed");
+    pin_mut!(result);
 
-    let next = StreamExt::next(&mut result).await.expect("stream needs to return Err");
+    let next = result.next().await.expect("stream needs to return Err");
 
     assert!(matches!(
         next,
@@ -293,10 +277,7 @@ async fn test_get_object_412_if_match() {
     ));
 }
 
-#[test_case(false; "early")]
-#[test_case(true; "after read")]
-#[tokio::test]
-async fn test_get_object_cancel(read: bool) {
+async fn get_object_cancel_test<Client: ObjectClient>(client: Client, read: bool) {
     const OBJECT_SIZE: usize = 30_000_000;
 
     let sdk_client = get_test_sdk_client().await;
@@ -314,12 +295,11 @@ async fn test_get_object_cancel(read: bool) {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
-
-    let mut request = client
+    let request = client
         .get_object(&bucket, &key, None, None)
         .await
         .expect("get_object should succeed");
+    let mut request = Box::pin(request);
 
     if read {
         let mut bytes = 0;
@@ -338,3 +318,56 @@ async fn test_get_object_cancel(read: bool) {
     // were actually cancelled, but we can at least check that the drop doesn't panic/deadlock.
     drop(request);
 }
+
+/// Instantiate each client-agnostic test for the client created by `$client`
+macro_rules! client_tests {
+    ($module:ident, $client:expr) => {
+        mod $module {
+            use super::*;
+            use test_case::test_case;
+
+            #[test_case(1, None; "1-byte object")]
+            #[test_case(10, None; "small object")]
+            #[test_case(30000000, None; "large object")]
+            #[test_case(1, Some(0..1); "1-byte object with range")]
+            #[test_case(10, Some(0..4); "small object with range")]
+            #[test_case(30000000, Some(10000000..10000100); "large object with small range")]
+            #[test_case(30000000, Some(10000000..30000000); "large object with large range")]
+            #[tokio::test]
+            async fn test_get_object(size: usize, range: Option<Range<u64>>) {
+                get_object_test($client, size, range).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_object_404_key() {
+                get_object_404_key_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_object_404_bucket() {
+                get_object_404_bucket_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_object_success_if_match() {
+                get_object_success_if_match_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_object_412_if_match() {
+                get_object_412_if_match_test($client).await;
+            }
+
+            #[test_case(false; "early")]
+            #[test_case(true; "after read")]
+            #[tokio::test]
+            async fn test_get_object_cancel(read: bool) {
+                get_object_cancel_test($client, read).await;
+            }
+        }
+    };
+}
+
+client_tests!(crt, get_test_client());
+#[cfg(all(feature = "http_client", not(feature = "s3express_tests")))]
+client_tests!(http, get_test_http_client().await);
@@ -10,7 +10,6 @@ use common::*;
 use mountpoint_s3_client::error::{GetObjectAttributesError, ObjectClientError};
 use mountpoint_s3_client::types::ObjectAttribute;
 use mountpoint_s3_client::{ObjectClient, S3CrtClient, S3RequestError};
-use test_case::test_case;
 
 fn default_storage_class() -> &'static str {
     if cfg!(feature = "s3express_tests") {
@@ -99,7 +98,10 @@ async fn create_mpu_object(
     (completed_parts, complete_mpu_output)
 }
 
-async fn test_with_checksum(checksum_algorithm: ChecksumAlgorithm) {
+async fn get_attributes_with_checksum_test<Client: ObjectClient>(
+    client: Client,
+    checksum_algorithm: ChecksumAlgorithm,
+) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_attributes_checksum");
 
@@ -119,8 +121,6 @@ async fn test_with_checksum(checksum_algorithm: ChecksumAlgorithm) {
     let expected_etag = put_object_output.e_tag().map(|s| s.trim_matches('"').to_string());
     let expected_storage_class = default_storage_class();
 
-    let client: S3CrtClient = get_test_client();
-
     let object_attributes = vec![
         ObjectAttribute::ETag,
         ObjectAttribute::Checksum,
@@ -163,8 +163,7 @@ async fn test_with_checksum(checksum_algorithm: ChecksumAlgorithm) {
     assert!(result.object_parts.is_none());
 }
 
-#[tokio::test]
-async fn test_get_attributes() {
+async fn get_attributes_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_attributes");
 
@@ -182,8 +181,6 @@ async fn test_get_attributes() {
     let expected_etag = put_object_output.e_tag().map(|s| s.trim_matches('"').to_string());
     let expected_storage_class = default_storage_class();
 
-    let client: S3CrtClient = get_test_client();
-
     let object_attributes = vec![
         ObjectAttribute::ETag,
         ObjectAttribute::Checksum,
@@ -205,17 +202,7 @@ async fn test_get_attributes() {
     assert!(result.object_parts.is_none());
 }
 
-#[test_case(ChecksumAlgorithm::Crc32; "Checksum CRC32")]
-#[test_case(ChecksumAlgorithm::Crc32C; "Checksum CRC32C")]
-#[test_case(ChecksumAlgorithm::Sha1; "Checksum SHA1")]
-#[test_case(ChecksumAlgorithm::Sha256; "Checksum SHA256")]
-#[tokio::test]
-async fn test_get_attributes_with_checksum(checksum_algorithm: ChecksumAlgorithm) {
-    test_with_checksum(checksum_algorithm).await;
-}
-
-#[tokio::test]
-async fn test_get_attributes_all_none() {
+async fn get_attributes_all_none_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_attributes_all_none");
 
@@ -232,8 +219,6 @@ async fn test_get_attributes_all_none() {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
-
     let object_attributes = vec![ObjectAttribute::ObjectParts];
 
     let result = client
@@ -248,8 +233,7 @@ async fn test_get_attributes_all_none() {
     assert!(result.object_size.is_none());
 }
 
-#[tokio::test]
-async fn test_get_attributes_mpu() {
+async fn get_attributes_mpu_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_attributes_mpu");
 
     // Create one object named "hello"
@@ -261,8 +245,6 @@ async fn test_get_attributes_mpu() {
     let expected_etag = complete_mpu_output.e_tag().map(|s| s.trim_matches('"').to_string());
     let expected_storage_class = default_storage_class();
 
-    let client: S3CrtClient = get_test_client();
-
     let object_attributes = vec![
         ObjectAttribute::ETag,
         ObjectAttribute::Checksum,
@@ -301,8 +283,7 @@ async fn test_get_attributes_mpu() {
     }
 }
 
-#[tokio::test]
-async fn test_get_attributes_mpu_with_checksum() {
+async fn get_attributes_mpu_with_checksum_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_attributes_mpu_with_checksum");
 
     // Create one object named "hello"
@@ -315,8 +296,6 @@ async fn test_get_attributes_mpu_with_checksum() {
     let expected_etag = complete_mpu_output.e_tag().map(|s| s.trim_matches('"').to_string());
     let expected_storage_class = default_storage_class();
 
-    let client: S3CrtClient = get_test_client();
-
     let object_attributes = vec![
         ObjectAttribute::ETag,
         ObjectAttribute::Checksum,
@@ -373,8 +352,7 @@ async fn test_get_attributes_mpu_with_checksum() {
     assert_eq!(part2.size, parts_size[1]);
 }
 
-#[tokio::test]
-async fn test_get_attributes_mpu_pagination() {
+async fn get_attributes_mpu_pagination_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_attributes_mpu_pagination");
 
     // Create one object named "hello"
@@ -384,8 +362,6 @@ async fn test_get_attributes_mpu_pagination() {
     let (completed_parts, _complete_mpu_output) =
         create_mpu_object(&bucket, &key, &parts_size, Some(ChecksumAlgorithm::Sha256)).await;
 
-    let client: S3CrtClient = get_test_client();
-
     let object_attributes = vec![ObjectAttribute::ObjectParts];
 
     // Get the first page with only one part
@@ -444,13 +420,11 @@ async fn test_get_attributes_mpu_pagination() {
     assert_eq!(part2.size, parts_size[1]);
 }
 
-#[tokio::test]
-async fn test_get_attributes_404_key() {
+async fn get_attributes_404_key_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_attributes_404");
 
     let key = format!("{prefix}/nonexistent_key");
 
-    let client: S3CrtClient = get_test_client();
     let object_attributes = vec![ObjectAttribute::ETag];
 
     let result = client
@@ -462,13 +436,11 @@ async fn test_get_attributes_404_key() {
     ));
 }
 
-#[tokio::test]
-async fn test_get_attributes_404_bucket() {
+async fn get_attributes_404_bucket_test<Client: ObjectClient>(client: Client) {
     let (_bucket, prefix) = get_test_bucket_and_prefix("test_get_attributes_404");
 
     let key = format!("{prefix}/nonexistent_key");
 
-    let client: S3CrtClient = get_test_client();
     let object_attributes = vec![ObjectAttribute::ETag];
 
     let result = client
@@ -499,3 +471,61 @@ async fn test_get_attributes_no_perm() {
         Err(ObjectClientError::ClientError(S3RequestError::Forbidden(_, _)))
     ));
 }
+
+/// Instantiate each client-agnostic test for the client created by `$client`
+macro_rules! client_tests {
+    ($module:ident, $client:expr) => {
+        mod $module {
+            use super::*;
+            use test_case::test_case;
+
+            #[test_case(ChecksumAlgorithm::Crc32; "Checksum CRC32")]
+            #[test_case(ChecksumAlgorithm::Crc32C; "Checksum CRC32C")]
+            #[test_case(ChecksumAlgorithm::Sha1; "Checksum SHA1")]
+            #[test_case(ChecksumAlgorithm::Sha256; "Checksum SHA256")]
+            #[tokio::test]
+            async fn test_get_attributes_with_checksum(checksum_algorithm: ChecksumAlgorithm) {
+                get_attributes_with_checksum_test($client, checksum_algorithm).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_attributes() {
+                get_attributes_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_attributes_all_none() {
+                get_attributes_all_none_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_attributes_mpu() {
+                get_attributes_mpu_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_attributes_mpu_with_checksum() {
+                get_attributes_mpu_with_checksum_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_attributes_mpu_pagination() {
+                get_attributes_mpu_pagination_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_attributes_404_key() {
+                get_attributes_404_key_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_get_attributes_404_bucket() {
+                get_attributes_404_bucket_test($client).await;
+            }
+        }
+    };
+}
+
+client_tests!(crt, get_test_client());
+#[cfg(all(feature = "http_client", not(feature = "s3express_tests")))]
+client_tests!(http, get_test_http_client().await);
@@ -14,11 +14,8 @@ use mountpoint_s3_client::error::{HeadObjectError, ObjectClientError};
 #[cfg(not(feature = "s3express_tests"))]
 use mountpoint_s3_client::types::RestoreStatus;
 use mountpoint_s3_client::{ObjectClient, S3CrtClient, S3RequestError};
-#[cfg(not(feature = "s3express_tests"))]
-use test_case::test_case;
 
-#[tokio::test]
-async fn test_head_object() {
+async fn head_object_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_head_object");
 
@@ -33,7 +30,6 @@ async fn test_head_object() {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
     let result = client.head_object(&bucket, &key).await.expect("head_object failed");
 
     assert_eq!(result.bucket, bucket);
@@ -41,12 +37,9 @@ async fn test_head_object() {
     assert_eq!(result.object.size as usize, body.len());
 }
 
-#[test_case("INTELLIGENT_TIERING")]
-#[test_case("GLACIER")]
-#[tokio::test]
 // S3 Express One Zone is a distinct storage class and can't be overridden
 #[cfg(not(feature = "s3express_tests"))]
-async fn test_head_object_storage_class(storage_class: &str) {
+async fn head_object_storage_class_test<Client: ObjectClient>(client: Client, storage_class: &str) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_head_object");
 
@@ -62,7 +55,6 @@ async fn test_head_object_storage_class(storage_class: &str) {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
     let result = client.head_object(&bucket, &key).await.expect("head_object failed");
 
     assert_eq!(result.bucket, bucket);
@@ -72,14 +64,11 @@ async fn test_head_object_storage_class(storage_class: &str) {
     assert!(result.object.restore_status.is_none());
 }
 
-#[tokio::test]
-async fn test_head_object_404_key() {
+async fn head_object_404_key_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_head_object_404_key");
 
     let key = format!("{prefix}/nonexistent_key");
 
-    let client: S3CrtClient = get_test_client();
-
     let result = client.head_object(&bucket, &key).await;
     assert!(matches!(
         result,
@@ -87,14 +76,11 @@ async fn test_head_object_404_key() {
     ));
 }
 
-#[tokio::test]
-async fn test_head_object_404_bucket() {
+async fn head_object_404_bucket_test<Client: ObjectClient>(client: Client) {
     let (_bucket, prefix) = get_test_bucket_and_prefix("test_head_object_404_bucket");
 
     let key = format!("{prefix}/nonexistent_key");
 
-    let client: S3CrtClient = get_test_client();
-
     let result = client.head_object("DOC-EXAMPLE-BUCKET", &key).await;
     assert!(matches!(
         result,
@@ -119,9 +105,8 @@ async fn test_head_object_no_perm() {
 }
 
 // This test relies on s3's expedited object restoration, it takes 1-5 minutes to complete
-#[tokio::test]
 #[cfg(not(feature = "s3express_tests"))]
-async fn test_head_object_restored() {
+async fn head_object_restored_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_head_object_restored");
 
@@ -137,7 +122,6 @@ async fn test_head_object_restored() {
         .await
         .unwrap();
 
-    let client: S3CrtClient = get_test_client();
     let result = client.head_object(&bucket, &key).await.expect("head_object failed");
 
     assert_eq!(result.bucket, bucket);
@@ -183,3 +167,47 @@ async fn test_head_object_restored() {
     }
     assert!(!timeouted, "timeouted while waiting for object become restored");
 }
+
+/// Instantiate each client-agnostic test for the client created by `$client`
+macro_rules! client_tests {
+    ($module:ident, $client:expr) => {
+        mod $module {
+            use super::*;
+            #[cfg(not(feature = "s3express_tests"))]
+            use test_case::test_case;
+
+            #[tokio::test]
+            async fn test_head_object() {
+                head_object_test($client).await;
+            }
+
+            #[test_case("INTELLIGENT_TIERING")]
+            #[test_case("GLACIER")]
+            #[tokio::test]
+            #[cfg(not(feature = "s3express_tests"))]
+            async fn test_head_object_storage_class(storage_class: &str) {
+                head_object_storage_class_test($client, storage_class).await;
+            }
+
+            #[tokio::test]
+            async fn test_head_object_404_key() {
+                head_object_404_key_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_head_object_404_bucket() {
+                head_object_404_bucket_test($client).await;
+            }
+
+            #[tokio::test]
+            #[cfg(not(feature = "s3express_tests"))]
+            async fn test_head_object_restored() {
+                head_object_restored_test($client).await;
+            }
+        }
+    };
+}
+
+client_tests!(crt, get_test_client());
+#[cfg(all(feature = "http_client", not(feature = "s3express_tests")))]
+client_tests!(http, get_test_http_client().await);
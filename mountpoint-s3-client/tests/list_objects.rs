@@ -4,16 +4,13 @@ pub mod common;
 
 use common::*;
 use mountpoint_s3_client::error::{ListObjectsError, ObjectClientError};
-use mountpoint_s3_client::{ObjectClient, S3CrtClient};
+use mountpoint_s3_client::ObjectClient;
 
-#[tokio::test]
-async fn test_list_objects() {
+async fn list_objects_test<Client: ObjectClient>(client: Client) {
     let sdk_client = get_test_sdk_client().await;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_list_objects");
     create_objects_for_test(&sdk_client, &bucket, &prefix, &["hello", "dir/a", "dir/b"]).await;
 
-    let client: S3CrtClient = get_test_client();
-
     let result = client
         .list_objects(&bucket, None, "/", 1000, &prefix)
         .await
@@ -27,8 +24,7 @@ async fn test_list_objects() {
     assert_eq!(result.common_prefixes[0], format!("{}{}", prefix, "dir/"));
 }
 
-#[tokio::test]
-async fn test_max_keys_continuation_token() {
+async fn max_keys_continuation_token_test<Client: ObjectClient>(client: Client) {
     // Max keys to get per request
     const MAX_KEYS_PER_REQUEST: usize = 4;
     // Total number of keys in directory
@@ -43,8 +39,6 @@ async fn test_max_keys_continuation_token() {
     let keys: Vec<String> = (0..TOTAL_KEYS).map(|i| format!("object_{i}")).collect();
     create_objects_for_test(&sdk_client, &bucket, &prefix, &keys[..]).await;
 
-    let client: S3CrtClient = get_test_client();
-
     let mut continuation_token: Option<String> = None;
     let mut keys_left = TOTAL_KEYS;
     let mut remaining_attempts = MAX_ATTEMPTS;
@@ -88,12 +82,9 @@ async fn test_max_keys_continuation_token() {
     assert_eq!(keys_left, 0, "should be no more keys left after reaching max attempts");
 }
 
-#[tokio::test]
-async fn test_invalid_list_objects() {
+async fn invalid_list_objects_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_invalid_list_objects");
 
-    let client: S3CrtClient = get_test_client();
-
     // Make a ListObjects request using some made-up continuation token.
     let continuation_token = Some("Made-up invalid token here");
     let result = client
@@ -104,12 +95,9 @@ async fn test_invalid_list_objects() {
     println!("{err}");
 }
 
-#[tokio::test]
-async fn test_list_objects_404_bucket() {
+async fn list_objects_404_bucket_test<Client: ObjectClient>(client: Client) {
     let (_bucket, prefix) = get_test_bucket_and_prefix("test_list_objects_404_bucket");
 
-    let client: S3CrtClient = get_test_client();
-
     let result = client
         .list_objects("DOC-EXAMPLE-BUCKET", None, "/", 1000, &prefix)
         .await;
@@ -122,8 +110,7 @@ async fn test_list_objects_404_bucket() {
 // Test list with keys and arguments that poke at URL encoding
 // For S3 Express One Zone, ListObjectsV2 API results are not lexicographically sorted and this test will be failing.
 #[cfg(not(feature = "s3express_tests"))]
-#[tokio::test]
-async fn test_interesting_keys() {
+async fn interesting_keys_test<Client: ObjectClient>(client: Client) {
     let keys = &[
         "the first one@@@",
         "the first one@@@/the 1st one!$%#@?_.-=&+^",
@@ -134,8 +121,6 @@ async fn test_interesting_keys() {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_list_objects");
     create_objects_for_test(&sdk_client, &bucket, &prefix, keys).await;
 
-    let client: S3CrtClient = get_test_client();
-
     let result = client
         .list_objects(&bucket, None, "/", 2, &prefix)
         .await
@@ -166,3 +151,42 @@ async fn test_interesting_keys() {
     assert_eq!(result.objects.len(), 2);
     assert!(result.next_continuation_token.is_none());
 }
+
+/// Instantiate each client-agnostic test for the client created by `$client`
+macro_rules! client_tests {
+    ($module:ident, $client:expr) => {
+        mod $module {
+            use super::*;
+
+            #[tokio::test]
+            async fn test_list_objects() {
+                list_objects_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_max_keys_continuation_token() {
+                max_keys_continuation_token_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_invalid_list_objects() {
+                invalid_list_objects_test($client).await;
+            }
+
+            #[tokio::test]
+            async fn test_list_objects_404_bucket() {
+                list_objects_404_bucket_test($client).await;
+            }
+
+            #[cfg(not(feature = "s3express_tests"))]
+            #[tokio::test]
+            async fn test_interesting_keys() {
+                interesting_keys_test($client).await;
+            }
+        }
+    };
+}
+
+client_tests!(crt, get_test_client());
+#[cfg(all(feature = "http_client", not(feature = "s3express_tests")))]
+client_tests!(http, get_test_http_client().await);
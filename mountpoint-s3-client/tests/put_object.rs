@@ -170,12 +170,8 @@ async fn test_put_object_dropped(client: &impl ObjectClient, bucket: &str, key:
 object_client_test!(test_put_object_dropped);
 
 // Test for abort PUT object.
-#[test_case(30; "small")]
-// #[test_case(30_000_000; "large")]  // The Abort and in-flight parts can race and cause some parts to be left behind, recreating the MPU
-#[tokio::test]
-async fn test_put_object_abort(size: usize) {
+async fn put_object_abort_test<Client: ObjectClient>(client: Client, size: usize) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_object_abort");
-    let client = get_test_client();
     let key = format!("{prefix}hello");
 
     let mut rng = rand::thread_rng();
@@ -305,21 +301,13 @@ async fn test_put_object_initiate_failure() {
     assert_eq!(uploads_in_progress, 0);
 }
 
-#[test_case(PutObjectTrailingChecksums::Enabled; "enabled")]
-#[test_case(PutObjectTrailingChecksums::ReviewOnly; "review only")]
-#[test_case(PutObjectTrailingChecksums::Disabled; "disabled")]
-#[tokio::test]
-async fn test_put_checksums(trailing_checksums: PutObjectTrailingChecksums) {
-    const PART_SIZE: usize = 5 * 1024 * 1024;
+async fn put_checksums_test<Client: ObjectClient>(client: Client, trailing_checksums: PutObjectTrailingChecksums) {
+    let part_size = client.write_part_size().unwrap();
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_checksums");
-    let client_config = S3ClientConfig::new()
-        .part_size(PART_SIZE)
-        .endpoint_config(EndpointConfig::new(&get_test_region()));
-    let client = S3CrtClient::new(client_config).expect("could not create test client");
     let key = format!("{prefix}hello");
 
     let mut rng = rand::thread_rng();
-    let mut contents = vec![0u8; PART_SIZE * 2];
+    let mut contents = vec![0u8; part_size * 2];
     rng.fill(&mut contents[..]);
 
     let params = PutObjectParams::new().trailing_checksums(trailing_checksums);
@@ -356,7 +344,7 @@ async fn test_put_checksums(trailing_checksums: PutObjectTrailingChecksums) {
 
     if trailing_checksums == PutObjectTrailingChecksums::Enabled {
         let checksums: Vec<_> = parts.iter().map(|p| p.checksum_crc32_c().unwrap()).collect();
-        let expected_checksums: Vec<_> = contents.chunks(PART_SIZE).map(crc32c::checksum).collect();
+        let expected_checksums: Vec<_> = contents.chunks(part_size).map(crc32c::checksum).collect();
 
         assert_eq!(checksums.len(), expected_checksums.len());
         for (checksum, expected_checksum) in checksums.into_iter().zip(expected_checksums.into_iter()) {
@@ -376,13 +364,11 @@ async fn test_put_checksums(trailing_checksums: PutObjectTrailingChecksums) {
     }
 }
 
-#[test_case(HashMap::new(); "Empty")]
-#[test_case(HashMap::from([("foo".to_string(), "bar".to_string()), ("a".to_string(), "b".to_string())]); "ASCII")]
-#[tokio::test]
-async fn test_put_user_object_metadata_happy(object_metadata: HashMap<String, String>) {
+async fn put_user_object_metadata_happy_test<Client: ObjectClient>(
+    client: Client,
+    object_metadata: HashMap<String, String>,
+) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_user_object_metadata_happy");
-    let client_config = S3ClientConfig::new().endpoint_config(EndpointConfig::new(&get_test_region()));
-    let client = S3CrtClient::new(client_config).expect("could not create test client");
     let key = format!("{prefix}hello");
 
     let params = PutObjectParams::new().object_metadata(object_metadata.clone());
@@ -491,14 +477,10 @@ async fn check_get_object<Client: ObjectClient>(
     Ok(())
 }
 
-#[test_case("INTELLIGENT_TIERING")]
-#[test_case("GLACIER")]
-#[tokio::test]
 // S3 Express One Zone is a distinct storage class and can't be overridden
 #[cfg(not(feature = "s3express_tests"))]
-async fn test_put_object_storage_class(storage_class: &str) {
+async fn put_object_storage_class_test<Client: ObjectClient>(client: Client, storage_class: &str) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_object_storage_class");
-    let client = get_test_client();
     let key = format!("{prefix}hello");
 
     let mut rng = rand::thread_rng();
@@ -587,18 +569,9 @@ async fn check_sse(
 // Test that SSE settings, which were used to create a new object, are reflected in:
 // 1. HEAD_OBJECT response queried via AWS SDK;
 // 2. returned `PutObjectResult`.
-#[test_case(Some("aws:kms"), Some(get_test_kms_key_id()))]
-#[test_case(Some("aws:kms"), None)]
-#[test_case(Some("aws:kms:dsse"), Some(get_test_kms_key_id()))]
-#[test_case(Some("aws:kms:dsse"), None)]
-#[test_case(None, None)]
-#[test_case(Some("AES256"), None)]
-#[tokio::test]
 #[cfg(not(feature = "s3express_tests"))]
-async fn test_put_object_sse(sse_type: Option<&str>, kms_key_id: Option<String>) {
+async fn put_object_sse_test<Client: ObjectClient>(client: Client, sse_type: Option<&str>, kms_key_id: Option<String>) {
     let bucket = get_test_bucket();
-    let client_config = S3ClientConfig::new().endpoint_config(EndpointConfig::new(&get_test_region()));
-    let client = S3CrtClient::new(client_config).expect("could not create test client");
     let request_params = PutObjectParams::new()
         .server_side_encryption(sse_type.map(|value| value.to_owned()))
         .ssekms_key_id(kms_key_id.to_owned());
@@ -672,10 +645,8 @@ async fn test_concurrent_put_objects(throughput_target_gbps: f64, max_concurrent
     drop(req_vec);
 }
 
-#[tokio::test]
-async fn test_put_object_header() {
+async fn put_object_header_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_object_header");
-    let client = get_test_client();
     let key = format!("{prefix}hello");
 
     let content_type = "application/json";
@@ -698,3 +669,64 @@ async fn test_put_object_header() {
 
     assert_eq!(Some(content_type), output.content_type());
 }
+
+/// Instantiate each client-agnostic test for the client created by `$client`
+macro_rules! client_tests {
+    ($module:ident, $client:expr) => {
+        mod $module {
+            use super::*;
+            use test_case::test_case;
+
+            #[test_case(30; "small")]
+            // #[test_case(30_000_000; "large")]  // The Abort and in-flight parts can race and cause some parts to be left behind, recreating the MPU
+            #[tokio::test]
+            async fn test_put_object_abort(size: usize) {
+                put_object_abort_test($client, size).await;
+            }
+
+            #[test_case(PutObjectTrailingChecksums::Enabled; "enabled")]
+            #[test_case(PutObjectTrailingChecksums::ReviewOnly; "review only")]
+            #[test_case(PutObjectTrailingChecksums::Disabled; "disabled")]
+            #[tokio::test]
+            async fn test_put_checksums(trailing_checksums: PutObjectTrailingChecksums) {
+                put_checksums_test($client, trailing_checksums).await;
+            }
+
+            #[test_case(HashMap::new(); "Empty")]
+            #[test_case(HashMap::from([("foo".to_string(), "bar".to_string()), ("a".to_string(), "b".to_string())]); "ASCII")]
+            #[tokio::test]
+            async fn test_put_user_object_metadata_happy(object_metadata: HashMap<String, String>) {
+                put_user_object_metadata_happy_test($client, object_metadata).await;
+            }
+
+            #[test_case("INTELLIGENT_TIERING")]
+            #[test_case("GLACIER")]
+            #[tokio::test]
+            #[cfg(not(feature = "s3express_tests"))]
+            async fn test_put_object_storage_class(storage_class: &str) {
+                put_object_storage_class_test($client, storage_class).await;
+            }
+
+            #[test_case(Some("aws:kms"), Some(get_test_kms_key_id()))]
+            #[test_case(Some("aws:kms"), None)]
+            #[test_case(Some("aws:kms:dsse"), Some(get_test_kms_key_id()))]
+            #[test_case(Some("aws:kms:dsse"), None)]
+            #[test_case(None, None)]
+            #[test_case(Some("AES256"), None)]
+            #[tokio::test]
+            #[cfg(not(feature = "s3express_tests"))]
+            async fn test_put_object_sse(sse_type: Option<&str>, kms_key_id: Option<String>) {
+                put_object_sse_test($client, sse_type, kms_key_id).await;
+            }
+
+            #[tokio::test]
+            async fn test_put_object_header() {
+                put_object_header_test($client).await;
+            }
+        }
+    };
+}
+
+client_tests!(crt, get_test_client());
+#[cfg(all(feature = "http_client", not(feature = "s3express_tests")))]
+client_tests!(http, get_test_http_client().await);
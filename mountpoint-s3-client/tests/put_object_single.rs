@@ -65,16 +65,9 @@ async fn test_put_object_single_empty(
 
 object_client_test!(test_put_object_single_empty);
 
-#[test_case(None; "no checksum")]
-#[test_case(Some(ChecksumAlgorithm::Crc32c); "crc32c")]
-#[tokio::test]
-async fn test_put_checksums(checksum_algorithm: Option<ChecksumAlgorithm>) {
+async fn put_checksums_test<Client: ObjectClient>(client: Client, checksum_algorithm: Option<ChecksumAlgorithm>) {
     const PART_SIZE: usize = 5 * 1024 * 1024;
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_checksums");
-    let client_config = S3ClientConfig::new()
-        .part_size(PART_SIZE)
-        .endpoint_config(EndpointConfig::new(&get_test_region()));
-    let client = S3CrtClient::new(client_config).expect("could not create test client");
     let key = format!("{prefix}hello");
 
     let mut rng = rand::thread_rng();
@@ -119,13 +112,11 @@ async fn test_put_checksums(checksum_algorithm: Option<ChecksumAlgorithm>) {
     }
 }
 
-#[test_case(HashMap::new(); "Empty")]
-#[test_case(HashMap::from([("foo".to_string(), "bar".to_string()), ("a".to_string(), "b".to_string())]); "ASCII")]
-#[tokio::test]
-async fn test_put_user_object_metadata_happy(object_metadata: HashMap<String, String>) {
+async fn put_user_object_metadata_happy_test<Client: ObjectClient>(
+    client: Client,
+    object_metadata: HashMap<String, String>,
+) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_user_object_metadata_happy");
-    let client_config = S3ClientConfig::new().endpoint_config(EndpointConfig::new(&get_test_region()));
-    let client = S3CrtClient::new(client_config).expect("could not create test client");
     let key = format!("{prefix}hello");
 
     let params = PutObjectSingleParams::new().object_metadata(object_metadata.clone());
@@ -162,14 +153,10 @@ async fn test_put_user_object_metadata_bad_header(object_metadata: HashMap<Strin
         .expect_err("header parsing should fail");
 }
 
-#[test_case("INTELLIGENT_TIERING")]
-#[test_case("GLACIER")]
-#[tokio::test]
 // S3 Express One Zone is a distinct storage class and can't be overridden
 #[cfg(not(feature = "s3express_tests"))]
-async fn test_put_object_storage_class(storage_class: &str) {
+async fn put_object_storage_class_test<Client: ObjectClient>(client: Client, storage_class: &str) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_object_abort");
-    let client = get_test_client();
     let key = format!("{prefix}hello");
 
     let mut rng = rand::thread_rng();
@@ -255,18 +242,13 @@ async fn check_sse(
 // Test that SSE settings, which were used to create a new object, are reflected in:
 // 1. HEAD_OBJECT response queried via AWS SDK;
 // 2. returned `PutObjectResult`.
-#[test_case(Some("aws:kms"), Some(get_test_kms_key_id()))]
-#[test_case(Some("aws:kms"), None)]
-#[test_case(Some("aws:kms:dsse"), Some(get_test_kms_key_id()))]
-#[test_case(Some("aws:kms:dsse"), None)]
-#[test_case(None, None)]
-#[test_case(Some("AES256"), None)]
-#[tokio::test]
 #[cfg(not(feature = "s3express_tests"))]
-async fn test_put_object_sse(sse_type: Option<&str>, kms_key_id: Option<String>) {
+async fn put_object_sse_test<Client: ObjectClient + Sync>(
+    client: Client,
+    sse_type: Option<&str>,
+    kms_key_id: Option<String>,
+) {
     let bucket = get_test_bucket();
-    let client_config = S3ClientConfig::new().endpoint_config(EndpointConfig::new(&get_test_region()));
-    let client = S3CrtClient::new(client_config).expect("could not create test client");
     let request_params = PutObjectSingleParams::new()
         .server_side_encryption(sse_type.map(|value| value.to_owned()))
         .ssekms_key_id(kms_key_id.to_owned());
@@ -282,10 +264,8 @@ async fn test_put_object_sse(sse_type: Option<&str>, kms_key_id: Option<String>)
     check_sse(&bucket, &key, sse_type, &kms_key_id, put_object_result).await;
 }
 
-#[tokio::test]
-async fn test_put_object_header() {
+async fn put_object_header_test<Client: ObjectClient>(client: Client) {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_put_object_header");
-    let client = get_test_client();
     let key = format!("{prefix}hello");
 
     let content_type = "application/json";
@@ -300,3 +280,56 @@ async fn test_put_object_header() {
 
     assert_eq!(Some(content_type), output.content_type());
 }
+
+/// Instantiate each client-agnostic test for the client created by `$client`
+macro_rules! client_tests {
+    ($module:ident, $client:expr) => {
+        mod $module {
+            use super::*;
+            use test_case::test_case;
+
+            #[test_case(None; "no checksum")]
+            #[test_case(Some(ChecksumAlgorithm::Crc32c); "crc32c")]
+            #[tokio::test]
+            async fn test_put_checksums(checksum_algorithm: Option<ChecksumAlgorithm>) {
+                put_checksums_test($client, checksum_algorithm).await;
+            }
+
+            #[test_case(HashMap::new(); "Empty")]
+            #[test_case(HashMap::from([("foo".to_string(), "bar".to_string()), ("a".to_string(), "b".to_string())]); "ASCII")]
+            #[tokio::test]
+            async fn test_put_user_object_metadata_happy(object_metadata: HashMap<String, String>) {
+                put_user_object_metadata_happy_test($client, object_metadata).await;
+            }
+
+            #[test_case("INTELLIGENT_TIERING")]
+            #[test_case("GLACIER")]
+            #[tokio::test]
+            #[cfg(not(feature = "s3express_tests"))]
+            async fn test_put_object_storage_class(storage_class: &str) {
+                put_object_storage_class_test($client, storage_class).await;
+            }
+
+            #[test_case(Some("aws:kms"), Some(get_test_kms_key_id()))]
+            #[test_case(Some("aws:kms"), None)]
+            #[test_case(Some("aws:kms:dsse"), Some(get_test_kms_key_id()))]
+            #[test_case(Some("aws:kms:dsse"), None)]
+            #[test_case(None, None)]
+            #[test_case(Some("AES256"), None)]
+            #[tokio::test]
+            #[cfg(not(feature = "s3express_tests"))]
+            async fn test_put_object_sse(sse_type: Option<&str>, kms_key_id: Option<String>) {
+                put_object_sse_test($client, sse_type, kms_key_id).await;
+            }
+
+            #[tokio::test]
+            async fn test_put_object_header() {
+                put_object_header_test($client).await;
+            }
+        }
+    };
+}
+
+client_tests!(crt, get_test_client());
+#[cfg(all(feature = "http_client", not(feature = "s3express_tests")))]
+client_tests!(http, get_test_http_client().await);
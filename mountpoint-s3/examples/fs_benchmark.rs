@@ -7,8 +7,9 @@ use mountpoint_s3_client::config::{EndpointConfig, S3ClientConfig};
 use mountpoint_s3_client::S3CrtClient;
 use mountpoint_s3_crt::common::rust_log_adapter::RustLogAdapter;
 use std::{
-    fs::{File, OpenOptions},
+    fs::{self, File, OpenOptions},
     io::{self, BufRead, BufReader},
+    path::Path,
     time::Instant,
 };
 use tempfile::tempdir;
@@ -38,7 +39,7 @@ fn main() -> io::Result<()> {
     const DEFAULT_BUF_CAP: usize = 128;
 
     let matches = Command::new("benchmark")
-        .about("Read a single file from a path and ignore its contents")
+        .about("Read a single file from a path and ignore its contents, or list a directory tree")
         .arg(Arg::new("bucket").required(true))
         .arg(
             Arg::new("file_path")
@@ -66,6 +67,24 @@ fn main() -> io::Result<()> {
                 .long("iterations")
                 .help("Number of times to download"),
         )
+        .arg(
+            Arg::new("walk")
+                .long("walk")
+                .help("Recursively list the directory at file_path instead of reading it, and report memory usage")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-unreferenced-inodes")
+                .long("max-unreferenced-inodes")
+                .help("Maximum number of inodes not referenced by the kernel to keep"),
+        )
+        .arg(
+            Arg::new("no-inode-eviction")
+                .long("no-inode-eviction")
+                .help("Keep every inode not referenced by the kernel, to compare memory usage with --walk")
+                .conflicts_with("max-unreferenced-inodes")
+                .action(ArgAction::SetTrue),
+        )
         .arg(Arg::new("region").long("region").default_value("us-east-1"))
         .get_matches();
 
@@ -81,9 +100,14 @@ fn main() -> io::Result<()> {
     let iterations = matches
         .get_one::<String>("iterations")
         .map(|s| s.parse::<usize>().expect("iterations must be a number"));
+    let walk = matches.get_flag("walk");
+    let max_unreferenced_inodes = matches
+        .get_one::<String>("max-unreferenced-inodes")
+        .map(|s| s.parse::<usize>().expect("max unreferenced inodes must be a number"))
+        .or(matches.get_flag("no-inode-eviction").then_some(usize::MAX));
     let region = matches.get_one::<String>("region").unwrap();
 
-    let session = mount_file_system(bucket_name, region, throughput_target_gbps);
+    let session = mount_file_system(bucket_name, region, throughput_target_gbps, max_unreferenced_inodes);
     let mountpoint = &session.mountpoint;
 
     #[cfg(not(target_os = "linux"))]
@@ -93,6 +117,22 @@ fn main() -> io::Result<()> {
 
     for i in 0..iterations.unwrap_or(1) {
         let file_path = mountpoint.join(file_path);
+        if walk {
+            let start = Instant::now();
+            let entries = walk_tree(&file_path)?;
+            let elapsed = start.elapsed();
+
+            println!(
+                "{}: listed {} entries in {:.2}s: {:.2} entries/s",
+                i,
+                entries,
+                elapsed.as_secs_f64(),
+                (entries as f64) / elapsed.as_secs_f64()
+            );
+            print_memory_usage(i);
+            continue;
+        }
+
         let file = if direct {
             let mut open = OpenOptions::new();
             open.read(true);
@@ -143,7 +183,40 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-fn mount_file_system(bucket_name: &str, region: &str, throughput_target_gbps: Option<f64>) -> BackgroundSession {
+/// Recursively list the directory at `path`, returning the number of entries found.
+fn walk_tree(path: &Path) -> io::Result<u64> {
+    let mut entries = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        entries += 1;
+        if entry.file_type()?.is_dir() {
+            entries += walk_tree(&entry.path())?;
+        }
+    }
+    Ok(entries)
+}
+
+/// Print the current and peak resident memory of this process, which includes the file system.
+fn print_memory_usage(iteration: usize) {
+    let Ok(status) = fs::read_to_string("/proc/self/status") else {
+        println!("{iteration}: memory usage not available");
+        return;
+    };
+    for line in status.lines() {
+        if let Some(rss) = line.strip_prefix("VmRSS:") {
+            println!("{}: resident memory {}", iteration, rss.trim());
+        } else if let Some(peak) = line.strip_prefix("VmHWM:") {
+            println!("{}: peak resident memory {}", iteration, peak.trim());
+        }
+    }
+}
+
+fn mount_file_system(
+    bucket_name: &str,
+    region: &str,
+    throughput_target_gbps: Option<f64>,
+    max_unreferenced_inodes: Option<usize>,
+) -> BackgroundSession {
     let temp_dir = tempdir().expect("Should be able to create temp directory");
     let mountpoint = temp_dir.path();
 
@@ -157,7 +230,10 @@ fn mount_file_system(bucket_name: &str, region: &str, throughput_target_gbps: Op
     let mut options = vec![MountOption::RO, MountOption::FSName("mountpoint-s3".to_string())];
     options.push(MountOption::AutoUnmount);
 
-    let filesystem_config = S3FilesystemConfig::default();
+    let mut filesystem_config = S3FilesystemConfig::default();
+    if let Some(max_unreferenced_inodes) = max_unreferenced_inodes {
+        filesystem_config.cache_config.unreferenced_inode_cache_size = max_unreferenced_inodes;
+    }
 
     println!(
         "Mounting bucket {} to path {}",
@@ -8,31 +8,154 @@
 //! sure we can't accidentally confuse this binary with a real `mount-s3` in any of our testing or
 //! release workflows, since real bucket names cannot start with this prefix.
 //!
+//! Alternatively, passing a `file://` URL as the --endpoint-url argument serves the bucket out of
+//! that local directory instead, using [LocalFsClient]. Objects written through the mount are
+//! stored as files in the directory, so they persist across mounts.
+//!
+//! Passing a `replay://` URL naming a trace file written by a `RecordingClient` instead serves the
+//! requests recorded in that trace using [ReplayClient], to reproduce the file system behavior of a
+//! recorded workload offline.
+//!
+//! Whichever backend is used, faults can be injected into its requests with [fault_injection_client]
+//! by setting the `MOCK_MOUNT_S3_FAULTS` environment variable to a JSON [FaultSpec], or
+//! `MOCK_MOUNT_S3_FAULTS_FILE` to the path of a file containing one.
+//!
 //! This binary is intended only for use in testing and development of Mountpoint.
 
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context as _};
+use clap::Parser;
 use futures::executor::ThreadPool;
 
 use mountpoint_s3::cli::CliArgs;
 use mountpoint_s3::s3::S3Personality;
+use mountpoint_s3_client::failure_client::fault_injection::{fault_injection_client, FaultSpec};
+use mountpoint_s3_client::local_fs_client::{LocalFsClient, LocalFsClientConfig};
 use mountpoint_s3_client::mock_client::throughput_client::ThroughputMockClient;
 use mountpoint_s3_client::mock_client::{MockClientConfig, MockObject};
+use mountpoint_s3_client::trace_client::ReplayClient;
 use mountpoint_s3_client::types::ETag;
+use mountpoint_s3_client::ObjectClient;
 
 fn main() -> anyhow::Result<()> {
-    mountpoint_s3::cli::main(create_mock_client)
+    // The client type is fixed by the builder we pass in, so look at the endpoint URL to pick one
+    let args = CliArgs::parse();
+    let faults = fault_spec()?;
+    if local_directory(&args).is_some() {
+        run(args, create_local_fs_client, faults)
+    } else if replay_trace(&args).is_some() {
+        run(args, create_replay_client, faults)
+    } else {
+        run(args, create_mock_client, faults)
+    }
 }
 
-fn create_mock_client(args: &CliArgs) -> anyhow::Result<(Arc<ThroughputMockClient>, ThreadPool, S3Personality)> {
-    // An extra little safety thing to make sure we can distinguish the real mount-s3 binary and
-    // this one. Buckets starting with "sthree-" are always invalid against real S3:
-    // https://docs.aws.amazon.com/AmazonS3/latest/userguide/bucketnamingrules.html
+/// Run `mount-s3` with the client from the given builder, injecting faults into it if a
+/// [FaultSpec] was given
+fn run<ClientBuilder, Client>(
+    args: CliArgs,
+    client_builder: ClientBuilder,
+    faults: Option<FaultSpec>,
+) -> anyhow::Result<()>
+where
+    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, ThreadPool, S3Personality)>,
+    Client: ObjectClient + Clone + Send + Sync + 'static,
+{
+    match faults {
+        Some(faults) => mountpoint_s3::cli::run(
+            |args| {
+                let (client, runtime, personality) = client_builder(args)?;
+                tracing::warn!("injecting faults into mock client requests");
+                Ok((Arc::new(fault_injection_client(client, faults)), runtime, personality))
+            },
+            args,
+        ),
+        None => mountpoint_s3::cli::run(client_builder, args),
+    }
+}
+
+/// The fault injection spec from the environment, if any
+fn fault_spec() -> anyhow::Result<Option<FaultSpec>> {
+    if let Some(json) = std::env::var_os("MOCK_MOUNT_S3_FAULTS") {
+        let json = json.to_str().context("MOCK_MOUNT_S3_FAULTS must be valid UTF-8")?;
+        let spec = FaultSpec::from_json(json).context("invalid MOCK_MOUNT_S3_FAULTS")?;
+        Ok(Some(spec))
+    } else if let Some(path) = std::env::var_os("MOCK_MOUNT_S3_FAULTS_FILE") {
+        let spec = FaultSpec::from_file(&path).with_context(|| format!("invalid fault spec file {path:?}"))?;
+        Ok(Some(spec))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The local directory to serve the bucket from, if the endpoint URL is a `file://` URL
+fn local_directory(args: &CliArgs) -> Option<PathBuf> {
+    let path = args.endpoint_url.as_deref()?.strip_prefix("file://")?;
+    Some(PathBuf::from(path))
+}
+
+/// The trace file to replay, if the endpoint URL is a `replay://` URL
+fn replay_trace(args: &CliArgs) -> Option<PathBuf> {
+    let path = args.endpoint_url.as_deref()?.strip_prefix("replay://")?;
+    Some(PathBuf::from(path))
+}
+
+/// An extra little safety thing to make sure we can distinguish the real mount-s3 binary and this
+/// one. Buckets starting with "sthree-" are always invalid against real S3:
+/// <https://docs.aws.amazon.com/AmazonS3/latest/userguide/bucketnamingrules.html>
+fn check_bucket_name(args: &CliArgs) -> anyhow::Result<()> {
     anyhow::ensure!(
         args.bucket_name.starts_with("sthree-"),
         "mock-mount-s3 bucket names must start with `sthree-`"
     );
+    Ok(())
+}
+
+fn s3_personality(args: &CliArgs) -> S3Personality {
+    if let Some(bucket_type) = &args.bucket_type {
+        bucket_type.to_personality()
+    } else {
+        S3Personality::Standard
+    }
+}
+
+fn create_local_fs_client(args: &CliArgs) -> anyhow::Result<(Arc<LocalFsClient>, ThreadPool, S3Personality)> {
+    check_bucket_name(args)?;
+
+    let root = local_directory(args).expect("endpoint URL should be a local directory");
+    tracing::warn!("using local directory {} as the bucket", root.display());
+
+    let config = LocalFsClientConfig {
+        bucket: args.bucket_name.clone(),
+        root,
+        part_size: args.part_size as usize,
+        enable_backpressure: true,
+        initial_read_window_size: 1024 * 1024 + 128 * 1024, // matching real MP
+    };
+    let client = LocalFsClient::new(config).context("failed to open local directory")?;
+
+    let runtime = ThreadPool::builder().name_prefix("runtime").create()?;
+
+    Ok((Arc::new(client), runtime, s3_personality(args)))
+}
+
+fn create_replay_client(args: &CliArgs) -> anyhow::Result<(Arc<ReplayClient>, ThreadPool, S3Personality)> {
+    check_bucket_name(args)?;
+
+    let path = replay_trace(args).expect("endpoint URL should be a trace file");
+    tracing::warn!("replaying trace {}", path.display());
+
+    let client = ReplayClient::from_file(&path).context("failed to load trace")?;
+
+    let runtime = ThreadPool::builder().name_prefix("runtime").create()?;
+
+    Ok((Arc::new(client), runtime, s3_personality(args)))
+}
+
+fn create_mock_client(args: &CliArgs) -> anyhow::Result<(Arc<ThroughputMockClient>, ThreadPool, S3Personality)> {
+    check_bucket_name(args)?;
 
     tracing::warn!("using mock client");
 
@@ -54,12 +177,6 @@ fn create_mock_client(args: &CliArgs) -> anyhow::Result<(Arc<ThroughputMockClien
 
     let runtime = ThreadPool::builder().name_prefix("runtime").create()?;
 
-    let s3_personality = if let Some(bucket_type) = &args.bucket_type {
-        bucket_type.to_personality()
-    } else {
-        S3Personality::Standard
-    };
-
     // Pre-populate the bucket with some interesting file sizes and a little structure
     for expt in 0..10 {
         let size = 2000 * 10u64.pow(expt);
@@ -83,5 +200,5 @@ fn create_mock_client(args: &CliArgs) -> anyhow::Result<(Arc<ThroughputMockClien
         MockObject::from_bytes(b"hello world", ETag::for_tests()),
     );
 
-    Ok((Arc::new(client), runtime, s3_personality))
+    Ok((Arc::new(client), runtime, s3_personality(args)))
 }
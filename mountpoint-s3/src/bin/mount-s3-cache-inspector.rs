@@ -0,0 +1,197 @@
+//! A helper binary for inspecting and maintaining a Mountpoint disk cache directory.
+//!
+//! It reports the objects with blocks in the cache, finds blocks that are corrupt or were written by
+//! another version of Mountpoint, and can remove blocks by key prefix or age. Blocks can only be
+//! removed while no Mountpoint process is using the cache directory.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use clap::Parser;
+use humansize::make_format;
+use mountpoint_s3::data_cache::{
+    object_coverage, BlockReport, BlockStatus, CacheInspector, EncryptionKey, ObjectCoverage, PurgeFilter,
+};
+use serde_json::json;
+
+#[derive(Parser, Debug)]
+#[clap(about = "Inspect and maintain a Mountpoint disk cache directory")]
+struct CliArgs {
+    #[clap(
+        help = "Cache directory to inspect, as passed to --cache when mounting",
+        value_name = "CACHE_DIRECTORY"
+    )]
+    cache_dir: PathBuf,
+
+    #[clap(long, help = "Print the report as JSON")]
+    json: bool,
+
+    #[clap(
+        long,
+        help = "Check the data of encrypted blocks with the key in the given file",
+        value_name = "FILE"
+    )]
+    encryption_key_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Remove the blocks of objects with keys starting with the given prefix",
+        value_name = "PREFIX"
+    )]
+    purge_prefix: Option<String>,
+
+    #[clap(
+        long,
+        help = "Remove the blocks written more than the given number of seconds ago",
+        value_name = "SECONDS"
+    )]
+    purge_older_than: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Remove corrupt blocks and blocks written by other versions of Mountpoint"
+    )]
+    purge_invalid: bool,
+}
+
+impl CliArgs {
+    fn purge_filter(&self) -> Option<PurgeFilter> {
+        if self.purge_prefix.is_none() && self.purge_older_than.is_none() && !self.purge_invalid {
+            return None;
+        }
+        Some(PurgeFilter {
+            prefix: self.purge_prefix.clone(),
+            older_than: self.purge_older_than.map(Duration::from_secs),
+            invalid: self.purge_invalid,
+        })
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = CliArgs::parse();
+
+    let mut inspector = CacheInspector::new(&args.cache_dir);
+    if let Some(key_file) = &args.encryption_key_file {
+        let key = EncryptionKey::from_file(key_file)
+            .with_context(|| format!("failed to load cache encryption key from {}", key_file.display()))?;
+        inspector = inspector.with_encryption_key(key);
+    }
+
+    let removed = match args.purge_filter() {
+        Some(filter) => Some(
+            inspector
+                .purge(&filter)
+                .context("failed to purge the cache directory")?,
+        ),
+        None => None,
+    };
+    let blocks = inspector.scan().context("failed to scan the cache directory")?;
+    let objects = object_coverage(&blocks);
+
+    if args.json {
+        print_json(&blocks, &objects, removed.as_deref())?;
+    } else {
+        print_report(&blocks, &objects, removed.as_deref());
+    }
+    Ok(())
+}
+
+fn print_json(
+    blocks: &[BlockReport],
+    objects: &[ObjectCoverage],
+    removed: Option<&[BlockReport]>,
+) -> anyhow::Result<()> {
+    let invalid: Vec<_> = blocks
+        .iter()
+        .filter(|block| !matches!(block.status, BlockStatus::Valid(_)))
+        .collect();
+    let mut report = json!({
+        "block_count": blocks.len(),
+        "size": blocks.iter().map(|block| block.size).sum::<u64>(),
+        "objects": objects,
+        "invalid_blocks": invalid,
+    });
+    if let Some(removed) = removed {
+        report["removed_blocks"] = json!(removed);
+    }
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn print_report(blocks: &[BlockReport], objects: &[ObjectCoverage], removed: Option<&[BlockReport]>) {
+    let format_size = make_format(humansize::BINARY);
+
+    if let Some(removed) = removed {
+        let size: u64 = removed.iter().map(|block| block.size).sum();
+        println!("Removed {} blocks ({})", removed.len(), format_size(size));
+        println!();
+    }
+
+    let size: u64 = blocks.iter().map(|block| block.size).sum();
+    let (mut valid, mut corrupt, mut foreign, mut unverified) = (0, 0, 0, 0);
+    for block in blocks {
+        match &block.status {
+            BlockStatus::Valid(info) => {
+                valid += 1;
+                if !info.data_verified {
+                    unverified += 1;
+                }
+            }
+            BlockStatus::Corrupt { .. } => corrupt += 1,
+            BlockStatus::ForeignVersion { .. } => foreign += 1,
+        }
+    }
+    println!(
+        "{} blocks ({}) for {} objects",
+        blocks.len(),
+        format_size(size),
+        objects.len()
+    );
+    println!("  valid: {valid}");
+    if unverified > 0 {
+        println!("    of which encrypted and not verified: {unverified} (use --encryption-key-file to verify them)");
+    }
+    println!("  corrupt: {corrupt}");
+    println!("  other versions: {foreign}");
+
+    if !objects.is_empty() {
+        println!();
+        println!("Objects:");
+        for object in objects {
+            let ranges: Vec<_> = object
+                .block_ranges
+                .iter()
+                .map(|(start, end)| {
+                    if start == end {
+                        start.to_string()
+                    } else {
+                        format!("{start}-{end}")
+                    }
+                })
+                .collect();
+            println!(
+                "  {} (etag {}): {} blocks ({}), blocks {}",
+                object.s3_key,
+                object.etag,
+                object.block_count,
+                format_size(object.size),
+                ranges.join(",")
+            );
+        }
+    }
+
+    if corrupt + foreign > 0 {
+        println!();
+        println!("Invalid blocks:");
+        for block in blocks {
+            match &block.status {
+                BlockStatus::Valid(_) => {}
+                BlockStatus::Corrupt { reason } => println!("  {}: {reason}", block.path.display()),
+                BlockStatus::ForeignVersion { version } => {
+                    println!("  {}: written with cache version {version}", block.path.display())
+                }
+            }
+        }
+    }
+}
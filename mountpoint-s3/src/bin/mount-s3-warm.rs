@@ -0,0 +1,8 @@
+//! A companion binary to `mount-s3` that fetches the objects under a prefix into the data cache
+//! ahead of time, so that a later (or already running) mount can read them from the cache.
+//!
+//! It accepts the same arguments as `mount-s3`, except for the mount point.
+
+fn main() -> anyhow::Result<()> {
+    mountpoint_s3::cli::warm_main(mountpoint_s3::cli::create_s3_client)
+}
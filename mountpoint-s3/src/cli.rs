@@ -5,15 +5,18 @@ use std::io::{Read, Write};
 use std::num::NonZeroUsize;
 use std::os::fd::AsRawFd;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context as _};
-use clap::{value_parser, Parser, ValueEnum};
+use clap::{value_parser, ArgGroup, Args, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use fuser::{MountOption, Session};
 use futures::task::Spawn;
+use humansize::make_format;
 use mountpoint_s3_client::config::{AddressingStyle, EndpointConfig, S3ClientAuthConfig, S3ClientConfig};
 use mountpoint_s3_client::error::ObjectClientError;
 use mountpoint_s3_client::instance_info::InstanceInfo;
+use mountpoint_s3_client::trace_client::{RecordingClient, TraceBodies};
 use mountpoint_s3_client::user_agent::UserAgent;
 use mountpoint_s3_client::{ObjectClient, S3CrtClient, S3RequestError};
 use mountpoint_s3_crt::auth::signing_config::SigningAlgorithm;
@@ -27,15 +30,28 @@ use regex::Regex;
 use sysinfo::{RefreshKind, System};
 
 use crate::build_info;
-use crate::data_cache::{CacheLimit, DiskDataCache, DiskDataCacheConfig, ExpressDataCache, ManagedCacheDir};
-use crate::fs::{CacheConfig, S3FilesystemConfig, ServerSideEncryption, TimeToLive};
+use crate::data_cache::{
+    CacheLimit, CachePolicy, CacheTierWrites, CompressionCodec, DiskDataCache, DiskDataCacheConfig, EncryptionKey,
+    ExpressDataCache, InMemoryDataCache, ManagedCacheDir, TieredDataCache,
+};
+use crate::events::{EventListener, EventSourceConfig};
+use crate::fs::{
+    CacheConfig, PersistentMetadataCache, PersistentMetadataCacheConfig, PrefixTtl, S3FilesystemConfig,
+    ServerSideEncryption, TimeToLive,
+};
+use crate::fuse::notify::spawn_invalidation_thread;
 use crate::fuse::session::FuseSession;
 use crate::fuse::S3FuseFilesystem;
 use crate::logging::{init_logging, prepare_log_file_name, LoggingConfig};
-use crate::mem_limiter::MINIMUM_MEM_LIMIT;
-use crate::prefetch::{caching_prefetch, default_prefetch, Prefetch};
+use crate::manifest::Manifest;
+use crate::mem_limiter::{MemoryLimiter, MINIMUM_MEM_LIMIT};
+use crate::prefetch::{
+    caching_prefetch_with_policy, default_prefetch, CacheWarmer, CacheWarmerConfig, Prefetch, WarmProgress,
+};
 use crate::prefix::Prefix;
 use crate::s3::S3Personality;
+use crate::sync::async_channel;
+use crate::upload::WriteThroughCache;
 use crate::{autoconfigure, metrics};
 
 const CLIENT_OPTIONS_HEADER: &str = "Client options";
@@ -48,6 +64,7 @@ const ADVANCED_OPTIONS_HEADER: &str = "Advanced options";
 
 #[derive(Parser, Debug)]
 #[clap(name = "mount-s3", about = "Mountpoint for Amazon S3", version = build_info::FULL_VERSION)]
+#[clap(group(ArgGroup::new("cache_group").multiple(true)))]
 pub struct CliArgs {
     #[clap(help = "Name of bucket to mount", value_parser = parse_bucket_name)]
     pub bucket_name: String,
@@ -111,6 +128,16 @@ pub struct CliArgs {
     #[clap(long, help = "Set the storage class for new objects", help_heading = BUCKET_OPTIONS_HEADER)]
     pub storage_class: Option<String>,
 
+    #[clap(
+        long,
+        help = "Serve the bucket's directory structure from the given manifest file (an S3 Inventory manifest.json, \
+                or a JSON or CSV list of keys, sizes and ETags) instead of listing the bucket",
+        help_heading = BUCKET_OPTIONS_HEADER,
+        value_name = "FILE",
+        requires = "read_only",
+    )]
+    pub manifest: Option<PathBuf>,
+
     #[clap(
         long,
         help = "Allow delete operations on file system",
@@ -125,6 +152,24 @@ pub struct CliArgs {
     )]
     pub allow_overwrite: bool,
 
+    #[clap(
+        long,
+        help = "Show keys containing empty, '.' or '..' path components under percent-encoded names \
+                instead of hiding them. '%' is encoded in all names, and names over 255 bytes are shortened.",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub escape_names: bool,
+
+    #[clap(
+        long,
+        help = "Show objects shadowed by a directory of the same name under their name followed by this suffix \
+                (for example, '.__file__') instead of hiding them",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        value_name = "SUFFIX",
+        value_parser = parse_shadowed_file_suffix
+    )]
+    pub shadowed_file_suffix: Option<String>,
+
     #[clap(long, help = "Automatically unmount on exit", help_heading = MOUNT_OPTIONS_HEADER)]
     pub auto_unmount: bool,
 
@@ -199,6 +244,17 @@ pub struct CliArgs {
     )]
     pub write_part_size: Option<u64>,
 
+    #[clap(
+        long,
+        help = "Number of ranges of keys to list concurrently when listing a directory. \
+            Only has an effect for general purpose buckets.",
+        value_name = "N",
+        default_value = "1",
+        value_parser = value_parser!(u64).range(1..=64),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub list_concurrency: u64,
+
     #[clap(
         long,
         help = "Owner UID [default: current user's UID]",
@@ -269,6 +325,14 @@ pub struct CliArgs {
     )]
     pub no_log: bool,
 
+    #[clap(
+        long,
+        help = "Record every request to S3, with its response, to a trace file",
+        help_heading = LOGGING_OPTIONS_HEADER,
+        value_name = "FILE",
+    )]
+    pub record_trace: Option<PathBuf>,
+
     #[clap(
         long,
         help = "Enable caching of object content to the given directory and set metadata TTL to 60 seconds",
@@ -280,12 +344,37 @@ pub struct CliArgs {
 
     #[clap(
         long,
-        help = "Time-to-live (TTL) for cached metadata in seconds [default: minimal, or 60 seconds if --cache is set]",
+        help = "Time-to-live (TTL) for cached metadata in seconds [default: minimal, or 60 seconds if a data cache is enabled]",
         value_name = "SECONDS|indefinite|minimal",
         help_heading = CACHING_OPTIONS_HEADER,
     )]
     pub metadata_ttl: Option<TimeToLive>,
 
+    #[clap(
+        long,
+        help = "Time-to-live (TTL) for cached lookups of files that do not exist, in seconds [default: same as --metadata-ttl]",
+        value_name = "SECONDS|indefinite|minimal",
+        help_heading = CACHING_OPTIONS_HEADER,
+    )]
+    pub negative_metadata_ttl: Option<TimeToLive>,
+
+    #[clap(
+        long,
+        help = "Time-to-live (TTL) for cached directory listings, in seconds [default: same as --metadata-ttl]",
+        value_name = "SECONDS|indefinite|minimal",
+        help_heading = CACHING_OPTIONS_HEADER,
+    )]
+    pub dir_listing_ttl: Option<TimeToLive>,
+
+    #[clap(
+        long,
+        help = "Time-to-live (TTL) for all cached metadata of the keys under a prefix, overriding the other TTLs. \
+                Can be repeated, and the longest matching prefix applies.",
+        value_name = "PREFIX=SECONDS|indefinite|minimal",
+        help_heading = CACHING_OPTIONS_HEADER,
+    )]
+    pub metadata_ttl_override: Vec<PrefixTtl>,
+
     #[clap(
         long,
         help = "Maximum size of the cache directory in MiB [default: preserve 5% of available space]",
@@ -296,10 +385,94 @@ pub struct CliArgs {
     )]
     pub max_cache_size: Option<u64>,
 
+    #[clap(
+        long,
+        help = "Keep the content of the cache directory when Mountpoint exits, and reuse it at the next mount",
+        help_heading = CACHING_OPTIONS_HEADER,
+        requires = "cache",
+    )]
+    pub persistent_cache: bool,
+
+    #[clap(
+        long,
+        help = "Allow other Mountpoint processes to use the cache directory at the same time, sharing its content and size limit. \
+                The content of the cache directory is kept when Mountpoint exits",
+        help_heading = CACHING_OPTIONS_HEADER,
+        requires = "cache",
+    )]
+    pub shared_cache: bool,
+
+    #[clap(
+        long,
+        help = "Keep directory listings and lookups in the cache directory, and reuse them at the next mount \
+                while they are within the metadata TTL",
+        help_heading = CACHING_OPTIONS_HEADER,
+        requires = "cache",
+    )]
+    pub persistent_metadata_cache: bool,
+
+    #[clap(
+        long,
+        help = "Maximum size in MiB of the directory listings and lookups kept with --persistent-metadata-cache \
+                [default: 64]",
+        help_heading = CACHING_OPTIONS_HEADER,
+        value_name = "MiB",
+        value_parser = value_parser!(u64).range(1..),
+        requires = "persistent_metadata_cache",
+    )]
+    pub persistent_metadata_cache_max_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Invalidate cached metadata and data of objects as they change, using the S3 event notifications \
+                read from a JSON-lines file (file:PATH) or received on a Unix socket (unix:PATH)",
+        help_heading = CACHING_OPTIONS_HEADER,
+        value_name = "file:PATH|unix:PATH",
+    )]
+    pub event_source: Option<EventSourceConfig>,
+
+    #[clap(
+        long,
+        help = "Compress blocks in the cache directory with the given codec",
+        help_heading = CACHING_OPTIONS_HEADER,
+        value_name = "CODEC",
+        requires = "cache",
+    )]
+    pub cache_compression: Option<CompressionCodec>,
+
+    #[clap(
+        long,
+        help = "Encrypt the content of the cache directory with a key generated at mount time and only kept in memory",
+        help_heading = CACHING_OPTIONS_HEADER,
+        requires = "cache",
+        conflicts_with = "cache_encryption_key_file",
+    )]
+    pub encrypt_cache: bool,
+
+    #[clap(
+        long,
+        help = "Encrypt the content of the cache directory with the 256-bit key in the given file, \
+                as raw bytes or hexadecimal characters",
+        help_heading = CACHING_OPTIONS_HEADER,
+        value_name = "FILE",
+        requires = "cache",
+    )]
+    pub cache_encryption_key_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Enable caching of object content in memory, up to the given size in MiB, and set metadata TTL to 60 seconds",
+        help_heading = CACHING_OPTIONS_HEADER,
+        value_name = "MiB",
+        value_parser = value_parser!(u64).range(1..),
+        group = "cache_group",
+    )]
+    pub cache_memory: Option<u64>,
+
     #[cfg(feature = "block_size")]
     #[clap(
         long,
-        help = "Size of a cache block in KiB [Default: 1024 (1 MiB) for disk cache, 512 (512 KiB) for S3 Express cache]",
+        help = "Size of a cache block in KiB [Default: 1024 (1 MiB) for disk and memory cache, 512 (512 KiB) for S3 Express cache]",
         help_heading = CACHING_OPTIONS_HEADER,
         value_name = "KiB",
         requires = "cache_group"
@@ -317,6 +490,45 @@ pub struct CliArgs {
     )]
     pub cache_express: Option<String>,
 
+    #[clap(
+        long,
+        help = "Comma-separated list of cache tiers to store newly fetched object content in, \
+                when combining multiple caches [default: all enabled caches]",
+        help_heading = CACHING_OPTIONS_HEADER,
+        value_name = "TIERS",
+        value_delimiter = ',',
+        requires = "cache_group",
+    )]
+    pub cache_write_tiers: Option<Vec<CacheTier>>,
+
+    #[clap(
+        long,
+        help = "Insert the content of files written through Mountpoint into the data cache, \
+                so that they can be read back without downloading them from S3",
+        help_heading = CACHING_OPTIONS_HEADER,
+        requires = "cache_group",
+    )]
+    pub cache_writes: bool,
+
+    #[clap(
+        long,
+        help = "Maximum size in MiB of the files inserted into the data cache with --cache-writes [default: 64]",
+        help_heading = CACHING_OPTIONS_HEADER,
+        value_name = "MiB",
+        value_parser = value_parser!(u64).range(1..),
+        requires = "cache_writes",
+    )]
+    pub cache_writes_max_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Apply the cache policy in the given JSON file, to exclude, pin or expire the content of objects by prefix",
+        help_heading = CACHING_OPTIONS_HEADER,
+        value_name = "FILE",
+        requires = "cache_group",
+    )]
+    pub cache_policy: Option<PathBuf>,
+
     #[clap(
         long,
         help = "Configure a string to be prepended to the 'User-Agent' HTTP request header for all S3 requests",
@@ -406,6 +618,47 @@ impl ValueEnum for UploadChecksums {
     }
 }
 
+/// A data cache that can be combined with others, from fastest to slowest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheTier {
+    Memory,
+    Disk,
+    Express,
+}
+
+impl CacheTier {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Memory => "memory",
+            Self::Disk => "disk",
+            Self::Express => "express",
+        }
+    }
+}
+
+impl ValueEnum for CacheTier {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Memory, Self::Disk, Self::Express]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
+
+impl ValueEnum for CompressionCodec {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Lz4, Self::Zstd]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            Self::Lz4 => Some(clap::builder::PossibleValue::new("lz4")),
+            Self::Zstd => Some(clap::builder::PossibleValue::new("zstd")),
+        }
+    }
+}
+
 impl CliArgs {
     fn addressing_style(&self) -> AddressingStyle {
         if self.force_path_style {
@@ -427,7 +680,30 @@ impl CliArgs {
         if self.cache_express_bucket_name().is_some() {
             return 512 * 1024; // 512 KiB block size - default for express cache
         }
-        1024 * 1024 // 1 MiB block size - default for disk cache
+        1024 * 1024 // 1 MiB block size - default for disk and memory cache
+    }
+
+    /// The data cache tiers enabled by the arguments, from fastest to slowest
+    fn enabled_cache_tiers(&self) -> Vec<CacheTier> {
+        let mut tiers = Vec::new();
+        if self.cache_memory.is_some() {
+            tiers.push(CacheTier::Memory);
+        }
+        if self.cache.is_some() && self.max_cache_size != Some(0) {
+            tiers.push(CacheTier::Disk);
+        }
+        if self.cache_express_bucket_name().is_some() {
+            tiers.push(CacheTier::Express);
+        }
+        tiers
+    }
+
+    /// Whether newly fetched object content should be stored in the given cache tier
+    fn cache_tier_writes(&self, tier: CacheTier) -> CacheTierWrites {
+        match &self.cache_write_tiers {
+            Some(tiers) if !tiers.contains(&tier) => CacheTierWrites::Disabled,
+            _ => CacheTierWrites::Enabled,
+        }
     }
 
     fn cache_express_bucket_name(&self) -> Option<&str> {
@@ -507,13 +783,61 @@ impl CliArgs {
     }
 }
 
+/// Arguments of `mount-s3-warm`, in addition to the [CliArgs] other than the mount point.
+#[derive(Args, Debug)]
+pub struct WarmArgs {
+    #[clap(
+        long,
+        help = "Number of objects to fetch concurrently",
+        value_name = "N",
+        default_value = "16",
+        value_parser = value_parser!(u64).range(1..),
+    )]
+    pub concurrency: u64,
+
+    #[clap(
+        long,
+        help = "Maximum rate to fetch object content at, in MiB per second [default: no limit]",
+        value_name = "MiB/s",
+        value_parser = value_parser!(u64).range(1..),
+    )]
+    pub throughput_limit: Option<u64>,
+}
+
+impl WarmArgs {
+    /// Parse the arguments of `mount-s3-warm`, which are the same as when mounting (and accept the
+    /// same caching options), except that no mount point is needed.
+    fn parse_with_cli_args() -> (CliArgs, WarmArgs) {
+        let command =
+            CliArgs::command().mut_arg("mount_point", |arg| arg.required(false).hide(true).default_value("."));
+        let command = WarmArgs::augment_args(command)
+            .name("mount-s3-warm")
+            .bin_name("mount-s3-warm")
+            .about("Fetch the objects under a prefix of a bucket into the Mountpoint data cache");
+        let matches = command.get_matches();
+        let cli_args = CliArgs::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+        let warm_args = WarmArgs::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+        (cli_args, warm_args)
+    }
+}
+
 pub fn main<ClientBuilder, Client, Runtime>(client_builder: ClientBuilder) -> anyhow::Result<()>
 where
     ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality)>,
     Client: ObjectClient + Clone + Send + Sync + 'static,
     Runtime: Spawn + Clone + Send + Sync + 'static,
 {
-    let args = CliArgs::parse();
+    run(client_builder, CliArgs::parse())
+}
+
+/// Mount the file system with arguments that were already parsed, for binaries that look at the
+/// arguments to choose a client builder.
+pub fn run<ClientBuilder, Client, Runtime>(client_builder: ClientBuilder, args: CliArgs) -> anyhow::Result<()>
+where
+    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality)>,
+    Client: ObjectClient + Clone + Send + Sync + 'static,
+    Runtime: Spawn + Clone + Send + Sync + 'static,
+{
     let successful_mount_msg = format!(
         "{} is mounted at {}",
         args.bucket_description(),
@@ -541,16 +865,12 @@ where
         // Prepare logging configuration up front, so the values are shared between the two processes.
         let logging_config = args.make_logging_config();
 
-        // Don't share args across the fork. It should just be plain data, so probably fine to be
-        // copy-on-write, but just in case we ever add something more fancy to the struct.
-        drop(args);
-
         // SAFETY: Child process has full ownership of its resources.
         // There is no shared data between parent and child processes other than logging configuration.
         let pid = unsafe { nix::unistd::fork() };
         match pid.expect("Failed to fork mount process") {
             ForkResult::Child => {
-                let args = CliArgs::parse();
+                // Only the child uses the arguments, which are plain data and so safe to use after the fork
                 init_logging(logging_config).context("failed to initialize logging")?;
 
                 let _metrics = metrics::install();
@@ -654,6 +974,103 @@ where
     Ok(())
 }
 
+/// Fetch the objects under the prefix given by the arguments into the data cache, so that they can
+/// be read from the cache by a later (or already running) mount.
+pub fn warm_main<ClientBuilder, Client, Runtime>(client_builder: ClientBuilder) -> anyhow::Result<()>
+where
+    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality)>,
+    Client: ObjectClient + Clone + Send + Sync + 'static,
+    Runtime: Spawn + Clone + Send + Sync + 'static,
+{
+    let (mut args, warm_args) = WarmArgs::parse_with_cli_args();
+    // Log like a foreground mount, since the command runs until the cache is warm
+    args.foreground = true;
+    init_logging(args.make_logging_config()).context("failed to initialize logging")?;
+
+    let _metrics = metrics::install();
+
+    tracing::info!("mount-s3-warm {}", build_info::FULL_VERSION);
+    tracing::debug!("{:?} {:?}", args, warm_args);
+
+    if args.record_trace.is_some() {
+        return Err(anyhow!("--record-trace is only supported when mounting"));
+    }
+    validate_warm_cache_args(&args)?;
+    validate_cache_write_tiers(&args)?;
+    validate_cache_encryption(&args)?;
+
+    let (client, runtime, _s3_personality) = client_builder(&args)?;
+    let cache_policy = load_cache_policy(&args)?;
+    let (cache, managed_cache_dir, _memory_cache) = create_data_cache(&args, &client, &cache_policy)?;
+    tracing::debug!(?cache, "warming data cache");
+
+    let sys = System::new_with_specifics(RefreshKind::everything());
+    let mem_limit = ((sys.total_memory() as f64 * 0.95) as u64).max(MINIMUM_MEM_LIMIT);
+    let mem_limiter = Arc::new(MemoryLimiter::new(client.clone(), mem_limit));
+
+    let config = CacheWarmerConfig {
+        concurrency: warm_args.concurrency as usize,
+        max_throughput: warm_args.throughput_limit.map(|limit| limit * 1024 * 1024),
+        policy: cache_policy,
+        ..Default::default()
+    };
+    let prefix = args.prefix.clone().unwrap_or_default();
+    let warmer = CacheWarmer::new(client, cache, runtime, &args.bucket_name, &prefix, mem_limiter, config);
+    let start = Instant::now();
+    let progress = futures::executor::block_on(warmer.run(|progress| {
+        println!("{}", format_warm_progress(progress, start.elapsed()));
+    }))
+    .context("failed to list objects to warm")?;
+    drop(warmer);
+    drop(managed_cache_dir);
+
+    if progress.objects_failed > 0 {
+        return Err(anyhow!(
+            "failed to warm {} of {} objects",
+            progress.objects_failed,
+            progress.objects_total
+        ));
+    }
+    println!("finished warming the cache for {}", args.bucket_description());
+    Ok(())
+}
+
+/// Warming is only useful with caches that outlive the `mount-s3-warm` process.
+fn validate_warm_cache_args(args: &CliArgs) -> anyhow::Result<()> {
+    if args.cache_memory.is_some() {
+        return Err(anyhow!(
+            "--cache-memory can't be warmed, as the memory cache is lost when Mountpoint exits"
+        ));
+    }
+    let disk_cache_enabled = args.cache.is_some() && args.max_cache_size != Some(0);
+    if disk_cache_enabled && !args.persistent_cache && !args.shared_cache {
+        return Err(anyhow!(
+            "warming --cache requires --persistent-cache or --shared-cache, otherwise the cache is removed when Mountpoint exits"
+        ));
+    }
+    if !disk_cache_enabled && args.cache_express_bucket_name().is_none() {
+        return Err(anyhow!("no data cache is enabled to warm"));
+    }
+    Ok(())
+}
+
+fn format_warm_progress(progress: &WarmProgress, elapsed: Duration) -> String {
+    let formatter = make_format(humansize::BINARY);
+    let throughput = progress.bytes_fetched as f64 / elapsed.as_secs_f64().max(0.001);
+    let mut message = format!(
+        "warmed {}/{} objects, {}/{} ({}/s)",
+        progress.objects_warmed,
+        progress.objects_total,
+        formatter(progress.bytes_fetched),
+        formatter(progress.bytes_total),
+        formatter(throughput as u64),
+    );
+    if progress.objects_failed > 0 {
+        message.push_str(&format!(", {} failed", progress.objects_failed));
+    }
+    message
+}
+
 /// Create a real S3 client
 pub fn create_s3_client(args: &CliArgs) -> anyhow::Result<(S3CrtClient, EventLoopGroup, S3Personality)> {
     const DEFAULT_TARGET_THROUGHPUT: f64 = 10.0;
@@ -696,13 +1113,58 @@ pub fn create_s3_client(args: &CliArgs) -> anyhow::Result<(S3CrtClient, EventLoo
     if args.read_only {
         user_agent.value("mp-readonly");
     }
+    if args.manifest.is_some() {
+        user_agent.value("mp-manifest");
+    }
+    if args.escape_names {
+        user_agent.value("mp-escape-names");
+    }
+    if args.shadowed_file_suffix.is_some() {
+        user_agent.value("mp-shadowed-file-suffix");
+    }
 
     if args.cache.is_some() {
         user_agent.value("mp-cache");
     }
+    if args.persistent_cache {
+        user_agent.value("mp-cache-persistent");
+    }
+    if args.shared_cache {
+        user_agent.value("mp-cache-shared");
+    }
+    if args.persistent_metadata_cache {
+        user_agent.value("mp-metadata-cache-persistent");
+    }
+    if args.event_source.is_some() {
+        user_agent.value("mp-events");
+    }
+    if args.cache_compression.is_some() {
+        user_agent.value("mp-cache-compression");
+    }
+    if args.encrypt_cache || args.cache_encryption_key_file.is_some() {
+        user_agent.value("mp-cache-encryption");
+    }
+    if args.cache_memory.is_some() {
+        user_agent.value("mp-cache-memory");
+    }
+    if args.cache_writes {
+        user_agent.value("mp-cache-writes");
+    }
+    if args.cache_policy.is_some() {
+        user_agent.value("mp-cache-policy");
+    }
     if let Some(ttl) = args.metadata_ttl {
         user_agent.key_value("mp-cache-ttl", &ttl.to_string());
     }
+    if let Some(ttl) = args.negative_metadata_ttl {
+        user_agent.key_value("mp-cache-negative-ttl", &ttl.to_string());
+    }
+    if let Some(ttl) = args.dir_listing_ttl {
+        user_agent.key_value("mp-cache-listing-ttl", &ttl.to_string());
+    }
+    if !args.metadata_ttl_override.is_empty() {
+        user_agent.value("mp-cache-ttl-override");
+    }
     if let Some(interfaces) = &args.bind {
         user_agent.key_value("mp-nw-interfaces", &interfaces.len().to_string());
     }
@@ -758,6 +1220,26 @@ pub fn create_s3_client(args: &CliArgs) -> anyhow::Result<(S3CrtClient, EventLoo
 }
 
 fn mount<ClientBuilder, Client, Runtime>(args: CliArgs, client_builder: ClientBuilder) -> anyhow::Result<FuseSession>
+where
+    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality)>,
+    Client: ObjectClient + Clone + Send + Sync + 'static,
+    Runtime: Spawn + Clone + Send + Sync + 'static,
+{
+    let Some(path) = &args.record_trace else {
+        return mount_with_client(args, client_builder);
+    };
+    let trace = File::create(path).with_context(|| format!("failed to create trace file {}", path.display()))?;
+    mount_with_client(args, move |args: &CliArgs| {
+        let (client, runtime, s3_personality) = client_builder(args)?;
+        let client = RecordingClient::new(client, trace, TraceBodies::default());
+        Ok((client, runtime, s3_personality))
+    })
+}
+
+fn mount_with_client<ClientBuilder, Client, Runtime>(
+    args: CliArgs,
+    client_builder: ClientBuilder,
+) -> anyhow::Result<FuseSession>
 where
     ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality)>,
     Client: ObjectClient + Clone + Send + Sync + 'static,
@@ -768,6 +1250,8 @@ where
 
     validate_mount_point(&args.mount_point)?;
     validate_sse_args(args.sse.as_deref(), args.sse_kms_key_id.as_deref())?;
+    validate_cache_write_tiers(&args)?;
+    validate_cache_encryption(&args)?;
 
     let (client, runtime, s3_personality) = client_builder(&args)?;
 
@@ -792,7 +1276,19 @@ where
     filesystem_config.storage_class = args.storage_class.clone();
     filesystem_config.allow_delete = args.allow_delete;
     filesystem_config.allow_overwrite = args.allow_overwrite;
+    filesystem_config.escape_names = args.escape_names;
+    filesystem_config.shadowed_file_suffix = args.shadowed_file_suffix.clone();
     filesystem_config.s3_personality = s3_personality;
+    filesystem_config.list_concurrency = args.list_concurrency as usize;
+    if args.list_concurrency > 1 && !s3_personality.is_list_ordered() {
+        tracing::warn!("--list-concurrency has no effect for directory buckets, which are listed sequentially");
+    }
+    if let Some(path) = &args.manifest {
+        let manifest =
+            Manifest::from_file(path).with_context(|| format!("failed to load manifest from {}", path.display()))?;
+        tracing::info!("loaded {} objects from manifest {}", manifest.len(), path.display());
+        filesystem_config.manifest = Some(Arc::new(manifest));
+    }
     filesystem_config.server_side_encryption = ServerSideEncryption::new(args.sse.clone(), args.sse_kms_key_id.clone());
 
     let sys = System::new_with_specifics(RefreshKind::everything());
@@ -817,7 +1313,7 @@ where
     let prefetcher_config = Default::default();
 
     let mut metadata_cache_ttl = args.metadata_ttl.unwrap_or_else(|| {
-        if args.cache.is_some() || args.cache_express_bucket_name().is_some() {
+        if args.cache.is_some() || args.cache_memory.is_some() || args.cache_express_bucket_name().is_some() {
             // When the data cache is enabled, use 1min as metadata-ttl.
             TimeToLive::Duration(Duration::from_secs(60))
         } else {
@@ -840,6 +1336,136 @@ where
     }
     tracing::trace!("using metadata TTL setting {metadata_cache_ttl:?}");
     filesystem_config.cache_config = CacheConfig::new(metadata_cache_ttl);
+    // With `minimal`, missing files and listings are not cached at all
+    let duration = |ttl: TimeToLive| match ttl {
+        TimeToLive::Minimal => Duration::ZERO,
+        TimeToLive::Indefinite => TimeToLive::INDEFINITE_DURATION,
+        TimeToLive::Duration(ttl) => ttl,
+    };
+    filesystem_config.cache_config.negative_ttl = args.negative_metadata_ttl.map(duration);
+    filesystem_config.cache_config.dir_listing_ttl = args.dir_listing_ttl.map(duration);
+    filesystem_config.cache_config.prefix_ttls = args.metadata_ttl_override.clone();
+    if let (true, Some(path)) = (args.persistent_metadata_cache, &args.cache) {
+        // Entries are identified by S3 key only, so keep the metadata of different buckets apart
+        let cache_key = env_unstable_cache_key().unwrap_or_else(|| args.bucket_name.clone().into());
+        let mut metadata_cache_config = PersistentMetadataCacheConfig {
+            // Entries are never served once older than every TTL
+            max_age: filesystem_config.cache_config.max_ttl(),
+            ..Default::default()
+        };
+        if let Some(max_size) = args.persistent_metadata_cache_max_size {
+            metadata_cache_config.max_size = max_size * 1024 * 1024;
+        }
+        let metadata_cache =
+            PersistentMetadataCache::new_from_parent_with_cache_key(path, &cache_key, metadata_cache_config);
+        tracing::debug!(?metadata_cache, "using persistent metadata cache");
+        filesystem_config.persistent_metadata_cache = Some(Arc::new(metadata_cache));
+    }
+
+    let cache_policy = load_cache_policy(&args)?;
+    let (cache, managed_cache_dir, memory_cache) = create_data_cache(&args, &client, &cache_policy)?;
+    if let Some(memory_cache) = memory_cache {
+        // Let the memory limiter evict cached blocks when it needs memory for reads
+        filesystem_config.cache_memory = Some(memory_cache);
+    }
+
+    let mut event_listener = match &args.event_source {
+        Some(event_source) => {
+            let source = event_source
+                .open()
+                .with_context(|| format!("failed to open event source {event_source:?}"))?;
+            Some(EventListener::new(source, &args.bucket_name))
+        }
+        None => None,
+    };
+
+    if cache.tier_count() > 0 {
+        tracing::debug!(?cache, "using data cache");
+        let cache = Arc::new(cache);
+        if let Some(listener) = event_listener.take() {
+            event_listener = Some(listener.with_data_cache(cache.clone()));
+        }
+        if args.cache_writes {
+            let mut write_through_cache =
+                WriteThroughCache::new(cache.clone(), runtime.clone()).with_policy(cache_policy.clone());
+            if let Some(max_size) = args.cache_writes_max_size {
+                write_through_cache = write_through_cache.with_max_object_size(max_size * 1024 * 1024);
+            }
+            filesystem_config.write_through_cache = Some(write_through_cache);
+        }
+        let prefetcher = caching_prefetch_with_policy(cache, cache_policy, runtime, prefetcher_config);
+        let mut fuse_session = create_filesystem(
+            client,
+            prefetcher,
+            &args.bucket_name,
+            &args.prefix.unwrap_or_default(),
+            filesystem_config,
+            fuse_config,
+            &bucket_description,
+            event_listener,
+        )?;
+
+        if let Some(managed_cache_dir) = managed_cache_dir {
+            fuse_session.run_on_close(Box::new(move || {
+                drop(managed_cache_dir);
+            }));
+        }
+
+        return Ok(fuse_session);
+    }
+
+    let prefetcher = default_prefetch(runtime, prefetcher_config);
+    create_filesystem(
+        client,
+        prefetcher,
+        &args.bucket_name,
+        &args.prefix.unwrap_or_default(),
+        filesystem_config,
+        fuse_config,
+        &bucket_description,
+        event_listener,
+    )
+}
+
+/// Load the cache policy file, if any. Without one, every object is cached.
+fn load_cache_policy(args: &CliArgs) -> anyhow::Result<Arc<CachePolicy>> {
+    let Some(path) = &args.cache_policy else {
+        return Ok(Default::default());
+    };
+    let policy =
+        CachePolicy::from_file(path).with_context(|| format!("failed to load cache policy from {}", path.display()))?;
+    tracing::debug!(?policy, "using cache policy");
+    Ok(Arc::new(policy))
+}
+
+/// Create the data caches enabled by the arguments, combined into tiers from fastest to slowest.
+///
+/// Also returns the cache directory, which should be kept until the cache is no longer used, and
+/// the memory cache, if enabled.
+fn create_data_cache<Client>(
+    args: &CliArgs,
+    client: &Client,
+    cache_policy: &Arc<CachePolicy>,
+) -> anyhow::Result<(TieredDataCache, Option<ManagedCacheDir>, Option<Arc<InMemoryDataCache>>)>
+where
+    Client: ObjectClient + Clone + Send + Sync + 'static,
+{
+    let mut cache = TieredDataCache::new(args.cache_block_size_in_bytes());
+    let mut managed_cache_dir = None;
+    let mut memory_cache = None;
+
+    if let Some(max_size_in_mib) = args.cache_memory {
+        let in_memory_cache = Arc::new(InMemoryDataCache::new_with_limit(
+            args.cache_block_size_in_bytes(),
+            max_size_in_mib * 1024 * 1024,
+        ));
+        cache = cache.with_tier(
+            CacheTier::Memory.name(),
+            in_memory_cache.clone(),
+            args.cache_tier_writes(CacheTier::Memory),
+        );
+        memory_cache = Some(in_memory_cache);
+    }
 
     if let Some(path) = &args.cache {
         let cache_limit = match args.max_cache_size {
@@ -851,87 +1477,102 @@ where
             None => Some(CacheLimit::default()),
         };
         if let Some(cache_limit) = cache_limit {
+            let encryption_key = if let Some(key_file) = &args.cache_encryption_key_file {
+                let key = EncryptionKey::from_file(key_file)
+                    .with_context(|| format!("failed to load cache encryption key from {}", key_file.display()))?;
+                Some(key)
+            } else if args.encrypt_cache {
+                Some(EncryptionKey::generate())
+            } else {
+                None
+            };
             let cache_config = DiskDataCacheConfig {
                 block_size: args.cache_block_size_in_bytes(),
                 limit: cache_limit,
+                compression: args.cache_compression,
+                encryption_key,
+                policy: cache_policy.clone(),
             };
-            let cache_key = env_unstable_cache_key();
-            let managed_cache_dir = ManagedCacheDir::new_from_parent_with_cache_key(path, cache_key)
-                .context("failed to create cache directory")?;
-
-            let cache = DiskDataCache::new(managed_cache_dir.as_path_buf(), cache_config);
-            let prefetcher = caching_prefetch(cache, runtime, prefetcher_config);
-            let mut fuse_session = create_filesystem(
-                client,
-                prefetcher,
-                &args.bucket_name,
-                &args.prefix.unwrap_or_default(),
-                filesystem_config,
-                fuse_config,
-                &bucket_description,
-            )?;
-
-            fuse_session.run_on_close(Box::new(move || {
-                drop(managed_cache_dir);
-            }));
-
-            return Ok(fuse_session);
+            // Cached blocks are identified by object key and ETag only, so keep the content of
+            // different buckets apart when reusing it
+            let bucket_cache_key = || env_unstable_cache_key().or_else(|| Some(args.bucket_name.clone().into()));
+            let disk_cache = if args.shared_cache {
+                let cache_dir = ManagedCacheDir::new_shared_from_parent_with_cache_key(path, bucket_cache_key())
+                    .context("failed to open shared cache directory")?;
+                let disk_cache = DiskDataCache::new_shared(cache_dir.as_path_buf(), cache_config)
+                    .context("failed to open shared cache")?;
+                managed_cache_dir = Some(cache_dir);
+                disk_cache
+            } else if args.persistent_cache {
+                let cache_key = bucket_cache_key();
+                let cache_dir = ManagedCacheDir::new_persistent_from_parent_with_cache_key(path, cache_key)
+                    .context("failed to open persistent cache directory")?;
+                let disk_cache = DiskDataCache::new_with_existing_blocks(cache_dir.as_path_buf(), cache_config)
+                    .context("failed to load existing cache content")?;
+                managed_cache_dir = Some(cache_dir);
+                disk_cache
+            } else {
+                let cache_key = env_unstable_cache_key();
+                let cache_dir = ManagedCacheDir::new_from_parent_with_cache_key(path, cache_key)
+                    .context("failed to create cache directory")?;
+                let disk_cache = DiskDataCache::new(cache_dir.as_path_buf(), cache_config);
+                managed_cache_dir = Some(cache_dir);
+                disk_cache
+            };
+            cache = cache.with_tier(
+                CacheTier::Disk.name(),
+                disk_cache,
+                args.cache_tier_writes(CacheTier::Disk),
+            );
         }
     }
 
     if let Some(express_bucket_name) = args.cache_express_bucket_name() {
         // The cache can be shared across instances mounting the same bucket (including with different prefixes)
         let source_description = &args.bucket_name;
-        let cache = ExpressDataCache::new(
+        let express_cache = ExpressDataCache::new(
             express_bucket_name,
             client.clone(),
             source_description,
             args.cache_block_size_in_bytes(),
         );
-        let prefetcher = caching_prefetch(cache, runtime, prefetcher_config);
-        let fuse_session = create_filesystem(
-            client,
-            prefetcher,
-            &args.bucket_name,
-            &args.prefix.unwrap_or_default(),
-            filesystem_config,
-            fuse_config,
-            &bucket_description,
-        )?;
-
-        return Ok(fuse_session);
-    };
+        cache = cache.with_tier(
+            CacheTier::Express.name(),
+            express_cache,
+            args.cache_tier_writes(CacheTier::Express),
+        );
+    }
 
-    let prefetcher = default_prefetch(runtime, prefetcher_config);
-    create_filesystem(
-        client,
-        prefetcher,
-        &args.bucket_name,
-        &args.prefix.unwrap_or_default(),
-        filesystem_config,
-        fuse_config,
-        &bucket_description,
-    )
+    Ok((cache, managed_cache_dir, memory_cache))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_filesystem<Client, Prefetcher>(
     client: Client,
     prefetcher: Prefetcher,
     bucket_name: &str,
     prefix: &Prefix,
-    filesystem_config: S3FilesystemConfig,
+    mut filesystem_config: S3FilesystemConfig,
     fuse_session_config: FuseSessionConfig,
     bucket_description: &str,
+    event_listener: Option<EventListener>,
 ) -> anyhow::Result<FuseSession>
 where
     Client: ObjectClient + Clone + Send + Sync + 'static,
     Prefetcher: Prefetch + Send + Sync + 'static,
 {
+    let (invalidation_sender, invalidation_receiver) = async_channel::unbounded();
+    filesystem_config.invalidations = Some(invalidation_sender);
     tracing::trace!(?filesystem_config, "creating file system");
     let fs = S3FuseFilesystem::new(client, prefetcher, bucket_name, prefix, filesystem_config);
+    if let Some(event_listener) = event_listener {
+        tracing::debug!(?event_listener, "listening for S3 events");
+        event_listener.spawn(fs.superblock())?;
+    }
     tracing::debug!(?fuse_session_config, "creating fuse session");
     let session = Session::new(fs, &fuse_session_config.mount_point, &fuse_session_config.options)
         .context("Failed to create FUSE session")?;
+    spawn_invalidation_thread(session.notifier(), invalidation_receiver)?;
     let session = FuseSession::new(session, fuse_session_config.max_threads).context("Failed to start FUSE session")?;
 
     tracing::info!(
@@ -1044,6 +1685,17 @@ fn parse_kms_key_arn(kms_key_arn: &str) -> anyhow::Result<String> {
     }
 }
 
+/// Validate the suffix of the names of shadowed files, which must make valid file names.
+fn parse_shadowed_file_suffix(suffix: &str) -> anyhow::Result<String> {
+    if suffix.is_empty() {
+        return Err(anyhow!("the suffix must not be empty"));
+    }
+    if suffix.contains(['/', '\0']) {
+        return Err(anyhow!("the suffix must not contain '/' or null bytes"));
+    }
+    Ok(suffix.to_owned())
+}
+
 fn env_region() -> Option<String> {
     env::var_os("AWS_REGION").map(|val| val.to_string_lossy().into())
 }
@@ -1153,6 +1805,29 @@ fn validate_sse_args(sse_type: Option<&str>, sse_kms_key_id: Option<&str>) -> an
     }
 }
 
+fn validate_cache_write_tiers(args: &CliArgs) -> anyhow::Result<()> {
+    let enabled_tiers = args.enabled_cache_tiers();
+    let write_tiers = args.cache_write_tiers.iter().flatten();
+    if let Some(tier) = write_tiers.into_iter().find(|tier| !enabled_tiers.contains(tier)) {
+        return Err(anyhow!(
+            "--cache-write-tiers includes the {} cache, which is not enabled",
+            tier.name()
+        ));
+    }
+    Ok(())
+}
+
+/// An ephemeral key can't decrypt the cache content written by a previous or another process.
+fn validate_cache_encryption(args: &CliArgs) -> anyhow::Result<()> {
+    if args.encrypt_cache && (args.persistent_cache || args.shared_cache) {
+        return Err(anyhow!(
+            "--encrypt-cache uses a key that is lost when Mountpoint exits, \
+            use --cache-encryption-key-file with --persistent-cache or --shared-cache"
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1194,4 +1869,18 @@ mod tests {
             parsed.expect_err("invalid kms key identifier");
         }
     }
+
+    #[test_case(".__file__", true; "dot suffix")]
+    #[test_case("~", true; "single character")]
+    #[test_case("", false; "empty")]
+    #[test_case("/file", false; "delimiter")]
+    #[test_case("\0", false; "null byte")]
+    fn test_parse_shadowed_file_suffix(suffix: &str, valid: bool) {
+        let parsed = parse_shadowed_file_suffix(suffix);
+        if valid {
+            assert_eq!(parsed.expect("valid suffix"), suffix);
+        } else {
+            parsed.expect_err("invalid suffix");
+        }
+    }
 }
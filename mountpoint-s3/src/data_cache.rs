@@ -5,18 +5,29 @@
 //! Ultimately, this means reduced cost in terms of S3 billing as well as compute time.
 
 mod cache_directory;
+mod cache_policy;
 mod disk_data_cache;
+mod encryption;
 mod express_data_cache;
 mod in_memory_data_cache;
+mod tiered_data_cache;
+
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use thiserror::Error;
 
 pub use crate::checksums::ChecksummedBytes;
 pub use crate::data_cache::cache_directory::ManagedCacheDir;
-pub use crate::data_cache::disk_data_cache::{CacheLimit, DiskDataCache, DiskDataCacheConfig};
+pub use crate::data_cache::cache_policy::{CacheAction, CachePolicy, CachePolicyError, CachePolicyRule};
+pub use crate::data_cache::disk_data_cache::{
+    object_coverage, BlockInfo, BlockReport, BlockStatus, CacheInspector, CacheLimit, CompressionCodec, DiskDataCache,
+    DiskDataCacheConfig, ObjectCoverage, PurgeError, PurgeFilter,
+};
+pub use crate::data_cache::encryption::{EncryptionKey, EncryptionKeyError};
 pub use crate::data_cache::express_data_cache::ExpressDataCache;
 pub use crate::data_cache::in_memory_data_cache::InMemoryDataCache;
+pub use crate::data_cache::tiered_data_cache::{CacheTierWrites, TieredDataCache};
 
 use crate::object::ObjectId;
 
@@ -65,4 +76,43 @@ pub trait DataCache {
 
     /// Returns the block size for the data cache.
     fn block_size(&self) -> u64;
+
+    /// Remove the blocks of every version of the object with the given key, for example after the
+    /// object was replaced or deleted in the bucket.
+    ///
+    /// Blocks are stored by [ObjectId], which includes the ETag, so the blocks of other versions
+    /// are never returned for the current version of an object and removing them only frees space
+    /// early. The default implementation does nothing, for caches that cannot find blocks by key,
+    /// such as [ExpressDataCache], which stores blocks under a hash of the key and ETag.
+    fn remove_object(&self, _key: &str) {}
+}
+
+#[async_trait]
+impl<Cache: DataCache + Send + Sync + ?Sized> DataCache for Arc<Cache> {
+    async fn get_block(
+        &self,
+        cache_key: &ObjectId,
+        block_idx: BlockIndex,
+        block_offset: u64,
+    ) -> DataCacheResult<Option<ChecksummedBytes>> {
+        self.as_ref().get_block(cache_key, block_idx, block_offset).await
+    }
+
+    async fn put_block(
+        &self,
+        cache_key: ObjectId,
+        block_idx: BlockIndex,
+        block_offset: u64,
+        bytes: ChecksummedBytes,
+    ) -> DataCacheResult<()> {
+        self.as_ref().put_block(cache_key, block_idx, block_offset, bytes).await
+    }
+
+    fn block_size(&self) -> u64 {
+        self.as_ref().block_size()
+    }
+
+    fn remove_object(&self, key: &str) {
+        self.as_ref().remove_object(key)
+    }
 }
@@ -1,5 +1,5 @@
 //! Provides functionality related to the inner cache directory Mountpoint creates or uses.
-//! Mountpoint attempts to cleanup the contents during mount and exit.
+//! Mountpoint attempts to cleanup the contents during mount and exit, unless the directory is persistent.
 //!
 //! Mountpoint uses a directory inside the user-provided cache directory
 //! to mitigate any impact from the user providing a directory that already contains data.
@@ -10,20 +10,36 @@ use sha2::{Digest, Sha256};
 use std::ffi::OsString;
 use std::fs;
 use std::io;
-use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
 use std::os::unix::prelude::OsStrExt;
 use std::path::{Path, PathBuf};
 
+use nix::errno::Errno;
+use nix::fcntl::{Flock, FlockArg};
 use thiserror::Error;
 
+/// Name of the lock file that marks a cache directory as in use by a Mountpoint process.
+const LOCK_FILE_NAME: &str = "mountpoint-cache.lock";
+
 /// Cache directory that has been created and emptied, and will be emptied when dropped.
 /// When using a `cache_key`, the key is hashed and added as a subdirectory of `mountpoint-cache`.
+///
+/// A persistent cache directory instead keeps its contents across mounts.
+///
+/// While it exists, a [ManagedCacheDir] holds an exclusive lock on a file in the managed path, so
+/// that two Mountpoint processes can't use the same cache directory at once. A shared cache
+/// directory instead holds a shared lock, so that it can be used by several Mountpoint processes
+/// but not by any using it exclusively.
 #[derive(Debug)]
 pub struct ManagedCacheDir {
     /// `<parent_path>/mountpoint-cache`
     mountpoint_cache_path: PathBuf,
     /// `<parent_path>/mountpoint-cache` or `<parent_path>/mountpoint-cache/<hashed_cache_key>`
     managed_cache_path: PathBuf,
+    /// Whether the contents should be kept when dropped
+    persistent: bool,
+    /// Lock on the managed path, released when dropped
+    _lock: Flock<fs::File>,
 }
 
 #[derive(Debug, Error)]
@@ -32,62 +48,200 @@ pub enum ManagedCacheDirError {
     CreationFailure(#[source] io::Error),
     #[error("cleanup of cache sub-directory failed due to IO error: {0}")]
     CleanupFailure(#[source] io::Error),
+    #[error("locking of cache sub-directory failed due to IO error: {0}")]
+    LockFailure(#[source] io::Error),
+    #[error("cache sub-directory {0:?} is already in use by another Mountpoint process")]
+    AlreadyInUse(PathBuf),
 }
 
 impl ManagedCacheDir {
     /// Create a new directory inside the provided parent path.
-    /// If `<parent_path>/mountpoint-cache` already exists, it will be deleted before being
-    /// recreated. This can cause performance degradation, but will never result in unused caches
-    /// being retained, assuming other Mountpoint instances are being ran on the host.
+    /// If the managed path already exists, its contents are deleted. This can cause performance
+    /// degradation, but will never result in unused caches being retained, assuming other
+    /// Mountpoint instances are being ran on the host.
+    ///
+    /// The managed path is locked before it is emptied, and stays locked until it is removed when
+    /// dropped. The cache directories of other cache keys inside it are left alone, since they may
+    /// be persistent or in use by other Mountpoint processes.
+    ///
+    /// Fails if another Mountpoint process is using the same managed path.
     pub fn new_from_parent_with_cache_key(
         parent_path: impl AsRef<Path>,
         cache_key: Option<OsString>,
     ) -> Result<Self, ManagedCacheDirError> {
-        let mountpoint_cache_path = parent_path.as_ref().join("mountpoint-cache");
-        let managed_cache_path = match cache_key {
-            None => mountpoint_cache_path.clone(),
-            Some(ref cache_key) => mountpoint_cache_path.join(hash_cache_key(cache_key.as_bytes())),
-        };
-        let managed_cache_dir = Self {
+        let (mountpoint_cache_path, managed_cache_path) = Self::paths(parent_path.as_ref(), cache_key.as_ref());
+
+        Self::create_dir_if_missing(&mountpoint_cache_path)?;
+        if cache_key.is_some() {
+            Self::create_dir_if_missing(&managed_cache_path)?;
+        }
+        let lock = Self::lock(&managed_cache_path, FlockArg::LockExclusiveNonblock)?;
+        Self::empty_dir(&managed_cache_path)?;
+        Self::set_dir_mode(&managed_cache_path)?;
+        Ok(Self {
             mountpoint_cache_path,
             managed_cache_path,
-        };
+            persistent: false,
+            _lock: lock,
+        })
+    }
+
+    /// Create or reuse a directory inside the provided parent path, keeping any existing contents.
+    /// The contents are also kept when the [ManagedCacheDir] is dropped, so that they can be
+    /// reused by the next Mountpoint process with the same parent path and `cache_key`.
+    ///
+    /// Fails if another Mountpoint process is using the same managed path.
+    pub fn new_persistent_from_parent_with_cache_key(
+        parent_path: impl AsRef<Path>,
+        cache_key: Option<OsString>,
+    ) -> Result<Self, ManagedCacheDirError> {
+        let (mountpoint_cache_path, managed_cache_path) = Self::paths(parent_path.as_ref(), cache_key.as_ref());
 
-        managed_cache_dir.remove()?;
-        Self::create_dir(&managed_cache_dir.mountpoint_cache_path)?;
+        Self::create_dir_if_missing(&mountpoint_cache_path)?;
         if cache_key.is_some() {
-            Self::create_dir(&managed_cache_dir.managed_cache_path)?;
+            Self::create_dir_if_missing(&managed_cache_path)?;
         }
-        Ok(managed_cache_dir)
+        let lock = Self::lock(&managed_cache_path, FlockArg::LockExclusiveNonblock)?;
+        tracing::debug!(cache_subdirectory = ?managed_cache_path, "reusing persistent cache sub-directory");
+        Ok(Self {
+            mountpoint_cache_path,
+            managed_cache_path,
+            persistent: true,
+            _lock: lock,
+        })
     }
 
-    /// Remove the cache sub-directory, along with its contents if any
+    /// Create or reuse a directory inside the provided parent path that can be used by several
+    /// Mountpoint processes at once. Like a persistent directory, the contents are kept when the
+    /// [ManagedCacheDir] is dropped, since other processes may still be using them.
+    ///
+    /// Fails if another Mountpoint process is using the same managed path exclusively.
+    pub fn new_shared_from_parent_with_cache_key(
+        parent_path: impl AsRef<Path>,
+        cache_key: Option<OsString>,
+    ) -> Result<Self, ManagedCacheDirError> {
+        let (mountpoint_cache_path, managed_cache_path) = Self::paths(parent_path.as_ref(), cache_key.as_ref());
+
+        Self::create_dir_if_missing(&mountpoint_cache_path)?;
+        if cache_key.is_some() {
+            Self::create_dir_if_missing(&managed_cache_path)?;
+        }
+        let lock = Self::lock(&managed_cache_path, FlockArg::LockSharedNonblock)?;
+        tracing::debug!(cache_subdirectory = ?managed_cache_path, "using shared cache sub-directory");
+        Ok(Self {
+            mountpoint_cache_path,
+            managed_cache_path,
+            persistent: true,
+            _lock: lock,
+        })
+    }
+
+    /// Lock the cache directory at `path` exclusively, like a Mountpoint process using it, so that it
+    /// can be maintained while no Mountpoint process uses it. The lock is released when dropped.
+    ///
+    /// Fails with [ManagedCacheDirError::AlreadyInUse] if a Mountpoint process is using it.
+    pub(crate) fn lock_for_maintenance(path: &Path) -> Result<Flock<fs::File>, ManagedCacheDirError> {
+        Self::lock(path, FlockArg::LockExclusiveNonblock)
+    }
+
+    /// Returns `<parent_path>/mountpoint-cache` and the managed path for the given `cache_key`.
+    fn paths(parent_path: &Path, cache_key: Option<&OsString>) -> (PathBuf, PathBuf) {
+        let mountpoint_cache_path = parent_path.join("mountpoint-cache");
+        let managed_cache_path = match cache_key {
+            None => mountpoint_cache_path.clone(),
+            Some(cache_key) => mountpoint_cache_path.join(hash_cache_key(cache_key.as_bytes())),
+        };
+        (mountpoint_cache_path, managed_cache_path)
+    }
+
+    /// Take a lock on the lock file in the given directory, creating it if needed.
+    fn lock(path: &Path, arg: FlockArg) -> Result<Flock<fs::File>, ManagedCacheDirError> {
+        let lock_file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path.join(LOCK_FILE_NAME))
+            .map_err(ManagedCacheDirError::LockFailure)?;
+        Flock::lock(lock_file, arg).map_err(|(_, errno)| match errno {
+            Errno::EWOULDBLOCK => ManagedCacheDirError::AlreadyInUse(path.to_owned()),
+            errno => ManagedCacheDirError::LockFailure(errno.into()),
+        })
+    }
+
+    /// Remove the managed path, along with its contents if any. This is only called while holding
+    /// the lock on the managed path.
     fn remove(&self) -> Result<(), ManagedCacheDirError> {
-        tracing::debug!(cache_subdirectory = ?self.mountpoint_cache_path, "removing the cache sub-directory and any contents");
-        if let Err(remove_dir_err) = fs::remove_dir_all(&self.mountpoint_cache_path) {
-            match remove_dir_err.kind() {
-                io::ErrorKind::NotFound => (),
-                _kind => return Err(ManagedCacheDirError::CleanupFailure(remove_dir_err)),
+        tracing::debug!(cache_subdirectory = ?self.managed_cache_path, "removing the cache sub-directory and any contents");
+        Self::empty_dir(&self.managed_cache_path)?;
+        match fs::remove_file(self.managed_cache_path.join(LOCK_FILE_NAME)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(ManagedCacheDirError::CleanupFailure(err)),
+            _ => {}
+        }
+        // The directories may still hold the cache directories of other cache keys
+        Self::remove_dir_if_empty(&self.managed_cache_path)?;
+        Self::remove_dir_if_empty(&self.mountpoint_cache_path)?;
+        tracing::trace!(cache_subdirectory = ?self.managed_cache_path, "cache sub-directory removal complete");
+        Ok(())
+    }
+
+    /// Remove the contents of the managed path at `path`, except for its lock file and for the
+    /// cache directories of other cache keys, which have their own lock file.
+    fn empty_dir(path: &Path) -> Result<(), ManagedCacheDirError> {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(ManagedCacheDirError::CleanupFailure(err)),
+        };
+        for entry in entries {
+            let entry = entry.map_err(ManagedCacheDirError::CleanupFailure)?;
+            let entry_path = entry.path();
+            let file_type = entry.file_type().map_err(ManagedCacheDirError::CleanupFailure)?;
+            let result = if file_type.is_dir() {
+                if entry_path.join(LOCK_FILE_NAME).exists() {
+                    tracing::trace!(cache_dir = ?entry_path, "keeping cache directory of another cache key");
+                    continue;
+                }
+                fs::remove_dir_all(&entry_path)
+            } else if entry.file_name() == LOCK_FILE_NAME {
+                continue;
+            } else {
+                fs::remove_file(&entry_path)
+            };
+            match result {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(ManagedCacheDirError::CleanupFailure(err))
+                }
+                _ => {}
             }
         }
-        tracing::trace!(cache_subdirectory = ?self.mountpoint_cache_path, "cache sub-directory removal complete");
         Ok(())
     }
 
-    /// Create a directory, assuming the parent path exists.
-    fn create_dir(path: &Path) -> Result<(), ManagedCacheDirError> {
-        let mkdir_result = fs::DirBuilder::new().mode(0o700).create(path);
-        if let Err(mkdir_err) = mkdir_result {
-            match mkdir_err.kind() {
-                io::ErrorKind::AlreadyExists => tracing::warn!(
-                    cache_dir = ?path,
-                    "cache sub-directory already existed immediately after removal",
-                ),
-                _kind => return Err(ManagedCacheDirError::CreationFailure(mkdir_err)),
+    /// Remove a directory if it is empty.
+    fn remove_dir_if_empty(path: &Path) -> Result<(), ManagedCacheDirError> {
+        match fs::remove_dir(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound && err.kind() != io::ErrorKind::DirectoryNotEmpty => {
+                Err(ManagedCacheDirError::CleanupFailure(err))
             }
+            _ => Ok(()),
         }
+    }
 
-        Ok(())
+    /// Restrict the permissions of an existing directory to its owner.
+    fn set_dir_mode(path: &Path) -> Result<(), ManagedCacheDirError> {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o700)).map_err(ManagedCacheDirError::CreationFailure)
+    }
+
+    /// Create a directory if it doesn't already exist, assuming the parent path exists.
+    fn create_dir_if_missing(path: &Path) -> Result<(), ManagedCacheDirError> {
+        match fs::DirBuilder::new().mode(0o700).create(path) {
+            Err(mkdir_err) if mkdir_err.kind() != io::ErrorKind::AlreadyExists => {
+                Err(ManagedCacheDirError::CreationFailure(mkdir_err))
+            }
+            _ => Ok(()),
+        }
     }
 
     /// Retrieve a reference to the managed path
@@ -103,8 +257,11 @@ impl ManagedCacheDir {
 
 impl Drop for ManagedCacheDir {
     fn drop(&mut self) {
+        if self.persistent {
+            return;
+        }
         if let Err(err) = self.remove() {
-            tracing::error!(cache_subdirectory = ?self.mountpoint_cache_path, "failed to remove cache sub-directory: {err}");
+            tracing::error!(cache_subdirectory = ?self.managed_cache_path, "failed to remove cache sub-directory: {err}");
         }
     }
 }
@@ -117,7 +274,7 @@ fn hash_cache_key(cache_key: &[u8]) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::{hash_cache_key, ManagedCacheDir};
+    use super::{hash_cache_key, ManagedCacheDir, ManagedCacheDirError, LOCK_FILE_NAME};
     use std::ffi::OsString;
 
     use std::fs;
@@ -234,8 +391,15 @@ mod tests {
 
         assert_dir_exists_with_permissions(&expected_path);
 
-        let dir_entries = fs::read_dir(&expected_path).unwrap().count();
-        assert!(dir_entries == 0, "directory should be empty");
+        let dir_entries: Vec<_> = fs::read_dir(&expected_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(
+            dir_entries,
+            [LOCK_FILE_NAME],
+            "directory should only contain the lock file"
+        );
 
         drop(managed_dir);
         assert!(
@@ -246,6 +410,118 @@ mod tests {
         temp_dir.close().unwrap();
     }
 
+    #[test]
+    fn test_persistent() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_key = OsString::from("cache_key");
+        let mp_cache_path = temp_dir.path().join("mountpoint-cache");
+        let expected_path = mp_cache_path.join(hash_cache_key(cache_key.as_bytes()));
+
+        let managed_dir =
+            ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some(cache_key.clone()))
+                .expect("creating managed dir should succeed");
+        assert_dir_exists_with_permissions(&expected_path);
+        fs::File::create(expected_path.join("file.txt"))
+            .expect("should be able to create file within managed directory");
+        drop(managed_dir);
+        assert!(expected_path.join("file.txt").exists(), "contents should be kept");
+
+        let managed_dir = ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some(cache_key))
+            .expect("reusing managed dir should succeed");
+        assert!(expected_path.join("file.txt").exists(), "contents should be kept");
+        drop(managed_dir);
+
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_already_in_use() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_key = OsString::from("cache_key");
+
+        let managed_dir =
+            ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some(cache_key.clone()))
+                .expect("creating managed dir should succeed");
+        fs::File::create(managed_dir.as_path().join("file.txt")).unwrap();
+
+        let result =
+            ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some(cache_key.clone()));
+        assert!(matches!(result, Err(ManagedCacheDirError::AlreadyInUse(_))));
+        let result = ManagedCacheDir::new_from_parent_with_cache_key(temp_dir.path(), Some(cache_key.clone()));
+        assert!(matches!(result, Err(ManagedCacheDirError::AlreadyInUse(_))));
+        assert!(
+            managed_dir.as_path().join("file.txt").exists(),
+            "contents should not be removed while in use"
+        );
+
+        // A different cache key can be used at the same time
+        let _other_dir =
+            ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some("other_key".into()))
+                .expect("creating managed dir with another key should succeed");
+
+        drop(managed_dir);
+        let _managed_dir = ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some(cache_key))
+            .expect("managed dir should be usable once released");
+    }
+
+    #[test]
+    fn test_shared() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_key = OsString::from("cache_key");
+
+        let first_dir =
+            ManagedCacheDir::new_shared_from_parent_with_cache_key(temp_dir.path(), Some(cache_key.clone()))
+                .expect("creating shared dir should succeed");
+        let second_dir =
+            ManagedCacheDir::new_shared_from_parent_with_cache_key(temp_dir.path(), Some(cache_key.clone()))
+                .expect("sharing the dir should succeed");
+        assert_eq!(first_dir.as_path(), second_dir.as_path());
+        fs::File::create(first_dir.as_path().join("file.txt")).unwrap();
+
+        let result =
+            ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some(cache_key.clone()));
+        assert!(matches!(result, Err(ManagedCacheDirError::AlreadyInUse(_))));
+
+        drop(first_dir);
+        assert!(
+            second_dir.as_path().join("file.txt").exists(),
+            "contents should be kept while shared"
+        );
+        drop(second_dir);
+
+        let _managed_dir = ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some(cache_key))
+            .expect("managed dir should be usable once released");
+    }
+
+    #[test]
+    fn test_keeps_other_cache_dirs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mp_cache_path = temp_dir.path().join("mountpoint-cache");
+
+        let persistent_dir =
+            ManagedCacheDir::new_persistent_from_parent_with_cache_key(temp_dir.path(), Some("persistent".into()))
+                .expect("creating persistent dir should succeed");
+        fs::File::create(persistent_dir.as_path().join("file.txt")).unwrap();
+        let shared_dir = ManagedCacheDir::new_shared_from_parent_with_cache_key(temp_dir.path(), Some("shared".into()))
+            .expect("creating shared dir should succeed");
+        fs::File::create(shared_dir.as_path().join("file.txt")).unwrap();
+        fs::File::create(mp_cache_path.join("stale.txt")).unwrap();
+
+        // Non-persistent mounts with and without a cache key only remove their own contents
+        for cache_key in [None, Some(OsString::from("other_key"))] {
+            let managed_dir = ManagedCacheDir::new_from_parent_with_cache_key(temp_dir.path(), cache_key)
+                .expect("creating managed dir should succeed");
+            fs::File::create(managed_dir.as_path().join("block")).unwrap();
+            drop(managed_dir);
+
+            assert!(persistent_dir.as_path().join("file.txt").exists());
+            assert!(shared_dir.as_path().join("file.txt").exists());
+        }
+        assert!(!mp_cache_path.join("stale.txt").exists());
+        assert!(!mp_cache_path.join("block").exists());
+        assert!(!mp_cache_path.join(hash_cache_key(b"other_key")).exists());
+    }
+
     fn assert_dir_does_not_exist(expected_path: &PathBuf) {
         assert!(fs::metadata(expected_path).is_err());
     }
@@ -0,0 +1,175 @@
+//! Rules deciding which objects are stored in a data cache, and how long their content is kept.
+//!
+//! A [CachePolicy] is loaded from a JSON file, for example:
+//!
+//! ```json
+//! {
+//!     "max_object_size": 1073741824,
+//!     "max_age_seconds": 86400,
+//!     "rules": [
+//!         { "prefix": "reference/", "action": "pin" },
+//!         { "prefix": "scans/", "action": "exclude" },
+//!         { "prefix": "daily/", "max_age_seconds": 3600 }
+//!     ]
+//! }
+//! ```
+//!
+//! Rules apply to the S3 keys of objects (including any prefix the bucket is mounted at), and the
+//! rule with the longest matching prefix applies to each object.
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Rules deciding which objects are stored in a data cache, and how long their content is kept.
+///
+/// The default policy caches every object, with no expiry.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CachePolicy {
+    /// Objects larger than this size in bytes are not cached.
+    #[serde(default)]
+    max_object_size: Option<u64>,
+    /// Cached content older than this is not served, unless a rule sets a different age.
+    #[serde(default)]
+    max_age_seconds: Option<u64>,
+    /// Rules for the objects under specific prefixes.
+    #[serde(default)]
+    rules: Vec<CachePolicyRule>,
+}
+
+/// Policy for the objects under a prefix.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CachePolicyRule {
+    prefix: String,
+    #[serde(default)]
+    action: CacheAction,
+    #[serde(default)]
+    max_age_seconds: Option<u64>,
+}
+
+/// How to cache the objects matching a [CachePolicyRule].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheAction {
+    /// Cache the objects, evicting their content when the cache is full
+    #[default]
+    Include,
+    /// Never cache the objects
+    Exclude,
+    /// Cache the objects, and never evict their content to make room for other objects
+    Pin,
+}
+
+/// Errors when loading a [CachePolicy].
+#[derive(Debug, Error)]
+pub enum CachePolicyError {
+    #[error("failed to read the cache policy file")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid cache policy")]
+    InvalidPolicy(#[from] serde_json::Error),
+}
+
+impl CachePolicy {
+    /// Load a policy from a JSON file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, CachePolicyError> {
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Whether content of the object with the given key and size should be stored in the cache.
+    pub fn should_cache(&self, key: &str, object_size: u64) -> bool {
+        if self.max_object_size.is_some_and(|max_size| object_size > max_size) {
+            return false;
+        }
+        self.action(key) != CacheAction::Exclude
+    }
+
+    /// Whether the content of the object with the given key should never be evicted.
+    pub fn is_pinned(&self, key: &str) -> bool {
+        self.action(key) == CacheAction::Pin
+    }
+
+    /// Whether any object may be pinned by this policy.
+    pub fn has_pinned_prefixes(&self) -> bool {
+        self.rules.iter().any(|rule| rule.action == CacheAction::Pin)
+    }
+
+    /// How long cached content of the object with the given key may be served for, if limited.
+    pub fn max_age(&self, key: &str) -> Option<Duration> {
+        let max_age_seconds = self
+            .rule(key)
+            .and_then(|rule| rule.max_age_seconds)
+            .or(self.max_age_seconds)?;
+        Some(Duration::from_secs(max_age_seconds))
+    }
+
+    fn action(&self, key: &str) -> CacheAction {
+        self.rule(key).map(|rule| rule.action).unwrap_or_default()
+    }
+
+    /// The rule with the longest prefix matching the key.
+    fn rule(&self, key: &str) -> Option<&CachePolicyRule> {
+        self.rules
+            .iter()
+            .filter(|rule| key.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> CachePolicy {
+        serde_json::from_str(json).expect("policy should be valid")
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let policy = CachePolicy::default();
+        assert!(policy.should_cache("a/b", u64::MAX));
+        assert!(!policy.is_pinned("a/b"));
+        assert!(!policy.has_pinned_prefixes());
+        assert_eq!(policy.max_age("a/b"), None);
+    }
+
+    #[test]
+    fn test_rules() {
+        let policy = parse(
+            r#"{
+                "max_object_size": 100,
+                "max_age_seconds": 60,
+                "rules": [
+                    { "prefix": "ref/", "action": "pin" },
+                    { "prefix": "ref/tmp/", "max_age_seconds": 5 },
+                    { "prefix": "scans/", "action": "exclude" }
+                ]
+            }"#,
+        );
+
+        assert!(policy.should_cache("other", 100));
+        assert!(!policy.should_cache("other", 101));
+        assert!(!policy.should_cache("scans/1", 1));
+        assert!(policy.should_cache("ref/1", 1));
+
+        // The longest matching prefix applies
+        assert!(policy.is_pinned("ref/1"));
+        assert!(!policy.is_pinned("ref/tmp/1"));
+        assert!(!policy.is_pinned("other"));
+        assert!(policy.has_pinned_prefixes());
+
+        assert_eq!(policy.max_age("ref/1"), Some(Duration::from_secs(60)));
+        assert_eq!(policy.max_age("ref/tmp/1"), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        serde_json::from_str::<CachePolicy>(r#"{ "rules": [{ "prefix": "a/", "action": "keep" }] }"#)
+            .expect_err("unknown action should be rejected");
+        serde_json::from_str::<CachePolicy>(r#"{ "max_size": 10 }"#).expect_err("unknown field should be rejected");
+    }
+}
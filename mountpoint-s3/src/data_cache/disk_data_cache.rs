@@ -1,15 +1,21 @@
 //! Module for the on-disk data cache implementation.
 
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::{ErrorKind, Read, Seek, Write};
 use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use async_io::Timer;
 use async_trait::async_trait;
 use bytes::Bytes;
 use linked_hash_map::LinkedHashMap;
 use mountpoint_s3_crt::checksums::crc32c::{self, Crc32c};
+use nix::errno::Errno;
+use nix::fcntl::{Flock, FlockArg};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use thiserror::Error;
@@ -20,20 +26,58 @@ use crate::data_cache::DataCacheError;
 use crate::object::ObjectId;
 use crate::sync::Mutex;
 
+use super::cache_policy::CachePolicy;
+use super::encryption::{AuthenticationError, BlockEncryption, EncryptionKey};
 use super::{BlockIndex, ChecksummedBytes, DataCache, DataCacheResult};
 
+mod inspect;
+
+pub use inspect::{
+    object_coverage, BlockInfo, BlockReport, BlockStatus, CacheInspector, ObjectCoverage, PurgeError, PurgeFilter,
+};
+
 /// Disk and file-layout versioning.
-const CACHE_VERSION: &str = "V1";
+const CACHE_VERSION: &str = "V5";
 
 /// Index where hashed directory names for the cache are split to avoid FS-specific limits.
 const HASHED_DIR_SPLIT_INDEX: usize = 2;
 
+/// Name of the file tracking the total size of a cache directory shared by several processes.
+const SHARED_USAGE_FILE_NAME: &str = "usage";
+
+/// Fraction of a [CacheLimit::TotalSize] limit to free up when evicting from a shared cache
+/// directory, so that each eviction (which scans the cache directory) makes room for many blocks.
+const SHARED_EVICTION_HEADROOM: f64 = 0.1;
+
+/// How often to retry locking a shared cache directory held by another process.
+const SHARED_LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long to wait for another process to release a shared cache directory before giving up on
+/// writing a block.
+const SHARED_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Compression level for [CompressionCodec::Zstd], favoring speed as blocks are compressed on the read path.
+const ZSTD_COMPRESSION_LEVEL: i32 = 3;
+
+/// Minimum fraction of its size a block must shrink by to be stored compressed. Blocks that don't
+/// compress well are stored uncompressed, so they can be read without decompressing.
+const MIN_COMPRESSION_SAVINGS: f64 = 0.1;
+
+/// Counter to give unique names to the temporary files blocks are written to.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 /// On-disk implementation of [DataCache].
 pub struct DiskDataCache {
     cache_directory: PathBuf,
     config: DiskDataCacheConfig,
-    /// Tracks blocks usage. `None` when no cache limit was set.
+    /// Tracks blocks usage. `None` when no cache limit was set or the cache directory is shared.
     usage: Option<Mutex<UsageInfo<DiskBlockKey>>>,
+    /// Tracks the size of a cache directory shared with other processes. `None` when no cache limit
+    /// was set or the cache directory is not shared.
+    shared_usage: Option<SharedUsage>,
+    /// Hashed keys of the versions of each object this cache has stored blocks for, so that their
+    /// blocks can be found by S3 key. Entries are only dropped when the object is removed.
+    object_versions: Mutex<HashMap<String, HashSet<[u8; 32]>>>,
 }
 
 /// Configuration for a [DiskDataCache].
@@ -43,6 +87,63 @@ pub struct DiskDataCacheConfig {
     pub block_size: u64,
     /// How to limit the cache size.
     pub limit: CacheLimit,
+    /// How to compress blocks, or `None` to store them uncompressed.
+    pub compression: Option<CompressionCodec>,
+    /// Key to encrypt blocks with, or `None` to store them unencrypted.
+    pub encryption_key: Option<EncryptionKey>,
+    /// Which blocks are never evicted, and how long blocks may be served for.
+    pub policy: Arc<CachePolicy>,
+}
+
+/// Compression codec for the blocks in a [DiskDataCache].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionCodec {
+    /// Fast compression with LZ4
+    Lz4,
+    /// Better compression with Zstandard, at a higher CPU cost
+    Zstd,
+}
+
+impl CompressionCodec {
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionCodec::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+            CompressionCodec::Zstd => zstd::bulk::compress(data, ZSTD_COMPRESSION_LEVEL),
+        }
+    }
+
+    /// Decompress `data`, failing if it would decompress to more than `max_size` bytes, so that a
+    /// corrupted block can't make us allocate an arbitrary amount of memory.
+    fn decompress(&self, data: &[u8], max_size: usize) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionCodec::Lz4 => {
+                let (size, data) = lz4_flex::block::uncompressed_size(data).map_err(std::io::Error::other)?;
+                if size > max_size {
+                    return Err(std::io::Error::other(format!(
+                        "decompressed size {size} exceeds the maximum of {max_size} bytes"
+                    )));
+                }
+                let mut buffer = vec![0; size];
+                let decompressed = lz4_flex::decompress_into(data, &mut buffer).map_err(std::io::Error::other)?;
+                if decompressed != size {
+                    return Err(std::io::Error::other(format!(
+                        "decompressed {decompressed} bytes but expected {size}"
+                    )));
+                }
+                Ok(buffer)
+            }
+            CompressionCodec::Zstd => zstd::bulk::decompress(data, max_size),
+        }
+    }
+
+    /// Identifies the codec when computing the header checksum
+    fn tag(codec: Option<Self>) -> u8 {
+        match codec {
+            None => 0,
+            Some(CompressionCodec::Lz4) => 1,
+            Some(CompressionCodec::Zstd) => 2,
+        }
+    }
 }
 
 /// Limit the cache size.
@@ -67,9 +168,22 @@ impl Default for CacheLimit {
 struct DiskBlockHeader {
     block_idx: BlockIndex,
     block_offset: u64,
+    /// Size of the blocks of the cache the block was written to, which may be smaller than the
+    /// data for the last block of an object
+    block_size: u64,
     etag: String,
     s3_key: String,
-    data_checksum: u32,
+    /// When the block was written, in seconds since the UNIX epoch. The file's modification time
+    /// can't be used, as it is updated when blocks of a shared cache are read.
+    written_at: u64,
+    /// Checksum of the uncompressed data, or `None` for encrypted blocks. Encrypted blocks are
+    /// authenticated when decrypted instead, and a checksum of the plaintext would leak information
+    /// about the content.
+    data_checksum: Option<u32>,
+    /// How the data is compressed, if at all
+    compression: Option<CompressionCodec>,
+    /// How the (compressed) data is encrypted, if at all
+    encryption: Option<BlockEncryption>,
     header_checksum: u32,
 }
 
@@ -88,67 +202,113 @@ enum DiskBlockAccessError {
     ChecksumError,
     #[error("one or more of the fields in this block were incorrect")]
     FieldMismatchError,
+    #[error("the block's data could not be decompressed")]
+    DecompressionFailure(#[source] std::io::Error),
+    #[error("the block is not encrypted as the cache expects")]
+    EncryptionMismatch,
+    #[error("the block's data could not be decrypted")]
+    DecryptionFailure(#[from] AuthenticationError),
 }
 
 impl DiskBlockHeader {
-    /// Creates a new [DiskBlockHeader]
-    pub fn new(block_idx: BlockIndex, block_offset: u64, etag: String, s3_key: String, data_checksum: Crc32c) -> Self {
-        let data_checksum = data_checksum.value();
-        let header_checksum = Self::compute_checksum(block_idx, block_offset, &etag, &s3_key, data_checksum).value();
-        DiskBlockHeader {
+    /// Creates a new [DiskBlockHeader] for the block at `block_idx` in a cache of `block_size` blocks
+    pub fn new(
+        block_idx: BlockIndex,
+        block_size: u64,
+        etag: String,
+        s3_key: String,
+        data_checksum: Option<Crc32c>,
+        compression: Option<CompressionCodec>,
+        encryption: Option<BlockEncryption>,
+    ) -> Self {
+        let mut header = DiskBlockHeader {
             block_idx,
-            block_offset,
+            block_offset: block_idx * block_size,
+            block_size,
             etag,
             s3_key,
-            data_checksum,
-            header_checksum,
+            written_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_secs()),
+            data_checksum: data_checksum.map(|checksum| checksum.value()),
+            compression,
+            encryption,
+            header_checksum: 0,
+        };
+        header.header_checksum = header.compute_checksum().value();
+        header
+    }
+
+    fn compute_checksum(&self) -> Crc32c {
+        let mut hasher = crc32c::Hasher::new();
+        hasher.update(&self.block_idx.to_be_bytes());
+        hasher.update(&self.block_offset.to_be_bytes());
+        hasher.update(&self.block_size.to_be_bytes());
+        hasher.update(self.etag.as_bytes());
+        hasher.update(self.s3_key.as_bytes());
+        hasher.update(&self.written_at.to_be_bytes());
+        if let Some(data_checksum) = self.data_checksum {
+            hasher.update(&data_checksum.to_be_bytes());
         }
+        hasher.update(&[CompressionCodec::tag(self.compression)]);
+        if let Some(encryption) = &self.encryption {
+            hasher.update(&encryption.nonce);
+            hasher.update(&encryption.tag);
+        }
+        hasher.finalize()
     }
 
-    fn compute_checksum(
+    /// Data authenticated along with an encrypted block, so that it can't be decrypted as part of
+    /// another object or at another offset.
+    fn associated_data(
         block_idx: BlockIndex,
-        block_offset: u64,
+        block_size: u64,
         etag: &str,
         s3_key: &str,
-        data_checksum: u32,
-    ) -> Crc32c {
-        let mut hasher = crc32c::Hasher::new();
-        hasher.update(&block_idx.to_be_bytes());
-        hasher.update(&block_offset.to_be_bytes());
-        hasher.update(etag.as_bytes());
-        hasher.update(s3_key.as_bytes());
-        hasher.update(&data_checksum.to_be_bytes());
-        hasher.finalize()
+        compression: Option<CompressionCodec>,
+    ) -> Vec<u8> {
+        let mut data = Vec::with_capacity(etag.len() + s3_key.len() + 33);
+        data.extend_from_slice(&block_idx.to_be_bytes());
+        data.extend_from_slice(&block_size.to_be_bytes());
+        data.extend_from_slice(&(etag.len() as u64).to_be_bytes());
+        data.extend_from_slice(etag.as_bytes());
+        data.extend_from_slice(&(s3_key.len() as u64).to_be_bytes());
+        data.extend_from_slice(s3_key.as_bytes());
+        data.push(CompressionCodec::tag(compression));
+        data
+    }
+
+    /// When the block was written.
+    fn written_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.written_at)
     }
 
-    /// Validate the integrity of the contained data and return the stored data checksum.
+    /// Validate the integrity of the contained data and return the stored data checksum, if any.
     ///
-    /// Execute this method before acting on the data contained within.
+    /// Execute this method before acting on the data contained within. Blocks written to a cache
+    /// with a different block size are rejected, even the first block of an object.
     pub fn validate(
         &self,
         s3_key: &str,
         etag: &str,
         block_idx: BlockIndex,
-        block_offset: u64,
-    ) -> Result<Crc32c, DiskBlockAccessError> {
+        block_size: u64,
+    ) -> Result<Option<Crc32c>, DiskBlockAccessError> {
         let s3_key_match = s3_key == self.s3_key;
         let etag_match = etag == self.etag;
         let block_idx_match = block_idx == self.block_idx;
-        let block_offset_match = block_offset == self.block_offset;
+        let block_size_match = block_size == self.block_size && block_idx * block_size == self.block_offset;
 
-        let data_checksum = self.data_checksum;
-        if s3_key_match && etag_match && block_idx_match && block_offset_match {
-            if Self::compute_checksum(block_idx, block_offset, etag, s3_key, data_checksum).value()
-                != self.header_checksum
-            {
+        if s3_key_match && etag_match && block_idx_match && block_size_match {
+            if self.compute_checksum().value() != self.header_checksum {
                 Err(DiskBlockAccessError::ChecksumError)
             } else {
-                Ok(Crc32c::new(data_checksum))
+                Ok(self.data_checksum.map(Crc32c::new))
             }
         } else {
             warn!(
                 s3_key_match,
-                etag_match, block_idx_match, "block data did not match expected values",
+                etag_match, block_idx_match, block_size_match, "block data did not match expected values",
             );
             Err(DiskBlockAccessError::FieldMismatchError)
         }
@@ -160,7 +320,7 @@ impl DiskBlockHeader {
 struct DiskBlock {
     /// Information describing the content of `data`, to be used to verify correctness
     header: DiskBlockHeader,
-    /// Cached bytes
+    /// Cached bytes, compressed and encrypted as described in the header
     data: Bytes,
 }
 
@@ -169,16 +329,51 @@ impl DiskBlock {
     ///
     /// This may return an integrity error if the checksummed byte buffer is found to be corrupt.
     /// However, this check is not guaranteed and it shouldn't be assumed that the data within the block is not corrupt.
+    /// The data is compressed with `compression` unless it doesn't shrink enough, then encrypted with
+    /// `encryption_key` if any. The header only records a checksum of the uncompressed data for
+    /// unencrypted blocks.
     fn new(
         cache_key: ObjectId,
         block_idx: BlockIndex,
-        block_offset: u64,
+        block_size: u64,
         bytes: ChecksummedBytes,
+        compression: Option<CompressionCodec>,
+        encryption_key: Option<&EncryptionKey>,
     ) -> Result<Self, DiskBlockCreationError> {
         let s3_key = cache_key.key().to_owned();
         let etag = cache_key.etag().as_str().to_owned();
         let (data, data_checksum) = bytes.into_inner()?;
-        let header = DiskBlockHeader::new(block_idx, block_offset, etag, s3_key, data_checksum);
+        let (data, compression) = match compression.map(|codec| (codec, codec.compress(&data))) {
+            Some((codec, Ok(compressed)))
+                if (compressed.len() as f64) <= data.len() as f64 * (1.0 - MIN_COMPRESSION_SAVINGS) =>
+            {
+                metrics::counter!("disk_data_cache.compressed_blocks").increment(1);
+                (compressed.into(), Some(codec))
+            }
+            Some((codec, Err(err))) => {
+                warn!(?codec, ?err, "failed to compress block, storing it uncompressed");
+                (data, None)
+            }
+            _ => (data, None),
+        };
+        let (data, encryption) = match encryption_key {
+            Some(key) => {
+                let associated_data =
+                    DiskBlockHeader::associated_data(block_idx, block_size, &etag, &s3_key, compression);
+                let (encrypted, encryption) = key.encrypt(&associated_data, &data);
+                (encrypted.into(), Some(encryption))
+            }
+            None => (data, None),
+        };
+        let header = DiskBlockHeader::new(
+            block_idx,
+            block_size,
+            etag,
+            s3_key,
+            encryption.is_none().then_some(data_checksum),
+            compression,
+            encryption,
+        );
 
         Ok(DiskBlock { data, header })
     }
@@ -186,16 +381,46 @@ impl DiskBlock {
     /// Extract the block data, checking that fields such as S3 key, etc. match what we expect.
     ///
     /// Comparing these fields helps ensure we have not corrupted or swapped block data on disk.
+    /// When the cache is encrypted, the block must be encrypted with `encryption_key`.
     fn data(
         &self,
         cache_key: &ObjectId,
         block_idx: BlockIndex,
-        block_offset: u64,
+        block_size: u64,
+        encryption_key: Option<&EncryptionKey>,
     ) -> Result<ChecksummedBytes, DiskBlockAccessError> {
-        let data_checksum =
-            self.header
-                .validate(cache_key.key(), cache_key.etag().as_str(), block_idx, block_offset)?;
-        let bytes = ChecksummedBytes::new_from_inner_data(self.data.clone(), data_checksum);
+        let data_checksum = self
+            .header
+            .validate(cache_key.key(), cache_key.etag().as_str(), block_idx, block_size)?;
+        let data = match (encryption_key, &self.header.encryption) {
+            // Unencrypted blocks can only be validated with their checksum
+            (None, None) if data_checksum.is_none() => return Err(DiskBlockAccessError::ChecksumError),
+            (None, None) => self.data.clone(),
+            (Some(key), Some(encryption)) => {
+                let associated_data = DiskBlockHeader::associated_data(
+                    block_idx,
+                    block_size,
+                    cache_key.etag().as_str(),
+                    cache_key.key(),
+                    self.header.compression,
+                );
+                key.decrypt(&associated_data, &self.data, encryption)?.into()
+            }
+            _ => return Err(DiskBlockAccessError::EncryptionMismatch),
+        };
+        let data = match self.header.compression {
+            None => data,
+            Some(codec) => codec
+                .decompress(&data, block_size as usize)
+                .map_err(DiskBlockAccessError::DecompressionFailure)?
+                .into(),
+        };
+        let bytes = match data_checksum {
+            // The checksum is over the uncompressed data, so it also validates the decompression
+            Some(data_checksum) => ChecksummedBytes::new_from_inner_data(data, data_checksum),
+            // The encrypted data was authenticated, so the decompressed data is what was written
+            None => ChecksummedBytes::new(data),
+        };
         Ok(bytes)
     }
 }
@@ -217,7 +442,170 @@ impl DiskDataCache {
             cache_directory,
             config,
             usage,
+            shared_usage: None,
+            object_versions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create a new instance of an [DiskDataCache] in a `cache_directory` that may be used by other
+    /// processes at the same time, for example several Mountpoint processes on the same host.
+    ///
+    /// Blocks are written atomically, and the total size of the cache is tracked in a file in the
+    /// cache directory so that the cache limit applies to all the processes together. When the
+    /// limit is exceeded, the least recently used blocks across all processes are evicted.
+    pub fn new_shared(cache_directory: PathBuf, config: DiskDataCacheConfig) -> DataCacheResult<Self> {
+        let mut cache = Self::new(cache_directory, config);
+        if cache.usage.take().is_none() {
+            // No limit, so no need to track the size
+            return Ok(cache);
+        }
+
+        let shared_usage = SharedUsage::new(cache.cache_directory.join(SHARED_USAGE_FILE_NAME));
+        {
+            let mut usage = shared_usage.lock()?;
+            if usage.size().is_none() {
+                // We are the first process to use this directory, initialize the total size
+                cache.remove_stale_versions()?;
+                let size = cache.list_blocks()?.iter().map(|block| block.size).sum();
+                usage.set_size(size)?;
+                tracing::debug!(cache_directory = ?cache.cache_directory, size, "initialized shared cache usage");
+            }
+        }
+        cache.shared_usage = Some(shared_usage);
+        Ok(cache)
+    }
+
+    /// Create a new instance of an [DiskDataCache] that reuses the blocks already stored in
+    /// `cache_directory`, for example by a previous Mountpoint process.
+    ///
+    /// Each existing block is checked against its header, and blocks that are invalid or were
+    /// written with a different [CACHE_VERSION] are removed. The remaining blocks are tracked in
+    /// order of their modification time, so the oldest will be evicted first.
+    pub fn new_with_existing_blocks(cache_directory: PathBuf, config: DiskDataCacheConfig) -> DataCacheResult<Self> {
+        let cache = Self::new(cache_directory, config);
+        cache.remove_stale_versions()?;
+
+        let mut blocks = cache.scan_blocks()?;
+        blocks.sort_by_key(|block| block.modified);
+        let block_count = blocks.len();
+        for block in &blocks {
+            cache.add_object_version(&block.s3_key, block.block_key.hashed_key);
+        }
+        if let Some(usage) = &cache.usage {
+            let mut usage = usage.lock().unwrap();
+            for block in blocks {
+                if cache.config.policy.is_pinned(&block.s3_key) {
+                    usage.add_pinned(block.block_key, block.size);
+                } else {
+                    usage.add(block.block_key, block.size);
+                }
+            }
+        }
+        tracing::debug!(cache_directory = ?cache.cache_directory, block_count, "reusing existing cache blocks");
+
+        if let Err(err) = cache.evict_if_needed() {
+            warn!(?err, "unable to evict existing blocks to honor the cache limit");
+        }
+        Ok(cache)
+    }
+
+    /// Remove directories containing blocks written with a different [CACHE_VERSION].
+    fn remove_stale_versions(&self) -> DataCacheResult<()> {
+        let entries = match fs::read_dir(&self.cache_directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if is_version_dir_name(name) && name != CACHE_VERSION && entry.file_type()?.is_dir() {
+                warn!(path = ?entry.path(), "removing blocks with stale cache version");
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the valid blocks in the cache directory, returning their keys, sizes, and modification
+    /// times. Invalid blocks are removed.
+    fn scan_blocks(&self) -> DataCacheResult<Vec<ExistingBlock>> {
+        let version_path = self.cache_directory.join(CACHE_VERSION);
+        let mut blocks = Vec::new();
+        let first_dirs = match fs::read_dir(&version_path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(blocks),
+            Err(err) => return Err(err.into()),
+        };
+        for first_dir in first_dirs {
+            let first_dir = first_dir?;
+            if !first_dir.file_type()?.is_dir() {
+                continue;
+            }
+            for second_dir in fs::read_dir(first_dir.path())? {
+                let second_dir = second_dir?;
+                if !second_dir.file_type()?.is_dir() {
+                    continue;
+                }
+                let hex_key = format!(
+                    "{}{}",
+                    first_dir.file_name().to_string_lossy(),
+                    second_dir.file_name().to_string_lossy()
+                );
+                for block_file in fs::read_dir(second_dir.path())? {
+                    let block_file = block_file?;
+                    let path = block_file.path();
+                    match self.check_existing_block(&hex_key, &block_file) {
+                        Some(block) => blocks.push(block),
+                        None => {
+                            warn!(?path, "removing invalid block from the cache");
+                            if let Err(err) = fs::remove_file(&path) {
+                                warn!(?path, ?err, "unable to remove invalid block");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Check that the block in `block_file` is stored at the path matching its header.
+    /// Only the header is read, so the integrity of the data is checked when the block is read.
+    fn check_existing_block(&self, hex_key: &str, block_file: &fs::DirEntry) -> Option<ExistingBlock> {
+        let block_idx: BlockIndex = block_file.file_name().to_str()?.parse().ok()?;
+        let metadata = block_file.metadata().ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+
+        let header = read_block_header(&block_file.path())?;
+        header
+            .validate(&header.s3_key, &header.etag, block_idx, self.config.block_size)
+            .ok()?;
+
+        let cache_key = ObjectId::new(header.s3_key, header.etag.as_str().into());
+        let block_key = DiskBlockKey::new(&cache_key, block_idx);
+        if block_key.hex_key() != hex_key {
+            return None;
         }
+        Some(ExistingBlock {
+            block_key,
+            size: metadata.len() as usize,
+            modified: metadata.modified().ok()?,
+            s3_key: cache_key.key().to_owned(),
+        })
+    }
+
+    /// Whether the block at `path` was written more than `max_age` ago, according to its header.
+    fn is_block_expired(&self, path: &Path, max_age: Duration) -> bool {
+        let Some(header) = read_block_header(path) else {
+            return false;
+        };
+        header.written_at().elapsed().is_ok_and(|age| age > max_age)
     }
 
     /// Get the relative path for the given block.
@@ -232,7 +620,6 @@ impl DiskDataCache {
         path: impl AsRef<Path>,
         cache_key: &ObjectId,
         block_idx: BlockIndex,
-        block_offset: u64,
     ) -> DataCacheResult<Option<ChecksummedBytes>> {
         let mut file = match fs::File::open(path.as_ref()) {
             Ok(file) => file,
@@ -258,17 +645,27 @@ impl DiskDataCache {
             }
         };
         let bytes = block
-            .data(cache_key, block_idx, block_offset)
+            .data(
+                cache_key,
+                block_idx,
+                self.config.block_size,
+                self.config.encryption_key.as_ref(),
+            )
             .map_err(|err| match err {
-                DiskBlockAccessError::ChecksumError | DiskBlockAccessError::FieldMismatchError => {
-                    DataCacheError::InvalidBlockContent
-                }
+                DiskBlockAccessError::ChecksumError
+                | DiskBlockAccessError::FieldMismatchError
+                | DiskBlockAccessError::DecompressionFailure(_)
+                | DiskBlockAccessError::EncryptionMismatch
+                | DiskBlockAccessError::DecryptionFailure(_) => DataCacheError::InvalidBlockContent,
             })?;
 
         Ok(Some(bytes))
     }
 
-    fn write_block(&self, path: impl AsRef<Path>, block: DiskBlock) -> DataCacheResult<usize> {
+    /// Write the block to a temporary file next to `path`, returning the temporary path and the
+    /// size of the file. The file should then be renamed to `path`, so that readers (including other
+    /// processes) never see a partially written block.
+    fn write_temp_block(&self, path: impl AsRef<Path>, block: DiskBlock) -> DataCacheResult<(PathBuf, usize)> {
         let cache_path_for_key = path
             .as_ref()
             .parent()
@@ -278,27 +675,97 @@ impl DiskDataCache {
             .recursive(true)
             .create(cache_path_for_key)?;
 
+        // Block file names are only digits, so temporary files can't be mistaken for blocks
+        let temp_path = path.as_ref().with_extension(format!(
+            "tmp{}-{}",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
         trace!(
             key = block.header.s3_key,
             offset = block.header.block_offset,
             "writing block at {}",
-            path.as_ref().display()
+            temp_path.display()
         );
         let mut file = fs::OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
             .mode(0o600)
-            .open(path.as_ref())?;
-        file.write_all(CACHE_VERSION.as_bytes())?;
-        let serialize_result = bincode::serialize_into(&mut file, &block);
-        if let Err(err) = serialize_result {
-            return match *err {
-                bincode::ErrorKind::Io(io_err) => return Err(DataCacheError::from(io_err)),
-                _ => Err(DataCacheError::InvalidBlockContent),
-            };
+            .open(&temp_path)?;
+        let result = file
+            .write_all(CACHE_VERSION.as_bytes())
+            .map_err(DataCacheError::from)
+            .and_then(|()| match bincode::serialize_into(&mut file, &block) {
+                Ok(()) => Ok(file.stream_position()? as usize),
+                Err(err) => match *err {
+                    bincode::ErrorKind::Io(io_err) => Err(DataCacheError::from(io_err)),
+                    _ => Err(DataCacheError::InvalidBlockContent),
+                },
+            });
+        match result {
+            Ok(size) => Ok((temp_path, size)),
+            Err(err) => {
+                remove_temp_file(&temp_path);
+                Err(err)
+            }
+        }
+    }
+
+    fn write_block(&self, path: impl AsRef<Path>, block: DiskBlock) -> DataCacheResult<usize> {
+        let (temp_path, size) = self.write_temp_block(&path, block)?;
+        if let Err(err) = fs::rename(&temp_path, path.as_ref()) {
+            remove_temp_file(&temp_path);
+            return Err(err.into());
+        }
+        Ok(size)
+    }
+
+    /// Write a block to a cache directory shared with other processes, evicting blocks if needed.
+    ///
+    /// Other processes may hold the lock on the directory for a while, e.g. when evicting, so we
+    /// poll for it rather than blocking the executor thread, and give up after [SHARED_LOCK_TIMEOUT].
+    async fn write_shared_block(
+        &self,
+        shared_usage: &SharedUsage,
+        path: impl AsRef<Path>,
+        block: DiskBlock,
+    ) -> DataCacheResult<usize> {
+        let (temp_path, size) = self.write_temp_block(&path, block)?;
+
+        // Hold the lock while updating the cache directory, so the total size stays accurate
+        let result = match shared_usage.lock_async().await {
+            Ok(mut usage) => self.commit_shared_block(&mut usage, &temp_path, path.as_ref(), size),
+            Err(err) => Err(err.into()),
+        };
+        if result.is_err() {
+            remove_temp_file(&temp_path);
+        }
+        result
+    }
+
+    /// Move a block written to `temp_path` into place at `path` in a shared cache directory.
+    fn commit_shared_block(
+        &self,
+        usage: &mut SharedUsageGuard,
+        temp_path: &Path,
+        path: &Path,
+        size: usize,
+    ) -> DataCacheResult<usize> {
+        let eviction_start = Instant::now();
+        let result = self.evict_shared_if_needed(usage, size);
+        metrics::histogram!("disk_data_cache.eviction_duration_us").record(eviction_start.elapsed().as_micros() as f64);
+        result?;
+
+        let replaced_size = match fs::metadata(path) {
+            Ok(metadata) => metadata.len() as usize,
+            Err(err) if err.kind() == ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
         };
-        Ok(file.stream_position()? as usize)
+        fs::rename(temp_path, path)?;
+        let total_size = usage.size().unwrap_or(0).saturating_sub(replaced_size);
+        usage.set_size(total_size.saturating_add(size))?;
+        Ok(size)
     }
 
     fn is_limit_exceeded(&self, size: usize) -> bool {
@@ -343,11 +810,178 @@ impl DiskDataCache {
         Ok(())
     }
 
+    /// Whether a shared cache directory should keep evicting blocks. Unlike [Self::is_limit_exceeded],
+    /// this leaves some headroom below a [CacheLimit::TotalSize] limit.
+    fn is_above_eviction_target(&self, size: usize) -> bool {
+        match self.config.limit {
+            CacheLimit::TotalSize { max_size } => {
+                let headroom = (max_size as f64 * SHARED_EVICTION_HEADROOM) as usize;
+                size > max_size - headroom
+            }
+            _ => self.is_limit_exceeded(size),
+        }
+    }
+
+    /// Evict the least recently used blocks from a shared cache directory if adding `new_size`
+    /// bytes would exceed the limit. This scans the cache directory, so it also corrects the total
+    /// size for any changes that were not tracked, e.g. if another process crashed.
+    fn evict_shared_if_needed(&self, usage: &mut SharedUsageGuard, new_size: usize) -> DataCacheResult<()> {
+        if !self.is_limit_exceeded(usage.size().unwrap_or(0).saturating_add(new_size)) {
+            return Ok(());
+        }
+
+        let blocks = self.list_blocks()?;
+        let mut size: usize = blocks.iter().map(|block| block.size).sum();
+        // Only read the block headers if some blocks may be pinned
+        let check_pinned = self.config.policy.has_pinned_prefixes();
+        let (mut pinned, mut unpinned): (Vec<_>, Vec<_>) = blocks.into_iter().partition(|block| {
+            check_pinned
+                && read_block_header(&block.path).is_some_and(|header| self.config.policy.is_pinned(&header.s3_key))
+        });
+        pinned.sort_by_key(|block| block.modified);
+        unpinned.sort_by_key(|block| block.modified);
+        // Pinned blocks are only evicted as a last resort, when they alone exceed the limit
+        let unpinned_count = unpinned.len();
+        let mut blocks = unpinned.into_iter().chain(pinned).enumerate();
+        while self.is_above_eviction_target(size.saturating_add(new_size)) {
+            let Some((index, block)) = blocks.next() else {
+                usage.set_size(size)?;
+                warn!("cache limit exceeded but nothing to evict");
+                return Err(DataCacheError::EvictionFailure);
+            };
+            if index >= unpinned_count {
+                warn!(path = ?block.path, "cache limit exceeded by pinned blocks, evicting pinned block");
+                metrics::counter!("disk_data_cache.evicted_pinned_blocks").increment(1);
+            }
+            trace!("evicting block at {}", block.path.display());
+            match fs::remove_file(&block.path) {
+                Ok(()) => size = size.saturating_sub(block.size),
+                Err(remove_err) if remove_err.kind() == ErrorKind::NotFound => size = size.saturating_sub(block.size),
+                Err(remove_err) => warn!("unable to evict block: {:?}", remove_err),
+            }
+        }
+        usage.set_size(size)?;
+        Ok(())
+    }
+
+    /// List the block files in the cache directory, without checking their content.
+    /// Blocks removed while listing are skipped.
+    fn list_blocks(&self) -> DataCacheResult<Vec<BlockFile>> {
+        fn read_dir(path: &Path) -> std::io::Result<Vec<fs::DirEntry>> {
+            match fs::read_dir(path) {
+                Ok(entries) => entries.collect(),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+                Err(err) => Err(err),
+            }
+        }
+
+        let mut blocks = Vec::new();
+        for first_dir in read_dir(&self.cache_directory.join(CACHE_VERSION))? {
+            for second_dir in read_dir(&first_dir.path())? {
+                for block_file in read_dir(&second_dir.path())? {
+                    let is_block = block_file
+                        .file_name()
+                        .to_str()
+                        .is_some_and(|name| name.parse::<BlockIndex>().is_ok());
+                    if !is_block {
+                        continue;
+                    }
+                    let metadata = match block_file.metadata() {
+                        Ok(metadata) => metadata,
+                        Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                        Err(err) => return Err(err.into()),
+                    };
+                    blocks.push(BlockFile {
+                        path: block_file.path(),
+                        size: metadata.len() as usize,
+                        modified: metadata.modified()?,
+                    });
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
     fn remove_block_from_usage(&self, block_key: &DiskBlockKey) {
         if let Some(usage) = &self.usage {
             usage.lock().unwrap().remove(block_key);
         }
     }
+
+    /// Record that blocks of a version of the object with key `s3_key` are stored under `hashed_key`.
+    fn add_object_version(&self, s3_key: &str, hashed_key: [u8; 32]) {
+        let mut object_versions = self.object_versions.lock().unwrap();
+        match object_versions.get_mut(s3_key) {
+            Some(hashed_keys) => {
+                hashed_keys.insert(hashed_key);
+            }
+            None => {
+                object_versions.insert(s3_key.to_owned(), HashSet::from([hashed_key]));
+            }
+        }
+    }
+
+    /// Remove the blocks of the object version stored under `hashed_key`, returning their total size.
+    fn remove_object_version(&self, hashed_key: [u8; 32]) -> usize {
+        let mut path = self.cache_directory.join(CACHE_VERSION);
+        append_object_dir_to_path(&hashed_key, &mut path);
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return 0,
+            Err(err) => {
+                warn!(?path, ?err, "unable to list blocks to remove");
+                return 0;
+            }
+        };
+
+        let mut removed_size = 0;
+        for entry in entries.flatten() {
+            // Skip the temporary files of blocks being written
+            let Some(block_index) = entry.file_name().to_str().and_then(|name| name.parse().ok()) else {
+                continue;
+            };
+            let size = entry.metadata().map_or(0, |metadata| metadata.len() as usize);
+            match fs::remove_file(entry.path()) {
+                Ok(()) => removed_size += size,
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => {
+                    warn!(path = ?entry.path(), ?err, "unable to remove block");
+                    continue;
+                }
+            }
+            self.remove_block_from_usage(&DiskBlockKey {
+                hashed_key,
+                block_index,
+            });
+        }
+        // Fails if a block is being written, in which case the directory is left for it
+        let _ = fs::remove_dir(&path);
+        removed_size
+    }
+}
+
+/// Whether `name` is the name of a directory of blocks written with some [CACHE_VERSION].
+fn is_version_dir_name(name: &str) -> bool {
+    name.len() > 1 && name.starts_with('V') && name[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Read the header of the block at `path`, if it is a valid block of the current [CACHE_VERSION].
+fn read_block_header(path: &Path) -> Option<DiskBlockHeader> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut block_version = [0; CACHE_VERSION.len()];
+    file.read_exact(&mut block_version).ok()?;
+    if block_version != CACHE_VERSION.as_bytes() {
+        return None;
+    }
+    // The header is serialized first, so we can deserialize it without reading the data.
+    bincode::deserialize_from(&file).ok()
+}
+
+/// Remove a temporary block file that won't be renamed into place.
+fn remove_temp_file(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        warn!(?path, ?err, "unable to remove temporary block file");
+    }
 }
 
 /// Hash the cache key using its fields as well as the [CACHE_VERSION].
@@ -376,7 +1010,21 @@ impl DataCache for DiskDataCache {
         let start = Instant::now();
         let block_key = DiskBlockKey::new(cache_key, block_idx);
         let path = self.get_path_for_block_key(&block_key);
-        match self.read_block(&path, cache_key, block_idx, block_offset) {
+        if let Some(max_age) = self.config.policy.max_age(cache_key.key()) {
+            if self.is_block_expired(&path, max_age) {
+                // Expired block. Count as cache miss.
+                trace!(?cache_key, block_idx, "removing expired block");
+                metrics::counter!("disk_data_cache.block_hit").increment(0);
+                metrics::counter!("disk_data_cache.expired_blocks").increment(1);
+                match fs::remove_file(&path) {
+                    Ok(()) => self.remove_block_from_usage(&block_key),
+                    Err(err) if err.kind() == ErrorKind::NotFound => self.remove_block_from_usage(&block_key),
+                    Err(err) => warn!(?path, ?err, "unable to remove expired block"),
+                }
+                return Ok(None);
+            }
+        }
+        match self.read_block(&path, cache_key, block_idx) {
             Ok(None) => {
                 // Cache miss.
                 metrics::counter!("disk_data_cache.block_hit").increment(0);
@@ -390,6 +1038,18 @@ impl DataCache for DiskDataCache {
                 if let Some(usage) = &self.usage {
                     usage.lock().unwrap().refresh(&block_key);
                 }
+                if self.shared_usage.is_some() {
+                    // Other processes only know the block was used from its modification time. The
+                    // access time isn't reliable, as file systems are often mounted with noatime or
+                    // relatime, and the time the block was written is stored in its header.
+                    let touch_result = fs::OpenOptions::new()
+                        .write(true)
+                        .open(&path)
+                        .and_then(|file| file.set_modified(SystemTime::now()));
+                    if let Err(err) = touch_result {
+                        trace!(?path, ?err, "unable to refresh block modification time");
+                    }
+                }
                 Ok(Some(bytes))
             }
             Err(err) => {
@@ -424,28 +1084,46 @@ impl DataCache for DiskDataCache {
         }
 
         let bytes_len = bytes.len();
+        let s3_key = cache_key.key().to_owned();
         let block_key = DiskBlockKey::new(&cache_key, block_idx);
         let path = self.get_path_for_block_key(&block_key);
         trace!(?cache_key, ?path, "new block will be created in disk cache");
 
-        let block = DiskBlock::new(cache_key, block_idx, block_offset, bytes).map_err(|err| match err {
+        let block = DiskBlock::new(
+            cache_key,
+            block_idx,
+            self.config.block_size,
+            bytes,
+            self.config.compression,
+            self.config.encryption_key.as_ref(),
+        )
+        .map_err(|err| match err {
             DiskBlockCreationError::IntegrityError(_e) => DataCacheError::InvalidBlockContent,
         })?;
 
-        {
-            let eviction_start = Instant::now();
-            let result = self.evict_if_needed();
-            metrics::histogram!("disk_data_cache.eviction_duration_us")
-                .record(eviction_start.elapsed().as_micros() as f64);
-            result
-        }?;
-
         let write_start = Instant::now();
-        let size = self.write_block(path, block)?;
+        let size = if let Some(shared_usage) = &self.shared_usage {
+            self.write_shared_block(shared_usage, path, block).await?
+        } else {
+            {
+                let eviction_start = Instant::now();
+                let result = self.evict_if_needed();
+                metrics::histogram!("disk_data_cache.eviction_duration_us")
+                    .record(eviction_start.elapsed().as_micros() as f64);
+                result
+            }?;
+            self.write_block(path, block)?
+        };
         metrics::histogram!("disk_data_cache.write_duration_us").record(write_start.elapsed().as_micros() as f64);
         metrics::counter!("disk_data_cache.total_bytes", "type" => "write").increment(bytes_len as u64);
+        self.add_object_version(&s3_key, block_key.hashed_key);
         if let Some(usage) = &self.usage {
-            usage.lock().unwrap().add(block_key, size);
+            let mut usage = usage.lock().unwrap();
+            if self.config.policy.is_pinned(&s3_key) {
+                usage.add_pinned(block_key, size);
+            } else {
+                usage.add(block_key, size);
+            }
         }
 
         Ok(())
@@ -454,6 +1132,34 @@ impl DataCache for DiskDataCache {
     fn block_size(&self) -> u64 {
         self.config.block_size
     }
+
+    /// Blocks are found from the versions of the object this cache has stored, or found when
+    /// reusing existing blocks. In a shared cache directory, blocks that only other processes
+    /// stored are not removed.
+    fn remove_object(&self, key: &str) {
+        let Some(hashed_keys) = self.object_versions.lock().unwrap().remove(key) else {
+            return;
+        };
+        // Hold the lock on a shared cache directory, so that its total size stays accurate
+        let mut shared_usage = match self.shared_usage.as_ref().map(SharedUsage::lock).transpose() {
+            Ok(shared_usage) => shared_usage,
+            Err(err) => {
+                warn!(key, ?err, "unable to lock shared cache directory to remove blocks");
+                return;
+            }
+        };
+        let removed_size: usize = hashed_keys
+            .into_iter()
+            .map(|hashed_key| self.remove_object_version(hashed_key))
+            .sum();
+        trace!(key, removed_size, "removed blocks of object");
+        if let Some(shared_usage) = &mut shared_usage {
+            let size = shared_usage.size().unwrap_or(0).saturating_sub(removed_size);
+            if let Err(err) = shared_usage.set_size(size) {
+                warn!(?err, "unable to update shared cache usage");
+            }
+        }
+    }
 }
 
 /// Key to identify a block in the disk cache, composed of a hash of the S3 key and Etag, and the block index.
@@ -480,21 +1186,28 @@ impl DiskBlockKey {
     }
 
     fn append_to_path(&self, path: &mut PathBuf) {
-        let hashed_cache_key = self.hex_key();
-
-        // Split directories by taking the first few chars of hash to avoid hitting any FS-specific maximum number of directory entries.
-        let (first, second) = hashed_cache_key.split_at(HASHED_DIR_SPLIT_INDEX);
-        path.push(first);
-        path.push(second);
+        append_object_dir_to_path(&self.hashed_key, path);
 
         // Append the block index.
         path.push(format!("{:010}", self.block_index));
     }
 }
 
+/// Append the directory containing the blocks of the object version with the given hashed key.
+fn append_object_dir_to_path(hashed_key: &[u8; 32], path: &mut PathBuf) {
+    let hashed_cache_key = hex::encode(hashed_key);
+
+    // Split directories by taking the first few chars of hash to avoid hitting any FS-specific maximum number of directory entries.
+    let (first, second) = hashed_cache_key.split_at(HASHED_DIR_SPLIT_INDEX);
+    path.push(first);
+    path.push(second);
+}
+
 /// Keeps track of entries usage and total size.
 struct UsageInfo<K> {
     entries: LinkedHashMap<K, usize>,
+    /// Entries that are only evicted once no other entries are left, and count towards the total size
+    pinned: LinkedHashMap<K, usize>,
     size: usize,
 }
 
@@ -505,6 +1218,7 @@ where
     fn new() -> Self {
         Self {
             entries: LinkedHashMap::new(),
+            pinned: LinkedHashMap::new(),
             size: 0,
         }
     }
@@ -512,34 +1226,146 @@ where
     /// Refresh the given key if present, marking it as the most recently used.
     /// Returns `false` if the key is not in the cache.
     fn refresh(&mut self, key: &K) -> bool {
-        self.entries.get_refresh(key).is_some()
+        self.entries.get_refresh(key).is_some() || self.pinned.get_refresh(key).is_some()
     }
 
     /// Add or replace a key and update the total size.
     fn add(&mut self, key: K, size: usize) {
-        if let Some(previous_size) = self.entries.insert(key, size) {
-            self.size = self.size.saturating_sub(previous_size);
-        }
+        self.remove(&key);
+        self.entries.insert(key, size);
+        self.size = self.size.saturating_add(size);
+    }
 
+    /// Add or replace a key that should only be evicted as a last resort and update the total size.
+    fn add_pinned(&mut self, key: K, size: usize) {
+        self.remove(&key);
+        self.pinned.insert(key, size);
         self.size = self.size.saturating_add(size);
     }
 
     /// Remove a key if present and update the total size.
     fn remove(&mut self, key: &K) {
-        if let Some(size) = self.entries.remove(key) {
+        if let Some(size) = self.entries.remove(key).or_else(|| self.pinned.remove(key)) {
             self.size = self.size.saturating_sub(size);
         }
     }
 
-    /// Remove the least recently used key and update the total size.
-    /// Return `None` if empty.
+    /// Remove the least recently used key and update the total size. Pinned keys are only removed
+    /// once no other keys are left. Return `None` if empty.
     fn evict_lru(&mut self) -> Option<K> {
-        let (key, size) = self.entries.pop_front()?;
+        let (key, size) = match self.entries.pop_front() {
+            Some(entry) => entry,
+            None => {
+                let entry = self.pinned.pop_front()?;
+                warn!(key = ?entry.0, "cache limit exceeded by pinned entries, evicting pinned entry");
+                metrics::counter!("disk_data_cache.evicted_pinned_blocks").increment(1);
+                entry
+            }
+        };
         self.size = self.size.saturating_sub(size);
         Some(key)
     }
 }
 
+/// A block file found when listing the cache directory.
+struct BlockFile {
+    path: PathBuf,
+    size: usize,
+    /// Last time the block was written or, in a shared cache, read
+    modified: SystemTime,
+}
+
+/// A valid block found in the cache directory when reusing existing blocks.
+struct ExistingBlock {
+    block_key: DiskBlockKey,
+    size: usize,
+    modified: SystemTime,
+    s3_key: String,
+}
+
+/// Keeps track of the total size of a cache directory shared by several processes, in a file that
+/// each process locks while it adds or removes blocks.
+struct SharedUsage {
+    path: PathBuf,
+}
+
+impl SharedUsage {
+    fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Lock the usage file, waiting for other processes to release it. This blocks the thread, so
+    /// async code should use [Self::lock_async] instead.
+    fn lock(&self) -> std::io::Result<SharedUsageGuard> {
+        let guard = self.lock_with(FlockArg::LockExclusive)?;
+        Ok(guard.expect("blocking lock should not fail with EWOULDBLOCK"))
+    }
+
+    /// Lock the usage file without blocking the thread, polling until other processes release it.
+    /// Fails with [ErrorKind::WouldBlock] if it is still locked after [SHARED_LOCK_TIMEOUT].
+    async fn lock_async(&self) -> std::io::Result<SharedUsageGuard> {
+        let deadline = Instant::now() + SHARED_LOCK_TIMEOUT;
+        loop {
+            if let Some(guard) = self.lock_with(FlockArg::LockExclusiveNonblock)? {
+                return Ok(guard);
+            }
+            if Instant::now() >= deadline {
+                metrics::counter!("disk_data_cache.shared_lock_timeout").increment(1);
+                return Err(std::io::Error::new(
+                    ErrorKind::WouldBlock,
+                    "shared cache directory is locked by another process",
+                ));
+            }
+            Timer::after(SHARED_LOCK_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Lock the usage file with `arg`, returning `None` if a non-blocking lock is held elsewhere.
+    fn lock_with(&self, arg: FlockArg) -> std::io::Result<Option<SharedUsageGuard>> {
+        // Each lock needs its own open file, as locks are shared by all users of an open file
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(&self.path)?;
+        let mut file = match Flock::lock(file, arg) {
+            Ok(file) => file,
+            Err((_, Errno::EWOULDBLOCK)) => return Ok(None),
+            Err((_, errno)) => return Err(errno.into()),
+        };
+
+        let mut buf = [0u8; 8];
+        let size = match file.read_exact(&mut buf) {
+            Ok(()) => Some(u64::from_le_bytes(buf) as usize),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => return Err(err),
+        };
+        Ok(Some(SharedUsageGuard { file, size }))
+    }
+}
+
+/// Exclusive access to the total size of a shared cache directory, until dropped.
+struct SharedUsageGuard {
+    file: Flock<fs::File>,
+    /// `None` if the size was never written
+    size: Option<usize>,
+}
+
+impl SharedUsageGuard {
+    fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    fn set_size(&mut self, size: usize) -> std::io::Result<()> {
+        self.file.rewind()?;
+        self.file.write_all(&(size as u64).to_le_bytes())?;
+        self.size = Some(size);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::ffi::OsString;
@@ -551,16 +1377,21 @@ mod tests {
     use mountpoint_s3_client::types::ETag;
     use rand::{Rng, SeedableRng};
     use rand_chacha::ChaCha20Rng;
+    use test_case::test_case;
 
     #[test]
     fn test_block_format_version_requires_update() {
         let cache_key = ObjectId::new("hello-world".to_string(), ETag::for_tests());
         let data = ChecksummedBytes::new("Foo".into());
-        let block = DiskBlock::new(cache_key, 100, 100 * 10, data).expect("should succeed as data checksum is valid");
+        let mut block =
+            DiskBlock::new(cache_key, 100, 10, data, None, None).expect("should succeed as data checksum is valid");
+        block.header.written_at = 1_700_000_000;
+        block.header.header_checksum = block.header.compute_checksum().value();
         let expected_bytes: Vec<u8> = vec![
-            100, 0, 0, 0, 0, 0, 0, 0, 232, 3, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0, 116, 101, 115, 116, 95, 101,
-            116, 97, 103, 11, 0, 0, 0, 0, 0, 0, 0, 104, 101, 108, 108, 111, 45, 119, 111, 114, 108, 100, 9, 85, 128,
-            46, 29, 32, 6, 192, 3, 0, 0, 0, 0, 0, 0, 0, 70, 111, 111,
+            100, 0, 0, 0, 0, 0, 0, 0, 232, 3, 0, 0, 0, 0, 0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0, 116,
+            101, 115, 116, 95, 101, 116, 97, 103, 11, 0, 0, 0, 0, 0, 0, 0, 104, 101, 108, 108, 111, 45, 119, 111, 114,
+            108, 100, 0, 241, 83, 101, 0, 0, 0, 0, 1, 9, 85, 128, 46, 0, 0, 223, 250, 10, 133, 3, 0, 0, 0, 0, 0, 0, 0,
+            70, 111, 111,
         ];
         let serialized_bytes = bincode::serialize(&block).unwrap();
         assert_eq!(
@@ -574,7 +1405,7 @@ mod tests {
         let s3_key = "a".repeat(266);
         let etag = ETag::for_tests();
         let key = ObjectId::new(s3_key, etag);
-        let expected_hash = "b717d5a78ed63238b0778e7295d83e963758aa54db6e969a822f2b13ce9a3067";
+        let expected_hash = "dee6fa8e7ee386175c9733afced6b7b83317b8d59122138c29a9037eeae5c797";
         let actual_hash = hex::encode(hash_cache_key_raw(&key));
         assert_eq!(expected_hash, actual_hash);
     }
@@ -587,6 +1418,9 @@ mod tests {
             DiskDataCacheConfig {
                 block_size: 1024,
                 limit: CacheLimit::Unbounded,
+                compression: None,
+                encryption_key: None,
+                policy: Default::default(),
             },
         );
 
@@ -617,6 +1451,9 @@ mod tests {
             DiskDataCacheConfig {
                 block_size: 1024,
                 limit: CacheLimit::Unbounded,
+                compression: None,
+                encryption_key: None,
+                policy: Default::default(),
             },
         );
 
@@ -652,6 +1489,9 @@ mod tests {
             DiskDataCacheConfig {
                 block_size,
                 limit: CacheLimit::Unbounded,
+                compression: None,
+                encryption_key: None,
+                policy: Default::default(),
             },
         );
         let cache_key_1 = ObjectId::new("a".into(), ETag::for_tests());
@@ -727,6 +1567,191 @@ mod tests {
         );
     }
 
+    #[test_case(CompressionCodec::Lz4; "lz4")]
+    #[test_case(CompressionCodec::Zstd; "zstd")]
+    fn test_compression(codec: CompressionCodec) {
+        const BLOCK_SIZE: u64 = 4096;
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+
+        // Compressible data is stored compressed, and decompressed when read back
+        let compressible: Bytes = "Foo".repeat(1024).into();
+        let block = DiskBlock::new(
+            cache_key.clone(),
+            0,
+            BLOCK_SIZE,
+            ChecksummedBytes::new(compressible.clone()),
+            Some(codec),
+            None,
+        )
+        .expect("should succeed as data checksum is valid");
+        assert_eq!(block.header.compression, Some(codec));
+        assert!(block.data.len() < compressible.len());
+        let data = block
+            .data(&cache_key, 0, BLOCK_SIZE, None)
+            .expect("block should be valid");
+        assert_eq!(compressible, data.into_bytes().unwrap());
+
+        // Incompressible data is stored as is
+        let mut rng = ChaCha20Rng::seed_from_u64(0x12345678);
+        let mut incompressible = vec![0u8; 1024];
+        rng.fill(&mut incompressible[..]);
+        let incompressible: Bytes = incompressible.into();
+        let block = DiskBlock::new(
+            cache_key.clone(),
+            0,
+            BLOCK_SIZE,
+            ChecksummedBytes::new(incompressible.clone()),
+            Some(codec),
+            None,
+        )
+        .expect("should succeed as data checksum is valid");
+        assert_eq!(block.header.compression, None);
+        assert_eq!(block.data, incompressible);
+        let data = block
+            .data(&cache_key, 0, BLOCK_SIZE, None)
+            .expect("block should be valid");
+        assert_eq!(incompressible, data.into_bytes().unwrap());
+    }
+
+    #[test_case(CompressionCodec::Lz4; "lz4")]
+    #[test_case(CompressionCodec::Zstd; "zstd")]
+    fn test_corrupted_compressed_block(codec: CompressionCodec) {
+        const BLOCK_SIZE: u64 = 4096;
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        let data = ChecksummedBytes::new("Foo".repeat(1024).into());
+        let mut block = DiskBlock::new(cache_key.clone(), 0, BLOCK_SIZE, data, Some(codec), None)
+            .expect("should succeed as data checksum is valid");
+
+        let mut corrupted = block.data.to_vec();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        block.data = corrupted.into();
+        // Either decompression fails, or the decompressed data doesn't match the checksum
+        match block.data(&cache_key, 0, BLOCK_SIZE, None) {
+            Err(DiskBlockAccessError::DecompressionFailure(_)) => {}
+            Err(err) => panic!("unexpected error: {err:?}"),
+            Ok(data) => assert!(data.into_bytes().is_err(), "corrupted block should be rejected"),
+        }
+    }
+
+    #[test_case(CompressionCodec::Lz4; "lz4")]
+    #[test_case(CompressionCodec::Zstd; "zstd")]
+    fn test_compressed_block_larger_than_block_size(codec: CompressionCodec) {
+        const BLOCK_SIZE: u64 = 4096;
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        let data = ChecksummedBytes::new("Foo".repeat(1024).into());
+        let mut block = DiskBlock::new(cache_key.clone(), 0, BLOCK_SIZE, data, Some(codec), None)
+            .expect("should succeed as data checksum is valid");
+
+        // Replace the data with content that decompresses to much more than a block
+        block.data = codec.compress(&vec![0u8; 64 * BLOCK_SIZE as usize]).unwrap().into();
+        let err = block
+            .data(&cache_key, 0, BLOCK_SIZE, None)
+            .expect_err("oversized block should be rejected");
+        assert!(matches!(err, DiskBlockAccessError::DecompressionFailure(_)), "{err:?}");
+    }
+
+    #[test_case(None; "uncompressed")]
+    #[test_case(Some(CompressionCodec::Lz4); "lz4")]
+    fn test_encryption(compression: Option<CompressionCodec>) {
+        const BLOCK_SIZE: u64 = 4096;
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        let key = EncryptionKey::generate();
+        let data: Bytes = "Foo".repeat(1024).into();
+        let block = DiskBlock::new(
+            cache_key.clone(),
+            1,
+            BLOCK_SIZE,
+            ChecksummedBytes::new(data.clone()),
+            compression,
+            Some(&key),
+        )
+        .expect("should succeed as data checksum is valid");
+        assert!(block.header.encryption.is_some());
+        assert_eq!(
+            block.header.data_checksum, None,
+            "plaintext checksum should not be stored"
+        );
+        assert!(
+            !block.data.windows(6).any(|window| window == b"FooFoo"),
+            "data should be encrypted"
+        );
+
+        let decrypted = block
+            .data(&cache_key, 1, BLOCK_SIZE, Some(&key))
+            .expect("block should be valid");
+        assert_eq!(data, decrypted.into_bytes().unwrap());
+
+        // Reading with another key, or without a key, fails
+        let err = block
+            .data(&cache_key, 1, BLOCK_SIZE, Some(&EncryptionKey::generate()))
+            .expect_err("should fail with a different key");
+        assert!(matches!(err, DiskBlockAccessError::DecryptionFailure(_)));
+        let err = block
+            .data(&cache_key, 1, BLOCK_SIZE, None)
+            .expect_err("should fail without a key");
+        assert!(matches!(err, DiskBlockAccessError::EncryptionMismatch));
+
+        // Unencrypted blocks are rejected by an encrypted cache
+        let plain_block = DiskBlock::new(
+            cache_key.clone(),
+            1,
+            BLOCK_SIZE,
+            ChecksummedBytes::new(data),
+            compression,
+            None,
+        )
+        .expect("should succeed as data checksum is valid");
+        let err = plain_block
+            .data(&cache_key, 1, BLOCK_SIZE, Some(&key))
+            .expect_err("should fail for an unencrypted block");
+        assert!(matches!(err, DiskBlockAccessError::EncryptionMismatch));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_cache_with_other_key() {
+        let cache_directory = tempfile::tempdir().unwrap();
+        let new_cache = |key| {
+            DiskDataCache::new(
+                cache_directory.path().to_owned(),
+                DiskDataCacheConfig {
+                    block_size: 1024,
+                    limit: CacheLimit::Unbounded,
+                    compression: None,
+                    encryption_key: Some(key),
+                    policy: Default::default(),
+                },
+            )
+        };
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        let data = ChecksummedBytes::new("Foo".into());
+
+        let cache = new_cache(EncryptionKey::generate());
+        cache
+            .put_block(cache_key.clone(), 0, 0, data.clone())
+            .await
+            .expect("cache should be accessible");
+        let entry = cache
+            .get_block(&cache_key, 0, 0)
+            .await
+            .expect("cache should be accessible")
+            .expect("cache entry should be returned");
+        assert_eq!(data, entry);
+
+        // A cache with a different key can't authenticate the block, and removes it
+        let other_cache = new_cache(EncryptionKey::generate());
+        let err = other_cache
+            .get_block(&cache_key, 0, 0)
+            .await
+            .expect_err("block should fail authentication");
+        assert!(matches!(err, DataCacheError::InvalidBlockContent));
+        let entry = cache
+            .get_block(&cache_key, 0, 0)
+            .await
+            .expect("cache should be accessible");
+        assert!(entry.is_none(), "invalid block should have been removed");
+    }
+
     #[tokio::test]
     async fn test_checksummed_bytes_slice() {
         let data = ChecksummedBytes::new("0123456789".into());
@@ -738,6 +1763,9 @@ mod tests {
             DiskDataCacheConfig {
                 block_size: 8 * 1024 * 1024,
                 limit: CacheLimit::Unbounded,
+                compression: None,
+                encryption_key: None,
+                policy: Default::default(),
             },
         );
         let cache_key = ObjectId::new("a".into(), ETag::for_tests());
@@ -817,6 +1845,9 @@ mod tests {
             DiskDataCacheConfig {
                 block_size: BLOCK_SIZE as u64,
                 limit: CacheLimit::TotalSize { max_size: CACHE_LIMIT },
+                compression: None,
+                encryption_key: None,
+                policy: Default::default(),
             },
         );
 
@@ -866,6 +1897,405 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_new_with_existing_blocks() {
+        const BLOCK_SIZE: u64 = 1024;
+        let data = ChecksummedBytes::new(vec![1u8; BLOCK_SIZE as usize].into());
+        let cache_directory = tempfile::tempdir().unwrap();
+        let config = || DiskDataCacheConfig {
+            block_size: BLOCK_SIZE,
+            limit: CacheLimit::TotalSize {
+                max_size: 100 * BLOCK_SIZE as usize,
+            },
+            compression: None,
+            encryption_key: None,
+            policy: Default::default(),
+        };
+        let cache_key_1 = ObjectId::new("a".into(), ETag::for_tests());
+        let cache_key_2 = ObjectId::new("b".into(), ETag::for_tests());
+
+        let cache = DiskDataCache::new(cache_directory.path().to_owned(), config());
+        for block_idx in 0..3 {
+            cache
+                .put_block(cache_key_1.clone(), block_idx, block_idx * BLOCK_SIZE, data.clone())
+                .await
+                .unwrap();
+        }
+        cache.put_block(cache_key_2.clone(), 0, 0, data.clone()).await.unwrap();
+
+        // Corrupt a block, and move another one to a different index
+        let corrupt_path = cache.get_path_for_block_key(&DiskBlockKey::new(&cache_key_1, 1));
+        fs::write(&corrupt_path, b"V1garbage").unwrap();
+        let moved_path = cache.get_path_for_block_key(&DiskBlockKey::new(&cache_key_1, 2));
+        let moved_to_path = cache.get_path_for_block_key(&DiskBlockKey::new(&cache_key_1, 5));
+        fs::rename(&moved_path, &moved_to_path).unwrap();
+        // Add a directory from an older cache version
+        let stale_path = cache_directory.path().join("V0");
+        fs::create_dir_all(stale_path.join("ab")).unwrap();
+        drop(cache);
+
+        let cache = DiskDataCache::new_with_existing_blocks(cache_directory.path().to_owned(), config())
+            .expect("existing blocks should be scanned");
+        assert!(!stale_path.exists(), "stale cache version should be removed");
+        assert!(!corrupt_path.exists(), "corrupt block should be removed");
+        assert!(!moved_to_path.exists(), "block at the wrong path should be removed");
+        assert_eq!(cache.usage.as_ref().unwrap().lock().unwrap().entries.len(), 2);
+
+        let entry = cache.get_block(&cache_key_1, 0, 0).await.unwrap();
+        assert_eq!(entry, Some(data.clone()));
+        let entry = cache.get_block(&cache_key_2, 0, 0).await.unwrap();
+        assert_eq!(entry, Some(data.clone()));
+        let entry = cache.get_block(&cache_key_1, 1, BLOCK_SIZE).await.unwrap();
+        assert!(entry.is_none());
+
+        // Reopening with a lower limit evicts the existing blocks
+        drop(cache);
+        let config = DiskDataCacheConfig {
+            block_size: BLOCK_SIZE,
+            limit: CacheLimit::TotalSize {
+                max_size: BLOCK_SIZE as usize + 100,
+            },
+            compression: None,
+            encryption_key: None,
+            policy: Default::default(),
+        };
+        let cache = DiskDataCache::new_with_existing_blocks(cache_directory.path().to_owned(), config)
+            .expect("existing blocks should be scanned");
+        assert_eq!(cache.usage.as_ref().unwrap().lock().unwrap().entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_existing_blocks_with_other_block_size() {
+        let data = ChecksummedBytes::new("Foo".into());
+        let cache_directory = tempfile::tempdir().unwrap();
+        let config = |block_size| DiskDataCacheConfig {
+            block_size,
+            limit: CacheLimit::TotalSize { max_size: 1024 * 1024 },
+            compression: None,
+            encryption_key: None,
+            policy: Default::default(),
+        };
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+
+        let cache = DiskDataCache::new(cache_directory.path().to_owned(), config(1024));
+        cache.put_block(cache_key.clone(), 0, 0, data.clone()).await.unwrap();
+        let path = cache.get_path_for_block_key(&DiskBlockKey::new(&cache_key, 0));
+        drop(cache);
+
+        // The first block is at offset 0 for any block size, but still belongs to the old cache
+        let cache = DiskDataCache::new_with_existing_blocks(cache_directory.path().to_owned(), config(2048))
+            .expect("existing blocks should be scanned");
+        assert!(!path.exists(), "block with another block size should be removed");
+        assert!(cache.usage.as_ref().unwrap().lock().unwrap().entries.is_empty());
+
+        // Blocks written by another process with a different block size are not served either
+        let other_cache = DiskDataCache::new(cache_directory.path().to_owned(), config(1024));
+        other_cache.put_block(cache_key.clone(), 0, 0, data).await.unwrap();
+        let err = cache
+            .get_block(&cache_key, 0, 0)
+            .await
+            .expect_err("block with another block size should be invalid");
+        assert!(matches!(err, DataCacheError::InvalidBlockContent));
+    }
+
+    #[tokio::test]
+    async fn test_shared_cache_directory() {
+        const BLOCK_SIZE: u64 = 1024;
+        let data = ChecksummedBytes::new(vec![1u8; BLOCK_SIZE as usize].into());
+        let cache_directory = tempfile::tempdir().unwrap();
+        let config = || DiskDataCacheConfig {
+            block_size: BLOCK_SIZE,
+            limit: CacheLimit::TotalSize {
+                max_size: 5 * (BLOCK_SIZE as usize + 250),
+            },
+            compression: None,
+            encryption_key: None,
+            policy: Default::default(),
+        };
+        let cache_key_a = ObjectId::new("a".into(), ETag::for_tests());
+        let cache_key_b = ObjectId::new("b".into(), ETag::for_tests());
+
+        let cache_a = DiskDataCache::new_shared(cache_directory.path().to_owned(), config()).unwrap();
+        let cache_b = DiskDataCache::new_shared(cache_directory.path().to_owned(), config()).unwrap();
+        for block_idx in 0..3 {
+            cache_a
+                .put_block(cache_key_a.clone(), block_idx, block_idx * BLOCK_SIZE, data.clone())
+                .await
+                .unwrap();
+        }
+        for block_idx in 0..2 {
+            cache_b
+                .put_block(cache_key_b.clone(), block_idx, block_idx * BLOCK_SIZE, data.clone())
+                .await
+                .unwrap();
+        }
+
+        // Blocks written by one cache can be read by the other
+        let entry = cache_b.get_block(&cache_key_a, 1, BLOCK_SIZE).await.unwrap();
+        assert_eq!(entry, Some(data.clone()));
+
+        // Make the first block the least recently used
+        let now = SystemTime::now();
+        for (i, block) in cache_a.list_blocks().unwrap().iter().enumerate() {
+            let modified = if block.path == cache_a.get_path_for_block_key(&DiskBlockKey::new(&cache_key_a, 0)) {
+                now - std::time::Duration::from_secs(3600)
+            } else {
+                now - std::time::Duration::from_secs(60 - i as u64)
+            };
+            fs::File::options()
+                .write(true)
+                .open(&block.path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+
+        // Exceeding the limit from either cache evicts the least recently used block
+        cache_b
+            .put_block(cache_key_b.clone(), 2, 2 * BLOCK_SIZE, data.clone())
+            .await
+            .unwrap();
+        let entry = cache_a.get_block(&cache_key_a, 0, 0).await.unwrap();
+        assert!(entry.is_none(), "least recently used block should be evicted");
+        let entry = cache_a.get_block(&cache_key_b, 2, 2 * BLOCK_SIZE).await.unwrap();
+        assert_eq!(entry, Some(data.clone()));
+
+        // The shared size matches the blocks in the directory, and no temporary files are left
+        let blocks = cache_a.list_blocks().unwrap();
+        assert_eq!(blocks.len(), 5);
+        let size = cache_a.shared_usage.as_ref().unwrap().lock().unwrap().size();
+        assert_eq!(size, Some(blocks.iter().map(|block| block.size).sum()));
+        let file_count = walkdir(&cache_directory.path().join(CACHE_VERSION));
+        assert_eq!(file_count, blocks.len());
+
+        // A new cache reuses the shared size
+        let cache_c = DiskDataCache::new_shared(cache_directory.path().to_owned(), config()).unwrap();
+        let size_c = cache_c.shared_usage.as_ref().unwrap().lock().unwrap().size();
+        assert_eq!(size_c, size);
+    }
+
+    #[tokio::test]
+    async fn test_shared_cache_directory_locked() {
+        let data = ChecksummedBytes::new("Foo".into());
+        let cache_directory = tempfile::tempdir().unwrap();
+        let config = DiskDataCacheConfig {
+            block_size: 1024,
+            limit: CacheLimit::TotalSize { max_size: 1024 * 1024 },
+            compression: None,
+            encryption_key: None,
+            policy: Default::default(),
+        };
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        let cache = DiskDataCache::new_shared(cache_directory.path().to_owned(), config).unwrap();
+
+        // While another process holds the lock, the block is dropped instead of blocking the thread
+        let other_process = SharedUsage::new(cache_directory.path().join(SHARED_USAGE_FILE_NAME));
+        let guard = other_process.lock().unwrap();
+        let start = Instant::now();
+        let err = cache
+            .put_block(cache_key.clone(), 0, 0, data.clone())
+            .await
+            .expect_err("should time out waiting for the lock");
+        assert!(matches!(err, DataCacheError::IoFailure(_)), "{err:?}");
+        assert!(start.elapsed() >= SHARED_LOCK_TIMEOUT);
+        assert_eq!(walkdir(&cache_directory.path().join(CACHE_VERSION)), 0);
+
+        drop(guard);
+        cache.put_block(cache_key.clone(), 0, 0, data.clone()).await.unwrap();
+        let entry = cache.get_block(&cache_key, 0, 0).await.unwrap();
+        assert_eq!(entry, Some(data));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_blocks_not_evicted() {
+        const BLOCK_SIZE: u64 = 1024;
+        let data = ChecksummedBytes::new(vec![1u8; BLOCK_SIZE as usize].into());
+        let policy: CachePolicy = serde_json::from_str(r#"{ "rules": [{ "prefix": "pinned/", "action": "pin" }] }"#)
+            .expect("policy should be valid");
+        let cache_directory = tempfile::tempdir().unwrap();
+        let config = || DiskDataCacheConfig {
+            block_size: BLOCK_SIZE,
+            limit: CacheLimit::TotalSize {
+                max_size: 3 * (BLOCK_SIZE as usize + 200),
+            },
+            compression: None,
+            encryption_key: None,
+            policy: Arc::new(policy.clone()),
+        };
+        let pinned_key = ObjectId::new("pinned/a".into(), ETag::for_tests());
+        let other_key = ObjectId::new("other/b".into(), ETag::for_tests());
+
+        let cache = DiskDataCache::new(cache_directory.path().to_owned(), config());
+        cache.put_block(pinned_key.clone(), 0, 0, data.clone()).await.unwrap();
+        for block_idx in 0..4 {
+            cache
+                .put_block(other_key.clone(), block_idx, block_idx * BLOCK_SIZE, data.clone())
+                .await
+                .unwrap();
+        }
+
+        // The pinned block was the least recently used, but other blocks are evicted instead
+        let entry = cache.get_block(&pinned_key, 0, 0).await.unwrap();
+        assert_eq!(entry, Some(data.clone()));
+        let entry = cache.get_block(&other_key, 0, 0).await.unwrap();
+        assert!(entry.is_none(), "unpinned block should be evicted");
+
+        // Blocks are still pinned when reused by a new cache
+        drop(cache);
+        let cache = DiskDataCache::new_with_existing_blocks(cache_directory.path().to_owned(), config())
+            .expect("existing blocks should be scanned");
+        assert_eq!(cache.usage.as_ref().unwrap().lock().unwrap().pinned.len(), 1);
+    }
+
+    #[test_case(false; "private")]
+    #[test_case(true; "shared")]
+    #[tokio::test]
+    async fn test_pinned_blocks_evicted_as_last_resort(shared: bool) {
+        const BLOCK_SIZE: u64 = 1024;
+        let data = ChecksummedBytes::new(vec![1u8; BLOCK_SIZE as usize].into());
+        let policy: CachePolicy = serde_json::from_str(r#"{ "rules": [{ "prefix": "pinned/", "action": "pin" }] }"#)
+            .expect("policy should be valid");
+        let cache_directory = tempfile::tempdir().unwrap();
+        let config = DiskDataCacheConfig {
+            block_size: BLOCK_SIZE,
+            limit: CacheLimit::TotalSize {
+                max_size: 3 * (BLOCK_SIZE as usize + 200),
+            },
+            compression: None,
+            encryption_key: None,
+            policy: Arc::new(policy),
+        };
+        let cache = if shared {
+            DiskDataCache::new_shared(cache_directory.path().to_owned(), config).unwrap()
+        } else {
+            DiskDataCache::new(cache_directory.path().to_owned(), config)
+        };
+        let pinned_key = ObjectId::new("pinned/a".into(), ETag::for_tests());
+
+        // Writing more pinned blocks than fit in the cache evicts the oldest pinned blocks
+        for block_idx in 0..5 {
+            cache
+                .put_block(pinned_key.clone(), block_idx, block_idx * BLOCK_SIZE, data.clone())
+                .await
+                .expect("pinned blocks should not make the cache unwritable");
+            if shared {
+                // Make sure the modification times of the blocks are ordered
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let entry = cache.get_block(&pinned_key, 0, 0).await.unwrap();
+        assert!(entry.is_none(), "oldest pinned block should be evicted");
+        let entry = cache.get_block(&pinned_key, 4, 4 * BLOCK_SIZE).await.unwrap();
+        assert_eq!(entry, Some(data));
+    }
+
+    #[test_case(false; "private")]
+    #[test_case(true; "shared")]
+    #[tokio::test]
+    async fn test_remove_object(shared: bool) {
+        const BLOCK_SIZE: u64 = 1024;
+        let data = ChecksummedBytes::new(vec![1u8; BLOCK_SIZE as usize].into());
+        let cache_directory = tempfile::tempdir().unwrap();
+        let config = DiskDataCacheConfig {
+            block_size: BLOCK_SIZE,
+            limit: CacheLimit::TotalSize { max_size: 1024 * 1024 },
+            compression: None,
+            encryption_key: None,
+            policy: Default::default(),
+        };
+        let cache = if shared {
+            DiskDataCache::new_shared(cache_directory.path().to_owned(), config).unwrap()
+        } else {
+            DiskDataCache::new(cache_directory.path().to_owned(), config)
+        };
+        let old_version = ObjectId::new("a".into(), ETag::from_str("old").unwrap());
+        let new_version = ObjectId::new("a".into(), ETag::from_str("new").unwrap());
+        let other_key = ObjectId::new("b".into(), ETag::for_tests());
+        for cache_key in [&old_version, &new_version, &other_key] {
+            for block_idx in 0..2 {
+                cache
+                    .put_block(cache_key.clone(), block_idx, block_idx * BLOCK_SIZE, data.clone())
+                    .await
+                    .expect("cache should be accessible");
+            }
+        }
+        let size = |cache: &DiskDataCache| match &cache.shared_usage {
+            Some(shared_usage) => shared_usage.lock().unwrap().size().unwrap(),
+            None => cache.usage.as_ref().unwrap().lock().unwrap().size,
+        };
+        cache.remove_object("a");
+        for cache_key in [&old_version, &new_version] {
+            let entry = cache.get_block(cache_key, 1, BLOCK_SIZE).await.unwrap();
+            assert!(entry.is_none(), "blocks of every version should be removed");
+        }
+        let entry = cache.get_block(&other_key, 1, BLOCK_SIZE).await.unwrap();
+        assert_eq!(entry, Some(data));
+        let remaining = cache.list_blocks().unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(size(&cache), remaining.iter().map(|block| block.size).sum::<usize>());
+    }
+
+    #[tokio::test]
+    async fn test_expired_blocks() {
+        let policy: CachePolicy =
+            serde_json::from_str(r#"{ "rules": [{ "prefix": "daily/", "max_age_seconds": 60 }] }"#)
+                .expect("policy should be valid");
+        let cache_directory = tempfile::tempdir().unwrap();
+        let cache = DiskDataCache::new(
+            cache_directory.path().to_owned(),
+            DiskDataCacheConfig {
+                block_size: 1024,
+                limit: CacheLimit::Unbounded,
+                compression: None,
+                encryption_key: None,
+                policy: Arc::new(policy),
+            },
+        );
+        let data = ChecksummedBytes::new("Foo".into());
+        let daily_key = ObjectId::new("daily/a".into(), ETag::for_tests());
+        let other_key = ObjectId::new("other/b".into(), ETag::for_tests());
+        for cache_key in [&daily_key, &other_key] {
+            cache.put_block(cache_key.clone(), 0, 0, data.clone()).await.unwrap();
+        }
+
+        // Recently written blocks are served
+        let entry = cache.get_block(&daily_key, 0, 0).await.unwrap();
+        assert_eq!(entry, Some(data.clone()));
+
+        // Make both blocks older than the maximum age
+        for cache_key in [&daily_key, &other_key] {
+            let path = cache.get_path_for_block_key(&DiskBlockKey::new(cache_key, 0));
+            let mut file = fs::File::open(&path).unwrap();
+            file.seek(std::io::SeekFrom::Start(CACHE_VERSION.len() as u64)).unwrap();
+            let mut block: DiskBlock = bincode::deserialize_from(&file).unwrap();
+            block.header.written_at -= 3600;
+            block.header.header_checksum = block.header.compute_checksum().value();
+            cache.write_block(&path, block).unwrap();
+        }
+
+        let entry = cache.get_block(&daily_key, 0, 0).await.unwrap();
+        assert!(entry.is_none(), "expired block should not be served");
+        let path = cache.get_path_for_block_key(&DiskBlockKey::new(&daily_key, 0));
+        assert!(!path.exists(), "expired block should be removed");
+        let entry = cache.get_block(&other_key, 0, 0).await.unwrap();
+        assert_eq!(entry, Some(data), "blocks without a maximum age should be served");
+    }
+
+    /// Count the files in a directory tree
+    fn walkdir(path: &Path) -> usize {
+        fs::read_dir(path)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                if entry.file_type().unwrap().is_dir() {
+                    walkdir(&entry.path())
+                } else {
+                    1
+                }
+            })
+            .sum()
+    }
+
     #[test]
     fn data_block_extract_checks() {
         let data_1 = ChecksummedBytes::new("Foo".into());
@@ -874,21 +2304,22 @@ mod tests {
         let cache_key_2 = ObjectId::new("b".into(), ETag::for_tests());
         let cache_key_3 = ObjectId::new("a".into(), ETag::from_str("badetag").unwrap());
 
-        let block = DiskBlock::new(cache_key_1.clone(), 0, 0, data_1.clone()).expect("should have no checksum err");
+        let block = DiskBlock::new(cache_key_1.clone(), 0, 1024, data_1.clone(), None, None)
+            .expect("should have no checksum err");
         block
-            .data(&cache_key_1, 1, 0)
+            .data(&cache_key_1, 1, 1024, None)
             .expect_err("should fail due to incorrect block index");
         block
-            .data(&cache_key_1, 0, 1024)
-            .expect_err("should fail due to incorrect block offset");
+            .data(&cache_key_1, 0, 2048, None)
+            .expect_err("should fail due to incorrect block size");
         block
-            .data(&cache_key_2, 0, 0)
+            .data(&cache_key_2, 0, 1024, None)
             .expect_err("should fail due to incorrect s3 key in cache key");
         block
-            .data(&cache_key_3, 0, 0)
+            .data(&cache_key_3, 0, 1024, None)
             .expect_err("should fail due to incorrect etag in cache key");
         let unpacked_bytes = block
-            .data(&cache_key_1, 0, 0)
+            .data(&cache_key_1, 0, 1024, None)
             .expect("should be OK as all fields match");
         assert_eq!(data_1, unpacked_bytes, "data block should return original bytes");
     }
@@ -896,45 +2327,47 @@ mod tests {
     #[test]
     fn validate_block_header() {
         let block_idx = 0;
-        let block_offset = 0;
+        let block_size = 1024;
         let etag = ETag::for_tests();
         let s3_key = String::from("s3/key");
         let data_checksum = Crc32c::new(42);
         let mut header = DiskBlockHeader::new(
             block_idx,
-            block_offset,
+            block_size,
             etag.as_str().to_owned(),
             s3_key.clone(),
-            data_checksum,
+            Some(data_checksum),
+            None,
+            None,
         );
 
         let checksum = header
-            .validate(&s3_key, etag.as_str(), block_idx, block_offset)
+            .validate(&s3_key, etag.as_str(), block_idx, block_size)
             .expect("should be OK with valid fields and checksum");
-        assert_eq!(data_checksum, checksum);
+        assert_eq!(Some(data_checksum), checksum);
 
         // Bad fields
         let err = header
-            .validate("hello", etag.as_str(), block_idx, block_offset)
+            .validate("hello", etag.as_str(), block_idx, block_size)
             .expect_err("should fail with invalid s3_key");
         assert!(matches!(err, DiskBlockAccessError::FieldMismatchError));
         let err = header
-            .validate(&s3_key, "bad etag", block_idx, block_offset)
+            .validate(&s3_key, "bad etag", block_idx, block_size)
             .expect_err("should fail with invalid etag");
         assert!(matches!(err, DiskBlockAccessError::FieldMismatchError));
         let err = header
-            .validate(&s3_key, etag.as_str(), 5, block_offset)
+            .validate(&s3_key, etag.as_str(), 5, block_size)
             .expect_err("should fail with invalid block idx");
         assert!(matches!(err, DiskBlockAccessError::FieldMismatchError));
         let err = header
-            .validate(&s3_key, etag.as_str(), block_idx, 1024)
-            .expect_err("should fail with invalid block offset");
+            .validate(&s3_key, etag.as_str(), block_idx, 2048)
+            .expect_err("should fail with invalid block size");
         assert!(matches!(err, DiskBlockAccessError::FieldMismatchError));
 
         // Bad checksum
         header.header_checksum = 23;
         let err = header
-            .validate(&s3_key, etag.as_str(), block_idx, block_offset)
+            .validate(&s3_key, etag.as_str(), block_idx, block_size)
             .expect_err("should fail with invalid checksum");
         assert!(matches!(err, DiskBlockAccessError::ChecksumError));
     }
@@ -0,0 +1,549 @@
+//! Offline inspection and maintenance of the directories used by a [DiskDataCache].
+//!
+//! The [CacheInspector] reads every block file in a cache directory and checks it the same way
+//! [DiskDataCache] does when serving it, without needing the S3 object it was fetched from. It is
+//! meant to be used while no Mountpoint process is using the directory, and refuses to remove
+//! blocks from a directory in use.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use nix::fcntl::{Flock, FlockArg};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use super::{
+    is_version_dir_name, CompressionCodec, DiskBlock, DiskBlockKey, DiskDataCache, EncryptionKey, SharedUsage,
+    SharedUsageGuard, CACHE_VERSION, SHARED_USAGE_FILE_NAME,
+};
+use crate::data_cache::cache_directory::{ManagedCacheDir, ManagedCacheDirError};
+use crate::data_cache::BlockIndex;
+use crate::object::ObjectId;
+
+/// Reads and checks the blocks stored in a cache directory, see the [module documentation](self).
+#[derive(Debug)]
+pub struct CacheInspector {
+    path: PathBuf,
+    encryption_key: Option<EncryptionKey>,
+}
+
+/// A block file found by a [CacheInspector].
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockReport {
+    /// Path of the block file
+    pub path: PathBuf,
+    /// Size of the block file in bytes
+    pub size: u64,
+    /// Modification time of the block file. This is the time the block was written, or the last
+    /// time it was read for a cache directory shared by several Mountpoint processes.
+    #[serde(skip)]
+    pub modified: SystemTime,
+    /// Result of checking the block
+    pub status: BlockStatus,
+}
+
+/// Result of checking a block file.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum BlockStatus {
+    /// The block is valid and would be served by Mountpoint.
+    Valid(BlockInfo),
+    /// The block is invalid, and would be removed by Mountpoint when read.
+    Corrupt { reason: String },
+    /// The block was written by a version of Mountpoint with a different cache format.
+    ForeignVersion { version: String },
+}
+
+/// Description of a valid block, from its header.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockInfo {
+    /// S3 key of the object the block belongs to
+    pub s3_key: String,
+    /// ETag of the object the block belongs to
+    pub etag: String,
+    /// Index of the block in the object
+    pub block_idx: BlockIndex,
+    /// Offset of the block in the object
+    pub block_offset: u64,
+    /// Size of the blocks of the cache the block was written to
+    pub block_size: u64,
+    /// Time the block was written, in seconds since the UNIX epoch
+    pub written_at: u64,
+    /// Codec the block is compressed with, if any
+    pub compression: Option<CompressionCodec>,
+    /// Whether the block is encrypted
+    pub encrypted: bool,
+    /// Whether the checksum of the data was checked. The data of encrypted blocks can only be
+    /// checked with the encryption key.
+    pub data_verified: bool,
+}
+
+/// The blocks of an object found in a cache directory.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ObjectCoverage {
+    /// S3 key of the object
+    pub s3_key: String,
+    /// ETag of the object
+    pub etag: String,
+    /// Number of blocks of the object in the cache
+    pub block_count: usize,
+    /// Total size of the block files in bytes
+    pub size: u64,
+    /// Ranges of consecutive block indices in the cache, inclusive
+    pub block_ranges: Vec<(BlockIndex, BlockIndex)>,
+}
+
+/// Which blocks to remove with [CacheInspector::purge].
+#[derive(Debug, Clone, Default)]
+pub struct PurgeFilter {
+    /// Remove the valid blocks of objects with keys starting with this prefix
+    pub prefix: Option<String>,
+    /// Remove the blocks written longer ago than this age
+    pub older_than: Option<Duration>,
+    /// Remove the corrupt and foreign version blocks
+    pub invalid: bool,
+}
+
+/// Error from [CacheInspector::purge].
+#[derive(Debug, Error)]
+pub enum PurgeError {
+    #[error("cache directory {0:?} is in use by a Mountpoint process")]
+    InUse(PathBuf),
+    #[error("IO error while purging the cache directory")]
+    IoFailure(#[from] io::Error),
+}
+
+/// Locks held on a cache directory while blocks are removed from it.
+struct CacheDirLock {
+    path: PathBuf,
+    _dir_lock: Flock<fs::File>,
+    _usage_lock: Option<SharedUsageGuard>,
+}
+
+impl CacheInspector {
+    /// Create a new [CacheInspector] for the blocks in `path`, which can either be the directory of
+    /// a [DiskDataCache] or any directory containing them, such as the `--cache` directory of Mountpoint.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            encryption_key: None,
+        }
+    }
+
+    /// Check the data of encrypted blocks with the given key.
+    pub fn with_encryption_key(mut self, encryption_key: EncryptionKey) -> Self {
+        self.encryption_key = Some(encryption_key);
+        self
+    }
+
+    /// Read and check every block file.
+    pub fn scan(&self) -> io::Result<Vec<BlockReport>> {
+        let mut reports = Vec::new();
+        for (version, version_path) in find_version_dirs(&self.path)? {
+            let is_current_version = version == CACHE_VERSION;
+            for (hex_key, path) in list_block_files(&version_path)? {
+                let metadata = fs::metadata(&path)?;
+                let status = if is_current_version {
+                    match self.check_block(&hex_key, &path) {
+                        Ok(info) => BlockStatus::Valid(info),
+                        Err(reason) => BlockStatus::Corrupt { reason },
+                    }
+                } else {
+                    BlockStatus::ForeignVersion {
+                        version: version.clone(),
+                    }
+                };
+                reports.push(BlockReport {
+                    path,
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                    status,
+                });
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Remove the blocks matching `filter`, returning the blocks removed.
+    ///
+    /// The cache directories are locked like Mountpoint does when using them, and the purge fails
+    /// with [PurgeError::InUse] if a Mountpoint process is using any of them.
+    ///
+    /// Directories shared by several Mountpoint processes track their total size in a file, which
+    /// is removed so that the next Mountpoint process computes it again.
+    pub fn purge(&self, filter: &PurgeFilter) -> Result<Vec<BlockReport>, PurgeError> {
+        let _locks = self.lock_cache_dirs()?;
+        let now = SystemTime::now();
+        let mut removed = Vec::new();
+        for block in self.scan()? {
+            let matches_prefix = match (&block.status, &filter.prefix) {
+                (BlockStatus::Valid(info), Some(prefix)) => info.s3_key.starts_with(prefix.as_str()),
+                _ => false,
+            };
+            let written_at = match &block.status {
+                BlockStatus::Valid(info) => UNIX_EPOCH + Duration::from_secs(info.written_at),
+                _ => block.modified,
+            };
+            let matches_age = filter
+                .older_than
+                .is_some_and(|max_age| now.duration_since(written_at).is_ok_and(|age| age > max_age));
+            let matches_invalid = filter.invalid && !matches!(block.status, BlockStatus::Valid(_));
+            if matches_prefix || matches_age || matches_invalid {
+                debug!(path = ?block.path, "removing block");
+                match fs::remove_file(&block.path) {
+                    Ok(()) => removed.push(block),
+                    Err(err) if err.kind() == ErrorKind::NotFound => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        if !removed.is_empty() {
+            for (_, version_path) in find_version_dirs(&self.path)? {
+                let usage_path = version_path.with_file_name(SHARED_USAGE_FILE_NAME);
+                match fs::remove_file(&usage_path) {
+                    Ok(()) => debug!(path = ?usage_path, "removed shared usage file"),
+                    Err(err) if err.kind() == ErrorKind::NotFound => {}
+                    Err(err) => warn!(path = ?usage_path, ?err, "unable to remove shared usage file"),
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Lock each directory containing blocks, failing if a Mountpoint process is using it. Both the
+    /// lock file of the directory and the usage file of a shared directory are locked.
+    fn lock_cache_dirs(&self) -> Result<Vec<CacheDirLock>, PurgeError> {
+        let mut locks: Vec<CacheDirLock> = Vec::new();
+        for (_, version_path) in find_version_dirs(&self.path)? {
+            let Some(cache_dir) = version_path.parent() else {
+                continue;
+            };
+            if locks.iter().any(|lock| lock.path == cache_dir) {
+                continue;
+            }
+            let dir_lock = ManagedCacheDir::lock_for_maintenance(cache_dir).map_err(|err| match err {
+                ManagedCacheDirError::AlreadyInUse(path) => PurgeError::InUse(path),
+                ManagedCacheDirError::LockFailure(err) => PurgeError::IoFailure(err),
+                err => PurgeError::IoFailure(io::Error::other(err)),
+            })?;
+            let usage_path = cache_dir.join(SHARED_USAGE_FILE_NAME);
+            let usage_lock = if usage_path.exists() {
+                let usage_lock = SharedUsage::new(usage_path).lock_with(FlockArg::LockExclusiveNonblock)?;
+                Some(usage_lock.ok_or_else(|| PurgeError::InUse(cache_dir.to_owned()))?)
+            } else {
+                None
+            };
+            debug!(path = ?cache_dir, "locked cache directory");
+            locks.push(CacheDirLock {
+                path: cache_dir.to_owned(),
+                _dir_lock: dir_lock,
+                _usage_lock: usage_lock,
+            });
+        }
+        Ok(locks)
+    }
+
+    /// Check the block in the file at `path`, returning why it is invalid otherwise.
+    fn check_block(&self, hex_key: &str, path: &Path) -> Result<BlockInfo, String> {
+        let block_idx: BlockIndex = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .and_then(|name| name.parse().ok())
+            .ok_or("file name is not a block index")?;
+
+        let mut file = fs::File::open(path).map_err(|err| format!("failed to open block: {err}"))?;
+        let mut block_version = [0; CACHE_VERSION.len()];
+        file.read_exact(&mut block_version)
+            .map_err(|err| format!("failed to read block version: {err}"))?;
+        if block_version != CACHE_VERSION.as_bytes() {
+            return Err(format!(
+                "unexpected block version {:?}",
+                String::from_utf8_lossy(&block_version)
+            ));
+        }
+        let block: DiskBlock =
+            bincode::deserialize_from(&file).map_err(|err| format!("block could not be deserialized: {err}"))?;
+        let header = &block.header;
+        header
+            .validate(&header.s3_key, &header.etag, block_idx, header.block_size)
+            .map_err(|err| err.to_string())?;
+
+        let cache_key = ObjectId::new(header.s3_key.clone(), header.etag.as_str().into());
+        if DiskBlockKey::new(&cache_key, block_idx).hex_key() != hex_key {
+            return Err("block is stored at the path of another object".to_owned());
+        }
+
+        let encrypted = header.encryption.is_some();
+        let data_verified = !encrypted || self.encryption_key.is_some();
+        if data_verified {
+            block
+                .data(&cache_key, block_idx, header.block_size, self.encryption_key.as_ref())
+                .map_err(|err| err.to_string())?
+                .validate()
+                .map_err(|err| err.to_string())?;
+        }
+
+        Ok(BlockInfo {
+            s3_key: header.s3_key.clone(),
+            etag: header.etag.clone(),
+            block_idx,
+            block_offset: header.block_offset,
+            block_size: header.block_size,
+            written_at: header.written_at,
+            compression: header.compression,
+            encrypted,
+            data_verified,
+        })
+    }
+}
+
+/// Group the valid blocks by object, ordered by key and ETag.
+pub fn object_coverage(blocks: &[BlockReport]) -> Vec<ObjectCoverage> {
+    let mut objects: BTreeMap<(&str, &str), (ObjectCoverage, Vec<BlockIndex>)> = BTreeMap::new();
+    for block in blocks {
+        let BlockStatus::Valid(info) = &block.status else {
+            continue;
+        };
+        let (object, indices) = objects.entry((&info.s3_key, &info.etag)).or_insert_with(|| {
+            let object = ObjectCoverage {
+                s3_key: info.s3_key.clone(),
+                etag: info.etag.clone(),
+                ..Default::default()
+            };
+            (object, Vec::new())
+        });
+        object.block_count += 1;
+        object.size += block.size;
+        indices.push(info.block_idx);
+    }
+
+    objects
+        .into_values()
+        .map(|(mut object, mut indices)| {
+            indices.sort_unstable();
+            for idx in indices {
+                match object.block_ranges.last_mut() {
+                    Some((_, end)) if *end + 1 == idx => *end = idx,
+                    _ => object.block_ranges.push((idx, idx)),
+                }
+            }
+            object
+        })
+        .collect()
+}
+
+/// Find the directories of block files under `path`, named after the cache version they were
+/// written with.
+fn find_version_dirs(path: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        match entry.file_name().to_str() {
+            Some(name) if is_version_dir_name(name) => dirs.push((name.to_owned(), entry.path())),
+            _ => dirs.extend(find_version_dirs(&entry.path())?),
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// List the block files in a version directory, with the hex encoded hash of their cache key.
+fn list_block_files(version_path: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    for first_dir in fs::read_dir(version_path)? {
+        let first_dir = first_dir?;
+        if !first_dir.file_type()?.is_dir() {
+            continue;
+        }
+        for second_dir in fs::read_dir(first_dir.path())? {
+            let second_dir = second_dir?;
+            if !second_dir.file_type()?.is_dir() {
+                continue;
+            }
+            let hex_key = format!(
+                "{}{}",
+                first_dir.file_name().to_string_lossy(),
+                second_dir.file_name().to_string_lossy()
+            );
+            for block_file in fs::read_dir(second_dir.path())? {
+                let block_file = block_file?;
+                // Skip the temporary files blocks are written to before being renamed into place
+                let is_temp_file = Path::new(&block_file.file_name())
+                    .extension()
+                    .is_some_and(|extension| extension.as_encoded_bytes().starts_with(b"tmp"));
+                if block_file.file_type()?.is_file() && !is_temp_file {
+                    files.push((hex_key.clone(), block_file.path()));
+                }
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+impl DiskDataCache {
+    /// Create a [CacheInspector] for the blocks of this cache.
+    pub fn inspector(&self) -> CacheInspector {
+        CacheInspector::new(&self.cache_directory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mountpoint_s3_client::types::ETag;
+
+    use crate::checksums::ChecksummedBytes;
+    use crate::data_cache::{CacheLimit, DataCache, DiskDataCacheConfig};
+
+    #[tokio::test]
+    async fn test_scan_and_purge() {
+        let cache_directory = tempfile::tempdir().unwrap();
+        let cache = DiskDataCache::new(
+            cache_directory.path().join("mountpoint-cache"),
+            DiskDataCacheConfig {
+                block_size: 8,
+                limit: CacheLimit::Unbounded,
+                compression: None,
+                encryption_key: None,
+                policy: Default::default(),
+            },
+        );
+        let data = ChecksummedBytes::new("Foo".into());
+        let key_a = ObjectId::new("dir/a".into(), ETag::for_tests());
+        let key_b = ObjectId::new("other/b".into(), ETag::for_tests());
+        for block_idx in [0, 1, 3] {
+            cache
+                .put_block(key_a.clone(), block_idx, block_idx * 8, data.clone())
+                .await
+                .unwrap();
+        }
+        cache.put_block(key_b.clone(), 0, 0, data.clone()).await.unwrap();
+
+        // Corrupt a block of b, and add a block from another version
+        let corrupt_path = cache.get_path_for_block_key(&DiskBlockKey::new(&key_b, 0));
+        let mut content = fs::read(&corrupt_path).unwrap();
+        *content.last_mut().unwrap() ^= 1;
+        fs::write(&corrupt_path, content).unwrap();
+        let foreign_path = cache_directory.path().join("mountpoint-cache/V1/ab/cdef/0000000000");
+        fs::create_dir_all(foreign_path.parent().unwrap()).unwrap();
+        fs::write(&foreign_path, "old block").unwrap();
+        // Temporary files of blocks being written are not blocks
+        let temp_path = corrupt_path.with_extension("tmp1234-0");
+        fs::write(&temp_path, "partial block").unwrap();
+
+        let inspector = CacheInspector::new(cache_directory.path());
+        let blocks = inspector.scan().unwrap();
+        assert_eq!(blocks.len(), 5);
+        let corrupt = blocks.iter().find(|block| block.path == corrupt_path).unwrap();
+        assert!(matches!(corrupt.status, BlockStatus::Corrupt { .. }), "{corrupt:?}");
+        let foreign = blocks.iter().find(|block| block.path == foreign_path).unwrap();
+        assert!(matches!(&foreign.status, BlockStatus::ForeignVersion { version } if version == "V1"));
+
+        let objects = object_coverage(&blocks);
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].s3_key, "dir/a");
+        assert_eq!(objects[0].block_count, 3);
+        assert_eq!(objects[0].block_ranges, vec![(0, 1), (3, 3)]);
+
+        let removed = inspector
+            .purge(&PurgeFilter {
+                invalid: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(removed.len(), 2);
+        let removed = inspector
+            .purge(&PurgeFilter {
+                prefix: Some("dir/".to_owned()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(removed.len(), 3);
+        assert!(inspector.scan().unwrap().is_empty());
+        assert!(temp_path.exists(), "temporary files should not be purged");
+    }
+
+    #[tokio::test]
+    async fn test_purge_in_use() {
+        let cache_directory = tempfile::tempdir().unwrap();
+        let managed_dir = ManagedCacheDir::new_shared_from_parent_with_cache_key(cache_directory.path(), None).unwrap();
+        let config = || DiskDataCacheConfig {
+            block_size: 8,
+            limit: CacheLimit::TotalSize { max_size: 1024 },
+            compression: None,
+            encryption_key: None,
+            policy: Default::default(),
+        };
+        let cache = DiskDataCache::new_shared(managed_dir.as_path_buf(), config()).unwrap();
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        cache
+            .put_block(cache_key, 0, 0, ChecksummedBytes::new("Foo".into()))
+            .await
+            .unwrap();
+
+        let filter = PurgeFilter {
+            prefix: Some(String::new()),
+            ..Default::default()
+        };
+        let inspector = CacheInspector::new(cache_directory.path());
+        let err = inspector.purge(&filter).expect_err("directory is in use");
+        assert!(matches!(err, PurgeError::InUse(_)), "{err:?}");
+
+        // The usage file is also locked while another process updates it
+        drop(managed_dir);
+        let usage = SharedUsage::new(cache.cache_directory.join(SHARED_USAGE_FILE_NAME));
+        let guard = usage.lock().unwrap();
+        let err = inspector.purge(&filter).expect_err("usage file is locked");
+        assert!(matches!(err, PurgeError::InUse(_)), "{err:?}");
+
+        drop(guard);
+        let removed = inspector.purge(&filter).unwrap();
+        assert_eq!(removed.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scan_encrypted() {
+        let key_bytes = [7u8; 32];
+        let cache_directory = tempfile::tempdir().unwrap();
+        let cache = DiskDataCache::new(
+            cache_directory.path().to_owned(),
+            DiskDataCacheConfig {
+                block_size: 8,
+                limit: CacheLimit::Unbounded,
+                compression: None,
+                encryption_key: Some(EncryptionKey::from_bytes(&key_bytes).unwrap()),
+                policy: Default::default(),
+            },
+        );
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        cache
+            .put_block(cache_key, 0, 0, ChecksummedBytes::new("Foo".into()))
+            .await
+            .unwrap();
+
+        // Without the key, only the header is checked
+        let blocks = cache.inspector().scan().unwrap();
+        assert!(matches!(&blocks[0].status, BlockStatus::Valid(info) if info.encrypted && !info.data_verified));
+
+        let inspector = cache
+            .inspector()
+            .with_encryption_key(EncryptionKey::from_bytes(&key_bytes).unwrap());
+        let blocks = inspector.scan().unwrap();
+        assert!(matches!(&blocks[0].status, BlockStatus::Valid(info) if info.data_verified));
+
+        let inspector = cache.inspector().with_encryption_key(EncryptionKey::generate());
+        let blocks = inspector.scan().unwrap();
+        assert!(matches!(blocks[0].status, BlockStatus::Corrupt { .. }));
+    }
+}
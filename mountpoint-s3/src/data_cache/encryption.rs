@@ -0,0 +1,171 @@
+//! Encryption of the blocks stored by a data cache.
+//!
+//! Blocks are encrypted with AES-256-GCM, an authenticated encryption (AEAD) cipher, so that
+//! reading a block also verifies it was written with the same key and has not been modified.
+
+use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+
+use aes_gcm::aead::{AeadCore, AeadInPlace, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce, Tag};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Length in bytes of an [EncryptionKey].
+pub const ENCRYPTION_KEY_LEN: usize = 32;
+
+/// Key used to encrypt the blocks of a data cache.
+///
+/// The key material is never printed, and only held in memory for the lifetime of the key.
+pub struct EncryptionKey {
+    cipher: Aes256Gcm,
+}
+
+/// Errors when loading an [EncryptionKey].
+#[derive(Debug, Error)]
+pub enum EncryptionKeyError {
+    #[error("failed to read the key file")]
+    IoError(#[from] io::Error),
+    #[error("the key must be {ENCRYPTION_KEY_LEN} bytes, or {} hexadecimal characters", ENCRYPTION_KEY_LEN * 2)]
+    InvalidKey,
+}
+
+/// The block could not be decrypted, either because it was encrypted with a different key or
+/// because its content or header was modified.
+#[derive(Debug, Error)]
+#[error("block failed authentication")]
+pub struct AuthenticationError;
+
+/// Nonce and authentication tag of an encrypted block, stored in the block header.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockEncryption {
+    pub(super) nonce: [u8; 12],
+    pub(super) tag: [u8; 16],
+}
+
+impl EncryptionKey {
+    /// Generate a new random key, for example to encrypt a cache that does not outlive the process.
+    pub fn generate() -> Self {
+        let key = Aes256Gcm::generate_key(OsRng);
+        Self {
+            cipher: Aes256Gcm::new(&key),
+        }
+    }
+
+    /// Create a key from its raw bytes.
+    pub fn from_bytes(key: &[u8]) -> Result<Self, EncryptionKeyError> {
+        let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| EncryptionKeyError::InvalidKey)?;
+        Ok(Self { cipher })
+    }
+
+    /// Load a key from a file, containing either the raw key or its hexadecimal encoding.
+    /// Whitespace around a hexadecimal key is ignored.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, EncryptionKeyError> {
+        let content = std::fs::read(path)?;
+        if content.len() == ENCRYPTION_KEY_LEN {
+            return Self::from_bytes(&content);
+        }
+        let key = hex::decode(content.trim_ascii()).map_err(|_| EncryptionKeyError::InvalidKey)?;
+        Self::from_bytes(&key)
+    }
+
+    /// Encrypt `data`, binding it to `associated_data` so it can't be decrypted in another context.
+    ///
+    /// A random nonce is used for each block. With 96-bit nonces, a key can safely encrypt billions
+    /// of blocks, much more than fit in a cache directory.
+    pub fn encrypt(&self, associated_data: &[u8], data: &[u8]) -> (Vec<u8>, BlockEncryption) {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut buffer = data.to_vec();
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(&nonce, associated_data, &mut buffer)
+            .expect("blocks should be smaller than the maximum AES-GCM message size");
+        let encryption = BlockEncryption {
+            nonce: nonce.into(),
+            tag: tag.into(),
+        };
+        (buffer, encryption)
+    }
+
+    /// Decrypt and authenticate `data`, which must have been encrypted with the same key and
+    /// `associated_data`.
+    pub fn decrypt(
+        &self,
+        associated_data: &[u8],
+        data: &[u8],
+        encryption: &BlockEncryption,
+    ) -> Result<Vec<u8>, AuthenticationError> {
+        let mut buffer = data.to_vec();
+        self.cipher
+            .decrypt_in_place_detached(
+                Nonce::from_slice(&encryption.nonce),
+                associated_data,
+                &mut buffer,
+                Tag::from_slice(&encryption.tag),
+            )
+            .map_err(|_| AuthenticationError)?;
+        Ok(buffer)
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionKey").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = EncryptionKey::generate();
+        let (encrypted, encryption) = key.encrypt(b"context", b"Foo");
+        assert_ne!(&encrypted, b"Foo");
+
+        let decrypted = key.decrypt(b"context", &encrypted, &encryption).unwrap();
+        assert_eq!(&decrypted, b"Foo");
+
+        // Different context
+        key.decrypt(b"other context", &encrypted, &encryption)
+            .expect_err("decryption should fail with a different context");
+
+        // Different key
+        let other_key = EncryptionKey::generate();
+        other_key
+            .decrypt(b"context", &encrypted, &encryption)
+            .expect_err("decryption should fail with a different key");
+
+        // Modified data
+        let mut modified = encrypted.clone();
+        modified[0] ^= 1;
+        key.decrypt(b"context", &modified, &encryption)
+            .expect_err("decryption should fail with modified data");
+    }
+
+    #[test]
+    fn test_key_from_file() {
+        let raw_key: Vec<u8> = (0..ENCRYPTION_KEY_LEN as u8).collect();
+
+        let mut raw_file = tempfile::NamedTempFile::new().unwrap();
+        raw_file.write_all(&raw_key).unwrap();
+        let raw = EncryptionKey::from_file(raw_file.path()).expect("raw key should be valid");
+
+        let mut hex_file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(hex_file, "{}", hex::encode(&raw_key)).unwrap();
+        let hex = EncryptionKey::from_file(hex_file.path()).expect("hex key should be valid");
+
+        // Both files contain the same key
+        let (encrypted, encryption) = raw.encrypt(b"", b"Foo");
+        assert_eq!(&hex.decrypt(b"", &encrypted, &encryption).unwrap(), b"Foo");
+
+        let mut short_file = tempfile::NamedTempFile::new().unwrap();
+        short_file.write_all(b"0123").unwrap();
+        let result = EncryptionKey::from_file(short_file.path());
+        assert!(matches!(result, Err(EncryptionKeyError::InvalidKey)), "{result:?}");
+    }
+}
@@ -1,33 +1,95 @@
-//! Module for the in-memory data cache implementation used for testing.
+//! Module for the in-memory data cache implementation.
 
-use std::collections::HashMap;
 use std::default::Default;
+use std::sync::{OnceLock, Weak};
 
 use async_trait::async_trait;
+use linked_hash_map::LinkedHashMap;
+use tracing::{trace, warn};
 
 use super::{BlockIndex, ChecksummedBytes, DataCache, DataCacheError, DataCacheResult};
+use crate::mem_limiter::{MemoryBudget, ReclaimableMemory};
 use crate::object::ObjectId;
-use crate::sync::RwLock;
+use crate::sync::Mutex;
 
-/// Simple in-memory (RAM) implementation of [DataCache]. Recommended for use in testing only.
+/// In-memory (RAM) implementation of [DataCache].
+///
+/// Without a size limit, the cache grows without bound and so is only suitable for testing. With a
+/// limit, the least recently used blocks are evicted to keep the total size of cached blocks under
+/// it. The cache also implements [ReclaimableMemory], so that the memory limiter can evict blocks
+/// when it needs the memory for something else, and can stop the cache from growing into memory
+/// that's reserved for something else.
+#[derive(Debug)]
 pub struct InMemoryDataCache {
-    data: RwLock<HashMap<ObjectId, HashMap<BlockIndex, ChecksummedBytes>>>,
+    state: Mutex<CacheState>,
     block_size: u64,
+    /// Maximum total size of cached blocks in bytes. `None` when no limit was set.
+    max_size: Option<u64>,
+    /// Memory the cache can grow into, set by the memory limiter
+    budget: OnceLock<Weak<dyn MemoryBudget>>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Cached blocks, in order from least to most recently used
+    blocks: LinkedHashMap<(ObjectId, BlockIndex), ChecksummedBytes>,
+    /// Total size of cached blocks in bytes
+    size: u64,
+}
+
+impl CacheState {
+    /// Remove the least recently used block. Returns its size, or `None` if the cache is empty.
+    fn evict_lru(&mut self) -> Option<u64> {
+        let ((cache_key, block_idx), bytes) = self.blocks.pop_front()?;
+        trace!(?cache_key, block_idx, "evicting block from in-memory cache");
+        let size = bytes.len() as u64;
+        self.size = self.size.saturating_sub(size);
+        metrics::counter!("in_memory_data_cache.evicted_blocks").increment(1);
+        metrics::gauge!("in_memory_data_cache.total_bytes").set(self.size as f64);
+        Some(size)
+    }
 }
 
 impl InMemoryDataCache {
-    /// Create a new instance of an [InMemoryDataCache] with the specified `block_size`.
+    /// Create a new instance of an unbounded [InMemoryDataCache] with the specified `block_size`.
     pub fn new(block_size: u64) -> Self {
         InMemoryDataCache {
-            data: Default::default(),
+            state: Default::default(),
             block_size,
+            max_size: None,
+            budget: OnceLock::new(),
+        }
+    }
+
+    /// Create a new instance of an [InMemoryDataCache] with the specified `block_size`, holding at
+    /// most `max_size` bytes of blocks.
+    pub fn new_with_limit(block_size: u64, max_size: u64) -> Self {
+        InMemoryDataCache {
+            state: Default::default(),
+            block_size,
+            max_size: Some(max_size),
+            budget: OnceLock::new(),
+        }
+    }
+
+    /// The most the cache can currently hold: its maximum size, if any, and no more than the memory
+    /// limiter can spare.
+    fn size_limit(&self) -> Option<u64> {
+        let budget = self
+            .budget
+            .get()
+            .and_then(Weak::upgrade)
+            .map(|budget| budget.reclaimable_mem_limit());
+        match (self.max_size, budget) {
+            (Some(max_size), Some(budget)) => Some(max_size.min(budget)),
+            (max_size, budget) => max_size.or(budget),
         }
     }
 
     /// Get number of caching blocks for the given cache key.
     pub fn block_count(&self, cache_key: &ObjectId) -> usize {
-        let data = self.data.read().unwrap();
-        data.get(cache_key).map_or(0, |cache| cache.len())
+        let state = self.state.lock().unwrap();
+        state.blocks.keys().filter(|(key, _)| key == cache_key).count()
     }
 }
 
@@ -42,8 +104,9 @@ impl DataCache for InMemoryDataCache {
         if block_offset != block_idx * self.block_size {
             return Err(DataCacheError::InvalidBlockOffset);
         }
-        let data = self.data.read().unwrap();
-        let block_data = data.get(cache_key).and_then(|blocks| blocks.get(&block_idx)).cloned();
+        let mut state = self.state.lock().unwrap();
+        let block_data = state.blocks.get_refresh(&(cache_key.clone(), block_idx)).cloned();
+        metrics::counter!("in_memory_data_cache.block_hit").increment(block_data.is_some() as u64);
         Ok(block_data)
     }
 
@@ -57,24 +120,94 @@ impl DataCache for InMemoryDataCache {
         if block_offset != block_idx * self.block_size {
             return Err(DataCacheError::InvalidBlockOffset);
         }
-        let mut data = self.data.write().unwrap();
-        let blocks = data.entry(cache_key).or_default();
-        blocks.insert(block_idx, bytes);
+        let size = bytes.len() as u64;
+        // Check the limit before locking the cache, as the memory limiter may reclaim from it
+        let size_limit = self.size_limit();
+        let mut state = self.state.lock().unwrap();
+        if let Some(previous) = state.blocks.remove(&(cache_key.clone(), block_idx)) {
+            state.size = state.size.saturating_sub(previous.len() as u64);
+        }
+        if let Some(size_limit) = size_limit {
+            if size > size_limit {
+                trace!(
+                    ?cache_key,
+                    block_idx,
+                    size,
+                    size_limit,
+                    "block doesn't fit in the in-memory cache"
+                );
+                return Ok(());
+            }
+            while state.size + size > size_limit {
+                if state.evict_lru().is_none() {
+                    break;
+                }
+            }
+        }
+        state.blocks.insert((cache_key, block_idx), bytes);
+        state.size += size;
+        metrics::gauge!("in_memory_data_cache.total_bytes").set(state.size as f64);
         Ok(())
     }
 
     fn block_size(&self) -> u64 {
         self.block_size
     }
+
+    fn remove_object(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        let removed: Vec<_> = state
+            .blocks
+            .keys()
+            .filter(|(cache_key, _)| cache_key.key() == key)
+            .cloned()
+            .collect();
+        for block in removed {
+            if let Some(bytes) = state.blocks.remove(&block) {
+                state.size = state.size.saturating_sub(bytes.len() as u64);
+            }
+        }
+        metrics::gauge!("in_memory_data_cache.total_bytes").set(state.size as f64);
+    }
+}
+
+impl ReclaimableMemory for InMemoryDataCache {
+    fn mem_used(&self) -> u64 {
+        self.state.lock().unwrap().size
+    }
+
+    fn reclaim(&self, size: u64) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let mut reclaimed = 0;
+        while reclaimed < size {
+            match state.evict_lru() {
+                Some(evicted) => reclaimed += evicted,
+                None => break,
+            }
+        }
+        reclaimed
+    }
+
+    fn set_memory_budget(&self, budget: Weak<dyn MemoryBudget>) {
+        if self.budget.set(budget).is_err() {
+            warn!("in-memory cache already has a memory budget");
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::str::FromStr;
+    use std::sync::Arc;
+
     use bytes::Bytes;
+    use mountpoint_s3_client::mock_client::MockClient;
     use mountpoint_s3_client::types::ETag;
 
+    use crate::mem_limiter::{MemoryLimiter, MINIMUM_MEM_LIMIT};
+
     #[tokio::test]
     async fn test_put_get() {
         let data_1 = Bytes::from_static(b"Hello world");
@@ -152,4 +285,159 @@ mod tests {
             "cache entry returned should match original bytes after put"
         );
     }
+
+    fn block(block_size: u64, value: u8) -> ChecksummedBytes {
+        ChecksummedBytes::new(vec![value; block_size as usize].into())
+    }
+
+    #[tokio::test]
+    async fn test_eviction() {
+        let block_size = 1024;
+        let cache = InMemoryDataCache::new_with_limit(block_size, 3 * block_size);
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+
+        for block_idx in 0..3 {
+            cache
+                .put_block(
+                    cache_key.clone(),
+                    block_idx,
+                    block_idx * block_size,
+                    block(block_size, 0),
+                )
+                .await
+                .expect("cache is accessible");
+        }
+        assert_eq!(cache.block_count(&cache_key), 3);
+
+        // Make block 0 the most recently used, so block 1 is evicted next
+        let block_0 = cache.get_block(&cache_key, 0, 0).await.expect("cache is accessible");
+        assert!(block_0.is_some(), "block 0 should be cached");
+        cache
+            .put_block(cache_key.clone(), 3, 3 * block_size, block(block_size, 0))
+            .await
+            .expect("cache is accessible");
+        assert_eq!(cache.block_count(&cache_key), 3);
+        assert_eq!(cache.mem_used(), 3 * block_size);
+        for (block_idx, expected) in [(0, true), (1, false), (2, true), (3, true)] {
+            let block = cache
+                .get_block(&cache_key, block_idx, block_idx * block_size)
+                .await
+                .expect("cache is accessible");
+            assert_eq!(block.is_some(), expected, "unexpected state for block {block_idx}");
+        }
+
+        // Blocks larger than the cache aren't cached at all
+        let large_block = ChecksummedBytes::new(vec![0u8; 4 * block_size as usize].into());
+        cache
+            .put_block(cache_key.clone(), 4, 4 * block_size, large_block)
+            .await
+            .expect("cache is accessible");
+        assert_eq!(cache.block_count(&cache_key), 3);
+    }
+
+    #[tokio::test]
+    async fn test_reclaim_from_memory_limiter() {
+        let block_size = 1024 * 1024;
+        let cache = Arc::new(InMemoryDataCache::new_with_limit(block_size, 8 * block_size));
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        for block_idx in 0..8 {
+            cache
+                .put_block(
+                    cache_key.clone(),
+                    block_idx,
+                    block_idx * block_size,
+                    block(block_size, 0),
+                )
+                .await
+                .expect("cache is accessible");
+        }
+
+        let client = MockClient::new(Default::default());
+        let mem_limiter = MemoryLimiter::new(client, MINIMUM_MEM_LIMIT).with_reclaimable_memory(cache.clone());
+
+        // Cached blocks count as available, as they can be evicted
+        let available = mem_limiter.available_mem();
+        assert!(mem_limiter.try_reserve(available - 2 * block_size));
+        assert_eq!(cache.block_count(&cache_key), 2);
+
+        // Only the cached blocks can be reclaimed
+        assert!(!mem_limiter.try_reserve(3 * block_size));
+        assert!(mem_limiter.try_reserve(2 * block_size));
+        assert_eq!(cache.block_count(&cache_key), 0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_limiter_bounds_cache() {
+        let block_size = 1024 * 1024;
+        // The cache's own limit is larger than the memory limiter's
+        let cache = Arc::new(InMemoryDataCache::new_with_limit(block_size, 2 * MINIMUM_MEM_LIMIT));
+        let client = MockClient::new(Default::default());
+        let mem_limiter =
+            Arc::new(MemoryLimiter::new(client, MINIMUM_MEM_LIMIT).with_reclaimable_memory(cache.clone()));
+        mem_limiter.limit_reclaimable_memory();
+
+        // Blocks share their data, so filling the cache doesn't need this much memory
+        let data = block(block_size, 0);
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        let put_blocks = |range: std::ops::Range<u64>| {
+            let cache = cache.clone();
+            let mem_limiter = mem_limiter.clone();
+            let data = data.clone();
+            let cache_key = cache_key.clone();
+            async move {
+                for block_idx in range {
+                    cache
+                        .put_block(cache_key.clone(), block_idx, block_idx * block_size, data.clone())
+                        .await
+                        .expect("cache is accessible");
+                    assert!(mem_limiter.mem_used() <= MINIMUM_MEM_LIMIT);
+                }
+            }
+        };
+
+        put_blocks(0..MINIMUM_MEM_LIMIT / block_size).await;
+        assert_eq!(mem_limiter.mem_used(), MINIMUM_MEM_LIMIT);
+        let cached = cache.block_count(&cache_key) as u64;
+        assert!(cached > 0);
+
+        // Reservations evict cached blocks, which aren't cached again while the memory is reserved
+        let reserved = 64 * block_size;
+        assert!(mem_limiter.try_reserve(reserved));
+        assert!(mem_limiter.mem_used() <= MINIMUM_MEM_LIMIT);
+        put_blocks(MINIMUM_MEM_LIMIT / block_size..2 * MINIMUM_MEM_LIMIT / block_size).await;
+        assert_eq!(cache.block_count(&cache_key) as u64, cached - reserved / block_size);
+
+        // Once released, the cache can use the memory again
+        mem_limiter.release(reserved);
+        put_blocks(2 * MINIMUM_MEM_LIMIT / block_size..3 * MINIMUM_MEM_LIMIT / block_size).await;
+        assert_eq!(cache.block_count(&cache_key) as u64, cached);
+    }
+
+    #[tokio::test]
+    async fn test_remove_object() {
+        let block_size = 1024;
+        let cache = InMemoryDataCache::new(block_size);
+        let old_version = ObjectId::new("a".into(), ETag::from_str("old").unwrap());
+        let new_version = ObjectId::new("a".into(), ETag::from_str("new").unwrap());
+        let other_key = ObjectId::new("b".into(), ETag::for_tests());
+        for cache_key in [&old_version, &new_version, &other_key] {
+            for block_idx in 0..2 {
+                cache
+                    .put_block(
+                        cache_key.clone(),
+                        block_idx,
+                        block_idx * block_size,
+                        block(block_size, 0),
+                    )
+                    .await
+                    .expect("cache is accessible");
+            }
+        }
+
+        cache.remove_object("a");
+        assert_eq!(cache.block_count(&old_version), 0);
+        assert_eq!(cache.block_count(&new_version), 0);
+        assert_eq!(cache.block_count(&other_key), 2);
+        assert_eq!(cache.state.lock().unwrap().size, 2 * block_size);
+    }
 }
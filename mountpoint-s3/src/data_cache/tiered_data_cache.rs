@@ -0,0 +1,293 @@
+//! Module for a data cache composed of multiple tiers of other caches.
+
+use async_trait::async_trait;
+use tracing::{trace, warn};
+
+use super::{BlockIndex, ChecksummedBytes, DataCache, DataCacheError, DataCacheResult};
+use crate::object::ObjectId;
+
+/// A [DataCache] that combines several caches, ordered from fastest to slowest.
+///
+/// Reads check each tier in order and return the first hit. A block found in a slower tier is
+/// promoted to all the faster tiers, so it will be served by them next time. New blocks are written
+/// to each tier configured with [CacheTierWrites::Enabled].
+///
+/// All tiers must have the same block size.
+pub struct TieredDataCache {
+    tiers: Vec<CacheTier>,
+    block_size: u64,
+}
+
+/// Whether a tier of a [TieredDataCache] accepts new blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheTierWrites {
+    /// Store new blocks in this tier.
+    Enabled,
+    /// Only store blocks promoted from slower tiers. A tier with no slower tiers will be read-only.
+    Disabled,
+}
+
+struct CacheTier {
+    /// Name of the tier, used to label metrics
+    name: &'static str,
+    cache: Box<dyn DataCache + Send + Sync>,
+    writes: CacheTierWrites,
+}
+
+impl std::fmt::Debug for TieredDataCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tiers: Vec<_> = self.tiers.iter().map(|tier| (tier.name, tier.writes)).collect();
+        f.debug_struct("TieredDataCache")
+            .field("tiers", &tiers)
+            .field("block_size", &self.block_size)
+            .finish()
+    }
+}
+
+impl TieredDataCache {
+    /// Create a new [TieredDataCache] with no tiers, for blocks of the specified `block_size`.
+    /// Tiers should be added with [TieredDataCache::with_tier] from fastest to slowest.
+    pub fn new(block_size: u64) -> Self {
+        Self {
+            tiers: Vec::new(),
+            block_size,
+        }
+    }
+
+    /// Add a tier behind all the existing tiers. `name` is used to label the metrics for this tier.
+    ///
+    /// Panics if the block size of `cache` doesn't match this cache's block size.
+    #[must_use = "TieredDataCache follows a builder pattern"]
+    pub fn with_tier<Cache>(mut self, name: &'static str, cache: Cache, writes: CacheTierWrites) -> Self
+    where
+        Cache: DataCache + Send + Sync + 'static,
+    {
+        assert_eq!(
+            cache.block_size(),
+            self.block_size,
+            "block size of tier {name} must match the tiered cache"
+        );
+        self.tiers.push(CacheTier {
+            name,
+            cache: Box::new(cache),
+            writes,
+        });
+        self
+    }
+
+    /// Number of tiers in this cache.
+    pub fn tier_count(&self) -> usize {
+        self.tiers.len()
+    }
+}
+
+#[async_trait]
+impl DataCache for TieredDataCache {
+    async fn get_block(
+        &self,
+        cache_key: &ObjectId,
+        block_idx: BlockIndex,
+        block_offset: u64,
+    ) -> DataCacheResult<Option<ChecksummedBytes>> {
+        if block_offset != block_idx * self.block_size {
+            return Err(DataCacheError::InvalidBlockOffset);
+        }
+
+        for (tier_idx, tier) in self.tiers.iter().enumerate() {
+            let block = match tier.cache.get_block(cache_key, block_idx, block_offset).await {
+                Ok(Some(block)) => block,
+                Ok(None) => {
+                    metrics::counter!("tiered_data_cache.block_hit", "tier" => tier.name).increment(0);
+                    continue;
+                }
+                Err(err) => {
+                    // Treat errors as a miss in this tier, the next tier might still have the block
+                    warn!(
+                        tier = tier.name,
+                        ?cache_key,
+                        block_idx,
+                        ?err,
+                        "error reading from cache tier"
+                    );
+                    metrics::counter!("tiered_data_cache.block_hit", "tier" => tier.name).increment(0);
+                    metrics::counter!("tiered_data_cache.block_err", "tier" => tier.name).increment(1);
+                    continue;
+                }
+            };
+            metrics::counter!("tiered_data_cache.block_hit", "tier" => tier.name).increment(1);
+
+            for faster_tier in &self.tiers[..tier_idx] {
+                trace!(
+                    from = tier.name,
+                    to = faster_tier.name,
+                    ?cache_key,
+                    block_idx,
+                    "promoting block"
+                );
+                let result = faster_tier
+                    .cache
+                    .put_block(cache_key.clone(), block_idx, block_offset, block.clone())
+                    .await;
+                match result {
+                    Ok(()) => {
+                        metrics::counter!("tiered_data_cache.promoted_blocks", "tier" => faster_tier.name).increment(1)
+                    }
+                    Err(err) => warn!(
+                        tier = faster_tier.name,
+                        ?cache_key,
+                        block_idx,
+                        ?err,
+                        "failed to promote block"
+                    ),
+                }
+            }
+            return Ok(Some(block));
+        }
+        Ok(None)
+    }
+
+    async fn put_block(
+        &self,
+        cache_key: ObjectId,
+        block_idx: BlockIndex,
+        block_offset: u64,
+        bytes: ChecksummedBytes,
+    ) -> DataCacheResult<()> {
+        if block_offset != block_idx * self.block_size {
+            return Err(DataCacheError::InvalidBlockOffset);
+        }
+
+        // Try to write to every tier even if one fails, but report the first failure
+        let mut result = Ok(());
+        for tier in self.tiers.iter().filter(|tier| tier.writes == CacheTierWrites::Enabled) {
+            if let Err(err) = tier
+                .cache
+                .put_block(cache_key.clone(), block_idx, block_offset, bytes.clone())
+                .await
+            {
+                warn!(
+                    tier = tier.name,
+                    ?cache_key,
+                    block_idx,
+                    ?err,
+                    "failed to write block to cache tier"
+                );
+                metrics::counter!("tiered_data_cache.write_err", "tier" => tier.name).increment(1);
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+
+    fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    fn remove_object(&self, key: &str) {
+        for tier in &self.tiers {
+            tier.cache.remove_object(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use mountpoint_s3_client::types::ETag;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::data_cache::InMemoryDataCache;
+
+    const BLOCK_SIZE: u64 = 1024;
+
+    fn block(value: u8) -> ChecksummedBytes {
+        ChecksummedBytes::new(Bytes::from(vec![value; BLOCK_SIZE as usize]))
+    }
+
+    #[tokio::test]
+    async fn test_get_promotes_to_faster_tiers() {
+        let memory = Arc::new(InMemoryDataCache::new(BLOCK_SIZE));
+        let disk = Arc::new(InMemoryDataCache::new(BLOCK_SIZE));
+        let remote = Arc::new(InMemoryDataCache::new(BLOCK_SIZE));
+        let cache = TieredDataCache::new(BLOCK_SIZE)
+            .with_tier("memory", memory.clone(), CacheTierWrites::Enabled)
+            .with_tier("disk", disk.clone(), CacheTierWrites::Enabled)
+            .with_tier("remote", remote.clone(), CacheTierWrites::Enabled);
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+
+        // Only the slowest tier has the block
+        remote
+            .put_block(cache_key.clone(), 1, BLOCK_SIZE, block(1))
+            .await
+            .unwrap();
+        let found = cache
+            .get_block(&cache_key, 1, BLOCK_SIZE)
+            .await
+            .expect("cache is accessible")
+            .expect("block should be found in the remote tier");
+        assert_eq!(found, block(1));
+        assert_eq!(memory.block_count(&cache_key), 1);
+        assert_eq!(disk.block_count(&cache_key), 1);
+
+        // A block in the middle tier is only promoted to the faster one
+        disk.put_block(cache_key.clone(), 2, 2 * BLOCK_SIZE, block(2))
+            .await
+            .unwrap();
+        let found = cache.get_block(&cache_key, 2, 2 * BLOCK_SIZE).await.unwrap();
+        assert_eq!(found, Some(block(2)));
+        assert_eq!(memory.block_count(&cache_key), 2);
+        assert_eq!(remote.block_count(&cache_key), 1);
+
+        let missing = cache.get_block(&cache_key, 3, 3 * BLOCK_SIZE).await.unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[test_case(CacheTierWrites::Enabled, CacheTierWrites::Enabled; "write to both")]
+    #[test_case(CacheTierWrites::Enabled, CacheTierWrites::Disabled; "write to first")]
+    #[test_case(CacheTierWrites::Disabled, CacheTierWrites::Enabled; "write to second")]
+    #[tokio::test]
+    async fn test_put_respects_tier_writes(first: CacheTierWrites, second: CacheTierWrites) {
+        let first_cache = Arc::new(InMemoryDataCache::new(BLOCK_SIZE));
+        let second_cache = Arc::new(InMemoryDataCache::new(BLOCK_SIZE));
+        let cache = TieredDataCache::new(BLOCK_SIZE)
+            .with_tier("first", first_cache.clone(), first)
+            .with_tier("second", second_cache.clone(), second);
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+
+        cache.put_block(cache_key.clone(), 0, 0, block(0)).await.unwrap();
+        let expected_count = |writes| (writes == CacheTierWrites::Enabled) as usize;
+        assert_eq!(first_cache.block_count(&cache_key), expected_count(first));
+        assert_eq!(second_cache.block_count(&cache_key), expected_count(second));
+
+        // Whichever tier has it, the block can be read back
+        let found = cache.get_block(&cache_key, 0, 0).await.unwrap();
+        assert_eq!(found, Some(block(0)));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_block_offset() {
+        let cache = TieredDataCache::new(BLOCK_SIZE).with_tier(
+            "memory",
+            InMemoryDataCache::new(BLOCK_SIZE),
+            CacheTierWrites::Enabled,
+        );
+        let cache_key = ObjectId::new("a".into(), ETag::for_tests());
+        let result = cache.put_block(cache_key.clone(), 1, 0, block(0)).await;
+        assert!(matches!(result, Err(DataCacheError::InvalidBlockOffset)));
+        let result = cache.get_block(&cache_key, 1, 0).await;
+        assert!(matches!(result, Err(DataCacheError::InvalidBlockOffset)));
+    }
+
+    #[test]
+    #[should_panic(expected = "block size of tier disk must match")]
+    fn test_block_size_mismatch() {
+        let _cache = TieredDataCache::new(BLOCK_SIZE)
+            .with_tier("memory", InMemoryDataCache::new(BLOCK_SIZE), CacheTierWrites::Enabled)
+            .with_tier("disk", InMemoryDataCache::new(2 * BLOCK_SIZE), CacheTierWrites::Enabled);
+    }
+}
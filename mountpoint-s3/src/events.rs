@@ -0,0 +1,629 @@
+//! Cache invalidation driven by S3 event notifications.
+//!
+//! When objects are created, overwritten, or deleted by other clients, Mountpoint only notices once
+//! its cached metadata expires. An [EventSource] provides the S3 event notifications of the bucket
+//! as they happen, so that the cached metadata and data of the affected keys can be invalidated
+//! right away, and long metadata TTLs can be used without serving stale content for long.
+//!
+//! Events use the [S3 event notification format], with one JSON message per line. They can be
+//! read from a file, which is followed as it grows, or received on a Unix socket, for example from
+//! a process that polls an SQS queue the bucket sends its notifications to.
+//!
+//! [S3 event notification format]: https://docs.aws.amazon.com/AmazonS3/latest/userguide/notification-content-structure.html
+
+use std::fmt::Debug;
+use std::fs::{File, Permissions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{debug, error, trace, warn};
+
+use crate::data_cache::DataCache;
+use crate::superblock::Superblock;
+use crate::sync::thread::{self, JoinHandle};
+use crate::sync::{mpsc, Arc};
+
+/// How long to wait for more events at the end of a [JsonLinesFile].
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A change to an object in a bucket, reported by an S3 event notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Event {
+    pub kind: S3EventKind,
+    pub bucket: String,
+    pub key: String,
+}
+
+/// The kinds of S3 events that affect the file system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum S3EventKind {
+    /// An object was created or overwritten (`s3:ObjectCreated:*`)
+    Created,
+    /// An object was deleted, or a delete marker was created for it (`s3:ObjectRemoved:*`)
+    Removed,
+}
+
+impl S3EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Removed => "removed",
+        }
+    }
+}
+
+/// Errors when reading S3 events.
+#[derive(Debug, Error)]
+pub enum EventSourceError {
+    #[error("invalid event notification")]
+    InvalidNotification(#[from] serde_json::Error),
+    #[error("failed to read events")]
+    IoError(#[from] io::Error),
+}
+
+/// A source of S3 events.
+pub trait EventSource: Debug + Send {
+    /// Wait for the next notification and return the events it contains, or `None` once the
+    /// source has no more events to provide.
+    ///
+    /// An [EventSourceError::InvalidNotification] error only affects the current notification, and
+    /// the following ones can still be read.
+    fn next_events(&mut self) -> Result<Option<Vec<S3Event>>, EventSourceError>;
+}
+
+/// An S3 event notification message. Messages without records, like the `s3:TestEvent` sent when
+/// notifications are configured, contain no events.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Notification {
+    /// Records are parsed one at a time, so that an invalid record doesn't hide the others
+    #[serde(default)]
+    records: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NotificationRecord {
+    event_name: String,
+    s3: NotificationEntity,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationEntity {
+    bucket: NotificationBucket,
+    object: NotificationObject,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationBucket {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationObject {
+    key: String,
+}
+
+/// Parse the events in an S3 event notification message. Events other than object creation and
+/// removal are ignored, and so are invalid records, which are logged and counted.
+pub fn parse_notification(message: &str) -> Result<Vec<S3Event>, EventSourceError> {
+    let notification: Notification = serde_json::from_str(message)?;
+    let mut events = Vec::with_capacity(notification.records.len());
+    for record in notification.records {
+        match parse_record(record) {
+            Ok(Some(event)) => events.push(event),
+            Ok(None) => {}
+            Err(err) => {
+                warn!(%err, "ignoring invalid event notification record");
+                metrics::counter!("events.invalid").increment(1);
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Parse a single record of an S3 event notification message, returning `None` for events other
+/// than object creation and removal.
+fn parse_record(record: serde_json::Value) -> Result<Option<S3Event>, String> {
+    let record: NotificationRecord = serde_json::from_value(record).map_err(|err| err.to_string())?;
+    // Event names are prefixed with `s3:` in notification configurations, but not in messages
+    let event_name = record.event_name.trim_start_matches("s3:");
+    let kind = if event_name.starts_with("ObjectCreated:") {
+        S3EventKind::Created
+    } else if event_name.starts_with("ObjectRemoved:") {
+        S3EventKind::Removed
+    } else {
+        trace!(event_name, "ignoring event");
+        return Ok(None);
+    };
+    // Keys are URL-encoded in event notifications
+    let key = percent_encoding::percent_decode_str(&record.s3.object.key.replace('+', " "))
+        .decode_utf8()
+        .map_err(|_| format!("invalid key {:?}", record.s3.object.key))?
+        .into_owned();
+    Ok(Some(S3Event {
+        kind,
+        bucket: record.s3.bucket.name,
+        key,
+    }))
+}
+
+/// Events read from a file with one notification message per line. Like `tail -F`, the file is
+/// followed as other processes append to it, from the end it had when it was opened. If the file
+/// is truncated, it is read again from the start, and if it is replaced by a new file (for example
+/// when it is rotated), the new file is read from the start.
+#[derive(Debug)]
+pub struct JsonLinesFile {
+    path: PathBuf,
+    reader: BufReader<File>,
+    line: String,
+}
+
+impl JsonLinesFile {
+    /// Open the file at `path`. Only the lines appended to it from now on are read, rather than
+    /// every event the file has collected, at each mount.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut file = File::open(&path)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            path,
+            reader: BufReader::new(file),
+            line: String::new(),
+        })
+    }
+
+    /// Called at the end of the file: start over if the file was truncated or replaced. Any
+    /// partial line read so far is discarded then, since it will never be completed.
+    fn check_rotated(&mut self) -> io::Result<()> {
+        let file = self.reader.get_ref();
+        let metadata = file.metadata()?;
+        let replaced = match std::fs::metadata(&self.path) {
+            Ok(current) => current.dev() != metadata.dev() || current.ino() != metadata.ino(),
+            // Until a new file is created, keep following the old one
+            Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+            Err(err) => return Err(err),
+        };
+        if replaced {
+            debug!(path=?self.path, "event file was replaced, reading the new file");
+            self.reader = BufReader::new(File::open(&self.path)?);
+            self.line.clear();
+        } else if metadata.len() < self.reader.stream_position()? {
+            debug!(path=?self.path, "event file was truncated, reading it from the start");
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.line.clear();
+        }
+        Ok(())
+    }
+}
+
+impl EventSource for JsonLinesFile {
+    fn next_events(&mut self) -> Result<Option<Vec<S3Event>>, EventSourceError> {
+        loop {
+            // Partial lines are kept until the writer completes them
+            let read = self.reader.read_line(&mut self.line)?;
+            if read == 0 || !self.line.ends_with('\n') {
+                self.check_rotated()?;
+                thread::sleep(FOLLOW_INTERVAL);
+                continue;
+            }
+            let line = std::mem::take(&mut self.line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            return parse_notification(&line).map(Some);
+        }
+    }
+}
+
+/// Events received on a Unix socket. Any number of processes can connect to the socket and write
+/// notification messages to it, one per line.
+#[derive(Debug)]
+pub struct UnixSocket {
+    path: PathBuf,
+    receiver: mpsc::Receiver<Result<Vec<S3Event>, EventSourceError>>,
+}
+
+impl UnixSocket {
+    /// Create a socket at `path` and start accepting connections to it. Only the user running
+    /// Mountpoint can connect to the socket.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let listener = UnixListener::bind(&path)?;
+        if let Err(err) = std::fs::set_permissions(&path, Permissions::from_mode(0o600)) {
+            let _ = std::fs::remove_file(&path);
+            return Err(err);
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new().name("event-socket".to_owned()).spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!(?err, "failed to accept connection to event socket");
+                        continue;
+                    }
+                };
+                let sender = sender.clone();
+                let spawned = thread::Builder::new()
+                    .name("event-connection".to_owned())
+                    .spawn(move || {
+                        for line in BufReader::new(stream).lines() {
+                            let events = match line {
+                                Ok(line) if line.trim().is_empty() => continue,
+                                Ok(line) => parse_notification(&line),
+                                Err(err) => {
+                                    debug!(?err, "failed to read from event socket connection");
+                                    break;
+                                }
+                            };
+                            if sender.send(events).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                if let Err(err) = spawned {
+                    warn!(?err, "failed to spawn thread for event socket connection");
+                }
+            }
+        })?;
+        Ok(Self { path, receiver })
+    }
+}
+
+impl EventSource for UnixSocket {
+    fn next_events(&mut self) -> Result<Option<Vec<S3Event>>, EventSourceError> {
+        match self.receiver.recv() {
+            Ok(events) => events.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Where to read S3 events from, as given on the command line: `file:PATH` or `unix:PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventSourceConfig {
+    /// A JSON-lines file, see [JsonLinesFile]
+    File(PathBuf),
+    /// A Unix socket to create, see [UnixSocket]
+    UnixSocket(PathBuf),
+}
+
+impl EventSourceConfig {
+    /// Open the configured event source.
+    pub fn open(&self) -> io::Result<Box<dyn EventSource>> {
+        Ok(match self {
+            Self::File(path) => Box::new(JsonLinesFile::open(path)?),
+            Self::UnixSocket(path) => Box::new(UnixSocket::bind(path)?),
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid event source {0:?}, expected file:PATH or unix:PATH")]
+pub struct EventSourceConfigError(String);
+
+impl FromStr for EventSourceConfig {
+    type Err = EventSourceConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("file", path)) if !path.is_empty() => Ok(Self::File(path.into())),
+            Some(("unix", path)) if !path.is_empty() => Ok(Self::UnixSocket(path.into())),
+            _ => Err(EventSourceConfigError(s.to_owned())),
+        }
+    }
+}
+
+/// Invalidates the caches of a file system for the S3 events read from an [EventSource].
+pub struct EventListener {
+    source: Box<dyn EventSource>,
+    bucket: String,
+    data_cache: Option<Arc<dyn DataCache + Send + Sync>>,
+}
+
+impl Debug for EventListener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventListener")
+            .field("source", &self.source)
+            .field("bucket", &self.bucket)
+            .field("data_cache", &self.data_cache.is_some())
+            .finish()
+    }
+}
+
+impl EventListener {
+    /// Create a listener for the events of `bucket`. Events for other buckets are ignored.
+    pub fn new(source: Box<dyn EventSource>, bucket: &str) -> Self {
+        Self {
+            source,
+            bucket: bucket.to_owned(),
+            data_cache: None,
+        }
+    }
+
+    /// Also remove the blocks of changed objects from the given data cache.
+    pub fn with_data_cache(mut self, data_cache: Arc<dyn DataCache + Send + Sync>) -> Self {
+        self.data_cache = Some(data_cache);
+        self
+    }
+
+    /// Spawn a thread that invalidates the caches for each event, until the source has no more
+    /// events or fails.
+    pub(crate) fn spawn(mut self, superblock: Superblock) -> anyhow::Result<JoinHandle<()>> {
+        thread::Builder::new()
+            .name("event-listener".to_owned())
+            .spawn(move || loop {
+                match self.source.next_events() {
+                    Ok(Some(events)) => {
+                        for event in events {
+                            self.handle(&superblock, &event);
+                        }
+                    }
+                    Ok(None) => {
+                        debug!("event source closed, stopping event listener");
+                        break;
+                    }
+                    Err(err @ EventSourceError::IoError(_)) => {
+                        error!(?err, "failed to read events, stopping event listener");
+                        break;
+                    }
+                    Err(err) => {
+                        warn!(?err, "ignoring invalid event notification");
+                        metrics::counter!("events.invalid").increment(1);
+                    }
+                }
+            })
+            .context("failed to spawn event listener thread")
+    }
+
+    fn handle(&self, superblock: &Superblock, event: &S3Event) {
+        if event.bucket != self.bucket {
+            trace!(?event, "ignoring event for another bucket");
+            return;
+        }
+        debug!(?event, "invalidating caches for event");
+        superblock.invalidate_key(&event.key);
+        if let Some(data_cache) = &self.data_cache {
+            data_cache.remove_object(&event.key);
+        }
+        metrics::counter!("events.received", "kind" => event.kind.as_str()).increment(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    use mountpoint_s3_client::types::ETag;
+
+    use super::*;
+    use crate::data_cache::{CacheInspector, CacheLimit, ChecksummedBytes, DiskDataCache, DiskDataCacheConfig};
+    use crate::object::ObjectId;
+    use crate::prefix::Prefix;
+
+    fn notification(event_name: &str, bucket: &str, key: &str) -> String {
+        serde_json::json!({
+            "Records": [{
+                "eventVersion": "2.1",
+                "eventSource": "aws:s3",
+                "eventName": event_name,
+                "s3": {
+                    "bucket": { "name": bucket, "arn": format!("arn:aws:s3:::{bucket}") },
+                    "object": { "key": key, "size": 1024, "eTag": "d41d8cd98f00b204e9800998ecf8427e" },
+                },
+            }]
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_parse_notification() {
+        let events = parse_notification(&notification("ObjectCreated:Put", "bucket", "dir/a+file%3D1.txt")).unwrap();
+        assert_eq!(
+            events,
+            vec![S3Event {
+                kind: S3EventKind::Created,
+                bucket: "bucket".to_owned(),
+                key: "dir/a file=1.txt".to_owned(),
+            }]
+        );
+
+        let events = parse_notification(&notification("ObjectRemoved:DeleteMarkerCreated", "bucket", "key")).unwrap();
+        assert_eq!(events[0].kind, S3EventKind::Removed);
+
+        let events = parse_notification(&notification("ObjectRestore:Completed", "bucket", "key")).unwrap();
+        assert!(events.is_empty());
+
+        let test_event = r#"{"Service":"Amazon S3","Event":"s3:TestEvent","Bucket":"bucket"}"#;
+        assert!(parse_notification(test_event).unwrap().is_empty());
+
+        assert!(matches!(
+            parse_notification("not json"),
+            Err(EventSourceError::InvalidNotification(_))
+        ));
+
+        // Invalid records are skipped without affecting the others
+        let mut message: serde_json::Value =
+            serde_json::from_str(&notification("ObjectCreated:Put", "bucket", "%FF")).unwrap();
+        let valid: serde_json::Value =
+            serde_json::from_str(&notification("ObjectRemoved:Delete", "bucket", "b")).unwrap();
+        let records = message["Records"].as_array_mut().unwrap();
+        records.push(serde_json::json!({ "eventName": "ObjectCreated:Put" }));
+        records.push(valid["Records"][0].clone());
+        let events = parse_notification(&message.to_string()).unwrap();
+        assert_eq!(
+            events,
+            vec![S3Event {
+                kind: S3EventKind::Removed,
+                bucket: "bucket".to_owned(),
+                key: "b".to_owned(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_removed_event_frees_disk_blocks() {
+        let cache_directory = tempfile::tempdir().unwrap();
+        let block_size = 1024;
+        let data_cache = Arc::new(DiskDataCache::new(
+            cache_directory.path().to_owned(),
+            DiskDataCacheConfig {
+                block_size,
+                limit: CacheLimit::Unbounded,
+                compression: None,
+                encryption_key: None,
+                policy: Default::default(),
+            },
+        ));
+        for key in ["dir/a", "b"] {
+            let cache_key = ObjectId::new(key.to_owned(), ETag::for_tests());
+            for block_idx in 0..2 {
+                let data = ChecksummedBytes::new(vec![0u8; block_size as usize].into());
+                data_cache
+                    .put_block(cache_key.clone(), block_idx, block_idx * block_size, data)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        File::create(&path).unwrap();
+        let source = JsonLinesFile::open(&path).unwrap();
+        let listener = EventListener::new(Box::new(source), "bucket").with_data_cache(data_cache);
+        let superblock = Superblock::new("bucket", &Prefix::new("").unwrap(), Default::default());
+        for event in parse_notification(&notification("ObjectRemoved:Delete", "bucket", "dir/a")).unwrap() {
+            listener.handle(&superblock, &event);
+        }
+
+        let blocks = CacheInspector::new(cache_directory.path()).scan().unwrap();
+        assert_eq!(blocks.len(), 2, "only the blocks of the other object should be left");
+    }
+
+    #[test]
+    fn test_event_source_config() {
+        assert_eq!(
+            "file:/tmp/events.jsonl".parse::<EventSourceConfig>().unwrap(),
+            EventSourceConfig::File("/tmp/events.jsonl".into())
+        );
+        assert_eq!(
+            "unix:/tmp/events.sock".parse::<EventSourceConfig>().unwrap(),
+            EventSourceConfig::UnixSocket("/tmp/events.sock".into())
+        );
+        for invalid in ["/tmp/events.jsonl", "file:", "sqs:queue"] {
+            invalid.parse::<EventSourceConfig>().expect_err("should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_json_lines_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let mut file = File::create(&path).unwrap();
+        // Events from before the file is opened are skipped
+        writeln!(file, "{}", notification("ObjectCreated:Put", "bucket", "old")).unwrap();
+
+        let mut source = JsonLinesFile::open(&path).unwrap();
+        writeln!(file, "{}", notification("ObjectCreated:Put", "bucket", "a")).unwrap();
+        writeln!(file).unwrap();
+        writeln!(file, "{{").unwrap();
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].key, "a");
+        source.next_events().expect_err("second notification is invalid");
+
+        // Lines appended later are read, once complete
+        let line = notification("ObjectRemoved:Delete", "bucket", "b");
+        let (first, second) = line.split_at(10);
+        let second = second.to_owned();
+        write!(file, "{first}").unwrap();
+        file.flush().unwrap();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(2 * FOLLOW_INTERVAL);
+            writeln!(file, "{second}").unwrap();
+        });
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].kind, S3EventKind::Removed);
+        assert_eq!(events[0].key, "b");
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_json_lines_file_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let mut file = File::create(&path).unwrap();
+
+        let mut source = JsonLinesFile::open(&path).unwrap();
+        writeln!(file, "{}", notification("ObjectCreated:Put", "bucket", "dir/a")).unwrap();
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].key, "dir/a");
+
+        // After truncation, the file is read from the start again. Like `tail -F`, truncation is
+        // only noticed if the file is then shorter than what was read so far.
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "{}", notification("ObjectCreated:Put", "bucket", "b")).unwrap();
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].key, "b");
+    }
+
+    #[test]
+    fn test_json_lines_file_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let mut file = File::create(&path).unwrap();
+
+        let mut source = JsonLinesFile::open(&path).unwrap();
+        writeln!(file, "{}", notification("ObjectCreated:Put", "bucket", "a")).unwrap();
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].key, "a");
+
+        // Lines written to the old file before it's replaced are still read, then the new file
+        std::fs::rename(&path, dir.path().join("events.jsonl.1")).unwrap();
+        writeln!(file, "{}", notification("ObjectCreated:Put", "bucket", "b")).unwrap();
+        let mut new_file = File::create(&path).unwrap();
+        writeln!(new_file, "{}", notification("ObjectCreated:Put", "bucket", "c")).unwrap();
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].key, "b");
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].key, "c");
+    }
+
+    #[test]
+    fn test_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.sock");
+        let mut source = UnixSocket::bind(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, "{}", notification("ObjectCreated:Copy", "bucket", "a")).unwrap();
+        writeln!(stream, "invalid").unwrap();
+        writeln!(stream, "{}", notification("ObjectRemoved:Delete", "bucket", "b")).unwrap();
+        drop(stream);
+
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].key, "a");
+        source.next_events().expect_err("second notification is invalid");
+        let events = source.next_events().unwrap().unwrap();
+        assert_eq!(events[0].key, "b");
+
+        drop(source);
+        assert!(!path.exists(), "socket should be removed");
+    }
+}
@@ -1,6 +1,7 @@
 //! FUSE file system types and operations, not tied to the _fuser_ library bindings.
 
 use bytes::Bytes;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::time::{Duration, UNIX_EPOCH};
@@ -16,15 +17,17 @@ use crate::logging;
 use crate::mem_limiter::MemoryLimiter;
 use crate::prefetch::{Prefetch, PrefetchResult};
 use crate::prefix::Prefix;
-use crate::superblock::{InodeError, InodeKind, LookedUp, ReaddirHandle, Superblock, SuperblockConfig};
+use crate::superblock::{
+    escape_name, unescape_name, Inode, InodeError, InodeKind, LookedUp, ReaddirHandle, Superblock, SuperblockConfig,
+};
 use crate::sync::atomic::{AtomicU64, Ordering};
 use crate::sync::{Arc, AsyncMutex, AsyncRwLock};
 use crate::upload::Uploader;
 
-pub use crate::superblock::InodeNo;
+pub use crate::superblock::{InodeNo, Invalidation, PersistentMetadataCache, PersistentMetadataCacheConfig};
 
 mod config;
-pub use config::{CacheConfig, S3FilesystemConfig};
+pub use config::{CacheConfig, PrefixTtl, S3FilesystemConfig};
 
 #[macro_use]
 mod error;
@@ -124,15 +127,29 @@ where
         let superblock_config = SuperblockConfig {
             cache_config: config.cache_config.clone(),
             s3_personality: config.s3_personality,
+            persistent_metadata_cache: config.persistent_metadata_cache.clone(),
+            manifest: config.manifest.clone(),
+            list_concurrency: config.list_concurrency,
+            invalidations: config.invalidations.clone(),
+            escape_names: config.escape_names,
+            shadowed_file_suffix: config.shadowed_file_suffix.clone(),
         };
         let superblock = Superblock::new(bucket, prefix, superblock_config);
-        let mem_limiter = Arc::new(MemoryLimiter::new(client.clone(), config.mem_limit));
-        let uploader = Uploader::new(
+        let mut mem_limiter = MemoryLimiter::new(client.clone(), config.mem_limit);
+        if let Some(cache_memory) = &config.cache_memory {
+            mem_limiter = mem_limiter.with_reclaimable_memory(cache_memory.clone());
+        }
+        let mem_limiter = Arc::new(mem_limiter);
+        mem_limiter.limit_reclaimable_memory();
+        let mut uploader = Uploader::new(
             client.clone(),
             config.storage_class.to_owned(),
             config.server_side_encryption.clone(),
             config.use_upload_checksums,
         );
+        if let Some(write_through_cache) = &config.write_through_cache {
+            uploader = uploader.with_write_through_cache(write_through_cache.clone(), mem_limiter.clone());
+        }
 
         Self {
             config,
@@ -149,6 +166,11 @@ where
         }
     }
 
+    /// A handle to the superblock of this file system, to invalidate its caches.
+    pub(crate) fn superblock(&self) -> Superblock {
+        self.superblock.clone()
+    }
+
     fn next_handle(&self) -> u64 {
         self.next_handle.fetch_add(1, Ordering::SeqCst)
     }
@@ -209,14 +231,54 @@ where
         }
     }
 
+    /// The name in the bucket of the entry the kernel refers to as `name`.
+    fn key_name<'a>(&self, name: &'a OsStr) -> Result<Cow<'a, OsStr>, InodeError> {
+        if !self.config.escape_names {
+            return Ok(Cow::Borrowed(name));
+        }
+        match name.to_str().and_then(unescape_name) {
+            Some(Cow::Borrowed(unescaped)) => Ok(Cow::Borrowed(OsStr::new(unescaped))),
+            Some(Cow::Owned(unescaped)) => Ok(Cow::Owned(unescaped.into())),
+            None => Err(InodeError::InvalidFileName(name.to_owned())),
+        }
+    }
+
+    /// Like [Self::key_name], but also resolves the names shortened because they were too long, for
+    /// operations on existing entries of the directory `parent`.
+    async fn existing_key_name<'a>(&self, parent: InodeNo, name: &'a OsStr) -> Result<Cow<'a, OsStr>, InodeError> {
+        match self.key_name(name) {
+            Err(InodeError::InvalidFileName(_)) if self.config.escape_names => {
+                let name = name
+                    .to_str()
+                    .ok_or_else(|| InodeError::InvalidFileName(name.to_owned()))?;
+                let resolved = self
+                    .superblock
+                    .resolve_shortened_name(&self.client, parent, name)
+                    .await?;
+                Ok(Cow::Owned(resolved.into()))
+            }
+            result => result,
+        }
+    }
+
+    /// The name the kernel is given for `inode`.
+    fn entry_name(&self, inode: &Inode) -> OsString {
+        if self.config.escape_names {
+            escape_name(inode.name()).as_ref().into()
+        } else {
+            inode.name().into()
+        }
+    }
+
     pub async fn lookup(&self, parent: InodeNo, name: &OsStr) -> Result<Entry, Error> {
         trace!("fs:lookup with parent {:?} name {:?}", parent, name);
 
-        let lookup = self
-            .superblock
-            .lookup(&self.client, parent, name)
-            .await
-            .map_err(|err| match err {
+        let lookup = async {
+            let name = self.existing_key_name(parent, name).await?;
+            self.superblock.lookup(&self.client, parent, &name).await
+        }
+        .await
+        .map_err(|err| match err {
                 InodeError::FileDoesNotExist(_, _) => {
                     // Lookup returning ENOENT is common case, and we dont want to warn in case `FileDoesNotExist` within ENOENT
                     err!(libc::ENOENT, source: err, Level::DEBUG, metadata: ErrorMetadata{error_code: Some(MOUNTPOINT_ERROR_LOOKUP_NONEXISTENT.to_string()), ..Default::default()}, "file does not exist")
@@ -307,7 +369,7 @@ where
         }
 
         let inode = lookup.inode.clone();
-        let full_key = lookup.inode.full_key().to_owned();
+        let full_key = lookup.inode.full_key().into_owned();
         let remote_file = lookup.inode.is_remote()?;
 
         // Open with O_APPEND is ok for new files because it's same as creating a new one.
@@ -402,9 +464,10 @@ where
             ));
         }
 
+        let name = self.key_name(name)?;
         let lookup = self
             .superblock
-            .create(&self.client, parent, name, InodeKind::File)
+            .create(&self.client, parent, &name, InodeKind::File)
             .await?;
         let attr = self.make_attr(&lookup);
         Ok(Entry {
@@ -415,9 +478,10 @@ where
     }
 
     pub async fn mkdir(&self, parent: InodeNo, name: &OsStr, _mode: libc::mode_t, _umask: u32) -> Result<Entry, Error> {
+        let name = self.key_name(name)?;
         let lookup = self
             .superblock
-            .create(&self.client, parent, name, InodeKind::Directory)
+            .create(&self.client, parent, &name, InodeKind::Directory)
             .await?;
         let attr = self.make_attr(&lookup);
         Ok(Entry {
@@ -640,7 +704,7 @@ where
             let entry = DirectoryEntry {
                 ino: attr.ino,
                 offset: dir_handle.offset() + 1,
-                name: next.inode.name().into(),
+                name: self.entry_name(&next.inode),
                 attr,
                 generation: 0,
                 ttl: next.validity(),
@@ -768,7 +832,8 @@ where
     }
 
     pub async fn rmdir(&self, parent_ino: InodeNo, name: &OsStr) -> Result<(), Error> {
-        self.superblock.rmdir(&self.client, parent_ino, name).await?;
+        let name = self.existing_key_name(parent_ino, name).await?;
+        self.superblock.rmdir(&self.client, parent_ino, &name).await?;
         Ok(())
     }
 
@@ -787,7 +852,8 @@ where
                 "Deletes are disabled. Use '--allow-delete' mount option to enable it."
             ));
         }
-        Ok(self.superblock.unlink(&self.client, parent_ino, name).await?)
+        let name = self.existing_key_name(parent_ino, name).await?;
+        Ok(self.superblock.unlink(&self.client, parent_ino, &name).await?)
     }
 }
 
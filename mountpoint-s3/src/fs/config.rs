@@ -1,10 +1,18 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use nix::unistd::{getgid, getuid};
 
-use crate::mem_limiter::MINIMUM_MEM_LIMIT;
+use crate::manifest::Manifest;
+use crate::mem_limiter::{ReclaimableMemory, MINIMUM_MEM_LIMIT};
 use crate::s3::S3Personality;
+use crate::superblock::{Invalidation, PersistentMetadataCache};
+use crate::sync::async_channel;
+use crate::upload::WriteThroughCache;
 
+use super::time_to_live::TimeToLiveError;
 use super::{ServerSideEncryption, TimeToLive};
 
 #[derive(Debug)]
@@ -35,6 +43,24 @@ pub struct S3FilesystemConfig {
     pub use_upload_checksums: bool,
     /// Memory limit
     pub mem_limit: u64,
+    /// Memory held by an in-memory data cache, which counts towards the memory limit
+    pub cache_memory: Option<Arc<dyn ReclaimableMemory>>,
+    /// Data cache to insert the content of uploaded objects into
+    pub write_through_cache: Option<WriteThroughCache>,
+    /// On-disk cache of directory listings and lookups, kept across mounts
+    pub persistent_metadata_cache: Option<Arc<PersistentMetadataCache>>,
+    /// Manifest to serve lookups and directory listings from, instead of listing the bucket
+    pub manifest: Option<Arc<Manifest>>,
+    /// Number of ranges of keys listed concurrently when listing a directory
+    pub list_concurrency: usize,
+    /// Where to send the invalidations of entries and inodes that changed remotely
+    pub invalidations: Option<async_channel::Sender<Invalidation>>,
+    /// Show keys with components that are not valid file names under percent-encoded names,
+    /// rather than hiding them
+    pub escape_names: bool,
+    /// Show the objects shadowed by a directory of the same name under their name followed by this
+    /// suffix, rather than hiding them
+    pub shadowed_file_suffix: Option<String>,
 }
 
 impl Default for S3FilesystemConfig {
@@ -56,6 +82,14 @@ impl Default for S3FilesystemConfig {
             server_side_encryption: Default::default(),
             use_upload_checksums: true,
             mem_limit: MINIMUM_MEM_LIMIT,
+            cache_memory: None,
+            write_through_cache: None,
+            persistent_metadata_cache: None,
+            manifest: None,
+            list_concurrency: 1,
+            invalidations: None,
+            escape_names: false,
+            shadowed_file_suffix: None,
         }
     }
 }
@@ -74,8 +108,20 @@ pub struct CacheConfig {
     pub file_ttl: Duration,
     /// How long the kernel will cache metadata for directories
     pub dir_ttl: Duration,
+    /// How long to remember that a file does not exist. When unset, `file_ttl` is used, but only
+    /// when serving lookups from the cache.
+    pub negative_ttl: Option<Duration>,
+    /// How long to cache the listing of a directory. When unset, the shorter of `file_ttl` and
+    /// `dir_ttl` is used, but only when serving lookups from the cache.
+    pub dir_listing_ttl: Option<Duration>,
+    /// TTLs replacing all of the above for the keys under some prefixes. The longest matching
+    /// prefix applies.
+    pub prefix_ttls: Vec<PrefixTtl>,
     /// Maximum number of negative entries to cache.
     pub negative_cache_size: usize,
+    /// Maximum number of inodes the kernel holds no reference to, such as the entries of a
+    /// `readdir`, to keep for serving later lookups.
+    pub unreferenced_inode_cache_size: usize,
 }
 
 impl Default for CacheConfig {
@@ -99,11 +145,22 @@ impl Default for CacheConfig {
         // monitored to verify if this limit needs reviewing.
         let negative_cache_size = 100_000;
 
+        // Inodes that the kernel doesn't reference, like the entries of a listing, would otherwise
+        // only be freed with their directory, so walking a large tree kept all of them in memory.
+        // This value should be large enough that the entries of a listing are still cached when the
+        // kernel looks them up. The metric `metadata_cache.unreferenced_inodes.evicted` can be
+        // monitored to verify if this limit needs reviewing.
+        let unreferenced_inode_cache_size = 1_000_000;
+
         Self {
             serve_lookup_from_cache: false,
             file_ttl,
             dir_ttl,
+            negative_ttl: None,
+            dir_listing_ttl: None,
+            prefix_ttls: Vec::new(),
             negative_cache_size,
+            unreferenced_inode_cache_size,
         }
     }
 }
@@ -127,4 +184,126 @@ impl CacheConfig {
             },
         }
     }
+
+    /// The configuration for the metadata of `key`, taking into account the [PrefixTtl]s.
+    pub fn for_key(&self, key: &str) -> Cow<'_, Self> {
+        let prefix_ttl = self
+            .prefix_ttls
+            .iter()
+            .filter(|prefix_ttl| key.starts_with(&prefix_ttl.prefix))
+            .max_by_key(|prefix_ttl| prefix_ttl.prefix.len());
+        match prefix_ttl {
+            Some(prefix_ttl) => Cow::Owned(Self {
+                negative_cache_size: self.negative_cache_size,
+                unreferenced_inode_cache_size: self.unreferenced_inode_cache_size,
+                ..Self::new(prefix_ttl.ttl)
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// How long to remember that a file does not exist, or [None] if missing files are not cached.
+    pub fn negative_ttl(&self) -> Option<Duration> {
+        match self.negative_ttl {
+            Some(ttl) => Some(ttl).filter(|ttl| !ttl.is_zero()),
+            None => self.serve_lookup_from_cache.then_some(self.file_ttl),
+        }
+    }
+
+    /// How long to cache the listing of a directory, or [None] if listings are not cached.
+    pub fn dir_listing_ttl(&self) -> Option<Duration> {
+        match self.dir_listing_ttl {
+            Some(ttl) => Some(ttl).filter(|ttl| !ttl.is_zero()),
+            // A listing contains both files and directories, so it expires with the first of them
+            None => self.serve_lookup_from_cache.then_some(self.file_ttl.min(self.dir_ttl)),
+        }
+    }
+
+    /// The longest time any metadata may be cached for, under any prefix.
+    pub fn max_ttl(&self) -> Duration {
+        let own_ttl = [
+            self.file_ttl,
+            self.dir_ttl,
+            self.negative_ttl().unwrap_or_default(),
+            self.dir_listing_ttl().unwrap_or_default(),
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or_default();
+        self.prefix_ttls
+            .iter()
+            .map(|prefix_ttl| Self::new(prefix_ttl.ttl).max_ttl())
+            .fold(own_ttl, Duration::max)
+    }
+}
+
+/// A TTL for all the cached metadata of the keys under a prefix, as if `--metadata-ttl` was set
+/// to it for these keys. Parsed from `PREFIX=TTL`.
+#[derive(Debug, Clone)]
+pub struct PrefixTtl {
+    pub prefix: String,
+    pub ttl: TimeToLive,
+}
+
+impl FromStr for PrefixTtl {
+    type Err = TimeToLiveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (prefix, ttl) = s.rsplit_once('=').ok_or(TimeToLiveError::MissingPrefix)?;
+        Ok(Self {
+            prefix: prefix.to_owned(),
+            ttl: ttl.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_ttls() {
+        let minimal = CacheConfig::new(TimeToLive::Minimal);
+        assert_eq!(minimal.negative_ttl(), None);
+        assert_eq!(minimal.dir_listing_ttl(), None);
+
+        let ttl = Duration::from_secs(60);
+        let cached = CacheConfig::new(TimeToLive::Duration(ttl));
+        assert_eq!(cached.negative_ttl(), Some(ttl));
+        assert_eq!(cached.dir_listing_ttl(), Some(ttl));
+
+        let separate = CacheConfig {
+            negative_ttl: Some(Duration::from_secs(600)),
+            dir_listing_ttl: Some(Duration::ZERO),
+            ..CacheConfig::new(TimeToLive::Duration(ttl))
+        };
+        assert_eq!(separate.negative_ttl(), Some(Duration::from_secs(600)));
+        assert_eq!(separate.dir_listing_ttl(), None);
+    }
+
+    #[test]
+    fn test_prefix_ttls() {
+        let cache_config = CacheConfig {
+            prefix_ttls: vec![
+                "data/=indefinite".parse().unwrap(),
+                "data/hot/=minimal".parse().unwrap(),
+                "logs=10".parse().unwrap(),
+            ],
+            ..CacheConfig::new(TimeToLive::Duration(Duration::from_secs(60)))
+        };
+        let file_ttl = |key| cache_config.for_key(key).file_ttl;
+        assert_eq!(file_ttl("other.txt"), Duration::from_secs(60));
+        assert_eq!(file_ttl("data/file.txt"), TimeToLive::INDEFINITE_DURATION);
+        assert_eq!(file_ttl("data/hot/file.txt"), CacheConfig::default().file_ttl);
+        assert_eq!(file_ttl("logs-2024/file.txt"), Duration::from_secs(10));
+        assert!(!cache_config.for_key("data/hot/file.txt").serve_lookup_from_cache);
+        assert_eq!(cache_config.max_ttl(), TimeToLive::INDEFINITE_DURATION);
+        assert_eq!(
+            CacheConfig::new(TimeToLive::Duration(Duration::from_secs(60))).max_ttl(),
+            Duration::from_secs(60)
+        );
+
+        "data/".parse::<PrefixTtl>().expect_err("TTL is required");
+        "data/=forever".parse::<PrefixTtl>().expect_err("TTL should be valid");
+    }
 }
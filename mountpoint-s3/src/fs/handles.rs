@@ -99,7 +99,7 @@ where
             .superblock
             .write(&fs.client, ino, fs.config.allow_overwrite, is_truncate)
             .await?;
-        let key = lookup.inode.full_key();
+        let key = &lookup.inode.full_key();
         let handle = match fs.uploader.put(&fs.bucket, key).await {
             Err(e) => {
                 return Err(err!(libc::EIO, source:e, "put failed to start"));
@@ -125,7 +125,7 @@ where
             ));
         }
         let handle = fs.superblock.read(&fs.client, lookup.inode.ino()).await?;
-        let full_key = lookup.inode.full_key().to_owned();
+        let full_key = lookup.inode.full_key().into_owned();
         let object_size = lookup.stat.size as u64;
         let etag = match &lookup.stat.etag {
             None => return Err(err!(libc::EBADF, "no E-Tag for inode {}", lookup.inode.ino())),
@@ -158,7 +158,7 @@ pub enum UploadState<Client: ObjectClient> {
     Failed(libc::c_int),
 }
 
-impl<Client: ObjectClient> UploadState<Client> {
+impl<Client: ObjectClient + Send + Sync + 'static> UploadState<Client> {
     pub async fn write(&mut self, offset: i64, data: &[u8], key: &str) -> Result<u32, Error> {
         let (upload, handle) = match self {
             Self::InProgress { request, handle, .. } => (request, handle),
@@ -20,6 +20,8 @@ pub enum TimeToLiveError {
         TimeToLive::MAXIMUM_TTL_YEARS
     )]
     TooLarge,
+    #[error("TTL override must be PREFIX=TTL")]
+    MissingPrefix,
 }
 
 impl TimeToLive {
@@ -11,6 +11,7 @@ use tracing::{field, instrument, Instrument};
 use crate::fs::{DirectoryEntry, DirectoryReplier, InodeNo, S3Filesystem, S3FilesystemConfig, ToErrno};
 use crate::prefetch::Prefetch;
 use crate::prefix::Prefix;
+use crate::superblock::Superblock;
 #[cfg(target_os = "macos")]
 use fuser::ReplyXTimes;
 use fuser::{
@@ -18,6 +19,7 @@ use fuser::{
     ReplyLock, ReplyLseek, ReplyOpen, ReplyWrite, ReplyXattr, Request, TimeOrNow,
 };
 
+pub mod notify;
 pub mod session;
 
 /// `tracing` doesn't allow dynamic levels but we want to dynamically choose the log level for
@@ -87,6 +89,11 @@ where
 
         Self { fs }
     }
+
+    /// A handle to the superblock of the file system, to invalidate its caches.
+    pub(crate) fn superblock(&self) -> Superblock {
+        self.fs.superblock()
+    }
 }
 
 impl<Client, Prefetcher> Filesystem for S3FuseFilesystem<Client, Prefetcher>
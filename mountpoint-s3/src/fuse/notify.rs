@@ -0,0 +1,47 @@
+//! Forwarding of invalidations discovered by the file system to the kernel.
+
+use std::ffi::OsStr;
+use std::io;
+
+use anyhow::Context;
+use fuser::Notifier;
+use tracing::{debug, trace};
+
+use crate::fs::Invalidation;
+use crate::sync::async_channel::Receiver;
+use crate::sync::thread::{self, JoinHandle};
+
+/// Spawn a thread that sends the invalidations received from the file system to the kernel, until
+/// the file system is dropped.
+///
+/// Notifications are sent from their own thread rather than from the FUSE worker that discovered
+/// the change, because the kernel can hold locks on the affected directory while it waits for the
+/// worker's reply, and would not process the notification until then.
+pub fn spawn_invalidation_thread(
+    notifier: Notifier,
+    invalidations: Receiver<Invalidation>,
+) -> anyhow::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name("fuse-notifier".to_owned())
+        .spawn(move || {
+            while let Ok(invalidation) = invalidations.recv_blocking() {
+                let (kind, result) = match &invalidation {
+                    Invalidation::Entry { parent, name } => ("entry", notifier.inval_entry(*parent, OsStr::new(name))),
+                    Invalidation::Inode { ino } => ("inode", notifier.inval_inode(*ino, 0, 0)),
+                };
+                match result {
+                    Ok(()) => {
+                        trace!(?invalidation, "invalidated kernel cache");
+                        metrics::counter!("fuse.invalidations", "kind" => kind).increment(1);
+                    }
+                    // The kernel does not know about the entry or inode, so it has nothing to invalidate
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                        trace!(?invalidation, "nothing to invalidate in kernel cache");
+                    }
+                    Err(err) => debug!(?invalidation, ?err, "failed to invalidate kernel cache"),
+                }
+            }
+            trace!("invalidation channel closed, stopping notifier thread");
+        })
+        .context("failed to spawn notifier thread")
+}
@@ -3,9 +3,11 @@ mod build_info;
 mod checksums;
 pub mod cli;
 pub mod data_cache;
+pub mod events;
 pub mod fs;
 pub mod fuse;
 pub mod logging;
+pub mod manifest;
 pub mod mem_limiter;
 pub mod metrics;
 pub mod object;
@@ -17,6 +19,7 @@ mod sync;
 mod upload;
 
 pub use fs::{S3Filesystem, S3FilesystemConfig, ServerSideEncryption};
+pub use upload::WriteThroughCache;
 
 /// Enable tracing and CRT logging when running unit tests.
 #[cfg(test)]
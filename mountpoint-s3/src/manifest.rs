@@ -0,0 +1,747 @@
+//! Namespace of a bucket loaded from a local manifest, to mount it without listing it.
+//!
+//! A [Manifest] lists the objects of a bucket, with their size and ETag. When the file system is
+//! configured with a manifest, lookups and directory listings are answered from it instead of S3,
+//! and the ETags are used to check that objects read from S3 have not changed since the manifest
+//! was created. Three formats are supported:
+//!
+//! * An S3 Inventory `manifest.json` file, with the CSV or Parquet data files it references
+//!   downloaded to the same directory. The inventory must include the `Size` and `ETag` fields.
+//! * A JSON array of objects, each with `key`, `size` and `etag` fields, and optionally
+//!   `last_modified` (RFC 3339) and `storage_class` fields.
+//! * A CSV file with `key,size,etag` columns, and optionally a `last_modified` column (RFC 3339).
+//!
+//! Files ending in `.gz` are decompressed when loaded. Files are read as a stream, so that only the
+//! objects they list are kept in memory. Parquet data files are read one row group at a time, and
+//! only the columns used by the manifest are decoded. S3 Inventory reports in the ORC format are
+//! not supported.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use flate2::read::MultiGzDecoder;
+use mountpoint_s3_client::types::ObjectInfo;
+use parquet::errors::ParquetError;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use parquet::schema::types::Type;
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// The objects of a bucket, loaded from a local manifest. See the [module documentation](self).
+#[derive(Debug, Default)]
+pub struct Manifest {
+    objects: BTreeMap<String, ManifestObject>,
+}
+
+#[derive(Debug, Clone)]
+struct ManifestObject {
+    size: u64,
+    etag: String,
+    last_modified: OffsetDateTime,
+    storage_class: Option<String>,
+}
+
+/// An entry found in a [Manifest], relative to a directory.
+#[derive(Debug, Clone)]
+pub(crate) enum ManifestEntry {
+    Directory { name: String },
+    Object { name: String, object_info: ObjectInfo },
+}
+
+/// Errors when loading a [Manifest].
+#[derive(Debug, Error)]
+pub enum ManifestError {
+    #[error("failed to read {0}")]
+    IoError(PathBuf, #[source] std::io::Error),
+    #[error("invalid JSON manifest")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("invalid entry at line {line} of {path}: {reason}")]
+    InvalidLine { path: PathBuf, line: usize, reason: String },
+    #[error("invalid entry for key {key:?}: {reason}")]
+    InvalidEntry { key: String, reason: String },
+    #[error("invalid Parquet file {0}")]
+    InvalidParquet(PathBuf, #[source] ParquetError),
+    #[error("invalid entry at row {row} of {path}: {reason}")]
+    InvalidRow { path: PathBuf, row: usize, reason: String },
+    #[error("unsupported S3 Inventory format {0}, only CSV and Parquet are supported")]
+    UnsupportedFormat(String),
+    #[error("S3 Inventory does not include the {0} field")]
+    MissingField(&'static str),
+}
+
+/// Columns of a Parquet S3 Inventory data file used by the manifest. Other columns are not decoded.
+const PARQUET_INVENTORY_COLUMNS: [&str; 7] = [
+    "key",
+    "size",
+    "e_tag",
+    "last_modified_date",
+    "storage_class",
+    "is_latest",
+    "is_delete_marker",
+];
+
+/// An S3 Inventory `manifest.json` file. Only the fields needed to load the data files are read.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InventoryManifest {
+    file_format: String,
+    file_schema: String,
+    files: Vec<InventoryFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InventoryFile {
+    key: String,
+}
+
+/// An entry of a JSON manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonEntry {
+    key: String,
+    size: u64,
+    etag: String,
+    #[serde(default)]
+    last_modified: Option<String>,
+    #[serde(default)]
+    storage_class: Option<String>,
+}
+
+impl Manifest {
+    /// Load a manifest from a local file, in one of the formats described in the
+    /// [module documentation](self).
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ManifestError> {
+        let path = path.as_ref();
+        let mut reader = open_file(path)?;
+        // Objects without a modification time in the manifest are reported as modified when the
+        // manifest was
+        let default_last_modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or_else(|_| SystemTime::now())
+            .into();
+
+        let mut manifest = Self::default();
+        match skip_whitespace(&mut reader).map_err(|err| ManifestError::IoError(path.to_owned(), err))? {
+            Some(b'{') => {
+                let inventory = serde_json::from_reader(reader)?;
+                manifest.load_inventory(path, inventory)?;
+            }
+            Some(b'[') => manifest.load_json(reader, default_last_modified)?,
+            _ => manifest.load_csv(path, reader, default_last_modified)?,
+        }
+        Ok(manifest)
+    }
+
+    /// Number of objects in the manifest.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Whether the manifest contains no objects.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Look up the given key, which is a directory if any object key starts with `key/`.
+    pub(crate) fn lookup(&self, key: &str) -> Option<ManifestEntry> {
+        let name = key.rsplit('/').next().unwrap_or(key);
+        let dir_prefix = format!("{key}/");
+        let is_directory = self
+            .objects
+            .range::<str, _>((Bound::Included(dir_prefix.as_str()), Bound::Unbounded))
+            .next()
+            .is_some_and(|(object_key, _)| object_key.starts_with(&dir_prefix));
+        if is_directory {
+            return Some(ManifestEntry::Directory { name: name.to_owned() });
+        }
+        let object = self.objects.get(key)?;
+        Some(ManifestEntry::Object {
+            name: name.to_owned(),
+            object_info: object.to_object_info(key),
+        })
+    }
+
+    /// Look up the object with the given key, if it is shadowed by a directory of the same name.
+    pub(crate) fn lookup_shadowed_object(&self, key: &str) -> Option<ObjectInfo> {
+        let object = self.objects.get(key)?;
+        matches!(self.lookup(key), Some(ManifestEntry::Directory { .. })).then(|| object.to_object_info(key))
+    }
+
+    /// List the entries directly under `prefix`, like a ListObjectsV2 call with a `/` delimiter.
+    pub(crate) fn list(&self, prefix: &str) -> Vec<ManifestEntry> {
+        let mut entries = Vec::new();
+        let mut range = self
+            .objects
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded));
+        while let Some((key, object)) = range.next() {
+            let Some(name) = key.strip_prefix(prefix) else {
+                break;
+            };
+            match name.split_once('/') {
+                Some((dir_name, _)) => {
+                    entries.push(ManifestEntry::Directory {
+                        name: dir_name.to_owned(),
+                    });
+                    // Skip the other keys in this directory: '0' is the character following '/'
+                    let next_key = format!("{prefix}{dir_name}0");
+                    range = self
+                        .objects
+                        .range::<str, _>((Bound::Included(next_key.as_str()), Bound::Unbounded));
+                }
+                // Directory marker for the prefix itself
+                None if name.is_empty() => {}
+                None => entries.push(ManifestEntry::Object {
+                    name: name.to_owned(),
+                    object_info: object.to_object_info(key),
+                }),
+            }
+        }
+        entries
+    }
+
+    fn insert(&mut self, key: String, object: ManifestObject) {
+        self.objects.insert(key, object);
+    }
+
+    fn load_inventory(&mut self, path: &Path, inventory: InventoryManifest) -> Result<(), ManifestError> {
+        // The data files are expected next to the manifest, with the same names as in the destination bucket
+        let directory = path.parent().unwrap_or(Path::new("."));
+        let data_paths: Vec<_> = inventory
+            .files
+            .iter()
+            .map(|file| directory.join(file.key.rsplit('/').next().unwrap_or(&file.key)))
+            .collect();
+        if inventory.file_format.eq_ignore_ascii_case("CSV") {
+            self.load_csv_inventory(&inventory.file_schema, &data_paths)
+        } else if inventory.file_format.eq_ignore_ascii_case("Parquet") {
+            for data_path in &data_paths {
+                self.load_parquet_inventory(data_path)?;
+            }
+            Ok(())
+        } else {
+            Err(ManifestError::UnsupportedFormat(inventory.file_format))
+        }
+    }
+
+    fn load_csv_inventory(&mut self, file_schema: &str, data_paths: &[PathBuf]) -> Result<(), ManifestError> {
+        let schema: Vec<_> = file_schema.split(',').map(str::trim).collect();
+        let column = |name| schema.iter().position(|field| *field == name);
+        let key_column = column("Key").ok_or(ManifestError::MissingField("Key"))?;
+        let size_column = column("Size").ok_or(ManifestError::MissingField("Size"))?;
+        let etag_column = column("ETag").ok_or(ManifestError::MissingField("ETag"))?;
+        let last_modified_column = column("LastModifiedDate");
+        let storage_class_column = column("StorageClass");
+        let is_latest_column = column("IsLatest");
+        let is_delete_marker_column = column("IsDeleteMarker");
+
+        for data_path in data_paths {
+            for record in csv_records(data_path, open_file(data_path)?) {
+                let (line, fields) = record?;
+                let invalid_line = |reason: String| ManifestError::InvalidLine {
+                    path: data_path.to_owned(),
+                    line,
+                    reason,
+                };
+                let field = |column: usize| fields.get(column).unwrap_or_default();
+                let optional_field = |column: Option<usize>| column.map(field).filter(|value| !value.is_empty());
+
+                // Only keep the current version of objects in versioned buckets
+                if optional_field(is_latest_column) == Some("false")
+                    || optional_field(is_delete_marker_column) == Some("true")
+                {
+                    continue;
+                }
+                // Keys are URL-encoded in S3 Inventory reports
+                let key = percent_encoding::percent_decode_str(&field(key_column).replace('+', " "))
+                    .decode_utf8()
+                    .map_err(|err| invalid_line(format!("invalid key: {err}")))?
+                    .into_owned();
+                let object = ManifestObject::new(
+                    field(size_column),
+                    field(etag_column),
+                    optional_field(last_modified_column),
+                    optional_field(storage_class_column),
+                    None,
+                )
+                .map_err(invalid_line)?;
+                self.insert(key, object);
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a Parquet S3 Inventory data file. Unlike in CSV reports, keys are not URL-encoded.
+    fn load_parquet_inventory(&mut self, data_path: &Path) -> Result<(), ManifestError> {
+        let invalid_file = |err| ManifestError::InvalidParquet(data_path.to_owned(), err);
+        let file = File::open(data_path).map_err(|err| ManifestError::IoError(data_path.to_owned(), err))?;
+        let reader = SerializedFileReader::new(file).map_err(invalid_file)?;
+
+        let root_schema = reader.metadata().file_metadata().schema_descr().root_schema();
+        let fields = root_schema.get_fields();
+        for (column, field) in [("key", "Key"), ("size", "Size"), ("e_tag", "ETag")] {
+            if !fields.iter().any(|field| field.name() == column) {
+                return Err(ManifestError::MissingField(field));
+            }
+        }
+        let projection = fields
+            .iter()
+            .filter(|field| PARQUET_INVENTORY_COLUMNS.contains(&field.name()))
+            .cloned()
+            .collect();
+        let projection = Type::group_type_builder(root_schema.name())
+            .with_fields(projection)
+            .build()
+            .map_err(invalid_file)?;
+
+        for (index, row) in reader.get_row_iter(Some(projection)).map_err(invalid_file)?.enumerate() {
+            let row = row.map_err(invalid_file)?;
+            let invalid_row = |reason: String| ManifestError::InvalidRow {
+                path: data_path.to_owned(),
+                row: index + 1,
+                reason,
+            };
+            let (mut key, mut size, mut etag, mut last_modified, mut storage_class) = (None, None, "", None, None);
+            let (mut is_latest, mut is_delete_marker) = (true, false);
+            for (name, value) in row.get_column_iter() {
+                match (name.as_str(), value) {
+                    ("key", Field::Str(value)) => key = Some(value),
+                    ("size", Field::Long(value)) => size = Some(*value),
+                    ("e_tag", Field::Str(value)) => etag = value,
+                    ("last_modified_date", Field::TimestampMillis(millis)) => {
+                        last_modified = Some(*millis as i128 * 1_000_000)
+                    }
+                    ("last_modified_date", Field::TimestampMicros(micros)) => {
+                        last_modified = Some(*micros as i128 * 1_000)
+                    }
+                    ("storage_class", Field::Str(value)) => storage_class = Some(value.as_str()),
+                    ("is_latest", Field::Bool(value)) => is_latest = *value,
+                    ("is_delete_marker", Field::Bool(value)) => is_delete_marker = *value,
+                    (_, Field::Null) => {}
+                    (name, value) => return Err(invalid_row(format!("unexpected value {value} for {name}"))),
+                }
+            }
+
+            // Only keep the current version of objects in versioned buckets
+            if !is_latest || is_delete_marker {
+                continue;
+            }
+            let key = key.ok_or_else(|| invalid_row("missing key".to_owned()))?;
+            let size = size.map(|size| size.to_string()).unwrap_or_default();
+            let mut object = ManifestObject::new(&size, etag, None, storage_class, None).map_err(invalid_row)?;
+            if let Some(nanos) = last_modified {
+                object.last_modified = OffsetDateTime::from_unix_timestamp_nanos(nanos)
+                    .map_err(|err| invalid_row(format!("invalid last modified date: {err}")))?;
+            }
+            self.insert(key.to_owned(), object);
+        }
+        Ok(())
+    }
+
+    fn load_json(&mut self, reader: impl Read, default_last_modified: OffsetDateTime) -> Result<(), ManifestError> {
+        // Insert the entries as they are parsed, rather than collecting them first
+        let mut loader = JsonLoader {
+            manifest: self,
+            default_last_modified,
+            error: None,
+        };
+        let result = (&mut loader).deserialize(&mut serde_json::Deserializer::from_reader(reader));
+        match loader.error {
+            Some(err) => Err(err),
+            None => Ok(result?),
+        }
+    }
+
+    fn load_csv(
+        &mut self,
+        path: &Path,
+        reader: impl Read,
+        default_last_modified: OffsetDateTime,
+    ) -> Result<(), ManifestError> {
+        for (index, record) in csv_records(path, reader).enumerate() {
+            let (line, fields) = record?;
+            let invalid_line = |reason: String| ManifestError::InvalidLine {
+                path: path.to_owned(),
+                line,
+                reason,
+            };
+            let (Some(key), Some(size), Some(etag)) = (fields.get(0), fields.get(1), fields.get(2)) else {
+                return Err(invalid_line("expected key, size and etag columns".to_owned()));
+            };
+            // Skip an optional header
+            if index == 0 && key.eq_ignore_ascii_case("key") && size.eq_ignore_ascii_case("size") {
+                continue;
+            }
+            let last_modified = fields.get(3).filter(|value| !value.is_empty());
+            let object = ManifestObject::new(size, etag, last_modified, None, Some(default_last_modified))
+                .map_err(invalid_line)?;
+            self.insert(key.to_owned(), object);
+        }
+        Ok(())
+    }
+}
+
+/// Loads the entries of a JSON manifest into a [Manifest] while the array is parsed.
+struct JsonLoader<'a> {
+    manifest: &'a mut Manifest,
+    default_last_modified: OffsetDateTime,
+    /// An invalid entry, which stopped the parsing
+    error: Option<ManifestError>,
+}
+
+impl<'de> DeserializeSeed<'de> for &mut JsonLoader<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for &mut JsonLoader<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of manifest entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(entry) = seq.next_element::<JsonEntry>()? {
+            let object = ManifestObject::new(
+                &entry.size.to_string(),
+                &entry.etag,
+                entry.last_modified.as_deref(),
+                entry.storage_class.as_deref(),
+                Some(self.default_last_modified),
+            );
+            match object {
+                Ok(object) => self.manifest.insert(entry.key, object),
+                Err(reason) => {
+                    self.error = Some(ManifestError::InvalidEntry { key: entry.key, reason });
+                    return Err(serde::de::Error::custom("invalid manifest entry"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ManifestObject {
+    fn new(
+        size: &str,
+        etag: &str,
+        last_modified: Option<&str>,
+        storage_class: Option<&str>,
+        default_last_modified: Option<OffsetDateTime>,
+    ) -> Result<Self, String> {
+        let size = size.parse().map_err(|_| format!("invalid size {size:?}"))?;
+        if etag.is_empty() {
+            return Err("missing ETag".to_owned());
+        }
+        // ETags are quoted in S3 responses, but not in S3 Inventory reports
+        let etag = if etag.starts_with('"') {
+            etag.to_owned()
+        } else {
+            format!("\"{etag}\"")
+        };
+        let last_modified = match last_modified {
+            Some(value) => OffsetDateTime::parse(value, &Rfc3339)
+                .map_err(|err| format!("invalid last modified date {value:?}: {err}"))?,
+            None => default_last_modified.unwrap_or(OffsetDateTime::UNIX_EPOCH),
+        };
+        Ok(Self {
+            size,
+            etag,
+            last_modified,
+            storage_class: storage_class.map(str::to_owned),
+        })
+    }
+
+    fn to_object_info(&self, key: &str) -> ObjectInfo {
+        ObjectInfo {
+            key: key.to_owned(),
+            size: self.size,
+            last_modified: self.last_modified,
+            storage_class: self.storage_class.clone(),
+            restore_status: None,
+            etag: self.etag.clone(),
+        }
+    }
+}
+
+/// Open a manifest or data file, decompressing it if its name ends in `.gz`.
+fn open_file(path: &Path) -> Result<Box<dyn BufRead>, ManifestError> {
+    let file = File::open(path).map_err(|err| ManifestError::IoError(path.to_owned(), err))?;
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        // Files may be concatenations of gzip members
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(BufReader::new(file)))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Skip leading whitespace, and return the next byte without consuming it.
+fn skip_whitespace(reader: &mut impl BufRead) -> std::io::Result<Option<u8>> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+        match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+            Some(position) => {
+                let next = buffer[position];
+                reader.consume(position);
+                return Ok(Some(next));
+            }
+            None => {
+                let len = buffer.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// The records of a CSV file without a header, with their line numbers.
+fn csv_records<'a>(
+    path: &'a Path,
+    reader: impl Read + 'a,
+) -> impl Iterator<Item = Result<(usize, csv::StringRecord), ManifestError>> + 'a {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader)
+        .into_records()
+        .map(move |record| {
+            let line = |position: Option<&csv::Position>| position.map_or(0, |position| position.line() as usize);
+            match record {
+                Ok(record) => Ok((line(record.position()), record)),
+                Err(err) => {
+                    let line = line(err.position());
+                    let reason = err.to_string();
+                    match err.into_kind() {
+                        csv::ErrorKind::Io(err) => Err(ManifestError::IoError(path.to_owned(), err)),
+                        _ => Err(ManifestError::InvalidLine {
+                            path: path.to_owned(),
+                            line,
+                            reason,
+                        }),
+                    }
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    fn names(entries: &[ManifestEntry]) -> Vec<&str> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                ManifestEntry::Directory { name } => name.as_str(),
+                ManifestEntry::Object { name, .. } => name.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_csv_manifest() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "key,size,etag").unwrap();
+        writeln!(file, "a/b/c.txt,10,etag1").unwrap();
+        writeln!(file, "\"a/b/b,d.txt\",20,\"\"\"etag2\"\"\",2024-01-01T00:00:00Z").unwrap();
+        writeln!(file, "a-1,30,etag3").unwrap();
+        writeln!(file, "a/,0,etag4").unwrap();
+        writeln!(file, "a,40,etag5").unwrap();
+        let manifest = Manifest::from_file(file.path()).unwrap();
+        assert_eq!(manifest.len(), 5);
+
+        assert_eq!(names(&manifest.list("")), &["a", "a-1", "a"]);
+        assert_eq!(names(&manifest.list("a/")), &["b"]);
+        assert_eq!(names(&manifest.list("a/b/")), &["b,d.txt", "c.txt"]);
+
+        let Some(ManifestEntry::Object { object_info, .. }) = manifest.lookup("a/b/b,d.txt") else {
+            panic!("should be an object");
+        };
+        assert_eq!(object_info.size, 20);
+        assert_eq!(object_info.etag, "\"etag2\"");
+        assert_eq!(object_info.last_modified, time::macros::datetime!(2024-01-01 0:00 UTC));
+        let Some(ManifestEntry::Object { object_info, .. }) = manifest.lookup("a/b/c.txt") else {
+            panic!("should be an object");
+        };
+        assert_eq!(object_info.etag, "\"etag1\"");
+
+        // Directories shadow objects with the same name
+        assert!(matches!(manifest.lookup("a"), Some(ManifestEntry::Directory { .. })));
+        assert!(matches!(manifest.lookup("a/b"), Some(ManifestEntry::Directory { .. })));
+        assert!(manifest.lookup("a/c").is_none());
+    }
+
+    #[test]
+    fn test_json_manifest() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"[
+                {{ "key": "dir/file", "size": 5, "etag": "\"etag\"", "storage_class": "GLACIER" }},
+                {{ "key": "file", "size": 1, "etag": "etag", "last_modified": "2024-01-01T00:00:00Z" }}
+            ]"#
+        )
+        .unwrap();
+        let manifest = Manifest::from_file(file.path()).unwrap();
+        assert_eq!(names(&manifest.list("")), &["dir", "file"]);
+        let Some(ManifestEntry::Object { object_info, .. }) = manifest.lookup("dir/file") else {
+            panic!("should be an object");
+        };
+        assert_eq!(object_info.storage_class.as_deref(), Some("GLACIER"));
+    }
+
+    #[test]
+    fn test_inventory_manifest() {
+        let directory = tempfile::tempdir().unwrap();
+        let manifest_json = r#"{
+            "sourceBucket": "bucket",
+            "fileFormat": "CSV",
+            "fileSchema": "Bucket, Key, VersionId, IsLatest, IsDeleteMarker, Size, LastModifiedDate, ETag",
+            "files": [{ "key": "inventory/bucket/data/data-1.csv.gz", "size": 1, "MD5checksum": "x" }]
+        }"#;
+        std::fs::write(directory.path().join("manifest.json"), manifest_json).unwrap();
+
+        let csv = [
+            r#""bucket","dir/file+1.txt","v2","true","false","10","2024-01-01T00:00:00.000Z","etag1""#,
+            r#""bucket","dir/old.txt","v1","false","false","10","2024-01-01T00:00:00.000Z","etag2""#,
+            r#""bucket","dir/deleted.txt","v3","true","true","","","""#,
+            r#""bucket","dir/%C3%A9t%C3%A9.txt","v4","true","false","20","2024-01-01T00:00:00.000Z","etag3""#,
+        ];
+        let gzip = |lines: &[&str]| {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            writeln!(encoder, "{}", lines.join("\n")).unwrap();
+            encoder.finish().unwrap()
+        };
+        // Each member of a multi-member gzip file is decompressed
+        let mut gzipped = gzip(&csv[..2]);
+        gzipped.extend(gzip(&csv[2..]));
+        std::fs::write(directory.path().join("data-1.csv.gz"), &gzipped).unwrap();
+
+        let manifest = Manifest::from_file(directory.path().join("manifest.json")).unwrap();
+        assert_eq!(names(&manifest.list("dir/")), &["file 1.txt", "été.txt"]);
+
+        // Corrupt data fails the CRC check
+        let crc_offset = gzipped.len() - 8;
+        gzipped[crc_offset] ^= 0xff;
+        std::fs::write(directory.path().join("data-1.csv.gz"), &gzipped).unwrap();
+        let err = Manifest::from_file(directory.path().join("manifest.json")).expect_err("should fail");
+        assert!(matches!(err, ManifestError::IoError(..)), "{err:?}");
+
+        let orc = manifest_json.replace("\"CSV\"", "\"ORC\"");
+        std::fs::write(directory.path().join("manifest.json"), orc).unwrap();
+        let err = Manifest::from_file(directory.path().join("manifest.json")).expect_err("should fail");
+        assert!(matches!(err, ManifestError::UnsupportedFormat(_)));
+    }
+
+    #[test]
+    fn test_parquet_inventory_manifest() {
+        use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DataType, Int64Type};
+        use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+        use parquet::schema::parser::parse_message_type;
+
+        fn write_column<T: DataType>(
+            row_group: &mut SerializedRowGroupWriter<'_, File>,
+            values: &[T::T],
+            def_levels: Option<&[i16]>,
+        ) {
+            let mut column = row_group
+                .next_column()
+                .unwrap()
+                .expect("schema should have more columns");
+            column.typed::<T>().write_batch(values, def_levels, None).unwrap();
+            column.close().unwrap();
+        }
+
+        let directory = tempfile::tempdir().unwrap();
+        let manifest_json = r#"{
+            "sourceBucket": "bucket",
+            "fileFormat": "Parquet",
+            "fileSchema": "message s3.inventory { required binary bucket (STRING); required binary key (STRING); optional binary version_id (STRING); optional boolean is_latest; optional boolean is_delete_marker; optional int64 size; optional int64 last_modified_date (TIMESTAMP_MILLIS); optional binary e_tag (STRING); }",
+            "files": [{ "key": "inventory/bucket/data/data-1.parquet", "size": 1, "MD5checksum": "x" }]
+        }"#;
+        std::fs::write(directory.path().join("manifest.json"), manifest_json).unwrap();
+
+        let schema = parse_message_type(
+            "message s3.inventory {
+                required binary bucket (STRING);
+                required binary key (STRING);
+                optional binary version_id (STRING);
+                optional boolean is_latest;
+                optional boolean is_delete_marker;
+                optional int64 size;
+                optional int64 last_modified_date (TIMESTAMP_MILLIS);
+                optional binary e_tag (STRING);
+            }",
+        )
+        .unwrap();
+        let file = File::create(directory.path().join("data-1.parquet")).unwrap();
+        let mut writer = SerializedFileWriter::new(file, schema.into(), Default::default()).unwrap();
+        let mut row_group = writer.next_row_group().unwrap();
+        let strings = |values: &[&str]| values.iter().map(|value| ByteArray::from(*value)).collect::<Vec<_>>();
+        // The third row is a delete marker, without a size or ETag
+        let some_null = Some(&[1, 1, 0, 1][..]);
+        let all_set = Some(&[1, 1, 1, 1][..]);
+        write_column::<ByteArrayType>(&mut row_group, &strings(&["bucket"; 4]), None);
+        write_column::<ByteArrayType>(
+            &mut row_group,
+            &strings(&["dir/file+1.txt", "dir/old.txt", "dir/deleted.txt", "dir/été.txt"]),
+            None,
+        );
+        write_column::<ByteArrayType>(&mut row_group, &strings(&["v2", "v1", "v3", "v4"]), all_set);
+        write_column::<BoolType>(&mut row_group, &[true, false, true, true], all_set);
+        write_column::<BoolType>(&mut row_group, &[false, false, true, false], all_set);
+        write_column::<Int64Type>(&mut row_group, &[10, 10, 20], some_null);
+        write_column::<Int64Type>(&mut row_group, &[1_704_067_200_000; 3], some_null);
+        write_column::<ByteArrayType>(&mut row_group, &strings(&["etag1", "etag2", "etag3"]), some_null);
+        row_group.close().unwrap();
+        writer.close().unwrap();
+
+        let manifest = Manifest::from_file(directory.path().join("manifest.json")).unwrap();
+        assert_eq!(names(&manifest.list("dir/")), &["file+1.txt", "été.txt"]);
+        let Some(ManifestEntry::Object { object_info, .. }) = manifest.lookup("dir/été.txt") else {
+            panic!("should be an object");
+        };
+        assert_eq!(object_info.size, 20);
+        assert_eq!(object_info.etag, "\"etag3\"");
+        assert_eq!(object_info.last_modified, time::macros::datetime!(2024-01-01 0:00 UTC));
+
+        std::fs::write(directory.path().join("data-1.parquet"), "not parquet").unwrap();
+        let err = Manifest::from_file(directory.path().join("manifest.json")).expect_err("should fail");
+        assert!(matches!(err, ManifestError::InvalidParquet(..)), "{err:?}");
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "a,10,etag").unwrap();
+        writeln!(file, "b,ten,etag").unwrap();
+        let err = Manifest::from_file(file.path()).expect_err("should fail");
+        assert!(matches!(err, ManifestError::InvalidLine { line: 2, .. }), "{err:?}");
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"a,10,etag\n\xff,10,etag\n").unwrap();
+        let err = Manifest::from_file(file.path()).expect_err("should fail");
+        assert!(matches!(err, ManifestError::InvalidLine { line: 2, .. }), "{err:?}");
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, r#"[{{ "key": "a", "size": 1, "etag": "" }}]"#).unwrap();
+        let err = Manifest::from_file(file.path()).expect_err("should fail");
+        assert!(matches!(err, ManifestError::InvalidEntry { .. }), "{err:?}");
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Weak};
 use std::{sync::atomic::Ordering, time::Instant};
 
 use humansize::make_format;
@@ -7,6 +9,29 @@ use tracing::{debug, trace};
 
 pub const MINIMUM_MEM_LIMIT: u64 = 512 * 1024 * 1024;
 
+/// Memory held by a component, such as an in-memory data cache, that can give it up on request.
+///
+/// [MemoryLimiter] counts this memory against its limit, but reclaims it before rejecting a
+/// reservation.
+pub trait ReclaimableMemory: Debug + Send + Sync {
+    /// Number of bytes currently held.
+    fn mem_used(&self) -> u64;
+
+    /// Release at least `size` bytes if possible. Returns the number of bytes released.
+    fn reclaim(&self, size: u64) -> u64;
+
+    /// Only grow as far as `budget` allows. Until this is called, only the component's own limits
+    /// apply.
+    fn set_memory_budget(&self, budget: Weak<dyn MemoryBudget>);
+}
+
+/// How much memory a [ReclaimableMemory] component can hold without going over the memory limit.
+pub trait MemoryBudget: Send + Sync {
+    /// Number of bytes reclaimable memory can hold in total, given the memory reserved by everything
+    /// else.
+    fn reclaimable_mem_limit(&self) -> u64;
+}
+
 /// `MemoryLimiter` tracks memory used by Mountpoint and makes decisions if a new memory reservation request can be accepted.
 /// Currently, there are two metrics we take into account:
 /// 1) the memory reserved by prefetcher instances for the data requested or fetched from CRT client.
@@ -48,6 +73,8 @@ pub struct MemoryLimiter<Client: ObjectClient> {
     // memory usage on the write path today, so we will rely on the client's stats
     // for "other buffers" and adjust the prefetcher read window accordingly.
     client: Client,
+    /// Memory that can be reclaimed when a reservation would exceed the limit.
+    reclaimable: Option<Arc<dyn ReclaimableMemory>>,
 }
 
 impl<Client: ObjectClient> MemoryLimiter<Client> {
@@ -65,14 +92,47 @@ impl<Client: ObjectClient> MemoryLimiter<Client> {
             mem_limit,
             prefetcher_mem_reserved: AtomicU64::new(0),
             additional_mem_reserved: reserved_mem,
+            reclaimable: None,
+        }
+    }
+
+    /// Count memory held by `reclaimable` against the limit, reclaiming it when needed. Call
+    /// [MemoryLimiter::limit_reclaimable_memory] once the limiter is shared to also stop
+    /// `reclaimable` from growing past the limit.
+    #[must_use = "MemoryLimiter follows a builder pattern"]
+    pub fn with_reclaimable_memory(mut self, reclaimable: Arc<dyn ReclaimableMemory>) -> Self {
+        self.reclaimable = Some(reclaimable);
+        self
+    }
+
+    /// Limit the reclaimable memory to what isn't reserved by anything else, so that it can't take
+    /// the total memory usage over the limit.
+    pub fn limit_reclaimable_memory(self: &Arc<Self>)
+    where
+        Client: Send + Sync + 'static,
+    {
+        if let Some(reclaimable) = &self.reclaimable {
+            let budget: Weak<dyn MemoryBudget> = Arc::downgrade(self) as Weak<Self>;
+            reclaimable.set_memory_budget(budget);
         }
     }
 
     /// Reserve the memory for future uses. Always succeeds, even if it means going beyond
     /// the configured memory limit.
     pub fn reserve(&self, size: u64) {
-        self.prefetcher_mem_reserved.fetch_add(size, Ordering::SeqCst);
+        let prefetcher_mem_reserved = self.prefetcher_mem_reserved.fetch_add(size, Ordering::SeqCst);
         metrics::gauge!("prefetch.bytes_reserved").increment(size as f64);
+        if let Some(reclaimable) = &self.reclaimable {
+            let total_mem_usage = prefetcher_mem_reserved
+                .saturating_add(size)
+                .saturating_add(self.client_mem_allocated())
+                .saturating_add(self.additional_mem_reserved)
+                .saturating_add(reclaimable.mem_used());
+            let over_limit = total_mem_usage.saturating_sub(self.mem_limit);
+            if over_limit > 0 {
+                self.reclaim(over_limit);
+            }
+        }
     }
 
     /// Reserve the memory for future uses. If there is not enough memory returns `false`.
@@ -85,11 +145,15 @@ impl<Client: ObjectClient> MemoryLimiter<Client> {
             let new_total_mem_usage = new_prefetcher_mem_reserved
                 .saturating_add(client_mem_allocated)
                 .saturating_add(self.additional_mem_reserved);
+            let reclaimable_mem = self.reclaimable.as_ref().map_or(0, |r| r.mem_used());
             if new_total_mem_usage > self.mem_limit {
                 trace!(new_total_mem_usage, "not enough memory to reserve");
                 metrics::histogram!("prefetch.mem_reserve_latency_us").record(start.elapsed().as_micros() as f64);
                 return false;
             }
+            let over_limit = new_total_mem_usage
+                .saturating_add(reclaimable_mem)
+                .saturating_sub(self.mem_limit);
             // Check that the value we have read is still the same before updating it
             match self.prefetcher_mem_reserved.compare_exchange_weak(
                 prefetcher_mem_reserved,
@@ -98,6 +162,10 @@ impl<Client: ObjectClient> MemoryLimiter<Client> {
                 Ordering::SeqCst,
             ) {
                 Ok(_) => {
+                    if over_limit > 0 {
+                        // We already know there will be enough memory once the reclaimable memory is released
+                        self.reclaim(over_limit);
+                    }
                     metrics::gauge!("prefetch.bytes_reserved").increment(size as f64);
                     metrics::histogram!("prefetch.mem_reserve_latency_us").record(start.elapsed().as_micros() as f64);
                     return true;
@@ -113,7 +181,9 @@ impl<Client: ObjectClient> MemoryLimiter<Client> {
         metrics::gauge!("prefetch.bytes_reserved").decrement(size as f64);
     }
 
-    /// Query available memory tracked by the memory limiter.
+    /// Query memory available for new reservations. Memory held by reclaimable memory isn't
+    /// subtracted, so this can be more than the limit minus [MemoryLimiter::mem_used]: a reservation
+    /// of up to this size succeeds, but evicts reclaimable memory to make room.
     pub fn available_mem(&self) -> u64 {
         let prefetcher_mem_reserved = self.prefetcher_mem_reserved.load(Ordering::SeqCst);
         let client_mem_allocated = self.client_mem_allocated();
@@ -123,6 +193,23 @@ impl<Client: ObjectClient> MemoryLimiter<Client> {
             .saturating_sub(self.additional_mem_reserved)
     }
 
+    /// Query total memory usage tracked by the memory limiter, including reclaimable memory.
+    pub fn mem_used(&self) -> u64 {
+        self.prefetcher_mem_reserved
+            .load(Ordering::SeqCst)
+            .saturating_add(self.client_mem_allocated())
+            .saturating_add(self.additional_mem_reserved)
+            .saturating_add(self.reclaimable.as_ref().map_or(0, |r| r.mem_used()))
+    }
+
+    fn reclaim(&self, size: u64) {
+        if let Some(reclaimable) = &self.reclaimable {
+            let reclaimed = reclaimable.reclaim(size);
+            trace!(size, reclaimed, "reclaimed memory to make room for reservation");
+            metrics::counter!("prefetch.mem_reclaimed_bytes").increment(reclaimed);
+        }
+    }
+
     // Get allocated memory for the client. Currently, only the CRT client is able to report its buffer pool stats.
     // The CRT allocates memory in two areas. The first one is primary storage where memory is allocated in blocks
     // and we can get number of allocated bytes from `primary_allocated` stat. Another area is called secondary storage
@@ -134,3 +221,9 @@ impl<Client: ObjectClient> MemoryLimiter<Client> {
             .map_or(0, |stats| stats.primary_allocated.saturating_add(stats.secondary_used))
     }
 }
+
+impl<Client: ObjectClient + Send + Sync> MemoryBudget for MemoryLimiter<Client> {
+    fn reclaimable_mem_limit(&self) -> u64 {
+        self.available_mem()
+    }
+}
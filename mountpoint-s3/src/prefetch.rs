@@ -38,6 +38,7 @@ mod part_queue;
 mod part_stream;
 mod seek_window;
 mod task;
+mod warm;
 
 use std::fmt::Debug;
 use std::time::Duration;
@@ -53,7 +54,7 @@ use thiserror::Error;
 use tracing::trace;
 
 use crate::checksums::{ChecksummedBytes, IntegrityError};
-use crate::data_cache::DataCache;
+use crate::data_cache::{CachePolicy, DataCache};
 use crate::mem_limiter::MemoryLimiter;
 use crate::object::ObjectId;
 use crate::prefetch::caching_stream::CachingPartStream;
@@ -62,6 +63,8 @@ use crate::prefetch::seek_window::SeekWindow;
 use crate::prefetch::task::RequestTask;
 use crate::sync::Arc;
 
+pub use warm::{CacheWarmer, CacheWarmerConfig, CacheWarmerError, WarmProgress};
+
 /// Generic interface to handle reading data from an object.
 pub trait Prefetch {
     type PrefetchResult<Client: ObjectClient + Clone + Send + Sync + 'static>: PrefetchResult<Client>;
@@ -139,7 +142,21 @@ where
     Cache: DataCache + Send + Sync + 'static,
     Runtime: Spawn + Clone + Send + Sync + 'static,
 {
-    let part_stream = CachingPartStream::new(runtime, cache);
+    caching_prefetch_with_policy(cache, Default::default(), runtime, prefetcher_config)
+}
+
+/// Creates an instance of a caching [Prefetch] that only caches the objects allowed by `policy`.
+pub fn caching_prefetch_with_policy<Cache, Runtime>(
+    cache: Cache,
+    policy: Arc<CachePolicy>,
+    runtime: Runtime,
+    prefetcher_config: PrefetcherConfig,
+) -> CachingPrefetcher<Cache, Runtime>
+where
+    Cache: DataCache + Send + Sync + 'static,
+    Runtime: Spawn + Clone + Send + Sync + 'static,
+{
+    let part_stream = CachingPartStream::new(runtime, cache).with_policy(policy);
     Prefetcher::new(part_stream, prefetcher_config)
 }
 
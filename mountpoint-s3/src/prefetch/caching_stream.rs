@@ -8,7 +8,7 @@ use mountpoint_s3_client::ObjectClient;
 use tracing::{debug_span, trace, warn, Instrument};
 
 use crate::checksums::ChecksummedBytes;
-use crate::data_cache::{BlockIndex, DataCache};
+use crate::data_cache::{BlockIndex, CachePolicy, DataCache};
 use crate::mem_limiter::MemoryLimiter;
 use crate::object::ObjectId;
 use crate::prefetch::backpressure_controller::{new_backpressure_controller, BackpressureConfig, BackpressureLimiter};
@@ -26,6 +26,7 @@ use crate::prefetch::PrefetchReadError;
 pub struct CachingPartStream<Cache, Runtime> {
     cache: Arc<Cache>,
     runtime: Runtime,
+    policy: Arc<CachePolicy>,
 }
 
 impl<Cache, Runtime> CachingPartStream<Cache, Runtime> {
@@ -33,8 +34,15 @@ impl<Cache, Runtime> CachingPartStream<Cache, Runtime> {
         Self {
             cache: Arc::new(cache),
             runtime,
+            policy: Default::default(),
         }
     }
+
+    /// Only cache the objects allowed by the given policy. Other objects are always read from the client.
+    pub fn with_policy(mut self, policy: Arc<CachePolicy>) -> Self {
+        self.policy = policy;
+        self
+    }
 }
 
 impl<Cache, Runtime> ObjectPartStream for CachingPartStream<Cache, Runtime>
@@ -66,12 +74,16 @@ where
         trace!(?range, "spawning request");
 
         let request_task = {
+            let should_cache = self
+                .policy
+                .should_cache(config.object_id.key(), range.object_size() as u64);
             let request = CachingRequest::new(
                 client.clone(),
                 self.cache.clone(),
                 self.runtime.clone(),
                 backpressure_limiter,
                 config,
+                should_cache,
             );
             let span = debug_span!("prefetch", ?range);
             request.get_from_cache(range, part_queue_producer).instrument(span)
@@ -90,6 +102,8 @@ struct CachingRequest<Client: ObjectClient, Cache, Runtime: Spawn> {
     runtime: Runtime,
     backpressure_limiter: BackpressureLimiter,
     config: RequestTaskConfig,
+    /// Whether the cache policy allows caching this object
+    should_cache: bool,
 }
 
 impl<Client, Cache, Runtime> CachingRequest<Client, Cache, Runtime>
@@ -104,6 +118,7 @@ where
         runtime: Runtime,
         backpressure_limiter: BackpressureLimiter,
         config: RequestTaskConfig,
+        should_cache: bool,
     ) -> Self {
         Self {
             client,
@@ -111,6 +126,7 @@ where
             runtime,
             backpressure_limiter,
             config,
+            should_cache,
         }
     }
 
@@ -135,6 +151,13 @@ where
         let block_size = self.cache.block_size();
         let block_range = self.block_indices_for_byte_range(&range);
 
+        if !self.should_cache {
+            // The object is excluded by the cache policy, so there is nothing to look up.
+            trace!(?cache_key, ?range, "object not cached by policy");
+            metrics::counter!("prefetch.blocks_requested_to_client").increment(block_range.end - block_range.start);
+            return self.get_from_client(range, block_range, part_queue_producer).await;
+        }
+
         // Scan the blocks and feed them from the cache. If a block is missing or invalid,
         // start a GetObject request on the client for the remainder of the stream.
         // We could check for missing blocks in advance and pre-emptively start a GetObject
@@ -226,6 +249,7 @@ where
             block_offset: block_range.start * block_size,
             cache: self.cache.clone(),
             runtime: self.runtime.clone(),
+            should_cache: self.should_cache,
         };
         part_composer.try_compose_parts(request_stream).await;
     }
@@ -250,6 +274,7 @@ struct CachingPartComposer<E: std::error::Error, Cache, Runtime: Spawn> {
     block_offset: u64,
     cache: Arc<Cache>,
     runtime: Runtime,
+    should_cache: bool,
 }
 
 impl<E, Cache, Runtime> CachingPartComposer<E, Cache, Runtime>
@@ -343,6 +368,9 @@ where
     }
 
     fn update_cache(&self, block: ChecksummedBytes, block_index: u64, block_offset: u64, object_id: &ObjectId) {
+        if !self.should_cache {
+            return;
+        }
         let object_id = object_id.clone();
         let cache = self.cache.clone();
         self.runtime
@@ -541,6 +569,54 @@ mod tests {
         }
     }
 
+    #[test_case("excluded/object", 1 * MB; "excluded prefix")]
+    #[test_case("object", 4 * MB; "larger than max object size")]
+    fn test_policy_excludes_object(key: &str, object_size: usize) {
+        let block_size = 1 * MB;
+        let client_part_size = 8 * MB;
+        let object = MockObject::ramp(0xaa, object_size, ETag::for_tests());
+        let id = ObjectId::new(key.to_owned(), object.etag());
+        let policy: CachePolicy = serde_json::from_str(
+            r#"{ "max_object_size": 2097152, "rules": [{ "prefix": "excluded/", "action": "exclude" }] }"#,
+        )
+        .unwrap();
+
+        let cache = InMemoryDataCache::new(block_size as u64);
+        let bucket = "test-bucket";
+        let config = MockClientConfig {
+            bucket: bucket.to_string(),
+            part_size: client_part_size,
+            enable_backpressure: true,
+            initial_read_window_size: 1 * MB,
+            ..Default::default()
+        };
+        let mock_client = Arc::new(MockClient::new(config));
+        let mem_limiter = Arc::new(MemoryLimiter::new(mock_client.clone(), MINIMUM_MEM_LIMIT));
+        mock_client.add_object(key, object.clone());
+
+        let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+        let stream = CachingPartStream::new(runtime, cache).with_policy(Arc::new(policy));
+
+        for _ in 0..2 {
+            let get_object_counter = mock_client.new_counter(Operation::GetObject);
+            let config = RequestTaskConfig {
+                bucket: bucket.to_owned(),
+                object_id: id.clone(),
+                range: RequestRange::new(object_size, 0, object_size),
+                read_part_size: client_part_size,
+                preferred_part_size: 256 * KB,
+                initial_read_window_size: 1 * MB,
+                max_read_window_size: 64 * MB,
+                read_window_size_multiplier: 2,
+            };
+            let request_task = stream.spawn_get_object_request(&mock_client, config, mem_limiter.clone());
+            compare_read(&id, &object, request_task);
+            // Every read goes to the client, since nothing is cached
+            assert!(get_object_counter.count() > 0);
+        }
+        assert_eq!(stream.cache.block_count(&id), 0);
+    }
+
     fn compare_read<Client: ObjectClient>(id: &ObjectId, object: &MockObject, mut request_task: RequestTask<Client>) {
         let mut offset = request_task.start_offset();
         let mut remaining = request_task.total_size();
@@ -0,0 +1,444 @@
+//! Warm a [DataCache] by fetching all the objects under a prefix ahead of reads.
+//!
+//! Objects are fetched through a [CachingPartStream], like reads from a mounted file system, so
+//! blocks already in the cache are not downloaded again and new blocks are written to the cache
+//! with [DataCache::put_block].
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_io::Timer;
+use async_stream::stream;
+use futures::future::{FutureExt, FutureObj};
+use futures::task::{Spawn, SpawnError};
+use futures::{Stream, StreamExt};
+use mountpoint_s3_client::error::{ListObjectsError, ObjectClientError};
+use mountpoint_s3_client::types::ObjectInfo;
+use mountpoint_s3_client::ObjectClient;
+use thiserror::Error;
+use tracing::{debug, trace, warn};
+
+use crate::data_cache::{CachePolicy, DataCache};
+use crate::mem_limiter::MemoryLimiter;
+use crate::object::ObjectId;
+use crate::prefetch::caching_stream::CachingPartStream;
+use crate::prefetch::part_stream::{ObjectPartStream, RequestRange, RequestTaskConfig};
+use crate::prefetch::PrefetchReadError;
+use crate::prefix::Prefix;
+use crate::sync::Mutex;
+
+/// Part size to request objects with if the client doesn't have a preferred one
+const DEFAULT_READ_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Configuration for a [CacheWarmer].
+#[derive(Debug, Clone)]
+pub struct CacheWarmerConfig {
+    /// Number of objects to fetch concurrently
+    pub concurrency: usize,
+    /// Maximum rate to fetch object content at, in bytes per second. `None` for no limit.
+    pub max_throughput: Option<u64>,
+    /// Minimum time between progress reports
+    pub progress_interval: Duration,
+    /// Maximum size of the read window for each object
+    pub max_read_window_size: usize,
+    /// Policy deciding which objects are cached. Objects it excludes are not fetched.
+    pub policy: Arc<CachePolicy>,
+}
+
+impl Default for CacheWarmerConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 16,
+            max_throughput: None,
+            progress_interval: Duration::from_secs(1),
+            max_read_window_size: 64 * 1024 * 1024,
+            policy: Default::default(),
+        }
+    }
+}
+
+/// Progress of a [CacheWarmer::run].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarmProgress {
+    /// Number of objects to warm listed so far
+    pub objects_total: usize,
+    /// Total size of the objects to warm listed so far
+    pub bytes_total: u64,
+    /// Number of objects successfully warmed
+    pub objects_warmed: usize,
+    /// Number of objects that couldn't be fetched
+    pub objects_failed: usize,
+    /// Bytes fetched so far, either from S3 or from the cache
+    pub bytes_fetched: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum CacheWarmerError<E: std::error::Error + Send + Sync + 'static> {
+    #[error("ListObjectsV2 failed")]
+    ListFailed(#[source] ObjectClientError<ListObjectsError, E>),
+}
+
+/// Fetches the objects under a prefix of a bucket into a [DataCache].
+pub struct CacheWarmer<Client: ObjectClient, Cache, Runtime> {
+    client: Client,
+    cache: Arc<Cache>,
+    runtime: Runtime,
+    mem_limiter: Arc<MemoryLimiter<Client>>,
+    bucket: String,
+    prefix: String,
+    config: CacheWarmerConfig,
+}
+
+impl<Client, Cache, Runtime> CacheWarmer<Client, Cache, Runtime>
+where
+    Client: ObjectClient + Clone + Send + Sync + 'static,
+    Cache: DataCache + Send + Sync + 'static,
+    Runtime: Spawn + Clone + Send + Sync + 'static,
+{
+    /// Create a new [CacheWarmer] for the objects under `prefix` in `bucket`.
+    pub fn new(
+        client: Client,
+        cache: Cache,
+        runtime: Runtime,
+        bucket: &str,
+        prefix: &Prefix,
+        mem_limiter: Arc<MemoryLimiter<Client>>,
+        config: CacheWarmerConfig,
+    ) -> Self {
+        Self {
+            client,
+            cache: Arc::new(cache),
+            runtime,
+            mem_limiter,
+            bucket: bucket.to_owned(),
+            prefix: prefix.as_str().to_owned(),
+            config,
+        }
+    }
+
+    /// List the objects under the prefix and fetch them all into the cache, calling `on_progress`
+    /// at most once per [CacheWarmerConfig::progress_interval]. Objects are fetched as each page of
+    /// the listing arrives, so the totals in the reported progress grow as the listing goes on.
+    /// Objects that can't be fetched are counted in [WarmProgress::objects_failed] rather than
+    /// stopping the others.
+    ///
+    /// Returns once all the fetched blocks have been written to the cache, even if the listing
+    /// failed part way through.
+    pub async fn run(
+        &self,
+        on_progress: impl FnMut(&WarmProgress),
+    ) -> Result<WarmProgress, CacheWarmerError<Client::ClientError>> {
+        debug!(prefix = self.prefix, "warming cache");
+        let reporter = Mutex::new(ProgressReporter {
+            progress: Default::default(),
+            last_report: Instant::now(),
+            interval: self.config.progress_interval,
+            on_progress,
+        });
+        let throughput_limiter = self.config.max_throughput.map(ThroughputLimiter::new);
+
+        // Track the tasks spawned by the part stream, including cache writes that outlive the
+        // requests, so we can wait for them to finish. Each task holds a sender, and the channel is
+        // closed once they are all dropped.
+        let (task_sender, task_receiver) = async_channel::bounded::<()>(1);
+        let runtime = TrackedSpawner {
+            runtime: self.runtime.clone(),
+            task_sender,
+        };
+        let part_stream = CachingPartStream::new(runtime, self.cache.clone()).with_policy(self.config.policy.clone());
+
+        let list_error = Mutex::new(None);
+        self.list_objects(&reporter, &list_error)
+            .for_each_concurrent(self.config.concurrency, |object| {
+                let part_stream = &part_stream;
+                let reporter = &reporter;
+                let throughput_limiter = throughput_limiter.as_ref();
+                async move {
+                    let result = self
+                        .warm_object(part_stream, &object, reporter, throughput_limiter)
+                        .await;
+                    let mut reporter = reporter.lock().unwrap();
+                    match result {
+                        Ok(()) => reporter.progress.objects_warmed += 1,
+                        Err(err) => {
+                            warn!(key = object.key, ?err, "failed to warm object");
+                            reporter.progress.objects_failed += 1;
+                        }
+                    }
+                    reporter.maybe_report();
+                }
+            })
+            .await;
+
+        drop(part_stream);
+        trace!("waiting for cache writes to complete");
+        let _ = task_receiver.recv().await;
+
+        if let Some(err) = list_error.into_inner().unwrap() {
+            return Err(err);
+        }
+        let mut reporter = reporter.into_inner().unwrap();
+        (reporter.on_progress)(&reporter.progress);
+        Ok(reporter.progress)
+    }
+
+    /// List the objects under the prefix one page at a time, skipping those that can't be read
+    /// and adding the others to the progress totals. If listing fails, the stream ends and the
+    /// error is stored in `list_error`.
+    fn list_objects<'a, F: FnMut(&WarmProgress) + 'a>(
+        &'a self,
+        reporter: &'a Mutex<ProgressReporter<F>>,
+        list_error: &'a Mutex<Option<CacheWarmerError<Client::ClientError>>>,
+    ) -> impl Stream<Item = ObjectInfo> + 'a {
+        stream! {
+            let mut continuation_token = None;
+            loop {
+                let result = match self
+                    .client
+                    .list_objects(&self.bucket, continuation_token.as_deref(), "", 1000, &self.prefix)
+                    .await
+                {
+                    Ok(result) => result,
+                    Err(err) => {
+                        *list_error.lock().unwrap() = Some(CacheWarmerError::ListFailed(err));
+                        break;
+                    }
+                };
+                let objects: Vec<_> = result
+                    .objects
+                    .into_iter()
+                    .filter(|object| match object.storage_class.as_deref() {
+                        Some("GLACIER") | Some("DEEP_ARCHIVE") => {
+                            debug!(key = object.key, "skipping archived object");
+                            false
+                        }
+                        _ if !self.config.policy.should_cache(&object.key, object.size) => {
+                            debug!(key = object.key, "skipping object excluded by cache policy");
+                            false
+                        }
+                        _ => true,
+                    })
+                    .collect();
+                {
+                    let mut reporter = reporter.lock().unwrap();
+                    reporter.progress.objects_total += objects.len();
+                    reporter.progress.bytes_total += objects.iter().map(|object| object.size).sum::<u64>();
+                }
+                for object in objects {
+                    yield object;
+                }
+                continuation_token = result.next_continuation_token;
+                if continuation_token.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn warm_object<Stream: ObjectPartStream, F: FnMut(&WarmProgress)>(
+        &self,
+        part_stream: &Stream,
+        object: &ObjectInfo,
+        reporter: &Mutex<ProgressReporter<F>>,
+        throughput_limiter: Option<&ThroughputLimiter>,
+    ) -> Result<(), PrefetchReadError<Client::ClientError>> {
+        let size = object.size as usize;
+        if size == 0 {
+            return Ok(());
+        }
+        trace!(key = object.key, size, "warming object");
+
+        let read_part_size = self.client.read_part_size().unwrap_or(DEFAULT_READ_PART_SIZE);
+        let config = RequestTaskConfig {
+            bucket: self.bucket.clone(),
+            object_id: ObjectId::new(object.key.clone(), object.etag.as_str().into()),
+            range: RequestRange::new(size, 0, size),
+            read_part_size,
+            preferred_part_size: read_part_size,
+            initial_read_window_size: read_part_size,
+            max_read_window_size: self.config.max_read_window_size.max(read_part_size),
+            read_window_size_multiplier: 2,
+        };
+        let mut request = part_stream.spawn_get_object_request(&self.client, config, self.mem_limiter.clone());
+        while request.remaining() > 0 {
+            let part = request.read(request.remaining()).await?;
+            let length = part.len() as u64;
+            drop(part);
+            {
+                let mut reporter = reporter.lock().unwrap();
+                reporter.progress.bytes_fetched += length;
+                reporter.maybe_report();
+            }
+            if let Some(delay) = throughput_limiter.and_then(|limiter| limiter.consume(length)) {
+                Timer::after(delay).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ProgressReporter<F> {
+    progress: WarmProgress,
+    last_report: Instant,
+    interval: Duration,
+    on_progress: F,
+}
+
+impl<F: FnMut(&WarmProgress)> ProgressReporter<F> {
+    fn maybe_report(&mut self) {
+        if self.last_report.elapsed() >= self.interval {
+            (self.on_progress)(&self.progress);
+            self.last_report = Instant::now();
+        }
+    }
+}
+
+/// Limits the average rate at which bytes are consumed across all the concurrent requests.
+#[derive(Debug)]
+struct ThroughputLimiter {
+    bytes_per_second: u64,
+    start: Instant,
+    consumed: Mutex<u64>,
+}
+
+impl ThroughputLimiter {
+    fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            start: Instant::now(),
+            consumed: Mutex::new(0),
+        }
+    }
+
+    /// Record that `bytes` were consumed, returning how long to wait to stay within the limit.
+    fn consume(&self, bytes: u64) -> Option<Duration> {
+        let mut consumed = self.consumed.lock().unwrap();
+        *consumed += bytes;
+        let allowed_at = Duration::from_secs_f64(*consumed as f64 / self.bytes_per_second as f64);
+        allowed_at.checked_sub(self.start.elapsed())
+    }
+}
+
+/// A [Spawn] implementation that keeps a sender alive for as long as each spawned task.
+#[derive(Clone)]
+struct TrackedSpawner<Runtime> {
+    runtime: Runtime,
+    task_sender: async_channel::Sender<()>,
+}
+
+impl<Runtime: Spawn> Spawn for TrackedSpawner<Runtime> {
+    fn spawn_obj(&self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+        let task_sender = self.task_sender.clone();
+        let future = future.map(move |()| drop(task_sender));
+        self.runtime.spawn_obj(FutureObj::new(Box::new(future)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::{block_on, ThreadPool};
+    use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockObject, Operation};
+    use mountpoint_s3_client::types::ETag;
+
+    use super::*;
+    use crate::data_cache::InMemoryDataCache;
+    use crate::mem_limiter::MINIMUM_MEM_LIMIT;
+
+    const BLOCK_SIZE: u64 = 1024 * 1024;
+
+    fn setup() -> (Arc<MockClient>, Arc<InMemoryDataCache>) {
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: "test-bucket".to_owned(),
+            part_size: 8 * 1024 * 1024,
+            enable_backpressure: true,
+            initial_read_window_size: 8 * 1024 * 1024,
+            ..Default::default()
+        }));
+        let cache = Arc::new(InMemoryDataCache::new(BLOCK_SIZE));
+        (client, cache)
+    }
+
+    fn warmer(
+        client: &Arc<MockClient>,
+        cache: &Arc<InMemoryDataCache>,
+        prefix: &str,
+        config: CacheWarmerConfig,
+    ) -> CacheWarmer<Arc<MockClient>, Arc<InMemoryDataCache>, ThreadPool> {
+        let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+        let mem_limiter = Arc::new(MemoryLimiter::new(client.clone(), MINIMUM_MEM_LIMIT));
+        CacheWarmer::new(
+            client.clone(),
+            cache.clone(),
+            runtime,
+            "test-bucket",
+            &Prefix::new(prefix).unwrap(),
+            mem_limiter,
+            config,
+        )
+    }
+
+    #[test]
+    fn test_warm_prefix() {
+        let (client, cache) = setup();
+        let sizes = [("data/a", 3 * BLOCK_SIZE + 10), ("data/sub/b", 10), ("data/empty", 0)];
+        for (key, size) in sizes {
+            client.add_object(key, MockObject::ramp(0xaa, size as usize, ETag::for_tests()));
+        }
+        client.add_object("other", MockObject::ramp(0xaa, 100, ETag::for_tests()));
+
+        let mut reports = Vec::new();
+        let warmer = warmer(&client, &cache, "data/", Default::default());
+        let progress = block_on(warmer.run(|progress| reports.push(progress.clone()))).unwrap();
+        let expected_bytes = sizes.iter().map(|(_, size)| size).sum();
+        assert_eq!(
+            progress,
+            WarmProgress {
+                objects_total: 3,
+                bytes_total: expected_bytes,
+                objects_warmed: 3,
+                objects_failed: 0,
+                bytes_fetched: expected_bytes,
+            }
+        );
+        assert_eq!(reports.last(), Some(&progress));
+
+        // All the blocks were written to the cache before returning
+        let object_id = ObjectId::new("data/a".into(), ETag::for_tests());
+        assert_eq!(cache.block_count(&object_id), 4);
+        let object_id = ObjectId::new("data/sub/b".into(), ETag::for_tests());
+        assert_eq!(cache.block_count(&object_id), 1);
+        let object_id = ObjectId::new("other".into(), ETag::for_tests());
+        assert_eq!(cache.block_count(&object_id), 0);
+
+        // Warming again is served from the cache
+        let get_object_counter = client.new_counter(Operation::GetObject);
+        let progress = block_on(warmer.run(|_| {})).unwrap();
+        assert_eq!(progress.objects_warmed, 3);
+        assert_eq!(get_object_counter.count(), 0);
+    }
+
+    #[test]
+    fn test_warm_multiple_pages() {
+        let (client, cache) = setup();
+        for i in 0..1005 {
+            client.add_object(&format!("data/{i:04}"), MockObject::constant(1, 1, ETag::for_tests()));
+        }
+
+        let list_counter = client.new_counter(Operation::ListObjectsV2);
+        let warmer = warmer(&client, &cache, "data/", Default::default());
+        let progress = block_on(warmer.run(|_| {})).unwrap();
+        assert_eq!(list_counter.count(), 2);
+        assert_eq!(progress.objects_total, 1005);
+        assert_eq!(progress.bytes_total, 1005);
+        assert_eq!(progress.objects_warmed, 1005);
+    }
+
+    #[test]
+    fn test_throughput_limit() {
+        let limiter = ThroughputLimiter::new(1000);
+        let delay = limiter.consume(2000).expect("should wait after exceeding the limit");
+        assert!(delay > Duration::from_millis(1000) && delay <= Duration::from_secs(2));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(limiter.consume(0).unwrap() < delay);
+    }
+}
@@ -21,6 +21,7 @@
 //! Some cached state is dependent on the inode kind; that state is hidden behind a [InodeStatKind]
 //! enum.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
@@ -39,27 +40,38 @@ use tracing::{debug, error, trace, warn};
 use crate::fs::error_metadata::{ErrorMetadata, MOUNTPOINT_ERROR_CLIENT};
 use crate::fs::CacheConfig;
 use crate::logging;
+use crate::manifest::{Manifest, ManifestEntry};
 use crate::prefix::Prefix;
 use crate::s3::S3Personality;
 use crate::sync::atomic::{AtomicU64, Ordering};
-use crate::sync::{Arc, RwLock};
+use crate::sync::{async_channel, Arc, RwLock};
+
+mod escaping;
+pub use escaping::{escape_name, shortened_name_prefix, unescape_name};
 
 mod expiry;
 use expiry::Expiry;
 
 mod inode;
-use inode::{valid_inode_name, InodeErrorInfo, InodeKindData, InodeStat, InodeState, WriteStatus};
+use inode::{valid_inode_name, InodeErrorInfo, InodeKey, InodeKindData, InodeStat, InodeState, WriteStatus};
 
 pub use inode::{Inode, InodeKind, InodeNo, ReadHandle, WriteHandle};
 
 mod negative_cache;
 use negative_cache::NegativeCache;
 
+mod persistent_cache;
+use persistent_cache::CachedEntry;
+pub use persistent_cache::{PersistentMetadataCache, PersistentMetadataCacheConfig};
+
 mod readdir;
 pub use readdir::ReaddirHandle;
 
+mod unreferenced_inodes;
+use unreferenced_inodes::UnreferencedInodes;
+
 /// Superblock is the root object of the file system
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Superblock {
     inner: Arc<SuperblockInner>,
 }
@@ -69,6 +81,7 @@ struct SuperblockInner {
     bucket: String,
     inodes: RwLock<InodeMap>,
     negative_cache: NegativeCache,
+    unreferenced_inodes: UnreferencedInodes,
     next_ino: AtomicU64,
     mount_time: OffsetDateTime,
     config: SuperblockConfig,
@@ -79,6 +92,28 @@ struct SuperblockInner {
 pub struct SuperblockConfig {
     pub cache_config: CacheConfig,
     pub s3_personality: S3Personality,
+    pub persistent_metadata_cache: Option<Arc<PersistentMetadataCache>>,
+    /// Manifest to serve lookups and listings from, instead of S3
+    pub manifest: Option<Arc<Manifest>>,
+    /// Number of ranges of keys listed concurrently when listing a directory
+    pub list_concurrency: usize,
+    /// Where to send the invalidations of entries and inodes that changed remotely
+    pub invalidations: Option<async_channel::Sender<Invalidation>>,
+    /// Show the key components that are not valid file names, which the file system escapes,
+    /// rather than hiding them
+    pub escape_names: bool,
+    /// Show the files shadowed by a directory of the same name under their name followed by this
+    /// suffix, rather than hiding them
+    pub shadowed_file_suffix: Option<String>,
+}
+
+/// A change to the file system discovered from the remote, which the kernel may still have cached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invalidation {
+    /// The entry `name` in directory `parent` was removed or now refers to a different inode
+    Entry { parent: InodeNo, name: String },
+    /// The content or attributes of the inode are no longer valid
+    Inode { ino: InodeNo },
 }
 
 impl Superblock {
@@ -90,12 +125,14 @@ impl Superblock {
         let mut inodes = InodeMap::default();
         inodes.insert(root.ino(), root);
 
-        let negative_cache = NegativeCache::new(config.cache_config.negative_cache_size, config.cache_config.file_ttl);
+        let negative_cache = NegativeCache::new(config.cache_config.negative_cache_size);
+        let unreferenced_inodes = UnreferencedInodes::new(config.cache_config.unreferenced_inode_cache_size);
 
         let inner = SuperblockInner {
             bucket: bucket.to_owned(),
             inodes: RwLock::new(inodes),
             negative_cache,
+            unreferenced_inodes,
             next_ino: AtomicU64::new(2),
             mount_time,
             config,
@@ -164,6 +201,86 @@ impl Superblock {
         }
     }
 
+    /// Discard what is cached about the object `key`, after learning that it was created,
+    /// overwritten, or deleted in the bucket.
+    ///
+    /// The listings of the directories containing the key and the negative cache entries on its
+    /// path are dropped, and the stats of the inodes on its path are expired so that the next
+    /// lookup revalidates them. The kernel is asked to invalidate the entry and inode for the key
+    /// itself. Keys outside the mounted prefix are ignored.
+    pub fn invalidate_key(&self, key: &str) {
+        let root = self
+            .inner
+            .get(crate::fs::FUSE_ROOT_INODE)
+            .expect("root inode always exists");
+        let Some(path) = key.strip_prefix(&*root.full_key()) else {
+            trace!(key, "ignoring invalidation outside of the mounted prefix");
+            return;
+        };
+        trace!(key, "invalidating key");
+        if let Some(cache) = &self.inner.config.persistent_metadata_cache {
+            cache.invalidate(key);
+        }
+
+        // Directory markers (`a/b/`) refer to the directory `a/b`. Keys with empty components are
+        // only visible when escaping names, where `a//` is the marker of the directory `a/%`.
+        let escape_names = self.inner.config.escape_names;
+        let path = if escape_names {
+            path.strip_suffix('/').unwrap_or(path)
+        } else {
+            path.trim_end_matches('/')
+        };
+        if path.is_empty() {
+            return;
+        }
+        let mut components = path.split('/').peekable();
+        let mut parent = root;
+        while let Some(name) = components.next() {
+            if name.is_empty() && !escape_names {
+                break;
+            }
+            let is_last = components.peek().is_none();
+            if let Some(suffix) = self.inner.config.shadowed_file_suffix.as_ref().filter(|_| is_last) {
+                // The object may be a file shadowed by a directory, visible under its alternate name
+                self.invalidate_child(&parent, &format!("{name}{suffix}"), true);
+            }
+            let Some(child) = self.invalidate_child(&parent, name, is_last) else {
+                break;
+            };
+            parent = child;
+        }
+    }
+
+    /// Discard what is cached about the entry `name` of the directory `parent` for
+    /// [Self::invalidate_key], and return its inode if it exists. If `is_last`, the entry is the
+    /// key itself, and the kernel is asked to invalidate it.
+    fn invalidate_child(&self, parent: &Inode, name: &str, is_last: bool) -> Option<Inode> {
+        self.inner.negative_cache.remove(parent.ino(), name);
+        let child = {
+            let mut parent_state = parent.get_mut_inode_state_no_check();
+            parent_state.kind_data.invalidate_listing();
+            match &parent_state.kind_data {
+                InodeKindData::Directory { children, .. } => children.get(name).cloned(),
+                InodeKindData::File {} => None,
+            }
+        }?;
+        {
+            let mut child_state = child.get_mut_inode_state_no_check();
+            // Local files are not in the bucket yet, so the event is about a different object
+            if child_state.write_status == WriteStatus::Remote {
+                child_state.stat.update_validity(Duration::ZERO);
+            }
+        }
+        if is_last {
+            self.inner.invalidate(Invalidation::Entry {
+                parent: parent.ino(),
+                name: name.to_owned(),
+            });
+            self.inner.invalidate(Invalidation::Inode { ino: child.ino() });
+        }
+        Some(child)
+    }
+
     /// Lookup an inode in the parent directory with the given name and
     /// increments its lookup count.
     pub async fn lookup<OC: ObjectClient>(
@@ -173,19 +290,76 @@ impl Superblock {
         name: &OsStr,
     ) -> Result<LookedUp, InodeError> {
         trace!(parent=?parent_ino, ?name, "lookup");
-        let lookup = self
-            .inner
-            .lookup_by_name(
-                client,
-                parent_ino,
-                name,
-                self.inner.config.cache_config.serve_lookup_from_cache,
-            )
-            .await?;
+        let lookup = self.inner.lookup_by_name(client, parent_ino, name, true).await?;
         self.inner.remember(&lookup.inode);
         Ok(lookup)
     }
 
+    /// Find the name of the entry of the directory `parent_ino` that [escape_name] shortened to
+    /// `name`, because its escaped name was too long to be a file name.
+    ///
+    /// The known children of the directory are searched first. Otherwise, the entries of the
+    /// directory starting with [shortened_name_prefix] are listed from the bucket, or the manifest.
+    pub async fn resolve_shortened_name<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        parent_ino: InodeNo,
+        name: &str,
+    ) -> Result<String, InodeError> {
+        let prefix = shortened_name_prefix(name).ok_or_else(|| InodeError::InvalidFileName(name.into()))?;
+        let parent = self.inner.get(parent_ino)?;
+        let shortens_to_name = |candidate: &str| candidate.starts_with(&prefix) && escape_name(candidate) == name;
+
+        match &parent.get_inode_state()?.kind_data {
+            InodeKindData::File {} => return Err(InodeError::NotADirectory(parent.err())),
+            InodeKindData::Directory { children, .. } => {
+                if let Some(child_name) = children.keys().find(|child_name| shortens_to_name(child_name)) {
+                    return Ok(child_name.clone());
+                }
+            }
+        }
+
+        let dir_key = parent.full_key();
+        if let Some(manifest) = &self.inner.config.manifest {
+            let found = manifest.list(&dir_key).into_iter().find_map(|entry| {
+                let (ManifestEntry::Directory { name } | ManifestEntry::Object { name, .. }) = entry;
+                shortens_to_name(&name).then_some(name)
+            });
+            return found.ok_or_else(|| InodeError::FileDoesNotExist(name.to_owned(), parent.err()));
+        }
+
+        let list_prefix = format!("{dir_key}{prefix}");
+        let mut continuation_token = None;
+        loop {
+            let result = client
+                .list_objects(
+                    &self.inner.bucket,
+                    continuation_token.as_deref(),
+                    "/",
+                    1000,
+                    &list_prefix,
+                )
+                .await
+                .map_err(|e| InodeError::client_error(e, "ListObjectsV2 failed", &self.inner.bucket, &list_prefix))?;
+            let names = result
+                .common_prefixes
+                .iter()
+                .filter_map(|common_prefix| common_prefix.strip_suffix('/'))
+                .chain(result.objects.iter().map(|object| object.key.as_str()))
+                .filter_map(|key| key.strip_prefix(&*dir_key));
+            for candidate in names {
+                if shortens_to_name(candidate) {
+                    trace!(parent = ?parent_ino, ?name, candidate, "resolved shortened name");
+                    return Ok(candidate.to_owned());
+                }
+            }
+            continuation_token = result.next_continuation_token;
+            if continuation_token.is_none() {
+                return Err(InodeError::FileDoesNotExist(name.to_owned(), parent.err()));
+            }
+        }
+    }
+
     /// Retrieve the attributes for an inode
     pub async fn getattr<OC: ObjectClient>(
         &self,
@@ -211,7 +385,7 @@ impl Superblock {
             .await?;
         if lookup.inode.ino() != ino {
             Err(InodeError::StaleInode {
-                remote_key: lookup.inode.full_key().to_owned(),
+                remote_key: lookup.inode.full_key().into_owned(),
                 old_inode: inode.err(),
                 new_inode: lookup.inode.err(),
             })
@@ -236,9 +410,10 @@ impl Superblock {
             return Err(InodeError::SetAttrNotPermittedOnRemoteInode(inode.err()));
         }
 
+        let cache_config = self.inner.config.cache_config.for_key(&inode.full_key());
         let validity = match inode.kind() {
-            InodeKind::File => self.inner.config.cache_config.file_ttl,
-            InodeKind::Directory => self.inner.config.cache_config.dir_ttl,
+            InodeKind::File => cache_config.file_ttl,
+            InodeKind::Directory => cache_config.dir_ttl,
         };
 
         // Resetting the InodeStat expiry because the new InodeStat should have new validity
@@ -313,15 +488,7 @@ impl Superblock {
     ) -> Result<LookedUp, InodeError> {
         trace!(parent=?dir, ?name, "create");
 
-        let existing = self
-            .inner
-            .lookup_by_name(
-                client,
-                dir,
-                name,
-                self.inner.config.cache_config.serve_lookup_from_cache,
-            )
-            .await;
+        let existing = self.inner.lookup_by_name(client, dir, name, true).await;
         match existing {
             Ok(lookup) => return Err(InodeError::FileAlreadyExists(lookup.inode.err())),
             Err(InodeError::FileDoesNotExist(_, _)) => (),
@@ -348,25 +515,22 @@ impl Superblock {
                 return Err(InodeError::FileAlreadyExists(inode.err()));
             }
 
+            let cache_config = self.inner.cache_config_for(&parent_inode.full_key(), name);
             let stat = match kind {
                 // Objects don't have an ETag until they are uploaded to S3
-                InodeKind::File => InodeStat::for_file(
-                    0,
-                    OffsetDateTime::now_utc(),
-                    None,
-                    None,
-                    None,
-                    self.inner.config.cache_config.file_ttl,
-                ),
-                InodeKind::Directory => {
-                    InodeStat::for_directory(self.inner.mount_time, self.inner.config.cache_config.dir_ttl)
+                InodeKind::File => {
+                    InodeStat::for_file(0, OffsetDateTime::now_utc(), None, None, None, cache_config.file_ttl)
                 }
+                InodeKind::Directory => InodeStat::for_directory(self.inner.mount_time, cache_config.dir_ttl),
             };
 
             let state = InodeState::new(&stat, kind, WriteStatus::LocalUnopened);
+            let key = parent_inode.child_key(name, kind);
             let inode = self
                 .inner
-                .create_inode_locked(&parent_inode, &mut parent_state, name, kind, state, true)?;
+                .create_inode_locked(&parent_inode, &mut parent_state, name, key, state, true)?;
+            parent_state.kind_data.invalidate_listing();
+            self.inner.negative_cache.remove(dir, name);
             LookedUp { inode, stat }
         };
 
@@ -382,15 +546,7 @@ impl Superblock {
         parent_ino: InodeNo,
         name: &OsStr,
     ) -> Result<(), InodeError> {
-        let LookedUp { inode, .. } = self
-            .inner
-            .lookup_by_name(
-                client,
-                parent_ino,
-                name,
-                self.inner.config.cache_config.serve_lookup_from_cache,
-            )
-            .await?;
+        let LookedUp { inode, .. } = self.inner.lookup_by_name(client, parent_ino, name, true).await?;
 
         if inode.kind() == InodeKind::File {
             return Err(InodeError::NotADirectory(inode.err()));
@@ -456,15 +612,7 @@ impl Superblock {
         name: &OsStr,
     ) -> Result<(), InodeError> {
         let parent = self.inner.get(parent_ino)?;
-        let LookedUp { inode, .. } = self
-            .inner
-            .lookup_by_name(
-                client,
-                parent_ino,
-                name,
-                self.inner.config.cache_config.serve_lookup_from_cache,
-            )
-            .await?;
+        let LookedUp { inode, .. } = self.inner.lookup_by_name(client, parent_ino, name, true).await?;
 
         if inode.kind() == InodeKind::Directory {
             return Err(InodeError::IsDirectory(inode.err()));
@@ -488,7 +636,7 @@ impl Superblock {
             WriteStatus::Remote => {
                 let (bucket, s3_key) = (self.inner.bucket.as_str(), inode.full_key());
                 debug!(parent=?parent_ino, ?name, "unlink on remote file will delete key {}", s3_key);
-                let delete_obj_result = client.delete_object(bucket, s3_key).await;
+                let delete_obj_result = client.delete_object(bucket, &s3_key).await;
 
                 match delete_obj_result {
                     Ok(_res) => (),
@@ -498,13 +646,17 @@ impl Superblock {
                             error=?e,
                             "DeleteObject failed for unlink",
                         );
-                        Err(InodeError::client_error(e, "DeleteObject failed", bucket, s3_key))?;
+                        Err(InodeError::client_error(e, "DeleteObject failed", bucket, &s3_key))?;
                     }
                 };
+                if let Some(cache) = &self.inner.config.persistent_metadata_cache {
+                    cache.invalidate(&s3_key);
+                }
             }
         }
 
         let mut parent_state = parent.get_mut_inode_state()?;
+        parent_state.kind_data.invalidate_listing();
         match &mut parent_state.kind_data {
             InodeKindData::File { .. } => {
                 debug_assert!(false, "inodes never change kind");
@@ -531,6 +683,43 @@ impl Superblock {
 }
 
 impl SuperblockInner {
+    /// Report a change discovered from the remote to the kernel, if configured.
+    fn invalidate(&self, invalidation: Invalidation) {
+        if let Some(sender) = &self.config.invalidations {
+            // The kernel knows entries by the names they are shown with
+            let invalidation = match invalidation {
+                Invalidation::Entry { parent, name } if self.config.escape_names => Invalidation::Entry {
+                    parent,
+                    name: escape_name(&name).into_owned(),
+                },
+                invalidation => invalidation,
+            };
+            trace!(?invalidation, "sending invalidation");
+            // The channel is unbounded, so this only fails if the receiver has gone away
+            let _ = sender.try_send(invalidation);
+        }
+    }
+
+    /// Whether `name` can be the name of an inode of the given kind. When escaping names, any key
+    /// component can be, except the empty name of the marker object of a directory.
+    fn is_valid_name(&self, name: &str, kind: InodeKind) -> bool {
+        if self.config.escape_names {
+            !name.contains('/') && (kind == InodeKind::Directory || !name.is_empty())
+        } else {
+            valid_inode_name(name)
+        }
+    }
+
+    /// The cache configuration for the entry `name` in the directory with key `dir_key`.
+    fn cache_config_for(&self, dir_key: &str, name: &str) -> Cow<'_, CacheConfig> {
+        let cache_config = &self.config.cache_config;
+        if cache_config.prefix_ttls.is_empty() {
+            // Avoid formatting the key when there is nothing to override
+            return Cow::Borrowed(cache_config);
+        }
+        cache_config.for_key(&format!("{dir_key}{name}"))
+    }
+
     /// Retrieve the inode for the given number if it exists.
     ///
     /// The expiry of its stat field is not checked.
@@ -549,9 +738,29 @@ impl SuperblockInner {
 
     /// Increase the lookup count of the given inode and
     /// ensure it is registered with this superblock.
+    ///
+    /// The lookup count is increased with the parent directory locked, so that the inode can't be
+    /// evicted from its parent by [Self::evict_unreferenced_inodes] at the same time. If the inode
+    /// was already removed from its parent between being looked up and being remembered, it is
+    /// added back, unless another inode has taken its name since.
     pub fn remember(&self, inode: &Inode) -> u64 {
-        let lookup_count = inode.inc_lookup_count();
+        let parent = (inode.ino() != inode.parent())
+            .then(|| self.get(inode.parent()).ok())
+            .flatten();
+        let lookup_count = {
+            let mut parent_state = parent.as_ref().map(|parent| parent.get_mut_inode_state_no_check());
+            let lookup_count = inode.inc_lookup_count();
+            if lookup_count == 1 {
+                if let Some(InodeKindData::Directory { children, .. }) =
+                    parent_state.as_deref_mut().map(|state| &mut state.kind_data)
+                {
+                    children.entry(inode.name().to_owned()).or_insert_with(|| inode.clone());
+                }
+            }
+            lookup_count
+        };
         if lookup_count == 1 {
+            self.unreferenced_inodes.remove(inode.ino());
             let previous = self.inodes.write().unwrap().insert(inode.ino(), inode.clone());
             assert!(previous.is_none(), "inode numbers are never reused");
         }
@@ -560,6 +769,9 @@ impl SuperblockInner {
 
     /// Lookup an inode in the parent directory with the given name.
     ///
+    /// Cached entries are used if `allow_cache` is set and the [CacheConfig] for the name allows
+    /// serving lookups from the cache. Cached missing files are always used.
+    ///
     /// Updates the parent inode to be in sync with the client, but does
     /// not add new inodes to the superblock. The caller is responsible
     /// for calling [`remember()`] if that is required.
@@ -580,38 +792,62 @@ impl SuperblockInner {
             return Err(InodeError::InvalidFileName(name.into()));
         }
 
-        let lookup = if allow_cache {
-            self.cache_lookup(parent_ino, name)
-        } else {
-            None
-        };
+        let allow_cache = allow_cache
+            && self
+                .cache_config_for(&self.get(parent_ino)?.full_key(), name)
+                .serve_lookup_from_cache;
+        let lookup = self.cache_lookup(parent_ino, name, allow_cache);
 
         let lookup = match lookup {
             Some(lookup) => lookup?,
+            None if self.config.manifest.is_some() => {
+                let remote = self.manifest_lookup(parent_ino, name)?;
+                self.update_from_remote(parent_ino, name, remote)?
+            }
             None => {
-                let remote = self.remote_lookup(client, parent_ino, name).await?;
+                let persisted = if allow_cache {
+                    self.persisted_lookup(parent_ino, name)
+                } else {
+                    None
+                };
+                let remote = match persisted {
+                    Some(remote) => remote,
+                    None => {
+                        let remote = self.remote_lookup(client, parent_ino, name).await?;
+                        // Shadowed files are stored under their own key, as part of the listing
+                        let is_shadowed = remote.as_ref().is_some_and(|remote| remote.shadowed);
+                        if let Some(cache) = self.config.persistent_metadata_cache.as_ref().filter(|_| !is_shadowed) {
+                            let key = format!("{}{}", self.get(parent_ino)?.full_key(), name);
+                            cache.put_lookup(&key, remote.as_ref());
+                        }
+                        remote
+                    }
+                };
                 self.update_from_remote(parent_ino, name, remote)?
             }
         };
 
         lookup.inode.verify_child(parent_ino, name.as_ref())?;
+        // Keep recently looked up inodes around, even if the kernel holds no reference to them
+        self.unreferenced_inodes.touch(lookup.inode.ino());
         Ok(lookup)
     }
 
-    /// Lookup an [Inode] against known directory entries in the parent,
+    /// Lookup an [Inode] against known directory entries in the parent, if `allow_cache` is set,
     /// verifying any returned entry has not expired.
     /// If no record for the given `name` is found, returns [None].
     /// If an entry is found in the negative cache, returns [Some(Err(InodeError::FileDoesNotExist))].
-    fn cache_lookup(&self, parent_ino: InodeNo, name: &str) -> Option<Result<LookedUp, InodeError>> {
+    fn cache_lookup(&self, parent_ino: InodeNo, name: &str, allow_cache: bool) -> Option<Result<LookedUp, InodeError>> {
         fn do_cache_lookup(
             superblock: &SuperblockInner,
             parent: Inode,
             name: &str,
+            allow_cache: bool,
         ) -> Option<Result<LookedUp, InodeError>> {
             match &parent.get_inode_state().ok()?.kind_data {
                 InodeKindData::File { .. } => unreachable!("parent should be a directory!"),
                 InodeKindData::Directory { children, .. } => {
-                    if let Some(inode) = children.get(name) {
+                    if let Some(inode) = children.get(name).filter(|_| allow_cache) {
                         let inode_stat = &inode.get_inode_state().ok()?.stat;
                         if inode_stat.is_valid() {
                             let lookup = LookedUp {
@@ -634,7 +870,7 @@ impl SuperblockInner {
         let lookup = self
             .get(parent_ino)
             .ok()
-            .and_then(|parent| do_cache_lookup(self, parent, name));
+            .and_then(|parent| do_cache_lookup(self, parent, name, allow_cache));
 
         match &lookup {
             Some(lookup) => trace!("lookup returned from cache: {:?}", lookup),
@@ -645,6 +881,148 @@ impl SuperblockInner {
         lookup
     }
 
+    /// Lookup an inode in the parent directory with the given name in the [Manifest].
+    fn manifest_lookup(&self, parent_ino: InodeNo, name: &str) -> Result<Option<RemoteLookup>, InodeError> {
+        let manifest = self.config.manifest.as_ref().expect("manifest should be configured");
+        let parent = self.get(parent_ino)?;
+        if parent.kind() != InodeKind::Directory {
+            return Err(InodeError::NotADirectory(parent.err()));
+        }
+        let cache_config = self.cache_config_for(&parent.full_key(), name);
+        let lookup = match manifest.lookup(&format!("{}{}", parent.full_key(), name)) {
+            Some(ManifestEntry::Directory { .. }) => Some(RemoteLookup {
+                kind: InodeKind::Directory,
+                stat: InodeStat::for_directory(self.mount_time, cache_config.dir_ttl),
+                shadowed: false,
+            }),
+            Some(ManifestEntry::Object { object_info, .. }) => Some(RemoteLookup {
+                kind: InodeKind::File,
+                stat: InodeStat::for_file(
+                    object_info.size as usize,
+                    object_info.last_modified,
+                    Some(object_info.etag),
+                    object_info.storage_class,
+                    object_info.restore_status,
+                    cache_config.file_ttl,
+                ),
+                shadowed: false,
+            }),
+            None => self
+                .shadowed_file_name(name)
+                .and_then(|key_name| manifest.lookup_shadowed_object(&format!("{}{}", parent.full_key(), key_name)))
+                .map(|object_info| RemoteLookup {
+                    kind: InodeKind::File,
+                    stat: InodeStat::for_file(
+                        object_info.size as usize,
+                        object_info.last_modified,
+                        Some(object_info.etag),
+                        object_info.storage_class,
+                        object_info.restore_status,
+                        cache_config.file_ttl,
+                    ),
+                    shadowed: true,
+                }),
+        };
+        trace!(parent = ?parent_ino, ?name, ?lookup, "lookup served from manifest");
+        Ok(lookup)
+    }
+
+    /// Lookup an inode in the parent directory with the given name in the [PersistentMetadataCache],
+    /// using the most recent of the entry's own lookup and the listing of the parent directory.
+    /// If no unexpired record is found, returns [None].
+    /// If the entry was not found when it was recorded, returns [Some(None)].
+    fn persisted_lookup(&self, parent_ino: InodeNo, name: &str) -> Option<Option<RemoteLookup>> {
+        let cache = self.config.persistent_metadata_cache.as_ref()?;
+        let parent = self.get(parent_ino).ok()?;
+        if parent.kind() != InodeKind::Directory {
+            return None;
+        }
+        let cache_config = self.cache_config_for(&parent.full_key(), name);
+        let ttl = |kind: Option<InodeKind>| match kind {
+            Some(InodeKind::Directory) => cache_config.dir_ttl,
+            Some(InodeKind::File) => cache_config.file_ttl,
+            // Missing entries expire like the negative cache
+            None => cache_config.negative_ttl().unwrap_or_default(),
+        };
+
+        let from_lookup = cache
+            .get_lookup(&format!("{}{}", parent.full_key(), name))
+            .and_then(|(lookup, age)| {
+                let validity = ttl(lookup.as_ref().map(|lookup| lookup.kind())).checked_sub(age)?;
+                let remote = match lookup {
+                    Some(lookup) => Some(lookup.to_remote_lookup(validity)?),
+                    None => None,
+                };
+                Some((remote, age))
+            });
+
+        let from_listing = cache.get_listing(&parent.full_key()).and_then(|(entries, age)| {
+            // Directories shadow files of the same name
+            let mut entry = entries
+                .iter()
+                .filter(|entry| entry.name() == name)
+                .min_by_key(|entry| !matches!(entry, CachedEntry::Directory { .. }));
+            let mut shadowed = false;
+            if let Some(key_name) = self.shadowed_file_name(name).filter(|_| entry.is_none()) {
+                let mut same_name = entries.iter().filter(|entry| entry.name() == key_name);
+                if same_name
+                    .clone()
+                    .any(|entry| matches!(entry, CachedEntry::Directory { .. }))
+                {
+                    entry = same_name.find(|entry| matches!(entry, CachedEntry::File { .. }));
+                    shadowed = entry.is_some();
+                }
+            }
+            let remote = match entry {
+                Some(CachedEntry::Directory { .. }) => {
+                    let validity = ttl(Some(InodeKind::Directory)).checked_sub(age)?;
+                    let stat = InodeStat::for_directory(self.mount_time, validity);
+                    Some(RemoteLookup {
+                        kind: InodeKind::Directory,
+                        stat,
+                        shadowed: false,
+                    })
+                }
+                Some(entry @ CachedEntry::File { .. }) => {
+                    let validity = ttl(Some(InodeKind::File)).checked_sub(age)?;
+                    let object = entry.to_object_info(&parent.full_key())?;
+                    let stat = InodeStat::for_file(
+                        object.size as usize,
+                        object.last_modified,
+                        Some(object.etag),
+                        object.storage_class,
+                        object.restore_status,
+                        validity,
+                    );
+                    Some(RemoteLookup {
+                        kind: InodeKind::File,
+                        stat,
+                        shadowed,
+                    })
+                }
+                None => {
+                    ttl(None).checked_sub(age)?;
+                    None
+                }
+            };
+            Some((remote, age))
+        });
+
+        let lookup = match (from_lookup, from_listing) {
+            (Some(from_lookup), Some(from_listing)) if from_listing.1 < from_lookup.1 => Some(from_listing.0),
+            (Some((remote, _)), _) | (None, Some((remote, _))) => Some(remote),
+            (None, None) => None,
+        };
+
+        match &lookup {
+            Some(lookup) => trace!("lookup returned from persistent cache: {:?}", lookup),
+            None => trace!("no lookup available from persistent cache"),
+        }
+        metrics::counter!("metadata_cache.persistent_cache_hit").increment(lookup.is_some().into());
+
+        lookup
+    }
+
     /// Lookup an inode in the parent directory with the given name
     /// on the remote client.
     async fn remote_lookup<OC: ObjectClient>(
@@ -657,12 +1035,13 @@ impl SuperblockInner {
         if parent.kind() != InodeKind::Directory {
             return Err(InodeError::NotADirectory(parent.err()));
         }
-        let mut full_path = parent.full_key().to_owned();
+        let mut full_path = parent.full_key().into_owned();
         assert!(full_path.is_empty() || full_path.ends_with('/'));
         full_path.push_str(name);
 
         let mut full_path_suffixed = full_path.clone();
         full_path_suffixed.push('/');
+        let cache_config = self.cache_config_for(&parent.full_key(), name);
 
         // We need to try two requests here, one to find an object with the given name, and one to
         // discover a possible shadowing (implicit) directory with the same name. There's a few
@@ -700,7 +1079,7 @@ impl SuperblockInner {
                 result = file_lookup => {
                     match result {
                         Ok(HeadObjectResult { object, .. }) => {
-                            let stat = InodeStat::for_file(object.size as usize, object.last_modified, Some(object.etag.clone()), object.storage_class, object.restore_status, self.config.cache_config.file_ttl);
+                            let stat = InodeStat::for_file(object.size as usize, object.last_modified, Some(object.etag.clone()), object.storage_class, object.restore_status, cache_config.file_ttl);
                             file_state = Some(stat);
                         }
                         // If the object is not found, might be a directory, so keep going
@@ -750,29 +1129,88 @@ impl SuperblockInner {
                     // semantics, directories always shadow files.
                     if found_directory {
                         trace!(parent = ?parent_ino, ?name, "lookup ListObjects found a directory");
-                        let stat = InodeStat::for_directory(self.mount_time, self.config.cache_config.dir_ttl);
-                        return Ok(Some(RemoteLookup { kind: InodeKind::Directory, stat }));
+                        let stat = InodeStat::for_directory(self.mount_time, cache_config.dir_ttl);
+                        return Ok(Some(RemoteLookup {
+                            kind: InodeKind::Directory,
+                            stat,
+                            shadowed: false,
+                        }));
                     }
                 }
             }
         }
 
         // If we reach here, the ListObjects didn't find a shadowing directory, so we know we either
-        // have a valid file, or both requests failed to find the object so the file must not exist remotely
-        if let Some(mut stat) = file_state {
+        // have a valid file, or both requests failed to find the object so the file must not exist remotely.
+        // An empty name can only be a directory, as the object is the marker of the parent directory.
+        if let Some(mut stat) = file_state.filter(|_| !name.is_empty()) {
             trace!(parent = ?parent_ino, ?name, etag =? stat.etag, "found a regular file in S3");
             // Update the validity of the stat in case the racing ListObjects took a long time
-            stat.update_validity(self.config.cache_config.file_ttl);
+            stat.update_validity(cache_config.file_ttl);
             Ok(Some(RemoteLookup {
                 kind: InodeKind::File,
                 stat,
+                shadowed: false,
             }))
+        } else if let Some(key_name) = self.shadowed_file_name(name) {
+            self.remote_lookup_shadowed(client, &parent, key_name, cache_config.file_ttl)
+                .await
         } else {
             trace!(parent = ?parent_ino, ?name, "not found");
             Ok(None)
         }
     }
 
+    /// Lookup the file `key_name` in the parent directory on the remote client, if it is shadowed
+    /// by a directory of the same name.
+    async fn remote_lookup_shadowed<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        parent: &Inode,
+        key_name: &str,
+        file_ttl: Duration,
+    ) -> Result<Option<RemoteLookup>, InodeError> {
+        let full_path = format!("{}{}", parent.full_key(), key_name);
+        let dir_prefix = format!("{full_path}/");
+        let (file_lookup, dir_lookup) = futures::join!(
+            client.head_object(&self.bucket, &full_path),
+            client.list_objects(&self.bucket, None, "/", 1, &dir_prefix),
+        );
+        let object = match file_lookup {
+            Ok(HeadObjectResult { object, .. }) => object,
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => return Ok(None),
+            Err(e) => {
+                return Err(InodeError::client_error(
+                    e,
+                    "HeadObject failed",
+                    &self.bucket,
+                    &full_path,
+                ))
+            }
+        };
+        let listing =
+            dir_lookup.map_err(|e| InodeError::client_error(e, "ListObjectsV2 failed", &self.bucket, &dir_prefix))?;
+        if listing.common_prefixes.is_empty() && listing.objects.is_empty() {
+            trace!(parent = ?parent.ino(), key_name, "file is not shadowed by a directory");
+            return Ok(None);
+        }
+
+        trace!(parent = ?parent.ino(), key_name, etag = ?object.etag, "found a shadowed file in S3");
+        let stat = InodeStat::for_file(
+            object.size as usize,
+            object.last_modified,
+            Some(object.etag),
+            object.storage_class,
+            object.restore_status,
+            file_ttl,
+        );
+        Ok(Some(RemoteLookup {
+            kind: InodeKind::File,
+            stat,
+            shadowed: true,
+        }))
+    }
+
     /// Update the inode with the given name in a parent directory with the remote data.
     /// It may update or delete an existing inode, or insert a new one.
     pub fn update_from_remote(
@@ -788,13 +1226,13 @@ impl SuperblockInner {
             return Err(InodeError::NotADirectory(parent.err()));
         }
 
-        if self.config.cache_config.serve_lookup_from_cache {
-            match &remote {
-                // Remove negative cache entry.
-                Some(_) => self.negative_cache.remove(parent_ino, name),
-                // Insert or update TTL of negative cache entry.
-                None => self.negative_cache.insert(parent_ino, name),
-            }
+        let cache_config = self.cache_config_for(&parent.full_key(), name);
+        match (&remote, cache_config.negative_ttl()) {
+            // Remove negative cache entry.
+            (Some(_), _) => self.negative_cache.remove(parent_ino, name),
+            // Insert or update TTL of negative cache entry.
+            (None, Some(negative_ttl)) => self.negative_cache.insert(parent_ino, name, negative_ttl),
+            (None, None) => {}
         }
 
         // Fast path: try with only a read lock on the directory first.
@@ -802,7 +1240,31 @@ impl SuperblockInner {
             return Ok(looked_up);
         }
 
-        self.update_slow_path(parent, name, remote)
+        let looked_up = self.update_slow_path(parent, name, remote);
+        self.evict_unreferenced_inodes();
+        looked_up
+    }
+
+    /// Evict the oldest inodes the kernel holds no reference to from their parent directory, if
+    /// there are too many of them.
+    ///
+    /// No inode lock may be held when calling this method.
+    fn evict_unreferenced_inodes(&self) {
+        for entry in self.unreferenced_inodes.take_excess() {
+            let mut parent_state = entry.parent.get_mut_inode_state_no_check();
+            let InodeKindData::Directory { children, .. } = &mut parent_state.kind_data else {
+                unreachable!("parent is always a directory");
+            };
+            // Leave the inode alone if it was replaced, or if it is now referenced or written to
+            let name = entry.inode.name();
+            if children.get(name).is_some_and(|child| child.ino() == entry.inode.ino())
+                && entry.inode.is_unreferenced_remote()
+            {
+                trace!(ino = entry.inode.ino(), "evicting unreferenced inode");
+                children.remove(name);
+                metrics::counter!("metadata_cache.unreferenced_inodes.evicted").increment(1);
+            }
+        }
     }
 
     /// Try to update the inode for the given name in the parent directory with only a read lock on
@@ -823,6 +1285,7 @@ impl SuperblockInner {
                 let mut existing_state = existing_inode.get_mut_inode_state()?;
                 let existing_is_remote = existing_state.write_status == WriteStatus::Remote;
                 if remote.kind == existing_inode.kind()
+                    && remote.shadowed == existing_inode.is_shadowed_file()
                     && existing_is_remote
                     && existing_state.stat.etag == remote.stat.etag
                 {
@@ -868,9 +1331,10 @@ impl SuperblockInner {
                 if writing_children.contains(&existing_inode.ino()) {
                     let mut sync = existing_inode.get_mut_inode_state()?;
 
+                    let cache_config = self.cache_config_for(&parent.full_key(), name);
                     let validity = match existing_inode.kind() {
-                        InodeKind::File => self.config.cache_config.file_ttl,
-                        InodeKind::Directory => self.config.cache_config.dir_ttl,
+                        InodeKind::File => cache_config.file_ttl,
+                        InodeKind::Directory => cache_config.dir_ttl,
                     };
                     sync.stat.update_validity(validity);
                     let stat = sync.stat.clone();
@@ -885,12 +1349,17 @@ impl SuperblockInner {
                     // being written. It must have previously existed but been removed on the remote
                     // side.
                     children.remove(name);
+                    self.invalidate(Invalidation::Entry {
+                        parent: parent.ino(),
+                        name: name.to_owned(),
+                    });
                     Err(InodeError::FileDoesNotExist(name.to_owned(), parent.err()))
                 }
             }
             (Some(remote), None) => {
                 let state = InodeState::new(&remote.stat, remote.kind, WriteStatus::Remote);
-                self.create_inode_locked(&parent, &mut parent_state, name, remote.kind, state, false)
+                let key = self.remote_key(&parent, name, &remote);
+                self.create_inode_locked(&parent, &mut parent_state, name, key, state, false)
                     .map(|inode| LookedUp {
                         inode,
                         stat: remote.stat,
@@ -916,7 +1385,8 @@ impl SuperblockInner {
                 // Try to update in place if we can. The fast path does this too, but here we can
                 // also handle the case of a local directory becoming remote, which requires
                 // updating the parent.
-                let same_kind = remote.kind == existing_inode.kind();
+                let same_kind =
+                    remote.kind == existing_inode.kind() && remote.shadowed == existing_inode.is_shadowed_file();
                 let same_etag = existing_state.stat.etag == remote.stat.etag;
                 if same_kind && same_etag && (existing_is_remote || remote.kind == InodeKind::Directory) {
                     trace!(parent=?existing_inode.parent(), name=?existing_inode.name(), ino=?existing_inode.ino(), "updating inode in place (slow path)");
@@ -953,8 +1423,15 @@ impl SuperblockInner {
                     "inode needs to be recreated",
                 );
                 let state = InodeState::new(&remote.stat, remote.kind, WriteStatus::Remote);
-                let new_inode =
-                    self.create_inode_locked(&parent, &mut parent_state, name, remote.kind, state, false)?;
+                let key = self.remote_key(&parent, name, &remote);
+                let new_inode = self.create_inode_locked(&parent, &mut parent_state, name, key, state, false)?;
+                self.invalidate(Invalidation::Entry {
+                    parent: parent.ino(),
+                    name: name.to_owned(),
+                });
+                self.invalidate(Invalidation::Inode {
+                    ino: existing_inode.ino(),
+                });
                 Ok(LookedUp {
                     inode: new_inode,
                     stat: remote.stat,
@@ -972,27 +1449,21 @@ impl SuperblockInner {
         parent: &Inode,
         parent_locked: &mut InodeState,
         name: &str,
-        kind: InodeKind,
+        key: InodeKey,
         state: InodeState,
         is_new_file: bool,
     ) -> Result<Inode, InodeError> {
-        if !valid_inode_name(name) {
+        let kind = key.kind();
+        if !self.is_valid_name(name, kind) {
             warn!(?name, "invalid file name; {} will not be available", kind.as_str());
             return Err(InodeError::InvalidFileName(OsString::from(name)));
         }
 
         let next_ino = self.next_ino.fetch_add(1, Ordering::SeqCst);
 
-        let mut full_key = parent.full_key().to_owned();
-        assert!(full_key.is_empty() || full_key.ends_with('/'));
-        full_key.push_str(name);
-        if kind == InodeKind::Directory {
-            full_key.push('/');
-        }
-
-        trace!(parent=?parent.ino(), ?name, ?kind, new_ino=?next_ino, ?full_key, "creating new inode");
+        trace!(parent=?parent.ino(), ?name, ?kind, new_ino=?next_ino, ?key, "creating new inode");
 
-        let inode = Inode::new(next_ino, parent.ino(), name.to_owned(), full_key, kind, state);
+        let inode = Inode::new(next_ino, parent.ino(), key, kind, state);
 
         match &mut parent_locked.kind_data {
             InodeKindData::File {} => {
@@ -1007,6 +1478,9 @@ impl SuperblockInner {
                 let existing_inode = children.insert(name.to_owned(), inode.clone());
                 if is_new_file {
                     writing_children.insert(next_ino);
+                } else {
+                    // Inodes from the remote are only referenced by the kernel once remembered
+                    self.unreferenced_inodes.insert(parent, &inode);
                 }
                 if let Some(existing_inode) = existing_inode {
                     writing_children.remove(&existing_inode.ino());
@@ -1016,6 +1490,21 @@ impl SuperblockInner {
 
         Ok(inode)
     }
+
+    /// The key of the inode for the entry `name` of the directory `parent` found on the remote.
+    fn remote_key(&self, parent: &Inode, name: &str, remote: &RemoteLookup) -> InodeKey {
+        match self.shadowed_file_name(name).filter(|_| remote.shadowed) {
+            Some(key_name) => parent.shadowed_child_key(name, key_name.len()),
+            None => parent.child_key(name, remote.kind),
+        }
+    }
+
+    /// The name of the file shadowed by a directory that is visible as `name`, if `name` is an
+    /// alternate name for shadowed files.
+    fn shadowed_file_name<'a>(&self, name: &'a str) -> Option<&'a str> {
+        let suffix = self.config.shadowed_file_suffix.as_deref()?;
+        name.strip_suffix(suffix).filter(|key_name| !key_name.is_empty())
+    }
 }
 
 /// Data from a remote object.
@@ -1023,6 +1512,8 @@ impl SuperblockInner {
 pub struct RemoteLookup {
     kind: InodeKind,
     stat: InodeStat,
+    /// The object is shadowed by a directory of the same name, and was found under its alternate name
+    shadowed: bool,
 }
 
 /// Result of a call to [Superblock::lookup] or [Superblock::getattr]. `stat` is a copy of the
@@ -1162,7 +1653,7 @@ mod tests {
     use test_case::test_case;
     use time::{Duration, OffsetDateTime};
 
-    use crate::fs::{ToErrno, FUSE_ROOT_INODE};
+    use crate::fs::{PrefixTtl, TimeToLive, ToErrno, FUSE_ROOT_INODE};
 
     use super::*;
 
@@ -1223,42 +1714,42 @@ mod tests {
                 .await
                 .expect("should exist");
             assert_inode_stat!(dir0, InodeKind::Directory, ts, 0);
-            assert_eq!(dir0.inode.full_key(), OsString::from(format!("{prefix}dir0/")));
+            assert_eq!(&*dir0.inode.full_key(), OsString::from(format!("{prefix}dir0/")));
 
             let dir1 = superblock
                 .lookup(&client, FUSE_ROOT_INODE, &OsString::from("dir1"))
                 .await
                 .expect("should exist");
             assert_inode_stat!(dir1, InodeKind::Directory, ts, 0);
-            assert_eq!(dir1.inode.full_key(), OsString::from(format!("{prefix}dir1/")));
+            assert_eq!(&*dir1.inode.full_key(), OsString::from(format!("{prefix}dir1/")));
 
             let sdir0 = superblock
                 .lookup(&client, dir0.inode.ino(), &OsString::from("sdir0"))
                 .await
                 .expect("should exist");
             assert_inode_stat!(sdir0, InodeKind::Directory, ts, 0);
-            assert_eq!(sdir0.inode.full_key(), OsString::from(format!("{prefix}dir0/sdir0/")));
+            assert_eq!(&*sdir0.inode.full_key(), OsString::from(format!("{prefix}dir0/sdir0/")));
 
             let sdir1 = superblock
                 .lookup(&client, dir0.inode.ino(), &OsString::from("sdir1"))
                 .await
                 .expect("should exist");
             assert_inode_stat!(sdir1, InodeKind::Directory, ts, 0);
-            assert_eq!(sdir1.inode.full_key(), OsString::from(format!("{prefix}dir0/sdir1/")));
+            assert_eq!(&*sdir1.inode.full_key(), OsString::from(format!("{prefix}dir0/sdir1/")));
 
             let sdir2 = superblock
                 .lookup(&client, dir1.inode.ino(), &OsString::from("sdir2"))
                 .await
                 .expect("should exist");
             assert_inode_stat!(sdir2, InodeKind::Directory, ts, 0);
-            assert_eq!(sdir2.inode.full_key(), OsString::from(format!("{prefix}dir1/sdir2/")));
+            assert_eq!(&*sdir2.inode.full_key(), OsString::from(format!("{prefix}dir1/sdir2/")));
 
             let sdir3 = superblock
                 .lookup(&client, dir1.inode.ino(), &OsString::from("sdir3"))
                 .await
                 .expect("should exist");
             assert_inode_stat!(sdir3, InodeKind::Directory, ts, 0);
-            assert_eq!(sdir3.inode.full_key(), OsString::from(format!("{prefix}dir1/sdir3/")));
+            assert_eq!(&*sdir3.inode.full_key(), OsString::from(format!("{prefix}dir1/sdir3/")));
 
             for (dir, sdir, ino, n) in &[
                 (0, 0, sdir0.inode.ino(), 3),
@@ -1273,14 +1764,14 @@ mod tests {
                         .expect("inode should exist");
                     // Grab last modified time according to mock S3
                     let modified_time = client
-                        .head_object(bucket, file.inode.full_key())
+                        .head_object(bucket, &file.inode.full_key())
                         .await
                         .expect("object should exist")
                         .object
                         .last_modified;
                     assert_inode_stat!(file, InodeKind::File, modified_time, object_size);
                     assert_eq!(
-                        file.inode.full_key(),
+                        &*file.inode.full_key(),
                         OsString::from(format!("{prefix}dir{dir}/sdir{sdir}/file{i}.txt"))
                     );
                 }
@@ -1333,6 +1824,7 @@ mod tests {
                     ..Default::default()
                 },
                 s3_personality: S3Personality::Standard,
+                ..Default::default()
             },
         );
 
@@ -1388,6 +1880,7 @@ mod tests {
                     ..Default::default()
                 },
                 s3_personality: S3Personality::Standard,
+                ..Default::default()
             },
         );
 
@@ -1424,6 +1917,101 @@ mod tests {
         }
     }
 
+    #[test_case(true; "cached")]
+    #[test_case(false; "expired")]
+    #[tokio::test]
+    async fn test_persistent_metadata_cache(cached: bool) {
+        let bucket = "test_bucket";
+        let client_config = MockClientConfig {
+            bucket: bucket.to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+
+        let keys = &["file0.txt", "sdir0/file0.txt", "sdir0/file1.txt"];
+        let last_modified = OffsetDateTime::UNIX_EPOCH + Duration::days(30);
+        for key in keys {
+            let mut obj = MockObject::constant(0xaa, 30, ETag::for_tests());
+            obj.set_last_modified(last_modified);
+            client.add_object(key, obj);
+        }
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let new_superblock = |ttl| {
+            let metadata_cache = PersistentMetadataCache::new(cache_dir.path(), Default::default());
+            Superblock::new(
+                bucket,
+                &Default::default(),
+                SuperblockConfig {
+                    cache_config: CacheConfig {
+                        serve_lookup_from_cache: true,
+                        dir_ttl: ttl,
+                        file_ttl: ttl,
+                        ..Default::default()
+                    },
+                    persistent_metadata_cache: Some(Arc::new(metadata_cache)),
+                    ..Default::default()
+                },
+            )
+        };
+
+        // Populate the cache with a listing of the root directory, and lookups in a subdirectory
+        let ttl = std::time::Duration::from_secs(60 * 60 * 24 * 7);
+        let superblock = new_superblock(ttl);
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        dir_handle.remember(&entries[1]);
+        let sdir0 = entries[1].inode.ino();
+        superblock
+            .lookup(&client, sdir0, "file0.txt".as_ref())
+            .await
+            .expect("should exist");
+        superblock
+            .lookup(&client, sdir0, "missing".as_ref())
+            .await
+            .expect_err("should not exist");
+
+        for key in keys {
+            client.remove_object(key);
+        }
+        client.add_object("sdir0/missing", MockObject::constant(0xaa, 30, ETag::for_tests()));
+
+        // A new superblock serves the entries from the persistent cache, unless they expired
+        let ttl = if cached { ttl } else { std::time::Duration::ZERO };
+        let superblock = new_superblock(ttl);
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        let names = entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>();
+        let sdir0 = superblock.lookup(&client, FUSE_ROOT_INODE, "sdir0".as_ref()).await;
+        if !cached {
+            assert_eq!(names, &["sdir0"]);
+            let sdir0 = sdir0.expect("should exist");
+            superblock
+                .lookup(&client, sdir0.inode.ino(), "file0.txt".as_ref())
+                .await
+                .expect_err("entry should have expired, and not be found in S3");
+            return;
+        }
+        assert_eq!(names, &["file0.txt", "sdir0"]);
+        assert_inode_stat!(entries[0], InodeKind::File, last_modified, 30);
+        let file0 = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file0.txt".as_ref())
+            .await
+            .expect("should be served from the listing");
+        assert_eq!(file0.stat.size, 30);
+        let sdir0 = sdir0.expect("should be served from the listing");
+        assert_eq!(sdir0.inode.kind(), InodeKind::Directory);
+        superblock
+            .lookup(&client, sdir0.inode.ino(), "file0.txt".as_ref())
+            .await
+            .expect("should be served from the lookup");
+        superblock
+            .lookup(&client, sdir0.inode.ino(), "missing".as_ref())
+            .await
+            .expect_err("negative lookup should be served from the cache");
+    }
+
     #[test_case(""; "unprefixed")]
     #[test_case("test_prefix/"; "prefixed")]
     #[tokio::test]
@@ -1795,6 +2383,58 @@ mod tests {
         }
     }
 
+    #[test_case("", 4; "unprefixed")]
+    #[test_case("test_prefix/", 4; "prefixed")]
+    #[test_case("test_prefix/", 64; "one partition per boundary")]
+    #[tokio::test]
+    async fn test_readdir_parallel(prefix: &str, list_concurrency: usize) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+
+        // Names around the boundaries between partitions, including objects and directories of
+        // the same name
+        let mut names = vec![];
+        for first in ['-', '0', '9', 'E', 'F', 'U', 'V', '_', 'j', 'k', 'z', '~'] {
+            names.push(first.to_string());
+            names.push(format!("{first}{}", char::MAX));
+            for i in 0..5 {
+                names.push(format!("{first}file{i}"));
+            }
+        }
+        let mut keys: Vec<_> = names.iter().map(|name| format!("{prefix}{name}")).collect();
+        keys.extend(["E/file", "F/file", "V/file", "k/file"].map(|key| format!("{prefix}{key}")));
+        for key in &keys {
+            client.add_object(key, MockObject::constant(0xaa, 30, ETag::for_tests()));
+        }
+
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let mut listings = vec![];
+        for list_concurrency in [1, list_concurrency] {
+            let superblock = Superblock::new(
+                "test_bucket",
+                &prefix,
+                SuperblockConfig {
+                    list_concurrency,
+                    ..Default::default()
+                },
+            );
+            let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 3).await.unwrap();
+            let entries = dir_handle.collect(&client).await.unwrap();
+            let entries: Vec<_> = entries.iter().map(|l| l.inode.name().to_owned()).collect();
+            listings.push(entries);
+        }
+
+        // Which of an object and a directory of the same name is shown depends on how they are
+        // split across pages, so only compare the names
+        names.sort();
+        assert_eq!(listings[0], names);
+        assert_eq!(listings[1], names);
+    }
+
     #[test_case(""; "unprefixed")]
     #[test_case("test_prefix/"; "prefixed")]
     #[tokio::test]
@@ -2007,44 +2647,643 @@ mod tests {
         }
     }
 
-    #[test_case(""; "no subdirectory")]
-    #[test_case("subdir/"; "with subdirectory")]
     #[tokio::test]
-    async fn test_lookup_directory_overlap(subdir: &str) {
+    async fn test_invalidations() {
         let client_config = MockClientConfig {
             bucket: "test_bucket".to_string(),
             part_size: 1024 * 1024,
             ..Default::default()
         };
         let client = Arc::new(MockClient::new(client_config));
-        // In this test the `/` delimiter comes back to bite us. `dir-1/` comes before `dir/` in
-        // lexicographical order (- is ASCII 0x2d, / is ASCII 0x2f), so `dir-1` will be the first
-        // common prefix when we do ListObjects with prefix = 'dir'. But `dir` comes before `dir-1`
-        // in lexicographical order, so `dir` will be the first common prefix when we do ListObjects
-        // with prefix = ''.
-        client.add_object(
-            &format!("dir/{subdir}file1.txt"),
-            MockObject::constant(0xaa, 30, ETag::for_tests()),
-        );
         client.add_object(
-            &format!("dir-1/{subdir}file1.txt"),
-            MockObject::constant(0xaa, 30, ETag::for_tests()),
+            "file1.txt",
+            MockObject::constant(0xaa, 30, ETag::from_str("etag1").unwrap()),
         );
+        client.add_object("file2.txt", MockObject::constant(0xaa, 30, ETag::for_tests()));
 
-        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
-
-        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
-        let entries = dir_handle.collect(&client).await.unwrap();
-        assert_eq!(
-            entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>(),
-            &["dir", "dir-1"]
+        let (sender, receiver) = async_channel::unbounded();
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                invalidations: Some(sender),
+                ..Default::default()
+            },
         );
 
-        let dir = superblock
-            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+        let file1 = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file1.txt".as_ref())
             .await
             .unwrap();
-        assert_eq!(dir.inode.full_key(), OsString::from("dir/"));
+        let _file2 = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file2.txt".as_ref())
+            .await
+            .unwrap();
+
+        // Nothing changed remotely
+        let _file1 = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file1.txt".as_ref())
+            .await
+            .unwrap();
+        assert!(receiver.is_empty());
+
+        // A new ETag replaces the inode
+        client.add_object(
+            "file1.txt",
+            MockObject::constant(0xbb, 30, ETag::from_str("etag2").unwrap()),
+        );
+        let new_file1 = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file1.txt".as_ref())
+            .await
+            .unwrap();
+        assert_ne!(file1.inode.ino(), new_file1.inode.ino());
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            Invalidation::Entry {
+                parent: FUSE_ROOT_INODE,
+                name: "file1.txt".to_owned(),
+            }
+        );
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            Invalidation::Inode { ino: file1.inode.ino() }
+        );
+
+        // A removed key removes the entry
+        client.remove_object("file2.txt");
+        superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file2.txt".as_ref())
+            .await
+            .expect_err("file2.txt was removed");
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            Invalidation::Entry {
+                parent: FUSE_ROOT_INODE,
+                name: "file2.txt".to_owned(),
+            }
+        );
+        assert!(receiver.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_key_with_empty_components() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        client.add_object("a//b", MockObject::constant(0xaa, 30, ETag::from_str("etag1").unwrap()));
+
+        let ttl = std::time::Duration::from_secs(600);
+        let (sender, receiver) = async_channel::unbounded();
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                cache_config: CacheConfig {
+                    serve_lookup_from_cache: true,
+                    file_ttl: ttl,
+                    dir_ttl: ttl,
+                    ..Default::default()
+                },
+                invalidations: Some(sender),
+                escape_names: true,
+                ..Default::default()
+            },
+        );
+
+        let a = superblock.lookup(&client, FUSE_ROOT_INODE, "a".as_ref()).await.unwrap();
+        let empty = superblock.lookup(&client, a.inode.ino(), "".as_ref()).await.unwrap();
+        let b = superblock
+            .lookup(&client, empty.inode.ino(), "b".as_ref())
+            .await
+            .unwrap();
+
+        // The event reaches the object below the empty component
+        superblock.invalidate_key("a//b");
+        let invalidations: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(
+            invalidations,
+            vec![
+                Invalidation::Entry {
+                    parent: empty.inode.ino(),
+                    name: "b".to_owned(),
+                },
+                Invalidation::Inode { ino: b.inode.ino() },
+            ]
+        );
+
+        // The marker object `a//` is the directory shown as `a/%`
+        superblock.invalidate_key("a//");
+        let invalidations: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(
+            invalidations,
+            vec![
+                Invalidation::Entry {
+                    parent: a.inode.ino(),
+                    name: "%".to_owned(),
+                },
+                Invalidation::Inode { ino: empty.inode.ino() },
+            ]
+        );
+    }
+
+    #[test_case(false; "from bucket")]
+    #[test_case(true; "from children")]
+    #[tokio::test]
+    async fn test_resolve_shortened_name(listed: bool) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let long_name = "a".repeat(300);
+        let other_long_name = format!("{long_name}b");
+        for key in [format!("dir/{long_name}"), format!("dir/{other_long_name}/file")] {
+            client.add_object(&key, MockObject::constant(0xaa, 30, ETag::for_tests()));
+        }
+
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                escape_names: true,
+                ..Default::default()
+            },
+        );
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .unwrap();
+        if listed {
+            let dir_handle = superblock.readdir(&client, dir.inode.ino(), 10).await.unwrap();
+            let entries = dir_handle.collect(&client).await.unwrap();
+            assert_eq!(entries.len(), 2);
+        }
+
+        for name in [&long_name, &other_long_name] {
+            let shortened = escape_name(name);
+            let resolved = superblock
+                .resolve_shortened_name(&client, dir.inode.ino(), &shortened)
+                .await
+                .unwrap();
+            assert_eq!(&resolved, name);
+        }
+
+        let missing = escape_name(&format!("{long_name}c")).into_owned();
+        let err = superblock
+            .resolve_shortened_name(&client, dir.inode.ino(), &missing)
+            .await
+            .expect_err("should not exist");
+        assert!(matches!(err, InodeError::FileDoesNotExist(_, _)));
+
+        let err = superblock
+            .resolve_shortened_name(&client, dir.inode.ino(), "plain")
+            .await
+            .expect_err("not a shortened name");
+        assert!(matches!(err, InodeError::InvalidFileName(_)));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_key() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        client.add_object(
+            "dir/file.txt",
+            MockObject::constant(0xaa, 30, ETag::from_str("etag1").unwrap()),
+        );
+
+        let ttl = std::time::Duration::from_secs(600);
+        let (sender, receiver) = async_channel::unbounded();
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                cache_config: CacheConfig {
+                    serve_lookup_from_cache: true,
+                    file_ttl: ttl,
+                    dir_ttl: ttl,
+                    ..Default::default()
+                },
+                invalidations: Some(sender),
+                ..Default::default()
+            },
+        );
+
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .unwrap();
+        let file = superblock
+            .lookup(&client, dir.inode.ino(), "file.txt".as_ref())
+            .await
+            .unwrap();
+        superblock
+            .lookup(&client, FUSE_ROOT_INODE, "new.txt".as_ref())
+            .await
+            .expect_err("new.txt does not exist yet");
+
+        // Remote changes are not visible while cached
+        client.add_object("new.txt", MockObject::constant(0xaa, 30, ETag::for_tests()));
+        client.add_object(
+            "dir/file.txt",
+            MockObject::constant(0xbb, 30, ETag::from_str("etag2").unwrap()),
+        );
+        superblock
+            .lookup(&client, FUSE_ROOT_INODE, "new.txt".as_ref())
+            .await
+            .expect_err("new.txt is in the negative cache");
+        let cached_file = superblock
+            .lookup(&client, dir.inode.ino(), "file.txt".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(cached_file.inode.ino(), file.inode.ino());
+
+        // The new object is found once its negative cache entry is invalidated
+        superblock.invalidate_key("new.txt");
+        superblock
+            .lookup(&client, FUSE_ROOT_INODE, "new.txt".as_ref())
+            .await
+            .expect("new.txt should be found after invalidation");
+
+        // The modified object gets a new inode, and the kernel is told about the old one
+        while receiver.try_recv().is_ok() {}
+        superblock.invalidate_key("dir/file.txt");
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            Invalidation::Entry {
+                parent: dir.inode.ino(),
+                name: "file.txt".to_owned(),
+            }
+        );
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            Invalidation::Inode { ino: file.inode.ino() }
+        );
+        let new_file = superblock
+            .lookup(&client, dir.inode.ino(), "file.txt".as_ref())
+            .await
+            .unwrap();
+        assert_ne!(new_file.inode.ino(), file.inode.ino());
+    }
+
+    #[tokio::test]
+    async fn test_negative_ttl_without_metadata_caching() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        client.add_object(
+            "existing.txt",
+            MockObject::constant(0xaa, 30, ETag::from_str("etag1").unwrap()),
+        );
+
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                cache_config: CacheConfig {
+                    negative_ttl: Some(std::time::Duration::from_secs(600)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        let existing = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "existing.txt".as_ref())
+            .await
+            .expect("should exist");
+        superblock
+            .lookup(&client, FUSE_ROOT_INODE, "missing.txt".as_ref())
+            .await
+            .expect_err("should not exist");
+
+        client.add_object("missing.txt", MockObject::constant(0xaa, 30, ETag::for_tests()));
+        client.add_object(
+            "existing.txt",
+            MockObject::constant(0xbb, 30, ETag::from_str("etag2").unwrap()),
+        );
+
+        superblock
+            .lookup(&client, FUSE_ROOT_INODE, "missing.txt".as_ref())
+            .await
+            .expect_err("negative entry should still be valid in the cache");
+        let lookup = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "existing.txt".as_ref())
+            .await
+            .expect("should exist");
+        assert_ne!(
+            lookup.inode.ino(),
+            existing.inode.ino(),
+            "existing file should be looked up in S3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evict_unreferenced_inodes() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        for i in 0..4 {
+            client.add_object(&format!("file{i}"), MockObject::constant(0xaa, 30, ETag::for_tests()));
+        }
+
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                cache_config: CacheConfig {
+                    unreferenced_inode_cache_size: 2,
+                    ..CacheConfig::new(TimeToLive::Indefinite)
+                },
+                ..Default::default()
+            },
+        );
+        let children = || {
+            let root = superblock.inner.get(FUSE_ROOT_INODE).unwrap();
+            let state = root.get_inode_state().unwrap();
+            let InodeKindData::Directory { children, .. } = &state.kind_data else {
+                panic!("root is always a directory");
+            };
+            let mut names: Vec<_> = children.keys().cloned().collect();
+            names.sort();
+            names
+        };
+
+        // Lookups that are not returned to the kernel leave the inodes unreferenced
+        let mut inos = Vec::new();
+        for i in 0..3 {
+            let lookup = superblock
+                .inner
+                .lookup_by_name(&client, FUSE_ROOT_INODE, format!("file{i}").as_ref(), true)
+                .await
+                .expect("should exist");
+            inos.push(lookup.inode.ino());
+        }
+        assert_eq!(children(), vec!["file1", "file2"]);
+
+        // Referenced inodes are not evicted
+        superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file3".as_ref())
+            .await
+            .expect("should exist");
+        assert_eq!(children(), vec!["file2", "file3"]);
+
+        let lookup = superblock
+            .inner
+            .lookup_by_name(&client, FUSE_ROOT_INODE, "file0".as_ref(), true)
+            .await
+            .expect("should exist");
+        assert_ne!(lookup.inode.ino(), inos[0], "evicted inode should be recreated");
+        assert_eq!(children(), vec!["file0", "file2", "file3"]);
+    }
+
+    #[test]
+    fn test_remember_concurrent_with_eviction() {
+        const THREADS: usize = 4;
+        const FILES: usize = 200;
+
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        for thread in 0..THREADS {
+            for i in 0..FILES {
+                client.add_object(
+                    &format!("file{thread}-{i}"),
+                    MockObject::constant(0xaa, 30, ETag::for_tests()),
+                );
+            }
+        }
+
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                cache_config: CacheConfig {
+                    unreferenced_inode_cache_size: 1,
+                    ..CacheConfig::new(TimeToLive::Indefinite)
+                },
+                ..Default::default()
+            },
+        );
+
+        // Every lookup creates an unreferenced inode and evicts another thread's, possibly just
+        // before it is remembered. Remembered inodes must stay visible in their parent.
+        std::thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let (client, superblock) = (&client, &superblock);
+                scope.spawn(move || {
+                    for i in 0..FILES {
+                        let name = format!("file{thread}-{i}");
+                        let lookup = futures::executor::block_on(superblock.inner.lookup_by_name(
+                            client,
+                            FUSE_ROOT_INODE,
+                            name.as_ref(),
+                            true,
+                        ))
+                        .expect("should exist");
+                        superblock.inner.remember(&lookup.inode);
+
+                        let root = superblock.inner.get(FUSE_ROOT_INODE).unwrap();
+                        let state = root.get_inode_state().unwrap();
+                        let InodeKindData::Directory { children, .. } = &state.kind_data else {
+                            panic!("root is always a directory");
+                        };
+                        let child = children.get(&name).expect("remembered inode should not be evicted");
+                        assert_eq!(child.ino(), lookup.inode.ino());
+                    }
+                });
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_prefix_ttl_override() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        for key in ["static/file.txt", "other.txt"] {
+            client.add_object(key, MockObject::constant(0xaa, 30, ETag::from_str("etag1").unwrap()));
+        }
+
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                cache_config: CacheConfig {
+                    prefix_ttls: vec![PrefixTtl {
+                        prefix: "static/".to_owned(),
+                        ttl: TimeToLive::Indefinite,
+                    }],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "static".as_ref())
+            .await
+            .expect("should exist");
+        let file = superblock
+            .lookup(&client, dir.inode.ino(), "file.txt".as_ref())
+            .await
+            .expect("should exist");
+        let other = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "other.txt".as_ref())
+            .await
+            .expect("should exist");
+
+        for key in ["static/file.txt", "other.txt"] {
+            client.add_object(key, MockObject::constant(0xbb, 30, ETag::from_str("etag2").unwrap()));
+        }
+
+        let lookup = superblock
+            .lookup(&client, dir.inode.ino(), "file.txt".as_ref())
+            .await
+            .expect("should exist");
+        assert_eq!(
+            lookup.inode.ino(),
+            file.inode.ino(),
+            "file under the prefix should be served from the cache"
+        );
+        let lookup = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "other.txt".as_ref())
+            .await
+            .expect("should exist");
+        assert_ne!(
+            lookup.inode.ino(),
+            other.inode.ino(),
+            "file outside of the prefix should be looked up in S3"
+        );
+    }
+
+    #[test_case(""; "no subdirectory")]
+    #[test_case("subdir/"; "with subdirectory")]
+    #[tokio::test]
+    async fn test_lookup_directory_overlap(subdir: &str) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        // In this test the `/` delimiter comes back to bite us. `dir-1/` comes before `dir/` in
+        // lexicographical order (- is ASCII 0x2d, / is ASCII 0x2f), so `dir-1` will be the first
+        // common prefix when we do ListObjects with prefix = 'dir'. But `dir` comes before `dir-1`
+        // in lexicographical order, so `dir` will be the first common prefix when we do ListObjects
+        // with prefix = ''.
+        client.add_object(
+            &format!("dir/{subdir}file1.txt"),
+            MockObject::constant(0xaa, 30, ETag::for_tests()),
+        );
+        client.add_object(
+            &format!("dir-1/{subdir}file1.txt"),
+            MockObject::constant(0xaa, 30, ETag::for_tests()),
+        );
+
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
+
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>(),
+            &["dir", "dir-1"]
+        );
+
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(&*dir.inode.full_key(), OsString::from("dir/"));
+    }
+
+    #[test_case(""; "unprefixed")]
+    #[test_case("test_prefix/"; "prefixed")]
+    #[tokio::test]
+    async fn test_shadowed_file_suffix(prefix: &str) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+
+        // `a` is shadowed by a directory, `c` too but another object has its alternate name, and
+        // `d` is not shadowed
+        for key in ["a", "a/b", "b.x", "c", "c/", "c.__file__", "d"] {
+            client.add_object(
+                &format!("{prefix}{key}"),
+                MockObject::constant(0xaa, key.len(), ETag::for_tests()),
+            );
+        }
+
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let superblock = Superblock::new(
+            "test_bucket",
+            &prefix,
+            SuperblockConfig {
+                shadowed_file_suffix: Some(".__file__".to_owned()),
+                ..Default::default()
+            },
+        );
+
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 10).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        let names: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.inode.name(), entry.inode.kind()))
+            .collect();
+        assert_eq!(
+            names,
+            &[
+                ("a", InodeKind::Directory),
+                ("a.__file__", InodeKind::File),
+                ("b.x", InodeKind::File),
+                ("c", InodeKind::Directory),
+                ("c.__file__", InodeKind::File),
+                ("d", InodeKind::File),
+            ]
+        );
+        assert_eq!(*entries[1].inode.full_key(), format!("{prefix}a"));
+        assert_eq!(*entries[4].inode.full_key(), format!("{prefix}c.__file__"));
+
+        // Lookups of the alternate names map back to the shadowed objects
+        for (name, key, size) in [("a.__file__", "a", 1), ("c.__file__", "c.__file__", 10)] {
+            let lookup = superblock
+                .lookup(&client, FUSE_ROOT_INODE, name.as_ref())
+                .await
+                .unwrap();
+            assert_eq!(*lookup.inode.full_key(), format!("{prefix}{key}"));
+            assert_eq!(lookup.stat.size, size);
+        }
+        let lookup = superblock.lookup(&client, FUSE_ROOT_INODE, "d.__file__".as_ref()).await;
+        assert!(matches!(lookup, Err(InodeError::FileDoesNotExist(_, _))));
+
+        // Once `a` is no longer shadowed, it is only visible under its own name
+        client.remove_object(&format!("{prefix}a/b"));
+        let lookup = superblock.lookup(&client, FUSE_ROOT_INODE, "a.__file__".as_ref()).await;
+        assert!(matches!(lookup, Err(InodeError::FileDoesNotExist(_, _))));
+        let lookup = superblock.lookup(&client, FUSE_ROOT_INODE, "a".as_ref()).await.unwrap();
+        assert_eq!(lookup.inode.kind(), InodeKind::File);
     }
 
     #[tokio::test]
@@ -0,0 +1,176 @@
+//! A reversible mapping from the components of S3 keys to file names.
+//!
+//! Some keys have components that can't be file names: they are empty (like in `a//b`), or are `.`
+//! or `..`. By default, these keys are hidden. When escaping names, these components are instead
+//! percent-encoded, so that `a//b` appears as `a/%/b` and `a/./b` as `a/%2E/b`. For the mapping
+//! to be reversible, `%` is also encoded in every name, as `%25`, along with NUL.
+//!
+//! Names longer than the file system limit of [NAME_MAX] bytes are shortened to the start of their
+//! escaped name, followed by `%~` and a hash of the full name. Escaped names never contain `%~`,
+//! so shortened names don't collide with them. The hash can't be reversed, so a shortened name is
+//! resolved by searching the directory for the names that start with [shortened_name_prefix] and
+//! shorten to the same name.
+
+use std::borrow::Cow;
+
+use sha2::{Digest, Sha256};
+
+/// The longest file name, in bytes, that file systems accept.
+pub const NAME_MAX: usize = 255;
+
+/// The name of an empty key component, which can't be represented as an escaped string.
+const EMPTY_NAME: &str = "%";
+
+/// Separates the start of a shortened name from the hash of the full name.
+const HASH_SEPARATOR: &str = "%~";
+
+/// Number of hexadecimal digits of the hash in shortened names.
+const HASH_LEN: usize = 16;
+
+/// Map a component of a key to a valid file name.
+pub fn escape_name(name: &str) -> Cow<'_, str> {
+    let escaped = match name {
+        "" => Cow::Borrowed(EMPTY_NAME),
+        "." => Cow::Borrowed("%2E"),
+        ".." => Cow::Borrowed("%2E%2E"),
+        _ if name.contains(['%', '\0']) => Cow::Owned(name.replace('%', "%25").replace('\0', "%00")),
+        _ => Cow::Borrowed(name),
+    };
+    if escaped.len() <= NAME_MAX {
+        return escaped;
+    }
+
+    // Escape the start of the name one character at a time, so that no escape sequence is cut
+    let max_prefix_len = NAME_MAX - HASH_SEPARATOR.len() - HASH_LEN;
+    let mut shortened = String::with_capacity(NAME_MAX);
+    let mut buf = [0; 4];
+    for c in name.chars() {
+        let escaped_char = match c {
+            '%' => "%25",
+            '\0' => "%00",
+            c => c.encode_utf8(&mut buf),
+        };
+        if shortened.len() + escaped_char.len() > max_prefix_len {
+            break;
+        }
+        shortened.push_str(escaped_char);
+    }
+    let hash = Sha256::digest(name.as_bytes());
+    shortened.push_str(HASH_SEPARATOR);
+    shortened.push_str(&hex::encode(&hash[..HASH_LEN / 2]));
+    Cow::Owned(shortened)
+}
+
+/// Map a file name back to the component of a key it was escaped from, or [None] if the name
+/// is not the result of [escape_name], or is a shortened name that needs to be resolved.
+pub fn unescape_name(name: &str) -> Option<Cow<'_, str>> {
+    if name == EMPTY_NAME {
+        return Some(Cow::Borrowed(""));
+    }
+    if !name.contains('%') {
+        // Only "." and ".." would need escaping, but they are never looked up by name
+        return (name != "." && name != "..").then_some(Cow::Borrowed(name));
+    }
+
+    let unescaped = percent_decode(name)?;
+
+    // Each key component has a single escaped name, so that names differing only in their escaping
+    // (like `%2e` and `%2E`, or `%41` and `A`) don't refer to the same key.
+    (escape_name(&unescaped) == name).then_some(Cow::Owned(unescaped))
+}
+
+/// If `name` is a shortened name, return the start of the key components it can be the shortened
+/// name of. Whether a component does shorten to `name` is then checked with [escape_name].
+pub fn shortened_name_prefix(name: &str) -> Option<String> {
+    let (prefix, hash) = name.rsplit_once(HASH_SEPARATOR)?;
+    let is_hash = hash.len() == HASH_LEN && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if !is_hash {
+        return None;
+    }
+    percent_decode(prefix)
+}
+
+/// Decode the `%XX` escape sequences in `name`.
+fn percent_decode(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok())?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("a", "a"; "plain")]
+    #[test_case("", "%"; "empty")]
+    #[test_case(".", "%2E"; "dot")]
+    #[test_case("..", "%2E%2E"; "dot dot")]
+    #[test_case("...", "..."; "three dots")]
+    #[test_case("100%", "100%25"; "percent")]
+    #[test_case("%2E", "%252E"; "escaped dot")]
+    #[test_case("a\0b", "a%00b"; "nul")]
+    fn test_escape_round_trip(name: &str, escaped: &str) {
+        assert_eq!(escape_name(name), escaped);
+        assert_eq!(unescape_name(escaped).as_deref(), Some(name));
+    }
+
+    #[test_case("."; "dot")]
+    #[test_case(".."; "dot dot")]
+    #[test_case("100%"; "unescaped percent")]
+    #[test_case("%2"; "truncated escape")]
+    #[test_case("%zz"; "invalid hex")]
+    #[test_case("%2e"; "lowercase escape")]
+    #[test_case("%41"; "unnecessary escape")]
+    #[test_case("%2Ex"; "escaped dot prefix")]
+    #[test_case("%FF"; "invalid utf8")]
+    fn test_unescape_invalid(name: &str) {
+        assert_eq!(unescape_name(name), None);
+        assert_eq!(shortened_name_prefix(name), None);
+    }
+
+    #[test_case("a"; "plain")]
+    #[test_case("%"; "percent")]
+    #[test_case("é"; "multi-byte")]
+    fn test_shorten_long_name(repeated: &str) {
+        let name = repeated.repeat(300);
+        let shortened = escape_name(&name);
+        assert!(shortened.len() <= NAME_MAX, "{shortened:?} is too long");
+        assert!(shortened.contains("%~"));
+        assert_eq!(unescape_name(&shortened), None, "shortened names can't be unescaped");
+
+        // The shortened name starts with an escaped prefix of the name
+        let prefix = shortened_name_prefix(&shortened).expect("should be a shortened name");
+        assert!(name.starts_with(&prefix));
+        assert!(prefix.len() > 50);
+
+        // Names with the same prefix are shortened to different names
+        let other = format!("{}x", &name[..name.len() - repeated.len()]);
+        assert_ne!(escape_name(&other), shortened);
+
+        // Names that fit don't change
+        let name = repeated.repeat(NAME_MAX / escape_name(repeated).len());
+        assert_eq!(unescape_name(&escape_name(&name)).as_deref(), Some(name.as_str()));
+    }
+
+    #[test_case("abc%~0123456789abcdef", Some("abc"); "shortened")]
+    #[test_case("%25%~0123456789abcdef", Some("%"); "escaped prefix")]
+    #[test_case("abc%~0123456789ABCDEF", None; "uppercase hash")]
+    #[test_case("abc%~0123", None; "short hash")]
+    #[test_case("abc%~0123456789abcdefg", None; "long hash")]
+    #[test_case("%zz%~0123456789abcdef", None; "invalid prefix")]
+    fn test_shortened_name_prefix(name: &str, prefix: Option<&str>) {
+        assert_eq!(shortened_name_prefix(name).as_deref(), prefix);
+    }
+}
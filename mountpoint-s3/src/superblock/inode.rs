@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fmt::{Debug, Display};
@@ -13,6 +14,7 @@ use tracing::trace;
 use crate::sync::atomic::{AtomicBool, Ordering};
 use crate::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
+use super::readdir::CachedListing;
 use super::{Expiry, InodeError, SuperblockInner};
 
 pub type InodeNo = u64;
@@ -32,9 +34,7 @@ struct InodeInner {
     // Immutable inode state -- any changes to these requires a new inode
     ino: InodeNo,
     parent: InodeNo,
-    name: String,
-    // TODO deduplicate keys by string interning or something -- many keys will have common prefixes
-    full_key: String,
+    key: InodeKey,
     kind: InodeKind,
     checksum: Crc32c,
 
@@ -61,15 +61,45 @@ impl Inode {
     }
 
     pub fn name(&self) -> &str {
-        &self.inner.name
+        self.inner.key.name()
     }
 
     pub fn kind(&self) -> InodeKind {
         self.inner.kind
     }
 
-    pub fn full_key(&self) -> &str {
-        &self.inner.full_key
+    /// The full key of this inode. Only directories store their full key, so for files it is
+    /// assembled from the key of their parent and their name.
+    pub fn full_key(&self) -> Cow<'_, str> {
+        self.inner.key.full_key()
+    }
+
+    /// The key of the child `name` of this directory, which shares the key of this directory.
+    pub(super) fn child_key(&self, name: &str, kind: InodeKind) -> InodeKey {
+        match &self.inner.key {
+            InodeKey::Directory { full_key, .. } => InodeKey::new(full_key.clone(), name, kind),
+            InodeKey::File { .. } | InodeKey::ShadowedFile { .. } => unreachable!("only directories have children"),
+        }
+    }
+
+    /// The key of a file in this directory that is shadowed by a directory of the same name, and
+    /// so is visible as `name`, of which the name in the key is the first `key_name_len` bytes.
+    pub(super) fn shadowed_child_key(&self, name: &str, key_name_len: usize) -> InodeKey {
+        let InodeKey::Directory { full_key, .. } = &self.inner.key else {
+            unreachable!("only directories have children");
+        };
+        debug_assert!(name.is_char_boundary(key_name_len));
+        InodeKey::ShadowedFile {
+            parent_key: full_key.clone(),
+            name: name.into(),
+            key_name_len,
+        }
+    }
+
+    /// Whether this inode is a file shadowed by a directory of the same name, visible under an
+    /// alternate name.
+    pub(super) fn is_shadowed_file(&self) -> bool {
+        matches!(self.inner.key, InodeKey::ShadowedFile { .. })
     }
 
     /// Increment lookup count for [Inode] by 1, returning the new value.
@@ -104,6 +134,15 @@ impl Inode {
         *lookup_count
     }
 
+    /// Whether the kernel holds no reference to this inode and it has no local changes, so that it
+    /// can be dropped and recreated from the remote when needed again.
+    ///
+    /// Locks [InodeState] for reading.
+    pub(super) fn is_unreferenced_remote(&self) -> bool {
+        let state = self.inner.sync.read().unwrap();
+        state.lookup_count == 0 && state.write_status == WriteStatus::Remote
+    }
+
     pub fn is_remote(&self) -> Result<bool, InodeError> {
         let state = self.get_inode_state()?;
         Ok(state.write_status == WriteStatus::Remote)
@@ -133,21 +172,13 @@ impl Inode {
     }
 
     /// Create a new inode.
-    pub(super) fn new(
-        ino: InodeNo,
-        parent: InodeNo,
-        name: String,
-        full_key: String,
-        kind: InodeKind,
-        state: InodeState,
-    ) -> Self {
-        let checksum = Self::compute_checksum(ino, &full_key);
+    pub(super) fn new(ino: InodeNo, parent: InodeNo, key: InodeKey, kind: InodeKind, state: InodeState) -> Self {
+        let checksum = Self::compute_checksum(ino, &key);
         let sync = RwLock::new(state);
         let inner = InodeInner {
             ino,
             parent,
-            name,
-            full_key,
+            key,
             kind,
             checksum,
             sync,
@@ -160,8 +191,7 @@ impl Inode {
         Self::new(
             ROOT_INODE_NO,
             ROOT_INODE_NO,
-            String::new(),
-            prefix,
+            InodeKey::root(prefix),
             InodeKind::Directory,
             InodeState {
                 // The root inode never expires because there's no remote to consult for its
@@ -177,7 +207,7 @@ impl Inode {
 
     /// Verify [Inode] has the expected inode number and the inode content is valid for its checksum.
     pub fn verify_inode(&self, expected_ino: InodeNo) -> Result<(), InodeError> {
-        let computed = Self::compute_checksum(self.ino(), self.full_key());
+        let computed = Self::compute_checksum(self.ino(), &self.inner.key);
         if computed == self.inner.checksum && self.ino() == expected_ino {
             Ok(())
         } else {
@@ -188,7 +218,7 @@ impl Inode {
     /// Verify [Inode] has the expected inode number, expected parent inode number,
     /// and the inode's content is valid for its checksum.
     pub fn verify_child(&self, expected_parent: InodeNo, expected_name: &str) -> Result<(), InodeError> {
-        let computed = Self::compute_checksum(self.ino(), self.full_key());
+        let computed = Self::compute_checksum(self.ino(), &self.inner.key);
         if computed == self.inner.checksum && self.parent() == expected_parent && self.name() == expected_name {
             Ok(())
         } else {
@@ -196,10 +226,13 @@ impl Inode {
         }
     }
 
-    fn compute_checksum(ino: InodeNo, full_key: &str) -> Crc32c {
+    fn compute_checksum(ino: InodeNo, key: &InodeKey) -> Crc32c {
         let mut hasher = crc32c::Hasher::new();
         hasher.update(ino.to_be_bytes().as_ref());
-        hasher.update(full_key.as_bytes());
+        // Same as the checksum of the full key, without assembling it
+        for part in key.parts() {
+            hasher.update(part.as_bytes());
+        }
         hasher.finalize()
     }
 
@@ -209,6 +242,91 @@ impl Inode {
     }
 }
 
+/// The key of an [Inode].
+///
+/// Directories own their full key, and share it with their children, which only store their own
+/// name. This way the common prefixes of keys are stored once per directory rather than once per
+/// inode, which dominates the memory usage of large trees with long keys.
+#[derive(Debug, Clone)]
+pub(super) enum InodeKey {
+    /// The full key of a directory (ending in `/`, unless it is the root and the mount has no
+    /// prefix), and where its name starts in it.
+    Directory { full_key: Arc<str>, name_start: usize },
+    /// The full key of the parent directory of a file, and the name of the file.
+    File { parent_key: Arc<str>, name: Box<str> },
+    /// A file shadowed by a directory of the same name, which is visible under its name followed
+    /// by a suffix. Only the first `key_name_len` bytes of the name are part of the key.
+    ShadowedFile {
+        parent_key: Arc<str>,
+        name: Box<str>,
+        key_name_len: usize,
+    },
+}
+
+impl InodeKey {
+    /// The key of the child `name` in the directory with the full key `parent_key`.
+    pub(super) fn new(parent_key: Arc<str>, name: &str, kind: InodeKind) -> Self {
+        debug_assert!(parent_key.is_empty() || parent_key.ends_with('/'));
+        match kind {
+            InodeKind::File => Self::File {
+                parent_key,
+                name: name.into(),
+            },
+            InodeKind::Directory => Self::Directory {
+                full_key: format!("{parent_key}{name}/").into(),
+                name_start: parent_key.len(),
+            },
+        }
+    }
+
+    /// The key of the root directory of a mount at `prefix`, whose name is empty.
+    fn root(prefix: String) -> Self {
+        Self::Directory {
+            name_start: prefix.len(),
+            full_key: prefix.into(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            // The root directory starts its name at the end of its key
+            Self::Directory { full_key, name_start } => full_key
+                .get(*name_start..full_key.len().saturating_sub(1))
+                .unwrap_or_default(),
+            Self::File { name, .. } | Self::ShadowedFile { name, .. } => name,
+        }
+    }
+
+    fn full_key(&self) -> Cow<'_, str> {
+        let [first, second] = self.parts();
+        if second.is_empty() {
+            Cow::Borrowed(first)
+        } else {
+            Cow::Owned(format!("{first}{second}"))
+        }
+    }
+
+    /// The parts that make up the full key, in order.
+    fn parts(&self) -> [&str; 2] {
+        match self {
+            Self::Directory { full_key, .. } => [full_key, ""],
+            Self::File { parent_key, name } => [parent_key, name],
+            Self::ShadowedFile {
+                parent_key,
+                name,
+                key_name_len,
+            } => [parent_key, &name[..*key_name_len]],
+        }
+    }
+
+    pub(super) fn kind(&self) -> InodeKind {
+        match self {
+            Self::Directory { .. } => InodeKind::Directory,
+            Self::File { .. } | Self::ShadowedFile { .. } => InodeKind::File,
+        }
+    }
+}
+
 pub fn valid_inode_name<T: AsRef<OsStr>>(name: T) -> bool {
     let name = name.as_ref();
     // Names cannot be empty
@@ -302,6 +420,14 @@ pub(super) enum InodeKindData {
 
         /// True if this directory has been deleted (`rmdir`) from its parent
         deleted: bool,
+
+        /// The last complete listing of this directory from S3, to serve `readdir` without
+        /// listing it again while it is valid.
+        listing: Option<CachedListing>,
+
+        /// Incremented every time [listing](Self::Directory::listing) is invalidated, so that
+        /// listings started before a change are not cached.
+        listing_generation: u64,
     },
 }
 
@@ -313,9 +439,24 @@ impl InodeKindData {
                 children: Default::default(),
                 writing_children: Default::default(),
                 deleted: false,
+                listing: None,
+                listing_generation: 0,
             },
         }
     }
+
+    /// Forget the cached listing of a directory, after its content changed.
+    pub fn invalidate_listing(&mut self) {
+        if let Self::Directory {
+            listing,
+            listing_generation,
+            ..
+        } = self
+        {
+            *listing = None;
+            *listing_generation += 1;
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -515,9 +656,15 @@ impl WriteHandle {
                             writing_children.remove(&child_ino);
                         }
                     }
+                    // The new object (or directory) is not in listings from before the upload
+                    ancestor_state.kind_data.invalidate_listing();
                     ancestor_state.write_status = WriteStatus::Remote;
                 }
 
+                if let Some(cache) = &self.inner.config.persistent_metadata_cache {
+                    cache.invalidate(&self.inode.full_key());
+                }
+
                 Ok(())
             }
             _ => Err(InodeError::InodeInvalidWriteStatus(self.inode.err())),
@@ -563,6 +710,44 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_inode_key() {
+        let root = InodeKey::root("prefix/".to_owned());
+        assert_eq!(root.name(), "");
+        assert_eq!(root.full_key(), "prefix/");
+
+        let InodeKey::Directory { full_key, .. } = &root else {
+            panic!("root is a directory");
+        };
+        let dir = InodeKey::new(full_key.clone(), "dir", InodeKind::Directory);
+        assert_eq!(dir.name(), "dir");
+        assert_eq!(dir.full_key(), "prefix/dir/");
+
+        let InodeKey::Directory { full_key, .. } = &dir else {
+            panic!("dir is a directory");
+        };
+        let file = InodeKey::new(full_key.clone(), "file", InodeKind::File);
+        assert_eq!(file.name(), "file");
+        assert_eq!(file.full_key(), "prefix/dir/file");
+
+        // The checksum covers the full key, however it is stored
+        let mut hasher = crc32c::Hasher::new();
+        hasher.update(42u64.to_be_bytes().as_ref());
+        hasher.update(b"prefix/dir/file");
+        assert_eq!(Inode::compute_checksum(42, &file), hasher.finalize());
+
+        assert_eq!(InodeKey::root(String::new()).name(), "");
+
+        let shadowed = InodeKey::ShadowedFile {
+            parent_key: full_key.clone(),
+            name: "file.__file__".into(),
+            key_name_len: 4,
+        };
+        assert_eq!(shadowed.name(), "file.__file__");
+        assert_eq!(shadowed.full_key(), "prefix/dir/file");
+        assert_eq!(shadowed.kind(), InodeKind::File);
+    }
+
     #[tokio::test]
     async fn test_forget() {
         let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
@@ -571,8 +756,7 @@ mod tests {
         let inode = Inode::new(
             ino,
             ROOT_INODE_NO,
-            inode_name.to_owned(),
-            inode_name.to_owned(),
+            InodeKey::new("".into(), inode_name, InodeKind::File),
             InodeKind::File,
             InodeState {
                 write_status: WriteStatus::Remote,
@@ -703,8 +887,7 @@ mod tests {
             inner: Arc::new(InodeInner {
                 ino: 42,
                 parent: parent_ino,
-                name: file_name.into(),
-                full_key: file_name.into(),
+                key: InodeKey::new("".into(), file_name, InodeKind::File),
                 kind: InodeKind::File,
                 checksum: bad_checksum,
                 sync: RwLock::new(InodeState {
@@ -763,8 +946,7 @@ mod tests {
             inner: Arc::new(InodeInner {
                 ino,
                 parent: ROOT_INODE_NO,
-                name: inode_name.to_owned(),
-                full_key: inode_name.to_owned(),
+                key: InodeKey::new("".into(), inode_name, InodeKind::File),
                 kind: InodeKind::File,
                 checksum,
                 sync: RwLock::new(InodeState {
@@ -7,15 +7,15 @@ use super::{expiry::Expiry, InodeNo};
 use crate::sync::RwLock;
 
 /// A caches for negative lookups.
-/// Maintains a bounded set of (parent_ino, child_name) entries that expire after a fixed time.
+/// Maintains a bounded set of (parent_ino, child_name) entries that expire after the TTL they were
+/// inserted with.
 #[derive(Debug)]
 pub struct NegativeCache {
-    /// Holds keys in expiration order from oldest to newest.
+    /// Holds keys in insertion order from oldest to newest, which is also their expiration order
+    /// when they are inserted with the same TTL.
     map: RwLock<LinkedHashMap<Key, Expiry>>,
     /// Upper bound for the cache.
     max_size: usize,
-    /// TTL of a key at insertion.
-    ttl: Duration,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq)]
@@ -25,11 +25,10 @@ struct Key {
 }
 
 impl NegativeCache {
-    pub fn new(max_size: usize, ttl: Duration) -> Self {
+    pub fn new(max_size: usize) -> Self {
         Self {
             map: RwLock::new(Default::default()),
             max_size,
-            ttl,
         }
     }
 
@@ -74,12 +73,12 @@ impl NegativeCache {
         .record(start.elapsed().as_micros() as f64);
     }
 
-    /// Insert an entry into the cache. If the entry already existed,
+    /// Insert an entry into the cache, expiring after `ttl`. If the entry already existed,
     /// update its TTL.
     /// Upon insertion, remove entries that exceed the cache limit or
     /// that have already expired.
-    pub fn insert(&self, parent_ino: InodeNo, child_name: &str) {
-        let expiry = Expiry::from_now(self.ttl);
+    pub fn insert(&self, parent_ino: InodeNo, child_name: &str, ttl: Duration) {
+        let expiry = Expiry::from_now(ttl);
         let key = Key {
             parent_ino,
             child_name: child_name.to_owned(),
@@ -121,9 +120,10 @@ mod tests {
 
     #[test]
     fn test_contains() {
-        let cache = NegativeCache::new(100, Duration::from_secs(60));
+        let ttl = Duration::from_secs(60);
+        let cache = NegativeCache::new(100);
 
-        cache.insert(1, "child1");
+        cache.insert(1, "child1", ttl);
         assert!(cache.contains(1, "child1"));
         assert!(!cache.contains(1, "child2"));
         assert!(!cache.contains(2, "child1"));
@@ -131,16 +131,17 @@ mod tests {
 
     #[test]
     fn test_insert() {
-        let cache = NegativeCache::new(100, Duration::from_secs(60));
+        let ttl = Duration::from_secs(60);
+        let cache = NegativeCache::new(100);
 
-        cache.insert(1, "child1");
+        cache.insert(1, "child1", ttl);
         assert!(cache.contains(1, "child1"));
 
-        cache.insert(1, "child2");
+        cache.insert(1, "child2", ttl);
         assert!(cache.contains(1, "child2"));
         assert!(cache.contains(1, "child1"));
 
-        cache.insert(2, "child1");
+        cache.insert(2, "child1", ttl);
         assert!(cache.contains(2, "child1"));
         assert!(cache.contains(1, "child2"));
         assert!(cache.contains(1, "child1"));
@@ -148,11 +149,12 @@ mod tests {
 
     #[test]
     fn test_remove() {
-        let cache = NegativeCache::new(100, Duration::from_secs(60));
+        let ttl = Duration::from_secs(60);
+        let cache = NegativeCache::new(100);
 
-        cache.insert(1, "child1");
-        cache.insert(1, "child2");
-        cache.insert(2, "child1");
+        cache.insert(1, "child1", ttl);
+        cache.insert(1, "child2", ttl);
+        cache.insert(2, "child1", ttl);
         assert!(cache.contains(1, "child1"));
         assert!(cache.contains(1, "child2"));
         assert!(cache.contains(2, "child1"));
@@ -165,16 +167,17 @@ mod tests {
 
     #[test]
     fn test_max_size() {
-        let cache = NegativeCache::new(2, Duration::from_secs(60));
+        let ttl = Duration::from_secs(60);
+        let cache = NegativeCache::new(2);
 
-        cache.insert(1, "child1");
+        cache.insert(1, "child1", ttl);
         assert!(cache.contains(1, "child1"));
 
-        cache.insert(1, "child2");
+        cache.insert(1, "child2", ttl);
         assert!(cache.contains(1, "child2"));
         assert!(cache.contains(1, "child1"));
 
-        cache.insert(1, "child3");
+        cache.insert(1, "child3", ttl);
         assert!(cache.contains(1, "child3"));
         assert!(cache.contains(1, "child2"));
         assert!(!cache.contains(1, "child1"));
@@ -182,31 +185,33 @@ mod tests {
 
     #[test]
     fn test_expiration() {
-        let cache = NegativeCache::new(100, Duration::from_millis(1));
+        let ttl = Duration::from_millis(1);
+        let cache = NegativeCache::new(100);
 
-        cache.insert(1, "child1");
+        cache.insert(1, "child1", ttl);
         sleep(Duration::from_millis(2));
         assert!(!cache.contains(1, "child1"));
     }
 
     #[test]
     fn test_insert_after_expiry() {
-        let cache = NegativeCache::new(100, Duration::from_millis(50));
+        let ttl = Duration::from_millis(50);
+        let cache = NegativeCache::new(100);
 
-        cache.insert(1, "child1");
+        cache.insert(1, "child1", ttl);
         sleep(Duration::from_millis(100));
         assert!(!cache.contains(1, "child1"));
 
-        cache.insert(1, "child1");
+        cache.insert(1, "child1", ttl);
         assert!(cache.contains(1, "child1"));
     }
 
     #[test]
     fn test_insert_resets_ttl() {
         let ttl = Duration::from_millis(100);
-        let cache = NegativeCache::new(100, ttl);
+        let cache = NegativeCache::new(100);
 
-        cache.insert(1, "child1");
+        cache.insert(1, "child1", ttl);
         let inserted_time = Instant::now();
         // Wait for about half ttl, verify the entry has not expirted yet.
         let half_ttl = ttl / 2;
@@ -216,7 +221,7 @@ mod tests {
         assert!(Instant::now().saturating_duration_since(inserted_time) < ttl);
         assert!(cache.contains(1, "child1"));
 
-        cache.insert(1, "child1");
+        cache.insert(1, "child1", ttl);
         let reset_time = Instant::now();
         // Wait until the initial insert has expired, but the reset has not.
         while Instant::now().saturating_duration_since(inserted_time) <= ttl {
@@ -225,4 +230,15 @@ mod tests {
         assert!(Instant::now().saturating_duration_since(reset_time) < ttl);
         assert!(cache.contains(1, "child1"));
     }
+
+    #[test]
+    fn test_insert_with_different_ttls() {
+        let cache = NegativeCache::new(100);
+
+        cache.insert(1, "short", Duration::from_millis(1));
+        cache.insert(1, "long", Duration::from_secs(60));
+        sleep(Duration::from_millis(2));
+        assert!(!cache.contains(1, "short"));
+        assert!(cache.contains(1, "long"));
+    }
 }
@@ -0,0 +1,646 @@
+//! On-disk cache of directory listings and lookups, kept across mounts.
+//!
+//! Without it, every mount starts with an empty [Superblock](super::Superblock), and needs to list
+//! every directory again. The [PersistentMetadataCache] stores the results of complete ListObjectsV2
+//! listings and of lookups in a directory, one file per S3 prefix or key, so that a new mount can
+//! serve them without any request to S3 while they are younger than the metadata TTL.
+//!
+//! The cache is best effort: entries that can't be read or written are treated as missing.
+//!
+//! Entries are removed when they are older than the longest metadata TTL, and the oldest entries
+//! are removed when the total size of the cache exceeds its limit. Both are checked at mount, and
+//! the size again whenever writing an entry takes the cache over its limit.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use mountpoint_s3_client::types::{ObjectInfo, RestoreStatus};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tracing::{debug, trace, warn};
+
+use crate::sync::atomic::{AtomicU64, Ordering};
+
+use super::expiry::Expiry;
+use super::{InodeKind, InodeStat, RemoteLookup};
+
+/// Name of the directory created under the parent cache directory.
+const METADATA_CACHE_DIR_NAME: &str = "mountpoint-metadata";
+
+/// Version of the on-disk format of the entries.
+const METADATA_CACHE_VERSION: &str = "V1";
+
+/// Index where hashed directory names are split to avoid FS-specific limits.
+const HASHED_DIR_SPLIT_INDEX: usize = 2;
+
+/// Temporary files older than this are left over from a writer that failed to rename them.
+const STALE_TEMP_FILE_AGE: Duration = Duration::from_secs(60);
+
+/// On-disk cache of directory listings and lookups, see the [module documentation](self).
+#[derive(Debug)]
+pub struct PersistentMetadataCache {
+    directory: PathBuf,
+    config: PersistentMetadataCacheConfig,
+    /// Counter to give unique names to the temporary files entries are written to
+    temp_file_counter: AtomicU64,
+    /// Estimated total size of the entries, measured again by every sweep
+    size: AtomicU64,
+    /// Held while sweeping, so that writers don't all sweep at once
+    sweep_lock: Mutex<()>,
+}
+
+/// Configuration for a [PersistentMetadataCache].
+#[derive(Debug, Clone)]
+pub struct PersistentMetadataCacheConfig {
+    /// Maximum total size of the entries, in bytes.
+    pub max_size: u64,
+    /// Age after which entries are no longer served, and are removed.
+    pub max_age: Duration,
+}
+
+impl Default for PersistentMetadataCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 64 * 1024 * 1024,
+            max_age: Duration::MAX,
+        }
+    }
+}
+
+/// Kinds of entries, stored in separate directories.
+#[derive(Debug, Clone, Copy)]
+enum Namespace {
+    /// Complete listing of the entries directly under an S3 prefix
+    Listing,
+    /// Result of looking up a single S3 key
+    Lookup,
+}
+
+impl Namespace {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Namespace::Listing => "listings",
+            Namespace::Lookup => "lookups",
+        }
+    }
+}
+
+/// A cached entry with the S3 key or prefix it is stored for, to detect hash collisions.
+#[derive(Serialize, Deserialize, Debug)]
+struct Record<T> {
+    key: String,
+    written_at: SystemTime,
+    value: T,
+}
+
+/// An entry of a cached directory listing.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(super) enum CachedEntry {
+    /// A common prefix, i.e. a subdirectory
+    Directory { name: String },
+    /// An object
+    File {
+        name: String,
+        size: u64,
+        last_modified_nanos: i128,
+        etag: String,
+        storage_class: Option<String>,
+        restore_status: Option<CachedRestoreStatus>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CachedRestoreStatus {
+    InProgress,
+    Restored { expiry: SystemTime },
+}
+
+/// A cached lookup of a file or directory.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(super) struct CachedLookup {
+    is_directory: bool,
+    size: usize,
+    mtime_nanos: i128,
+    etag: Option<String>,
+    is_readable: bool,
+}
+
+impl PersistentMetadataCache {
+    /// Create a new [PersistentMetadataCache] storing its entries in `directory`, which is created
+    /// if needed. Entries already in `directory` are reused, except for the expired ones and the
+    /// oldest ones over the size limit, which are removed.
+    pub fn new(directory: impl Into<PathBuf>, config: PersistentMetadataCacheConfig) -> Self {
+        let cache = Self {
+            directory: directory.into(),
+            config,
+            temp_file_counter: AtomicU64::new(0),
+            size: AtomicU64::new(0),
+            sweep_lock: Mutex::new(()),
+        };
+        cache.remove_other_versions();
+        cache.sweep(cache.config.max_size);
+        cache
+    }
+
+    /// Create a new [PersistentMetadataCache] in a subdirectory of `parent_path` specific to the
+    /// given cache key, which should identify the bucket.
+    pub fn new_from_parent_with_cache_key(
+        parent_path: impl AsRef<Path>,
+        cache_key: &OsStr,
+        config: PersistentMetadataCacheConfig,
+    ) -> Self {
+        let hashed_key = hex::encode(Sha256::digest(cache_key.as_bytes()));
+        Self::new(
+            parent_path.as_ref().join(METADATA_CACHE_DIR_NAME).join(hashed_key),
+            config,
+        )
+    }
+
+    /// Get the listing of the entries directly under `prefix`, and how long ago it was stored.
+    pub(super) fn get_listing(&self, prefix: &str) -> Option<(Vec<CachedEntry>, Duration)> {
+        self.read(Namespace::Listing, prefix)
+    }
+
+    /// Store the complete listing of the entries directly under `prefix`.
+    pub(super) fn put_listing(&self, prefix: &str, entries: Vec<CachedEntry>) {
+        trace!(
+            prefix,
+            count = entries.len(),
+            "storing listing in persistent metadata cache"
+        );
+        self.write(Namespace::Listing, prefix, entries);
+    }
+
+    /// Get the lookup of `key`, which is [None] if the key was not found, and how long ago it was stored.
+    pub(super) fn get_lookup(&self, key: &str) -> Option<(Option<CachedLookup>, Duration)> {
+        self.read(Namespace::Lookup, key)
+    }
+
+    /// Store the result of looking up `key`, including when the key was not found.
+    pub(super) fn put_lookup(&self, key: &str, lookup: Option<&RemoteLookup>) {
+        self.write(Namespace::Lookup, key, lookup.map(CachedLookup::from));
+    }
+
+    /// Remove the entries that may be affected by a change to the object `key`: its own lookup, and
+    /// the listings and lookups of every directory containing it.
+    pub(super) fn invalidate(&self, key: &str) {
+        trace!(key, "invalidating persistent metadata cache");
+        self.remove(Namespace::Lookup, key);
+        for (i, _) in key.match_indices('/').chain(std::iter::once((0, ""))) {
+            let (dir_key, prefix) = if i == 0 { ("", "") } else { (&key[..i], &key[..=i]) };
+            self.remove(Namespace::Listing, prefix);
+            if !dir_key.is_empty() {
+                self.remove(Namespace::Lookup, dir_key);
+            }
+        }
+    }
+
+    fn read<T: DeserializeOwned>(&self, namespace: Namespace, key: &str) -> Option<(T, Duration)> {
+        let path = self.path(namespace, key);
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return None,
+            Err(err) => {
+                warn!(?path, ?err, "unable to read persistent metadata cache entry");
+                return None;
+            }
+        };
+        let record: Record<T> = match bincode::deserialize(&content) {
+            Ok(record) => record,
+            Err(err) => {
+                warn!(?path, ?err, "invalid persistent metadata cache entry");
+                return None;
+            }
+        };
+        if record.key != key {
+            trace!(
+                ?path,
+                key,
+                stored_key = record.key,
+                "persistent metadata cache entry for another key"
+            );
+            return None;
+        }
+        // Entries written in the future (e.g. after a clock change) are considered new
+        let age = record.written_at.elapsed().unwrap_or_default();
+        if age >= self.config.max_age {
+            return None;
+        }
+        Some((record.value, age))
+    }
+
+    fn write<T: Serialize>(&self, namespace: Namespace, key: &str, value: T) {
+        let path = self.path(namespace, key);
+        let record = Record {
+            key: key.to_owned(),
+            written_at: SystemTime::now(),
+            value,
+        };
+        if let Err(err) = self.write_file(&path, &record) {
+            warn!(?path, ?err, "unable to write persistent metadata cache entry");
+            return;
+        }
+        if self.size.load(Ordering::Relaxed) > self.config.max_size {
+            // Leave some room, so that the next writes don't need to sweep again
+            self.sweep(self.config.max_size / 10 * 9);
+        }
+    }
+
+    /// Write the record to a temporary file, then rename it into place so that readers never see a
+    /// partially written entry.
+    fn write_file<T: Serialize>(&self, path: &Path, record: &Record<T>) -> std::io::Result<()> {
+        let parent = path.parent().expect("path should include the hashed key");
+        fs::DirBuilder::new().mode(0o700).recursive(true).create(parent)?;
+
+        let content = bincode::serialize(record).map_err(std::io::Error::other)?;
+        let temp_path = path.with_extension(format!(
+            "tmp{}-{}",
+            std::process::id(),
+            self.temp_file_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temp_path)
+            .and_then(|mut file| file.write_all(&content));
+        let replaced_size = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+        let result = result.and_then(|()| fs::rename(&temp_path, path));
+        match result {
+            Ok(()) => {
+                self.size.fetch_add(content.len() as u64, Ordering::Relaxed);
+                self.sub_size(replaced_size);
+            }
+            Err(_) => {
+                let _ = fs::remove_file(&temp_path);
+            }
+        }
+        result
+    }
+
+    fn remove(&self, namespace: Namespace, key: &str) {
+        let path = self.path(namespace, key);
+        let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                trace!(?path, "removed persistent metadata cache entry");
+                self.sub_size(size);
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => warn!(?path, ?err, "unable to remove persistent metadata cache entry"),
+        }
+    }
+
+    fn sub_size(&self, size: u64) {
+        let _ = self.size.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+            Some(total.saturating_sub(size))
+        });
+    }
+
+    /// Remove the expired entries and stale temporary files, then the oldest entries until the
+    /// total size is at most `target_size`. Does nothing if another sweep is in progress.
+    fn sweep(&self, target_size: u64) {
+        let Ok(_guard) = self.sweep_lock.try_lock() else {
+            return;
+        };
+        let now = SystemTime::now();
+        let mut entries = Vec::new();
+        let mut total_size = 0;
+        let mut expired = 0;
+        for namespace in [Namespace::Listing, Namespace::Lookup] {
+            let namespace_dir = self.directory.join(METADATA_CACHE_VERSION).join(namespace.as_str());
+            for path in Self::list_entry_files(&namespace_dir) {
+                let Ok(metadata) = fs::metadata(&path) else {
+                    continue;
+                };
+                // Entries are renamed into place once written, so their mtime is their write time
+                let age = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .unwrap_or_default();
+                let is_temp_file = path.extension().is_some();
+                if (is_temp_file && age >= STALE_TEMP_FILE_AGE) || (!is_temp_file && age >= self.config.max_age) {
+                    if fs::remove_file(&path).is_ok() {
+                        expired += 1;
+                    }
+                } else {
+                    total_size += metadata.len();
+                    if !is_temp_file {
+                        entries.push((age, metadata.len(), path));
+                    }
+                }
+            }
+        }
+
+        // Oldest first
+        entries.sort_unstable_by(|(age1, ..), (age2, ..)| age2.cmp(age1));
+        let mut evicted = 0;
+        for (_, size, path) in entries {
+            if total_size <= target_size {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    total_size -= size;
+                    evicted += 1;
+                }
+                Err(err) if err.kind() == ErrorKind::NotFound => total_size -= size,
+                Err(err) => warn!(?path, ?err, "unable to remove persistent metadata cache entry"),
+            }
+        }
+        self.size.store(total_size, Ordering::Relaxed);
+        metrics::counter!("metadata_cache.persistent_cache_expired").increment(expired);
+        metrics::counter!("metadata_cache.persistent_cache_evicted").increment(evicted);
+        debug!(expired, evicted, total_size, "swept persistent metadata cache");
+    }
+
+    /// The files in the hashed directories under `namespace_dir`, including temporary files.
+    fn list_entry_files(namespace_dir: &Path) -> impl Iterator<Item = PathBuf> {
+        fs::read_dir(namespace_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .flat_map(|hashed_dir| fs::read_dir(hashed_dir.path()).into_iter().flatten().flatten())
+            .map(|entry| entry.path())
+    }
+
+    /// Remove the entries stored in the format of other versions, which are never read.
+    fn remove_other_versions(&self) {
+        let Ok(versions) = fs::read_dir(&self.directory) else {
+            return;
+        };
+        for version in versions.flatten() {
+            if version.file_name() != METADATA_CACHE_VERSION {
+                let path = version.path();
+                if let Err(err) = fs::remove_dir_all(&path) {
+                    warn!(?path, ?err, "unable to remove old persistent metadata cache entries");
+                }
+            }
+        }
+    }
+
+    fn path(&self, namespace: Namespace, key: &str) -> PathBuf {
+        let hashed_key = hex::encode(Sha256::digest(key));
+        let (first, second) = hashed_key.split_at(HASHED_DIR_SPLIT_INDEX);
+
+        let mut path = self.directory.join(METADATA_CACHE_VERSION);
+        path.push(namespace.as_str());
+        path.push(first);
+        path.push(second);
+        path
+    }
+}
+
+impl CachedEntry {
+    pub(super) fn name(&self) -> &str {
+        match self {
+            CachedEntry::Directory { name } => name,
+            CachedEntry::File { name, .. } => name,
+        }
+    }
+
+    pub(super) fn from_object_info(name: &str, object_info: &ObjectInfo) -> Self {
+        CachedEntry::File {
+            name: name.to_owned(),
+            size: object_info.size,
+            last_modified_nanos: object_info.last_modified.unix_timestamp_nanos(),
+            etag: object_info.etag.clone(),
+            storage_class: object_info.storage_class.clone(),
+            restore_status: object_info.restore_status.map(|status| match status {
+                RestoreStatus::InProgress => CachedRestoreStatus::InProgress,
+                RestoreStatus::Restored { expiry } => CachedRestoreStatus::Restored { expiry },
+            }),
+        }
+    }
+
+    /// The object described by this entry, if it is a file in the directory `full_path`.
+    pub(super) fn to_object_info(&self, full_path: &str) -> Option<ObjectInfo> {
+        let CachedEntry::File {
+            name,
+            size,
+            last_modified_nanos,
+            etag,
+            storage_class,
+            restore_status,
+        } = self
+        else {
+            return None;
+        };
+        Some(ObjectInfo {
+            key: format!("{full_path}{name}"),
+            size: *size,
+            last_modified: OffsetDateTime::from_unix_timestamp_nanos(*last_modified_nanos).ok()?,
+            storage_class: storage_class.clone(),
+            restore_status: restore_status.map(|status| match status {
+                CachedRestoreStatus::InProgress => RestoreStatus::InProgress,
+                CachedRestoreStatus::Restored { expiry } => RestoreStatus::Restored { expiry },
+            }),
+            etag: etag.clone(),
+        })
+    }
+}
+
+impl CachedLookup {
+    /// The lookup this entry was created from, valid for the given duration.
+    pub(super) fn to_remote_lookup(&self, validity: Duration) -> Option<RemoteLookup> {
+        let mtime = OffsetDateTime::from_unix_timestamp_nanos(self.mtime_nanos).ok()?;
+        let kind = if self.is_directory {
+            InodeKind::Directory
+        } else {
+            InodeKind::File
+        };
+        let stat = InodeStat {
+            expiry: Expiry::from_now(validity),
+            size: self.size,
+            mtime,
+            ctime: mtime,
+            atime: mtime,
+            etag: self.etag.clone(),
+            is_readable: self.is_readable,
+        };
+        Some(RemoteLookup {
+            kind,
+            stat,
+            shadowed: false,
+        })
+    }
+
+    pub(super) fn kind(&self) -> InodeKind {
+        if self.is_directory {
+            InodeKind::Directory
+        } else {
+            InodeKind::File
+        }
+    }
+}
+
+impl From<&RemoteLookup> for CachedLookup {
+    fn from(lookup: &RemoteLookup) -> Self {
+        Self {
+            is_directory: lookup.kind == InodeKind::Directory,
+            size: lookup.stat.size,
+            mtime_nanos: lookup.stat.mtime.unix_timestamp_nanos(),
+            etag: lookup.stat.etag.clone(),
+            is_readable: lookup.stat.is_readable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = PersistentMetadataCache::new(directory.path(), Default::default());
+        assert!(cache.get_listing("dir/").is_none());
+
+        let object_info = ObjectInfo {
+            key: "dir/file".to_owned(),
+            size: 42,
+            last_modified: OffsetDateTime::UNIX_EPOCH,
+            storage_class: Some("GLACIER".to_owned()),
+            restore_status: Some(RestoreStatus::InProgress),
+            etag: "etag".to_owned(),
+        };
+        let entries = vec![
+            CachedEntry::Directory { name: "a".to_owned() },
+            CachedEntry::from_object_info("file", &object_info),
+        ];
+        cache.put_listing("dir/", entries.clone());
+
+        // A new instance reuses the entries
+        let cache = PersistentMetadataCache::new(directory.path(), Default::default());
+        let (cached, age) = cache.get_listing("dir/").expect("listing should be cached");
+        assert_eq!(cached, entries);
+        assert!(age < Duration::from_secs(60));
+        let cached_object = cached[1].to_object_info("dir/").unwrap();
+        assert_eq!(cached_object.key, object_info.key);
+        assert_eq!(cached_object.size, object_info.size);
+        assert!(matches!(cached_object.restore_status, Some(RestoreStatus::InProgress)));
+        assert!(cached[0].to_object_info("dir/").is_none());
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = PersistentMetadataCache::new(directory.path(), Default::default());
+        let lookup = RemoteLookup {
+            kind: InodeKind::Directory,
+            stat: InodeStat::for_directory(OffsetDateTime::UNIX_EPOCH, Duration::from_secs(1)),
+            shadowed: false,
+        };
+        for prefix in ["", "a/", "a/b/", "other/"] {
+            cache.put_listing(prefix, vec![]);
+        }
+        for key in ["a", "a/b", "a/b/c", "other"] {
+            cache.put_lookup(key, Some(&lookup));
+        }
+
+        cache.invalidate("a/b/c");
+        for prefix in ["", "a/", "a/b/"] {
+            assert!(
+                cache.get_listing(prefix).is_none(),
+                "listing of {prefix:?} should be removed"
+            );
+        }
+        for key in ["a", "a/b", "a/b/c"] {
+            assert!(cache.get_lookup(key).is_none(), "lookup of {key:?} should be removed");
+        }
+        assert!(cache.get_listing("other/").is_some());
+        let (cached, _) = cache.get_lookup("other").expect("lookup should be cached");
+        assert_eq!(cached.unwrap().kind(), InodeKind::Directory);
+
+        // Negative lookups are cached too
+        cache.put_lookup("other", None);
+        let (cached, _) = cache.get_lookup("other").expect("lookup should be cached");
+        assert!(cached.is_none());
+    }
+
+    #[test]
+    fn test_expired_entries_removed_at_mount() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = PersistentMetadataCacheConfig {
+            max_age: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let cache = PersistentMetadataCache::new(directory.path(), config.clone());
+        cache.put_listing("old/", vec![]);
+        cache.put_listing("new/", vec![]);
+        cache.put_lookup("missing", None);
+        let old_path = cache.path(Namespace::Listing, "old/");
+        let two_hours_ago = SystemTime::now() - Duration::from_secs(7200);
+        let set_modified = |path: &Path| {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(two_hours_ago)
+                .unwrap()
+        };
+        set_modified(&old_path);
+        set_modified(&cache.path(Namespace::Lookup, "missing"));
+        let stale_temp_path = old_path.with_extension("tmp1-1");
+        fs::write(&stale_temp_path, b"partial").unwrap();
+        set_modified(&stale_temp_path);
+        let other_version = directory.path().join("V0");
+        fs::create_dir(&other_version).unwrap();
+
+        let cache = PersistentMetadataCache::new(directory.path(), config);
+        assert!(!old_path.exists());
+        assert!(!cache.path(Namespace::Lookup, "missing").exists());
+        assert!(!stale_temp_path.exists());
+        assert!(!other_version.exists());
+        assert!(cache.get_listing("new/").is_some());
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries: Vec<_> = (0..10)
+            .map(|i| CachedEntry::Directory {
+                name: format!("directory-{i}"),
+            })
+            .collect();
+        let cache = PersistentMetadataCache::new(directory.path(), Default::default());
+        cache.put_listing("probe/", entries.clone());
+        let entry_size = fs::metadata(cache.path(Namespace::Listing, "probe/")).unwrap().len();
+        cache.remove(Namespace::Listing, "probe/");
+
+        let config = PersistentMetadataCacheConfig {
+            max_size: 10 * entry_size,
+            ..Default::default()
+        };
+        let cache = PersistentMetadataCache::new(directory.path(), config.clone());
+        for i in 0..25 {
+            cache.put_listing(&format!("dir{i}/"), entries.clone());
+        }
+        let total_size = |cache: &PersistentMetadataCache| {
+            PersistentMetadataCache::list_entry_files(&cache.directory.join(METADATA_CACHE_VERSION).join("listings"))
+                .map(|path| fs::metadata(path).unwrap().len())
+                .sum::<u64>()
+        };
+        assert!(total_size(&cache) <= config.max_size);
+        assert!(cache.get_listing("dir24/").is_some(), "newest entry should be kept");
+
+        // The limit also applies to the entries found at mount
+        let config = PersistentMetadataCacheConfig {
+            max_size: 2 * entry_size,
+            ..Default::default()
+        };
+        let cache = PersistentMetadataCache::new(directory.path(), config.clone());
+        assert!(total_size(&cache) <= config.max_size);
+        assert!(cache.get_listing("dir24/").is_some(), "newest entry should be kept");
+    }
+}
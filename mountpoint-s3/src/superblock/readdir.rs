@@ -35,7 +35,10 @@
 //!   depending on if the S3 implementation returns ordered or unordered list results.
 //! * [RemoteIter] is an iterator over [ReaddirEntry]s returned by paginated calls to ListObjectsV2.
 //!   Rather than directly streaming the entries out of the list call, it collects them in memory
-//!   and re-sorts them to handle point 3.
+//!   and re-sorts them to handle point 3. It can also record the entries, so that [ReaddirHandle]
+//!   caches complete listings in the directory inode as a [CachedListing], and in the
+//!   [PersistentMetadataCache] if configured. It can instead be created from the entries of a
+//!   cached listing or of a [Manifest](crate::manifest::Manifest), without calling ListObjectsV2.
 //! * A collection or iterator of [ReaddirEntry]s is built up and used by [ReaddirIter],
 //!   representing the local children of the directory.
 //!   These children are listed only once, at the start of the readdir operation, and so are a
@@ -43,17 +46,18 @@
 
 use std::cmp::Ordering;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
-use mountpoint_s3_client::types::ObjectInfo;
+use mountpoint_s3_client::error::{ListObjectsError, ObjectClientError};
+use mountpoint_s3_client::types::{ObjectClientResult, ObjectInfo};
 use mountpoint_s3_client::ObjectClient;
 use tracing::{error, trace, warn};
 
+use crate::manifest::ManifestEntry;
 use crate::sync::{Arc, AsyncMutex, Mutex};
 
-use super::{
-    inode::valid_inode_name, InodeError, InodeKind, InodeKindData, InodeNo, InodeStat, LookedUp, RemoteLookup,
-    SuperblockInner,
-};
+use super::persistent_cache::{CachedEntry, PersistentMetadataCache};
+use super::{InodeError, InodeKind, InodeKindData, InodeNo, InodeStat, LookedUp, RemoteLookup, SuperblockInner};
 
 /// Handle for an inflight directory listing
 #[derive(Debug)]
@@ -63,6 +67,21 @@ pub struct ReaddirHandle {
     parent_ino: InodeNo,
     iter: AsyncMutex<ReaddirIter>,
     readded: Mutex<Option<LookedUp>>,
+    /// S3 prefix of the directory
+    full_path: String,
+    /// Age of the listing, if it was loaded from a cache
+    cached_age: Option<Duration>,
+    /// When the listing started, and the generation of the directory's cached listing at the time
+    listing_started: (Instant, u64),
+}
+
+/// A complete listing of a directory from S3, cached in the directory inode.
+#[derive(Debug, Clone)]
+pub(super) struct CachedListing {
+    /// Remote entries of the directory, in the order they were listed
+    entries: Vec<ReaddirEntry>,
+    /// When the listing started
+    listed_at: Instant,
 }
 
 impl ReaddirHandle {
@@ -73,24 +92,33 @@ impl ReaddirHandle {
         full_path: String,
         page_size: usize,
     ) -> Result<Self, InodeError> {
-        let local_entries = {
+        let listing_started = Instant::now();
+        let (local_entries, cached_listing, listing_generation) = {
             let inode = inner.get(dir_ino)?;
             let kind_data = &inode.get_inode_state()?.kind_data;
-            let local_files = match kind_data {
+            let (local_files, cached_listing, listing_generation) = match kind_data {
                 InodeKindData::File { .. } => return Err(InodeError::NotADirectory(inode.err())),
-                InodeKindData::Directory { writing_children, .. } => writing_children.iter().map(|ino| {
-                    let inode = inner.get(*ino)?;
-                    let stat = inode.get_inode_state()?.stat.clone();
-                    Ok(ReaddirEntry::LocalInode {
-                        lookup: LookedUp { inode, stat },
-                    })
-                }),
+                InodeKindData::Directory {
+                    writing_children,
+                    listing,
+                    listing_generation,
+                    ..
+                } => {
+                    let local_files = writing_children.iter().map(|ino| {
+                        let inode = inner.get(*ino)?;
+                        let stat = inode.get_inode_state()?.stat.clone();
+                        Ok(ReaddirEntry::LocalInode {
+                            lookup: LookedUp { inode, stat },
+                        })
+                    });
+                    (local_files, listing.clone(), *listing_generation)
+                }
             };
 
             match local_files.collect::<Result<Vec<_>, _>>() {
                 Ok(mut new_results) => {
                     new_results.sort();
-                    new_results
+                    (new_results, cached_listing, listing_generation)
                 }
                 Err(e) => {
                     error!(error=?e, "readdir failed listing local files");
@@ -99,10 +127,66 @@ impl ReaddirHandle {
             }
         };
 
-        let iter = if inner.config.s3_personality.is_list_ordered() {
-            ReaddirIter::ordered(&inner.bucket, &full_path, page_size, local_entries.into())
+        let ordered = inner.config.s3_personality.is_list_ordered();
+        let cache_config = inner.config.cache_config.for_key(&full_path);
+        let listing_ttl = cache_config.dir_listing_ttl();
+        let mut cached_age = None;
+        let remote = if let Some(manifest) = &inner.config.manifest {
+            let entries = manifest.list(&full_path).into_iter().map(ReaddirEntry::from).collect();
+            RemoteIter::from_entries(&full_path, entries, ordered)
+        } else if let Some((entries, age)) = cached_listing
+            .map(|listing| (listing.entries, listing.listed_at.elapsed()))
+            .filter(|(_, age)| listing_ttl.is_some_and(|ttl| *age < ttl))
+        {
+            trace!(prefix = full_path, ?age, "listing served from cache");
+            metrics::counter!("metadata_cache.listing_cache_hit").increment(1);
+            cached_age = Some(age);
+            RemoteIter::from_entries(&full_path, entries, ordered)
+        } else if let Some((entries, age)) = inner
+            .config
+            .persistent_metadata_cache
+            .as_ref()
+            .zip(listing_ttl)
+            .and_then(|(cache, listing_ttl)| Self::persisted_listing(cache, &full_path, listing_ttl))
+        {
+            trace!(
+                prefix = full_path,
+                ?age,
+                "listing served from persistent metadata cache"
+            );
+            metrics::counter!("metadata_cache.persistent_cache_hit").increment(1);
+            cached_age = Some(age);
+            RemoteIter::from_entries(&full_path, entries, ordered)
         } else {
-            ReaddirIter::unordered(&inner.bucket, &full_path, page_size, local_entries.into())
+            if listing_ttl.is_some() {
+                metrics::counter!("metadata_cache.listing_cache_hit").increment(0);
+            }
+            if inner.config.persistent_metadata_cache.is_some() {
+                metrics::counter!("metadata_cache.persistent_cache_hit").increment(0);
+            }
+            let remote = if inner.config.list_concurrency > 1 {
+                RemoteIter::parallel(
+                    &inner.bucket,
+                    &full_path,
+                    page_size,
+                    ordered,
+                    inner.config.list_concurrency,
+                )
+            } else {
+                RemoteIter::new(&inner.bucket, &full_path, page_size, ordered)
+            };
+            if listing_ttl.is_some() || inner.config.persistent_metadata_cache.is_some() {
+                remote.record_listing()
+            } else {
+                remote
+            }
+        };
+
+        let iter = if ordered {
+            let shadowed_file_suffix = inner.config.shadowed_file_suffix.clone();
+            ReaddirIter::ordered(remote, local_entries.into(), shadowed_file_suffix)
+        } else {
+            ReaddirIter::unordered(remote, local_entries.into())
         };
 
         Ok(Self {
@@ -111,13 +195,33 @@ impl ReaddirHandle {
             parent_ino,
             iter: AsyncMutex::new(iter),
             readded: Default::default(),
+            full_path,
+            cached_age,
+            listing_started: (listing_started, listing_generation),
         })
     }
 
+    /// Load the listing of `full_path` from the persistent cache, if it is younger than `ttl`.
+    fn persisted_listing(
+        cache: &PersistentMetadataCache,
+        full_path: &str,
+        ttl: Duration,
+    ) -> Option<(Vec<ReaddirEntry>, Duration)> {
+        let (entries, age) = cache.get_listing(full_path)?;
+        if age >= ttl {
+            return None;
+        }
+        let entries = entries
+            .into_iter()
+            .map(|entry| ReaddirEntry::from_cached(entry, full_path))
+            .collect::<Option<_>>()?;
+        Some((entries, age))
+    }
+
     /// Return the next inode for the directory stream. If the stream is finished, returns
     /// `Ok(None)`. Does not increment the lookup count of the returned inodes: the caller
     /// is responsible for calling [`remember()`] if required.
-    pub async fn next<OC: ObjectClient>(&self, client: &OC) -> Result<Option<LookedUp>, InodeError> {
+    pub async fn next<OC: ObjectClient + Sync>(&self, client: &OC) -> Result<Option<LookedUp>, InodeError> {
         if let Some(readded) = self.readded.lock().unwrap().take() {
             return Ok(Some(readded));
         }
@@ -127,12 +231,18 @@ impl ReaddirHandle {
         loop {
             let next = {
                 let mut iter = self.iter.lock().await;
-                iter.next(client).await?
+                let next = iter.next(client).await?;
+                if next.is_none() {
+                    if let Some(listing) = iter.take_listing() {
+                        self.store_listing(listing);
+                    }
+                }
+                next
             };
 
             if let Some(next) = next {
                 // Short-circuit the update if we know it'll fail because the name is invalid
-                if !valid_inode_name(next.name()) {
+                if !self.inner.is_valid_name(next.name(), next.inode_kind()) {
                     warn!("{} has an invalid name and will be unavailable", next.description());
                 } else {
                     let lookup = self.instantiate_remote_inode(next)?;
@@ -144,6 +254,36 @@ impl ReaddirHandle {
         }
     }
 
+    /// Cache a complete listing of the directory from S3.
+    fn store_listing(&self, entries: Vec<ReaddirEntry>) {
+        if let Some(cache) = &self.inner.config.persistent_metadata_cache {
+            let cached_entries = entries.iter().filter_map(ReaddirEntry::to_cached).collect();
+            cache.put_listing(&self.full_path, cached_entries);
+        }
+        if !self.inner.config.cache_config.serve_lookup_from_cache {
+            return;
+        }
+        let Ok(dir) = self.inner.get(self.dir_ino) else {
+            return;
+        };
+        let Ok(mut state) = dir.get_mut_inode_state() else {
+            return;
+        };
+        let (listed_at, generation) = self.listing_started;
+        if let InodeKindData::Directory {
+            listing,
+            listing_generation,
+            ..
+        } = &mut state.kind_data
+        {
+            // Don't cache the listing if the directory changed while it was listed
+            if *listing_generation == generation {
+                trace!(prefix = self.full_path, count = entries.len(), "caching listing");
+                *listing = Some(CachedListing { entries, listed_at });
+            }
+        }
+    }
+
     /// Re-add an entry to the front of the queue if the consumer wasn't able to use it
     pub fn readd(&self, entry: LookedUp) {
         let old = self.readded.lock().unwrap().replace(entry);
@@ -163,30 +303,35 @@ impl ReaddirHandle {
 
     /// Create or update an inode for the given ReaddirEntry.
     fn instantiate_remote_inode(&self, entry: ReaddirEntry) -> Result<LookedUp, InodeError> {
+        // Entries from a cached listing are only valid for the remaining part of the TTLs
+        let validity = |ttl: Duration| ttl.saturating_sub(self.cached_age.unwrap_or_default());
+        let cache_config = self.inner.cache_config_for(&self.full_path, entry.name());
         let remote_lookup = match &entry {
             // If we made it this far with a local inode, we know there's nothing on the remote with
-            // the same name, because [LocalInode] is last in the ordering and so otherwise would
+            // the same name, because [LocalInode] sorts after remote entries and so otherwise would
             // have been deduplicated by now.
             ReaddirEntry::LocalInode { .. } => None,
             ReaddirEntry::RemotePrefix { .. } => {
-                let stat = InodeStat::for_directory(self.inner.mount_time, self.inner.config.cache_config.dir_ttl);
+                let stat = InodeStat::for_directory(self.inner.mount_time, validity(cache_config.dir_ttl));
                 Some(RemoteLookup {
                     stat,
                     kind: InodeKind::Directory,
+                    shadowed: false,
                 })
             }
-            ReaddirEntry::RemoteObject { object_info, .. } => {
+            ReaddirEntry::RemoteObject { object_info, .. } | ReaddirEntry::ShadowedObject { object_info, .. } => {
                 let stat = InodeStat::for_file(
                     object_info.size as usize,
                     object_info.last_modified,
                     Some(object_info.etag.clone()),
                     object_info.storage_class.clone(),
                     object_info.restore_status,
-                    self.inner.config.cache_config.file_ttl,
+                    validity(cache_config.file_ttl),
                 );
                 Some(RemoteLookup {
                     stat,
                     kind: InodeKind::File,
+                    shadowed: matches!(entry, ReaddirEntry::ShadowedObject { .. }),
                 })
             }
         };
@@ -194,7 +339,7 @@ impl ReaddirHandle {
     }
 
     #[cfg(test)]
-    pub(super) async fn collect<OC: ObjectClient>(&self, client: &OC) -> Result<Vec<LookedUp>, InodeError> {
+    pub(super) async fn collect<OC: ObjectClient + Sync>(&self, client: &OC) -> Result<Vec<LookedUp>, InodeError> {
         let mut result = vec![];
         while let Some(entry) = self.next(client).await? {
             result.push(entry);
@@ -204,12 +349,14 @@ impl ReaddirHandle {
 }
 
 /// A single entry in a readdir stream. Remote entries have not yet been converted to inodes -- that
-/// should be done lazily by the consumer of the entry.
+/// should be done lazily by the consumer of the entry. A shadowed object is a remote object
+/// shadowed by a directory of the same name, returned under its alternate name.
 #[derive(Debug, Clone)]
 enum ReaddirEntry {
     RemotePrefix { name: String },
     RemoteObject { name: String, object_info: ObjectInfo },
     LocalInode { lookup: LookedUp },
+    ShadowedObject { name: String, object_info: ObjectInfo },
 }
 
 // This looks a little silly but makes the [Ord] implementation for [ReaddirEntry] a bunch clearer
@@ -218,22 +365,65 @@ enum ReaddirEntryKind {
     RemotePrefix,
     RemoteObject,
     LocalInode,
+    ShadowedObject,
 }
 
 impl ReaddirEntry {
     fn name(&self) -> &str {
         match self {
             Self::RemotePrefix { name } => name,
-            Self::RemoteObject { name, .. } => name,
+            Self::RemoteObject { name, .. } | Self::ShadowedObject { name, .. } => name,
             Self::LocalInode { lookup } => lookup.inode.name(),
         }
     }
 
+    /// The name of the entry relative to the directory as ListObjectsV2 returns it, with a trailing
+    /// `/` for common prefixes.
+    fn listed_name(&self) -> String {
+        match self {
+            Self::RemotePrefix { name } => format!("{name}/"),
+            _ => self.name().to_owned(),
+        }
+    }
+
+    /// The kind of the inode for this entry.
+    fn inode_kind(&self) -> InodeKind {
+        match self {
+            Self::RemotePrefix { .. } => InodeKind::Directory,
+            Self::RemoteObject { .. } | Self::ShadowedObject { .. } => InodeKind::File,
+            Self::LocalInode { lookup } => lookup.inode.kind(),
+        }
+    }
+
     fn kind(&self) -> ReaddirEntryKind {
         match self {
             Self::RemotePrefix { .. } => ReaddirEntryKind::RemotePrefix,
             Self::RemoteObject { .. } => ReaddirEntryKind::RemoteObject,
             Self::LocalInode { .. } => ReaddirEntryKind::LocalInode,
+            Self::ShadowedObject { .. } => ReaddirEntryKind::ShadowedObject,
+        }
+    }
+
+    /// Convert a remote entry to be stored in the [PersistentMetadataCache].
+    fn to_cached(&self) -> Option<CachedEntry> {
+        match self {
+            Self::RemotePrefix { name } => Some(CachedEntry::Directory { name: name.clone() }),
+            Self::RemoteObject { name, object_info } => Some(CachedEntry::from_object_info(name, object_info)),
+            Self::LocalInode { .. } | Self::ShadowedObject { .. } => None,
+        }
+    }
+
+    /// Convert an entry of a cached listing of the directory `full_path`.
+    fn from_cached(entry: CachedEntry, full_path: &str) -> Option<Self> {
+        match entry.to_object_info(full_path) {
+            Some(object_info) => Some(Self::RemoteObject {
+                name: entry.name().to_owned(),
+                object_info,
+            }),
+            None => match entry {
+                CachedEntry::Directory { name } => Some(Self::RemotePrefix { name }),
+                CachedEntry::File { .. } => None,
+            },
         }
     }
 
@@ -253,6 +443,18 @@ impl ReaddirEntry {
                 };
                 format!("local {} '{}'", kind, lookup.inode.name())
             }
+            Self::ShadowedObject { name, object_info } => {
+                format!("shadowed file '{}' (full key {:?})", name, object_info.key)
+            }
+        }
+    }
+}
+
+impl From<ManifestEntry> for ReaddirEntry {
+    fn from(entry: ManifestEntry) -> Self {
+        match entry {
+            ManifestEntry::Directory { name } => Self::RemotePrefix { name },
+            ManifestEntry::Object { name, object_info } => Self::RemoteObject { name, object_info },
         }
     }
 }
@@ -272,7 +474,8 @@ impl PartialOrd for ReaddirEntry {
 }
 
 // We sort readdir entries by name, and then by kind. So if two entries have the same name, a remote
-// directory sorts before a remote object, which sorts before a local entry.
+// directory sorts before a remote object, which sorts before a local entry, which sorts before an
+// object shadowed by a directory and shown under that name.
 impl Ord for ReaddirEntry {
     fn cmp(&self, other: &Self) -> Ordering {
         self.name()
@@ -293,20 +496,32 @@ enum ReaddirIter {
 }
 
 impl ReaddirIter {
-    fn ordered(bucket: &str, full_path: &str, page_size: usize, local_entries: VecDeque<ReaddirEntry>) -> Self {
-        Self::Ordered(ordered::ReaddirIter::new(bucket, full_path, page_size, local_entries))
+    fn ordered(
+        remote: RemoteIter,
+        local_entries: VecDeque<ReaddirEntry>,
+        shadowed_file_suffix: Option<String>,
+    ) -> Self {
+        Self::Ordered(ordered::ReaddirIter::new(remote, local_entries, shadowed_file_suffix))
     }
 
-    fn unordered(bucket: &str, full_path: &str, page_size: usize, local_entries: VecDeque<ReaddirEntry>) -> Self {
-        Self::Unordered(unordered::ReaddirIter::new(bucket, full_path, page_size, local_entries))
+    fn unordered(remote: RemoteIter, local_entries: VecDeque<ReaddirEntry>) -> Self {
+        Self::Unordered(unordered::ReaddirIter::new(remote, local_entries))
     }
 
-    async fn next(&mut self, client: &impl ObjectClient) -> Result<Option<ReaddirEntry>, InodeError> {
+    async fn next(&mut self, client: &(impl ObjectClient + Sync)) -> Result<Option<ReaddirEntry>, InodeError> {
         match self {
             Self::Ordered(iter) => iter.next(client).await,
             Self::Unordered(iter) => iter.next(client).await,
         }
     }
+
+    /// The remote entries listed from S3, once the listing is complete, if they were recorded.
+    fn take_listing(&mut self) -> Option<Vec<ReaddirEntry>> {
+        match self {
+            Self::Ordered(iter) => iter.remote.take_listing(),
+            Self::Unordered(iter) => iter.remote.take_listing(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -317,11 +532,226 @@ enum RemoteIterState {
     Finished,
 }
 
+/// How many pages each partition of a parallel listing can list ahead of the entries returned.
+const MAX_PAGES_AHEAD: usize = 4;
+
+/// A range of keys listed by its own series of paginated ListObjects calls.
+#[derive(Debug)]
+struct ListPartition {
+    /// The partition contains the keys after this one
+    start_after: Option<String>,
+    /// The partition contains the keys up to and including this one
+    end: Option<String>,
+    state: RemoteIterState,
+    /// Entries listed but not yet returned by the [RemoteIter]
+    entries: Vec<ReaddirEntry>,
+}
+
+impl ListPartition {
+    fn new(start_after: Option<String>, end: Option<String>) -> Self {
+        Self {
+            start_after,
+            end,
+            state: RemoteIterState::InProgress(None),
+            entries: Vec::new(),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.state == RemoteIterState::Finished
+    }
+
+    /// Make the next ListObjects call for this partition. Returns the new entries and the next
+    /// state of the partition.
+    async fn list_page(
+        &self,
+        client: &(impl ObjectClient + Sync),
+        bucket: &str,
+        full_path: &str,
+        page_size: usize,
+    ) -> Result<(Vec<ReaddirEntry>, RemoteIterState), InodeError> {
+        let continuation_token = match &self.state {
+            RemoteIterState::Finished => return Ok((Vec::new(), RemoteIterState::Finished)),
+            RemoteIterState::InProgress(token) => token.as_deref(),
+        };
+
+        trace!(prefix=?full_path, start_after=?self.start_after, ?continuation_token, "continuing remote iter");
+
+        let result = match &self.start_after {
+            Some(start_after) => {
+                client
+                    .list_objects_after(bucket, start_after, continuation_token, "/", page_size, full_path)
+                    .await
+            }
+            None => {
+                client
+                    .list_objects(bucket, continuation_token, "/", page_size, full_path)
+                    .await
+            }
+        }
+        .map_err(|e| InodeError::client_error(e, "ListObjectsV2 failed", bucket, full_path))?;
+
+        let mut state = match result.next_continuation_token {
+            Some(token) => RemoteIterState::InProgress(Some(token)),
+            None => RemoteIterState::Finished,
+        };
+
+        // Results are in key order, so the partition is complete once its end is passed
+        let in_partition = |key: &str| self.end.as_deref().is_none_or(|end| key <= end);
+        let before = result.common_prefixes.len() + result.objects.len();
+
+        let prefixes = result
+            .common_prefixes
+            .into_iter()
+            .filter(|prefix| in_partition(prefix))
+            .map(|prefix| ReaddirEntry::RemotePrefix {
+                name: prefix[full_path.len()..prefix.len() - 1].to_owned(),
+            });
+
+        let objects = result
+            .objects
+            .into_iter()
+            .filter(|object_info| in_partition(&object_info.key))
+            .map(|object_info| ReaddirEntry::RemoteObject {
+                name: object_info.key[full_path.len()..].to_owned(),
+                object_info,
+            });
+
+        let entries = prefixes.chain(objects).collect::<Vec<_>>();
+        if entries.len() < before {
+            state = RemoteIterState::Finished;
+        }
+        Ok((entries, state))
+    }
+}
+
+/// Samples the keys of a directory with ListObjects calls returning a single entry, to find where
+/// to split them into [ListPartition]s.
+///
+/// Names often share a long prefix, like `part-00001` to `part-99999`, so the keys are split by the
+/// first two characters following the prefix shared by the names still to be listed. The
+/// characters used are those seen in the names of the first page, so that numbered names are split
+/// into partitions of similar sizes.
+struct BoundarySampler<'a, Client> {
+    client: &'a Client,
+    bucket: &'a str,
+    full_path: &'a str,
+}
+
+impl<Client: ObjectClient + Sync> BoundarySampler<'_, Client> {
+    /// Find up to `count - 1` keys splitting the keys after the name `last` into partitions, given
+    /// the `names` of the first page. Each key is the end of a partition and the start (exclusive)
+    /// of the next.
+    async fn sample(
+        &self,
+        names: &[String],
+        last: &str,
+        count: usize,
+    ) -> ObjectClientResult<Vec<String>, ListObjectsError, Client::ClientError> {
+        let last: Vec<char> = last.chars().collect();
+
+        // Find the longest prefix of `last` that all the following names share
+        let (mut shared, mut not_shared) = (0, last.len() + 1);
+        while shared + 1 < not_shared {
+            let len = (shared + not_shared) / 2;
+            let prefix: String = last[..len].iter().collect();
+            if self.has_names_after(&format!("{prefix}{}", char::MAX)).await? {
+                not_shared = len;
+            } else {
+                shared = len;
+            }
+        }
+        let prefix: String = last[..shared].iter().collect();
+
+        // Find the range of the characters following the prefix, looking for ASCII characters only
+        let first_char = last.get(shared).copied().unwrap_or(' ');
+        let last_char = if first_char >= '\x7f' || self.has_names_after(&format!("{prefix}\x7f{}", char::MAX)).await? {
+            char::MAX
+        } else {
+            let (mut low, mut high) = (first_char as u32, 0x7f);
+            while low < high {
+                let mid = (low + high) / 2;
+                let mid_char = char::from_u32(mid).expect("ASCII characters are valid");
+                if self
+                    .has_names_after(&format!("{prefix}{mid_char}{}", char::MAX))
+                    .await?
+                {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            char::from_u32(low).expect("ASCII characters are valid")
+        };
+
+        let mut alphabet: Vec<char> = names.iter().flat_map(|name| name.chars().skip(shared)).collect();
+        alphabet.sort_unstable();
+        alphabet.dedup();
+        let mut first_chars: Vec<char> = alphabet
+            .iter()
+            .copied()
+            .chain([first_char, last_char])
+            .filter(|c| (first_char..=last_char).contains(c))
+            .collect();
+        first_chars.sort_unstable();
+        first_chars.dedup();
+        let second_chars: Vec<Option<char>> = match alphabet.is_empty() {
+            true => vec![None],
+            false => alphabet.iter().copied().map(Some).collect(),
+        };
+
+        // Spread the boundaries evenly over the two characters following the prefix, as digits
+        let first_index = first_chars.partition_point(|c| *c < first_char);
+        let second_index = last
+            .get(shared + 1)
+            .map_or(0, |second| alphabet.partition_point(|c| c < second));
+        let low = first_index * second_chars.len() + second_index;
+        let total = first_chars.len() * second_chars.len() - low;
+        let mut boundaries: Vec<String> = Vec::new();
+        let last: String = last.into_iter().collect();
+        for i in 1..count {
+            let value = low + i * total / count;
+            let mut start = prefix.clone();
+            start.push(first_chars[value / second_chars.len()]);
+            start.extend(second_chars[value % second_chars.len()]);
+            // End the previous partition just before the names starting with `start`
+            let mut boundary: Vec<char> = start.chars().collect();
+            let last_start_char = boundary.pop().expect("start is not empty") as u32;
+            if let Some(before) = last_start_char.checked_sub(1) {
+                boundary.push(char::from_u32(before).unwrap_or('\u{d7ff}'));
+                boundary.push(char::MAX);
+            }
+            let boundary: String = boundary.into_iter().collect();
+            if boundary > last && boundaries.last().is_none_or(|previous| boundary > *previous) {
+                boundaries.push(boundary);
+            }
+        }
+        Ok(boundaries
+            .into_iter()
+            .map(|boundary| format!("{}{boundary}", self.full_path))
+            .collect())
+    }
+
+    /// Whether any entry follows the name `name` in the directory.
+    async fn has_names_after(&self, name: &str) -> ObjectClientResult<bool, ListObjectsError, Client::ClientError> {
+        let start_after = format!("{}{name}", self.full_path);
+        let result = self
+            .client
+            .list_objects_after(self.bucket, &start_after, None, "/", 1, self.full_path)
+            .await?;
+        Ok(!result.objects.is_empty() || !result.common_prefixes.is_empty())
+    }
+}
+
 /// An iterator over [ReaddirEntry]s returned by paginated ListObjects calls to S3.
 /// This iterator combines directories (common prefixes) and files (objects) into a single stream.
 ///
 /// If the S3 implementation returns ordered results, this iterator will re-sort the stream to
 /// account for common prefixes not being in lexicographic order (see the module comment).
+///
+/// For S3 implementations that return ordered results, the keys can be split into several
+/// [ListPartition]s that are listed concurrently. The entries of each partition are returned in
+/// turn, so the stream stays in key order.
 #[derive(Debug)]
 struct RemoteIter {
     /// Prepared entries in order to be returned by the iterator.
@@ -331,9 +761,14 @@ struct RemoteIter {
     full_path: String,
     /// The maximum number of keys to be returned by a single S3 ListObjectsV2 request.
     page_size: usize,
-    state: RemoteIterState,
+    /// Ranges of keys still to be listed or returned, in key order.
+    partitions: VecDeque<ListPartition>,
+    /// How many partitions to split the keys into once the first page is listed, if more than one
+    split_into: usize,
     /// Does the S3 implementation return ordered results?
     ordered: bool,
+    /// The entries listed so far, if they should be recorded.
+    listing: Option<Vec<ReaddirEntry>>,
 }
 
 impl RemoteIter {
@@ -343,65 +778,148 @@ impl RemoteIter {
             bucket: bucket.to_owned(),
             full_path: full_path.to_owned(),
             page_size,
-            state: RemoteIterState::InProgress(None),
+            partitions: VecDeque::from([ListPartition::new(None, None)]),
+            split_into: 0,
             ordered,
+            listing: None,
         }
     }
 
-    async fn next(&mut self, client: &impl ObjectClient) -> Result<Option<ReaddirEntry>, InodeError> {
-        if self.entries.is_empty() {
-            let continuation_token = match &mut self.state {
-                RemoteIterState::Finished => {
-                    trace!(self=?self as *const _, prefix=?self.full_path, "remote iter finished");
-                    return Ok(None);
-                }
-                RemoteIterState::InProgress(token) => token.take(),
-            };
+    /// Create an iterator that lists up to `partitions` ranges of keys concurrently. The keys can
+    /// only be split if the S3 implementation returns ordered results, and are only split if there
+    /// is more than one page of them.
+    fn parallel(bucket: &str, full_path: &str, page_size: usize, ordered: bool, partitions: usize) -> Self {
+        let mut iter = Self::new(bucket, full_path, page_size, ordered);
+        if ordered {
+            iter.split_into = partitions;
+        }
+        iter
+    }
 
-            trace!(self=?self as *const _, prefix=?self.full_path, ?continuation_token, "continuing remote iter");
+    /// Create an iterator over entries already listed, from the [PersistentMetadataCache] or a manifest.
+    fn from_entries(full_path: &str, mut entries: Vec<ReaddirEntry>, ordered: bool) -> Self {
+        if ordered {
+            entries.sort();
+        }
+        Self {
+            entries: entries.into(),
+            bucket: String::new(),
+            full_path: full_path.to_owned(),
+            page_size: 0,
+            partitions: VecDeque::new(),
+            split_into: 0,
+            ordered,
+            listing: None,
+        }
+    }
 
-            let result = client
-                .list_objects(
-                    &self.bucket,
-                    continuation_token.as_deref(),
-                    "/",
-                    self.page_size,
-                    self.full_path.as_str(),
-                )
-                .await
-                .map_err(|e| InodeError::client_error(e, "ListObjectsV2 failed", &self.bucket, &self.full_path))?;
+    /// Record the listed entries, to be returned by [Self::take_listing].
+    fn record_listing(mut self) -> Self {
+        self.listing = Some(Vec::new());
+        self
+    }
 
-            self.state = match result.next_continuation_token {
-                Some(token) => RemoteIterState::InProgress(Some(token)),
-                None => RemoteIterState::Finished,
+    /// The recorded entries, if all the pages have been listed.
+    fn take_listing(&mut self) -> Option<Vec<ReaddirEntry>> {
+        if !self.partitions.is_empty() {
+            return None;
+        }
+        self.listing.take()
+    }
+
+    /// Split the keys after the first page, whose entries are `first_page`, into up to `count`
+    /// partitions. Falls back to listing the keys in a single partition if the boundaries can't be
+    /// sampled.
+    async fn split(&mut self, client: &(impl ObjectClient + Sync), first_page: &[ReaddirEntry], count: usize) {
+        let names: Vec<_> = first_page.iter().map(ReaddirEntry::listed_name).collect();
+        let Some(last) = names.iter().max() else {
+            return;
+        };
+        let sampler = BoundarySampler {
+            client,
+            bucket: &self.bucket,
+            full_path: &self.full_path,
+        };
+        let boundaries = match sampler.sample(&names, last, count).await {
+            Ok(boundaries) => boundaries,
+            Err(ObjectClientError::ServiceError(ListObjectsError::StartAfterNotSupported)) => {
+                trace!(prefix=?self.full_path, "client cannot list after a key, listing sequentially");
+                return;
+            }
+            Err(err) => {
+                warn!(prefix=?self.full_path, ?err, "failed to sample keys, listing sequentially");
+                return;
+            }
+        };
+        trace!(prefix=?self.full_path, ?boundaries, "splitting listing");
+
+        let first = self
+            .partitions
+            .front_mut()
+            .expect("the first partition is being listed");
+        first.end = boundaries.first().cloned();
+        let starts = boundaries.iter().cloned().map(Some);
+        let ends = boundaries
+            .iter()
+            .skip(1)
+            .cloned()
+            .map(Some)
+            .chain(std::iter::once(None));
+        self.partitions
+            .extend(starts.zip(ends).map(|(start, end)| ListPartition::new(start, end)));
+    }
+
+    async fn next(&mut self, client: &(impl ObjectClient + Sync)) -> Result<Option<ReaddirEntry>, InodeError> {
+        while self.entries.is_empty() {
+            let Some(partition) = self.partitions.front_mut() else {
+                trace!(self=?self as *const _, prefix=?self.full_path, "remote iter finished");
+                return Ok(None);
             };
 
-            let prefixes = result
-                .common_prefixes
-                .into_iter()
-                .map(|prefix| ReaddirEntry::RemotePrefix {
-                    name: prefix[self.full_path.len()..prefix.len() - 1].to_owned(),
-                });
+            if !partition.entries.is_empty() {
+                self.entries.extend(partition.entries.drain(..));
+                continue;
+            }
+            if partition.is_finished() {
+                self.partitions.pop_front();
+                continue;
+            }
 
-            let objects = result
-                .objects
-                .into_iter()
-                .map(|object_info| ReaddirEntry::RemoteObject {
-                    name: object_info.key[self.full_path.len()..].to_owned(),
-                    object_info,
+            // List the next page of the first partition, and of any partition not too far ahead
+            let (bucket, full_path, page_size) = (&self.bucket, &self.full_path, self.page_size);
+            let max_entries_ahead = MAX_PAGES_AHEAD * page_size;
+            let listing = self
+                .partitions
+                .iter()
+                .enumerate()
+                .filter(|(_, partition)| !partition.is_finished() && partition.entries.len() < max_entries_ahead)
+                .map(|(index, partition)| async move {
+                    let page = partition.list_page(client, bucket, full_path, page_size).await?;
+                    Ok::<_, InodeError>((index, page))
                 });
+            let pages = futures::future::try_join_all(listing).await?;
 
-            if self.ordered {
-                // ListObjectsV2 results are sorted, so ideally we'd just merge-sort the two streams.
-                // But `prefixes` isn't quite in sorted order any more because we trimmed off the
-                // trailing `/` from the names. There's still probably a less naive way to do this sort,
-                // but this should be good enough.
-                let mut new_entries = prefixes.chain(objects).collect::<Vec<_>>();
-                new_entries.sort();
+            for (index, (mut new_entries, state)) in pages {
+                if let Some(listing) = &mut self.listing {
+                    listing.extend(new_entries.iter().cloned());
+                }
 
-                self.entries.extend(new_entries);
-            } else {
-                self.entries.extend(prefixes.chain(objects));
+                if self.split_into > 1 && matches!(state, RemoteIterState::InProgress(Some(_))) {
+                    // Only the first page is listed before splitting, from the only partition
+                    let count = std::mem::take(&mut self.split_into);
+                    self.split(client, &new_entries, count).await;
+                }
+
+                if self.ordered {
+                    // ListObjectsV2 results are sorted, so ideally we'd just merge-sort the two streams.
+                    // But `prefixes` isn't quite in sorted order any more because we trimmed off the
+                    // trailing `/` from the names. There's still probably a less naive way to do this sort,
+                    // but this should be good enough.
+                    new_entries.sort();
+                }
+                let partition = &mut self.partitions[index];
+                partition.entries.extend(new_entries);
+                partition.state = state;
             }
         }
 
@@ -413,39 +931,52 @@ impl RemoteIter {
 ///
 /// See [self::ReaddirIter] for exact behavior differences.
 mod ordered {
+    use std::collections::BTreeSet;
+
     use super::*;
 
     /// An iterator over [ReaddirEntry]s for a directory. This merges iterators of remote and local
     /// [ReaddirEntry]s, returning them in name order, and filtering out entries that are shadowed by
     /// other entries of the same name.
+    ///
+    /// If a suffix for shadowed files is configured, a remote object shadowed by a directory is
+    /// instead returned under its name followed by the suffix, in order with the other entries,
+    /// unless another entry already has that name.
     #[derive(Debug)]
     pub struct ReaddirIter {
-        remote: RemoteIter,
+        pub(super) remote: RemoteIter,
         local: LocalIter,
         next_remote: Option<ReaddirEntry>,
         next_local: Option<ReaddirEntry>,
         last_entry: Option<ReaddirEntry>,
+        shadowed_file_suffix: Option<String>,
+        /// Shadowed objects to be returned under their alternate names
+        shadowed: BTreeSet<ReaddirEntry>,
     }
 
     impl ReaddirIter {
         pub(super) fn new(
-            bucket: &str,
-            full_path: &str,
-            page_size: usize,
+            remote: RemoteIter,
             local_entries: VecDeque<ReaddirEntry>,
+            shadowed_file_suffix: Option<String>,
         ) -> Self {
             Self {
-                remote: RemoteIter::new(bucket, full_path, page_size, true),
+                remote,
                 local: LocalIter::new(local_entries),
                 next_remote: None,
                 next_local: None,
                 last_entry: None,
+                shadowed_file_suffix,
+                shadowed: BTreeSet::new(),
             }
         }
 
         /// Return the next [ReaddirEntry] for the directory stream. If the stream is finished, returns
         /// `Ok(None)`.
-        pub(super) async fn next(&mut self, client: &impl ObjectClient) -> Result<Option<ReaddirEntry>, InodeError> {
+        pub(super) async fn next(
+            &mut self,
+            client: &(impl ObjectClient + Sync),
+        ) -> Result<Option<ReaddirEntry>, InodeError> {
             // The only reason to go around this loop more than once is if the next entry to return is
             // a duplicate, in which case it's skipped.
             loop {
@@ -458,23 +989,37 @@ mod ordered {
                 }
 
                 // Merge-sort the two iterators, preferring the remote iterator if the two entries are
-                // equal (i.e. have the same name)
-                let next = match (&self.next_remote, &self.next_local) {
-                    (Some(remote), Some(local)) => {
-                        if remote <= local {
-                            self.next_remote.take()
-                        } else {
-                            self.next_local.take()
+                // equal (i.e. have the same name). Shadowed objects sort after both for equal names.
+                let next_shadowed = self.shadowed.first();
+                let next = if next_shadowed.is_some_and(|shadowed| {
+                    [&self.next_remote, &self.next_local]
+                        .into_iter()
+                        .all(|next| next.as_ref().is_none_or(|next| shadowed < next))
+                }) {
+                    self.shadowed.pop_first()
+                } else {
+                    match (&self.next_remote, &self.next_local) {
+                        (Some(remote), Some(local)) => {
+                            if remote <= local {
+                                self.next_remote.take()
+                            } else {
+                                self.next_local.take()
+                            }
                         }
+                        (Some(_), None) => self.next_remote.take(),
+                        (None, _) => self.next_local.take(),
                     }
-                    (Some(_), None) => self.next_remote.take(),
-                    (None, _) => self.next_local.take(),
                 };
 
                 // Deduplicate the entry we want to return
                 match (next, &self.last_entry) {
                     (Some(entry), Some(last_entry)) => {
                         if last_entry.name() == entry.name() {
+                            if let Some(shadowed) = self.shadowed_entry(last_entry, &entry) {
+                                trace!("{} is shadowed by {}", entry.description(), last_entry.description());
+                                self.shadowed.insert(shadowed);
+                                continue;
+                            }
                             warn!(
                                 "{} is omitted because another {} exist with the same name",
                                 entry.description(),
@@ -493,6 +1038,23 @@ mod ordered {
                 }
             }
         }
+
+        /// The entry to return under its alternate name for the remote object `entry`, if it is
+        /// shadowed by the remote directory `last_entry`.
+        fn shadowed_entry(&self, last_entry: &ReaddirEntry, entry: &ReaddirEntry) -> Option<ReaddirEntry> {
+            let suffix = self.shadowed_file_suffix.as_ref()?;
+            match (last_entry, entry) {
+                (ReaddirEntry::RemotePrefix { .. }, ReaddirEntry::RemoteObject { name, object_info })
+                    if !name.is_empty() =>
+                {
+                    Some(ReaddirEntry::ShadowedObject {
+                        name: format!("{name}{suffix}"),
+                        object_info: object_info.clone(),
+                    })
+                }
+                _ => None,
+            }
+        }
     }
 
     /// An iterator over local [ReaddirEntry]s listed from a directory at the start of a [ReaddirHandle]
@@ -526,7 +1088,7 @@ mod unordered {
     /// local entries that have not been shadowed.
     #[derive(Debug)]
     pub struct ReaddirIter {
-        remote: RemoteIter,
+        pub(super) remote: RemoteIter,
         /// Local entries to be returned.
         /// Entries may be removed from this collection if entries of the same name are returned by [Self::remote].
         local: HashMap<String, ReaddirEntry>,
@@ -535,12 +1097,7 @@ mod unordered {
     }
 
     impl ReaddirIter {
-        pub(super) fn new(
-            bucket: &str,
-            full_path: &str,
-            page_size: usize,
-            local_entries: VecDeque<ReaddirEntry>,
-        ) -> Self {
+        pub(super) fn new(remote: RemoteIter, local_entries: VecDeque<ReaddirEntry>) -> Self {
             let local_map = local_entries
                 .into_iter()
                 .map(|entry| {
@@ -552,7 +1109,7 @@ mod unordered {
                 .collect::<HashMap<_, _>>();
 
             Self {
-                remote: RemoteIter::new(bucket, full_path, page_size, false),
+                remote,
                 local: local_map,
                 local_iter: VecDeque::new(),
             }
@@ -560,7 +1117,10 @@ mod unordered {
 
         /// Return the next [ReaddirEntry] for the directory stream. If the stream is finished, returns
         /// `Ok(None)`.
-        pub(super) async fn next(&mut self, client: &impl ObjectClient) -> Result<Option<ReaddirEntry>, InodeError> {
+        pub(super) async fn next(
+            &mut self,
+            client: &(impl ObjectClient + Sync),
+        ) -> Result<Option<ReaddirEntry>, InodeError> {
             if let Some(remote) = self.remote.next(client).await? {
                 self.local.remove(remote.name());
                 return Ok(Some(remote));
@@ -574,3 +1134,187 @@ mod unordered {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use async_trait::async_trait;
+    use mountpoint_s3_client::error::{
+        DeleteObjectError, GetObjectAttributesError, GetObjectError, HeadObjectError, PutObjectError,
+    };
+    use mountpoint_s3_client::mock_client::{
+        MockClient, MockClientConfig, MockClientError, MockGetObjectRequest, MockObject, MockPutObjectRequest,
+    };
+    use mountpoint_s3_client::types::{
+        DeleteObjectResult, ETag, GetObjectAttributesResult, HeadObjectResult, ListObjectsResult, ObjectAttribute,
+        PutObjectParams, PutObjectResult, PutObjectSingleParams,
+    };
+    use mountpoint_s3_crt::s3::client::BufferPoolUsageStats;
+    use test_case::test_case;
+
+    use super::*;
+
+    /// A client that can't start a listing after a given key, relying on the default
+    /// [ObjectClient::list_objects_after].
+    struct SequentialListClient(MockClient);
+
+    #[async_trait]
+    impl ObjectClient for SequentialListClient {
+        type GetObjectRequest = MockGetObjectRequest;
+        type PutObjectRequest = MockPutObjectRequest;
+        type ClientError = MockClientError;
+
+        fn read_part_size(&self) -> Option<usize> {
+            self.0.read_part_size()
+        }
+
+        fn write_part_size(&self) -> Option<usize> {
+            self.0.write_part_size()
+        }
+
+        fn initial_read_window_size(&self) -> Option<usize> {
+            self.0.initial_read_window_size()
+        }
+
+        fn mem_usage_stats(&self) -> Option<BufferPoolUsageStats> {
+            self.0.mem_usage_stats()
+        }
+
+        async fn delete_object(
+            &self,
+            bucket: &str,
+            key: &str,
+        ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+            self.0.delete_object(bucket, key).await
+        }
+
+        async fn get_object(
+            &self,
+            bucket: &str,
+            key: &str,
+            range: Option<Range<u64>>,
+            if_match: Option<ETag>,
+        ) -> ObjectClientResult<Self::GetObjectRequest, GetObjectError, Self::ClientError> {
+            self.0.get_object(bucket, key, range, if_match).await
+        }
+
+        async fn list_objects(
+            &self,
+            bucket: &str,
+            continuation_token: Option<&str>,
+            delimiter: &str,
+            max_keys: usize,
+            prefix: &str,
+        ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+            self.0
+                .list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
+                .await
+        }
+
+        async fn head_object(
+            &self,
+            bucket: &str,
+            key: &str,
+        ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+            self.0.head_object(bucket, key).await
+        }
+
+        async fn put_object(
+            &self,
+            bucket: &str,
+            key: &str,
+            params: &PutObjectParams,
+        ) -> ObjectClientResult<Self::PutObjectRequest, PutObjectError, Self::ClientError> {
+            self.0.put_object(bucket, key, params).await
+        }
+
+        async fn put_object_single<'a>(
+            &self,
+            bucket: &str,
+            key: &str,
+            params: &PutObjectSingleParams,
+            contents: impl AsRef<[u8]> + Send + 'a,
+        ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+            self.0.put_object_single(bucket, key, params, contents).await
+        }
+
+        async fn get_object_attributes(
+            &self,
+            bucket: &str,
+            key: &str,
+            max_parts: Option<usize>,
+            part_number_marker: Option<usize>,
+            object_attributes: &[ObjectAttribute],
+        ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+            self.0
+                .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
+                .await
+        }
+    }
+
+    fn new_client(names: &[String]) -> MockClient {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        });
+        for name in names {
+            client.add_object(&format!("dir/{name}"), MockObject::constant(0xaa, 1, ETag::for_tests()));
+        }
+        client
+    }
+
+    async fn list_all(iter: &mut RemoteIter, client: &(impl ObjectClient + Sync)) -> Vec<String> {
+        let mut names = Vec::new();
+        while let Some(entry) = iter.next(client).await.unwrap() {
+            names.push(entry.name().to_owned());
+        }
+        names
+    }
+
+    #[test_case(&(0..2000).map(|i| format!("part-{i:05}")).collect::<Vec<_>>(); "numbered")]
+    #[test_case(&(0..500).map(|i| format!("{i:x}-{}", i * 7)).collect::<Vec<_>>(); "hexadecimal")]
+    #[test_case(&(0..200).flat_map(|i| [format!("file{i:03}"), format!("file{i:03}/a")]).collect::<Vec<_>>(); "shadowed")]
+    #[tokio::test]
+    async fn test_sampled_partitions(names: &[String]) {
+        let client = new_client(names);
+        let mut expected: Vec<_> = names
+            .iter()
+            .map(|name| name.split('/').next().unwrap().to_owned())
+            .collect();
+        expected.sort();
+        expected.dedup();
+
+        let mut iter = RemoteIter::parallel("test_bucket", "dir/", 100, true, 8);
+        let first = iter.next(&client).await.unwrap().unwrap();
+        assert!(
+            iter.partitions.len() > 4,
+            "keys should be split, got {:?}",
+            iter.partitions
+        );
+        let mut listed = vec![first.name().to_owned()];
+        listed.extend(list_all(&mut iter, &client).await);
+        listed.dedup();
+        assert_eq!(listed, expected);
+    }
+
+    #[tokio::test]
+    async fn test_sequential_fallback() {
+        let names: Vec<_> = (0..500).map(|i| format!("part-{i:05}")).collect();
+        let client = SequentialListClient(new_client(&names));
+
+        let mut iter = RemoteIter::parallel("test_bucket", "dir/", 100, true, 8);
+        let first = iter.next(&client).await.unwrap().unwrap();
+        assert_eq!(iter.partitions.len(), 1, "keys should not be split");
+        let mut listed = vec![first.name().to_owned()];
+        listed.extend(list_all(&mut iter, &client).await);
+        assert_eq!(listed, names);
+
+        // A single page is never split
+        let client = new_client(&names[..50]);
+        let mut iter = RemoteIter::parallel("test_bucket", "dir/", 100, true, 8);
+        assert_eq!(list_all(&mut iter, &client).await, names[..50]);
+        assert!(iter.partitions.is_empty());
+    }
+}
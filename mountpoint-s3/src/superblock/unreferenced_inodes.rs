@@ -0,0 +1,116 @@
+use linked_hash_map::LinkedHashMap;
+
+use super::{Inode, InodeNo};
+
+use crate::sync::Mutex;
+
+/// The inodes that the kernel holds no reference to, because they were only discovered by a
+/// listing or a lookup whose result was not returned to the kernel.
+///
+/// Such inodes are only kept in the children of their parent directory, to serve lookups from.
+/// This bounded set keeps track of them in least recently used order, so that the oldest ones can
+/// be evicted from their parent once there are too many of them.
+#[derive(Debug)]
+pub struct UnreferencedInodes {
+    map: Mutex<LinkedHashMap<InodeNo, Entry>>,
+    /// Upper bound for the number of inodes kept.
+    max_size: usize,
+}
+
+/// An inode to evict and the directory it should be evicted from.
+#[derive(Debug)]
+pub struct Entry {
+    pub parent: Inode,
+    pub inode: Inode,
+}
+
+impl UnreferencedInodes {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            map: Mutex::new(Default::default()),
+            max_size,
+        }
+    }
+
+    /// Track a newly created inode in the directory `parent`.
+    pub fn insert(&self, parent: &Inode, inode: &Inode) {
+        let entry = Entry {
+            parent: parent.clone(),
+            inode: inode.clone(),
+        };
+        let mut map = self.map.lock().unwrap();
+        map.insert(inode.ino(), entry);
+        metrics::gauge!("metadata_cache.unreferenced_inodes.entries").set(map.len() as f64);
+    }
+
+    /// Stop tracking an inode, because the kernel now holds a reference to it. If the inode was
+    /// not tracked, this is a no-op.
+    pub fn remove(&self, ino: InodeNo) {
+        let mut map = self.map.lock().unwrap();
+        if map.remove(&ino).is_some() {
+            metrics::gauge!("metadata_cache.unreferenced_inodes.entries").set(map.len() as f64);
+        }
+    }
+
+    /// Mark an inode as recently used, if it is tracked.
+    pub fn touch(&self, ino: InodeNo) {
+        let mut map = self.map.lock().unwrap();
+        map.get_refresh(&ino);
+    }
+
+    /// Remove and return the oldest entries that exceed the limit.
+    pub fn take_excess(&self) -> Vec<Entry> {
+        let mut map = self.map.lock().unwrap();
+        let excess = map.len().saturating_sub(self.max_size);
+        if excess == 0 {
+            return Vec::new();
+        }
+        let evicted: Vec<_> = (0..excess).filter_map(|_| map.pop_front()).map(|(_, e)| e).collect();
+        metrics::gauge!("metadata_cache.unreferenced_inodes.entries").set(map.len() as f64);
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::super::inode::{InodeKey, InodeKind, InodeStat, InodeState, WriteStatus};
+    use super::*;
+
+    fn make_inode(ino: InodeNo, name: &str) -> Inode {
+        let stat = InodeStat::for_file(0, OffsetDateTime::now_utc(), None, None, None, Default::default());
+        Inode::new(
+            ino,
+            1,
+            InodeKey::new("".into(), name, InodeKind::File),
+            InodeKind::File,
+            InodeState::new(&stat, InodeKind::File, WriteStatus::Remote),
+        )
+    }
+
+    #[test]
+    fn test_take_excess() {
+        let parent = make_inode(1, "parent");
+        let inodes = UnreferencedInodes::new(2);
+
+        for ino in 2..6 {
+            inodes.insert(&parent, &make_inode(ino, &format!("child{ino}")));
+        }
+        inodes.remove(3);
+
+        let evicted: Vec<_> = inodes.take_excess().iter().map(|e| e.inode.ino()).collect();
+        assert_eq!(evicted, vec![2]);
+        assert!(inodes.take_excess().is_empty());
+
+        inodes.insert(&parent, &make_inode(6, "child6"));
+        let evicted: Vec<_> = inodes.take_excess().iter().map(|e| e.inode.ino()).collect();
+        assert_eq!(evicted, vec![4]);
+
+        // Used inodes are evicted last
+        inodes.touch(5);
+        inodes.insert(&parent, &make_inode(7, "child7"));
+        let evicted: Vec<_> = inodes.take_excess().iter().map(|e| e.inode.ino()).collect();
+        assert_eq!(evicted, vec![6]);
+    }
+}
@@ -1,33 +1,96 @@
 use std::{fmt::Debug, sync::Arc};
 
+use bytes::BytesMut;
+use futures::task::{Spawn, SpawnExt};
 use mountpoint_s3_client::checksums::crc32c_from_base64;
 use mountpoint_s3_client::error::{ObjectClientError, PutObjectError};
-use mountpoint_s3_client::types::{PutObjectParams, PutObjectResult, PutObjectTrailingChecksums, UploadReview};
+use mountpoint_s3_client::types::{ETag, PutObjectParams, PutObjectResult, PutObjectTrailingChecksums, UploadReview};
 use mountpoint_s3_client::{ObjectClient, PutObjectRequest};
 
 use mountpoint_s3_crt::checksums::crc32c::{Crc32c, Hasher};
 use thiserror::Error;
-use tracing::error;
+use tracing::{error, trace, warn};
 
-use crate::checksums::combine_checksums;
+use crate::checksums::{combine_checksums, ChecksummedBytes};
+use crate::data_cache::{BlockIndex, CachePolicy, DataCache};
 use crate::fs::{ServerSideEncryption, SseCorruptedError};
+use crate::mem_limiter::MemoryLimiter;
+use crate::object::ObjectId;
 
 type PutRequestError<Client> = ObjectClientError<PutObjectError, <Client as ObjectClient>::ClientError>;
 
 const MAX_S3_MULTIPART_UPLOAD_PARTS: usize = 10000;
 
+/// Default maximum size of the objects inserted into a [WriteThroughCache].
+const DEFAULT_WRITE_THROUGH_MAX_OBJECT_SIZE: u64 = 64 * 1024 * 1024;
+
 /// An [Uploader] creates and manages streaming PutObject requests.
 #[derive(Debug)]
-pub struct Uploader<Client> {
+pub struct Uploader<Client: ObjectClient> {
     inner: Arc<UploaderInner<Client>>,
 }
 
 #[derive(Debug)]
-struct UploaderInner<Client> {
+struct UploaderInner<Client: ObjectClient> {
     client: Client,
     storage_class: Option<String>,
     server_side_encryption: ServerSideEncryption,
     use_additional_checksums: bool,
+    write_through_cache: Option<WriteThroughCache>,
+    mem_limiter: Option<Arc<MemoryLimiter<Client>>>,
+}
+
+/// A data cache populated with the content of uploaded objects, so that objects written through
+/// Mountpoint can be read back without downloading them from S3.
+///
+/// The content of an object is held in memory until its upload completes, as the blocks can only be
+/// inserted into the cache once S3 returns the ETag of the new object. This memory is reserved from
+/// the [MemoryLimiter], and objects are not cached when it refuses the reservation. Objects larger
+/// than `max_object_size`, or excluded by the cache policy, are not cached either.
+///
+/// The blocks are inserted into the cache by a task spawned on `runtime`, so completing an upload
+/// doesn't wait for the cache.
+#[derive(Clone)]
+pub struct WriteThroughCache {
+    cache: Arc<dyn DataCache + Send + Sync>,
+    runtime: Arc<dyn Spawn + Send + Sync>,
+    max_object_size: u64,
+    policy: Arc<CachePolicy>,
+}
+
+impl WriteThroughCache {
+    /// Create a new [WriteThroughCache] inserting blocks into the given cache from tasks spawned on
+    /// `runtime`.
+    pub fn new(cache: Arc<dyn DataCache + Send + Sync>, runtime: impl Spawn + Send + Sync + 'static) -> Self {
+        Self {
+            cache,
+            runtime: Arc::new(runtime),
+            max_object_size: DEFAULT_WRITE_THROUGH_MAX_OBJECT_SIZE,
+            policy: Default::default(),
+        }
+    }
+
+    /// Set the maximum size of the objects to cache.
+    pub fn with_max_object_size(mut self, max_object_size: u64) -> Self {
+        self.max_object_size = max_object_size;
+        self
+    }
+
+    /// Only cache the objects allowed by the given policy.
+    pub fn with_policy(mut self, policy: Arc<CachePolicy>) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl Debug for WriteThroughCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WriteThroughCache")
+            .field("block_size", &self.cache.block_size())
+            .field("max_object_size", &self.max_object_size)
+            .field("policy", &self.policy)
+            .finish()
+    }
 }
 
 #[derive(Debug, Error)]
@@ -51,10 +114,25 @@ impl<Client: ObjectClient> Uploader<Client> {
             storage_class,
             server_side_encryption,
             use_additional_checksums,
+            write_through_cache: None,
+            mem_limiter: None,
         };
         Self { inner: Arc::new(inner) }
     }
 
+    /// Insert the content of uploaded objects into the given cache once their upload completes,
+    /// reserving the memory to buffer their content from `mem_limiter`.
+    pub fn with_write_through_cache(
+        mut self,
+        cache: WriteThroughCache,
+        mem_limiter: Arc<MemoryLimiter<Client>>,
+    ) -> Self {
+        let inner = Arc::get_mut(&mut self.inner).expect("uploader should not be shared yet");
+        inner.write_through_cache = Some(cache);
+        inner.mem_limiter = Some(mem_limiter);
+        self
+    }
+
     /// Start a new put request to the specified object.
     pub async fn put(
         &self,
@@ -96,6 +174,10 @@ pub struct UploadRequest<Client: ObjectClient> {
     request: Client::PutObjectRequest,
     maximum_upload_size: Option<usize>,
     sse: ServerSideEncryption,
+    /// Content written so far, to insert into the cache once the upload completes. `None` when
+    /// write-through caching is disabled, the object is too large to cache, or there wasn't enough
+    /// memory to buffer it.
+    cache_buffer: Option<Box<CacheBuffer<Client>>>,
 }
 
 impl<Client: ObjectClient> UploadRequest<Client> {
@@ -137,6 +219,12 @@ impl<Client: ObjectClient> UploadRequest<Client> {
             request,
             maximum_upload_size,
             sse: inner.server_side_encryption.clone(),
+            cache_buffer: inner
+                .write_through_cache
+                .clone()
+                .zip(inner.mem_limiter.clone())
+                .filter(|(cache, _)| cache.policy.should_cache(key, 0))
+                .map(|(cache, mem_limiter)| Box::new(CacheBuffer::new(cache, mem_limiter))),
         })
     }
 
@@ -165,10 +253,24 @@ impl<Client: ObjectClient> UploadRequest<Client> {
         self.hasher.update(data);
         self.request.write(data).await?;
         self.next_request_offset += data.len() as u64;
+        if let Some(cache_buffer) = &mut self.cache_buffer {
+            if !cache_buffer.append(data) {
+                trace!(
+                    key = self.key,
+                    "object is too large to be cached on write, or there is not enough memory to buffer it"
+                );
+                self.cache_buffer = None;
+            }
+        }
         Ok(data.len())
     }
 
-    pub async fn complete(self) -> Result<PutObjectResult, PutRequestError<Client>> {
+    /// Complete the upload. When write-through caching is enabled, the content is inserted into the
+    /// cache in the background once the upload has completed.
+    pub async fn complete(self) -> Result<PutObjectResult, PutRequestError<Client>>
+    where
+        Client: Send + Sync + 'static,
+    {
         let size = self.size();
         let checksum = self.hasher.finalize();
         let result = self
@@ -187,6 +289,9 @@ impl<Client: ObjectClient> UploadRequest<Client> {
             // 2. the reported error is severe as the object was already uploaded to S3.
             std::process::exit(1);
         }
+        if let Some(cache_buffer) = self.cache_buffer {
+            cache_buffer.spawn_insert(self.key, result.etag.clone());
+        }
         Ok(result)
     }
 }
@@ -202,6 +307,109 @@ impl<Client: ObjectClient> Debug for UploadRequest<Client> {
     }
 }
 
+/// Splits the content written to an [UploadRequest] into blocks for a [WriteThroughCache].
+///
+/// The memory for each block is reserved from the [MemoryLimiter] when the block is started, and
+/// released when the buffer is dropped.
+struct CacheBuffer<Client: ObjectClient> {
+    cache: WriteThroughCache,
+    mem_limiter: Arc<MemoryLimiter<Client>>,
+    size: u64,
+    reserved: u64,
+    blocks: Vec<ChecksummedBytes>,
+    current_block: BytesMut,
+}
+
+impl<Client: ObjectClient> CacheBuffer<Client> {
+    fn new(cache: WriteThroughCache, mem_limiter: Arc<MemoryLimiter<Client>>) -> Self {
+        Self {
+            cache,
+            mem_limiter,
+            size: 0,
+            reserved: 0,
+            blocks: Vec::new(),
+            current_block: BytesMut::new(),
+        }
+    }
+
+    /// Append the next `data` written. Returns `false` if the object is now too large to cache, or
+    /// if the memory to buffer it couldn't be reserved.
+    fn append(&mut self, mut data: &[u8]) -> bool {
+        self.size += data.len() as u64;
+        if self.size > self.cache.max_object_size {
+            return false;
+        }
+
+        let block_size = self.cache.cache.block_size() as usize;
+        while !data.is_empty() {
+            if self.current_block.is_empty() {
+                if !self.mem_limiter.try_reserve(block_size as u64) {
+                    return false;
+                }
+                self.reserved += block_size as u64;
+            }
+            let len = (block_size - self.current_block.len()).min(data.len());
+            self.current_block.extend_from_slice(&data[..len]);
+            data = &data[len..];
+            if self.current_block.len() == block_size {
+                let block = std::mem::take(&mut self.current_block).freeze();
+                self.blocks.push(ChecksummedBytes::new(block));
+            }
+        }
+        true
+    }
+
+    /// Insert the blocks into the cache from a new task, for the object uploaded with the given
+    /// `etag`.
+    fn spawn_insert(self: Box<Self>, key: String, etag: ETag)
+    where
+        Client: Send + Sync + 'static,
+    {
+        let runtime = self.cache.runtime.clone();
+        if let Err(error) = runtime.spawn(async move { self.insert(&key, etag).await }) {
+            warn!(
+                ?error,
+                "failed to spawn the insertion of an uploaded object into the cache"
+            );
+        }
+    }
+
+    /// Insert the blocks into the cache, for the object uploaded with the given `etag`.
+    async fn insert(mut self, key: &str, etag: ETag) {
+        if !self.current_block.is_empty() {
+            let block = std::mem::take(&mut self.current_block).freeze();
+            self.blocks.push(ChecksummedBytes::new(block));
+        }
+        if !self.cache.policy.should_cache(key, self.size) {
+            trace!(key, size = self.size, "uploaded object not cached by policy");
+            return;
+        }
+
+        let block_size = self.cache.cache.block_size();
+        let object_id = ObjectId::new(key.to_owned(), etag);
+        for (block_idx, block) in std::mem::take(&mut self.blocks).into_iter().enumerate() {
+            let block_idx = block_idx as BlockIndex;
+            let block_offset = block_idx * block_size;
+            if let Err(error) = self
+                .cache
+                .cache
+                .put_block(object_id.clone(), block_idx, block_offset, block)
+                .await
+            {
+                warn!(key, block_idx, ?error, "failed to insert uploaded block into the cache");
+                return;
+            }
+        }
+        metrics::counter!("upload.cached_bytes").increment(self.size);
+    }
+}
+
+impl<Client: ObjectClient> Drop for CacheBuffer<Client> {
+    fn drop(&mut self) {
+        self.mem_limiter.release(self.reserved);
+    }
+}
+
 fn verify_checksums(review: UploadReview, expected_size: u64, expected_checksum: Crc32c) -> bool {
     let mut uploaded_size = 0u64;
     let mut uploaded_checksum = Crc32c::new(0);
@@ -247,7 +455,13 @@ fn verify_checksums(review: UploadReview, expected_size: u64, expected_checksum:
 mod tests {
     use std::collections::HashMap;
 
+    use std::time::{Duration, Instant};
+
+    use futures::executor::ThreadPool;
+
     use super::*;
+    use crate::data_cache::InMemoryDataCache;
+    use crate::mem_limiter::MINIMUM_MEM_LIMIT;
     use mountpoint_s3_client::{
         failure_client::countdown_failure_client,
         mock_client::{MockClient, MockClientConfig, MockClientError},
@@ -452,4 +666,160 @@ mod tests {
         );
         uploader.put(bucket, key).await.expect("put with sse should succeed");
     }
+
+    fn new_write_through_cache(cache: &Arc<InMemoryDataCache>) -> WriteThroughCache {
+        let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+        WriteThroughCache::new(cache.clone(), runtime)
+    }
+
+    fn new_mem_limiter(client: &Arc<MockClient>) -> Arc<MemoryLimiter<Arc<MockClient>>> {
+        Arc::new(MemoryLimiter::new(client.clone(), MINIMUM_MEM_LIMIT))
+    }
+
+    /// Wait for the blocks of an uploaded object to be inserted into the cache in the background.
+    fn wait_for_blocks(cache: &InMemoryDataCache, object_id: &ObjectId, block_count: usize) {
+        let start = Instant::now();
+        while cache.block_count(object_id) < block_count {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "blocks were not cached in time"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test_case(10, 3; "small writes")]
+    #[test_case(100, 25; "large writes")]
+    #[test_case(64, 16; "aligned writes")]
+    #[tokio::test]
+    async fn write_through_cache_test(object_size: usize, write_size: usize) {
+        let bucket = "bucket";
+        let key = "hello";
+        let block_size = 16;
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let cache = Arc::new(InMemoryDataCache::new(block_size));
+        let mem_limiter = new_mem_limiter(&client);
+        let available_mem = mem_limiter.available_mem();
+        let uploader = Uploader::new(client.clone(), None, ServerSideEncryption::default(), true)
+            .with_write_through_cache(new_write_through_cache(&cache), mem_limiter.clone());
+
+        let body: Vec<u8> = (0..object_size).map(|i| i as u8).collect();
+        let mut request = uploader.put(bucket, key).await.unwrap();
+        let mut offset = 0;
+        for data in body.chunks(write_size) {
+            offset += request.write(offset, data).await.unwrap() as i64;
+        }
+        let result = request.complete().await.unwrap();
+
+        // The cached blocks match the uploaded object
+        let object_id = ObjectId::new(key.to_owned(), result.etag);
+        let block_count = object_size.div_ceil(block_size as usize);
+        wait_for_blocks(&cache, &object_id, block_count);
+        assert_eq!(cache.block_count(&object_id), block_count);
+        let mut cached = Vec::new();
+        for block_idx in 0..block_count as u64 {
+            let block = cache
+                .get_block(&object_id, block_idx, block_idx * block_size)
+                .await
+                .expect("cache should be accessible")
+                .expect("block should be cached");
+            cached.extend_from_slice(&block.into_bytes().unwrap());
+        }
+        assert_eq!(cached, body);
+
+        // The memory buffering the blocks is released once they are cached
+        let start = Instant::now();
+        while mem_limiter.available_mem() != available_mem {
+            assert!(start.elapsed() < Duration::from_secs(10), "memory was not released");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[tokio::test]
+    async fn write_through_cache_memory_limit_test() {
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let cache = Arc::new(InMemoryDataCache::new(16));
+        let mem_limiter = new_mem_limiter(&client);
+        let uploader = Uploader::new(client.clone(), None, ServerSideEncryption::default(), true)
+            .with_write_through_cache(new_write_through_cache(&cache), mem_limiter.clone());
+
+        // Use up the memory after the first block is buffered
+        let mut request = uploader.put(bucket, key).await.unwrap();
+        request.write(0, &[0u8; 10]).await.unwrap();
+        let available_mem = mem_limiter.available_mem();
+        mem_limiter.reserve(available_mem);
+        request.write(10, &[0u8; 30]).await.unwrap();
+        let result = request.complete().await.unwrap();
+
+        // The object is not cached, and the memory reserved for it is released
+        let object_id = ObjectId::new(key.to_owned(), result.etag);
+        assert_eq!(cache.block_count(&object_id), 0);
+        assert_eq!(mem_limiter.available_mem(), 16);
+        mem_limiter.release(available_mem);
+        assert_eq!(mem_limiter.available_mem(), available_mem + 16);
+    }
+
+    #[tokio::test]
+    async fn write_through_cache_max_size_test() {
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let cache = Arc::new(InMemoryDataCache::new(16));
+        let write_through_cache = new_write_through_cache(&cache).with_max_object_size(50);
+        let uploader = Uploader::new(client.clone(), None, ServerSideEncryption::default(), true)
+            .with_write_through_cache(write_through_cache, new_mem_limiter(&client));
+
+        let mut request = uploader.put(bucket, key).await.unwrap();
+        request.write(0, &[0u8; 40]).await.unwrap();
+        request.write(40, &[0u8; 40]).await.unwrap();
+        let result = request.complete().await.unwrap();
+
+        // The object is too large to be cached
+        let object_id = ObjectId::new(key.to_owned(), result.etag);
+        assert_eq!(cache.block_count(&object_id), 0);
+    }
+
+    #[tokio::test]
+    async fn write_through_cache_policy_test() {
+        let bucket = "bucket";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let cache = Arc::new(InMemoryDataCache::new(16));
+        let policy: CachePolicy =
+            serde_json::from_str(r#"{ "rules": [{ "prefix": "tmp/", "action": "exclude" }] }"#).unwrap();
+        let write_through_cache = new_write_through_cache(&cache).with_policy(Arc::new(policy));
+        let uploader = Uploader::new(client.clone(), None, ServerSideEncryption::default(), true)
+            .with_write_through_cache(write_through_cache, new_mem_limiter(&client));
+
+        for (key, expected_blocks) in [("tmp/hello", 0), ("hello", 3)] {
+            let mut request = uploader.put(bucket, key).await.unwrap();
+            request.write(0, &[0u8; 40]).await.unwrap();
+            let result = request.complete().await.unwrap();
+
+            let object_id = ObjectId::new(key.to_owned(), result.etag);
+            wait_for_blocks(&cache, &object_id, expected_blocks);
+            assert_eq!(cache.block_count(&object_id), expected_blocks, "key {key}");
+        }
+    }
 }
@@ -245,6 +245,21 @@ fn invalid_ttl(invalid_ttl: &str) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn invalid_ttl_override() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("mount-s3")?;
+
+    cmd.arg("test-bucket")
+        .arg(dir.path())
+        .arg("--metadata-ttl-override")
+        .arg("logs/");
+    let error_message = "TTL override must be PREFIX=TTL";
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    Ok(())
+}
+
 #[test]
 fn sse_args_non_empty() -> Result<(), Box<dyn std::error::Error>> {
     let dir = assert_fs::TempDir::new()?;
@@ -301,3 +316,131 @@ fn verify_new_part_size_config_conflict_with_old_one(
 
     Ok(())
 }
+
+#[test]
+fn cache_write_tiers_must_be_enabled() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("mount-s3")?;
+    cmd.arg("test-bucket")
+        .arg(dir.path())
+        .arg("--cache-memory=1024")
+        .arg("--cache-write-tiers=memory,disk");
+    let error_message = "--cache-write-tiers includes the disk cache, which is not enabled";
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    Ok(())
+}
+
+#[test]
+fn encrypt_cache_not_persistent() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("mount-s3")?;
+    cmd.arg("test-bucket")
+        .arg(dir.path())
+        .arg("--cache")
+        .arg(dir.path())
+        .arg("--persistent-cache")
+        .arg("--encrypt-cache");
+    let error_message = "--encrypt-cache uses a key that is lost when Mountpoint exits";
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    Ok(())
+}
+
+#[test]
+fn warm_requires_persistent_cache() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("mount-s3-warm")?;
+    cmd.arg("test-bucket").arg("--cache").arg(dir.path());
+    let error_message = "warming --cache requires --persistent-cache or --shared-cache";
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    let mut cmd = Command::cargo_bin("mount-s3-warm")?;
+    cmd.arg("test-bucket");
+    let error_message = "no data cache is enabled to warm";
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    Ok(())
+}
+
+#[test]
+fn warm_help() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("mount-s3-warm")?;
+    cmd.arg("--help");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Usage: mount-s3-warm [OPTIONS] <BUCKET_NAME>\n",
+        ))
+        .stdout(predicate::str::contains("--throughput-limit"));
+
+    Ok(())
+}
+
+#[test]
+fn mount_bucket_named_warm() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("mount-s3")?;
+    cmd.arg("warm").arg("test/dir");
+    let error_message = "mount point test/dir does not exist";
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    Ok(())
+}
+
+#[test]
+fn record_trace_file_not_creatable() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let trace = dir.path().join("missing").join("trace.jsonl");
+    let mut cmd = Command::cargo_bin("mount-s3")?;
+    cmd.arg("test-bucket").arg(dir.path()).arg("--record-trace").arg(&trace);
+    let error_message = format!("failed to create trace file {}", trace.display());
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    Ok(())
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn record_trace_wraps_client() -> Result<(), Box<dyn std::error::Error>> {
+    let mount_point = assert_fs::TempDir::new()?;
+    let bucket_dir = assert_fs::TempDir::new()?;
+    let trace = mount_point.path().join("trace.jsonl");
+    // Loading the manifest fails after the client is created, so the mount stops before FUSE
+    let manifest = bucket_dir.path().join("missing-manifest.json");
+    let mut cmd = Command::cargo_bin("mock-mount-s3")?;
+    cmd.arg("sthree-bucket")
+        .arg(mount_point.path())
+        .arg("--foreground")
+        .arg(format!("--endpoint-url=file://{}", bucket_dir.path().display()))
+        .arg("--read-only")
+        .arg("--manifest")
+        .arg(&manifest)
+        .arg("--record-trace")
+        .arg(&trace);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("failed to load manifest"));
+
+    // The trace starts with the configuration of the recorded client
+    let contents = fs::read_to_string(&trace)?;
+    let header = contents.lines().next().expect("trace should have a header");
+    assert!(
+        header.contains(r#""operation":"header""#),
+        "unexpected header: {header}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn warm_rejects_record_trace() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("mount-s3-warm")?;
+    cmd.arg("test-bucket")
+        .arg("--record-trace")
+        .arg(dir.path().join("trace.jsonl"));
+    let error_message = "--record-trace is only supported when mounting";
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    Ok(())
+}
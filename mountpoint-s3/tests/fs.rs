@@ -1,14 +1,19 @@
 //! Manually implemented tests executing the FUSE protocol against [S3Filesystem]
 
 use fuser::FileType;
+use futures::executor::ThreadPool;
+use mountpoint_s3::data_cache::InMemoryDataCache;
 #[cfg(feature = "s3_tests")]
 use mountpoint_s3::fs::error_metadata::MOUNTPOINT_ERROR_LOOKUP_NONEXISTENT;
 #[cfg(all(feature = "s3_tests", not(feature = "s3express_tests")))]
 use mountpoint_s3::fs::error_metadata::{ErrorMetadata, MOUNTPOINT_ERROR_CLIENT};
 use mountpoint_s3::fs::{CacheConfig, OpenFlags, ToErrno, FUSE_ROOT_INODE};
+use mountpoint_s3::manifest::Manifest;
+use mountpoint_s3::object::ObjectId;
+use mountpoint_s3::prefetch::caching_prefetch;
 use mountpoint_s3::prefix::Prefix;
 use mountpoint_s3::s3::S3Personality;
-use mountpoint_s3::S3FilesystemConfig;
+use mountpoint_s3::{S3Filesystem, S3FilesystemConfig, WriteThroughCache};
 #[cfg(feature = "s3_tests")]
 use mountpoint_s3_client::config::{EndpointConfig, S3ClientConfig};
 #[cfg(all(feature = "s3_tests", not(feature = "s3express_tests")))]
@@ -29,7 +34,7 @@ use std::ffi::OsString;
 use std::ops::Add;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use test_case::test_case;
 
 mod common;
@@ -341,8 +346,8 @@ async fn test_readdir_then_open_cached() {
 
     client.add_object("file1.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
 
-    // Repeat to check readdir is not currently served from cache
-    for _ in 0..2 {
+    // Repeat to check the second readdir is served from the cached listing
+    for expected_list_count in [1, 0] {
         let head_counter = client.new_counter(Operation::HeadObject);
         let list_counter = client.new_counter(Operation::ListObjectsV2);
 
@@ -363,20 +368,72 @@ async fn test_readdir_then_open_cached() {
         assert_eq!(entry.attr.kind, FileType::RegularFile);
 
         assert_eq!(head_counter.count(), 0);
-        assert_eq!(list_counter.count(), 1);
+        assert_eq!(list_counter.count(), expected_list_count);
 
         let fh = fs.open(entry.ino, OpenFlags::empty(), 0).await.unwrap().fh;
 
         assert_eq!(head_counter.count(), 0);
-        assert_eq!(list_counter.count(), 1);
+        assert_eq!(list_counter.count(), expected_list_count);
         fs.release(entry.ino, fh, 0, None, true).await.unwrap();
         fs.releasedir(dir_ino, dir_handle, 0).await.unwrap();
 
         assert_eq!(head_counter.count(), 0);
-        assert_eq!(list_counter.count(), 1);
+        assert_eq!(list_counter.count(), expected_list_count);
     }
 }
 
+#[tokio::test]
+async fn test_readdir_cached_invalidated_by_unlink() {
+    let fs_config = S3FilesystemConfig {
+        cache_config: CacheConfig {
+            serve_lookup_from_cache: true,
+            dir_ttl: Duration::from_secs(600),
+            file_ttl: Duration::from_secs(600),
+            ..Default::default()
+        },
+        allow_delete: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(
+        "test_readdir_cached_invalidated_by_unlink",
+        &Default::default(),
+        fs_config,
+    );
+
+    client.add_object("file1.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    client.add_object("file2.txt", MockObject::constant(0xa2, 15, ETag::for_tests()));
+
+    let list_counter = client.new_counter(Operation::ListObjectsV2);
+    let readdir_names = || async {
+        let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+        let mut reply = DirectoryReply::new(5);
+        let _reply = fs
+            .readdirplus(FUSE_ROOT_INODE, dir_handle, 0, &mut reply)
+            .await
+            .unwrap();
+        fs.releasedir(FUSE_ROOT_INODE, dir_handle, 0).await.unwrap();
+        reply
+            .entries
+            .into_iter()
+            .skip(2)
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(readdir_names().await, ["file1.txt", "file2.txt"]);
+    assert_eq!(list_counter.count(), 1);
+
+    // Served from the cached listing, even though the bucket changed
+    client.remove_object("file2.txt");
+    assert_eq!(readdir_names().await, ["file1.txt", "file2.txt"]);
+    assert_eq!(list_counter.count(), 1);
+
+    // Unlinking a child drops the cached listing of its parent
+    fs.unlink(FUSE_ROOT_INODE, "file1.txt".as_ref()).await.unwrap();
+    assert_eq!(readdir_names().await, Vec::<OsString>::new());
+    assert_eq!(list_counter.count(), 2);
+}
+
 #[tokio::test]
 async fn test_unlink_cached() {
     let fs_config = S3FilesystemConfig {
@@ -561,6 +618,190 @@ async fn test_implicit_directory_shadow(prefix: &str) {
     fs.releasedir(dir_ino, dir_handle, 0).await.unwrap();
 }
 
+#[test_case(""; "unprefixed")]
+#[test_case("test_prefix/"; "prefixed")]
+#[tokio::test]
+async fn test_shadowed_file_suffix(prefix: &str) {
+    let prefix = Prefix::new(prefix).expect("valid prefix");
+    let fs_config = S3FilesystemConfig {
+        shadowed_file_suffix: Some(".__file__".to_owned()),
+        allow_delete: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_shadowed_file_suffix", &prefix, fs_config);
+
+    client.add_object(
+        &format!("{prefix}dir1"),
+        MockObject::constant(0xa1, 15, ETag::from_str("test_etag_1").unwrap()),
+    );
+    client.add_object(
+        &format!("{prefix}dir1/file2.txt"),
+        MockObject::constant(0xa2, 15, ETag::from_str("test_etag_2").unwrap()),
+    );
+
+    let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+    let mut reply = Default::default();
+    let _reply = fs
+        .readdirplus(FUSE_ROOT_INODE, dir_handle, 0, &mut reply)
+        .await
+        .unwrap();
+    fs.releasedir(FUSE_ROOT_INODE, dir_handle, 0).await.unwrap();
+
+    assert_eq!(reply.entries.len(), 2 + 2);
+    assert_eq!(reply.entries[2].name, "dir1");
+    assert_eq!(reply.entries[2].attr.kind, FileType::Directory);
+    assert_eq!(reply.entries[3].name, "dir1.__file__");
+    assert_eq!(reply.entries[3].attr.kind, FileType::RegularFile);
+
+    // The shadowed object is read through its alternate name
+    let entry = fs.lookup(FUSE_ROOT_INODE, "dir1.__file__".as_ref()).await.unwrap();
+    assert_eq!(entry.attr.ino, reply.entries[3].ino);
+    let fh = fs.open(entry.attr.ino, OpenFlags::empty(), 0).await.unwrap().fh;
+    let bytes_read = fs
+        .read(entry.attr.ino, fh, 0, 4096, 0, None)
+        .await
+        .expect("fs read should succeed");
+    assert_eq!(&bytes_read[..], &[0xa1; 15]);
+    fs.release(entry.attr.ino, fh, 0, None, true).await.unwrap();
+
+    // Deleting it deletes the shadowed object
+    fs.unlink(FUSE_ROOT_INODE, "dir1.__file__".as_ref()).await.unwrap();
+    assert!(!client.contains_key(&format!("{prefix}dir1")));
+    assert!(client.contains_key(&format!("{prefix}dir1/file2.txt")));
+}
+
+#[test_case(""; "unprefixed")]
+#[test_case("test_prefix/"; "prefixed")]
+#[tokio::test]
+async fn test_escape_names(prefix: &str) {
+    let prefix = Prefix::new(prefix).expect("valid prefix");
+    let fs_config = S3FilesystemConfig {
+        escape_names: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_escape_names", &prefix, fs_config);
+
+    for key in ["dir//file1.txt", "dir/./file2.txt", "dir/../file3.txt", "dir/100%"] {
+        client.add_object(
+            &format!("{prefix}{key}"),
+            MockObject::constant(0xa1, 15, ETag::for_tests()),
+        );
+    }
+
+    let dir_ino = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap().attr.ino;
+    let dir_handle = fs.opendir(dir_ino, 0).await.unwrap().fh;
+    let mut reply = Default::default();
+    let _reply = fs.readdirplus(dir_ino, dir_handle, 0, &mut reply).await.unwrap();
+    fs.releasedir(dir_ino, dir_handle, 0).await.unwrap();
+
+    let names: Vec<_> = reply.entries.iter().skip(2).map(|entry| entry.name.clone()).collect();
+    assert_eq!(names, ["%", "%2E", "%2E%2E", "100%25"]);
+
+    // Escaped names resolve to the original keys
+    let entry = fs.lookup(dir_ino, "%2E".as_ref()).await.unwrap();
+    assert_eq!(entry.attr.kind, FileType::Directory);
+    let entry = fs.lookup(entry.attr.ino, "file2.txt".as_ref()).await.unwrap();
+    assert_eq!(entry.attr.kind, FileType::RegularFile);
+    let entry = fs.lookup(dir_ino, "100%25".as_ref()).await.unwrap();
+    assert_eq!(entry.attr.kind, FileType::RegularFile);
+
+    // Names that aren't the escaped form of any key are invalid
+    for name in ["100%", "%2e", "%41"] {
+        let err = fs
+            .lookup(dir_ino, name.as_ref())
+            .await
+            .expect_err("name should be invalid");
+        assert_eq!(err.to_errno(), libc::EINVAL);
+    }
+
+    // New files are written to the unescaped key
+    let mode = libc::S_IFREG | libc::S_IRWXU;
+    let empty_ino = fs.lookup(dir_ino, "%".as_ref()).await.unwrap().attr.ino;
+    let new_ino = fs
+        .mknod(empty_ino, "50%25".as_ref(), mode, 0, 0)
+        .await
+        .unwrap()
+        .attr
+        .ino;
+    let fh = fs.open(new_ino, OpenFlags::O_WRONLY, 0).await.unwrap().fh;
+    fs.write(new_ino, fh, 0, &[0xa2; 8], 0, 0, None).await.unwrap();
+    fs.release(new_ino, fh, 0, None, true).await.unwrap();
+    assert!(client.contains_key(&format!("{prefix}dir//50%")));
+}
+
+#[test_case(""; "unprefixed")]
+#[test_case("test_prefix/"; "prefixed")]
+#[tokio::test]
+async fn test_escape_long_names(prefix: &str) {
+    let prefix = Prefix::new(prefix).expect("valid prefix");
+    let fs_config = S3FilesystemConfig {
+        escape_names: true,
+        allow_delete: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_escape_long_names", &prefix, fs_config);
+
+    let long_name = "a".repeat(300);
+    let other_long_name = format!("{long_name}b");
+    for key in [
+        format!("{long_name}/file.txt"),
+        format!("{long_name}.txt"),
+        format!("{other_long_name}.txt"),
+    ] {
+        client.add_object(
+            &format!("{prefix}{key}"),
+            MockObject::constant(0xa1, 15, ETag::for_tests()),
+        );
+    }
+
+    let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+    let mut reply = Default::default();
+    let _reply = fs
+        .readdirplus(FUSE_ROOT_INODE, dir_handle, 0, &mut reply)
+        .await
+        .unwrap();
+    fs.releasedir(FUSE_ROOT_INODE, dir_handle, 0).await.unwrap();
+    let entries: Vec<_> = reply.entries.iter().skip(2).collect();
+    assert_eq!(entries.len(), 3);
+    for entry in &entries {
+        let name = entry.name.to_str().unwrap();
+        assert!(name.len() <= 255, "{name:?} is too long");
+        assert!(
+            name.starts_with(&long_name[..200]),
+            "{name:?} should start like its key"
+        );
+    }
+
+    // Shortened names resolve to the original keys
+    let dir_entry = entries
+        .iter()
+        .find(|entry| entry.attr.kind == FileType::Directory)
+        .unwrap();
+    let entry = fs.lookup(FUSE_ROOT_INODE, &dir_entry.name).await.unwrap();
+    assert_eq!(entry.attr.ino, dir_entry.ino);
+    let entry = fs.lookup(entry.attr.ino, "file.txt".as_ref()).await.unwrap();
+    assert_eq!(entry.attr.kind, FileType::RegularFile);
+
+    // A shortened name that no key shortens to doesn't exist
+    let mut missing_name = dir_entry.name.to_str().unwrap().to_owned();
+    let last = if missing_name.ends_with('0') { "1" } else { "0" };
+    missing_name.replace_range(missing_name.len() - 1.., last);
+    let err = fs
+        .lookup(FUSE_ROOT_INODE, missing_name.as_ref())
+        .await
+        .expect_err("should not exist");
+    assert_eq!(err.to_errno(), libc::ENOENT);
+
+    // Files can be removed by their shortened name
+    for entry in entries.iter().filter(|entry| entry.attr.kind == FileType::RegularFile) {
+        fs.lookup(FUSE_ROOT_INODE, &entry.name).await.unwrap();
+        fs.unlink(FUSE_ROOT_INODE, &entry.name).await.unwrap();
+    }
+    assert!(!client.contains_key(&format!("{prefix}{long_name}.txt")));
+    assert!(!client.contains_key(&format!("{prefix}{other_long_name}.txt")));
+    assert!(client.contains_key(&format!("{prefix}{long_name}/file.txt")));
+}
+
 #[test_case(1024; "small")]
 #[test_case(50 * 1024; "large")]
 #[tokio::test]
@@ -650,6 +891,71 @@ async fn test_sequential_write(write_size: usize) {
     fs.release(file_ino, fh, 0, None, true).await.unwrap();
 }
 
+#[tokio::test]
+async fn test_read_after_write_from_cache() {
+    const BUCKET_NAME: &str = "test_read_after_write_from_cache";
+    const OBJECT_SIZE: usize = 50 * 1024;
+
+    let client = Arc::new(MockClient::new(MockClientConfig {
+        bucket: BUCKET_NAME.to_string(),
+        part_size: 1024 * 1024,
+        enable_backpressure: true,
+        initial_read_window_size: 256 * 1024,
+        ..Default::default()
+    }));
+    let cache = Arc::new(InMemoryDataCache::new(8 * 1024));
+    let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+    let config = S3FilesystemConfig {
+        write_through_cache: Some(WriteThroughCache::new(cache.clone(), runtime.clone())),
+        ..Default::default()
+    };
+    let prefetcher = caching_prefetch(cache.clone(), runtime, Default::default());
+    let fs = S3Filesystem::new(client.clone(), prefetcher, BUCKET_NAME, &Default::default(), config);
+
+    let mut rng = ChaCha20Rng::seed_from_u64(0x12345678 + OBJECT_SIZE as u64);
+    let mut body = vec![0u8; OBJECT_SIZE];
+    rng.fill(&mut body[..]);
+
+    // Write the object
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+    let fh = fs.open(file_ino, OpenFlags::O_WRONLY, 0).await.unwrap().fh;
+    let written = fs.write(file_ino, fh, 0, &body, 0, 0, None).await.unwrap();
+    assert_eq!(written as usize, body.len());
+    fs.release(file_ino, fh, 0, None, false).await.unwrap();
+
+    // The blocks are inserted into the cache in the background
+    let head = client.head_object(BUCKET_NAME, "file.bin").await.unwrap();
+    let object_id = ObjectId::new("file.bin".to_owned(), head.object.etag.as_str().into());
+    let start = Instant::now();
+    while cache.block_count(&object_id) < OBJECT_SIZE.div_ceil(8 * 1024) {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "blocks were not cached in time"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // Read it back, which should be entirely served from the cache
+    let get_counter = client.new_counter(Operation::GetObject);
+    let dentry = fs.lookup(FUSE_ROOT_INODE, "file.bin".as_ref()).await.unwrap();
+    assert_eq!(dentry.attr.size as usize, body.len());
+    let file_ino = dentry.attr.ino;
+    let fh = fs.open(file_ino, OpenFlags::empty(), 0).await.unwrap().fh;
+    let bytes_read = fs
+        .read(file_ino, fh, 0, OBJECT_SIZE as u32, 0, None)
+        .await
+        .expect("fs read should succeed");
+    assert_eq!(&bytes_read[..], &body[..]);
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+
+    assert_eq!(get_counter.count(), 0, "object should be read from the cache");
+}
+
 #[test_case(-27; "earlier offset")]
 #[test_case(28; "later offset")]
 #[tokio::test]
@@ -1562,6 +1868,61 @@ async fn test_lookup_forbidden() {
     );
 }
 
+#[tokio::test]
+async fn test_manifest() {
+    let manifest_file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        manifest_file.path(),
+        r#"[
+            { "key": "dir/file1.bin", "size": 15, "etag": "\"etag1\"" },
+            { "key": "dir/file2.bin", "size": 15, "etag": "\"stale\"" },
+            { "key": "file3.bin", "size": 15, "etag": "\"etag3\"" }
+        ]"#,
+    )
+    .unwrap();
+    let manifest = Manifest::from_file(manifest_file.path()).unwrap();
+    let fs_config = S3FilesystemConfig {
+        manifest: Some(Arc::new(manifest)),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_manifest", &Default::default(), fs_config);
+    let etag = |etag: &str| ETag::from_str(etag).unwrap();
+    client.add_object("dir/file1.bin", MockObject::constant(0xa1, 15, etag("\"etag1\"")));
+    client.add_object("dir/file2.bin", MockObject::constant(0xa2, 15, etag("\"etag2\"")));
+    client.add_object("dir/unlisted.bin", MockObject::constant(0xa3, 15, etag("\"etag3\"")));
+
+    let head_counter = client.new_counter(Operation::HeadObject);
+    let list_counter = client.new_counter(Operation::ListObjectsV2);
+
+    let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+    let entries = ls(&fs, dir_handle, 0, 10).await;
+    let names: Vec<_> = entries.iter().map(|(_, name)| name.to_str().unwrap()).collect();
+    assert_eq!(names, &[".", "..", "dir", "file3.bin"]);
+
+    let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+    assert_eq!(dir.attr.kind, FileType::Directory);
+    let file1 = fs.lookup(dir.attr.ino, "file1.bin".as_ref()).await.unwrap();
+    assert_eq!(file1.attr.size, 15);
+    let err = fs
+        .lookup(dir.attr.ino, "unlisted.bin".as_ref())
+        .await
+        .expect_err("objects missing from the manifest should not be found");
+    assert_eq!(err.to_errno(), libc::ENOENT);
+    assert_eq!(head_counter.count(), 0);
+    assert_eq!(list_counter.count(), 0);
+
+    // Reads check that the object still matches the ETag in the manifest
+    let fh = fs.open(file1.attr.ino, OpenFlags::empty(), 0).await.unwrap().fh;
+    let data = fs.read(file1.attr.ino, fh, 0, 4096, 0, None).await.unwrap();
+    assert_eq!(&data[..], &[0xa1; 15]);
+
+    let file2 = fs.lookup(dir.attr.ino, "file2.bin".as_ref()).await.unwrap();
+    let fh = fs.open(file2.attr.ino, OpenFlags::empty(), 0).await.unwrap().fh;
+    fs.read(file2.attr.ino, fh, 0, 4096, 0, None)
+        .await
+        .expect_err("read should fail when the object changed since the manifest was created");
+}
+
 async fn new_local_file(fs: &TestS3Filesystem<Arc<MockClient>>, filename: &str) {
     let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
     let dentry = fs.mknod(FUSE_ROOT_INODE, filename.as_ref(), mode, 0, 0).await.unwrap();
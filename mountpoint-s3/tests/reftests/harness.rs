@@ -561,7 +561,7 @@ impl Harness {
             dir.as_ref().join(name)
         };
         assert!(key_as_path.has_root());
-        let key = key_as_path.strip_prefix("/").unwrap().display().to_string();
+        let key = self.reference.path_to_key(&key_as_path);
         trace!(key, "put object");
 
         let object = contents.to_mock_object();
@@ -724,11 +724,16 @@ mod read_only {
     }
 
     fn run_test(tree: TreeNode, check: CheckType, readdir_limit: usize) {
+        run_test_with_escaping(tree, check, readdir_limit, false);
+    }
+
+    fn run_test_with_escaping(tree: TreeNode, check: CheckType, readdir_limit: usize, escape_names: bool) {
         const BUCKET_NAME: &str = "test-bucket";
 
         let test_prefix = Prefix::new("").expect("valid prefix");
         let config = S3FilesystemConfig {
             readdir_size: 5,
+            escape_names,
             ..Default::default()
         };
         let (client, fs) = make_test_filesystem(BUCKET_NAME, &test_prefix, config);
@@ -738,7 +743,7 @@ mod read_only {
             client.add_object(&format!("{test_prefix}{key}"), object.clone());
         }
 
-        let reference = Reference::new(namespace);
+        let reference = Reference::new(namespace, escape_names);
 
         let harness = Harness::new(fs, client, reference, BUCKET_NAME, readdir_limit);
 
@@ -765,6 +770,16 @@ mod read_only {
         fn reftest_random_tree_single(tree in gen_tree(5, 100, 5, 20), path_index: usize) {
             run_test(tree, CheckType::SinglePath { path_index }, 0);
         }
+
+        #[test]
+        fn reftest_random_tree_full_escaped(readdir_limit in 0..10usize, tree in gen_tree(5, 100, 5, 20)) {
+            run_test_with_escaping(tree, CheckType::FullTree, readdir_limit, true);
+        }
+
+        #[test]
+        fn reftest_random_tree_single_escaped(tree in gen_tree(5, 100, 5, 20), path_index: usize) {
+            run_test_with_escaping(tree, CheckType::SinglePath { path_index }, 0, true);
+        }
     }
 
     #[test]
@@ -859,6 +874,57 @@ mod read_only {
             0,
         )
     }
+
+    fn escaped_names_tree() -> TreeNode {
+        TreeNode::Directory(BTreeMap::from([
+            (
+                "-".into(),
+                TreeNode::Directory(BTreeMap::from([
+                    (".".into(), TreeNode::File(FileContent(0, FileSize::Small(1)))),
+                    ("..".into(), TreeNode::File(FileContent(1, FileSize::Small(2)))),
+                    ("/a".into(), TreeNode::File(FileContent(2, FileSize::Small(3)))),
+                    ("100%".into(), TreeNode::File(FileContent(3, FileSize::Small(4)))),
+                    ("%2E".into(), TreeNode::File(FileContent(4, FileSize::Small(5)))),
+                ])),
+            ),
+            (
+                ".".into(),
+                TreeNode::Directory(BTreeMap::from([(
+                    "a".into(),
+                    TreeNode::File(FileContent(5, FileSize::Small(6))),
+                )])),
+            ),
+            // Creates the marker object `a/` of the directory `a`, which stays hidden
+            ("a/".into(), TreeNode::File(FileContent(6, FileSize::Small(7)))),
+            ("a".into(), TreeNode::File(FileContent(7, FileSize::Small(8)))),
+            // Names too long to be file names are shortened, including names with a common start
+            (
+                Name("%".repeat(100)),
+                TreeNode::Directory(BTreeMap::from([
+                    (
+                        Name("b".repeat(300)),
+                        TreeNode::File(FileContent(8, FileSize::Small(9))),
+                    ),
+                    (
+                        Name(format!("{}c", "b".repeat(300))),
+                        TreeNode::File(FileContent(9, FileSize::Small(10))),
+                    ),
+                ])),
+            ),
+        ]))
+    }
+
+    #[test]
+    fn random_tree_regression_escaped_names() {
+        run_test_with_escaping(escaped_names_tree(), CheckType::FullTree, 0, true);
+    }
+
+    #[test]
+    fn random_tree_regression_escaped_names_lookup() {
+        for path_index in 0..13 {
+            run_test_with_escaping(escaped_names_tree(), CheckType::SinglePath { path_index }, 0, true);
+        }
+    }
 }
 
 /// Mutation tests that run a sequence of mutations against a file system and check equivalence to
@@ -868,12 +934,17 @@ mod mutations {
     use proptest::collection::vec;
 
     fn run_test(initial_tree: TreeNode, ops: Vec<Op>, readdir_limit: usize) {
+        run_test_with_escaping(initial_tree, ops, readdir_limit, false);
+    }
+
+    fn run_test_with_escaping(initial_tree: TreeNode, ops: Vec<Op>, readdir_limit: usize, escape_names: bool) {
         const BUCKET_NAME: &str = "test-bucket";
 
         let test_prefix = Prefix::new("").expect("valid prefix");
         let config = S3FilesystemConfig {
             readdir_size: 5,
             allow_delete: true,
+            escape_names,
             cache_config: CacheConfig {
                 // We are only interested in strong consistency for the reference tests. FUSE isn't even in the loop.
                 serve_lookup_from_cache: false,
@@ -890,7 +961,7 @@ mod mutations {
             client.add_object(&format!("{test_prefix}{key}"), object.clone());
         }
 
-        let reference = Reference::new(namespace);
+        let reference = Reference::new(namespace, escape_names);
 
         let mut harness = Harness::new(fs, client, reference, BUCKET_NAME, readdir_limit);
 
@@ -907,6 +978,11 @@ mod mutations {
         fn reftest_random_tree(tree in gen_tree(5, 100, 5, 20), readdir_limit in 0..10usize, ops in vec(any::<Op>(), 1..10)) {
             run_test(tree, ops, readdir_limit);
         }
+
+        #[test]
+        fn reftest_random_tree_escaped(tree in gen_tree(5, 100, 5, 20), readdir_limit in 0..10usize, ops in vec(any::<Op>(), 1..10)) {
+            run_test_with_escaping(tree, ops, readdir_limit, true);
+        }
     }
 
     #[test]
@@ -927,6 +1003,25 @@ mod mutations {
         );
     }
 
+    #[test]
+    fn regression_write_into_escaped_directory() {
+        run_test_with_escaping(
+            TreeNode::Directory(BTreeMap::from([(
+                ".".into(),
+                TreeNode::Directory(BTreeMap::from([(
+                    "".into(),
+                    TreeNode::File(FileContent(0, FileSize::Small(1))),
+                )])),
+            )])),
+            vec![
+                Op::WriteFile("100%25".into(), DirectoryIndex(1), FileContent(1, FileSize::Small(2))),
+                Op::PutObject(DirectoryIndex(1), "..".into(), FileContent(2, FileSize::Small(3))),
+            ],
+            0,
+            true,
+        );
+    }
+
     #[test]
     fn regression_overwrite() {
         run_test(
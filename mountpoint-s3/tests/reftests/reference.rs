@@ -1,4 +1,5 @@
 use mountpoint_s3_client::mock_client::MockObject;
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::path::{Component, Path, PathBuf};
@@ -67,6 +68,8 @@ pub struct Reference {
     local_files: Vec<PathBuf>,
     /// Local directories
     local_directories: Vec<PathBuf>,
+    /// Whether key components that are not valid file names are escaped rather than hidden
+    escape_names: bool,
     /// Materialized state
     materialized: MaterializedReference,
 }
@@ -138,14 +141,15 @@ impl MaterializedReference {
 }
 
 impl Reference {
-    pub fn new(remote_keys: Vec<(String, MockObject)>) -> Self {
+    pub fn new(remote_keys: Vec<(String, MockObject)>, escape_names: bool) -> Self {
         let local_files = vec![];
         let local_directories = vec![];
-        let materialized = build_reference(remote_keys.iter().map(|(k, o): &(_, _)| (k, o)));
+        let materialized = build_reference(remote_keys.iter().map(|(k, o): &(_, _)| (k, o)), escape_names);
         Self {
             remote_keys: remote_keys.into_iter().collect(),
             local_files,
             local_directories,
+            escape_names,
             materialized,
         }
     }
@@ -155,7 +159,7 @@ impl Reference {
             remote_keys=?self.remote_keys, local_files=?self.local_files, local_directories=?self.local_directories,
             "rematerialize",
         );
-        let mut materialized = build_reference(self.remote_keys.iter());
+        let mut materialized = build_reference(self.remote_keys.iter(), self.escape_names);
         for local_dir in self.local_directories.iter() {
             let added = materialized.add_local_node(local_dir, NodeType::Directory);
             if added {
@@ -252,15 +256,49 @@ impl Reference {
     }
 
     pub fn add_remote_file(&mut self, path: impl AsRef<Path>, object: MockObject) {
-        let key = path.as_ref().to_string_lossy();
-        assert_eq!(key.chars().next(), Some('/'));
-        self.add_remote_key(&key[1..], object);
+        let key = self.path_to_key(path);
+        self.add_remote_key(&key, object);
     }
 
     pub fn remove_remote_file(&mut self, path: impl AsRef<Path>) {
-        let key = path.as_ref().to_string_lossy();
-        assert_eq!(key.chars().next(), Some('/'));
-        self.remove_remote_key(&key[1..]);
+        let key = self.path_to_key(path);
+        self.remove_remote_key(&key);
+    }
+
+    /// The key of the object at an absolute path, which is the path itself unless names are
+    /// escaped.
+    pub fn path_to_key(&self, path: impl AsRef<Path>) -> String {
+        let path = path.as_ref().to_string_lossy();
+        assert_eq!(path.chars().next(), Some('/'));
+        if self.escape_names {
+            let mut key = String::new();
+            for (i, name) in path[1..].split('/').enumerate() {
+                if i > 0 {
+                    key.push('/');
+                }
+                let component = self
+                    .resolve_shortened_name(&key, name)
+                    .unwrap_or_else(|| unescape_name(name));
+                key.push_str(&component);
+            }
+            key
+        } else {
+            path[1..].to_owned()
+        }
+    }
+
+    /// The component of a key following `key_prefix` that was shortened to `name` by [escape_name],
+    /// if there is one in the bucket.
+    fn resolve_shortened_name(&self, key_prefix: &str, name: &str) -> Option<String> {
+        if !name.contains("%~") {
+            return None;
+        }
+        self.remote_keys
+            .keys()
+            .filter_map(|key| key.strip_prefix(key_prefix))
+            .map(|rest| rest.split('/').next().unwrap())
+            .find(|component| escape_name(component) == name)
+            .map(str::to_owned)
     }
 
     /// Get a node from a full path, if it exists. If any path component does not exist in the
@@ -299,10 +337,52 @@ pub fn valid_inode_name(name: &str) -> bool {
     !name.is_empty() && name != "." && name != ".." && !name.contains('\0')
 }
 
+/// The file name for a component of a key when escaping names: percent-encode the components that
+/// aren't valid names, and `%` in every name so that the mapping can be reversed. Names that would
+/// be longer than 255 bytes are cut short and end with `%~` and a hash of the component instead.
+pub fn escape_name(name: &str) -> String {
+    let escaped = match name {
+        "" => "%".to_owned(),
+        "." => "%2E".to_owned(),
+        ".." => "%2E%2E".to_owned(),
+        _ => name.replace('%', "%25").replace('\0', "%00"),
+    };
+    if escaped.len() <= 255 {
+        return escaped;
+    }
+    let mut shortened = String::new();
+    for c in name.chars() {
+        let escaped_char = match c {
+            '%' => "%25".to_owned(),
+            '\0' => "%00".to_owned(),
+            c => c.to_string(),
+        };
+        if shortened.len() + escaped_char.len() > 255 - 18 {
+            break;
+        }
+        shortened.push_str(&escaped_char);
+    }
+    let hash = Sha256::digest(name.as_bytes());
+    format!("{shortened}%~{}", hex::encode(&hash[..8]))
+}
+
+/// The component of a key for a file name produced by [escape_name].
+fn unescape_name(name: &str) -> String {
+    match name {
+        "%" => String::new(),
+        "%2E" => ".".to_owned(),
+        "%2E%2E" => "..".to_owned(),
+        _ => name.replace("%00", "\0").replace("%25", "%"),
+    }
+}
+
 /// Take an S3 namespace (list of keys) and create the expected reference file system tree. This is
 /// where all our semantics decisions about how to present a flat keyspace as a file system are
 /// made; we'll be testing the connector against the decisions made here.
-fn build_reference<'a>(flat: impl Iterator<Item = (&'a String, &'a MockObject)>) -> MaterializedReference {
+fn build_reference<'a>(
+    flat: impl Iterator<Item = (&'a String, &'a MockObject)>,
+    escape_names: bool,
+) -> MaterializedReference {
     #[derive(Debug)]
     enum RefNode {
         Directory(Rc<RefCell<BTreeMap<String, RefNode>>>),
@@ -324,36 +404,46 @@ fn build_reference<'a>(flat: impl Iterator<Item = (&'a String, &'a MockObject)>)
         let mut leaf_dir = tree.clone();
         for dir in components.iter().take(components.len().saturating_sub(1)) {
             // Semantics decision: these characters are invalid in directory names, so nothing
-            // below them should be visible.
-            if !valid_inode_name(dir) {
+            // below them should be visible, unless names are escaped.
+            let dir = if escape_names {
+                escape_name(dir)
+            } else if valid_inode_name(dir) {
+                dir.to_string()
+            } else {
                 continue 'next_key;
-            }
+            };
 
             let mut leaf = leaf_dir.borrow_mut();
             // Semantics decision: directories shadow files of the same name, so overwrite if it
             // exists but is a file.
             let should_create = leaf
-                .get(*dir)
+                .get(&dir)
                 .map(|node| matches!(node, RefNode::File(_)))
                 .unwrap_or(true);
             if should_create {
-                leaf.insert(dir.to_string(), RefNode::Directory(Default::default()));
+                leaf.insert(dir.clone(), RefNode::Directory(Default::default()));
             }
 
-            let next_leaf_dir = leaf.get(*dir).unwrap().children().clone();
+            let next_leaf_dir = leaf.get(&dir).unwrap().children().clone();
             drop(leaf);
             leaf_dir = next_leaf_dir;
         }
 
         // Semantics decision: these characters are invalid in file names, so they should not be
-        // visible, but the directories they're in will still be present.
+        // visible unless names are escaped, but the directories they're in will still be present.
+        // An empty file name is the marker object of its directory, which is never visible.
         let file_name = components.iter().last().unwrap();
+        let (file_name, is_valid) = if escape_names {
+            (escape_name(file_name), !file_name.is_empty())
+        } else {
+            (file_name.to_string(), valid_inode_name(file_name))
+        };
         let should_create = leaf_dir
             .borrow()
-            .get(*file_name)
+            .get(&file_name)
             .map(|node| matches!(node, RefNode::File(_)))
             .unwrap_or(true);
-        if valid_inode_name(file_name) && should_create {
+        if is_valid && should_create {
             leaf_dir
                 .borrow_mut()
                 .insert(file_name.to_string(), RefNode::File(file.clone()));